@@ -1,8 +1,10 @@
 package app.slipnet.service
 
 import android.app.NotificationManager
+import android.content.BroadcastReceiver
 import android.content.Context
 import android.content.Intent
+import android.content.IntentFilter
 import android.content.SharedPreferences
 import android.net.ConnectivityManager
 import android.net.LinkProperties
@@ -116,6 +118,16 @@ class SlipNetVpnService : VpnService() {
     private lateinit var prefs: SharedPreferences
     private var wakeLock: PowerManager.WakeLock? = null
 
+    // Throttle native idle polling while the screen is off
+    private val screenStateReceiver = object : BroadcastReceiver() {
+        override fun onReceive(context: Context, intent: Intent) {
+            when (intent.action) {
+                Intent.ACTION_SCREEN_OFF -> SlipstreamBridge.setBatterySaver(true)
+                Intent.ACTION_SCREEN_ON -> SlipstreamBridge.setBatterySaver(false)
+            }
+        }
+    }
+
 
     override fun onCreate() {
         super.onCreate()
@@ -131,6 +143,12 @@ class SlipNetVpnService : VpnService() {
         ).apply {
             setReferenceCounted(false)
         }
+
+        SlipstreamBridge.setBatterySaver(!powerManager.isInteractive)
+        registerReceiver(screenStateReceiver, IntentFilter().apply {
+            addAction(Intent.ACTION_SCREEN_OFF)
+            addAction(Intent.ACTION_SCREEN_ON)
+        })
     }
 
     override fun onStartCommand(intent: Intent?, flags: Int, startId: Int): Int {
@@ -2832,6 +2850,9 @@ class SlipNetVpnService : VpnService() {
         // Release WakeLock
         releaseWakeLock()
 
+        unregisterReceiver(screenStateReceiver)
+        SlipstreamBridge.setBatterySaver(false)
+
         serviceScope.cancel()
         super.onDestroy()
     }
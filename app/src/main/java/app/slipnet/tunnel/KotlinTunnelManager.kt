@@ -39,7 +39,9 @@ data class KotlinTunnelConfig(
 data class ResolverConfig(
     val host: String,
     val port: Int,
-    val authoritative: Boolean
+    val authoritative: Boolean,
+    // Fixed IP for [host], used instead of system DNS (null = resolve normally)
+    val overrideIp: String? = null
 )
 
 /**
@@ -15,6 +15,16 @@ object SlipstreamBridge {
     const val DEFAULT_SLIPSTREAM_PORT = 1080
     const val DEFAULT_LISTEN_HOST = "127.0.0.1"
 
+    // Pacing profiles for setPacingProfile (pacing.rs PacingProfile codes)
+    const val PACING_PROFILE_BALANCED = 0
+    const val PACING_PROFILE_TURBO = 1
+    const val PACING_PROFILE_COMPATIBILITY = 2
+
+    // Zero-send stall recovery for setZeroSendReset (ZeroSendReset in the client config)
+    const val ZERO_SEND_RESET_OFF = 0
+    const val ZERO_SEND_RESET_STREAM = 1
+    const val ZERO_SEND_RESET_CONNECTION = 2
+
     private var isLibraryLoaded = false
     private var currentPort = DEFAULT_SLIPSTREAM_PORT
 
@@ -79,6 +89,8 @@ object SlipstreamBridge {
      * @param gsoEnabled Enable Generic Segmentation Offload
      * @param debugPoll Enable debug logging for DNS polling
      * @param debugStreams Enable debug logging for streams
+     * @param maxConsecutiveFailures Reconnects that never become ready before
+     *        the client gives up; 0 keeps retrying indefinitely
      */
     fun startClient(
         domain: String,
@@ -90,7 +102,8 @@ object SlipstreamBridge {
         gsoEnabled: Boolean = false,
         debugPoll: Boolean = false,
         debugStreams: Boolean = false,
-        idlePollIntervalMs: Int = 2000
+        idlePollIntervalMs: Int = 2000,
+        maxConsecutiveFailures: Int = 5
     ): Result<Unit> {
         if (!isLibraryLoaded) {
             return Result.failure(IllegalStateException("Native library not loaded"))
@@ -133,6 +146,7 @@ object SlipstreamBridge {
                 resolverHosts = resolvers.map { it.host }.toTypedArray(),
                 resolverPorts = resolvers.map { it.port }.toIntArray(),
                 resolverAuthoritative = resolvers.map { it.authoritative }.toBooleanArray(),
+                resolverOverrides = resolvers.map { it.overrideIp.orEmpty() }.toTypedArray(),
                 listenPort = actualPort,
                 listenHost = tcpListenHost,
                 congestionControl = congestionControl,
@@ -140,7 +154,8 @@ object SlipstreamBridge {
                 gsoEnabled = gsoEnabled,
                 debugPoll = debugPoll,
                 debugStreams = debugStreams,
-                idlePollInterval = idlePollIntervalMs
+                idlePollInterval = idlePollIntervalMs,
+                maxConsecutiveFailures = maxConsecutiveFailures
             )
 
             when (result) {
@@ -150,6 +165,8 @@ object SlipstreamBridge {
                 }
                 -1 -> Result.failure(RuntimeException("Invalid domain"))
                 -2 -> Result.failure(RuntimeException("Invalid resolver configuration"))
+                -4 -> Result.failure(RuntimeException("Resolver arrays differ in length"))
+                -5 -> Result.failure(RuntimeException("Previous client still shutting down, retry"))
                 -10 -> Result.failure(RuntimeException("Failed to spawn client thread"))
                 -11 -> Result.failure(RuntimeException("Failed to listen on port"))
                 else -> Result.failure(RuntimeException("Failed to start client: error $result"))
@@ -273,6 +290,7 @@ object SlipstreamBridge {
         resolverHosts: Array<String>,
         resolverPorts: IntArray,
         resolverAuthoritative: BooleanArray,
+        resolverOverrides: Array<String>,
         listenPort: Int,
         listenHost: String,
         congestionControl: String,
@@ -280,12 +298,39 @@ object SlipstreamBridge {
         gsoEnabled: Boolean,
         debugPoll: Boolean,
         debugStreams: Boolean,
-        idlePollInterval: Int
+        idlePollInterval: Int,
+        maxConsecutiveFailures: Int
     ): Int
 
     private external fun nativeStopSlipstreamClient()
     private external fun nativeIsClientRunning(): Boolean
     private external fun nativeIsQuicReady(): Boolean
+    private external fun nativeGetQuicVersion(): Int
+    private external fun nativeGetCipherSuite(): Int
+    private external fun nativeSetBatterySaver(active: Boolean)
+    private external fun nativeGetMemoryUsage(): Long
+    private external fun nativeGetAuthoritativeGateState(): Int
+    private external fun nativeGetSessionCounters(): LongArray?
+    private external fun nativeGetTtfbPercentiles(): LongArray?
+    private external fun nativeGetAcceptCounters(): LongArray?
+    private external fun nativeGetIdleOverhead(): LongArray?
+    private external fun nativeGetKeepAliveTraffic(): LongArray?
+    private external fun nativeGetRtt(): Long
+    private external fun nativeGetPathRtts(): String?
+    private external fun nativeGetConsecutiveFailures(): Int
+    private external fun nativeGetPermanentFailure(): String?
+    private external fun nativeGetPoisonedResolvers(): String?
+    private external fun nativeGetStatelessResets(): Long
+    private external fun nativeIsAcceptorBlockedOnZeroCredit(): Boolean
+    private external fun nativeGetZeroCreditStalls(): Long
+    private external fun nativeGetConfigSummary(): String?
+    private external fun nativeSetRecentLogBytes(bytes: Int): Int
+    private external fun nativeGetRecentLogs(): String?
+    private external fun nativeResetConsecutiveFailures()
+    private external fun nativeSetPacingProfile(profile: Int): Boolean
+    private external fun nativeGetPacingProfile(): Int
+    private external fun nativeSetZeroSendReset(mode: Int): Boolean
+    private external fun nativeSetTicketStoreKey(key: ByteArray?): Boolean
 
     /**
      * Check if the native client reports it's running (alias for isClientRunning).
@@ -306,4 +351,401 @@ object SlipstreamBridge {
             false
         }
     }
+
+    /**
+     * Negotiated QUIC version of the current connection (raw 32-bit value),
+     * or 0 when the connection is not ready.
+     */
+    fun getQuicVersion(): Int {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetQuicVersion()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading QUIC version", e)
+            0
+        }
+    }
+
+    /**
+     * IANA TLS cipher suite ID of the current connection (e.g. 0x1301),
+     * or 0 when the connection is not ready.
+     */
+    fun getCipherSuite(): Int {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetCipherSuite()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading cipher suite", e)
+            0
+        }
+    }
+
+    /**
+     * Authoritative path gate of the current connection: 0 when not required,
+     * 1 while streams wait for an authoritative path, 2 once one validated,
+     * 3 after falling back on timeout.
+     */
+    fun getAuthoritativeGateState(): Int {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetAuthoritativeGateState()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading authoritative gate state", e)
+            0
+        }
+    }
+
+    /**
+     * Coarse tunnel memory footprint in bytes (loop buffers, resolver tables
+     * and queued stream data), or 0 before the client has reported one.
+     */
+    fun getMemoryUsage(): Long {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetMemoryUsage()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading memory usage", e)
+            0
+        }
+    }
+
+    /**
+     * Cumulative session totals as [bytesUp, bytesDown, streamsOpened].
+     * They keep counting across reconnects and reset when the client is
+     * started again, so they suit accounting rather than live throughput.
+     */
+    fun getSessionCounters(): LongArray {
+        if (!isLibraryLoaded) return LongArray(3)
+        return try {
+            nativeGetSessionCounters() ?: LongArray(3)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading session counters", e)
+            LongArray(3)
+        }
+    }
+
+    /**
+     * Stream time-to-first-byte for this session as
+     * [count, p50Ms, p95Ms, p99Ms], from local accept to the first response
+     * byte. Percentiles are bucket upper bounds; all zeros without samples.
+     */
+    fun getTtfbPercentiles(): LongArray {
+        if (!isLibraryLoaded) return LongArray(4)
+        return try {
+            nativeGetTtfbPercentiles() ?: LongArray(4)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading TTFB percentiles", e)
+            LongArray(4)
+        }
+    }
+
+    /**
+     * Local listener accepts as
+     * [acceptsPerSec, accepted, blockedOnCredit, vetoed, errors, blockedOnQueue].
+     * Many accepts blocked on credit at a low rate mean the server's stream
+     * credit, not the local apps, is the bottleneck. blockedOnQueue counts
+     * connections that waited because the command channel cap was reached.
+     * Totals reset on start.
+     */
+    fun getAcceptCounters(): LongArray {
+        if (!isLibraryLoaded) return LongArray(6)
+        return try {
+            nativeGetAcceptCounters() ?: LongArray(6)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading accept counters", e)
+            LongArray(6)
+        }
+    }
+
+    /**
+     * Smoothed round-trip time of the tunnel in milliseconds, averaged over
+     * the resolver paths by how much each carries, or null until the
+     * connection is ready and a path has been measured. Updated every loop
+     * iteration, so it suits a live "ping" indicator.
+     */
+    fun getRttMs(): Long? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetRtt().takeIf { it >= 0 }?.let { it / 1000 }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading RTT", e)
+            null
+        }
+    }
+
+    /**
+     * Smoothed round-trip time in milliseconds of each measured resolver
+     * path, keyed by `ip:port`, in resolver order. Empty before ready.
+     */
+    fun getPathRttsMs(): Map<String, Long> {
+        if (!isLibraryLoaded) return emptyMap()
+        return try {
+            nativeGetPathRtts()
+                ?.split(",")
+                ?.mapNotNull { entry ->
+                    val addr = entry.substringBeforeLast('=', "")
+                    val micros = entry.substringAfterLast('=').toLongOrNull()
+                    if (addr.isEmpty() || micros == null) null else addr to micros / 1000
+                }
+                ?.toMap()
+                ?: emptyMap()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading path RTTs", e)
+            emptyMap()
+        }
+    }
+
+    /**
+     * What the tunnel costs while idle, as [bytesPerHour, bytes, idleSeconds]:
+     * keep-alive and poll bytes sent while the tunnel was idle, and the
+     * idle time they were sent over. Totals reset on start.
+     */
+    fun getIdleOverhead(): LongArray {
+        if (!isLibraryLoaded) return LongArray(3)
+        return try {
+            nativeGetIdleOverhead() ?: LongArray(3)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading idle overhead", e)
+            LongArray(3)
+        }
+    }
+
+    /**
+     * What keep-alives have cost since start, as [queries, bytes]: DNS
+     * queries sent while no stream was open, carrying PINGs and ACKs, and
+     * keep-alive polls. Totals reset on start.
+     */
+    fun getKeepAliveTraffic(): LongArray {
+        if (!isLibraryLoaded) return LongArray(2)
+        return try {
+            nativeGetKeepAliveTraffic() ?: LongArray(2)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading keep-alive traffic", e)
+            LongArray(2)
+        }
+    }
+
+    /**
+     * One-line summary of the effective client configuration from the last
+     * start, with secrets redacted, or null before the client has logged it.
+     */
+    fun getConfigSummary(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetConfigSummary()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading config summary", e)
+            null
+        }
+    }
+
+    /**
+     * Connections ended by a QUIC stateless reset since the client was
+     * started. A count that keeps climbing while the server stays up points
+     * at a middlebox injecting resets.
+     */
+    fun getStatelessResets(): Long {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetStatelessResets()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading stateless resets", e)
+            0
+        }
+    }
+
+    /**
+     * True while local connects wait on a ready connection whose server
+     * grants no streams (an initial bidirectional stream limit of 0). The
+     * tunnel looks up but every connect hangs; this does not clear
+     * without a server-side fix, so surface it to the user.
+     */
+    fun isAcceptorBlockedOnZeroCredit(): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeIsAcceptorBlockedOnZeroCredit()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading zero credit state", e)
+            false
+        }
+    }
+
+    /**
+     * Times local connects got stuck on zero stream credit since the client
+     * was started.
+     */
+    fun getZeroCreditStalls(): Long {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetZeroCreditStalls()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading zero credit stalls", e)
+            0
+        }
+    }
+
+    /**
+     * Connections in a row that never became ready. The client gives up when
+     * this reaches the `maxConsecutiveFailures` passed to [startClient].
+     */
+    fun getConsecutiveFailures(): Int {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeGetConsecutiveFailures()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading consecutive failures", e)
+            0
+        }
+    }
+
+    /**
+     * Error the last run gave up with because every attempt failed the same
+     * way, e.g. a certificate pin the server never matches, or null. Unlike
+     * hitting `maxConsecutiveFailures`, retrying will not help until the
+     * configuration changes. Cleared by [startClient].
+     */
+    fun getPermanentFailure(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetPermanentFailure()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading permanent failure", e)
+            null
+        }
+    }
+
+    /**
+     * Resolvers (`ip:port`) the client stopped using since [startClient]
+     * because nearly every answer they relayed was forged. Empty when none.
+     */
+    fun getPoisonedResolvers(): List<String> {
+        if (!isLibraryLoaded) return emptyList()
+        return try {
+            nativeGetPoisonedResolvers()?.split(",") ?: emptyList()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading poisoned resolvers", e)
+            emptyList()
+        }
+    }
+
+    /**
+     * Keep up to [bytes] of the most recent native log lines in memory for
+     * [getRecentLogs], e.g. 64 KiB while a diagnostics screen is open; 0 (the
+     * default) stops recording and frees them. Returns the capacity in
+     * effect, which is capped at 4 MiB.
+     */
+    fun setRecentLogBytes(bytes: Int): Int {
+        if (!isLibraryLoaded) return 0
+        return try {
+            nativeSetRecentLogBytes(bytes)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting recent log buffer", e)
+            0
+        }
+    }
+
+    /**
+     * Native log lines recorded since [setRecentLogBytes] turned recording
+     * on, oldest first, for attaching to a bug report without adb. Null when
+     * recording is off or nothing was logged.
+     */
+    fun getRecentLogs(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetRecentLogs()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading recent logs", e)
+            null
+        }
+    }
+
+    /**
+     * Clear the consecutive failure count, e.g. when the user retries or the
+     * network comes back, so a running client gets the full limit again.
+     */
+    fun resetConsecutiveFailures() {
+        if (!isLibraryLoaded) return
+        try {
+            nativeResetConsecutiveFailures()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error resetting consecutive failures", e)
+        }
+    }
+
+    /**
+     * Switch pacing without restarting the tunnel: [PACING_PROFILE_BALANCED],
+     * [PACING_PROFILE_TURBO] (aggressive, for authoritative paths) or
+     * [PACING_PROFILE_COMPATIBILITY] (conservative, safe for recursive
+     * resolvers). Takes effect on the next loop iteration and persists across
+     * restarts. Returns false for an unknown profile.
+     */
+    fun setPacingProfile(profile: Int): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetPacingProfile(profile)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting pacing profile", e)
+            false
+        }
+    }
+
+    /**
+     * Choose what the client does when picoquic keeps producing nothing to
+     * send while streams have data: [ZERO_SEND_RESET_OFF] (default),
+     * [ZERO_SEND_RESET_STREAM] (reset the stream with the largest backlog)
+     * or [ZERO_SEND_RESET_CONNECTION] (reconnect). Applies from the next
+     * start. Returns false for an unknown mode.
+     */
+    fun setZeroSendReset(mode: Int): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetZeroSendReset(mode)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting zero-send reset", e)
+            false
+        }
+    }
+
+    /**
+     * Set the 32-byte AES-256-GCM key for the session ticket store, or pass
+     * null to store tickets in plaintext. Keep the key in the Android
+     * Keystore and hand it over before [startClient]; it applies from the
+     * next start. Returns false and keeps the current key for other lengths.
+     * Reserved for now: the client does not persist tickets yet.
+     */
+    fun setTicketStoreKey(key: ByteArray?): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetTicketStoreKey(key)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting ticket store key", e)
+            false
+        }
+    }
+
+    /**
+     * Current pacing profile, one of the PACING_PROFILE_* constants.
+     */
+    fun getPacingProfile(): Int {
+        if (!isLibraryLoaded) return PACING_PROFILE_BALANCED
+        return try {
+            nativeGetPacingProfile()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading pacing profile", e)
+            PACING_PROFILE_BALANCED
+        }
+    }
+
+    /**
+     * Throttle idle DNS polling while the screen is off. A newly opened
+     * stream still polls immediately. The setting persists across restarts.
+     */
+    fun setBatterySaver(active: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetBatterySaver(active)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting battery saver", e)
+        }
+    }
 }
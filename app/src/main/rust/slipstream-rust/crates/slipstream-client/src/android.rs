@@ -6,18 +6,37 @@
 //! - Socket protection via VpnService.protect()
 
 use crate::error::ClientError;
-use crate::runtime::run_client;
-use jni::objects::{JBooleanArray, JClass, JIntArray, JObject, JObjectArray, JString, JValue};
-use jni::sys::{jboolean, jbooleanArray, jint, jintArray, JNI_FALSE, JNI_TRUE};
+use crate::jni_args::{build_resolver_specs, ResolverArrayLengths, START_INVALID_RESOLVERS};
+use crate::log_ring::{self, RECENT_LOGS};
+use crate::pacing::PacingProfile;
+use crate::runtime::{
+    run_client, PathRtt, DEFAULT_CLOSING_TIMEOUT_MS, DEFAULT_ZERO_SEND_STALL_ITERATIONS,
+};
+use crate::streams::acceptor::AcceptCounters;
+use crate::streams::SessionCounters;
+use crate::supervisor::{ClientSupervisor, StartCheck};
+use jni::objects::{
+    JBooleanArray, JByteArray, JClass, JIntArray, JObject, JObjectArray, JString, JValue,
+};
+use jni::sys::{
+    jboolean, jbooleanArray, jint, jintArray, jlong, jlongArray, jstring, JNI_FALSE, JNI_TRUE,
+};
 use jni::JNIEnv;
 use once_cell::sync::OnceCell;
-use slipstream_core::HostPort;
-use slipstream_ffi::{ClientConfig, ResolverMode, ResolverSpec};
+use slipstream_core::latency::LatencyPercentiles;
+use slipstream_core::normalize_domain;
+use slipstream_ffi::{
+    AuthoritativePathTimeout, ClientConfig, KeepAlivePath, Profile, ResolverSpec, TicketStoreKey,
+    ZeroSendReset,
+};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::os::unix::io::RawFd;
 use std::panic;
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
 use std::sync::Mutex;
-use std::thread::{self, JoinHandle};
+use std::thread;
+use std::time::Duration;
 use tokio::runtime::Builder;
 use tracing::{debug, error, info, warn};
 
@@ -25,29 +44,152 @@ use tracing::{debug, error, info, warn};
 // Global State
 // ============================================================================
 
-/// Flag indicating whether the client is running.
-static IS_RUNNING: AtomicBool = AtomicBool::new(false);
-
 /// Flag indicating whether the TCP listener is ready.
 static IS_LISTENER_READY: AtomicBool = AtomicBool::new(false);
 
 /// Flag indicating whether the QUIC connection is established and ready.
 static IS_QUIC_READY: AtomicBool = AtomicBool::new(false);
 
-/// Flag to signal the client thread to shut down.
-static SHOULD_SHUTDOWN: AtomicBool = AtomicBool::new(false);
-
-/// Flag indicating the client thread has finished.
-static IS_THREAD_DONE: AtomicBool = AtomicBool::new(true);
+/// Negotiated QUIC version of the ready connection (0 when not ready).
+static QUIC_VERSION: AtomicU32 = AtomicU32::new(0);
+
+/// IANA TLS cipher suite ID of the ready connection (0 when not ready).
+static CIPHER_SUITE: AtomicU32 = AtomicU32::new(0);
+
+/// Authoritative path gate of the current connection: 0 open (not required),
+/// 1 waiting, 2 authoritative path validated, 3 fell back after the timeout.
+static AUTHORITATIVE_GATE_STATE: AtomicI32 = AtomicI32::new(0);
+
+/// Coarse tunnel memory estimate in bytes, refreshed by the client loop.
+static MEMORY_USAGE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Session accounting totals (see `SessionCounters`), published by the client
+/// loop. They survive reconnects and only reset when the client is started.
+static SESSION_BYTES_UP: AtomicU64 = AtomicU64::new(0);
+static SESSION_BYTES_DOWN: AtomicU64 = AtomicU64::new(0);
+static SESSION_STREAMS_OPENED: AtomicU64 = AtomicU64::new(0);
+/// Session time-to-first-byte percentiles, see `record_ttfb_percentiles`.
+static TTFB_COUNT: AtomicU64 = AtomicU64::new(0);
+static TTFB_P50_MS: AtomicU64 = AtomicU64::new(0);
+static TTFB_P95_MS: AtomicU64 = AtomicU64::new(0);
+static TTFB_P99_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Connections ended by a stateless reset since the client was started.
+static STATELESS_RESETS: AtomicU64 = AtomicU64::new(0);
+
+/// Local listener accept rate and totals, see `record_accept_counters`.
+static ACCEPTS_PER_SEC: AtomicU64 = AtomicU64::new(0);
+static ACCEPTS: AtomicU64 = AtomicU64::new(0);
+static ACCEPTS_BLOCKED_ON_CREDIT: AtomicU64 = AtomicU64::new(0);
+static ACCEPTS_VETOED: AtomicU64 = AtomicU64::new(0);
+static ACCEPT_ERRORS: AtomicU64 = AtomicU64::new(0);
+static ACCEPTS_BLOCKED_ON_QUEUE: AtomicU64 = AtomicU64::new(0);
+/// Local connects stuck on a ready connection that grants no streams; see
+/// `record_zero_credit`.
+static ACCEPTOR_ZERO_CREDIT: AtomicBool = AtomicBool::new(false);
+static ZERO_CREDIT_STALLS: AtomicU64 = AtomicU64::new(0);
+
+/// Tunnel RTT in microseconds, 0 while there is none; see `record_rtt`.
+static RTT_US: AtomicU64 = AtomicU64::new(0);
+/// Smoothed RTT of each measured resolver path, in resolver order.
+static PATH_RTTS: Mutex<Vec<(SocketAddr, u64)>> = Mutex::new(Vec::new());
+
+/// Idle tunnel overhead, see `record_idle_overhead`.
+static IDLE_OVERHEAD_BYTES_PER_HOUR: AtomicU64 = AtomicU64::new(0);
+static IDLE_OVERHEAD_BYTES: AtomicU64 = AtomicU64::new(0);
+static IDLE_SECONDS: AtomicU64 = AtomicU64::new(0);
+
+/// Keep-alive queries and their bytes, see `record_keep_alive_traffic`.
+static KEEP_ALIVE_QUERIES: AtomicU64 = AtomicU64::new(0);
+static KEEP_ALIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Effective configuration line logged by the last client start.
+static CONFIG_SUMMARY: Mutex<Option<String>> = Mutex::new(None);
+
+/// Error the last client run gave up with because retrying cannot help.
+static PERMANENT_FAILURE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Resolvers judged to forge answers since the client was started.
+static POISONED_RESOLVERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Set while the screen is off; the client loop then throttles idle polling
+/// to BATTERY_SAVER_POLL_INTERVAL_MS.
+static BATTERY_SAVER_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Pacing profile code (see `PacingProfile`), read by the client loop on
+/// every iteration; survives client restarts.
+static PACING_PROFILE: AtomicI32 = AtomicI32::new(0);
+
+/// Key for the encrypted session ticket store, set from the Keystore by
+/// `nativeSetTicketStoreKey`; read on every start.
+static TICKET_STORE_KEY: Mutex<Option<TicketStoreKey>> = Mutex::new(None);
+
+/// Last step of zero-send stall recovery, set by `nativeSetZeroSendReset`:
+/// 0 off (default), 1 reset the worst stream, 2 reconnect. Read on every
+/// start.
+static ZERO_SEND_RESET: AtomicI32 = AtomicI32::new(0);
 
 /// Count of consecutive connection failures (connections that never became ready).
 static CONSECUTIVE_FAILURES: AtomicI32 = AtomicI32::new(0);
 
-/// Maximum consecutive failures before giving up.
-const MAX_CONSECUTIVE_FAILURES: i32 = 5;
+/// Consecutive failures before giving up when the app does not pick a limit.
+const DEFAULT_MAX_CONSECUTIVE_FAILURES: i32 = 5;
+
+/// Consecutive failures before giving up, set on start; 0 never gives up.
+static MAX_CONSECUTIVE_FAILURES: AtomicI32 = AtomicI32::new(DEFAULT_MAX_CONSECUTIVE_FAILURES);
+
+/// Loop watchdog stall threshold (matches the CLI `--watchdog-timeout` default).
+const WATCHDOG_TIMEOUT_MS: u64 = 30_000;
+
+/// Stale-response grace after each reconnect (matches the CLI `--reconnect-poll-grace` default).
+const RECONNECT_POLL_GRACE_MS: u64 = 2_000;
+
+/// Usable resolver path deadline (matches the CLI `--usable-path-timeout` default).
+const USABLE_PATH_TIMEOUT_MS: u64 = 15_000;
+
+/// Minimum gap between reconnect attempts; network switches on mobile can
+/// otherwise drop fresh connections back to back.
+const MIN_RECONNECT_INTERVAL_MS: u64 = 1_000;
+
+/// Reconnect backoff floor and cap (match the CLI `--reconnect-delay-min`
+/// and `--reconnect-delay-max` defaults).
+const RECONNECT_DELAY_MIN_MS: u64 = 250;
+const RECONNECT_DELAY_MAX_MS: u64 = 5_000;
+
+/// Stateless resets in a row before one backs off (matches the CLI
+/// `--stateless-reset-tolerance` default).
+const STATELESS_RESET_TOLERANCE: u32 = 1;
+
+/// Identical TLS or version failures in a row before giving up (matches the
+/// CLI `--terminal-failure-repeats` default).
+const TERMINAL_FAILURE_REPEATS: u32 = 3;
+
+/// Connection ID rotation stays off (matches the CLI `--cid-rotation-interval` default).
+const CID_ROTATION_INTERVAL_MS: u64 = 0;
 
-/// Handle to the client thread.
-static CLIENT_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+/// Authoritative path wait (matches the CLI `--authoritative-path-timeout`
+/// default); only used when the gate is required.
+const AUTHORITATIVE_PATH_TIMEOUT_MS: u64 = 10_000;
+
+/// Pacing polls held back while stream data can flow (matches the CLI
+/// `--data-poll-bias` default).
+const DATA_POLL_BIAS_PERCENT: u8 = 100;
+
+/// Queries per `sendmmsg` batch; 0 sends them one at a time (matches the CLI
+/// `--send-batch` default).
+const SEND_BATCH_SIZE: usize = 0;
+
+/// Longest idle sleep of the client loop; shutdown wakes it regardless.
+const MAX_IDLE_SLEEP_MS: u64 = 2_000;
+
+/// Idle poll spacing while battery saver is active (screen off).
+const BATTERY_SAVER_POLL_INTERVAL_MS: u64 = 30_000;
+
+/// How long a stop waits for the client thread before abandoning it.
+const CLIENT_STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The client thread: running, shutdown and thread-done state.
+static SUPERVISOR: ClientSupervisor = ClientSupervisor::new(CLIENT_STOP_TIMEOUT);
 
 /// Global JVM reference for callbacks.
 static JAVA_VM: OnceCell<jni::JavaVM> = OnceCell::new();
@@ -62,7 +204,12 @@ static BRIDGE_CLASS: OnceCell<jni::objects::GlobalRef> = OnceCell::new();
 
 /// Check if the client should shut down.
 pub fn should_shutdown() -> bool {
-    SHOULD_SHUTDOWN.load(Ordering::SeqCst)
+    SUPERVISOR.should_shutdown()
+}
+
+/// Resolves once the client has been asked to shut down.
+pub async fn shutdown_requested() {
+    SUPERVISOR.shutdown_requested().await
 }
 
 /// Signal that the TCP listener is ready.
@@ -78,12 +225,137 @@ pub fn signal_quic_ready() {
     info!("QUIC connection is ready");
 }
 
+/// Record the negotiated QUIC version and cipher suite for the status API.
+pub fn record_connection_info(quic_version: u32, cipher_suite: u16) {
+    QUIC_VERSION.store(quic_version, Ordering::SeqCst);
+    CIPHER_SUITE.store(cipher_suite as u32, Ordering::SeqCst);
+}
+
+/// Record the authoritative path gate state for the status API.
+pub fn record_authoritative_gate(state: i32) {
+    AUTHORITATIVE_GATE_STATE.store(state, Ordering::SeqCst);
+}
+
 /// Reset the QUIC ready flag (called on reconnect).
 pub fn reset_quic_ready() {
     IS_QUIC_READY.store(false, Ordering::SeqCst);
+    QUIC_VERSION.store(0, Ordering::SeqCst);
+    CIPHER_SUITE.store(0, Ordering::SeqCst);
+    AUTHORITATIVE_GATE_STATE.store(0, Ordering::SeqCst);
     debug!("QUIC ready flag reset for reconnection");
 }
 
+/// Record the client loop's memory estimate for the status API.
+pub fn record_memory_usage(bytes: u64) {
+    MEMORY_USAGE_BYTES.store(bytes, Ordering::SeqCst);
+}
+
+/// Record the session accounting totals for the status API.
+pub(crate) fn record_session_counters(counters: SessionCounters) {
+    SESSION_BYTES_UP.fetch_max(counters.bytes_up, Ordering::SeqCst);
+    SESSION_BYTES_DOWN.fetch_max(counters.bytes_down, Ordering::SeqCst);
+    SESSION_STREAMS_OPENED.fetch_max(counters.streams_opened, Ordering::SeqCst);
+}
+
+/// Record the session's stream time-to-first-byte percentiles for the
+/// status API.
+pub(crate) fn record_ttfb_percentiles(percentiles: LatencyPercentiles) {
+    TTFB_COUNT.store(percentiles.count, Ordering::SeqCst);
+    TTFB_P50_MS.store(percentiles.p50_ms, Ordering::SeqCst);
+    TTFB_P95_MS.store(percentiles.p95_ms, Ordering::SeqCst);
+    TTFB_P99_MS.store(percentiles.p99_ms, Ordering::SeqCst);
+}
+
+/// Keep the session's poisoned resolvers for the diagnostics screen.
+pub(crate) fn record_poisoned_resolvers(addrs: &[std::net::SocketAddr]) {
+    if let Ok(mut slot) = POISONED_RESOLVERS.lock() {
+        *slot = addrs.iter().map(ToString::to_string).collect();
+    }
+}
+
+/// Keep the startup configuration summary for the diagnostics screen.
+pub(crate) fn record_config_summary(summary: &str) {
+    if let Ok(mut slot) = CONFIG_SUMMARY.lock() {
+        *slot = Some(summary.to_string());
+    }
+}
+
+/// Count a connection that ended with a stateless reset.
+pub(crate) fn record_stateless_reset() {
+    STATELESS_RESETS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Record the local listener's accept totals and recent accept rate for
+/// the status API.
+pub(crate) fn record_accept_counters(counters: AcceptCounters, accepts_per_sec: u64) {
+    ACCEPTS_PER_SEC.store(accepts_per_sec, Ordering::SeqCst);
+    ACCEPTS.store(counters.accepted, Ordering::SeqCst);
+    ACCEPTS_BLOCKED_ON_CREDIT.store(counters.blocked_on_credit, Ordering::SeqCst);
+    ACCEPTS_VETOED.store(counters.vetoed, Ordering::SeqCst);
+    ACCEPT_ERRORS.store(counters.errors, Ordering::SeqCst);
+    ACCEPTS_BLOCKED_ON_QUEUE.store(counters.blocked_on_queue, Ordering::SeqCst);
+}
+
+/// Record the tunnel RTT and the per-path RTTs behind it, every loop
+/// iteration; None and no paths until the connection is ready.
+pub(crate) fn record_rtt(aggregate_us: Option<u64>, paths: &[PathRtt]) {
+    RTT_US.store(aggregate_us.unwrap_or(0), Ordering::SeqCst);
+    if let Ok(mut slot) = PATH_RTTS.lock() {
+        let unchanged = slot.len() == paths.len()
+            && slot
+                .iter()
+                .zip(paths)
+                .all(|(&(addr, rtt_us), path)| addr == path.addr && rtt_us == path.smoothed_us);
+        if !unchanged {
+            *slot = paths
+                .iter()
+                .map(|path| (path.addr, path.smoothed_us))
+                .collect();
+        }
+    }
+}
+
+/// Record whether local connects are stuck on zero stream credit and the
+/// stalls seen this session.
+pub(crate) fn record_zero_credit(blocked: bool, stalls: u64) {
+    ACCEPTOR_ZERO_CREDIT.store(blocked, Ordering::SeqCst);
+    ZERO_CREDIT_STALLS.store(stalls, Ordering::SeqCst);
+}
+
+/// Record the keep-alive and poll bytes sent while the tunnel was idle,
+/// the idle time they cover and the hourly rate they add up to.
+pub(crate) fn record_idle_overhead(bytes_per_hour: u64, bytes: u64, idle_us: u64) {
+    IDLE_OVERHEAD_BYTES_PER_HOUR.store(bytes_per_hour, Ordering::SeqCst);
+    IDLE_OVERHEAD_BYTES.store(bytes, Ordering::SeqCst);
+    IDLE_SECONDS.store(idle_us / 1_000_000, Ordering::SeqCst);
+}
+
+/// Record the keep-alive queries sent since the client was started and the
+/// bytes they carried.
+pub(crate) fn record_keep_alive_traffic(queries: u64, bytes: u64) {
+    KEEP_ALIVE_QUERIES.store(queries, Ordering::SeqCst);
+    KEEP_ALIVE_BYTES.store(bytes, Ordering::SeqCst);
+}
+
+/// Check if battery saver (screen off) is active.
+pub fn battery_saver_active() -> bool {
+    BATTERY_SAVER_ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Pacing profile selected through the JNI setter.
+pub(crate) fn pacing_profile() -> PacingProfile {
+    PacingProfile::from_code(PACING_PROFILE.load(Ordering::SeqCst)).unwrap_or_default()
+}
+
+fn zero_send_reset_from_code(code: jint) -> Option<ZeroSendReset> {
+    match code {
+        0 => Some(ZeroSendReset::Off),
+        1 => Some(ZeroSendReset::Stream),
+        2 => Some(ZeroSendReset::Connection),
+        _ => None,
+    }
+}
+
 /// Record a connection failure (connection that never became ready).
 pub fn record_connection_failure() {
     let failures = CONSECUTIVE_FAILURES.fetch_add(1, Ordering::SeqCst) + 1;
@@ -92,12 +364,49 @@ pub fn record_connection_failure() {
 
 /// Check if we've exceeded the maximum consecutive failures.
 pub fn exceeded_max_failures() -> bool {
-    CONSECUTIVE_FAILURES.load(Ordering::SeqCst) >= MAX_CONSECUTIVE_FAILURES
+    let max = MAX_CONSECUTIVE_FAILURES.load(Ordering::SeqCst);
+    max > 0 && CONSECUTIVE_FAILURES.load(Ordering::SeqCst) >= max
+}
+
+/// Attempts to attach the calling thread to the JVM before giving up.
+const JVM_ATTACH_ATTEMPTS: u32 = 3;
+
+/// Pause between JVM attach attempts.
+const JVM_ATTACH_RETRY_DELAY_MS: u64 = 20;
+
+/// Local reference slots for one protectSocket call.
+const PROTECT_LOCAL_FRAME_CAPACITY: i32 = 4;
+
+/// Run `attach` up to `attempts` times, pausing `delay_ms` after each failure;
+/// returns the last error once the attempts run out.
+fn retry_attach<T, E: std::fmt::Debug>(
+    attempts: u32,
+    delay_ms: u64,
+    mut attach: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 1;
+    loop {
+        match attach() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < attempts => {
+                warn!("JVM attach attempt {} failed: {:?}", attempt, e);
+                attempt += 1;
+                thread::sleep(std::time::Duration::from_millis(delay_ms));
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 /// Protect a socket file descriptor via VpnService.protect().
 /// This MUST be called for the UDP socket used for DNS queries BEFORE sending any data.
 /// Returns true if protection succeeded, false otherwise.
+///
+/// The calling thread is attached to the JVM once and stays attached until it
+/// exits, when jni detaches it, so a session that reconnects many times does
+/// not attach and detach per socket. Each call runs in its own local frame:
+/// a native thread never returns to Java to release local references, and any
+/// made by the call (an exception, say) would otherwise pile up until detach.
 pub fn protect_socket(fd: RawFd) -> bool {
     let jvm = match JAVA_VM.get() {
         Some(vm) => vm,
@@ -115,27 +424,32 @@ pub fn protect_socket(fd: RawFd) -> bool {
         }
     };
 
-    let mut env = match jvm.attach_current_thread() {
+    let mut env = match retry_attach(JVM_ATTACH_ATTEMPTS, JVM_ATTACH_RETRY_DELAY_MS, || {
+        jvm.attach_current_thread_permanently()
+    }) {
         Ok(env) => env,
         Err(e) => {
-            error!("Failed to attach to JVM: {:?}", e);
+            error!(
+                "Failed to attach to JVM after {} attempts: {:?}",
+                JVM_ATTACH_ATTEMPTS, e
+            );
             return false;
         }
     };
 
     // Call SlipstreamBridge.protectSocket(fd) using cached class reference
-    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
-    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
-    let result = env.call_static_method(
-        class,
-        "protectSocket",
-        "(I)Z",
-        &[JValue::Int(fd)],
+    let result = env.with_local_frame(
+        PROTECT_LOCAL_FRAME_CAPACITY,
+        |env| -> jni::errors::Result<bool> {
+            // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+            let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+            let val = env.call_static_method(class, "protectSocket", "(I)Z", &[JValue::Int(fd)])?;
+            Ok(val.z().unwrap_or(false))
+        },
     );
 
     match result {
-        Ok(val) => {
-            let protected = val.z().unwrap_or(false);
+        Ok(protected) => {
             if protected {
                 debug!("Socket fd={} protected successfully", fd);
             } else {
@@ -152,6 +466,60 @@ pub fn protect_socket(fd: RawFd) -> bool {
     }
 }
 
+/// Start result while an abandoned client thread is still alive.
+const START_PREVIOUS_STILL_STOPPING: jint = -5;
+
+/// Probe attempts (100 ms apart) for the listen port to become bindable.
+const LISTEN_PORT_PROBE_ATTEMPTS: u32 = 30;
+
+/// Wait until the listen port can be bound, the way the runtime binds it.
+/// Returns false if it is still busy after the last probe.
+fn wait_for_listen_port(host: &str, port: u16) -> bool {
+    for attempt in 0..LISTEN_PORT_PROBE_ATTEMPTS {
+        if listen_port_bindable(host, port) {
+            if attempt > 0 {
+                info!("Listen port {}:{} free after {} probes", host, port, attempt);
+            }
+            return true;
+        }
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
+    false
+}
+
+fn listen_port_bindable(host: &str, port: u16) -> bool {
+    let addrs: Vec<SocketAddr> = match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs.collect(),
+        // Leave resolution errors for the runtime to report.
+        Err(_) => return true,
+    };
+    addrs.into_iter().any(|addr| {
+        let domain = match addr {
+            SocketAddr::V4(_) => Domain::IPV4,
+            SocketAddr::V6(_) => Domain::IPV6,
+        };
+        let Ok(socket) = Socket::new(domain, Type::STREAM, Some(Protocol::TCP)) else {
+            return true;
+        };
+        // SO_REUSEADDR as in the runtime: TIME_WAIT leftovers do not count,
+        // a socket still listening does.
+        let _ = socket.set_reuse_address(true);
+        if addr.is_ipv6() {
+            let _ = socket.set_only_v6(false);
+        }
+        socket.bind(&SockAddr::from(addr)).is_ok()
+    })
+}
+
+/// Log a failed JNI call and clear whatever exception it left pending, so
+/// the caller's error code reaches Java instead of a stray throw.
+fn jni_failure(env: &mut JNIEnv, what: &str, err: jni::errors::Error) {
+    error!("{}: {:?}", what, err);
+    if env.exception_check().unwrap_or(false) {
+        let _ = env.exception_clear();
+    }
+}
+
 // ============================================================================
 // JNI Functions
 // ============================================================================
@@ -170,13 +538,20 @@ fn init_android_logging() {
         );
     }
 
-    // Also initialize tracing for the slipstream code
+    // Also initialize tracing for the slipstream code, with the recent-log
+    // ring behind the same filter.
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
     use tracing_subscriber::EnvFilter;
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .without_time()
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .without_time(),
+        )
+        .with(log_ring::layer(&RECENT_LOGS))
         .try_init();
 }
 
@@ -201,6 +576,7 @@ pub extern "system" fn JNI_OnLoad(vm: jni::JavaVM, _: *mut std::ffi::c_void) ->
 /// - resolverHosts: Array of resolver hostnames/IPs
 /// - resolverPorts: Array of resolver ports
 /// - resolverAuthoritative: Array of booleans indicating authoritative mode
+/// - resolverOverrides: Array of fixed IPs for each resolver host ("" = use DNS)
 /// - listenPort: TCP port to listen on
 /// - listenHost: TCP host to bind to
 /// - congestionControl: Congestion control algorithm ("bbr" or "dcubic")
@@ -213,9 +589,16 @@ pub extern "system" fn JNI_OnLoad(vm: jni::JavaVM, _: *mut std::ffi::c_void) ->
 /// - 0: Success
 /// - -1: Invalid domain
 /// - -2: Invalid resolver configuration
+/// - -3: SlipstreamBridge class not found
+/// - -4: Resolver hosts, ports, authoritative flags and overrides differ in length
+/// - -5: Previous instance still shutting down; retry shortly
 /// - -10: Failed to spawn client thread
 /// - -11: Failed to listen on port
 /// - -12: Exceeded max connection failures
+///
+/// `maxConsecutiveFailures` caps reconnects that never become ready before
+/// the client gives up; 0 never gives up and a negative value uses the
+/// default of 5.
 #[no_mangle]
 pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlipstreamClient<'local>(
     mut env: JNIEnv<'local>,
@@ -224,6 +607,7 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
     resolver_hosts: JObjectArray<'local>,
     resolver_ports: jintArray,
     resolver_authoritative: jbooleanArray,
+    resolver_overrides: JObjectArray<'local>,
     listen_port: jint,
     listen_host: JString<'local>,
     congestion_control: JString<'local>,
@@ -232,6 +616,7 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
     debug_poll: jboolean,
     debug_streams: jboolean,
     idle_poll_interval: jint,
+    max_consecutive_failures: jint,
 ) -> jint {
     // Catch panics to prevent crashes
     let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
@@ -241,6 +626,7 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
             resolver_hosts,
             resolver_ports,
             resolver_authoritative,
+            resolver_overrides,
             listen_port,
             listen_host,
             congestion_control,
@@ -249,6 +635,7 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
             debug_poll,
             debug_streams,
             idle_poll_interval,
+            max_consecutive_failures,
         )
     }));
 
@@ -256,7 +643,6 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
         Ok(code) => code,
         Err(e) => {
             error!("Panic in nativeStartSlipstreamClient: {:?}", e);
-            IS_RUNNING.store(false, Ordering::SeqCst);
             -100
         }
     }
@@ -268,6 +654,7 @@ fn start_client_impl<'local>(
     resolver_hosts: JObjectArray<'local>,
     resolver_ports: jintArray,
     resolver_authoritative: jbooleanArray,
+    resolver_overrides: JObjectArray<'local>,
     listen_port: jint,
     listen_host: JString<'local>,
     congestion_control: JString<'local>,
@@ -276,13 +663,22 @@ fn start_client_impl<'local>(
     debug_poll: jboolean,
     debug_streams: jboolean,
     idle_poll_interval: jint,
+    max_consecutive_failures: jint,
 ) -> jint {
     info!("nativeStartSlipstreamClient called");
 
-    // Check if already running
-    if IS_RUNNING.load(Ordering::SeqCst) {
-        warn!("Client already running");
-        return 0;
+    // Refuse a second client, and wait for any abandoned thread to finish:
+    // a second instance would race the old one for the listener port.
+    match SUPERVISOR.prepare_start() {
+        StartCheck::Ready => {}
+        StartCheck::AlreadyRunning => {
+            warn!("Client already running");
+            return 0;
+        }
+        StartCheck::PreviousStillRunning => {
+            warn!("Previous client thread still running, asking caller to retry");
+            return START_PREVIOUS_STILL_STOPPING;
+        }
     }
 
     // Cache the SlipstreamBridge class for callbacks from native threads.
@@ -297,61 +693,72 @@ fn start_client_impl<'local>(
                         info!("Cached SlipstreamBridge class for callbacks");
                     }
                     Err(e) => {
-                        error!("Failed to create global ref for SlipstreamBridge: {:?}", e);
+                        jni_failure(env, "Failed to create global ref for SlipstreamBridge", e);
                         return -3;
                     }
                 }
             }
             Err(e) => {
-                error!("Failed to find SlipstreamBridge class: {:?}", e);
+                jni_failure(env, "Failed to find SlipstreamBridge class", e);
                 return -3;
             }
         }
     }
 
-    // Wait for any abandoned thread to finish. After nativeStop abandons a thread,
-    // SHOULD_SHUTDOWN stays true so the thread can see it and exit. Wait here for
-    // that to happen before resetting the flag for the new thread.
-    if !IS_THREAD_DONE.load(Ordering::SeqCst) {
-        info!("Waiting for previous client thread to finish...");
-        for _ in 0..30 {
-            if IS_THREAD_DONE.load(Ordering::SeqCst) {
-                break;
-            }
-            thread::sleep(std::time::Duration::from_millis(100));
-        }
-        if !IS_THREAD_DONE.load(Ordering::SeqCst) {
-            warn!("Previous client thread still running, proceeding anyway");
-        }
-    }
-
     // Reset state
-    SHOULD_SHUTDOWN.store(false, Ordering::SeqCst);
     IS_LISTENER_READY.store(false, Ordering::SeqCst);
     IS_QUIC_READY.store(false, Ordering::SeqCst);
-    IS_THREAD_DONE.store(false, Ordering::SeqCst);
     CONSECUTIVE_FAILURES.store(0, Ordering::SeqCst);
+    MAX_CONSECUTIVE_FAILURES.store(
+        if max_consecutive_failures < 0 {
+            DEFAULT_MAX_CONSECUTIVE_FAILURES
+        } else {
+            max_consecutive_failures
+        },
+        Ordering::SeqCst,
+    );
+    SESSION_BYTES_UP.store(0, Ordering::SeqCst);
+    SESSION_BYTES_DOWN.store(0, Ordering::SeqCst);
+    SESSION_STREAMS_OPENED.store(0, Ordering::SeqCst);
+    record_ttfb_percentiles(LatencyPercentiles::default());
+    STATELESS_RESETS.store(0, Ordering::SeqCst);
+    record_accept_counters(AcceptCounters::default(), 0);
+    record_idle_overhead(0, 0, 0);
+    record_keep_alive_traffic(0, 0);
+    record_rtt(None, &[]);
+    record_zero_credit(false, 0);
+    if let Ok(mut slot) = CONFIG_SUMMARY.lock() {
+        *slot = None;
+    }
+    if let Ok(mut slot) = PERMANENT_FAILURE.lock() {
+        *slot = None;
+    }
+    record_poisoned_resolvers(&[]);
 
     // Extract domain
     let domain_str: String = match env.get_string(&domain) {
         Ok(s) => s.into(),
         Err(e) => {
-            error!("Failed to get domain string: {:?}", e);
+            jni_failure(env, "Failed to get domain string", e);
             return -1;
         }
     };
 
-    if domain_str.is_empty() {
-        error!("Domain is empty");
-        return -1;
-    }
+    // Same rules as the CLI: lowercase, no trailing dot.
+    let domain_str = match normalize_domain(&domain_str) {
+        Ok(domain) => domain,
+        Err(e) => {
+            error!("Invalid domain: {}", e);
+            return -1;
+        }
+    };
 
     // Extract listen host
     let listen_host_str: String = match env.get_string(&listen_host) {
         Ok(s) => s.into(),
         Err(e) => {
-            error!("Failed to get listen host string: {:?}", e);
-            return -2;
+            jni_failure(env, "Failed to get listen host string", e);
+            return START_INVALID_RESOLVERS;
         }
     };
 
@@ -359,121 +766,140 @@ fn start_client_impl<'local>(
     let cc_str: String = match env.get_string(&congestion_control) {
         Ok(s) => s.into(),
         Err(e) => {
-            error!("Failed to get congestion control string: {:?}", e);
-            return -2;
+            jni_failure(env, "Failed to get congestion control string", e);
+            return START_INVALID_RESOLVERS;
         }
     };
     let cc_option = if cc_str.is_empty() { None } else { Some(cc_str) };
 
-    // Extract resolver configuration
-    let resolver_count = match env.get_array_length(&resolver_hosts) {
-        Ok(len) => len as usize,
-        Err(e) => {
-            error!("Failed to get resolver hosts length: {:?}", e);
-            return -2;
-        }
-    };
-
-    if resolver_count == 0 {
-        error!("No resolvers provided");
-        return -2;
+    // The previous thread has exited, but its listener may take a moment to
+    // be released; wait for the port itself rather than a fixed delay.
+    let listen_port_u16 = listen_port as u16;
+    if !wait_for_listen_port(&listen_host_str, listen_port_u16) {
+        warn!(
+            "Listen port {}:{} still busy, starting anyway",
+            listen_host_str, listen_port_u16
+        );
     }
 
     // Wrap raw arrays in safe JNI types
     let resolver_ports_arr = unsafe { JIntArray::from_raw(resolver_ports) };
     let resolver_auth_arr = unsafe { JBooleanArray::from_raw(resolver_authoritative) };
 
+    // Check every resolver array has the same length before reading regions,
+    // so a Java-side mismatch fails cleanly instead of throwing.
+    let mut lengths = [0usize; 4];
+    let length_results = [
+        ("resolver hosts", env.get_array_length(&resolver_hosts)),
+        ("resolver ports", env.get_array_length(&resolver_ports_arr)),
+        ("authoritative flags", env.get_array_length(&resolver_auth_arr)),
+        ("resolver overrides", env.get_array_length(&resolver_overrides)),
+    ];
+    for (slot, (what, result)) in lengths.iter_mut().zip(length_results) {
+        match result {
+            Ok(len) => *slot = len.max(0) as usize,
+            Err(e) => {
+                jni_failure(env, &format!("Failed to get {} length", what), e);
+                return START_INVALID_RESOLVERS;
+            }
+        }
+    }
+    let [hosts_len, ports_len, auth_len, overrides_len] = lengths;
+    let array_lengths = ResolverArrayLengths {
+        hosts: hosts_len,
+        ports: ports_len,
+        authoritative: auth_len,
+        overrides: overrides_len,
+    };
+    let resolver_count = match array_lengths.resolver_count() {
+        Ok(count) => count,
+        Err(e) => {
+            error!("{}", e);
+            return e.code();
+        }
+    };
+
     // Get ports array using get_array_region which is more portable
     let mut ports: Vec<i32> = vec![0; resolver_count];
     if let Err(e) = env.get_int_array_region(&resolver_ports_arr, 0, &mut ports) {
-        error!("Failed to get resolver ports: {:?}", e);
-        return -2;
+        jni_failure(env, "Failed to get resolver ports", e);
+        return START_INVALID_RESOLVERS;
     }
 
     // Get authoritative flags using get_array_region
     let mut auth_flags: Vec<u8> = vec![0; resolver_count];
     if let Err(e) = env.get_boolean_array_region(&resolver_auth_arr, 0, &mut auth_flags) {
-        error!("Failed to get authoritative flags: {:?}", e);
-        return -2;
+        jni_failure(env, "Failed to get authoritative flags", e);
+        return START_INVALID_RESOLVERS;
     }
 
-    // Build resolver specs
-    let mut resolvers: Vec<ResolverSpec> = Vec::with_capacity(resolver_count);
+    // Copy host strings and their optional fixed addresses
+    let mut hosts: Vec<String> = Vec::with_capacity(resolver_count);
+    let mut overrides: Vec<String> = Vec::with_capacity(resolver_count);
     for i in 0..resolver_count {
-        // Get host string
-        let host_obj: JObject = match env.get_object_array_element(&resolver_hosts, i as i32) {
-            Ok(obj) => obj,
-            Err(e) => {
-                error!("Failed to get resolver host at index {}: {:?}", i, e);
-                return -2;
-            }
-        };
-        let host_jstr = JString::from(host_obj);
-        let host: String = match env.get_string(&host_jstr) {
-            Ok(s) => s.into(),
+        for (array, out, what) in [
+            (&resolver_hosts, &mut hosts, "resolver host"),
+            (&resolver_overrides, &mut overrides, "resolver override"),
+        ] {
+            let obj: JObject = match env.get_object_array_element(array, i as i32) {
+                Ok(obj) => obj,
+                Err(e) => {
+                    jni_failure(env, &format!("Failed to get {} at index {}", what, i), e);
+                    return START_INVALID_RESOLVERS;
+                }
+            };
+            let jstr = JString::from(obj);
+            let value: String = match env.get_string(&jstr) {
+                Ok(s) => s.into(),
+                Err(e) => {
+                    let what = format!("Failed to convert {} at index {}", what, i);
+                    jni_failure(env, &what, e);
+                    return START_INVALID_RESOLVERS;
+                }
+            };
+            out.push(value);
+        }
+    }
+
+    let (resolvers, host_overrides) =
+        match build_resolver_specs(hosts, &ports, &auth_flags, &overrides) {
+            Ok(args) => args,
             Err(e) => {
-                error!("Failed to convert resolver host at index {}: {:?}", i, e);
-                return -2;
+                error!("{}", e);
+                return e.code();
             }
         };
 
-        let port = ports[i] as u16;
-        let authoritative = auth_flags[i] != 0;
-
-        let mode = if authoritative {
-            ResolverMode::Authoritative
-        } else {
-            ResolverMode::Recursive
-        };
-
-        // Use V4 as default address family - DNS over UDP typically uses IPv4
-        resolvers.push(ResolverSpec {
-            resolver: HostPort {
-                host,
-                port,
-                family: slipstream_core::AddressFamily::V4,
-            },
-            mode,
-        });
-    }
-
     info!(
         "Starting client: domain={}, resolvers={}, port={}, host={}",
         domain_str, resolver_count, listen_port, listen_host_str
     );
 
-    // Mark as running
-    IS_RUNNING.store(true, Ordering::SeqCst);
-
     // Spawn client thread
-    let listen_port_u16 = listen_port as u16;
     let keep_alive = keep_alive_interval as usize;
     let gso = gso_enabled != JNI_FALSE;
     let dbg_poll = debug_poll != JNI_FALSE;
     let dbg_streams = debug_streams != JNI_FALSE;
     let idle_poll_ms = idle_poll_interval.max(0) as u64;
 
-    let handle = thread::Builder::new()
-        .name("slipstream-client".to_string())
-        .spawn(move || {
-            run_client_thread(
-                domain_str,
-                resolvers,
-                listen_port_u16,
-                listen_host_str,
-                cc_option,
-                keep_alive,
-                gso,
-                dbg_poll,
-                dbg_streams,
-                idle_poll_ms,
-            );
-        });
+    let spawned = SUPERVISOR.spawn("slipstream-client", move || {
+        run_client_thread(
+            domain_str,
+            resolvers,
+            host_overrides,
+            listen_port_u16,
+            listen_host_str,
+            cc_option,
+            keep_alive,
+            gso,
+            dbg_poll,
+            dbg_streams,
+            idle_poll_ms,
+        );
+    });
 
-    match handle {
-        Ok(h) => {
-            let mut guard = CLIENT_THREAD.lock().unwrap();
-            *guard = Some(h);
+    match spawned {
+        Ok(()) => {
             info!("Client thread spawned successfully");
 
             // Wait for listener to be ready (up to 5 seconds)
@@ -482,7 +908,7 @@ fn start_client_impl<'local>(
                     info!("Listener confirmed ready");
                     return 0;
                 }
-                if !IS_RUNNING.load(Ordering::SeqCst) {
+                if !SUPERVISOR.is_running() {
                     error!("Client stopped before listener ready");
                     return -11;
                 }
@@ -499,7 +925,6 @@ fn start_client_impl<'local>(
         }
         Err(e) => {
             error!("Failed to spawn client thread: {:?}", e);
-            IS_RUNNING.store(false, Ordering::SeqCst);
             -10
         }
     }
@@ -508,6 +933,7 @@ fn start_client_impl<'local>(
 fn run_client_thread(
     domain: String,
     resolvers: Vec<ResolverSpec>,
+    host_overrides: Vec<(String, IpAddr)>,
     listen_port: u16,
     listen_host: String,
     congestion_control: Option<String>,
@@ -523,15 +949,63 @@ fn run_client_thread(
         let config = ClientConfig {
             tcp_listen_host: &listen_host,
             tcp_listen_port: listen_port,
+            // The Android proxy only serves the local VPN service; never expose it to the LAN.
+            loopback_only: true,
             resolvers: &resolvers,
+            host_overrides,
             domain: &domain,
+            rotation_domains: &[],
             cert: None, // TODO: Support certificate pinning from Android
             congestion_control: congestion_control.as_deref(),
             gso,
             keep_alive_interval,
+            keep_alive_path: KeepAlivePath::Any,
             debug_poll,
             debug_streams,
             idle_poll_interval_ms,
+            watchdog_timeout_ms: WATCHDOG_TIMEOUT_MS,
+            reconnect_poll_grace_ms: RECONNECT_POLL_GRACE_MS,
+            usable_path_timeout_ms: USABLE_PATH_TIMEOUT_MS,
+            min_reconnect_interval_ms: MIN_RECONNECT_INTERVAL_MS,
+            cid_rotation_interval_ms: CID_ROTATION_INTERVAL_MS,
+            domain_rotation_interval_ms: 0,
+            domain_rotation_bytes: 0,
+            battery_saver_poll_interval_ms: Some(BATTERY_SAVER_POLL_INTERVAL_MS),
+            zero_send_reset: zero_send_reset_from_code(ZERO_SEND_RESET.load(Ordering::SeqCst))
+                .unwrap_or(ZeroSendReset::Off),
+            zero_send_stall_iterations: DEFAULT_ZERO_SEND_STALL_ITERATIONS,
+            stream_path_affinity: false,
+            require_authoritative_path: false,
+            authoritative_path_timeout_ms: AUTHORITATIVE_PATH_TIMEOUT_MS,
+            authoritative_path_timeout_action: AuthoritativePathTimeout::Fallback,
+            ready_requires_usable_path: false,
+            data_poll_bias_percent: DATA_POLL_BIAS_PERCENT,
+            send_batch_size: SEND_BATCH_SIZE,
+            reconnect_delay_min_ms: RECONNECT_DELAY_MIN_MS,
+            reconnect_delay_max_ms: RECONNECT_DELAY_MAX_MS,
+            debug_stream_checksums: false,
+            stateless_reset_tolerance: STATELESS_RESET_TOLERANCE,
+            qlog_dir: None,
+            service_label: None,
+            mtu_probe: false,
+            path_callbacks: true,
+            ticket_store_key: TICKET_STORE_KEY.lock().ok().and_then(|key| *key),
+            stop_sending_half_close: false,
+            key_update_interval_ms: 0,
+            key_update_bytes: 0,
+            terminal_failure_repeats: TERMINAL_FAILURE_REPEATS,
+            stream_pool_size: 0,
+            command_channel_capacity: 0,
+            reconnect_on_zero_credit: false,
+            max_idle_sleep_ms: MAX_IDLE_SLEEP_MS,
+            debug_stream_trailers: false,
+            heartbeat_interval_ms: 0,
+            profile: Profile::Balanced,
+            max_local_streams: 0,
+            closing_timeout_ms: DEFAULT_CLOSING_TIMEOUT_MS,
+            ecs_opt_out: false,
+            max_streams_floor: 0,
+            response_size_feedback_percent: 0,
         };
 
         // Build tokio runtime
@@ -554,6 +1028,11 @@ fn run_client_thread(
             }
             Err(e) => {
                 error!("Client error: {:?}", e);
+                if e.is_permanent() {
+                    if let Ok(mut slot) = PERMANENT_FAILURE.lock() {
+                        *slot = Some(e.to_string());
+                    }
+                }
             }
         }
     }));
@@ -562,11 +1041,9 @@ fn run_client_thread(
         error!("Panic in client thread: {:?}", e);
     }
 
-    // Cleanup
-    IS_RUNNING.store(false, Ordering::SeqCst);
+    // Cleanup; the supervisor marks the thread done once this returns.
     IS_LISTENER_READY.store(false, Ordering::SeqCst);
     IS_QUIC_READY.store(false, Ordering::SeqCst);
-    IS_THREAD_DONE.store(true, Ordering::SeqCst);
 
     info!("Client thread finished");
 }
@@ -587,36 +1064,12 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStopSlipst
 ) {
     info!("nativeStopSlipstreamClient called");
 
-    // Signal shutdown
-    SHOULD_SHUTDOWN.store(true, Ordering::SeqCst);
-
-    // Give the client thread time to exit gracefully
-    let mut waited = 0;
-    while !IS_THREAD_DONE.load(Ordering::SeqCst) && waited < 3000 {
-        thread::sleep(std::time::Duration::from_millis(100));
-        waited += 100;
-    }
-
-    if !IS_THREAD_DONE.load(Ordering::SeqCst) {
-        warn!("Client thread did not exit within timeout, abandoning");
-        // Abandon the thread handle to avoid blocking
-        let mut guard = CLIENT_THREAD.lock().unwrap();
-        if let Some(handle) = guard.take() {
-            std::mem::forget(handle);
-        }
-        // Leave SHOULD_SHUTDOWN=true so the abandoned thread sees it and exits,
-        // releasing the TCP listener port. The next nativeStart resets it.
-    } else {
-        // Join the thread if it exited
-        let mut guard = CLIENT_THREAD.lock().unwrap();
-        if let Some(handle) = guard.take() {
-            let _ = handle.join();
-        }
-        SHOULD_SHUTDOWN.store(false, Ordering::SeqCst);
-    }
+    // Signal shutdown and give the client thread time to exit gracefully. An
+    // abandoned thread keeps seeing the shutdown request and releases the TCP
+    // listener port; the next nativeStart waits for it.
+    SUPERVISOR.stop();
 
     // Reset state
-    IS_RUNNING.store(false, Ordering::SeqCst);
     IS_LISTENER_READY.store(false, Ordering::SeqCst);
     IS_QUIC_READY.store(false, Ordering::SeqCst);
 
@@ -629,7 +1082,7 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsClientRu
     _env: JNIEnv,
     _class: JClass,
 ) -> jboolean {
-    if IS_RUNNING.load(Ordering::SeqCst) {
+    if SUPERVISOR.is_running() {
         JNI_TRUE
     } else {
         JNI_FALSE
@@ -649,6 +1102,482 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsQuicRead
     }
 }
 
+/// Negotiated QUIC version (raw 32-bit value), or 0 when not connected.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetQuicVersion(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    QUIC_VERSION.load(Ordering::SeqCst) as jint
+}
+
+/// Negotiated TLS cipher suite ID, or 0 when not connected.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetCipherSuite(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    CIPHER_SUITE.load(Ordering::SeqCst) as jint
+}
+
+/// Get the authoritative path gate state of the current connection
+/// (see AUTHORITATIVE_GATE_STATE for the codes).
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetAuthoritativeGateState(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    AUTHORITATIVE_GATE_STATE.load(Ordering::SeqCst) as jint
+}
+
+/// Get the tunnel memory estimate in bytes (buffers, resolver tables and
+/// queued stream data), or 0 before the client loop has reported one.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetMemoryUsage(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    MEMORY_USAGE_BYTES.load(Ordering::SeqCst) as jlong
+}
+
+/// Get the session accounting totals as `[bytes_up, bytes_down,
+/// streams_opened]`. They keep counting across reconnects and reset on the
+/// next start; returns null if the array cannot be allocated.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetSessionCounters(
+    env: JNIEnv,
+    _class: JClass,
+) -> jlongArray {
+    let values = [
+        SESSION_BYTES_UP.load(Ordering::SeqCst) as jlong,
+        SESSION_BYTES_DOWN.load(Ordering::SeqCst) as jlong,
+        SESSION_STREAMS_OPENED.load(Ordering::SeqCst) as jlong,
+    ];
+    let array = match env.new_long_array(values.len() as jint) {
+        Ok(array) => array,
+        Err(e) => {
+            error!("Failed to allocate session counters array: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    if let Err(e) = env.set_long_array_region(&array, 0, &values) {
+        error!("Failed to fill session counters array: {:?}", e);
+        return std::ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+/// Get the session's stream time-to-first-byte as `[count, p50_ms, p95_ms,
+/// p99_ms]`, measured from local accept to the first response byte written
+/// back. Percentiles are bucket upper bounds and all zero without samples;
+/// returns null if the array cannot be allocated.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetTtfbPercentiles(
+    env: JNIEnv,
+    _class: JClass,
+) -> jlongArray {
+    let values = [
+        TTFB_COUNT.load(Ordering::SeqCst) as jlong,
+        TTFB_P50_MS.load(Ordering::SeqCst) as jlong,
+        TTFB_P95_MS.load(Ordering::SeqCst) as jlong,
+        TTFB_P99_MS.load(Ordering::SeqCst) as jlong,
+    ];
+    let array = match env.new_long_array(values.len() as jint) {
+        Ok(array) => array,
+        Err(e) => {
+            error!("Failed to allocate TTFB percentiles array: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    if let Err(e) = env.set_long_array_region(&array, 0, &values) {
+        error!("Failed to fill TTFB percentiles array: {:?}", e);
+        return std::ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+/// Get the local listener's accepts as `[accepts_per_sec, accepted,
+/// blocked_on_credit, vetoed, errors, blocked_on_queue]`. A high
+/// blocked_on_credit next to a low rate means the server's stream credit is
+/// the bottleneck rather than the local apps; blocked_on_queue counts
+/// connections that waited at the command channel cap. Totals reset on the next
+/// start; returns null if the array cannot be allocated.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetAcceptCounters(
+    env: JNIEnv,
+    _class: JClass,
+) -> jlongArray {
+    let values = [
+        ACCEPTS_PER_SEC.load(Ordering::SeqCst) as jlong,
+        ACCEPTS.load(Ordering::SeqCst) as jlong,
+        ACCEPTS_BLOCKED_ON_CREDIT.load(Ordering::SeqCst) as jlong,
+        ACCEPTS_VETOED.load(Ordering::SeqCst) as jlong,
+        ACCEPT_ERRORS.load(Ordering::SeqCst) as jlong,
+        ACCEPTS_BLOCKED_ON_QUEUE.load(Ordering::SeqCst) as jlong,
+    ];
+    let array = match env.new_long_array(values.len() as jint) {
+        Ok(array) => array,
+        Err(e) => {
+            error!("Failed to allocate accept counters array: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    if let Err(e) = env.set_long_array_region(&array, 0, &values) {
+        error!("Failed to fill accept counters array: {:?}", e);
+        return std::ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+/// Get the tunnel's smoothed RTT in microseconds, averaged over the resolver
+/// paths by their traffic, or -1 while the connection is not ready or no
+/// path has been measured yet.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetRtt(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    match RTT_US.load(Ordering::SeqCst) {
+        0 => -1,
+        rtt_us => rtt_us as jlong,
+    }
+}
+
+/// Get the smoothed RTT of each measured resolver path as comma-separated
+/// `ip:port=micros` entries, or null when there is none.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetPathRtts(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let rtts = match PATH_RTTS.lock() {
+        Ok(slot) if !slot.is_empty() => slot
+            .iter()
+            .map(|(addr, rtt_us)| format!("{}={}", addr, rtt_us))
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => return std::ptr::null_mut(),
+    };
+    match env.new_string(rtts) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to allocate path RTT string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Check whether local connects are waiting on a ready connection whose
+/// server grants no streams (an initial bidirectional stream limit of 0).
+/// This does not heal by itself; the server's stream limit needs fixing.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsAcceptorBlockedOnZeroCredit(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    if ACCEPTOR_ZERO_CREDIT.load(Ordering::SeqCst) {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
+
+/// Get the number of zero stream credit stalls since the client was
+/// started.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetZeroCreditStalls(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    ZERO_CREDIT_STALLS.load(Ordering::SeqCst) as jlong
+}
+
+/// Get the idle tunnel overhead as [bytesPerHour, bytes, idleSeconds]:
+/// keep-alive and poll bytes sent while idle and the idle time they cover.
+/// Totals reset on the next start; returns null if the array cannot be
+/// allocated.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetIdleOverhead(
+    env: JNIEnv,
+    _class: JClass,
+) -> jlongArray {
+    let values = [
+        IDLE_OVERHEAD_BYTES_PER_HOUR.load(Ordering::SeqCst) as jlong,
+        IDLE_OVERHEAD_BYTES.load(Ordering::SeqCst) as jlong,
+        IDLE_SECONDS.load(Ordering::SeqCst) as jlong,
+    ];
+    let array = match env.new_long_array(values.len() as jint) {
+        Ok(array) => array,
+        Err(e) => {
+            error!("Failed to allocate idle overhead array: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    if let Err(e) = env.set_long_array_region(&array, 0, &values) {
+        error!("Failed to fill idle overhead array: {:?}", e);
+        return std::ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+/// Get the keep-alive traffic as [queries, bytes]: queries sent while the
+/// connection was ready with no stream open, which are picoquic's PINGs and
+/// ACKs, plus keep-alive polls. Totals reset on the next start; returns null
+/// if the array cannot be allocated.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetKeepAliveTraffic(
+    env: JNIEnv,
+    _class: JClass,
+) -> jlongArray {
+    let values = [
+        KEEP_ALIVE_QUERIES.load(Ordering::SeqCst) as jlong,
+        KEEP_ALIVE_BYTES.load(Ordering::SeqCst) as jlong,
+    ];
+    let array = match env.new_long_array(values.len() as jint) {
+        Ok(array) => array,
+        Err(e) => {
+            error!("Failed to allocate keep-alive traffic array: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    if let Err(e) = env.set_long_array_region(&array, 0, &values) {
+        error!("Failed to fill keep-alive traffic array: {:?}", e);
+        return std::ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+/// Get the effective configuration summary logged by the last client start
+/// (secrets redacted), or null before the client has logged one.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetConfigSummary(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let Some(summary) = CONFIG_SUMMARY.lock().ok().and_then(|slot| slot.clone()) else {
+        return std::ptr::null_mut();
+    };
+    match env.new_string(summary) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to allocate config summary string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Keep the most recent log lines, up to `bytes` (at most 4 MiB), for
+/// `nativeGetRecentLogs`; 0 (the default) stops recording and clears them.
+/// Survives client restarts. Returns the capacity in effect.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetRecentLogBytes(
+    _env: JNIEnv,
+    _class: JClass,
+    bytes: jint,
+) -> jint {
+    let bytes = RECENT_LOGS.set_capacity(usize::try_from(bytes).unwrap_or(0));
+    info!("Recent log buffer set to {} bytes", bytes);
+    bytes as jint
+}
+
+/// Get the recorded log lines, oldest first and one per line, or null when
+/// recording is off or nothing has been logged since it was turned on.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetRecentLogs(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let logs = RECENT_LOGS.snapshot();
+    if logs.is_empty() {
+        return std::ptr::null_mut();
+    }
+    match env.new_string(logs) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to allocate recent logs string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get the error the last run gave up with without using up its failure
+/// limit, because every attempt failed the same way (e.g. a certificate
+/// pin mismatch), or null. Cleared on start.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetPermanentFailure(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let Some(failure) = PERMANENT_FAILURE.lock().ok().and_then(|slot| slot.clone()) else {
+        return std::ptr::null_mut();
+    };
+    match env.new_string(failure) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to allocate permanent failure string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get the resolvers judged poisoned since the client was started, as
+/// comma-separated `ip:port` addresses, or null when there are none.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetPoisonedResolvers(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let addrs = match POISONED_RESOLVERS.lock() {
+        Ok(slot) if !slot.is_empty() => slot.join(","),
+        _ => return std::ptr::null_mut(),
+    };
+    match env.new_string(addrs) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to allocate poisoned resolvers string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get the number of connections ended by a stateless reset since the
+/// client was started. A count that climbs while the server stays up points
+/// at resets injected on the path rather than server restarts.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetStatelessResets(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    STATELESS_RESETS.load(Ordering::SeqCst) as jlong
+}
+
+/// Get the number of consecutive connections that never became ready.
+/// The client gives up once this reaches the start-time limit.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetConsecutiveFailures(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    CONSECUTIVE_FAILURES.load(Ordering::SeqCst)
+}
+
+/// Clear the consecutive failure count, e.g. after the user retries or the
+/// network comes back, so a running client gets the full limit again.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeResetConsecutiveFailures(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    let previous = CONSECUTIVE_FAILURES.swap(0, Ordering::SeqCst);
+    info!("Consecutive failure count reset (was {})", previous);
+}
+
+/// Switch the pacing profile: 0 balanced (default), 1 turbo (aggressive
+/// authoritative-style polling), 2 compatibility (conservative, safe for
+/// recursive resolvers). Applies on the next loop iteration without a
+/// restart. Returns false and keeps the current profile for unknown codes.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetPacingProfile(
+    _env: JNIEnv,
+    _class: JClass,
+    profile: jint,
+) -> jboolean {
+    match PacingProfile::from_code(profile) {
+        Some(parsed) => {
+            PACING_PROFILE.store(parsed.code(), Ordering::SeqCst);
+            info!("Pacing profile set to {:?}", parsed);
+            JNI_TRUE
+        }
+        None => {
+            warn!("Unknown pacing profile {}", profile);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Get the current pacing profile code.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetPacingProfile(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    pacing_profile().code()
+}
+
+/// Choose the last step of zero-send stall recovery: 0 off (default), 1
+/// reset the stream with the largest backlog, 2 reconnect. Applies on the
+/// next start. Returns false and keeps the current mode for unknown codes.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetZeroSendReset(
+    _env: JNIEnv,
+    _class: JClass,
+    mode: jint,
+) -> jboolean {
+    match zero_send_reset_from_code(mode) {
+        Some(parsed) => {
+            ZERO_SEND_RESET.store(mode, Ordering::SeqCst);
+            info!("Zero-send reset set to {:?}", parsed);
+            JNI_TRUE
+        }
+        None => {
+            warn!("Unknown zero-send reset mode {}", mode);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Enable or disable battery saver polling (called on screen off/on).
+///
+/// Takes effect on the next loop iteration and survives client restarts.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetBatterySaver(
+    _env: JNIEnv,
+    _class: JClass,
+    active: jboolean,
+) {
+    let active = active != JNI_FALSE;
+    BATTERY_SAVER_ACTIVE.store(active, Ordering::SeqCst);
+    info!("Battery saver {}", if active { "enabled" } else { "disabled" });
+}
+
+/// Set the 32-byte key that encrypts the session ticket store, or clear it
+/// with null so tickets are stored in plaintext. Applies on the next start.
+/// Returns false and keeps the current key when the length is wrong. The
+/// key is reserved until the client persists tickets.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetTicketStoreKey<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    key: JByteArray<'local>,
+) -> jboolean {
+    let key = if key.as_raw().is_null() {
+        None
+    } else {
+        let bytes = match env.convert_byte_array(&key) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!("Could not read ticket store key: {}", err);
+                return JNI_FALSE;
+            }
+        };
+        match <[u8; 32]>::try_from(bytes.as_slice()) {
+            Ok(key) => Some(TicketStoreKey(key)),
+            Err(_) => {
+                warn!(
+                    "Ticket store key must be 32 bytes, got {}; keeping the current key",
+                    bytes.len()
+                );
+                return JNI_FALSE;
+            }
+        }
+    };
+    let Ok(mut slot) = TICKET_STORE_KEY.lock() else {
+        return JNI_FALSE;
+    };
+    *slot = key;
+    JNI_TRUE
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -659,17 +1588,16 @@ mod tests {
 
     #[test]
     fn test_state_flags() {
-        // Initial state
-        assert!(!IS_RUNNING.load(Ordering::SeqCst));
+        // Initial state; the running flag itself is covered by the
+        // supervisor's tests.
+        assert!(!SUPERVISOR.is_running());
         assert!(!IS_LISTENER_READY.load(Ordering::SeqCst));
         assert!(!IS_QUIC_READY.load(Ordering::SeqCst));
 
         // Set flags
-        IS_RUNNING.store(true, Ordering::SeqCst);
         signal_listener_ready();
         signal_quic_ready();
 
-        assert!(IS_RUNNING.load(Ordering::SeqCst));
         assert!(IS_LISTENER_READY.load(Ordering::SeqCst));
         assert!(IS_QUIC_READY.load(Ordering::SeqCst));
 
@@ -678,23 +1606,58 @@ mod tests {
         assert!(!IS_QUIC_READY.load(Ordering::SeqCst));
 
         // Cleanup
-        IS_RUNNING.store(false, Ordering::SeqCst);
         IS_LISTENER_READY.store(false, Ordering::SeqCst);
     }
 
+    #[test]
+    fn attach_retry_is_bounded() {
+        let mut calls = 0;
+        let result: Result<(), &str> = retry_attach(3, 0, || {
+            calls += 1;
+            Err("detached")
+        });
+        assert_eq!(result, Err("detached"));
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result = retry_attach(3, 0, || {
+            calls += 1;
+            if calls < 2 {
+                Err("detached")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(2));
+    }
+
     #[test]
     fn test_failure_tracking() {
         CONSECUTIVE_FAILURES.store(0, Ordering::SeqCst);
 
         assert!(!exceeded_max_failures());
 
-        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+        for _ in 0..DEFAULT_MAX_CONSECUTIVE_FAILURES {
             record_connection_failure();
         }
 
         assert!(exceeded_max_failures());
 
+        // A limit of 0 never gives up
+        MAX_CONSECUTIVE_FAILURES.store(0, Ordering::SeqCst);
+        assert!(!exceeded_max_failures());
+
         // Reset
+        MAX_CONSECUTIVE_FAILURES.store(DEFAULT_MAX_CONSECUTIVE_FAILURES, Ordering::SeqCst);
         CONSECUTIVE_FAILURES.store(0, Ordering::SeqCst);
     }
+
+    #[test]
+    fn test_listen_port_probe() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(!listen_port_bindable("127.0.0.1", port));
+        drop(listener);
+        assert!(listen_port_bindable("127.0.0.1", port));
+    }
 }
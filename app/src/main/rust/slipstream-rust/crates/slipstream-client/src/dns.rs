@@ -1,13 +1,22 @@
 mod debug;
+mod ecs;
 mod path;
+mod poison;
 mod poll;
 mod resolver;
 mod response;
+mod send;
 
-pub(crate) use debug::maybe_report_debug;
+pub(crate) use debug::{
+    bytes_per, bytes_per_hour, classify_send, maybe_report_debug, SendClass, TrafficCount,
+};
+pub(crate) use ecs::EcsResolvers;
 pub(crate) use path::{add_paths, refresh_resolver_path, resolver_mode_to_c};
-pub(crate) use poll::{expire_inflight_polls, send_poll_queries};
+pub(crate) use poison::PoisonedResolvers;
+pub(crate) use poll::{expire_inflight_polls, rebase_inflight_polls, send_poll_queries};
 pub(crate) use resolver::{
-    reset_resolver_path, resolve_resolvers, sockaddr_storage_to_socket_addr, ResolverState,
+    reset_resolver_path, resolve_resolvers, sockaddr_storage_to_socket_addr, ResolverAddrCache,
+    ResolverState,
 };
 pub(crate) use response::{handle_dns_response, DnsResponseContext};
+pub(crate) use send::{retry_query, send_query, SendOutcome};
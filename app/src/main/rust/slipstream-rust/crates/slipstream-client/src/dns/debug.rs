@@ -2,19 +2,100 @@ use crate::pacing::PacingBudgetSnapshot;
 use tracing::debug;
 
 use super::resolver::ResolverState;
+use super::send::SendOutcome;
 
 const DEBUG_REPORT_INTERVAL_US: u64 = 1_000_000;
+const US_PER_HOUR: u64 = 3_600_000_000;
+
+/// What an outgoing query carried, judged when it is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SendClass {
+    /// Sent before the connection was ready.
+    Handshake,
+    /// A poll query from `send_poll_queries`, asking for server data.
+    Poll,
+    /// Sent by the QUIC loop while no stream was open: PINGs, ACKs and
+    /// path upkeep.
+    KeepAlive,
+    /// Sent by the QUIC loop while streams were open.
+    Data,
+}
+
+/// Classify one sent query. Handshake comes first, so polls before ready
+/// count towards the handshake.
+pub(crate) fn classify_send(from_poll: bool, ready: bool, streams_open: bool) -> SendClass {
+    if !ready {
+        SendClass::Handshake
+    } else if from_poll {
+        SendClass::Poll
+    } else if streams_open {
+        SendClass::Data
+    } else {
+        SendClass::KeepAlive
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TrafficCount {
+    pub(crate) packets: u64,
+    pub(crate) bytes: u64,
+}
+
+/// Sent queries split by [`SendClass`]; the totals are in `send_packets`
+/// and `send_bytes`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SendCounters {
+    pub(crate) handshake: TrafficCount,
+    pub(crate) poll: TrafficCount,
+    pub(crate) keep_alive: TrafficCount,
+    pub(crate) data: TrafficCount,
+}
+
+impl SendCounters {
+    pub(crate) fn record(&mut self, class: SendClass, bytes: usize) {
+        let count = match class {
+            SendClass::Handshake => &mut self.handshake,
+            SendClass::Poll => &mut self.poll,
+            SendClass::KeepAlive => &mut self.keep_alive,
+            SendClass::Data => &mut self.data,
+        };
+        count.packets = count.packets.saturating_add(1);
+        count.bytes = count.bytes.saturating_add(bytes as u64);
+    }
+
+    /// Bytes an idle tunnel still sends: keep-alives and polls.
+    pub(crate) fn overhead_bytes(&self) -> u64 {
+        self.keep_alive.bytes.saturating_add(self.poll.bytes)
+    }
+}
+
+/// `bytes` sent over `elapsed_us`, scaled to an hour.
+pub(crate) fn bytes_per_hour(bytes: u64, elapsed_us: u64) -> u64 {
+    if elapsed_us == 0 {
+        return 0;
+    }
+    let per_hour = u128::from(bytes) * u128::from(US_PER_HOUR) / u128::from(elapsed_us);
+    u64::try_from(per_hour).unwrap_or(u64::MAX)
+}
 
 pub(crate) struct DebugMetrics {
     pub(crate) enabled: bool,
     pub(crate) last_report_at: u64,
     pub(crate) dns_responses: u64,
+    pub(crate) stale_responses: u64,
     pub(crate) zero_send_loops: u64,
     pub(crate) zero_send_with_streams: u64,
     pub(crate) enqueued_bytes: u64,
     pub(crate) send_packets: u64,
     pub(crate) send_bytes: u64,
     pub(crate) polls_sent: u64,
+    pub(crate) sends: SendCounters,
+    /// Queries that went out on the retry after a route change error.
+    pub(crate) send_retries: u64,
+    /// Queries lost to transient send errors, after any retry.
+    pub(crate) send_drops: u64,
+    /// QUIC payload bytes decoded from DNS responses.
+    pub(crate) response_bytes: u64,
     pub(crate) last_enqueue_at: u64,
     pub(crate) last_report_dns: u64,
     pub(crate) last_report_zero: u64,
@@ -23,20 +104,43 @@ pub(crate) struct DebugMetrics {
     pub(crate) last_report_send_packets: u64,
     pub(crate) last_report_send_bytes: u64,
     pub(crate) last_report_polls: u64,
+    pub(crate) last_report_response_bytes: u64,
+    pub(crate) last_report_sends: SendCounters,
 }
 
 impl DebugMetrics {
+    /// Count one sent query of `bytes` under `class` and in the totals.
+    pub(crate) fn record_send(&mut self, class: SendClass, bytes: usize) {
+        self.send_packets = self.send_packets.saturating_add(1);
+        self.send_bytes = self.send_bytes.saturating_add(bytes as u64);
+        self.sends.record(class, bytes);
+    }
+
+    /// Count a retried or dropped query.
+    pub(crate) fn record_send_outcome(&mut self, outcome: SendOutcome) {
+        match outcome {
+            SendOutcome::Sent => {}
+            SendOutcome::Retried => self.send_retries = self.send_retries.saturating_add(1),
+            SendOutcome::Dropped => self.send_drops = self.send_drops.saturating_add(1),
+        }
+    }
+
     pub(crate) fn new(enabled: bool) -> Self {
         Self {
             enabled,
             last_report_at: 0,
             dns_responses: 0,
+            stale_responses: 0,
             zero_send_loops: 0,
             zero_send_with_streams: 0,
             enqueued_bytes: 0,
             send_packets: 0,
             send_bytes: 0,
             polls_sent: 0,
+            sends: SendCounters::default(),
+            send_retries: 0,
+            send_drops: 0,
+            response_bytes: 0,
             last_enqueue_at: 0,
             last_report_dns: 0,
             last_report_zero: 0,
@@ -45,10 +149,18 @@ impl DebugMetrics {
             last_report_send_packets: 0,
             last_report_send_bytes: 0,
             last_report_polls: 0,
+            last_report_response_bytes: 0,
+            last_report_sends: SendCounters::default(),
         }
     }
 }
 
+/// Average tunnel bytes per query or response; a low value means DNS
+/// overhead dominates and a shorter domain or larger record type may help.
+pub(crate) fn bytes_per(bytes: u64, count: u64) -> u64 {
+    bytes.checked_div(count).unwrap_or(0)
+}
+
 pub(crate) fn maybe_report_debug(
     resolver: &mut ResolverState,
     now: u64,
@@ -72,6 +184,7 @@ pub(crate) fn maybe_report_debug(
         return;
     }
     let dns_delta = debug.dns_responses.saturating_sub(debug.last_report_dns);
+    let stale_total = debug.stale_responses;
     let zero_delta = debug.zero_send_loops.saturating_sub(debug.last_report_zero);
     let zero_stream_delta = debug
         .zero_send_with_streams
@@ -86,6 +199,28 @@ pub(crate) fn maybe_report_debug(
         .send_bytes
         .saturating_sub(debug.last_report_send_bytes);
     let polls_delta = debug.polls_sent.saturating_sub(debug.last_report_polls);
+    let response_bytes_delta = debug
+        .response_bytes
+        .saturating_sub(debug.last_report_response_bytes);
+    let sends = debug.sends;
+    let (send_retries, send_drops) = (debug.send_retries, debug.send_drops);
+    let last_sends = debug.last_report_sends;
+    let class_bytes =
+        |count: TrafficCount, last: TrafficCount| count.bytes.saturating_sub(last.bytes);
+    let overhead_delta = sends
+        .overhead_bytes()
+        .saturating_sub(last_sends.overhead_bytes());
+    // Only an idle interval tells what the idle tunnel costs.
+    let idle_overhead = if is_idle {
+        format!(
+            " idle_overhead_bytes_per_hour={}",
+            bytes_per_hour(overhead_delta, elapsed)
+        )
+    } else {
+        String::new()
+    };
+    let bytes_per_query = bytes_per(send_bytes_delta, send_pkt_delta);
+    let bytes_per_response = bytes_per(response_bytes_delta, dns_delta);
     let enqueue_ms = if debug.last_enqueue_at == 0 {
         0
     } else {
@@ -93,19 +228,33 @@ pub(crate) fn maybe_report_debug(
     };
     let pacing_summary = if let Some(snapshot) = pacing_snapshot {
         format!(
-            " pacing_rate={} qps_target={:.2} target_inflight={} gain={:.2}",
-            snapshot.pacing_rate, snapshot.qps, snapshot.target_inflight, snapshot.gain
+            " pacing_rate={} qps_target={:.2} target_inflight={} gain={:.2} response_fill={:.2}",
+            snapshot.pacing_rate,
+            snapshot.qps,
+            snapshot.target_inflight,
+            snapshot.gain,
+            snapshot.response_fill
         )
     } else {
         String::new()
     };
     debug!(
-        "debug: {} dns+={} send_pkts+={} send_bytes+={} polls+={} zero_send+={} zero_send_streams+={} streams={} enqueued+={} last_enqueue_ms={} pending_polls={} inflight_polls={} idle={}{}",
+        "debug: {} dns+={} stale_dns={} send_pkts+={} send_bytes+={} handshake_bytes+={} poll_bytes+={} keep_alive_bytes+={} data_bytes+={} send_retries={} send_drops={} polls+={} recv_bytes+={} bytes_per_query={} bytes_per_response={} zero_send+={} zero_send_streams+={} streams={} enqueued+={} last_enqueue_ms={} pending_polls={} inflight_polls={} idle={}{}{}",
         label,
         dns_delta,
+        stale_total,
         send_pkt_delta,
         send_bytes_delta,
+        class_bytes(sends.handshake, last_sends.handshake),
+        class_bytes(sends.poll, last_sends.poll),
+        class_bytes(sends.keep_alive, last_sends.keep_alive),
+        class_bytes(sends.data, last_sends.data),
+        send_retries,
+        send_drops,
         polls_delta,
+        response_bytes_delta,
+        bytes_per_query,
+        bytes_per_response,
         zero_delta,
         zero_stream_delta,
         streams_len,
@@ -114,6 +263,7 @@ pub(crate) fn maybe_report_debug(
         pending_polls,
         inflight_polls,
         is_idle,
+        idle_overhead,
         pacing_summary
     );
     debug.last_report_at = now;
@@ -124,4 +274,43 @@ pub(crate) fn maybe_report_debug(
     debug.last_report_send_packets = debug.send_packets;
     debug.last_report_send_bytes = debug.send_bytes;
     debug.last_report_polls = debug.polls_sent;
+    debug.last_report_response_bytes = debug.response_bytes;
+    debug.last_report_sends = debug.sends;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bytes_per, bytes_per_hour, classify_send, SendClass, SendCounters};
+
+    #[test]
+    fn bytes_per_handles_empty_intervals() {
+        assert_eq!(bytes_per(0, 0), 0);
+        assert_eq!(bytes_per(1_000, 0), 0);
+        assert_eq!(bytes_per(1_000, 8), 125);
+    }
+
+    #[test]
+    fn sends_are_classified_by_readiness_origin_and_streams() {
+        assert_eq!(classify_send(false, false, false), SendClass::Handshake);
+        assert_eq!(classify_send(true, false, true), SendClass::Handshake);
+        assert_eq!(classify_send(true, true, false), SendClass::Poll);
+        assert_eq!(classify_send(true, true, true), SendClass::Poll);
+        assert_eq!(classify_send(false, true, false), SendClass::KeepAlive);
+        assert_eq!(classify_send(false, true, true), SendClass::Data);
+
+        let mut sends = SendCounters::default();
+        sends.record(SendClass::KeepAlive, 60);
+        sends.record(SendClass::Poll, 90);
+        sends.record(SendClass::Data, 140);
+        assert_eq!(sends.keep_alive.packets, 1);
+        assert_eq!(sends.overhead_bytes(), 150);
+    }
+
+    #[test]
+    fn idle_overhead_scales_to_an_hour() {
+        // 150 bytes over 30 seconds.
+        assert_eq!(bytes_per_hour(150, 30_000_000), 18_000);
+        assert_eq!(bytes_per_hour(150, 0), 0);
+        assert_eq!(bytes_per_hour(u64::MAX, 1), u64::MAX);
+    }
 }
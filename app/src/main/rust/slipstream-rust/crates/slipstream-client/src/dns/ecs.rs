@@ -0,0 +1,119 @@
+use slipstream_dns::EcsObservation;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use tracing::warn;
+
+use super::resolver::ResolverState;
+
+/// Notes an EDNS Client Subnet option in a response from `resolver`.
+///
+/// A resolver that echoes one with a non-zero source prefix most likely
+/// forwards part of the client's address with every query it relays, so the
+/// tunnel server's operator (and anyone watching near it) learns the
+/// client's network. Warns the first time a resolver is caught.
+pub(crate) fn observe_ecs(resolver: &mut ResolverState, observation: EcsObservation) {
+    if resolver.ecs_observed || !observation.may_reveal_subnet() {
+        return;
+    }
+    resolver.ecs_observed = true;
+    let detail = match observation {
+        EcsObservation::Present {
+            family,
+            source_prefix,
+            scope_prefix,
+        } => format!(
+            "family={} source_prefix={} scope_prefix={}",
+            family, source_prefix, scope_prefix
+        ),
+        _ => "malformed option".to_string(),
+    };
+    warn!(
+        "Resolver {} answered with EDNS Client Subnet ({}); it may forward part of this device's address to the tunnel server. --ecs-opt-out asks it not to",
+        resolver.addr, detail
+    );
+}
+
+/// Resolvers seen using EDNS Client Subnet during this client session.
+/// Kept across reconnects so each one is warned about once.
+#[derive(Debug, Default)]
+pub(crate) struct EcsResolvers {
+    addrs: HashSet<SocketAddr>,
+}
+
+impl EcsResolvers {
+    /// Take over what the current connection observed.
+    pub(crate) fn collect(&mut self, resolvers: &[ResolverState]) {
+        self.addrs.extend(
+            resolvers
+                .iter()
+                .filter(|resolver| resolver.ecs_observed)
+                .map(|resolver| resolver.addr),
+        );
+    }
+
+    /// Carry earlier observations onto a freshly resolved list.
+    pub(crate) fn mark(&self, resolvers: &mut [ResolverState]) {
+        for resolver in resolvers.iter_mut() {
+            resolver.ecs_observed = self.addrs.contains(&resolver.addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{observe_ecs, EcsResolvers};
+    use crate::dns::{resolve_resolvers, ResolverAddrCache, ResolverState};
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_dns::EcsObservation;
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    fn resolvers() -> Vec<ResolverState> {
+        let specs: Vec<_> = ["192.0.2.1", "192.0.2.2"]
+            .iter()
+            .map(|host| ResolverSpec {
+                resolver: HostPort {
+                    host: host.to_string(),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+                max_inflight_polls: 0,
+            })
+            .collect();
+        let mut resolved = Vec::new();
+        resolve_resolvers(
+            &specs,
+            &[],
+            &mut ResolverAddrCache::default(),
+            900,
+            false,
+            &mut resolved,
+        )
+        .expect("literal resolvers");
+        resolved
+    }
+
+    #[test]
+    fn only_subnet_carrying_options_flag_a_resolver_for_the_session() {
+        let mut list = resolvers();
+        observe_ecs(&mut list[0], EcsObservation::Absent);
+        observe_ecs(
+            &mut list[0],
+            EcsObservation::Present {
+                family: 1,
+                source_prefix: 0,
+                scope_prefix: 0,
+            },
+        );
+        assert!(!list[0].ecs_observed, "an opt-out echo reveals nothing");
+        observe_ecs(&mut list[1], EcsObservation::Malformed);
+        assert!(list[1].ecs_observed);
+
+        let mut session = EcsResolvers::default();
+        session.collect(&list);
+        let mut reconnected = resolvers();
+        session.mark(&mut reconnected);
+        assert!(!reconnected[0].ecs_observed);
+        assert!(reconnected[1].ecs_observed);
+    }
+}
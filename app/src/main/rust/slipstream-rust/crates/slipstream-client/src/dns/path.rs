@@ -1,6 +1,7 @@
 use crate::error::ClientError;
+use slipstream_core::clock::Clock;
 use slipstream_ffi::picoquic::{
-    picoquic_cnx_t, picoquic_current_time, picoquic_get_path_addr, picoquic_probe_new_path_ex,
+    picoquic_cnx_t, picoquic_get_path_addr, picoquic_probe_new_path_ex,
     slipstream_find_path_id_by_addr, slipstream_get_path_id_from_unique,
     slipstream_set_default_path_mode,
 };
@@ -46,6 +47,7 @@ pub(crate) fn refresh_resolver_path(
 pub(crate) fn add_paths(
     cnx: *mut picoquic_cnx_t,
     resolvers: &mut [ResolverState],
+    clock: &dyn Clock,
 ) -> Result<(), ClientError> {
     if resolvers.len() <= 1 {
         return Ok(());
@@ -56,7 +58,7 @@ pub(crate) fn add_paths(
     if ret != 0 {
         return Ok(());
     }
-    let now = unsafe { picoquic_current_time() };
+    let now = clock.now_us();
     let primary_mode = resolvers[0].mode;
     let mut default_mode = primary_mode;
 
@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use super::resolver::ResolverState;
+
+/// Responses per judgement window.
+const POISON_WINDOW_RESPONSES: u32 = 32;
+/// A window with at most this many accepted responses marks the resolver
+/// poisoned; genuine paths lose far fewer than 31 in 32.
+const POISON_MAX_ACCEPTED: u32 = 1;
+
+/// Whether picoquic accepts the QUIC payloads of one resolver's responses.
+///
+/// A resolver that forges TXT answers for the tunnel domain delivers
+/// responses that picoquic cannot match to the connection or decrypt.
+/// Responses are judged in fixed windows, so an old poor stretch does not
+/// outweigh recent ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IngestWindow {
+    responses: u32,
+    accepted: u32,
+    /// Responses picoquic rejected on this connection.
+    pub(crate) rejected_total: u64,
+}
+
+impl IngestWindow {
+    /// Returns true when this response closes a window in which nearly
+    /// every response was rejected.
+    pub(crate) fn record(&mut self, accepted: bool) -> bool {
+        self.responses += 1;
+        if accepted {
+            self.accepted += 1;
+        } else {
+            self.rejected_total = self.rejected_total.saturating_add(1);
+        }
+        if self.responses < POISON_WINDOW_RESPONSES {
+            return false;
+        }
+        let poisoned = self.accepted <= POISON_MAX_ACCEPTED;
+        self.responses = 0;
+        self.accepted = 0;
+        poisoned
+    }
+}
+
+/// Resolvers judged poisoned during this client session. Kept across
+/// reconnects so the next connection does not start on them again.
+#[derive(Debug, Default)]
+pub(crate) struct PoisonedResolvers {
+    addrs: HashSet<SocketAddr>,
+}
+
+impl PoisonedResolvers {
+    pub(crate) fn contains(&self, addr: SocketAddr) -> bool {
+        self.addrs.contains(&addr)
+    }
+
+    pub(crate) fn addrs(&self) -> Vec<SocketAddr> {
+        let mut addrs: Vec<_> = self.addrs.iter().copied().collect();
+        addrs.sort();
+        addrs
+    }
+
+    /// Take over the verdicts reached on the current connection and return
+    /// the resolvers that were not known to be poisoned yet.
+    pub(crate) fn collect(&mut self, resolvers: &[ResolverState]) -> Vec<SocketAddr> {
+        resolvers
+            .iter()
+            .filter(|resolver| resolver.poisoned && self.addrs.insert(resolver.addr))
+            .map(|resolver| resolver.addr)
+            .collect()
+    }
+
+    /// Mark known poisoned resolvers on a freshly resolved list and move
+    /// them behind the others, so the first clean one becomes the primary
+    /// path and poisoned ones are never added as paths. When every
+    /// resolver is poisoned the order is kept and false is returned; they
+    /// are still used, as there is nothing else.
+    pub(crate) fn demote(&self, resolvers: &mut [ResolverState]) -> bool {
+        for resolver in resolvers.iter_mut() {
+            resolver.poisoned = self.contains(resolver.addr);
+        }
+        if resolvers.iter().all(|resolver| resolver.poisoned) {
+            return resolvers.is_empty();
+        }
+        if !resolvers[0].poisoned {
+            return true;
+        }
+        // Stable, so the configured order holds within each group.
+        resolvers.sort_by_key(|resolver| resolver.poisoned);
+        for (idx, resolver) in resolvers.iter_mut().enumerate() {
+            let is_primary = idx == 0;
+            resolver.added = is_primary;
+            resolver.path_id = if is_primary { 0 } else { -1 };
+            resolver.unique_path_id = if is_primary { Some(0) } else { None };
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IngestWindow, PoisonedResolvers, POISON_WINDOW_RESPONSES};
+    use crate::dns::{resolve_resolvers, ResolverAddrCache, ResolverState};
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    fn resolvers(hosts: &[&str]) -> Vec<ResolverState> {
+        let specs: Vec<_> = hosts
+            .iter()
+            .map(|host| ResolverSpec {
+                resolver: HostPort {
+                    host: host.to_string(),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+                max_inflight_polls: 0,
+            })
+            .collect();
+        let mut resolved = Vec::new();
+        resolve_resolvers(
+            &specs,
+            &[],
+            &mut ResolverAddrCache::default(),
+            900,
+            false,
+            &mut resolved,
+        )
+        .expect("literal resolvers");
+        resolved
+    }
+
+    #[test]
+    fn a_window_of_rejected_responses_marks_the_resolver() {
+        let mut window = IngestWindow::default();
+        // One accepted response in a window is still poisoned.
+        assert!(!window.record(true));
+        for _ in 1..POISON_WINDOW_RESPONSES - 1 {
+            assert!(!window.record(false));
+        }
+        assert!(window.record(false));
+
+        // Two accepted in the next window clear it.
+        assert!(!window.record(true));
+        assert!(!window.record(true));
+        for _ in 2..POISON_WINDOW_RESPONSES - 1 {
+            assert!(!window.record(false));
+        }
+        assert!(!window.record(false));
+        assert_eq!(
+            window.rejected_total,
+            u64::from(POISON_WINDOW_RESPONSES) * 2 - 3
+        );
+    }
+
+    #[test]
+    fn poisoned_resolvers_move_behind_clean_ones() {
+        let mut list = resolvers(&["192.0.2.1", "192.0.2.2", "192.0.2.3"]);
+        let mut poisoned = PoisonedResolvers::default();
+        list[0].poisoned = true;
+        assert_eq!(poisoned.collect(&list), vec![list[0].addr]);
+        assert!(poisoned.collect(&list).is_empty());
+
+        let mut list = resolvers(&["192.0.2.1", "192.0.2.2", "192.0.2.3"]);
+        let addrs: Vec<_> = list.iter().map(|resolver| resolver.addr).collect();
+        assert!(poisoned.demote(&mut list));
+        let order: Vec<_> = list.iter().map(|resolver| resolver.addr).collect();
+        assert_eq!(order, [addrs[1], addrs[2], addrs[0]]);
+        assert!(list[0].added && list[0].path_id == 0);
+        assert!(!list[2].added && list[2].poisoned && list[2].path_id == -1);
+
+        let mut only = resolvers(&["192.0.2.1"]);
+        assert!(!poisoned.demote(&mut only));
+        assert!(only[0].poisoned && only[0].added);
+    }
+}
@@ -1,15 +1,17 @@
 use crate::error::ClientError;
-use slipstream_core::net::is_transient_udp_error;
-use slipstream_dns::{build_qname, encode_query, QueryParams, CLASS_IN, RR_TXT};
+use crate::runtime::QnameGuard;
+use slipstream_core::clock::Clock;
+use slipstream_dns::{encode_query_with_options, QueryOptions, QueryParams, CLASS_IN, RR_TXT};
 use slipstream_ffi::picoquic::{
-    picoquic_cnx_t, picoquic_current_time, picoquic_prepare_packet_ex, slipstream_request_poll,
+    picoquic_cnx_t, picoquic_prepare_packet_ex, slipstream_request_poll,
 };
-use slipstream_ffi::{ClientConfig, ResolverMode};
 use std::collections::HashMap;
 use tokio::net::UdpSocket as TokioUdpSocket;
 
+use super::debug::SendClass;
 use super::path::refresh_resolver_path;
 use super::resolver::{sockaddr_storage_to_socket_addr, ResolverState};
+use super::send::{send_query, SendOutcome};
 use slipstream_core::normalize_dual_stack_addr;
 
 const AUTHORITATIVE_POLL_TIMEOUT_US: u64 = 5_000_000;
@@ -30,16 +32,29 @@ pub(crate) fn expire_inflight_polls(inflight_poll_ids: &mut HashMap<u16, u64>, n
     }
 }
 
+/// Moves every inflight poll's send time forward by `jump_us`, so a clock
+/// jump does not expire polls that never had a chance to be answered.
+pub(crate) fn rebase_inflight_polls(inflight_poll_ids: &mut HashMap<u16, u64>, jump_us: u64) {
+    for sent_at in inflight_poll_ids.values_mut() {
+        *sent_at = sent_at.saturating_add(jump_us);
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) async fn send_poll_queries(
     cnx: *mut picoquic_cnx_t,
     udp: &TokioUdpSocket,
-    config: &ClientConfig<'_>,
+    domain: &str,
+    service_label: Option<&str>,
     local_addr_storage: &mut libc::sockaddr_storage,
     dns_id: &mut u16,
     resolver: &mut ResolverState,
     remaining: &mut usize,
     send_buf: &mut [u8],
+    qname_guard: &mut QnameGuard,
+    query_options: &QueryOptions,
+    clock: &dyn Clock,
+    class: SendClass,
 ) -> Result<(), ClientError> {
     if !refresh_resolver_path(cnx, resolver) {
         return Ok(());
@@ -48,7 +63,7 @@ pub(crate) async fn send_poll_queries(
     *remaining = 0;
 
     while remaining_count > 0 {
-        let current_time = unsafe { picoquic_current_time() };
+        let current_time = clock.now_us();
         unsafe {
             slipstream_request_poll(cnx);
         }
@@ -82,13 +97,14 @@ pub(crate) async fn send_poll_queries(
         remaining_count -= 1;
         *local_addr_storage = addr_from;
         resolver.local_addr_storage = Some(unsafe { std::ptr::read(local_addr_storage) });
-        resolver.debug.send_packets = resolver.debug.send_packets.saturating_add(1);
-        resolver.debug.send_bytes = resolver.debug.send_bytes.saturating_add(send_length as u64);
+        resolver.debug.record_send(class, send_length);
         resolver.debug.polls_sent = resolver.debug.polls_sent.saturating_add(1);
 
         let poll_id = *dns_id;
-        let qname = build_qname(&send_buf[..send_length], config.domain)
-            .map_err(|err| ClientError::new(err.to_string()))?;
+        let Some(qname) = qname_guard.qname(&send_buf[..send_length], service_label, domain)?
+        else {
+            continue;
+        };
         let params = QueryParams {
             id: poll_id,
             qname: &qname,
@@ -100,22 +116,143 @@ pub(crate) async fn send_poll_queries(
             is_query: true,
         };
         *dns_id = dns_id.wrapping_add(1);
-        let packet = encode_query(&params).map_err(|err| ClientError::new(err.to_string()))?;
+        let packet = encode_query_with_options(&params, query_options)
+            .map_err(|err| ClientError::new(err.to_string()))?;
 
         let dest = sockaddr_storage_to_socket_addr(&addr_to)?;
         let dest = normalize_dual_stack_addr(dest);
-        if let Err(err) = udp.send_to(&packet, dest).await {
-            if is_transient_udp_error(&err) {
-                remaining_count = remaining_count.saturating_add(1);
-                *remaining = remaining_count;
-                break;
-            }
-            return Err(ClientError::new(err.to_string()));
-        }
-        if resolver.mode == ResolverMode::Authoritative {
-            resolver.inflight_poll_ids.insert(poll_id, current_time);
+        let outcome = send_query(udp, &packet, dest)
+            .await
+            .map_err(|err| ClientError::new(err.to_string()))?;
+        resolver.debug.record_send_outcome(outcome);
+        if outcome == SendOutcome::Dropped {
+            remaining_count = remaining_count.saturating_add(1);
+            *remaining = remaining_count;
+            break;
         }
+        resolver.record_poll_sent(poll_id, current_time);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{expire_inflight_polls, rebase_inflight_polls, AUTHORITATIVE_POLL_TIMEOUT_US};
+    use crate::pacing::{cwnd_target_polls, inflight_packet_estimate};
+    use slipstream_core::clock::{Clock, ManualClock};
+    use slipstream_core::test_support::DelayLine;
+    use std::collections::HashMap;
+
+    const SIM_MTU: u32 = 900;
+    const SIM_CWIN: u64 = 16 * SIM_MTU as u64;
+    const SIM_STEP_US: u64 = 1_000;
+
+    #[derive(Debug, Default)]
+    struct SimOutcome {
+        max_inflight: usize,
+        answered: u64,
+        expired: u64,
+    }
+
+    /// Keeps an authoritative path topped up to its cwnd target, the way
+    /// the send loop does without a pacing rate, while a `DelayLine` plays
+    /// the resolver answering each poll.
+    fn simulate_authoritative_polls(
+        latency_us: u64,
+        jitter_us: u64,
+        duration_us: u64,
+    ) -> SimOutcome {
+        let clock = ManualClock::new(1_000_000);
+        let mut resolver = DelayLine::new(latency_us, jitter_us, 0x5eed);
+        let mut inflight = HashMap::new();
+        let mut next_id = 0u16;
+        let mut outcome = SimOutcome::default();
+        let end = clock.now_us() + duration_us;
+        while clock.now_us() < end {
+            let now = clock.now_us();
+            while let Some(id) = resolver.pop_due(now) {
+                if inflight.remove(&id).is_some() {
+                    outcome.answered += 1;
+                }
+            }
+            let before = inflight.len();
+            expire_inflight_polls(&mut inflight, now);
+            outcome.expired += (before - inflight.len()) as u64;
+
+            let bytes_in_transit = inflight.len() as u64 * SIM_MTU as u64;
+            let deficit = cwnd_target_polls(SIM_CWIN, SIM_MTU)
+                .saturating_sub(inflight_packet_estimate(bytes_in_transit, SIM_MTU));
+            for _ in 0..deficit {
+                inflight.insert(next_id, now);
+                resolver.push(next_id, now);
+                next_id = next_id.wrapping_add(1);
+            }
+            outcome.max_inflight = outcome.max_inflight.max(inflight.len());
+            clock.advance_us(SIM_STEP_US);
+        }
+        outcome
+    }
+
+    #[test]
+    fn inflight_polls_expire_after_timeout() {
+        let clock = ManualClock::new(1_000_000);
+        let mut inflight = HashMap::new();
+        inflight.insert(1u16, clock.now_us());
+        clock.advance_us(1_000_000);
+        inflight.insert(2u16, clock.now_us());
+
+        clock.advance_us(AUTHORITATIVE_POLL_TIMEOUT_US - 1_000_001);
+        expire_inflight_polls(&mut inflight, clock.now_us());
+        assert_eq!(inflight.len(), 2);
+
+        clock.advance_us(1);
+        expire_inflight_polls(&mut inflight, clock.now_us());
+        assert!(!inflight.contains_key(&1));
+        assert!(inflight.contains_key(&2));
+
+        clock.advance_us(1_000_000);
+        expire_inflight_polls(&mut inflight, clock.now_us());
+        assert!(inflight.is_empty());
+    }
+
+    #[test]
+    fn rebased_polls_survive_a_clock_jump() {
+        let clock = ManualClock::new(1_000_000);
+        let mut inflight = HashMap::new();
+        inflight.insert(1u16, clock.now_us());
+
+        let jump_us = 60_000_000;
+        clock.advance_us(jump_us + 1_000);
+        rebase_inflight_polls(&mut inflight, jump_us);
+        expire_inflight_polls(&mut inflight, clock.now_us());
+        assert!(inflight.contains_key(&1));
+
+        clock.advance_us(AUTHORITATIVE_POLL_TIMEOUT_US);
+        expire_inflight_polls(&mut inflight, clock.now_us());
+        assert!(inflight.is_empty());
+    }
+
+    #[test]
+    fn jittered_latency_keeps_polls_at_the_cwnd_target() {
+        let outcome = simulate_authoritative_polls(150_000, 100_000, 20_000_000);
+        assert_eq!(outcome.max_inflight, 16);
+        assert_eq!(outcome.expired, 0);
+        // 16 polls per round trip of 150-250ms over 20s.
+        assert!(
+            (1_250..=2_150).contains(&outcome.answered),
+            "answered {}",
+            outcome.answered
+        );
+    }
+
+    #[test]
+    fn latency_past_the_poll_timeout_expires_and_refills() {
+        let outcome =
+            simulate_authoritative_polls(AUTHORITATIVE_POLL_TIMEOUT_US + 1_000_000, 0, 20_000_000);
+        assert_eq!(outcome.max_inflight, 16);
+        assert_eq!(outcome.answered, 0);
+        // Each 5s expiry frees the whole window for a fresh batch.
+        assert_eq!(outcome.expired, 48);
+    }
+}
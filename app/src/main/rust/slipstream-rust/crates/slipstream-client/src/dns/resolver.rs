@@ -1,15 +1,48 @@
 use crate::error::ClientError;
 use crate::pacing::{PacingBudgetSnapshot, PacingPollBudget};
-use slipstream_core::{normalize_dual_stack_addr, resolve_host_port};
+use slipstream_core::{normalize_dual_stack_addr, resolve_host_port, HostPort};
 use slipstream_ffi::{socket_addr_to_storage, ResolverMode, ResolverSpec};
 use std::collections::HashMap;
-use std::net::SocketAddr;
-use tracing::warn;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use tracing::{info, warn};
 
 use super::debug::DebugMetrics;
+use super::poison::IngestWindow;
+
+/// Where a resolver's address came from on the last (re-)resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AddrSource {
+    /// `ClientConfig::host_overrides`, checked before anything else.
+    Override,
+    /// The resolver was given as an IP literal.
+    Literal,
+    System,
+    /// System resolution failed; reused the address that last succeeded.
+    Cache,
+}
+
+impl fmt::Display for AddrSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AddrSource::Override => "override",
+            AddrSource::Literal => "literal",
+            AddrSource::System => "system",
+            AddrSource::Cache => "cache",
+        })
+    }
+}
+
+/// Last system-resolved address per resolver host and port, kept across
+/// reconnects so a lookup failure mid-session does not end the client.
+#[derive(Default)]
+pub(crate) struct ResolverAddrCache {
+    entries: HashMap<(String, u16), SocketAddr>,
+}
 
 pub(crate) struct ResolverState {
     pub(crate) addr: SocketAddr,
+    pub(crate) addr_source: AddrSource,
     pub(crate) storage: libc::sockaddr_storage,
     pub(crate) local_addr_storage: Option<libc::sockaddr_storage>,
     pub(crate) mode: ResolverMode,
@@ -18,32 +51,127 @@ pub(crate) struct ResolverState {
     pub(crate) unique_path_id: Option<u64>,
     pub(crate) probe_attempts: u32,
     pub(crate) next_probe_at: u64,
+    /// Time of the last DNS response from this resolver; 0 until one arrives.
+    pub(crate) last_response_at: u64,
+    /// Time the last poll went out to this resolver; 0 until one does.
+    pub(crate) last_poll_at: u64,
     pub(crate) pending_polls: usize,
     pub(crate) inflight_poll_ids: HashMap<u16, u64>,
+    /// `ResolverSpec::max_inflight_polls`.
+    pub(crate) max_inflight_polls: usize,
+    /// Unanswered recursive polls; only tracked while `max_inflight_polls`
+    /// is set, since nothing else needs them.
+    pub(crate) recursive_poll_ids: HashMap<u16, u64>,
     pub(crate) pacing_budget: Option<PacingPollBudget>,
     pub(crate) last_pacing_snapshot: Option<PacingBudgetSnapshot>,
     pub(crate) debug: DebugMetrics,
+    pub(crate) ingest: IngestWindow,
+    /// Judged to forge answers, on this connection or an earlier one.
+    pub(crate) poisoned: bool,
+    /// Answered with an EDNS Client Subnet option that may carry the
+    /// client's subnet, on this connection or an earlier one.
+    pub(crate) ecs_observed: bool,
 }
 
 impl ResolverState {
+    /// An added path that has delivered at least one response.
+    pub(crate) fn is_usable(&self) -> bool {
+        self.added && self.last_response_at > 0
+    }
+
     pub(crate) fn label(&self) -> String {
         format!(
             "path_id={} unique_id={:?} resolver={} mode={:?}",
             self.path_id, self.unique_path_id, self.addr, self.mode
         )
     }
+
+    /// Polls sent to this resolver that are still waiting for an answer.
+    pub(crate) fn inflight_polls(&self) -> usize {
+        match self.mode {
+            ResolverMode::Authoritative => self.inflight_poll_ids.len(),
+            ResolverMode::Recursive => self.recursive_poll_ids.len(),
+        }
+    }
+
+    /// Polls that may be sent before hitting `max_inflight_polls`.
+    pub(crate) fn poll_slots(&self) -> usize {
+        if self.max_inflight_polls == 0 {
+            return usize::MAX;
+        }
+        self.max_inflight_polls
+            .saturating_sub(self.inflight_polls())
+    }
+
+    pub(crate) fn record_poll_sent(&mut self, id: u16, now: u64) {
+        self.last_poll_at = now;
+        match self.mode {
+            ResolverMode::Authoritative => {
+                self.inflight_poll_ids.insert(id, now);
+            }
+            ResolverMode::Recursive if self.max_inflight_polls > 0 => {
+                self.recursive_poll_ids.insert(id, now);
+            }
+            ResolverMode::Recursive => {}
+        }
+    }
+
+    pub(crate) fn record_poll_answered(&mut self, id: u16) {
+        match self.mode {
+            ResolverMode::Authoritative => self.inflight_poll_ids.remove(&id),
+            ResolverMode::Recursive => self.recursive_poll_ids.remove(&id),
+        };
+    }
 }
 
+fn lookup_resolver_addr(
+    resolver: &HostPort,
+    host_overrides: &[(String, IpAddr)],
+    cache: &mut ResolverAddrCache,
+) -> Result<(SocketAddr, AddrSource), ClientError> {
+    let host = resolver.host.trim_end_matches('.');
+    if let Some((_, ip)) = host_overrides
+        .iter()
+        .find(|(name, _)| name.trim_end_matches('.').eq_ignore_ascii_case(host))
+    {
+        return Ok((SocketAddr::new(*ip, resolver.port), AddrSource::Override));
+    }
+    if let Ok(ip) = resolver.host.parse::<IpAddr>() {
+        return Ok((SocketAddr::new(ip, resolver.port), AddrSource::Literal));
+    }
+    let key = (resolver.host.to_ascii_lowercase(), resolver.port);
+    match resolve_host_port(resolver) {
+        Ok(addr) => {
+            cache.entries.insert(key, addr);
+            Ok((addr, AddrSource::System))
+        }
+        Err(err) => match cache.entries.get(&key) {
+            Some(addr) => {
+                warn!(
+                    "Resolving {} failed ({}); reusing last known address {}",
+                    resolver.host, err, addr
+                );
+                Ok((*addr, AddrSource::Cache))
+            }
+            None => Err(ClientError::new(err.to_string())),
+        },
+    }
+}
+
+/// Rebuilds `resolved` from the specs, reusing its allocation.
 pub(crate) fn resolve_resolvers(
     resolvers: &[ResolverSpec],
+    host_overrides: &[(String, IpAddr)],
+    cache: &mut ResolverAddrCache,
     mtu: u32,
     debug_poll: bool,
-) -> Result<Vec<ResolverState>, ClientError> {
-    let mut resolved = Vec::with_capacity(resolvers.len());
+    resolved: &mut Vec<ResolverState>,
+) -> Result<(), ClientError> {
+    resolved.clear();
+    resolved.reserve(resolvers.len());
     let mut seen = HashMap::new();
     for (idx, resolver) in resolvers.iter().enumerate() {
-        let addr = resolve_host_port(&resolver.resolver)
-            .map_err(|err| ClientError::new(err.to_string()))?;
+        let (addr, addr_source) = lookup_resolver_addr(&resolver.resolver, host_overrides, cache)?;
         let addr = normalize_dual_stack_addr(addr);
         if let Some(existing_mode) = seen.get(&addr) {
             return Err(ClientError::new(format!(
@@ -52,9 +180,14 @@ pub(crate) fn resolve_resolvers(
             )));
         }
         seen.insert(addr, resolver.mode);
+        info!(
+            "Resolver {} -> {} (source={}, mode={:?})",
+            resolver.resolver.host, addr, addr_source, resolver.mode
+        );
         let is_primary = idx == 0;
         resolved.push(ResolverState {
             addr,
+            addr_source,
             storage: socket_addr_to_storage(addr),
             local_addr_storage: None,
             mode: resolver.mode,
@@ -63,17 +196,24 @@ pub(crate) fn resolve_resolvers(
             unique_path_id: if is_primary { Some(0) } else { None },
             probe_attempts: 0,
             next_probe_at: 0,
+            last_response_at: 0,
+            last_poll_at: 0,
             pending_polls: 0,
             inflight_poll_ids: HashMap::new(),
+            max_inflight_polls: resolver.max_inflight_polls,
+            recursive_poll_ids: HashMap::new(),
             pacing_budget: match resolver.mode {
                 ResolverMode::Authoritative => Some(PacingPollBudget::new(mtu)),
                 ResolverMode::Recursive => None,
             },
             last_pacing_snapshot: None,
             debug: DebugMetrics::new(debug_poll),
+            ingest: IngestWindow::default(),
+            poisoned: false,
+            ecs_observed: false,
         });
     }
-    Ok(resolved)
+    Ok(())
 }
 
 pub(crate) fn reset_resolver_path(resolver: &mut ResolverState) {
@@ -87,9 +227,11 @@ pub(crate) fn reset_resolver_path(resolver: &mut ResolverState) {
     resolver.local_addr_storage = None;
     resolver.pending_polls = 0;
     resolver.inflight_poll_ids.clear();
+    resolver.recursive_poll_ids.clear();
     resolver.last_pacing_snapshot = None;
     resolver.probe_attempts = 0;
     resolver.next_probe_at = 0;
+    resolver.last_response_at = 0;
 }
 
 pub(crate) fn sockaddr_storage_to_socket_addr(
@@ -100,9 +242,10 @@ pub(crate) fn sockaddr_storage_to_socket_addr(
 
 #[cfg(test)]
 mod tests {
-    use super::resolve_resolvers;
-    use slipstream_core::{AddressFamily, HostPort};
+    use super::{resolve_resolvers, AddrSource, ResolverAddrCache};
+    use slipstream_core::{normalize_dual_stack_addr, AddressFamily, HostPort};
     use slipstream_ffi::{ResolverMode, ResolverSpec};
+    use std::net::{IpAddr, Ipv4Addr};
 
     #[test]
     fn rejects_duplicate_resolver_addr() {
@@ -114,6 +257,7 @@ mod tests {
                     family: AddressFamily::V4,
                 },
                 mode: ResolverMode::Recursive,
+                max_inflight_polls: 0,
             },
             ResolverSpec {
                 resolver: HostPort {
@@ -122,12 +266,159 @@ mod tests {
                     family: AddressFamily::V4,
                 },
                 mode: ResolverMode::Authoritative,
+                max_inflight_polls: 0,
             },
         ];
 
-        match resolve_resolvers(&resolvers, 900, false) {
+        match resolve_resolvers(
+            &resolvers,
+            &[],
+            &mut ResolverAddrCache::default(),
+            900,
+            false,
+            &mut Vec::new(),
+        ) {
             Ok(_) => panic!("expected duplicate resolver error"),
             Err(err) => assert!(err.to_string().contains("Duplicate resolver address")),
         }
     }
+
+    #[test]
+    fn re_resolved_resolvers_start_with_clean_poll_state() {
+        let specs = vec![
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "127.0.0.1".to_string(),
+                    port: 8853,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+                max_inflight_polls: 0,
+            },
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "127.0.0.1".to_string(),
+                    port: 8854,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Authoritative,
+                max_inflight_polls: 0,
+            },
+        ];
+
+        let mut cache = ResolverAddrCache::default();
+        let mut resolved = Vec::new();
+        resolve_resolvers(&specs, &[], &mut cache, 900, false, &mut resolved).expect("resolve");
+        // What the previous connection left behind.
+        for resolver in resolved.iter_mut() {
+            resolver.pending_polls = 16;
+            resolver.inflight_poll_ids.insert(7, 1_000);
+            resolver.recursive_poll_ids.insert(8, 1_000);
+            resolver.last_poll_at = 1_000;
+            resolver.last_response_at = 2_000;
+            resolver.debug.stale_responses = 3;
+        }
+        let capacity = resolved.capacity();
+
+        // Each reconnect rebuilds resolver state from the specs, in place.
+        resolve_resolvers(&specs, &[], &mut cache, 900, false, &mut resolved).expect("resolve");
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved.capacity(), capacity);
+        for resolver in &resolved {
+            assert_eq!(resolver.pending_polls, 0);
+            assert!(resolver.inflight_poll_ids.is_empty());
+            assert!(resolver.recursive_poll_ids.is_empty());
+            assert_eq!(resolver.last_poll_at, 0);
+            assert_eq!(resolver.last_response_at, 0);
+            assert!(resolver.last_pacing_snapshot.is_none());
+            assert_eq!(resolver.debug.stale_responses, 0);
+        }
+        assert!(resolved[0].pacing_budget.is_none());
+        assert!(resolved[1].pacing_budget.is_some());
+    }
+
+    #[test]
+    fn inflight_cap_limits_poll_slots_in_both_modes() {
+        let specs = vec![
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "127.0.0.1".to_string(),
+                    port: 8853,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+                max_inflight_polls: 2,
+            },
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "127.0.0.1".to_string(),
+                    port: 8854,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Authoritative,
+                max_inflight_polls: 0,
+            },
+        ];
+        let mut cache = ResolverAddrCache::default();
+        let mut resolved = Vec::new();
+        resolve_resolvers(&specs, &[], &mut cache, 900, false, &mut resolved).expect("resolve");
+
+        let recursive = &mut resolved[0];
+        assert_eq!(recursive.poll_slots(), 2);
+        recursive.record_poll_sent(1, 1_000);
+        recursive.record_poll_sent(2, 1_000);
+        assert_eq!(recursive.inflight_polls(), 2);
+        assert_eq!(recursive.poll_slots(), 0);
+        recursive.record_poll_answered(1);
+        assert_eq!(recursive.poll_slots(), 1);
+
+        let authoritative = &mut resolved[1];
+        authoritative.record_poll_sent(3, 1_000);
+        assert_eq!(authoritative.inflight_polls(), 1);
+        assert_eq!(authoritative.poll_slots(), usize::MAX);
+        authoritative.max_inflight_polls = 1;
+        assert_eq!(authoritative.poll_slots(), 0);
+    }
+
+    #[test]
+    fn host_overrides_win_over_system_resolution() {
+        let specs = vec![
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "DNS.Corp.Example.".to_string(),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+                max_inflight_polls: 0,
+            },
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "127.0.0.1".to_string(),
+                    port: 8853,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Authoritative,
+                max_inflight_polls: 0,
+            },
+        ];
+        let overrides = vec![(
+            "dns.corp.example".to_string(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 53)),
+        )];
+        let mut cache = ResolverAddrCache::default();
+
+        // Re-resolution on reconnect goes through the same lookup.
+        let mut resolved = Vec::new();
+        for _ in 0..2 {
+            resolve_resolvers(&specs, &overrides, &mut cache, 900, false, &mut resolved)
+                .expect("resolve");
+            assert_eq!(
+                resolved[0].addr,
+                normalize_dual_stack_addr("10.0.0.53:53".parse().unwrap())
+            );
+            assert_eq!(resolved[0].addr_source, AddrSource::Override);
+            assert_eq!(resolved[1].addr_source, AddrSource::Literal);
+        }
+    }
 }
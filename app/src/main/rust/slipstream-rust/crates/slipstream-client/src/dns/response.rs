@@ -1,21 +1,34 @@
 use crate::error::ClientError;
-use slipstream_dns::decode_response;
+use slipstream_dns::{parse_tunnel_response, response_ecs, TunnelResponse};
 use slipstream_ffi::picoquic::{
-    picoquic_cnx_t, picoquic_current_time, picoquic_incoming_packet_ex, picoquic_quic_t,
+    picoquic_cnx_t, picoquic_incoming_packet_ex, picoquic_quic_t, slipstream_get_packets_received,
     PICOQUIC_PACKET_LOOP_RECV_MAX,
 };
-use slipstream_ffi::{socket_addr_to_storage, ResolverMode};
+use slipstream_ffi::socket_addr_to_storage;
 use std::net::SocketAddr;
+use tracing::{error, info};
 
+use super::ecs::observe_ecs;
 use super::resolver::ResolverState;
+use crate::streams::ClientState;
+use slipstream_core::callback_state::CallbackState;
+use slipstream_core::clock::Clock;
 use slipstream_core::normalize_dual_stack_addr;
 
 const MAX_POLL_BURST: usize = PICOQUIC_PACKET_LOOP_RECV_MAX;
 
 pub(crate) struct DnsResponseContext<'a> {
     pub(crate) quic: *mut picoquic_quic_t,
+    /// The live connection, whose received-packet count tells whether
+    /// picoquic accepted a payload.
+    pub(crate) cnx: *mut picoquic_cnx_t,
     pub(crate) local_addr_storage: &'a libc::sockaddr_storage,
     pub(crate) resolvers: &'a mut [ResolverState],
+    pub(crate) state: &'a mut CallbackState<ClientState>,
+    pub(crate) clock: &'a dyn Clock,
+    /// Until this time, responses no live connection claims are treated as
+    /// leftovers from before the last reconnect.
+    pub(crate) poll_grace_until: u64,
 }
 
 pub(crate) fn handle_dns_response(
@@ -23,73 +36,124 @@ pub(crate) fn handle_dns_response(
     peer: SocketAddr,
     ctx: &mut DnsResponseContext<'_>,
 ) -> Result<(), ClientError> {
-    let peer = normalize_dual_stack_addr(peer);
-    let response_id = dns_response_id(buf);
-    if let Some(payload) = decode_response(buf) {
-        let resolver_index = ctx
-            .resolvers
-            .iter()
-            .position(|resolver| resolver.addr == peer);
-        let mut peer_storage = socket_addr_to_storage(peer);
-        let mut local_storage = if let Some(index) = resolver_index {
-            ctx.resolvers[index]
-                .local_addr_storage
-                .as_ref()
-                .map(|storage| unsafe { std::ptr::read(storage) })
-                .unwrap_or_else(|| unsafe { std::ptr::read(ctx.local_addr_storage) })
-        } else {
-            unsafe { std::ptr::read(ctx.local_addr_storage) }
-        };
-        let mut first_cnx: *mut picoquic_cnx_t = std::ptr::null_mut();
-        let mut first_path: libc::c_int = -1;
-        let current_time = unsafe { picoquic_current_time() };
-        let ret = unsafe {
-            picoquic_incoming_packet_ex(
-                ctx.quic,
-                payload.as_ptr() as *mut u8,
-                payload.len(),
-                &mut peer_storage as *mut _ as *mut libc::sockaddr,
-                &mut local_storage as *mut _ as *mut libc::sockaddr,
-                0,
-                0,
-                &mut first_cnx,
-                &mut first_path,
-                current_time,
-            )
-        };
-        if ret < 0 {
-            return Err(ClientError::new("Failed processing inbound QUIC packet"));
-        }
-        let resolver = if let Some(resolver) = find_resolver_by_path_id(ctx.resolvers, first_path) {
-            Some(resolver)
-        } else {
-            find_resolver_by_addr(ctx.resolvers, peer)
-        };
-        if let Some(resolver) = resolver {
-            if first_path >= 0 && resolver.path_id != first_path {
-                resolver.path_id = first_path;
-                resolver.added = true;
-            }
-            resolver.debug.dns_responses = resolver.debug.dns_responses.saturating_add(1);
-            if let Some(response_id) = response_id {
-                if resolver.mode == ResolverMode::Authoritative {
-                    resolver.inflight_poll_ids.remove(&response_id);
-                }
+    match parse_tunnel_response(buf) {
+        Ok(response) => {
+            if let Some(resolver) = find_resolver_by_addr(ctx.resolvers, peer) {
+                observe_ecs(resolver, response_ecs(buf));
             }
-            // Both modes: each response triggers a demand-driven poll.
-            // For authoritative mode this provides a floor so that the poll
-            // rate never drops below the actual response rate, even when BBR's
-            // pacing estimate is conservative.
-            resolver.pending_polls =
-                resolver.pending_polls.saturating_add(1).min(MAX_POLL_BURST);
+            ingest_response(response, peer, ctx)
         }
-    } else if let Some(response_id) = response_id {
+        // Not a DNS response we can attribute; nothing to account for.
+        Err(_) => Ok(()),
+    }
+}
+
+/// Stateful half of response handling: resolver matching, inflight and
+/// demand-poll bookkeeping, and handing the payload to picoquic.
+pub(crate) fn ingest_response(
+    response: TunnelResponse,
+    peer: SocketAddr,
+    ctx: &mut DnsResponseContext<'_>,
+) -> Result<(), ClientError> {
+    let peer = normalize_dual_stack_addr(peer);
+    let Some(payload) = response.payload else {
         if let Some(resolver) = find_resolver_by_addr(ctx.resolvers, peer) {
             resolver.debug.dns_responses = resolver.debug.dns_responses.saturating_add(1);
-            if resolver.mode == ResolverMode::Authoritative {
-                resolver.inflight_poll_ids.remove(&response_id);
+            resolver.last_response_at = ctx.clock.now_us();
+            resolver.record_poll_answered(response.id);
+            if let Some(budget) = resolver.pacing_budget.as_mut() {
+                budget.observe_response(0);
             }
         }
+        return Ok(());
+    };
+    if let Some(versions) = parse_version_negotiation(&payload) {
+        let count = ctx.state.get_mut().record_version_negotiation();
+        let versions: Vec<String> = versions
+            .iter()
+            .map(|version| format!("0x{:08x}", version))
+            .collect();
+        info!(
+            "Version negotiation from {}: server offers [{}] (count={})",
+            peer,
+            versions.join(", "),
+            count
+        );
+    }
+    let resolver_index = ctx
+        .resolvers
+        .iter()
+        .position(|resolver| resolver.addr == peer);
+    let mut peer_storage = socket_addr_to_storage(peer);
+    let mut local_storage = if let Some(index) = resolver_index {
+        ctx.resolvers[index]
+            .local_addr_storage
+            .as_ref()
+            .map(|storage| unsafe { std::ptr::read(storage) })
+            .unwrap_or_else(|| unsafe { std::ptr::read(ctx.local_addr_storage) })
+    } else {
+        unsafe { std::ptr::read(ctx.local_addr_storage) }
+    };
+    let mut first_cnx: *mut picoquic_cnx_t = std::ptr::null_mut();
+    let mut first_path: libc::c_int = -1;
+    let current_time = ctx.clock.now_us();
+    let received_before = unsafe { slipstream_get_packets_received(ctx.cnx) };
+    let ret = unsafe {
+        picoquic_incoming_packet_ex(
+            ctx.quic,
+            payload.as_ptr() as *mut u8,
+            payload.len(),
+            &mut peer_storage as *mut _ as *mut libc::sockaddr,
+            &mut local_storage as *mut _ as *mut libc::sockaddr,
+            0,
+            0,
+            &mut first_cnx,
+            &mut first_path,
+            current_time,
+        )
+    };
+    if ret < 0 {
+        return Err(ClientError::new("Failed processing inbound QUIC packet"));
+    }
+    let accepted = unsafe { slipstream_get_packets_received(ctx.cnx) } > received_before;
+    let resolver = if let Some(resolver) = find_resolver_by_path_id(ctx.resolvers, first_path) {
+        Some(resolver)
+    } else {
+        find_resolver_by_addr(ctx.resolvers, peer)
+    };
+    if let Some(resolver) = resolver {
+        if first_path >= 0 && resolver.path_id != first_path {
+            resolver.path_id = first_path;
+            resolver.added = true;
+        }
+        resolver.debug.dns_responses = resolver.debug.dns_responses.saturating_add(1);
+        resolver.debug.response_bytes = resolver
+            .debug
+            .response_bytes
+            .saturating_add(payload.len() as u64);
+        resolver.last_response_at = current_time;
+        resolver.record_poll_answered(response.id);
+        // Leftovers from the previous connection are rejected for good
+        // reason; they say nothing about the resolver.
+        if !counts_as_demand(!first_cnx.is_null(), current_time, ctx.poll_grace_until) {
+            resolver.debug.stale_responses = resolver.debug.stale_responses.saturating_add(1);
+            return Ok(());
+        }
+        if let Some(budget) = resolver.pacing_budget.as_mut() {
+            budget.observe_response(payload.len());
+        }
+        if resolver.ingest.record(accepted) && !resolver.poisoned {
+            resolver.poisoned = true;
+            error!(
+                "Resolver {} looks poisoned: picoquic rejected nearly every response it relayed (rejected_responses={}); no longer using it this session",
+                resolver.addr, resolver.ingest.rejected_total
+            );
+        }
+        // Both modes: each response triggers a demand-driven poll.
+        // For authoritative mode this provides a floor so that the poll
+        // rate never drops below the actual response rate, even when BBR's
+        // pacing estimate is conservative.
+        resolver.pending_polls = resolver.pending_polls.saturating_add(1).min(MAX_POLL_BURST);
     }
     Ok(())
 }
@@ -114,14 +178,69 @@ fn find_resolver_by_addr(
     resolvers.iter_mut().find(|resolver| resolver.addr == peer)
 }
 
-fn dns_response_id(packet: &[u8]) -> Option<u16> {
-    if packet.len() < 12 {
+/// Whether a response should seed a demand-driven poll. Inside the reconnect
+/// grace window, only responses picoquic matched to a connection count.
+fn counts_as_demand(matched_cnx: bool, now: u64, grace_until: u64) -> bool {
+    matched_cnx || now >= grace_until
+}
+
+/// Supported versions listed in a QUIC Version Negotiation packet
+/// (RFC 9000 section 17.2.1): long header form with version 0.
+fn parse_version_negotiation(packet: &[u8]) -> Option<Vec<u32>> {
+    if packet.len() < 7 || packet[0] & 0x80 == 0 {
         return None;
     }
-    let id = u16::from_be_bytes([packet[0], packet[1]]);
-    let flags = u16::from_be_bytes([packet[2], packet[3]]);
-    if flags & 0x8000 == 0 {
+    if packet[1..5] != [0, 0, 0, 0] {
         return None;
     }
-    Some(id)
+    let mut offset = 5;
+    for _ in 0..2 {
+        let cid_len = *packet.get(offset)? as usize;
+        offset = offset.checked_add(1 + cid_len)?;
+        if offset > packet.len() {
+            return None;
+        }
+    }
+    let versions = packet[offset..]
+        .chunks_exact(4)
+        .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    Some(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{counts_as_demand, parse_version_negotiation};
+
+    #[test]
+    fn stale_responses_skip_demand_during_grace() {
+        assert!(!counts_as_demand(false, 1_000, 2_000));
+        assert!(counts_as_demand(true, 1_000, 2_000));
+        assert!(counts_as_demand(false, 2_000, 2_000));
+        assert!(counts_as_demand(false, 5_000, 0));
+    }
+
+    #[test]
+    fn parses_version_negotiation_offers() {
+        let mut packet = vec![0xc3, 0, 0, 0, 0, 2, 0xaa, 0xbb, 1, 0xcc];
+        packet.extend_from_slice(&0x0000_0001u32.to_be_bytes());
+        packet.extend_from_slice(&0x6b33_43cfu32.to_be_bytes());
+        assert_eq!(
+            parse_version_negotiation(&packet),
+            Some(vec![0x0000_0001, 0x6b33_43cf])
+        );
+    }
+
+    #[test]
+    fn ignores_regular_and_malformed_packets() {
+        // Initial packet: long header with a non-zero version.
+        let initial = [0xc0, 0, 0, 0, 1, 0, 0, 0, 0];
+        assert_eq!(parse_version_negotiation(&initial), None);
+        // Short header.
+        let short = [0x40, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(parse_version_negotiation(&short), None);
+        // Connection ID length runs past the packet.
+        let truncated = [0x80, 0, 0, 0, 0, 20, 1, 2];
+        assert_eq!(parse_version_negotiation(&truncated), None);
+    }
 }
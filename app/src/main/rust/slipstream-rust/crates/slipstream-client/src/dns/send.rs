@@ -0,0 +1,49 @@
+use slipstream_core::net::{is_route_change_error, is_transient_udp_error};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket as TokioUdpSocket;
+
+/// Pause before the one retry of a send that failed on a route change.
+const ROUTE_CHANGE_RETRY_DELAY: Duration = Duration::from_millis(2);
+
+/// What became of one query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SendOutcome {
+    Sent,
+    /// Went out on the retry after a route change error.
+    Retried,
+    /// Lost to a transient error, after the retry where there was one;
+    /// QUIC retransmits what it carried.
+    Dropped,
+}
+
+/// Send one query. A route change error is retried once after a short
+/// pause, other transient errors drop the query, and the rest are returned.
+pub(crate) async fn send_query(
+    udp: &TokioUdpSocket,
+    packet: &[u8],
+    dest: SocketAddr,
+) -> io::Result<SendOutcome> {
+    match udp.send_to(packet, dest).await {
+        Ok(_) => Ok(SendOutcome::Sent),
+        Err(err) if is_route_change_error(&err) => retry_query(udp, packet, dest).await,
+        Err(err) if is_transient_udp_error(&err) => Ok(SendOutcome::Dropped),
+        Err(err) => Err(err),
+    }
+}
+
+/// The retry half of [`send_query`], for a send that already failed with a
+/// route change error elsewhere (a batched send).
+pub(crate) async fn retry_query(
+    udp: &TokioUdpSocket,
+    packet: &[u8],
+    dest: SocketAddr,
+) -> io::Result<SendOutcome> {
+    tokio::time::sleep(ROUTE_CHANGE_RETRY_DELAY).await;
+    match udp.send_to(packet, dest).await {
+        Ok(_) => Ok(SendOutcome::Retried),
+        Err(err) if is_transient_udp_error(&err) => Ok(SendOutcome::Dropped),
+        Err(err) => Err(err),
+    }
+}
@@ -3,14 +3,31 @@ use std::fmt;
 #[derive(Debug)]
 pub struct ClientError {
     message: String,
+    permanent: bool,
 }
 
 impl ClientError {
     pub(crate) fn new(message: impl Into<String>) -> Self {
         Self {
             message: message.into(),
+            permanent: false,
         }
     }
+
+    /// An error that retrying will not fix, such as a certificate pin the
+    /// server never matches.
+    pub(crate) fn permanent(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            permanent: true,
+        }
+    }
+
+    /// The client gave up because the configuration cannot work, not
+    /// because the network or server was unavailable.
+    pub fn is_permanent(&self) -> bool {
+        self.permanent
+    }
 }
 
 impl fmt::Display for ClientError {
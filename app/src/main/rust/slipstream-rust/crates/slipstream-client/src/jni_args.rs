@@ -0,0 +1,177 @@
+//! Validation of the resolver arrays handed to `nativeStartSlipstreamClient`.
+//!
+//! The JNI layer copies the Java arrays into plain Rust values first; the
+//! checks here are free of JNI types so they can be tested without a JVM.
+
+use slipstream_core::{AddressFamily, HostPort};
+use slipstream_ffi::{ResolverMode, ResolverSpec};
+use std::fmt;
+use std::net::IpAddr;
+
+/// Start result for an invalid resolver configuration.
+pub(crate) const START_INVALID_RESOLVERS: i32 = -2;
+/// Start result when the resolver arrays disagree in length.
+pub(crate) const START_ARRAY_LENGTH_MISMATCH: i32 = -4;
+
+/// Lengths of the parallel resolver arrays, as reported by the JVM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResolverArrayLengths {
+    pub(crate) hosts: usize,
+    pub(crate) ports: usize,
+    pub(crate) authoritative: usize,
+    pub(crate) overrides: usize,
+}
+
+impl ResolverArrayLengths {
+    /// Returns the resolver count once every array has the same length.
+    pub(crate) fn resolver_count(&self) -> Result<usize, ResolverArgsError> {
+        if self.ports != self.hosts
+            || self.authoritative != self.hosts
+            || self.overrides != self.hosts
+        {
+            return Err(ResolverArgsError::LengthMismatch(*self));
+        }
+        if self.hosts == 0 {
+            return Err(ResolverArgsError::Empty);
+        }
+        Ok(self.hosts)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ResolverArgsError {
+    Empty,
+    LengthMismatch(ResolverArrayLengths),
+    InvalidOverride { index: usize, value: String },
+}
+
+impl ResolverArgsError {
+    /// Code returned to Java for this error.
+    pub(crate) fn code(&self) -> i32 {
+        match self {
+            ResolverArgsError::LengthMismatch(_) => START_ARRAY_LENGTH_MISMATCH,
+            ResolverArgsError::Empty | ResolverArgsError::InvalidOverride { .. } => {
+                START_INVALID_RESOLVERS
+            }
+        }
+    }
+}
+
+impl fmt::Display for ResolverArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolverArgsError::Empty => write!(f, "No resolvers provided"),
+            ResolverArgsError::LengthMismatch(lengths) => write!(
+                f,
+                "Resolver array lengths differ: hosts={} ports={} authoritative={} overrides={}",
+                lengths.hosts, lengths.ports, lengths.authoritative, lengths.overrides
+            ),
+            ResolverArgsError::InvalidOverride { index, value } => {
+                write!(f, "Invalid resolver override at index {}: {}", index, value)
+            }
+        }
+    }
+}
+
+/// Resolver specs plus the fixed addresses (empty override = resolve `host`).
+pub(crate) type ResolverArgs = (Vec<ResolverSpec>, Vec<(String, IpAddr)>);
+
+/// Builds resolver specs from the copied Java arrays.
+pub(crate) fn build_resolver_specs(
+    hosts: Vec<String>,
+    ports: &[i32],
+    authoritative: &[u8],
+    overrides: &[String],
+) -> Result<ResolverArgs, ResolverArgsError> {
+    let count = ResolverArrayLengths {
+        hosts: hosts.len(),
+        ports: ports.len(),
+        authoritative: authoritative.len(),
+        overrides: overrides.len(),
+    }
+    .resolver_count()?;
+
+    let mut resolvers = Vec::with_capacity(count);
+    let mut host_overrides = Vec::new();
+    for (index, host) in hosts.into_iter().enumerate() {
+        let override_ip = &overrides[index];
+        if !override_ip.is_empty() {
+            match override_ip.parse::<IpAddr>() {
+                Ok(ip) => host_overrides.push((host.clone(), ip)),
+                Err(_) => {
+                    return Err(ResolverArgsError::InvalidOverride {
+                        index,
+                        value: override_ip.clone(),
+                    })
+                }
+            }
+        }
+        let mode = if authoritative[index] != 0 {
+            ResolverMode::Authoritative
+        } else {
+            ResolverMode::Recursive
+        };
+        // Use V4 as default address family - DNS over UDP typically uses IPv4
+        resolvers.push(ResolverSpec {
+            resolver: HostPort {
+                host,
+                port: ports[index] as u16,
+                family: AddressFamily::V4,
+            },
+            mode,
+            max_inflight_polls: 0,
+        });
+    }
+    Ok((resolvers, host_overrides))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn mismatched_lengths_get_their_own_code() {
+        let err = build_resolver_specs(
+            strings(&["a.example", "b.example"]),
+            &[53],
+            &[0, 1],
+            &strings(&["", ""]),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), START_ARRAY_LENGTH_MISMATCH);
+
+        let err = build_resolver_specs(strings(&["a.example"]), &[53], &[0], &[]).unwrap_err();
+        assert_eq!(err.code(), START_ARRAY_LENGTH_MISMATCH);
+
+        let err = build_resolver_specs(Vec::new(), &[], &[], &[]).unwrap_err();
+        assert_eq!(err, ResolverArgsError::Empty);
+        assert_eq!(err.code(), START_INVALID_RESOLVERS);
+    }
+
+    #[test]
+    fn builds_specs_and_overrides() {
+        let (resolvers, overrides) = build_resolver_specs(
+            strings(&["a.example", "b.example"]),
+            &[53, 5353],
+            &[0, 1],
+            &strings(&["", "192.0.2.7"]),
+        )
+        .unwrap();
+        assert_eq!(resolvers.len(), 2);
+        assert_eq!(resolvers[0].resolver.port, 53);
+        assert_eq!(resolvers[0].mode, ResolverMode::Recursive);
+        assert_eq!(resolvers[1].mode, ResolverMode::Authoritative);
+        assert_eq!(
+            overrides,
+            vec![("b.example".to_string(), "192.0.2.7".parse().unwrap())]
+        );
+
+        let err = build_resolver_specs(strings(&["a.example"]), &[53], &[0], &strings(&["nope"]))
+            .unwrap_err();
+        assert_eq!(err.code(), START_INVALID_RESOLVERS);
+    }
+}
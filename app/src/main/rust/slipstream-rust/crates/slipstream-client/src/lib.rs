@@ -5,14 +5,20 @@
 
 pub mod dns;
 pub mod error;
+#[cfg(any(target_os = "android", test))]
+mod jni_args;
+#[cfg(any(target_os = "android", test))]
+mod log_ring;
 pub mod pacing;
 pub mod pinning;
 pub mod runtime;
 pub mod streams;
+pub mod supervisor;
+pub mod ticket_store;
 
 #[cfg(target_os = "android")]
 pub mod android;
 
 // Re-export key types for library users
 pub use error::ClientError;
-pub use runtime::run_client;
+pub use runtime::{run_client, run_client_validate};
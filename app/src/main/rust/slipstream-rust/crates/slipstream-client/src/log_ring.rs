@@ -0,0 +1,208 @@
+//! Recent log lines kept in memory for `nativeGetRecentLogs`, so the app
+//! can attach them to a bug report without logcat access.
+//!
+//! The ring starts disabled. Until `nativeSetRecentLogBytes` gives it a
+//! capacity its layer filters every event out, so nothing is formatted.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Largest capacity Java may ask for.
+pub(crate) const MAX_RECENT_LOG_BYTES: usize = 4 * 1024 * 1024;
+
+#[cfg(target_os = "android")]
+pub(crate) static RECENT_LOGS: LogRing = LogRing::new();
+
+/// Bounded, thread-safe buffer of whole log lines; the oldest lines go
+/// first once the total passes the capacity.
+pub(crate) struct LogRing {
+    /// Mirrors `lines.capacity_bytes` so the filter checks it without the
+    /// lock.
+    capacity_bytes: AtomicUsize,
+    lines: Mutex<RingLines>,
+}
+
+struct RingLines {
+    lines: VecDeque<String>,
+    bytes: usize,
+    capacity_bytes: usize,
+}
+
+impl LogRing {
+    pub(crate) const fn new() -> Self {
+        Self {
+            capacity_bytes: AtomicUsize::new(0),
+            lines: Mutex::new(RingLines {
+                lines: VecDeque::new(),
+                bytes: 0,
+                capacity_bytes: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity_bytes.load(Ordering::Relaxed) > 0
+    }
+
+    /// Sets the capacity, capped at [`MAX_RECENT_LOG_BYTES`], and drops the
+    /// oldest lines that no longer fit; 0 disables the ring and empties it.
+    pub(crate) fn set_capacity(&self, bytes: usize) -> usize {
+        let bytes = bytes.min(MAX_RECENT_LOG_BYTES);
+        let Ok(mut ring) = self.lines.lock() else {
+            return 0;
+        };
+        ring.capacity_bytes = bytes;
+        ring.evict();
+        self.capacity_bytes.store(bytes, Ordering::Relaxed);
+        bytes
+    }
+
+    pub(crate) fn push(&self, line: &str) {
+        let Ok(mut ring) = self.lines.lock() else {
+            return;
+        };
+        if ring.capacity_bytes == 0 {
+            return;
+        }
+        // A line longer than the whole ring keeps its start.
+        let mut end = line.len().min(ring.capacity_bytes);
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        let line = &line[..end];
+        ring.bytes += line.len();
+        ring.lines.push_back(line.to_string());
+        ring.evict();
+    }
+
+    /// The buffered lines, oldest first, one per line.
+    pub(crate) fn snapshot(&self) -> String {
+        let Ok(ring) = self.lines.lock() else {
+            return String::new();
+        };
+        let mut out = String::with_capacity(ring.bytes + ring.lines.len());
+        for line in &ring.lines {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl RingLines {
+    fn evict(&mut self) {
+        while self.bytes > self.capacity_bytes {
+            let Some(line) = self.lines.pop_front() else {
+                break;
+            };
+            self.bytes -= line.len();
+        }
+    }
+}
+
+/// Collects one formatted event and pushes it to the ring on drop.
+pub(crate) struct RingWriter {
+    ring: &'static LogRing,
+    buf: Vec<u8>,
+}
+
+impl io::Write for RingWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.buf);
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            self.ring.push(line);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct RingMakeWriter(&'static LogRing);
+
+impl<'a> MakeWriter<'a> for RingMakeWriter {
+    type Writer = RingWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RingWriter {
+            ring: self.0,
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// The formatting layer that feeds `ring`; it sees only the events the
+/// global filter lets through, and none while the ring is disabled.
+pub(crate) fn layer<S>(ring: &'static LogRing) -> impl Layer<S>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_writer(RingMakeWriter(ring))
+        .with_ansi(false)
+        .with_target(false)
+        .with_filter(filter_fn(move |_| ring.enabled()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layer, LogRing, MAX_RECENT_LOG_BYTES};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn ring_keeps_the_newest_lines_within_its_capacity() {
+        let ring = LogRing::new();
+        ring.push("dropped while disabled");
+        assert_eq!(ring.snapshot(), "");
+
+        assert_eq!(ring.set_capacity(12), 12);
+        for line in ["first", "second", "third"] {
+            ring.push(line);
+        }
+        assert_eq!(ring.snapshot(), "second\nthird\n");
+        ring.push("abc");
+        assert_eq!(ring.snapshot(), "third\nabc\n");
+        ring.push("a line longer than the ring");
+        assert_eq!(ring.snapshot(), "a line longe\n");
+
+        ring.set_capacity(0);
+        assert!(!ring.enabled());
+        assert_eq!(ring.snapshot(), "");
+        assert_eq!(ring.set_capacity(usize::MAX), MAX_RECENT_LOG_BYTES);
+    }
+
+    #[test]
+    fn layer_records_events_only_while_enabled() {
+        static RING: LogRing = LogRing::new();
+        let subscriber = tracing_subscriber::registry().with(layer(&RING));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("before the ring is enabled");
+            RING.set_capacity(4096);
+            tracing::warn!("resolver {} stopped answering", "1.1.1.1:53");
+        });
+        let logs = RING.snapshot();
+        assert!(!logs.contains("before"), "{}", logs);
+        assert_eq!(logs.lines().count(), 1, "{}", logs);
+        assert!(
+            logs.trim_end()
+                .ends_with("WARN resolver 1.1.1.1:53 stopped answering"),
+            "{}",
+            logs
+        );
+    }
+}
@@ -9,11 +9,16 @@ use clap::{parser::ValueSource, ArgGroup, CommandFactory, FromArgMatches, Parser
 use slipstream_core::{
     normalize_domain, parse_host_port, parse_host_port_parts, sip003, AddressKind, HostPort,
 };
-use slipstream_ffi::{ClientConfig, ResolverMode, ResolverSpec};
+use slipstream_dns::validate_service_label;
+use slipstream_ffi::{
+    AuthoritativePathTimeout, ClientConfig, KeepAlivePath, Profile, ResolverMode, ResolverSpec,
+    TicketStoreKey, ZeroSendReset,
+};
+use std::net::IpAddr;
 use tokio::runtime::Builder;
 use tracing_subscriber::EnvFilter;
 
-use runtime::run_client;
+use runtime::{run_client, run_client_validate};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -40,6 +45,8 @@ struct Args {
     congestion_control: Option<String>,
     #[arg(long = "authoritative", value_parser = parse_resolver)]
     authoritative: Vec<HostPort>,
+    #[arg(long = "host-override", value_name = "HOST=IP", value_parser = parse_host_override)]
+    host_override: Vec<(String, IpAddr)>,
     #[arg(
         short = 'g',
         long = "gso",
@@ -50,16 +57,152 @@ struct Args {
     gso: bool,
     #[arg(long = "domain", short = 'd', value_parser = parse_domain)]
     domain: Option<String>,
+    #[arg(long = "rotate-domain", value_parser = parse_domain)]
+    rotate_domain: Vec<String>,
     #[arg(long = "cert", value_name = "PATH")]
     cert: Option<String>,
     #[arg(long = "keep-alive-interval", short = 't', default_value_t = 400)]
     keep_alive_interval: u16,
+    #[arg(
+        long = "keep-alive-path",
+        default_value = "any",
+        value_parser = ["any", "authoritative", "recursive"]
+    )]
+    keep_alive_path: String,
     #[arg(long = "debug-poll")]
     debug_poll: bool,
     #[arg(long = "debug-streams")]
     debug_streams: bool,
     #[arg(long = "idle-poll-interval", default_value_t = 2000)]
     idle_poll_interval: u64,
+    #[arg(long = "watchdog-timeout", default_value_t = 30000)]
+    watchdog_timeout: u64,
+    #[arg(long = "reconnect-poll-grace", default_value_t = 2000)]
+    reconnect_poll_grace: u64,
+    #[arg(long = "usable-path-timeout", default_value_t = 15000)]
+    usable_path_timeout: u64,
+    #[arg(long = "min-reconnect-interval", default_value_t = 250)]
+    min_reconnect_interval: u64,
+    #[arg(long = "cid-rotation-interval", default_value_t = 0)]
+    cid_rotation_interval: u64,
+    #[arg(long = "domain-rotation-interval", default_value_t = 0)]
+    domain_rotation_interval: u64,
+    #[arg(long = "domain-rotation-bytes", default_value_t = 0)]
+    domain_rotation_bytes: u64,
+    #[arg(long = "loopback-only")]
+    loopback_only: bool,
+    #[arg(
+        long = "zero-send-reset",
+        default_value = "off",
+        value_parser = ["off", "stream", "connection"]
+    )]
+    zero_send_reset: String,
+    #[arg(
+        long = "zero-send-stall-iterations",
+        default_value_t = runtime::DEFAULT_ZERO_SEND_STALL_ITERATIONS,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    zero_send_stall_iterations: u64,
+    #[arg(long = "stream-path-affinity")]
+    stream_path_affinity: bool,
+    #[arg(long = "require-authoritative-path")]
+    require_authoritative_path: bool,
+    #[arg(long = "authoritative-path-timeout", default_value_t = 10000)]
+    authoritative_path_timeout: u64,
+    #[arg(
+        long = "authoritative-path-fallback",
+        default_value = "fallback",
+        value_parser = ["fallback", "fail"]
+    )]
+    authoritative_path_fallback: String,
+    #[arg(long = "ready-after-usable-path")]
+    ready_after_usable_path: bool,
+    #[arg(
+        long = "data-poll-bias",
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    data_poll_bias: u8,
+    #[arg(long = "send-batch", default_value_t = 0)]
+    send_batch: usize,
+    #[arg(long = "reconnect-delay-min", default_value_t = 250)]
+    reconnect_delay_min: u64,
+    #[arg(long = "reconnect-delay-max", default_value_t = 5000)]
+    reconnect_delay_max: u64,
+    #[arg(long = "debug-stream-checksums")]
+    debug_stream_checksums: bool,
+    #[arg(long = "stateless-reset-tolerance", default_value_t = 1)]
+    stateless_reset_tolerance: u32,
+    #[arg(
+        long = "max-inflight-polls",
+        value_name = "[RESOLVER=]N",
+        value_parser = parse_inflight_cap
+    )]
+    max_inflight_polls: Vec<InflightCap>,
+    #[arg(long = "qlog-dir", value_name = "DIR")]
+    qlog_dir: Option<String>,
+    #[arg(long = "service-label", value_name = "LABEL", value_parser = parse_service_label)]
+    service_label: Option<String>,
+    #[arg(long = "mtu-probe")]
+    mtu_probe: bool,
+    #[arg(long = "no-path-callbacks")]
+    no_path_callbacks: bool,
+    #[arg(long = "stop-sending-half-close")]
+    stop_sending_half_close: bool,
+    #[arg(long = "key-update-interval", default_value_t = 0)]
+    key_update_interval: u64,
+    #[arg(long = "key-update-bytes", default_value_t = 0)]
+    key_update_bytes: u64,
+    #[arg(long = "validate-only")]
+    validate_only: bool,
+    #[arg(long = "terminal-failure-repeats", default_value_t = 3)]
+    terminal_failure_repeats: u32,
+    #[arg(long = "stream-pool", default_value_t = 0)]
+    stream_pool: usize,
+    #[arg(long = "command-channel-cap", default_value_t = 0)]
+    command_channel_cap: usize,
+    #[arg(long = "reconnect-on-zero-credit")]
+    reconnect_on_zero_credit: bool,
+    #[arg(
+        long = "max-idle-sleep-ms",
+        default_value_t = runtime::DEFAULT_MAX_IDLE_SLEEP_MS,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_idle_sleep_ms: u64,
+    #[arg(long = "debug-stream-trailers")]
+    debug_stream_trailers: bool,
+    #[arg(long = "heartbeat-interval-ms", default_value_t = 0)]
+    heartbeat_interval_ms: u64,
+    #[arg(
+        long = "profile",
+        default_value = "balanced",
+        value_parser = ["balanced", "interactive", "bulk"]
+    )]
+    profile: String,
+    #[arg(long = "max-local-streams", default_value_t = 0)]
+    max_local_streams: usize,
+    #[arg(long = "closing-timeout-ms", default_value_t = runtime::DEFAULT_CLOSING_TIMEOUT_MS)]
+    closing_timeout_ms: u64,
+    #[arg(long = "ecs-opt-out")]
+    ecs_opt_out: bool,
+    #[arg(long = "max-streams-floor", default_value_t = 0)]
+    max_streams_floor: usize,
+    #[arg(
+        long = "response-size-feedback",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    response_size_feedback: u8,
+    #[arg(long = "ticket-store-key-file", value_name = "PATH")]
+    ticket_store_key_file: Option<String>,
+}
+
+/// One `--max-inflight-polls` value; without a resolver it is the default
+/// for every resolver.
+#[derive(Debug, Clone)]
+struct InflightCap {
+    resolver: Option<HostPort>,
+    max: usize,
 }
 
 fn main() {
@@ -106,7 +249,7 @@ fn main() {
     };
 
     let cli_has_resolvers = has_cli_resolvers(&matches);
-    let resolvers = if cli_has_resolvers {
+    let mut resolvers = if cli_has_resolvers {
         build_resolvers(&matches, true).unwrap_or_else(|err| {
             tracing::error!("Resolver error: {}", err);
             std::process::exit(2);
@@ -141,13 +284,21 @@ fn main() {
                             tracing::error!("SIP003 env error: {}", err);
                             std::process::exit(2);
                         });
-                vec![ResolverSpec { resolver, mode }]
+                vec![ResolverSpec {
+                    resolver,
+                    mode,
+                    max_inflight_polls: 0,
+                }]
             } else {
                 tracing::error!("At least one resolver is required");
                 std::process::exit(2);
             }
         }
     };
+    apply_inflight_caps(&mut resolvers, &args.max_inflight_polls).unwrap_or_else(|err| {
+        tracing::error!("Resolver error: {}", err);
+        std::process::exit(2);
+    });
 
     let congestion_control = if args.congestion_control.is_some() {
         args.congestion_control.clone()
@@ -169,6 +320,16 @@ fn main() {
         );
     }
 
+    let ticket_store_key = args.ticket_store_key_file.as_deref().map(|path| {
+        std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {}", path, err))
+            .and_then(|text| parse_ticket_store_key(&text))
+            .unwrap_or_else(|err| {
+                tracing::error!("Ticket store key error: {}", err);
+                std::process::exit(2);
+            })
+    });
+
     let keep_alive_interval = if cli_provided(&matches, "keep_alive_interval") {
         args.keep_alive_interval
     } else {
@@ -191,18 +352,90 @@ fn main() {
         idle_poll_override.unwrap_or(args.idle_poll_interval)
     };
 
+    let watchdog_timeout = if cli_provided(&matches, "watchdog_timeout") {
+        args.watchdog_timeout
+    } else {
+        let watchdog_override =
+            parse_watchdog_timeout(&sip003_env.plugin_options).unwrap_or_else(|err| {
+                tracing::error!("SIP003 env error: {}", err);
+                std::process::exit(2);
+            });
+        watchdog_override.unwrap_or(args.watchdog_timeout)
+    };
+
     let config = ClientConfig {
         tcp_listen_host: &tcp_listen_host,
         tcp_listen_port,
+        loopback_only: args.loopback_only,
         resolvers: &resolvers,
+        host_overrides: args.host_override.clone(),
         congestion_control: congestion_control.as_deref(),
         gso: args.gso,
         domain: &domain,
+        rotation_domains: &args.rotate_domain,
         cert: cert.as_deref(),
         keep_alive_interval: keep_alive_interval as usize,
+        keep_alive_path: match args.keep_alive_path.as_str() {
+            "authoritative" => KeepAlivePath::Authoritative,
+            "recursive" => KeepAlivePath::Recursive,
+            _ => KeepAlivePath::Any,
+        },
         debug_poll: args.debug_poll,
         debug_streams: args.debug_streams,
         idle_poll_interval_ms: idle_poll_interval,
+        watchdog_timeout_ms: watchdog_timeout,
+        reconnect_poll_grace_ms: args.reconnect_poll_grace,
+        usable_path_timeout_ms: args.usable_path_timeout,
+        min_reconnect_interval_ms: args.min_reconnect_interval,
+        cid_rotation_interval_ms: args.cid_rotation_interval,
+        domain_rotation_interval_ms: args.domain_rotation_interval,
+        domain_rotation_bytes: args.domain_rotation_bytes,
+        battery_saver_poll_interval_ms: None,
+        zero_send_reset: match args.zero_send_reset.as_str() {
+            "stream" => ZeroSendReset::Stream,
+            "connection" => ZeroSendReset::Connection,
+            _ => ZeroSendReset::Off,
+        },
+        zero_send_stall_iterations: args.zero_send_stall_iterations,
+        stream_path_affinity: args.stream_path_affinity,
+        require_authoritative_path: args.require_authoritative_path,
+        authoritative_path_timeout_ms: args.authoritative_path_timeout,
+        authoritative_path_timeout_action: match args.authoritative_path_fallback.as_str() {
+            "fail" => AuthoritativePathTimeout::Fail,
+            _ => AuthoritativePathTimeout::Fallback,
+        },
+        ready_requires_usable_path: args.ready_after_usable_path,
+        data_poll_bias_percent: args.data_poll_bias,
+        send_batch_size: args.send_batch,
+        reconnect_delay_min_ms: args.reconnect_delay_min,
+        reconnect_delay_max_ms: args.reconnect_delay_max,
+        debug_stream_checksums: args.debug_stream_checksums,
+        stateless_reset_tolerance: args.stateless_reset_tolerance,
+        qlog_dir: args.qlog_dir.as_deref(),
+        service_label: args.service_label.as_deref(),
+        mtu_probe: args.mtu_probe,
+        path_callbacks: !args.no_path_callbacks,
+        ticket_store_key,
+        stop_sending_half_close: args.stop_sending_half_close,
+        key_update_interval_ms: args.key_update_interval,
+        key_update_bytes: args.key_update_bytes,
+        terminal_failure_repeats: args.terminal_failure_repeats,
+        stream_pool_size: args.stream_pool,
+        command_channel_capacity: args.command_channel_cap,
+        reconnect_on_zero_credit: args.reconnect_on_zero_credit,
+        max_idle_sleep_ms: args.max_idle_sleep_ms,
+        debug_stream_trailers: args.debug_stream_trailers,
+        heartbeat_interval_ms: args.heartbeat_interval_ms,
+        profile: match args.profile.as_str() {
+            "interactive" => Profile::Interactive,
+            "bulk" => Profile::Bulk,
+            _ => Profile::Balanced,
+        },
+        max_local_streams: args.max_local_streams,
+        closing_timeout_ms: args.closing_timeout_ms,
+        ecs_opt_out: args.ecs_opt_out,
+        max_streams_floor: args.max_streams_floor,
+        response_size_feedback_percent: args.response_size_feedback,
     };
 
     let runtime = Builder::new_current_thread()
@@ -210,11 +443,23 @@ fn main() {
         .enable_time()
         .build()
         .expect("Failed to build Tokio runtime");
+    if args.validate_only {
+        match runtime.block_on(run_client_validate(&config)) {
+            Ok(report) => {
+                println!("{}", report);
+                std::process::exit(report.exit_code());
+            }
+            Err(err) => {
+                tracing::error!("Client error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
     match runtime.block_on(run_client(&config)) {
         Ok(code) => std::process::exit(code),
         Err(err) => {
             tracing::error!("Client error: {}", err);
-            std::process::exit(1);
+            std::process::exit(if err.is_permanent() { 8 } else { 1 });
         }
     }
 }
@@ -232,10 +477,74 @@ fn parse_domain(input: &str) -> Result<String, String> {
     normalize_domain(input).map_err(|err| err.to_string())
 }
 
+fn parse_service_label(input: &str) -> Result<String, String> {
+    validate_service_label(input).map_err(|err| err.to_string())?;
+    Ok(input.to_ascii_lowercase())
+}
+
 fn parse_resolver(input: &str) -> Result<HostPort, String> {
     parse_host_port(input, 53, AddressKind::Resolver).map_err(|err| err.to_string())
 }
 
+fn parse_host_override(input: &str) -> Result<(String, IpAddr), String> {
+    let (host, ip) = input
+        .split_once('=')
+        .ok_or_else(|| format!("Expected HOST=IP, got {}", input))?;
+    let host = host.trim();
+    if host.is_empty() {
+        return Err(format!("Empty host in override {}", input));
+    }
+    let ip = ip
+        .trim()
+        .parse::<IpAddr>()
+        .map_err(|_| format!("Invalid IP address in override {}", input))?;
+    Ok((host.to_string(), ip))
+}
+
+fn parse_inflight_cap(input: &str) -> Result<InflightCap, String> {
+    let (resolver, max) = match input.rsplit_once('=') {
+        Some((resolver, max)) => (Some(parse_resolver(resolver.trim())?), max),
+        None => (None, input),
+    };
+    let max = max
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid inflight poll cap in {}", input))?;
+    Ok(InflightCap { resolver, max })
+}
+
+/// Sets `max_inflight_polls` on every resolver: the last bare value is the
+/// default and `RESOLVER=N` entries override it for matching resolvers.
+fn apply_inflight_caps(resolvers: &mut [ResolverSpec], caps: &[InflightCap]) -> Result<(), String> {
+    let default = caps
+        .iter()
+        .rfind(|cap| cap.resolver.is_none())
+        .map_or(0, |cap| cap.max);
+    for spec in resolvers.iter_mut() {
+        spec.max_inflight_polls = default;
+    }
+    for cap in caps {
+        let Some(target) = &cap.resolver else {
+            continue;
+        };
+        let mut matched = false;
+        for spec in resolvers.iter_mut().filter(|spec| {
+            spec.resolver.port == target.port
+                && spec.resolver.host.eq_ignore_ascii_case(&target.host)
+        }) {
+            spec.max_inflight_polls = cap.max;
+            matched = true;
+        }
+        if !matched {
+            return Err(format!(
+                "--max-inflight-polls {}:{} does not match any resolver",
+                target.host, target.port
+            ));
+        }
+    }
+    Ok(())
+}
+
 fn build_resolvers(matches: &clap::ArgMatches, require: bool) -> Result<Vec<ResolverSpec>, String> {
     let mut ordered = Vec::new();
     collect_resolvers(matches, "resolver", ResolverMode::Recursive, &mut ordered)?;
@@ -269,7 +578,14 @@ fn collect_resolvers(
         return Err(format!("Mismatched {} arguments", name));
     }
     for (idx, resolver) in indices.into_iter().zip(values) {
-        ordered.push((idx, ResolverSpec { resolver, mode }));
+        ordered.push((
+            idx,
+            ResolverSpec {
+                resolver,
+                mode,
+                max_inflight_polls: 0,
+            },
+        ));
     }
     Ok(())
 }
@@ -340,7 +656,11 @@ fn parse_resolvers_from_options(
         for entry in entries {
             let resolver = parse_host_port(&entry, 53, AddressKind::Resolver)
                 .map_err(|err| err.to_string())?;
-            ordered.push(ResolverSpec { resolver, mode });
+            ordered.push(ResolverSpec {
+                resolver,
+                mode,
+                max_inflight_polls: 0,
+            });
         }
     }
     Ok(ResolverOptions {
@@ -391,10 +711,49 @@ fn parse_idle_poll_interval(options: &[sip003::Sip003Option]) -> Result<Option<u
     Ok(last)
 }
 
+fn parse_watchdog_timeout(options: &[sip003::Sip003Option]) -> Result<Option<u64>, String> {
+    let mut last = None;
+    for option in options {
+        if option.key == "watchdog-timeout" {
+            let value = option.value.trim();
+            let parsed = value
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid watchdog-timeout value: {}", value))?;
+            last = Some(parsed);
+        }
+    }
+    Ok(last)
+}
+
+/// A ticket store key written as 64 hex digits, surrounding whitespace
+/// allowed.
+fn parse_ticket_store_key(text: &str) -> Result<TicketStoreKey, String> {
+    let hex = text.trim();
+    if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err("the key must be 64 hex digits (32 bytes)".to_string());
+    }
+    let mut key = [0u8; 32];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .map_err(|err| format!("invalid key digits: {}", err))?;
+    }
+    Ok(TicketStoreKey(key))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parses_hex_ticket_store_keys() {
+        let hex = "00ff".repeat(16);
+        let key = parse_ticket_store_key(&format!("  {}\n", hex)).expect("key should parse");
+        assert_eq!(key.0[..2], [0x00, 0xff]);
+        assert_eq!(key.0[31], 0xff);
+        assert!(parse_ticket_store_key(&hex[..62]).is_err());
+        assert!(parse_ticket_store_key(&format!("{}zz", &hex[..62])).is_err());
+    }
+
     #[test]
     fn preserves_ordered_resolvers() {
         let matches = Args::command()
@@ -421,6 +780,34 @@ mod tests {
         assert_eq!(resolvers[2].resolver.port, 5353);
     }
 
+    #[test]
+    fn applies_default_and_per_resolver_inflight_caps() {
+        let matches = Args::command()
+            .try_get_matches_from([
+                "slipstream-client",
+                "--domain",
+                "example.com",
+                "--resolver",
+                "1.1.1.1",
+                "--authoritative",
+                "2.2.2.2:5353",
+                "--max-inflight-polls",
+                "8",
+                "--max-inflight-polls",
+                "2.2.2.2:5353=3",
+            ])
+            .expect("matches should parse");
+        let args = Args::from_arg_matches(&matches).expect("args should parse");
+        let mut resolvers = build_resolvers(&matches, true).expect("resolvers should parse");
+        apply_inflight_caps(&mut resolvers, &args.max_inflight_polls).expect("caps should apply");
+        assert_eq!(resolvers[0].max_inflight_polls, 8);
+        assert_eq!(resolvers[1].max_inflight_polls, 3);
+
+        let unmatched = parse_inflight_cap("2.2.2.2=3").expect("cap should parse");
+        assert!(apply_inflight_caps(&mut resolvers, &[unmatched]).is_err());
+        assert!(parse_inflight_cap("1.1.1.1=many").is_err());
+    }
+
     #[test]
     fn maps_authoritative_first() {
         let matches = Args::command()
@@ -516,4 +903,14 @@ mod tests {
         assert!(parsed.resolvers.is_empty());
         assert!(parsed.authoritative_remote);
     }
+
+    #[test]
+    fn host_override_requires_host_and_ip() {
+        let parsed = parse_host_override("dns.corp.example=10.0.0.53").expect("override");
+        assert_eq!(parsed.0, "dns.corp.example");
+        assert_eq!(parsed.1, "10.0.0.53".parse::<IpAddr>().unwrap());
+        assert!(parse_host_override("dns.corp.example").is_err());
+        assert!(parse_host_override("=10.0.0.53").is_err());
+        assert!(parse_host_override("dns.corp.example=not-an-ip").is_err());
+    }
 }
@@ -1,22 +1,101 @@
 use slipstream_ffi::picoquic::picoquic_path_quality_t;
 
 // Pacing gain tuning for the poll-based pacing loop.
-const PACING_GAIN_BASE: f64 = 1.0;
-const PACING_GAIN_PROBE: f64 = 1.25;
 const PACING_GAIN_EPSILON: f64 = 0.05;
+/// Weight of each response in the smoothed response fill, as in RTT
+/// smoothing (RFC 6298).
+const RESPONSE_FILL_ALPHA: f64 = 0.125;
+/// Fill at which response-size feedback leaves the poll target alone.
+const RESPONSE_FILL_NEUTRAL: f64 = 0.5;
+
+/// Pacing parameters the loop reads on every iteration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PacingParams {
+    /// Gain on the pacing-rate (or cwnd) poll target while the rate holds.
+    pub(crate) gain_base: f64,
+    /// Gain while the pacing rate is growing, to probe for more.
+    pub(crate) gain_probe: f64,
+    /// Packet loop and poll burst multiplier for authoritative paths.
+    pub(crate) authoritative_burst_multiplier: usize,
+    /// Packet loop and poll burst multiplier for recursive paths.
+    pub(crate) recursive_burst_multiplier: usize,
+}
+
+/// Named pacing parameter sets, switchable while the tunnel runs
+/// (Android `nativeSetPacingProfile`). Other builds stay on `Balanced`.
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum PacingProfile {
+    #[default]
+    Balanced,
+    /// Authoritative-style aggressive polling for paths that can take it.
+    Turbo,
+    /// Conservative polling that stays safe behind public recursive resolvers.
+    Compatibility,
+}
+
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+impl PacingProfile {
+    pub(crate) fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Balanced),
+            1 => Some(Self::Turbo),
+            2 => Some(Self::Compatibility),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn code(self) -> i32 {
+        match self {
+            Self::Balanced => 0,
+            Self::Turbo => 1,
+            Self::Compatibility => 2,
+        }
+    }
+
+    pub(crate) fn params(self) -> PacingParams {
+        match self {
+            Self::Balanced => PacingParams {
+                gain_base: 1.0,
+                gain_probe: 1.25,
+                authoritative_burst_multiplier: 2,
+                recursive_burst_multiplier: 1,
+            },
+            Self::Turbo => PacingParams {
+                gain_base: 1.25,
+                gain_probe: 1.5,
+                authoritative_burst_multiplier: 4,
+                recursive_burst_multiplier: 2,
+            },
+            Self::Compatibility => PacingParams {
+                gain_base: 0.75,
+                gain_probe: 1.0,
+                authoritative_burst_multiplier: 1,
+                recursive_burst_multiplier: 1,
+            },
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, Default)]
 pub(crate) struct PacingBudgetSnapshot {
     pub(crate) pacing_rate: u64,
     pub(crate) qps: f64,
+    /// Pacing gain times the response-size feedback factor.
     pub(crate) gain: f64,
     pub(crate) target_inflight: usize,
+    /// Smoothed share of the largest response payload the responses carry.
+    pub(crate) response_fill: f64,
 }
 
 pub(crate) struct PacingPollBudget {
     payload_bytes: f64,
     mtu: u32,
     last_pacing_rate: u64,
+    response_fill: f64,
+    /// Largest response payload seen, the measure of a full response. The
+    /// server's MTU is not known here, so it starts at ours.
+    full_response_bytes: usize,
 }
 
 impl PacingPollBudget {
@@ -26,58 +105,95 @@ impl PacingPollBudget {
             payload_bytes: mtu.max(1) as f64,
             mtu,
             last_pacing_rate: 0,
+            response_fill: RESPONSE_FILL_NEUTRAL,
+            full_response_bytes: mtu.max(1) as usize,
         }
     }
 
+    /// Poll target for the path. `feedback_percent`
+    /// (`--response-size-feedback`) scales it by how full responses have
+    /// been: by up to that share more when they arrive full, and as much
+    /// less when they arrive empty.
     pub(crate) fn target_inflight(
         &mut self,
         quality: &picoquic_path_quality_t,
         rtt_proxy_us: u64,
+        params: PacingParams,
+        feedback_percent: u8,
     ) -> PacingBudgetSnapshot {
         let pacing_rate = quality.pacing_rate;
         let rtt_seconds = (self.derive_rtt_us(quality.rtt, rtt_proxy_us) as f64) / 1_000_000.0;
+        let feedback = self.response_feedback(feedback_percent);
         if pacing_rate == 0 {
-            let target_inflight = cwnd_target_polls(quality.cwin, self.mtu);
+            let cwnd_polls = cwnd_target_polls(quality.cwin, self.mtu) as f64;
+            let gain = params.gain_base * feedback;
+            let target_inflight = scaled_polls(cwnd_polls * gain);
             let qps = target_inflight as f64 / rtt_seconds;
             self.last_pacing_rate = 0;
             return PacingBudgetSnapshot {
                 pacing_rate,
                 qps,
-                gain: PACING_GAIN_BASE,
+                gain,
                 target_inflight,
+                response_fill: self.response_fill,
             };
         }
 
-        let gain = self.next_gain(pacing_rate);
+        let gain = self.next_gain(pacing_rate, params) * feedback;
         let qps = (pacing_rate as f64 / self.payload_bytes) * gain;
-        let target_inflight = (qps * rtt_seconds).ceil().min(usize::MAX as f64) as usize;
+        let target_inflight = scaled_polls(qps * rtt_seconds);
 
         PacingBudgetSnapshot {
             pacing_rate,
             qps,
             gain,
             target_inflight,
+            response_fill: self.response_fill,
         }
     }
 
+    /// Folds one answered poll into the response fill; `payload_len` is 0
+    /// for a response that carried no QUIC packet.
+    pub(crate) fn observe_response(&mut self, payload_len: usize) {
+        self.full_response_bytes = self.full_response_bytes.max(payload_len);
+        let fill = payload_len as f64 / self.full_response_bytes as f64;
+        self.response_fill += (fill - self.response_fill) * RESPONSE_FILL_ALPHA;
+    }
+
+    fn response_feedback(&self, feedback_percent: u8) -> f64 {
+        let strength = feedback_percent.min(100) as f64 / 100.0;
+        let lean = (self.response_fill - RESPONSE_FILL_NEUTRAL) / RESPONSE_FILL_NEUTRAL;
+        1.0 + strength * lean
+    }
+
+    /// Forget the last pacing rate, as after a clock jump: rates measured
+    /// across the jump say nothing about the path.
+    pub(crate) fn reset_baseline(&mut self) {
+        self.last_pacing_rate = 0;
+    }
+
     fn derive_rtt_us(&self, rtt_us: u64, rtt_proxy_us: u64) -> u64 {
         let candidate = if rtt_us > 0 { rtt_us } else { rtt_proxy_us };
         // Clamp to 1us to avoid divide-by-zero when RTT is unknown.
         candidate.max(1)
     }
 
-    fn next_gain(&mut self, pacing_rate: u64) -> f64 {
+    fn next_gain(&mut self, pacing_rate: u64, params: PacingParams) -> f64 {
         let gain =
             if pacing_rate as f64 > (self.last_pacing_rate as f64) * (1.0 + PACING_GAIN_EPSILON) {
-                PACING_GAIN_PROBE
+                params.gain_probe
             } else {
-                PACING_GAIN_BASE
+                params.gain_base
             };
         self.last_pacing_rate = pacing_rate;
         gain
     }
 }
 
+fn scaled_polls(polls: f64) -> usize {
+    polls.ceil().min(usize::MAX as f64) as usize
+}
+
 pub(crate) fn cwnd_target_polls(cwin: u64, mtu: u32) -> usize {
     debug_assert!(mtu > 0, "mtu must be > 0");
     let mtu = mtu as u64;
@@ -101,3 +217,143 @@ pub(crate) fn inflight_packet_estimate(bytes_in_transit: u64, mtu: u32) -> usize
         packets as usize
     }
 }
+
+/// Scale an authoritative path's pacing poll deficit while QUIC has stream
+/// data it can send (`--data-poll-bias`). Data-bearing queries poll anyway,
+/// so at 100 no extra pacing polls go out; at 0 pacing polls are never held
+/// back. Flow-blocked connections keep the full deficit so polling can pull
+/// in the MAX_DATA that unblocks them.
+pub(crate) fn data_biased_poll_deficit(
+    pacing_deficit: usize,
+    has_ready_stream: bool,
+    flow_blocked: bool,
+    data_bias_percent: u8,
+) -> usize {
+    if !has_ready_stream || flow_blocked {
+        return pacing_deficit;
+    }
+    let keep_percent = 100usize.saturating_sub(data_bias_percent.min(100) as usize);
+    pacing_deficit.saturating_mul(keep_percent) / 100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_biased_poll_deficit, PacingPollBudget, PacingProfile};
+    use slipstream_ffi::picoquic::picoquic_path_quality_t;
+
+    const PROFILES: [PacingProfile; 3] = [
+        PacingProfile::Compatibility,
+        PacingProfile::Balanced,
+        PacingProfile::Turbo,
+    ];
+
+    fn quality(pacing_rate: u64, cwin: u64, rtt_us: u64) -> picoquic_path_quality_t {
+        // SAFETY: path quality is plain-old-data; zeroing is valid.
+        let mut quality: picoquic_path_quality_t = unsafe { std::mem::zeroed() };
+        quality.pacing_rate = pacing_rate;
+        quality.cwin = cwin;
+        quality.rtt = rtt_us;
+        quality
+    }
+
+    #[test]
+    fn profile_codes_round_trip() {
+        for profile in PROFILES {
+            assert_eq!(PacingProfile::from_code(profile.code()), Some(profile));
+        }
+        assert_eq!(PacingProfile::from_code(3), None);
+        assert_eq!(PacingProfile::default(), PacingProfile::Balanced);
+    }
+
+    #[test]
+    fn profile_targets_stay_ordered_and_bounded() {
+        // 100 kB/s over 1000-byte polls at 100 ms RTT is 10 polls in flight
+        // at unit gain; cwnd-only paths have 20 MTUs of window.
+        let paced = quality(100_000, 0, 100_000);
+        let cwnd_only = quality(0, 20_000, 100_000);
+        let mut previous = (0usize, 0usize, 0usize, 0usize);
+        for profile in PROFILES {
+            let params = profile.params();
+            assert!(params.gain_base > 0.0 && params.gain_base <= params.gain_probe);
+            assert!(params.authoritative_burst_multiplier >= params.recursive_burst_multiplier);
+            assert!(params.recursive_burst_multiplier >= 1);
+
+            let mut budget = PacingPollBudget::new(1000);
+            let probing = budget.target_inflight(&paced, 1, params, 0).target_inflight;
+            let steady = budget.target_inflight(&paced, 1, params, 0).target_inflight;
+            let cwnd = budget
+                .target_inflight(&cwnd_only, 1, params, 0)
+                .target_inflight;
+            assert!(
+                (5..=20).contains(&steady),
+                "{:?} steady={}",
+                profile,
+                steady
+            );
+            assert!(probing >= steady);
+            assert!((10..=30).contains(&cwnd), "{:?} cwnd={}", profile, cwnd);
+
+            let current = (steady, probing, cwnd, params.authoritative_burst_multiplier);
+            assert!(current.0 >= previous.0 && current.1 >= previous.1);
+            assert!(current.2 >= previous.2 && current.3 >= previous.3);
+            previous = current;
+        }
+        let balanced = PacingProfile::Balanced.params();
+        let mut budget = PacingPollBudget::new(1000);
+        assert_eq!(
+            budget
+                .target_inflight(&paced, 1, balanced, 0)
+                .target_inflight,
+            13
+        );
+        assert_eq!(
+            budget
+                .target_inflight(&paced, 1, balanced, 0)
+                .target_inflight,
+            10
+        );
+    }
+
+    #[test]
+    fn response_feedback_follows_response_fill() {
+        let paced = quality(100_000, 0, 100_000);
+        let balanced = PacingProfile::Balanced.params();
+        let steady_target = |budget: &mut PacingPollBudget, percent| {
+            budget.reset_baseline();
+            budget.target_inflight(&paced, 1, balanced, percent);
+            budget.target_inflight(&paced, 1, balanced, percent)
+        };
+
+        let mut budget = PacingPollBudget::new(1000);
+        assert_eq!(steady_target(&mut budget, 100).target_inflight, 10);
+        for _ in 0..64 {
+            budget.observe_response(1000);
+        }
+        let full = steady_target(&mut budget, 100);
+        assert!(full.response_fill > 0.99, "fill={}", full.response_fill);
+        assert!((19..=20).contains(&full.target_inflight), "{:?}", full);
+        assert_eq!(steady_target(&mut budget, 50).target_inflight, 15);
+        assert_eq!(steady_target(&mut budget, 0).target_inflight, 10);
+
+        for _ in 0..64 {
+            budget.observe_response(0);
+        }
+        let empty = steady_target(&mut budget, 100);
+        assert!(empty.response_fill < 0.01, "fill={}", empty.response_fill);
+        assert!(empty.target_inflight <= 1, "{:?}", empty);
+
+        // A payload larger than the MTU becomes the new measure of full.
+        budget.observe_response(1400);
+        budget.observe_response(700);
+        assert!(steady_target(&mut budget, 100).response_fill < 0.2);
+    }
+
+    #[test]
+    fn data_bias_scales_pacing_polls_only_when_data_can_flow() {
+        assert_eq!(data_biased_poll_deficit(8, true, false, 100), 0);
+        assert_eq!(data_biased_poll_deficit(8, true, false, 75), 2);
+        assert_eq!(data_biased_poll_deficit(8, true, false, 0), 8);
+        assert_eq!(data_biased_poll_deficit(8, true, true, 100), 8);
+        assert_eq!(data_biased_poll_deficit(8, false, false, 100), 8);
+    }
+}
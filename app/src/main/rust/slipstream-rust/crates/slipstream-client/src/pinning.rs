@@ -80,6 +80,14 @@ pub fn configure_pinned_certificate(
     Ok(())
 }
 
+/// SHA-256 of the pinned certificate's DER encoding.
+pub fn pinned_cert_fingerprint(cert_path: &str) -> Result<Vec<u8>, String> {
+    let (der, _) = load_pinned_cert(cert_path)?;
+    openssl::hash::hash(MessageDigest::sha256(), &der)
+        .map(|digest| digest.to_vec())
+        .map_err(|err| format!("Failed to hash cert {}: {}", cert_path, err))
+}
+
 fn load_pinned_cert(cert_path: &str) -> Result<(Vec<u8>, PKey<Public>), String> {
     let pem =
         fs::read(cert_path).map_err(|err| format!("Failed to read cert {}: {}", cert_path, err))?;
@@ -1,17 +1,72 @@
+mod authoritative_gate;
+mod buffers;
+mod cid_rotation;
+mod clock_jump;
+mod domain_rotation;
+mod heartbeat;
+mod idle;
+mod keep_alive;
+mod key_update;
+mod mtu_probe;
 mod path;
+mod profile;
+mod qname_guard;
+mod reconnect;
+mod rtt;
+mod send_batch;
 mod setup;
+mod stateless_reset;
+mod summary;
+mod terminal_failure;
+mod usable_path;
+mod validate;
+mod watchdog;
+mod zero_credit;
+mod zero_send;
 
+use self::authoritative_gate::{AuthoritativeGate, GateOutcome, GateState};
+use self::buffers::{memory_estimate, LoopBuffers, MEMORY_REPORT_INTERVAL_US};
+use self::cid_rotation::{renew_path_cids, CidRotation, ROTATING_CID_LENGTH};
+use self::clock_jump::ClockJumpDetector;
+use self::domain_rotation::{longest_domain, longest_domain_len, DomainRotation};
+use self::heartbeat::{heartbeat_message, Heartbeat, HeartbeatState};
+use self::idle::{IdleOverhead, IdleScheduler};
+use self::keep_alive::{
+    check_keep_alive_options, keep_alive_mode, KeepAliveTimer, KeepAliveTraffic,
+};
+use self::key_update::{KeyUpdate, KeyUpdateOutcome};
+use self::mtu_probe::probe_mtu;
 use self::path::{
-    apply_path_mode, drain_path_events, fetch_path_quality, find_resolver_by_addr_mut,
-    loop_burst_total, path_poll_burst_max,
+    apply_path_mode, bytes_in_transit_total, drain_path_events, fetch_path_quality,
+    find_resolver_by_addr_mut, loop_burst_total, path_poll_burst_max, sync_stream_paths,
+};
+use self::profile::{profile_message, profile_tunables};
+pub(crate) use self::qname_guard::QnameGuard;
+use self::reconnect::{reconnect_sleep, ReconnectBackoff};
+pub(crate) use self::rtt::PathRtt;
+use self::rtt::{aggregate_rtt, collect_path_rtts};
+use self::send_batch::SendBatch;
+use self::setup::{
+    bind_local_listener, bind_udp_socket, check_resolver_options, compute_mtu, listen_label, map_io,
 };
-use self::setup::{bind_tcp_listener, bind_udp_socket, compute_mtu, map_io};
+use self::stateless_reset::{ResetVerdict, StatelessResetPolicy};
+use self::summary::client_config_summary;
+use self::terminal_failure::TerminalFailure;
+use self::usable_path::{ReadyOutcome, ReadySignal, UsablePathDeadline};
+pub use self::validate::run_client_validate;
+use self::watchdog::{abort_wedged_loop, LoopWatchdog, WATCHDOG_SNAPSHOT_INTERVAL_US};
+use self::zero_credit::ZeroCreditWatch;
+use self::zero_send::{ZeroSendAction, ZeroSendTracker};
 
 // Android-specific imports for state signaling
 #[cfg(target_os = "android")]
 use crate::android::{
-    exceeded_max_failures, record_connection_failure, reset_quic_ready, should_shutdown,
-    signal_listener_ready, signal_quic_ready,
+    battery_saver_active, exceeded_max_failures, pacing_profile, record_accept_counters,
+    record_authoritative_gate, record_config_summary, record_connection_failure,
+    record_connection_info, record_idle_overhead, record_keep_alive_traffic, record_memory_usage,
+    record_poisoned_resolvers, record_rtt, record_session_counters, record_stateless_reset,
+    record_ttfb_percentiles, record_zero_credit, reset_quic_ready, should_shutdown,
+    shutdown_requested, signal_listener_ready, signal_quic_ready,
 };
 
 // No-op implementations for non-Android platforms
@@ -20,10 +75,18 @@ fn should_shutdown() -> bool {
     false
 }
 #[cfg(not(target_os = "android"))]
+async fn shutdown_requested() {
+    std::future::pending().await
+}
+#[cfg(not(target_os = "android"))]
 fn signal_listener_ready() {}
 #[cfg(not(target_os = "android"))]
 fn signal_quic_ready() {}
 #[cfg(not(target_os = "android"))]
+fn record_connection_info(_quic_version: u32, _cipher_suite: u16) {}
+#[cfg(not(target_os = "android"))]
+fn record_authoritative_gate(_state: i32) {}
+#[cfg(not(target_os = "android"))]
 fn reset_quic_ready() {}
 #[cfg(not(target_os = "android"))]
 fn record_connection_failure() {}
@@ -31,39 +94,79 @@ fn record_connection_failure() {}
 fn exceeded_max_failures() -> bool {
     false
 }
+#[cfg(not(target_os = "android"))]
+fn battery_saver_active() -> bool {
+    false
+}
+#[cfg(not(target_os = "android"))]
+fn pacing_profile() -> crate::pacing::PacingProfile {
+    crate::pacing::PacingProfile::default()
+}
+#[cfg(not(target_os = "android"))]
+fn record_memory_usage(_bytes: u64) {}
+#[cfg(not(target_os = "android"))]
+fn record_session_counters(_counters: crate::streams::SessionCounters) {}
+#[cfg(not(target_os = "android"))]
+fn record_stateless_reset() {}
+#[cfg(not(target_os = "android"))]
+fn record_accept_counters(_counters: crate::streams::acceptor::AcceptCounters, _per_sec: u64) {}
+#[cfg(not(target_os = "android"))]
+fn record_ttfb_percentiles(_percentiles: slipstream_core::latency::LatencyPercentiles) {}
+#[cfg(not(target_os = "android"))]
+fn record_config_summary(_summary: &str) {}
+#[cfg(not(target_os = "android"))]
+fn record_poisoned_resolvers(_addrs: &[std::net::SocketAddr]) {}
+#[cfg(not(target_os = "android"))]
+fn record_rtt(_aggregate_us: Option<u64>, _paths: &[PathRtt]) {}
+#[cfg(not(target_os = "android"))]
+fn record_zero_credit(_blocked: bool, _stalls: u64) {}
+#[cfg(not(target_os = "android"))]
+fn record_idle_overhead(_bytes_per_hour: u64, _bytes: u64, _idle_us: u64) {}
+#[cfg(not(target_os = "android"))]
+fn record_keep_alive_traffic(_queries: u64, _bytes: u64) {}
 use crate::dns::{
-    add_paths, expire_inflight_polls, handle_dns_response, maybe_report_debug,
-    refresh_resolver_path, resolve_resolvers, resolver_mode_to_c, send_poll_queries,
-    sockaddr_storage_to_socket_addr, DnsResponseContext,
+    add_paths, bytes_per, classify_send, expire_inflight_polls, handle_dns_response,
+    maybe_report_debug, rebase_inflight_polls, refresh_resolver_path, resolve_resolvers,
+    resolver_mode_to_c, send_poll_queries, send_query, sockaddr_storage_to_socket_addr,
+    DnsResponseContext, EcsResolvers, PoisonedResolvers, ResolverAddrCache, ResolverState,
+    SendClass,
 };
 use crate::error::ClientError;
-use crate::pacing::{cwnd_target_polls, inflight_packet_estimate};
+use crate::pacing::{cwnd_target_polls, data_biased_poll_deficit, inflight_packet_estimate};
 use crate::pinning::configure_pinned_certificate;
 use crate::streams::{
-    acceptor::ClientAcceptor, client_callback, drain_commands, drain_stream_data, handle_command,
-    ClientState, Command,
+    acceptor::ClientAcceptor, client_callback, command_channel, drain_commands, drain_stream_data,
+    expire_stuck_closing, flush_sampled_logs, handle_command, ClientState, Command,
+    CommandReceiver,
+};
+use slipstream_core::{
+    callback_state::CallbackState, clock::Clock, net::is_transient_udp_error,
+    normalize_dual_stack_addr, stream_trailer::STREAM_TRAILER_ALPN,
+};
+use slipstream_dns::{
+    encode_query_with_options, validate_service_label, QueryOptions, QueryParams, CLASS_IN, RR_TXT,
 };
-use slipstream_core::{net::is_transient_udp_error, normalize_dual_stack_addr};
-use slipstream_dns::{build_qname, encode_query, QueryParams, CLASS_IN, RR_TXT};
 use slipstream_ffi::{
     configure_quic_with_custom,
     picoquic::{
         picoquic_close, picoquic_cnx_t, picoquic_connection_id_t, picoquic_create,
-        picoquic_create_client_cnx, picoquic_current_time, picoquic_disable_keep_alive,
-        picoquic_enable_keep_alive, picoquic_enable_path_callbacks,
-        picoquic_enable_path_callbacks_default, picoquic_get_next_wake_delay,
-        picoquic_prepare_next_packet_ex, picoquic_set_callback, slipstream_has_ready_stream,
-        slipstream_is_flow_blocked, slipstream_mixed_cc_algorithm, slipstream_set_cc_override,
-        slipstream_set_default_path_mode, PICOQUIC_CONNECTION_ID_MAX_SIZE,
-        PICOQUIC_MAX_PACKET_SIZE, PICOQUIC_PACKET_LOOP_RECV_MAX, PICOQUIC_PACKET_LOOP_SEND_MAX,
+        picoquic_create_client_cnx, picoquic_disable_keep_alive, picoquic_enable_keep_alive,
+        picoquic_enable_path_callbacks, picoquic_enable_path_callbacks_default,
+        picoquic_get_cnx_state, picoquic_get_next_wake_delay, picoquic_prepare_next_packet_ex,
+        picoquic_set_callback, picoquic_set_default_connection_id_length, picoquic_set_mtu_max,
+        picoquic_start_key_rotation, slipstream_clamp_send_mtu, slipstream_get_key_updates,
+        slipstream_has_ready_stream, slipstream_is_flow_blocked, slipstream_mixed_cc_algorithm,
+        slipstream_set_cc_override, slipstream_set_default_path_mode,
+        PICOQUIC_CONNECTION_ID_MAX_SIZE, PICOQUIC_MAX_PACKET_SIZE, PICOQUIC_PACKET_LOOP_RECV_MAX,
+        PICOQUIC_PACKET_LOOP_SEND_MAX,
     },
-    socket_addr_to_storage, take_crypto_errors, ClientConfig, QuicGuard, ResolverMode,
+    set_log_dir, socket_addr_to_storage, take_crypto_errors, ClientConfig, PicoquicClock,
+    QuicGuard, ResolverMode, ZeroSendReset,
 };
 use std::ffi::CString;
-use std::net::Ipv6Addr;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{mpsc, Notify};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
 use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 
@@ -71,19 +174,35 @@ use tracing::{debug, error, info, warn};
 const SLIPSTREAM_ALPN: &str = "picoquic_sample";
 const SLIPSTREAM_SNI: &str = "test.example.com";
 const DNS_WAKE_DELAY_MAX_US: i64 = 10_000_000;
+/// Default [`ClientConfig::max_idle_sleep_ms`]: sleep as long as picoquic
+/// allows.
+pub const DEFAULT_MAX_IDLE_SLEEP_MS: u64 = DNS_WAKE_DELAY_MAX_US as u64 / 1_000;
+/// Default [`ClientConfig::closing_timeout_ms`].
+pub const DEFAULT_CLOSING_TIMEOUT_MS: u64 = 10_000;
+/// Default [`ClientConfig::zero_send_stall_iterations`]: consecutive stalled
+/// loop iterations per escalation stage.
+pub const DEFAULT_ZERO_SEND_STALL_ITERATIONS: u64 = 64;
 const DNS_POLL_SLICE_US: u64 = 50_000;
-const RECONNECT_SLEEP_MIN_MS: u64 = 250;
-const RECONNECT_SLEEP_MAX_MS: u64 = 5_000;
 const FLOW_BLOCKED_LOG_INTERVAL_US: u64 = 1_000_000;
-const IDLE_THRESHOLD_US: u64 = 2_000_000; // 2s without streams → idle
 
-fn is_ipv6_unspecified(host: &str) -> bool {
-    host.parse::<Ipv6Addr>()
-        .map(|addr| addr.is_unspecified())
-        .unwrap_or(false)
+/// Whether streams carry CRC32 trailers; they are a stream debugging aid
+/// and stay off without `debug_streams`.
+pub(crate) fn stream_trailers_enabled(config: &ClientConfig<'_>) -> bool {
+    config.debug_streams && config.debug_stream_trailers
 }
 
-fn drain_disconnected_commands(command_rx: &mut mpsc::UnboundedReceiver<Command>) -> usize {
+/// The ALPN to offer. Trailers change what every stream carries, so a
+/// client with them offers its own ALPN, which only a server with trailers
+/// on accepts.
+pub(crate) fn client_alpn(config: &ClientConfig<'_>) -> &'static str {
+    if stream_trailers_enabled(config) {
+        STREAM_TRAILER_ALPN
+    } else {
+        SLIPSTREAM_ALPN
+    }
+}
+
+fn drain_disconnected_commands(command_rx: &mut CommandReceiver) -> usize {
     let mut dropped = 0usize;
     while let Ok(command) = command_rx.try_recv() {
         dropped += 1;
@@ -94,50 +213,191 @@ fn drain_disconnected_commands(command_rx: &mut mpsc::UnboundedReceiver<Command>
     dropped
 }
 
+fn watchdog_diagnostics(
+    state: &ClientState,
+    resolvers: &[ResolverState],
+    qname_guard: &QnameGuard,
+    memory_bytes: u64,
+    now: u64,
+) -> String {
+    let metrics = state.stream_debug_metrics();
+    let backlog = state.stream_backlog_summaries(8);
+    let (enqueued_bytes, last_enqueue_at) = state.debug_snapshot();
+    let last_enqueue_ms = if last_enqueue_at == 0 {
+        0
+    } else {
+        now.saturating_sub(last_enqueue_at) / 1_000
+    };
+    let mut out = format!(
+        "streams={} streams_with_rx_queued={} queued_bytes_total={} streams_with_recv_fin={} streams_with_send_fin={} streams_discarding={} streams_with_unconsumed_rx={} enqueued_bytes={} last_enqueue_ms={} memory_bytes={} backlog={:?}",
+        state.streams_len(),
+        metrics.streams_with_rx_queued,
+        metrics.queued_bytes_total,
+        metrics.streams_with_recv_fin,
+        metrics.streams_with_send_fin,
+        metrics.streams_discarding,
+        metrics.streams_with_unconsumed_rx,
+        enqueued_bytes,
+        last_enqueue_ms,
+        memory_bytes,
+        backlog
+    );
+    let (abort_failures, abort_retried, abort_abandoned) = state.abort_counters();
+    if abort_failures > 0 {
+        out.push_str(&format!(
+            " abort_failures={} abort_retried={} abort_abandoned={}",
+            abort_failures, abort_retried, abort_abandoned
+        ));
+    }
+    if let Some(count) = state.version_negotiation_seen() {
+        out.push_str(&format!(" version_negotiations={}", count));
+    }
+    let key_updates = state.session_counters().key_updates;
+    if key_updates > 0 {
+        out.push_str(&format!(" key_updates={}", key_updates));
+    }
+    if qname_guard.dropped() > 0 {
+        out.push_str(&format!(
+            " oversize_packets_dropped={}",
+            qname_guard.dropped()
+        ));
+    }
+    let accepts = state.accept_counters();
+    out.push_str(&format!(
+        " accepts={} accepts_blocked_on_credit={} accepts_vetoed={} accept_errors={} accepts_blocked_on_queue={}",
+        accepts.accepted,
+        accepts.blocked_on_credit,
+        accepts.vetoed,
+        accepts.errors,
+        accepts.blocked_on_queue
+    ));
+    let (local_streams, local_streams_peak) = state.local_streams();
+    out.push_str(&format!(
+        " local_streams={} local_streams_peak={} accepts_blocked_on_stream_cap={}",
+        local_streams, local_streams_peak, accepts.blocked_on_stream_cap
+    ));
+    let stuck_closing = state.stuck_closing_streams();
+    if stuck_closing > 0 {
+        out.push_str(&format!(" stuck_closing={}", stuck_closing));
+    }
+    let floor_overruns = state.max_streams_floor_overruns();
+    if floor_overruns > 0 {
+        out.push_str(&format!(" max_streams_floor_overruns={}", floor_overruns));
+    }
+    let pool = state.stream_pool_counters();
+    if pool.opened > 0 {
+        out.push_str(&format!(
+            " pool_opened={} pool_hits={} pool_misses={} pool_reset_before_use={}",
+            pool.opened, pool.hits, pool.misses, pool.reset_before_use
+        ));
+    }
+    for resolver in resolvers {
+        out.push_str(&format!(
+            " | {} source={} added={} poisoned={} ecs_observed={} rejected_responses={} dns_responses={} send_pkts={} send_bytes={} handshake_bytes={} poll_bytes={} keep_alive_queries={} keep_alive_bytes={} data_bytes={} send_retries={} send_drops={} polls_sent={} recv_bytes={} bytes_per_query={} bytes_per_response={} zero_send={} zero_send_streams={} pending_polls={} inflight_polls={}",
+            resolver.label(),
+            resolver.addr_source,
+            resolver.added,
+            resolver.poisoned,
+            resolver.ecs_observed,
+            resolver.ingest.rejected_total,
+            resolver.debug.dns_responses,
+            resolver.debug.send_packets,
+            resolver.debug.send_bytes,
+            resolver.debug.sends.handshake.bytes,
+            resolver.debug.sends.poll.bytes,
+            resolver.debug.sends.keep_alive.packets,
+            resolver.debug.sends.keep_alive.bytes,
+            resolver.debug.sends.data.bytes,
+            resolver.debug.send_retries,
+            resolver.debug.send_drops,
+            resolver.debug.polls_sent,
+            resolver.debug.response_bytes,
+            bytes_per(resolver.debug.send_bytes, resolver.debug.send_packets),
+            bytes_per(resolver.debug.response_bytes, resolver.debug.dns_responses),
+            resolver.debug.zero_send_loops,
+            resolver.debug.zero_send_with_streams,
+            resolver.pending_polls,
+            resolver.inflight_poll_ids.len()
+        ));
+    }
+    out
+}
+
 pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
-    let domain_len = config.domain.len();
-    let mtu = compute_mtu(domain_len)?;
+    // Size packets for the longest domain so rotation never overflows a query.
+    let domain_len = longest_domain_len(config.domain, config.rotation_domains);
+    if let Some(label) = config.service_label {
+        validate_service_label(label)
+            .map_err(|err| ClientError::new(format!("Invalid service label: {}", err)))?;
+    }
+    let mtu = compute_mtu(domain_len, config.service_label)?;
+    if !config.rotation_domains.is_empty()
+        && config.domain_rotation_interval_ms == 0
+        && config.domain_rotation_bytes == 0
+    {
+        warn!(
+            "Rotation domains configured without an interval or byte limit; keeping {}",
+            config.domain
+        );
+    }
+    check_resolver_options(config)?;
+    check_keep_alive_options(config)?;
+    let mut reconnect_backoff =
+        ReconnectBackoff::new(config.reconnect_delay_min_ms, config.reconnect_delay_max_ms)
+            .map_err(ClientError::new)?;
     let udp = bind_udp_socket().await?;
 
-    let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+    let (command_tx, mut command_rx) = command_channel(config.command_channel_capacity);
     let data_notify = Arc::new(Notify::new());
-    let acceptor = ClientAcceptor::new();
+    let acceptor = ClientAcceptor::with_max_local_streams(config.max_local_streams);
     let debug_streams = config.debug_streams;
-    let tcp_host = config.tcp_listen_host;
     let tcp_port = config.tcp_listen_port;
-    let mut bound_host = tcp_host.to_string();
-    let listener = match bind_tcp_listener(tcp_host, tcp_port).await {
-        Ok(listener) => listener,
-        Err(err) => {
-            if is_ipv6_unspecified(tcp_host) {
-                warn!(
-                    "Failed to bind TCP listener on {}:{} ({}); falling back to 0.0.0.0",
-                    tcp_host, tcp_port, err
-                );
-                match bind_tcp_listener("0.0.0.0", tcp_port).await {
-                    Ok(listener) => {
-                        bound_host = "0.0.0.0".to_string();
-                        listener
-                    }
-                    Err(fallback_err) => {
-                        return Err(ClientError::new(format!(
-                            "Failed to bind TCP listener on {}:{} ({}) or 0.0.0.0:{} ({})",
-                            tcp_host, tcp_port, err, tcp_port, fallback_err
-                        )));
-                    }
-                }
-            } else {
-                return Err(err);
-            }
-        }
-    };
+    let (listener, bound_host) =
+        bind_local_listener(config.tcp_listen_host, tcp_port, config.loopback_only).await?;
     acceptor.spawn(listener, command_tx.clone());
     info!("Listening on TCP port {} (host {})", tcp_port, bound_host);
+    let listen = listen_label(&bound_host, tcp_port);
+    let mut resolver_addr_cache = ResolverAddrCache::default();
+    let mut poisoned_resolvers = PoisonedResolvers::default();
+    let mut ecs_resolvers = EcsResolvers::default();
+    let query_options = QueryOptions {
+        ecs_opt_out: config.ecs_opt_out,
+    };
+    let mut mtu = if config.mtu_probe {
+        let mut probe_resolvers = Vec::new();
+        resolve_resolvers(
+            config.resolvers,
+            &config.host_overrides,
+            &mut resolver_addr_cache,
+            mtu,
+            false,
+            &mut probe_resolvers,
+        )?;
+        let addrs: Vec<_> = probe_resolvers
+            .iter()
+            .map(|resolver| resolver.addr)
+            .collect();
+        probe_mtu(
+            &addrs,
+            longest_domain(config.domain, config.rotation_domains),
+            config.service_label,
+            &query_options,
+            mtu,
+        )
+        .await?
+    } else {
+        mtu
+    };
+    let summary = client_config_summary(config, mtu, &listen).to_string();
+    info!("{}", summary);
+    record_config_summary(&summary);
+    let tunables = profile_tunables(config.profile, config.send_batch_size);
+    info!("{}", profile_message(config.profile, &tunables));
 
     // Signal to Android that the TCP listener is ready
     signal_listener_ready();
 
-    let alpn = CString::new(SLIPSTREAM_ALPN)
+    let alpn = CString::new(client_alpn(config))
         .map_err(|_| ClientError::new("ALPN contains an unexpected null byte"))?;
     let sni = CString::new(SLIPSTREAM_SNI)
         .map_err(|_| ClientError::new("SNI contains an unexpected null byte"))?;
@@ -148,16 +408,50 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         None => None,
     };
 
-    let mut state = Box::new(ClientState::new(
+    let clock = PicoquicClock;
+    // Declared before the QUIC context so picoquic is freed first.
+    let mut state = CallbackState::new(ClientState::new(
         command_tx,
         data_notify.clone(),
         debug_streams,
+        config.stream_path_affinity,
         acceptor,
     ));
-    let state_ptr: *mut ClientState = &mut *state;
-    let _state = state;
+    state
+        .get_mut()
+        .set_stream_checksums(config.debug_stream_checksums);
+    state
+        .get_mut()
+        .set_stream_trailers(stream_trailers_enabled(config));
+    state
+        .get_mut()
+        .set_stop_sending_half_close(config.stop_sending_half_close);
+    state
+        .get_mut()
+        .set_stream_pool_size(config.stream_pool_size);
+    state
+        .get_mut()
+        .set_closing_timeout(Duration::from_millis(config.closing_timeout_ms));
+    state
+        .get_mut()
+        .set_max_streams_floor(config.max_streams_floor);
+    let watchdog = LoopWatchdog::spawn(
+        config.watchdog_timeout_ms,
+        data_notify.clone(),
+        abort_wedged_loop,
+    );
 
-    let mut reconnect_delay = Duration::from_millis(RECONNECT_SLEEP_MIN_MS);
+    let min_reconnect_interval = Duration::from_millis(config.min_reconnect_interval_ms);
+    let mut throttled_reconnects = 0u64;
+    let mut stateless_resets = StatelessResetPolicy::new(config.stateless_reset_tolerance);
+    let mut terminal_failure = TerminalFailure::new(config.terminal_failure_repeats);
+    let mut clock_jump = ClockJumpDetector::new();
+    let mut idle_overhead = IdleOverhead::default();
+    let mut keep_alive_traffic = KeepAliveTraffic::default();
+    let mut zero_credit = ZeroCreditWatch::default();
+    let mut buffers = LoopBuffers::new(PICOQUIC_MAX_PACKET_SIZE);
+    let mut qname_guard = QnameGuard::new(mtu);
+    let mut memory_bytes = 0u64;
 
     loop {
         // Check for shutdown before QUIC setup (picoquic_create etc. can be slow)
@@ -166,14 +460,33 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             return Ok(0);
         }
 
-        let mut resolvers = resolve_resolvers(config.resolvers, mtu, config.debug_poll)?;
+        let last_reconnect_at = Instant::now();
+        buffers.reset();
+        state.get_mut().set_path_mtu(mtu);
+        resolve_resolvers(
+            config.resolvers,
+            &config.host_overrides,
+            &mut resolver_addr_cache,
+            mtu,
+            config.debug_poll,
+            &mut buffers.resolvers,
+        )?;
+        let LoopBuffers {
+            recv_buf,
+            send_buf,
+            resolvers,
+        } = &mut buffers;
         if resolvers.is_empty() {
             return Err(ClientError::new("At least one resolver is required"));
         }
+        if !poisoned_resolvers.demote(resolvers) {
+            warn!("Every resolver is marked poisoned; connecting through them anyway");
+        }
+        ecs_resolvers.mark(resolvers);
 
         let mut local_addr_storage = socket_addr_to_storage(udp.local_addr().map_err(map_io)?);
 
-        let current_time = unsafe { picoquic_current_time() };
+        let current_time = clock.now_us();
         let quic = unsafe {
             picoquic_create(
                 8,
@@ -182,7 +495,7 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 std::ptr::null(),
                 alpn.as_ptr(),
                 Some(client_callback),
-                state_ptr as *mut _,
+                state.callback_ctx(),
                 None,
                 std::ptr::null_mut(),
                 std::ptr::null(),
@@ -210,7 +523,24 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         }
         unsafe {
             configure_quic_with_custom(quic, mixed_cc, mtu);
-            picoquic_enable_path_callbacks_default(quic, 1);
+            if let Some(dir) = config.qlog_dir {
+                set_log_dir(quic, dir).map_err(ClientError::new)?;
+                warn!(
+                    "QUIC connection logs go to {}; they grow with traffic and hold connection details",
+                    dir
+                );
+            }
+            if config.path_callbacks {
+                picoquic_enable_path_callbacks_default(quic, 1);
+            }
+            if config.cid_rotation_interval_ms > 0
+                && picoquic_set_default_connection_id_length(quic, ROTATING_CID_LENGTH) != 0
+            {
+                warn!(
+                    "Could not shorten local connection IDs to {} bytes",
+                    ROTATING_CID_LENGTH
+                );
+            }
             let override_ptr = cc_override
                 .as_ref()
                 .map(|value| value.as_ptr())
@@ -234,7 +564,7 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 sni.as_ptr(),
                 alpn.as_ptr(),
                 Some(client_callback),
-                state_ptr as *mut _,
+                state.callback_ctx(),
             )
         };
         if cnx.is_null() {
@@ -242,11 +572,19 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         }
 
         apply_path_mode(cnx, &mut resolvers[0])?;
+        sync_stream_paths(resolvers, state.get_mut());
+        // Responses to queries sent by the previous connection keep arriving on the
+        // shared UDP socket; don't let them seed demand polls on the fresh resolvers.
+        let poll_grace_until =
+            current_time.saturating_add(config.reconnect_poll_grace_ms.saturating_mul(1_000));
 
         unsafe {
-            picoquic_set_callback(cnx, Some(client_callback), state_ptr as *mut _);
-            picoquic_enable_path_callbacks(cnx, 1);
-            if config.keep_alive_interval > 0 {
+            picoquic_set_callback(cnx, Some(client_callback), state.callback_ctx());
+            if config.path_callbacks {
+                picoquic_enable_path_callbacks(cnx, 1);
+            }
+            // A keep-alive path replaces picoquic's PINGs with polls.
+            if config.keep_alive_interval > 0 && keep_alive_mode(config.keep_alive_path).is_none() {
                 picoquic_enable_keep_alive(cnx, config.keep_alive_interval as u64 * 1000);
             } else {
                 picoquic_disable_keep_alive(cnx);
@@ -258,72 +596,288 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         }
 
         let mut dns_id = 1u16;
-        let mut recv_buf = vec![0u8; 4096];
-        let mut send_buf = vec![0u8; PICOQUIC_MAX_PACKET_SIZE];
-        let packet_loop_send_max = loop_burst_total(&resolvers, PICOQUIC_PACKET_LOOP_SEND_MAX);
-        let packet_loop_recv_max = loop_burst_total(&resolvers, PICOQUIC_PACKET_LOOP_RECV_MAX);
+        let mut keep_alive = KeepAliveTimer::new(
+            config.keep_alive_path,
+            config.keep_alive_interval,
+            current_time,
+        );
+        let mut pacing_profile_in_use = pacing_profile();
         let mut zero_send_loops = 0u64;
         let mut zero_send_with_streams = 0u64;
+        let mut zero_send_tracker = ZeroSendTracker::new(config.zero_send_stall_iterations);
+        let mut send_batch = SendBatch::new(tunables.send_batch_size);
         let mut last_flow_block_log_at = 0u64;
-        let mut quic_ready_signaled = false;
+        let mut ready_signal = ReadySignal::new(config.ready_requires_usable_path);
+        let mut usable_path_deadline = UsablePathDeadline::new(config.usable_path_timeout_ms);
+        let mut usable_path_failed = false;
+        let mut authoritative_gate = AuthoritativeGate::new(
+            config.require_authoritative_path,
+            config.authoritative_path_timeout_ms,
+            config.authoritative_path_timeout_action,
+        );
+        let mut authoritative_path_failed = false;
+        state
+            .get_mut()
+            .set_acceptor_paused(authoritative_gate.is_waiting());
+        record_authoritative_gate(authoritative_gate.state().code());
+        let mut cid_rotation = CidRotation::new(config.cid_rotation_interval_ms);
+        let mut domain_rotation = DomainRotation::new(
+            config.domain,
+            config.rotation_domains,
+            config.domain_rotation_interval_ms,
+            config.domain_rotation_bytes,
+        );
+        let mut key_update = KeyUpdate::new(config.key_update_interval_ms, config.key_update_bytes);
         let idle_poll_interval_us = config.idle_poll_interval_ms.saturating_mul(1000);
-        let mut last_active_at: u64 = 0;
-        let mut last_idle_poll_at: u64 = 0;
+        let battery_saver_interval_us = config
+            .battery_saver_poll_interval_ms
+            .map(|interval_ms| interval_ms.saturating_mul(1000));
+        let mut idle = IdleScheduler::new(idle_poll_interval_us);
+        let mut last_watchdog_snapshot_at: u64 = 0;
+        let mut last_memory_report_at: u64 = 0;
+        let mut heartbeat = Heartbeat::new(config.heartbeat_interval_ms);
+        // Accept totals at the last report, for the accept rate.
+        let mut last_accept_sample = (clock.now_us(), state.get().accept_counters());
 
         loop {
             // Check for shutdown signal from Android
             if should_shutdown() {
                 info!("Shutdown signal received, exiting");
+                record_session_counters(state.get().session_counters());
                 return Ok(0);
             }
 
-            let current_time = unsafe { picoquic_current_time() };
-            drain_commands(cnx, state_ptr, &mut command_rx);
-            drain_stream_data(cnx, state_ptr);
-            let closing = unsafe { (*state_ptr).is_closing() };
+            let moved_packets = resolvers
+                .iter()
+                .map(|resolver| resolver.debug.send_packets + resolver.debug.dns_responses)
+                .sum();
+            watchdog.beat(moved_packets, state.get().streams_len());
+            if watchdog.take_force_close() {
+                warn!("Watchdog detected a stalled client loop; closing connection");
+                break;
+            }
+
+            let profile = pacing_profile();
+            if profile != pacing_profile_in_use {
+                info!("Pacing profile switched to {:?}", profile);
+                pacing_profile_in_use = profile;
+            }
+            let pacing_params = profile.params();
+            let packet_loop_send_max =
+                loop_burst_total(resolvers, PICOQUIC_PACKET_LOOP_SEND_MAX, pacing_params);
+            let packet_loop_recv_max =
+                loop_burst_total(resolvers, PICOQUIC_PACKET_LOOP_RECV_MAX, pacing_params);
+
+            let current_time = clock.now_us();
+            drain_commands(cnx, state.get_mut(), &mut command_rx);
+            drain_stream_data(cnx, state.get_mut());
+            state.get_mut().retry_deferred_aborts();
+            expire_stuck_closing(cnx, state.get_mut());
+            flush_sampled_logs();
+            let closing = state.get().is_closing();
             if closing {
                 break;
             }
 
-            let ready = unsafe { (*state_ptr).is_ready() };
+            let ready = state.get().is_ready();
+            // No RTT before ready; the handshake's samples are not the
+            // tunnel's.
+            let path_rtts = if ready {
+                collect_path_rtts(cnx, resolvers)
+            } else {
+                Vec::new()
+            };
+            record_rtt(aggregate_rtt(&path_rtts), &path_rtts);
+            let starved_accepts = state.get().credit_starved_accepts();
+            if zero_credit.observe(current_time, starved_accepts) {
+                error!(
+                    "Connection is ready but the server grants no streams; {} local connects are waiting (zero_credit_stalls={}). Check that the server allows bidirectional streams{}",
+                    starved_accepts,
+                    zero_credit.stalls(),
+                    if config.reconnect_on_zero_credit {
+                        "; reconnecting"
+                    } else {
+                        ""
+                    }
+                );
+                record_zero_credit(true, zero_credit.stalls());
+                if config.reconnect_on_zero_credit {
+                    break;
+                }
+            } else if !zero_credit.is_blocked() {
+                record_zero_credit(false, zero_credit.stalls());
+            }
             if ready {
                 // Signal QUIC ready to Android (only once per connection)
-                if !quic_ready_signaled {
-                    signal_quic_ready();
-                    quic_ready_signaled = true;
+                let any_usable = resolvers.iter().any(ResolverState::is_usable);
+                match ready_signal.observe(ready, any_usable) {
+                    ReadyOutcome::Signal => {
+                        let (quic_version, cipher_suite) = state.get().connection_info();
+                        record_connection_info(quic_version, cipher_suite);
+                        signal_quic_ready();
+                        if authoritative_gate.is_waiting() {
+                            info!(
+                                "Waiting for an authoritative resolver path before accepting streams (timeout={}ms)",
+                                config.authoritative_path_timeout_ms
+                            );
+                        }
+                    }
+                    ReadyOutcome::Deferred => {
+                        info!("Handshake complete; waiting for a usable resolver path before signaling ready");
+                    }
+                    ReadyOutcome::Unchanged => {}
                 }
 
-                unsafe {
-                    (*state_ptr).update_acceptor_limit(cnx);
-                }
-                if reconnect_delay != Duration::from_millis(RECONNECT_SLEEP_MIN_MS) {
-                    reconnect_delay = Duration::from_millis(RECONNECT_SLEEP_MIN_MS);
+                state.get_mut().update_acceptor_limit(cnx);
+                reconnect_backoff.reset();
+                if config.path_callbacks {
+                    add_paths(cnx, resolvers, &clock)?;
                 }
-                add_paths(cnx, &mut resolvers)?;
                 for resolver in resolvers.iter_mut() {
                     if resolver.added {
                         apply_path_mode(cnx, resolver)?;
                     }
                 }
+                if cid_rotation.due(current_time) {
+                    let (renewed, failed) = renew_path_cids(cnx, resolvers);
+                    cid_rotation.record(current_time, renewed, failed);
+                    if renewed > 0 {
+                        info!(
+                            "Rotated connection ID on {} paths (cid_rotations={} cid_rotation_failures={})",
+                            renewed,
+                            cid_rotation.rotations(),
+                            cid_rotation.failures()
+                        );
+                    } else {
+                        debug!(
+                            "Connection ID rotation skipped; no spare CIDs on {} paths (cid_rotation_failures={})",
+                            failed,
+                            cid_rotation.failures()
+                        );
+                    }
+                }
+                let sent_bytes = resolvers
+                    .iter()
+                    .map(|resolver| resolver.debug.send_bytes)
+                    .sum();
+                if let Some(reason) = key_update.due(current_time, sent_bytes) {
+                    let ret = unsafe { picoquic_start_key_rotation(cnx) };
+                    match key_update.record(current_time, sent_bytes, ret) {
+                        KeyUpdateOutcome::Started => info!(
+                            "Requested QUIC key update (reason={} key_updates_requested={})",
+                            reason,
+                            key_update.requested()
+                        ),
+                        KeyUpdateOutcome::Deferred => debug!(
+                            "QUIC key update deferred; previous update not yet acknowledged (key_update_deferrals={})",
+                            key_update.deferred()
+                        ),
+                        KeyUpdateOutcome::Failed(err) => {
+                            warn!("QUIC key update request failed (ret={})", err)
+                        }
+                    }
+                }
+                let completed = key_update.observe(unsafe { slipstream_get_key_updates(cnx) });
+                if completed > 0 {
+                    state.get_mut().record_key_updates(completed);
+                    info!(
+                        "QUIC key update completed (key_updates={})",
+                        state.get().session_counters().key_updates
+                    );
+                }
+                if let Some((previous, reason)) =
+                    domain_rotation.maybe_rotate(current_time, sent_bytes)
+                {
+                    info!(
+                        "Rotated tunnel domain {} -> {} (reason={} domain_rotations={})",
+                        previous,
+                        domain_rotation.current(),
+                        reason,
+                        domain_rotation.rotations()
+                    );
+                }
+            } else {
+                state.get_mut().apply_max_streams_floor();
+            }
+            if config.path_callbacks {
+                drain_path_events(cnx, resolvers, state.get_mut());
+            }
+            let any_usable = resolvers.iter().any(ResolverState::is_usable);
+            if usable_path_deadline.observe(current_time, ready, any_usable) {
+                let unusable: Vec<String> = resolvers
+                    .iter()
+                    .map(|resolver| {
+                        let reason = if resolver.added {
+                            "no responses"
+                        } else {
+                            "no path"
+                        };
+                        format!("{} ({})", resolver.addr, reason)
+                    })
+                    .collect();
+                warn!(
+                    "No usable resolver path within {}ms; reconnecting. Unusable resolvers: {}",
+                    config.usable_path_timeout_ms,
+                    unusable.join(", ")
+                );
+                usable_path_failed = true;
+                break;
+            }
+            let authoritative_path = resolvers
+                .iter()
+                .find(|resolver| resolver.mode == ResolverMode::Authoritative && resolver.added);
+            match authoritative_gate.observe(current_time, ready, authoritative_path.is_some()) {
+                GateOutcome::Unchanged => {}
+                GateOutcome::Opened(gate_state) => {
+                    if gate_state == GateState::Validated {
+                        info!(
+                            "Authoritative path {} validated; accepting streams",
+                            authoritative_path
+                                .map(|resolver| resolver.addr.to_string())
+                                .unwrap_or_default()
+                        );
+                    } else {
+                        warn!(
+                            "No authoritative resolver path validated within {}ms; falling back to the available paths",
+                            config.authoritative_path_timeout_ms
+                        );
+                    }
+                    record_authoritative_gate(gate_state.code());
+                    let client = state.get_mut();
+                    client.set_acceptor_paused(false);
+                    client.update_acceptor_limit(cnx);
+                }
+                GateOutcome::Failed => {
+                    warn!(
+                        "No authoritative resolver path validated within {}ms; reconnecting",
+                        config.authoritative_path_timeout_ms
+                    );
+                    authoritative_path_failed = true;
+                    break;
+                }
             }
-            drain_path_events(cnx, &mut resolvers, state_ptr);
 
             for resolver in resolvers.iter_mut() {
                 if resolver.mode == ResolverMode::Authoritative {
                     expire_inflight_polls(&mut resolver.inflight_poll_ids, current_time);
                 }
+                expire_inflight_polls(&mut resolver.recursive_poll_ids, current_time);
             }
 
-            let delay_us =
-                unsafe { picoquic_get_next_wake_delay(quic, current_time, DNS_WAKE_DELAY_MAX_US) };
+            let delay_us = unsafe {
+                picoquic_get_next_wake_delay(quic, current_time, tunables.wake_delay_max_us)
+            };
             let delay_us = if delay_us < 0 { 0 } else { delay_us as u64 };
-            let streams_len_for_sleep = unsafe { (*state_ptr).streams_len() };
-            let current_time_for_idle = unsafe { picoquic_current_time() };
-            if streams_len_for_sleep > 0 {
-                last_active_at = current_time_for_idle;
-            }
-            let is_idle = idle_poll_interval_us > 0
-                && current_time_for_idle.saturating_sub(last_active_at) >= IDLE_THRESHOLD_US;
+            let streams_len_for_sleep = state.get().streams_len();
+            let battery_saver = battery_saver_interval_us.filter(|_| battery_saver_active());
+            let (is_idle, _) = idle.update(&clock, streams_len_for_sleep, battery_saver);
+            idle_overhead.observe(
+                clock.now_us(),
+                is_idle,
+                resolvers.iter().fold(0u64, |total, resolver| {
+                    total.saturating_add(resolver.debug.sends.overhead_bytes())
+                }),
+            );
 
             let mut has_work = streams_len_for_sleep > 0;
             for resolver in resolvers.iter_mut() {
@@ -333,10 +887,14 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 let pending_for_sleep = match resolver.mode {
                     ResolverMode::Authoritative => {
                         let quality = fetch_path_quality(cnx, resolver);
-                        let snapshot = resolver
-                            .pacing_budget
-                            .as_mut()
-                            .map(|budget| budget.target_inflight(&quality, delay_us.max(1)));
+                        let snapshot = resolver.pacing_budget.as_mut().map(|budget| {
+                            budget.target_inflight(
+                                &quality,
+                                delay_us.max(1),
+                                pacing_params,
+                                config.response_size_feedback_percent,
+                            )
+                        });
                         resolver.last_pacing_snapshot = snapshot;
                         let target = snapshot
                             .map(|snapshot| snapshot.target_inflight)
@@ -346,16 +904,19 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                         let pacing = target.saturating_sub(inflight_packets);
                         // Include demand-driven pending_polls so we wake up to
                         // send response-triggered polls even when pacing is zero.
-                        pacing.max(resolver.pending_polls)
+                        pacing
+                            .max(resolver.pending_polls)
+                            .max(tunables.demand_poll_floor)
                     }
                     ResolverMode::Recursive => resolver.pending_polls,
                 };
+                // A resolver at its inflight cap has nothing to send until
+                // an answer or an expiry frees a slot.
+                let pending_for_sleep = pending_for_sleep.min(resolver.poll_slots());
                 if pending_for_sleep > 0 {
                     if is_idle && resolver.mode == ResolverMode::Authoritative {
                         // When idle, only wake for the next idle poll interval
-                        if current_time_for_idle.saturating_sub(last_idle_poll_at)
-                            >= idle_poll_interval_us
-                        {
+                        if idle.idle_poll_due(&clock) {
                             has_work = true;
                         }
                     } else {
@@ -372,35 +933,44 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 }
             }
             // Avoid a tight poll loop when idle, but keep the short slice during active transfers.
-            // Cap at 2 seconds so shutdown checks (should_shutdown()) happen within the
-            // native stop timeout (3s). Without this cap, idle QUIC delays up to 10s
-            // can cause the JNI stop to abandon the thread while it still holds the port.
-            const MAX_SLEEP_US: u64 = 2_000_000;
+            // Idle sleeps stop at --max-idle-sleep-ms; a shutdown request ends them early.
+            let max_sleep_us = config.max_idle_sleep_ms.max(1).saturating_mul(1_000);
+            let delay_us =
+                keep_alive.cap_wake_delay(clock.now_us(), state.get().is_ready(), delay_us);
             let timeout_us = if has_work {
-                delay_us.clamp(1, DNS_POLL_SLICE_US)
+                delay_us.clamp(1, tunables.poll_slice_us)
+            } else if let (true, Some(interval_us)) = (is_idle, battery_saver) {
+                // Battery saver: sleep through picoquic wake-ups while no streams
+                // are open; a new stream command still interrupts the sleep.
+                delay_us.max(interval_us).min(max_sleep_us)
             } else {
-                delay_us.max(1).min(MAX_SLEEP_US)
+                delay_us.max(1).min(max_sleep_us)
             };
             let timeout = Duration::from_micros(timeout_us);
+            clock_jump.arm(&clock, timeout_us);
 
             tokio::select! {
                 command = command_rx.recv() => {
                     if let Some(command) = command {
-                        handle_command(cnx, state_ptr, command);
+                        handle_command(cnx, state.get_mut(), command);
                     }
                 }
                 _ = data_notify.notified() => {}
-                recv = udp.recv_from(&mut recv_buf) => {
+                recv = udp.recv_from(recv_buf) => {
                     match recv {
                         Ok((size, peer)) => {
                             let mut response_ctx = DnsResponseContext {
                                 quic,
+                                cnx,
                                 local_addr_storage: &local_addr_storage,
-                                resolvers: &mut resolvers,
+                                resolvers,
+                                state: &mut state,
+                                clock: &clock,
+                                poll_grace_until,
                             };
                             handle_dns_response(&recv_buf[..size], peer, &mut response_ctx)?;
                             for _ in 1..packet_loop_recv_max {
-                                match udp.try_recv_from(&mut recv_buf) {
+                                match udp.try_recv_from(recv_buf) {
                                     Ok((size, peer)) => {
                                         handle_dns_response(&recv_buf[..size], peer, &mut response_ctx)?;
                                     }
@@ -422,15 +992,49 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                         }
                     }
                 }
+                _ = shutdown_requested() => {}
                 _ = sleep(timeout) => {}
             }
 
-            drain_commands(cnx, state_ptr, &mut command_rx);
-            drain_stream_data(cnx, state_ptr);
-            drain_path_events(cnx, &mut resolvers, state_ptr);
+            let newly_poisoned = poisoned_resolvers.collect(resolvers);
+            if !newly_poisoned.is_empty() {
+                record_poisoned_resolvers(&poisoned_resolvers.addrs());
+                if resolvers.iter().any(|resolver| !resolver.poisoned) {
+                    warn!(
+                        "Reconnecting without poisoned resolvers: {:?}",
+                        newly_poisoned
+                    );
+                    break;
+                }
+                warn!("Every resolver is poisoned; keeping the current connection");
+            }
+
+            if let Some(jump_us) = clock_jump.check(&clock) {
+                info!(
+                    "Clock jump of {:.1}s detected; rebasing poll expiry, pacing and idle timers (clock_jumps={})",
+                    jump_us as f64 / 1_000_000.0,
+                    clock_jump.jumps()
+                );
+                for resolver in resolvers.iter_mut() {
+                    rebase_inflight_polls(&mut resolver.inflight_poll_ids, jump_us);
+                    rebase_inflight_polls(&mut resolver.recursive_poll_ids, jump_us);
+                    if let Some(budget) = resolver.pacing_budget.as_mut() {
+                        budget.reset_baseline();
+                    }
+                }
+                idle.skip_clock_jump(jump_us);
+                idle_overhead.skip_clock_jump(jump_us);
+            }
+
+            drain_commands(cnx, state.get_mut(), &mut command_rx);
+            drain_stream_data(cnx, state.get_mut());
+            if config.path_callbacks {
+                drain_path_events(cnx, resolvers, state.get_mut());
+            }
 
+            let mut sent_packets = 0usize;
             for _ in 0..packet_loop_send_max {
-                let current_time = unsafe { picoquic_current_time() };
+                let current_time = clock.now_us();
                 let mut send_length: libc::size_t = 0;
                 let mut addr_to: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
                 let mut addr_from: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
@@ -461,7 +1065,7 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 }
                 if send_length == 0 {
                     zero_send_loops = zero_send_loops.saturating_add(1);
-                    let streams_len = unsafe { (*state_ptr).streams_len() };
+                    let streams_len = state.get().streams_len();
                     if streams_len > 0 {
                         zero_send_with_streams = zero_send_with_streams.saturating_add(1);
                         let flow_blocked = unsafe { slipstream_is_flow_blocked(cnx) } != 0;
@@ -481,16 +1085,23 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 }
                 if let Ok(dest) = sockaddr_storage_to_socket_addr(&addr_to) {
                     let dest = normalize_dual_stack_addr(dest);
-                    if let Some(resolver) = find_resolver_by_addr_mut(&mut resolvers, dest) {
+                    if let Some(resolver) = find_resolver_by_addr_mut(resolvers, dest) {
                         resolver.local_addr_storage = Some(unsafe { std::ptr::read(&addr_from) });
-                        resolver.debug.send_packets = resolver.debug.send_packets.saturating_add(1);
-                        resolver.debug.send_bytes =
-                            resolver.debug.send_bytes.saturating_add(send_length as u64);
+                        let client = state.get();
+                        let class =
+                            classify_send(false, client.is_ready(), client.streams_len() > 0);
+                        resolver.debug.record_send(class, send_length);
                     }
                 }
 
-                let qname = build_qname(&send_buf[..send_length], config.domain)
-                    .map_err(|err| ClientError::new(err.to_string()))?;
+                let Some(qname) = qname_guard.qname(
+                    &send_buf[..send_length],
+                    config.service_label,
+                    domain_rotation.current(),
+                )?
+                else {
+                    continue;
+                };
                 let params = QueryParams {
                     id: dns_id,
                     qname: &qname,
@@ -502,29 +1113,35 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     is_query: true,
                 };
                 dns_id = dns_id.wrapping_add(1);
-                let packet =
-                    encode_query(&params).map_err(|err| ClientError::new(err.to_string()))?;
+                let packet = encode_query_with_options(&params, &query_options)
+                    .map_err(|err| ClientError::new(err.to_string()))?;
 
                 let dest = sockaddr_storage_to_socket_addr(&addr_to)?;
                 let dest = normalize_dual_stack_addr(dest);
                 local_addr_storage = addr_from;
-                if let Err(err) = udp.send_to(&packet, dest).await {
-                    if !is_transient_udp_error(&err) {
-                        return Err(map_io(err));
+                if send_batch.enabled() {
+                    if send_batch.push(packet, dest) {
+                        send_batch.flush(&udp, resolvers).await.map_err(map_io)?;
+                    }
+                } else {
+                    let outcome = send_query(&udp, &packet, dest).await.map_err(map_io)?;
+                    if let Some(resolver) = find_resolver_by_addr_mut(resolvers, dest) {
+                        resolver.debug.record_send_outcome(outcome);
                     }
                 }
+                sent_packets += 1;
             }
+            send_batch.flush(&udp, resolvers).await.map_err(map_io)?;
 
             let has_ready_stream = unsafe { slipstream_has_ready_stream(cnx) != 0 };
             let flow_blocked = unsafe { slipstream_is_flow_blocked(cnx) != 0 };
-            let streams_len = unsafe { (*state_ptr).streams_len() };
+            let streams_len = state.get().streams_len();
             if streams_len > 0 && has_ready_stream && flow_blocked {
-                let now = unsafe { picoquic_current_time() };
+                let now = clock.now_us();
                 if now.saturating_sub(last_flow_block_log_at) >= FLOW_BLOCKED_LOG_INTERVAL_US {
-                    let metrics = unsafe { (*state_ptr).stream_debug_metrics() };
-                    let backlog = unsafe { (*state_ptr).stream_backlog_summaries(8) };
-                    let (enqueued_bytes, last_enqueue_at) =
-                        unsafe { (*state_ptr).debug_snapshot() };
+                    let metrics = state.get().stream_debug_metrics();
+                    let backlog = state.get().stream_backlog_summaries(8);
+                    let (enqueued_bytes, last_enqueue_at) = state.get().debug_snapshot();
                     let last_enqueue_ms = if last_enqueue_at == 0 {
                         0
                     } else {
@@ -550,6 +1167,55 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     last_flow_block_log_at = now;
                 }
             }
+            let stalled = sent_packets == 0
+                && streams_len > 0
+                && has_ready_stream
+                && !flow_blocked
+                && bytes_in_transit_total(cnx, resolvers) == 0;
+            match zero_send_tracker.observe(stalled) {
+                Some(ZeroSendAction::MarkActive) => {
+                    let marked = state.get().mark_streams_active(cnx);
+                    warn!(
+                        "zero-send stall: consecutive={} streams={} marked_active={}",
+                        zero_send_tracker.consecutive(),
+                        streams_len,
+                        marked
+                    );
+                }
+                Some(ZeroSendAction::Diagnose) => {
+                    let cnx_state = unsafe { picoquic_get_cnx_state(cnx) };
+                    let metrics = state.get().stream_debug_metrics();
+                    let backlog = state.get().stream_backlog_summaries(8);
+                    error!(
+                        "zero-send stall persists: consecutive={} cnx_state={:?} streams={} streams_with_rx_queued={} queued_bytes_total={} streams_with_send_fin={} zero_send_with_streams={} zero_send_loops={} backlog={:?}",
+                        zero_send_tracker.consecutive(),
+                        cnx_state,
+                        streams_len,
+                        metrics.streams_with_rx_queued,
+                        metrics.queued_bytes_total,
+                        metrics.streams_with_send_fin,
+                        zero_send_with_streams,
+                        zero_send_loops,
+                        backlog
+                    );
+                }
+                Some(ZeroSendAction::Reset) => match config.zero_send_reset {
+                    ZeroSendReset::Off => {
+                        warn!("zero-send stall unresolved; reset disabled");
+                    }
+                    ZeroSendReset::Stream => {
+                        if let Some(stream_id) = state.get_mut().reset_worst_stream(cnx) {
+                            warn!("zero-send stall unresolved; reset stream {}", stream_id);
+                        }
+                    }
+                    ZeroSendReset::Connection => {
+                        warn!("zero-send stall unresolved; closing connection");
+                        break;
+                    }
+                },
+                None => {}
+            }
+            let poll_class = classify_send(true, state.get().is_ready(), streams_len > 0);
             for resolver in resolvers.iter_mut() {
                 if !refresh_resolver_path(cnx, resolver) {
                     continue;
@@ -563,26 +1229,24 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                             .unwrap_or_else(|| cwnd_target_polls(quality.cwin, mtu));
                         let inflight_packets =
                             inflight_packet_estimate(quality.bytes_in_transit, mtu);
-                        let mut pacing_deficit = pacing_target.saturating_sub(inflight_packets);
-                        if has_ready_stream && !flow_blocked {
-                            pacing_deficit = 0;
-                        }
+                        let pacing_deficit = data_biased_poll_deficit(
+                            pacing_target.saturating_sub(inflight_packets),
+                            has_ready_stream,
+                            flow_blocked,
+                            config.data_poll_bias_percent,
+                        );
                         // Demand-driven floor: use pending_polls from DNS responses
                         // so the poll rate never drops below the actual response rate,
                         // even when BBR's pacing estimate is conservative.
-                        let demand_polls = resolver.pending_polls;
+                        let demand_polls = resolver.pending_polls.max(tunables.demand_poll_floor);
                         resolver.pending_polls = 0;
-                        let mut poll_deficit = pacing_deficit.max(demand_polls);
+                        // Never more unanswered polls than the resolver's cap,
+                        // whatever pacing asks for.
+                        let mut poll_deficit =
+                            pacing_deficit.max(demand_polls).min(resolver.poll_slots());
                         // Idle throttling: suppress polls until interval elapses, then allow 1
                         if is_idle && poll_deficit > 0 {
-                            let now_for_idle = unsafe { picoquic_current_time() };
-                            if now_for_idle.saturating_sub(last_idle_poll_at)
-                                < idle_poll_interval_us
-                            {
-                                poll_deficit = 0;
-                            } else {
-                                poll_deficit = 1;
-                            }
+                            poll_deficit = if idle.idle_poll_due(&clock) { 1 } else { 0 };
                         }
                         if poll_deficit > 0 && resolver.debug.enabled {
                             debug!(
@@ -597,39 +1261,51 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                             );
                         }
                         if poll_deficit > 0 {
-                            let burst_max = path_poll_burst_max(resolver);
+                            let burst_max = path_poll_burst_max(resolver, pacing_params);
                             let mut to_send = poll_deficit.min(burst_max);
                             send_poll_queries(
                                 cnx,
                                 &udp,
-                                config,
+                                domain_rotation.current(),
+                                config.service_label,
                                 &mut local_addr_storage,
                                 &mut dns_id,
                                 resolver,
                                 &mut to_send,
-                                &mut send_buf,
+                                send_buf,
+                                &mut qname_guard,
+                                &query_options,
+                                &clock,
+                                poll_class,
                             )
                             .await?;
                             if is_idle {
-                                last_idle_poll_at = unsafe { picoquic_current_time() };
+                                idle.record_idle_poll(&clock);
                             }
                         }
                     }
                     ResolverMode::Recursive => {
                         resolver.last_pacing_snapshot = None;
-                        if resolver.pending_polls > 0 {
-                            let burst_max = path_poll_burst_max(resolver);
+                        // Polls held back by the inflight cap stay pending.
+                        let burst_max =
+                            path_poll_burst_max(resolver, pacing_params).min(resolver.poll_slots());
+                        if resolver.pending_polls > 0 && burst_max > 0 {
                             if resolver.pending_polls > burst_max {
                                 let mut to_send = burst_max;
                                 send_poll_queries(
                                     cnx,
                                     &udp,
-                                    config,
+                                    domain_rotation.current(),
+                                    config.service_label,
                                     &mut local_addr_storage,
                                     &mut dns_id,
                                     resolver,
                                     &mut to_send,
-                                    &mut send_buf,
+                                    send_buf,
+                                    &mut qname_guard,
+                                    &query_options,
+                                    &clock,
+                                    poll_class,
                                 )
                                 .await?;
                                 resolver.pending_polls = resolver
@@ -641,12 +1317,17 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                                 send_poll_queries(
                                     cnx,
                                     &udp,
-                                    config,
+                                    domain_rotation.current(),
+                                    config.service_label,
                                     &mut local_addr_storage,
                                     &mut dns_id,
                                     resolver,
                                     &mut pending,
-                                    &mut send_buf,
+                                    send_buf,
+                                    &mut qname_guard,
+                                    &query_options,
+                                    &clock,
+                                    poll_class,
                                 )
                                 .await?;
                                 resolver.pending_polls = pending;
@@ -656,9 +1337,51 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 }
             }
 
-            let report_time = unsafe { picoquic_current_time() };
-            let (enqueued_bytes, last_enqueue_at) = unsafe { (*state_ptr).debug_snapshot() };
-            let streams_len = unsafe { (*state_ptr).streams_len() };
+            let sent_queries = resolvers.iter().fold(0u64, |total, resolver| {
+                total.saturating_add(resolver.debug.send_packets)
+            });
+            if keep_alive.poll_due(clock.now_us(), state.get().is_ready(), sent_queries) {
+                // The first usable path of the chosen kind carries it.
+                for resolver in resolvers.iter_mut() {
+                    if Some(resolver.mode) != keep_alive.mode()
+                        || !refresh_resolver_path(cnx, resolver)
+                    {
+                        continue;
+                    }
+                    let mut to_send = 1;
+                    send_poll_queries(
+                        cnx,
+                        &udp,
+                        domain_rotation.current(),
+                        config.service_label,
+                        &mut local_addr_storage,
+                        &mut dns_id,
+                        resolver,
+                        &mut to_send,
+                        send_buf,
+                        &mut qname_guard,
+                        &query_options,
+                        &clock,
+                        SendClass::KeepAlive,
+                    )
+                    .await?;
+                    break;
+                }
+            }
+
+            if let Some(clamped) = qname_guard.take_clamp() {
+                // Later connections start at the clamp too.
+                mtu = clamped;
+                state.get_mut().set_path_mtu(mtu);
+                unsafe {
+                    picoquic_set_mtu_max(quic, mtu);
+                    slipstream_clamp_send_mtu(cnx, mtu);
+                }
+            }
+
+            let report_time = clock.now_us();
+            let (enqueued_bytes, last_enqueue_at) = state.get().debug_snapshot();
+            let streams_len = state.get().streams_len();
             for resolver in resolvers.iter_mut() {
                 resolver.debug.enqueued_bytes = enqueued_bytes;
                 resolver.debug.last_enqueue_at = last_enqueue_at;
@@ -667,7 +1390,7 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 if !refresh_resolver_path(cnx, resolver) {
                     continue;
                 }
-                let inflight_polls = resolver.inflight_poll_ids.len();
+                let inflight_polls = resolver.inflight_polls();
                 let pending_for_debug = match resolver.mode {
                     ResolverMode::Authoritative => {
                         let quality = fetch_path_quality(cnx, resolver);
@@ -692,15 +1415,105 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     is_idle,
                 );
             }
+            if report_time.saturating_sub(last_memory_report_at) >= MEMORY_REPORT_INTERVAL_US {
+                let queued = state.get().stream_debug_metrics().queued_bytes_total;
+                memory_bytes = memory_estimate(recv_buf, send_buf, resolvers, queued);
+                record_memory_usage(memory_bytes);
+                record_session_counters(state.get().session_counters());
+                record_ttfb_percentiles(state.get().ttfb_percentiles());
+                let accepts = state.get().accept_counters();
+                let (sampled_at, sampled) = last_accept_sample;
+                record_accept_counters(
+                    accepts,
+                    accepts.accepts_per_sec(&sampled, report_time.saturating_sub(sampled_at)),
+                );
+                last_accept_sample = (report_time, accepts);
+                record_idle_overhead(
+                    idle_overhead.bytes_per_hour(),
+                    idle_overhead.bytes(),
+                    idle_overhead.idle_us(),
+                );
+                let keep_alives = keep_alive_traffic.total(resolvers);
+                record_keep_alive_traffic(keep_alives.packets, keep_alives.bytes);
+                last_memory_report_at = report_time;
+            }
+            if heartbeat.due(report_time) {
+                let latest = |at: fn(&ResolverState) -> u64| resolvers.iter().map(at).max();
+                let heartbeat_state = HeartbeatState {
+                    ready: state.get().is_ready(),
+                    idle: is_idle,
+                    streams: streams_len,
+                    last_poll_at: latest(|resolver| resolver.last_poll_at).unwrap_or(0),
+                    last_response_at: latest(|resolver| resolver.last_response_at).unwrap_or(0),
+                    rtt_us: aggregate_rtt(&path_rtts),
+                };
+                info!("{}", heartbeat_message(report_time, &heartbeat_state));
+            }
+            if streams_len > 0
+                && report_time.saturating_sub(last_watchdog_snapshot_at)
+                    >= WATCHDOG_SNAPSHOT_INTERVAL_US
+            {
+                // Published ahead of time so the watchdog can dump it without the loop.
+                watchdog.publish_diagnostics(watchdog_diagnostics(
+                    state.get(),
+                    resolvers,
+                    &qname_guard,
+                    memory_bytes,
+                    report_time,
+                ));
+                last_watchdog_snapshot_at = report_time;
+            }
         }
 
+        // Count a key update the server confirmed in the last loop pass
+        // before the connection goes away.
+        let completed = key_update.observe(unsafe { slipstream_get_key_updates(cnx) });
+        state.get_mut().record_key_updates(completed);
         unsafe {
             picoquic_close(cnx, 0);
         }
 
-        // Track connection failures - if we never became ready, count as failure
-        if !quic_ready_signaled {
+        let reset_verdict = stateless_resets.observe(state.get().closed_by_stateless_reset());
+        match reset_verdict {
+            ResetVerdict::NotReset => {}
+            ResetVerdict::Tolerated { consecutive } => {
+                record_stateless_reset();
+                info!(
+                    "Stateless reset {}/{} tolerated; reconnecting without backoff (stateless_resets={})",
+                    consecutive,
+                    stateless_resets.tolerance(),
+                    stateless_resets.total()
+                );
+            }
+            ResetVerdict::Reconnect { consecutive } => {
+                record_stateless_reset();
+                warn!(
+                    "Connection ended by stateless reset (consecutive={} stateless_resets={})",
+                    consecutive,
+                    stateless_resets.total()
+                );
+            }
+        }
+        let reset_tolerated = matches!(reset_verdict, ResetVerdict::Tolerated { .. });
+
+        // Track connection failures - if we never became ready (or never got a
+        // usable or required authoritative resolver path), count as failure
+        let failed = !reset_tolerated
+            && (!ready_signal.signaled() || usable_path_failed || authoritative_path_failed);
+        let terminal = terminal_failure.observe(failed, state.get().close_reasons());
+        if failed {
             record_connection_failure();
+            if let Some(error) = terminal {
+                error!(
+                    "Connection failed {} times in a row with {}, giving up",
+                    terminal_failure.repeats(),
+                    error
+                );
+                return Err(ClientError::permanent(format!(
+                    "Connection failed repeatedly with {} - check the pinned certificate and server version",
+                    error
+                )));
+            }
             if exceeded_max_failures() {
                 error!("Exceeded max consecutive connection failures, giving up");
                 return Err(ClientError::new(
@@ -712,9 +1525,19 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         // Reset QUIC ready state for reconnection
         reset_quic_ready();
 
-        unsafe {
-            (*state_ptr).reset_for_reconnect();
-        }
+        let session = state.get().session_counters();
+        record_session_counters(session);
+        let ttfb = state.get().ttfb_percentiles();
+        record_ttfb_percentiles(ttfb);
+        info!(
+            "Session totals: bytes_up={} bytes_down={} streams_opened={} key_updates={} ttfb: {}",
+            session.bytes_up, session.bytes_down, session.streams_opened, session.key_updates, ttfb
+        );
+
+        ecs_resolvers.collect(resolvers);
+        keep_alive_traffic.collect(resolvers);
+        state.get_mut().reset_for_reconnect();
+        watchdog.beat(0, 0);
         let dropped = drain_disconnected_commands(&mut command_rx);
         if dropped > 0 {
             warn!("Dropped {} queued commands while reconnecting", dropped);
@@ -726,12 +1549,32 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             return Ok(0);
         }
 
-        warn!(
-            "Connection closed; reconnecting in {}ms",
-            reconnect_delay.as_millis()
+        let backoff_delay = if reset_tolerated {
+            Duration::ZERO
+        } else {
+            reconnect_backoff.delay()
+        };
+        let (reconnect_sleep, throttled) = reconnect_sleep(
+            backoff_delay,
+            min_reconnect_interval,
+            last_reconnect_at.elapsed(),
         );
+        if throttled {
+            throttled_reconnects = throttled_reconnects.saturating_add(1);
+            warn!(
+                "Connection closed; reconnecting in {}ms (throttled to min reconnect interval {}ms, throttled_reconnects={})",
+                reconnect_sleep.as_millis(),
+                min_reconnect_interval.as_millis(),
+                throttled_reconnects
+            );
+        } else {
+            warn!(
+                "Connection closed; reconnecting in {}ms",
+                reconnect_sleep.as_millis()
+            );
+        }
         // Sleep in small chunks and drop commands that arrive while disconnected.
-        let mut remaining_sleep = reconnect_delay;
+        let mut remaining_sleep = reconnect_sleep;
         while remaining_sleep > Duration::ZERO {
             // Check shutdown during sleep
             if should_shutdown() {
@@ -743,6 +1586,8 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             remaining_sleep -= chunk;
             let _ = drain_disconnected_commands(&mut command_rx);
         }
-        reconnect_delay = (reconnect_delay * 2).min(Duration::from_millis(RECONNECT_SLEEP_MAX_MS));
+        if !reset_tolerated {
+            reconnect_backoff.advance();
+        }
     }
 }
@@ -0,0 +1,151 @@
+use slipstream_ffi::AuthoritativePathTimeout;
+
+/// Where the acceptor gate stands on the current connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GateState {
+    /// No authoritative path is required.
+    Open,
+    /// Streams are held back until an authoritative path validates.
+    Waiting,
+    /// An authoritative path validated; streams are accepted.
+    Validated,
+    /// The wait timed out and streams run over whatever paths exist.
+    FellBack,
+}
+
+impl GateState {
+    /// Stable code for the status API.
+    pub(crate) fn code(self) -> i32 {
+        match self {
+            GateState::Open => 0,
+            GateState::Waiting => 1,
+            GateState::Validated => 2,
+            GateState::FellBack => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GateOutcome {
+    Unchanged,
+    Opened(GateState),
+    /// The wait timed out and the policy is to give up on the connection.
+    Failed,
+}
+
+/// Keeps the TCP acceptor paused until an authoritative resolver path has
+/// validated (`--require-authoritative-path`).
+///
+/// The wait starts when the connection becomes ready. Once the gate opens
+/// it stays open for the rest of the connection.
+pub(crate) struct AuthoritativeGate {
+    state: GateState,
+    timeout_us: u64,
+    on_timeout: AuthoritativePathTimeout,
+    ready_since: Option<u64>,
+}
+
+impl AuthoritativeGate {
+    /// A zero timeout waits indefinitely.
+    pub(crate) fn new(
+        required: bool,
+        timeout_ms: u64,
+        on_timeout: AuthoritativePathTimeout,
+    ) -> Self {
+        Self {
+            state: if required {
+                GateState::Waiting
+            } else {
+                GateState::Open
+            },
+            timeout_us: timeout_ms.saturating_mul(1_000),
+            on_timeout,
+            ready_since: None,
+        }
+    }
+
+    pub(crate) fn state(&self) -> GateState {
+        self.state
+    }
+
+    pub(crate) fn is_waiting(&self) -> bool {
+        self.state == GateState::Waiting
+    }
+
+    pub(crate) fn observe(
+        &mut self,
+        now: u64,
+        ready: bool,
+        authoritative_validated: bool,
+    ) -> GateOutcome {
+        if !self.is_waiting() || !ready {
+            return GateOutcome::Unchanged;
+        }
+        if authoritative_validated {
+            self.state = GateState::Validated;
+            return GateOutcome::Opened(self.state);
+        }
+        let since = *self.ready_since.get_or_insert(now);
+        if self.timeout_us == 0 || now.saturating_sub(since) < self.timeout_us {
+            return GateOutcome::Unchanged;
+        }
+        match self.on_timeout {
+            AuthoritativePathTimeout::Fallback => {
+                self.state = GateState::FellBack;
+                GateOutcome::Opened(self.state)
+            }
+            AuthoritativePathTimeout::Fail => GateOutcome::Failed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthoritativeGate, GateOutcome, GateState};
+    use slipstream_ffi::AuthoritativePathTimeout;
+
+    #[test]
+    fn not_required_is_open() {
+        let mut gate = AuthoritativeGate::new(false, 10, AuthoritativePathTimeout::Fail);
+        assert!(!gate.is_waiting());
+        assert_eq!(gate.observe(u64::MAX, true, false), GateOutcome::Unchanged);
+        assert_eq!(gate.state(), GateState::Open);
+    }
+
+    #[test]
+    fn opens_once_authoritative_path_validates() {
+        let mut gate = AuthoritativeGate::new(true, 10, AuthoritativePathTimeout::Fail);
+        // Handshake time does not count against the timeout.
+        assert_eq!(gate.observe(50_000, false, false), GateOutcome::Unchanged);
+        assert_eq!(gate.observe(60_000, true, false), GateOutcome::Unchanged);
+        assert_eq!(
+            gate.observe(65_000, true, true),
+            GateOutcome::Opened(GateState::Validated)
+        );
+        assert!(!gate.is_waiting());
+        assert_eq!(gate.observe(100_000, true, false), GateOutcome::Unchanged);
+    }
+
+    #[test]
+    fn timeout_follows_policy() {
+        let mut gate = AuthoritativeGate::new(true, 10, AuthoritativePathTimeout::Fallback);
+        assert_eq!(gate.observe(0, true, false), GateOutcome::Unchanged);
+        assert_eq!(gate.observe(9_999, true, false), GateOutcome::Unchanged);
+        assert_eq!(
+            gate.observe(10_000, true, false),
+            GateOutcome::Opened(GateState::FellBack)
+        );
+
+        let mut gate = AuthoritativeGate::new(true, 10, AuthoritativePathTimeout::Fail);
+        assert_eq!(gate.observe(0, true, false), GateOutcome::Unchanged);
+        assert_eq!(gate.observe(10_000, true, false), GateOutcome::Failed);
+        assert!(gate.is_waiting());
+    }
+
+    #[test]
+    fn zero_timeout_waits_forever() {
+        let mut gate = AuthoritativeGate::new(true, 0, AuthoritativePathTimeout::Fail);
+        assert_eq!(gate.observe(0, true, false), GateOutcome::Unchanged);
+        assert_eq!(gate.observe(u64::MAX, true, false), GateOutcome::Unchanged);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::dns::ResolverState;
+use std::mem::size_of;
+
+/// How often the memory estimate is refreshed for the status API.
+pub(crate) const MEMORY_REPORT_INTERVAL_US: u64 = 1_000_000;
+
+const RECV_BUF_LEN: usize = 4096;
+
+/// Scratch state that outlives individual connections.
+///
+/// Created once per client run and reset on every reconnect, so repeated
+/// reconnects reuse the same allocations instead of fragmenting the heap.
+pub(crate) struct LoopBuffers {
+    pub(crate) recv_buf: Vec<u8>,
+    pub(crate) send_buf: Vec<u8>,
+    pub(crate) resolvers: Vec<ResolverState>,
+}
+
+impl LoopBuffers {
+    pub(crate) fn new(send_buf_len: usize) -> Self {
+        Self {
+            recv_buf: vec![0u8; RECV_BUF_LEN],
+            send_buf: vec![0u8; send_buf_len],
+            resolvers: Vec::new(),
+        }
+    }
+
+    /// Clears contents left by the previous connection, keeping capacity.
+    pub(crate) fn reset(&mut self) {
+        self.recv_buf.fill(0);
+        self.send_buf.fill(0);
+        self.resolvers.clear();
+    }
+}
+
+/// Coarse tunnel memory footprint in bytes.
+///
+/// Covers the loop buffers, the resolver tables (including inflight poll
+/// maps) and stream bytes queued for local TCP writes. picoquic's own
+/// allocations are not included.
+pub(crate) fn memory_estimate(
+    recv_buf: &[u8],
+    send_buf: &[u8],
+    resolvers: &Vec<ResolverState>,
+    queued_stream_bytes: u64,
+) -> u64 {
+    let poll_maps: usize = resolvers
+        .iter()
+        .map(|resolver| {
+            (resolver.inflight_poll_ids.capacity() + resolver.recursive_poll_ids.capacity())
+                * size_of::<(u16, u64)>()
+        })
+        .sum();
+    let tables = resolvers.capacity() * size_of::<ResolverState>() + poll_maps;
+    ((recv_buf.len() + send_buf.len() + tables) as u64).saturating_add(queued_stream_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{memory_estimate, LoopBuffers, RECV_BUF_LEN};
+
+    #[test]
+    fn reset_keeps_allocations() {
+        let mut buffers = LoopBuffers::new(1500);
+        buffers.recv_buf[0] = 1;
+        buffers.resolvers.reserve(4);
+        let recv_ptr = buffers.recv_buf.as_ptr();
+        let resolvers_capacity = buffers.resolvers.capacity();
+        buffers.reset();
+        assert_eq!(buffers.recv_buf.as_ptr(), recv_ptr);
+        assert_eq!(buffers.recv_buf[0], 0);
+        assert_eq!(buffers.resolvers.capacity(), resolvers_capacity);
+    }
+
+    #[test]
+    fn estimate_counts_buffers_and_queued_bytes() {
+        let buffers = LoopBuffers::new(1500);
+        let base = memory_estimate(&buffers.recv_buf, &buffers.send_buf, &buffers.resolvers, 0);
+        assert_eq!(base, (RECV_BUF_LEN + 1500) as u64);
+        let queued = memory_estimate(
+            &buffers.recv_buf,
+            &buffers.send_buf,
+            &buffers.resolvers,
+            10_000,
+        );
+        assert_eq!(queued, base + 10_000);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::dns::ResolverState;
+use slipstream_ffi::picoquic::{picoquic_cnx_t, picoquic_renew_connection_id};
+
+/// Local connection ID length used while rotation is enabled. Shorter than
+/// picoquic's default of 8 so every answer carries fewer linkable bytes.
+pub(crate) const ROTATING_CID_LENGTH: u8 = 4;
+
+/// Schedule and counters for client-driven connection ID rotation.
+///
+/// On each rotation the client switches every path to a fresh server-issued
+/// CID from picoquic's stash. The CID carried in query payloads therefore
+/// changes as the connection goes through successive resolver hops.
+pub(crate) struct CidRotation {
+    interval_us: u64,
+    next_at: Option<u64>,
+    rotations: u64,
+    failures: u64,
+}
+
+impl CidRotation {
+    /// A zero interval disables rotation.
+    pub(crate) fn new(interval_ms: u64) -> Self {
+        Self {
+            interval_us: interval_ms.saturating_mul(1_000),
+            next_at: None,
+            rotations: 0,
+            failures: 0,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.interval_us > 0
+    }
+
+    /// The first check after the connection is ready only arms the schedule.
+    pub(crate) fn due(&mut self, now: u64) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+        match self.next_at {
+            None => {
+                self.next_at = Some(now.saturating_add(self.interval_us));
+                false
+            }
+            Some(next_at) => now >= next_at,
+        }
+    }
+
+    pub(crate) fn record(&mut self, now: u64, renewed: u64, failed: u64) {
+        self.rotations = self.rotations.saturating_add(renewed);
+        self.failures = self.failures.saturating_add(failed);
+        self.next_at = Some(now.saturating_add(self.interval_us));
+    }
+
+    pub(crate) fn rotations(&self) -> u64 {
+        self.rotations
+    }
+
+    pub(crate) fn failures(&self) -> u64 {
+        self.failures
+    }
+}
+
+/// Switch every added path to a new remote CID; returns (renewed, failed).
+///
+/// Failures are expected while picoquic has no unused server-issued CID
+/// stashed for a path; the next rotation retries.
+pub(crate) fn renew_path_cids(cnx: *mut picoquic_cnx_t, resolvers: &[ResolverState]) -> (u64, u64) {
+    let mut renewed = 0u64;
+    let mut failed = 0u64;
+    for resolver in resolvers
+        .iter()
+        .filter(|resolver| resolver.added && resolver.path_id >= 0)
+    {
+        if unsafe { picoquic_renew_connection_id(cnx, resolver.path_id) } == 0 {
+            renewed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+    (renewed, failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CidRotation;
+
+    #[test]
+    fn rotation_schedule_follows_interval() {
+        let mut rotation = CidRotation::new(2);
+        assert!(!rotation.due(1_000));
+        assert!(!rotation.due(2_999));
+        assert!(rotation.due(3_000));
+        rotation.record(3_500, 2, 1);
+        assert_eq!((rotation.rotations(), rotation.failures()), (2, 1));
+        assert!(!rotation.due(5_499));
+        assert!(rotation.due(5_500));
+    }
+
+    #[test]
+    fn zero_interval_disables_rotation() {
+        let mut rotation = CidRotation::new(0);
+        assert!(!rotation.enabled());
+        assert!(!rotation.due(0));
+        assert!(!rotation.due(u64::MAX));
+    }
+}
@@ -0,0 +1,80 @@
+use slipstream_core::clock::Clock;
+
+/// Oversleep past the requested timeout that counts as a clock jump. The
+/// loop never asks for more than a few seconds, so this only trips when the
+/// device suspended or the clock was stepped.
+pub(crate) const CLOCK_JUMP_THRESHOLD_US: u64 = 5_000_000;
+
+/// Detects forward clock jumps across the loop's wait.
+///
+/// The loop arms the detector with the timeout it is about to sleep for and
+/// checks it once it wakes up; if the clock moved further than that timeout
+/// plus [`CLOCK_JUMP_THRESHOLD_US`], the excess is reported as a jump so
+/// timers based on the loop clock can be shifted past it.
+pub(crate) struct ClockJumpDetector {
+    armed_at: u64,
+    timeout_us: u64,
+    jumps: u64,
+}
+
+impl ClockJumpDetector {
+    pub(crate) fn new() -> Self {
+        Self {
+            armed_at: 0,
+            timeout_us: 0,
+            jumps: 0,
+        }
+    }
+
+    pub(crate) fn arm(&mut self, clock: &dyn Clock, timeout_us: u64) {
+        self.armed_at = clock.now_us();
+        self.timeout_us = timeout_us;
+    }
+
+    /// The jump in microseconds beyond the requested timeout, if any.
+    pub(crate) fn check(&mut self, clock: &dyn Clock) -> Option<u64> {
+        let slept = clock.now_us().saturating_sub(self.armed_at);
+        let jump = slept.saturating_sub(self.timeout_us);
+        if jump < CLOCK_JUMP_THRESHOLD_US {
+            return None;
+        }
+        self.jumps = self.jumps.saturating_add(1);
+        Some(jump)
+    }
+
+    pub(crate) fn jumps(&self) -> u64 {
+        self.jumps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClockJumpDetector, CLOCK_JUMP_THRESHOLD_US};
+    use slipstream_core::clock::ManualClock;
+
+    #[test]
+    fn long_sleeps_within_the_timeout_are_not_jumps() {
+        let clock = ManualClock::new(1_000_000);
+        let mut detector = ClockJumpDetector::new();
+        detector.arm(&clock, 2_000_000);
+        clock.advance_us(2_000_000 + CLOCK_JUMP_THRESHOLD_US - 1);
+        assert_eq!(detector.check(&clock), None);
+        assert_eq!(detector.jumps(), 0);
+    }
+
+    #[test]
+    fn reports_the_excess_over_the_timeout() {
+        let clock = ManualClock::new(1_000_000);
+        let mut detector = ClockJumpDetector::new();
+        detector.arm(&clock, 10_000);
+        clock.advance_us(30_000_000);
+        assert_eq!(detector.check(&clock), Some(29_990_000));
+        assert_eq!(detector.jumps(), 1);
+
+        // Each wait is judged on its own.
+        detector.arm(&clock, 10_000);
+        clock.advance_us(5_000);
+        assert_eq!(detector.check(&clock), None);
+        assert_eq!(detector.jumps(), 1);
+    }
+}
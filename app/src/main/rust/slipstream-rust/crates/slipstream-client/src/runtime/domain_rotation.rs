@@ -0,0 +1,185 @@
+use std::fmt;
+
+/// Why the tunnel domain changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RotationReason {
+    Interval,
+    Bytes,
+}
+
+impl fmt::Display for RotationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RotationReason::Interval => "interval",
+            RotationReason::Bytes => "bytes",
+        })
+    }
+}
+
+/// Per-connection schedule for cycling the tunnel domain suffix.
+///
+/// Every connection starts on the primary domain. Once the interval has
+/// elapsed or the byte budget has been sent under the current domain, new
+/// queries move on to the next domain in the set. Poll responses are
+/// matched by DNS ID, so queries sent under the previous domain still count.
+pub(crate) struct DomainRotation<'a> {
+    domains: Vec<&'a str>,
+    index: usize,
+    interval_us: u64,
+    bytes_limit: u64,
+    current_since: Option<u64>,
+    current_start_bytes: u64,
+    rotations: u64,
+}
+
+impl<'a> DomainRotation<'a> {
+    /// Zero interval and byte limits disable the respective trigger; with
+    /// both disabled, or no extra domains, the primary domain is kept.
+    pub(crate) fn new(
+        primary: &'a str,
+        extra: &'a [String],
+        interval_ms: u64,
+        bytes_limit: u64,
+    ) -> Self {
+        let mut domains = vec![primary];
+        domains.extend(extra.iter().map(String::as_str));
+        Self {
+            domains,
+            index: 0,
+            interval_us: interval_ms.saturating_mul(1_000),
+            bytes_limit,
+            current_since: None,
+            current_start_bytes: 0,
+            rotations: 0,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.domains.len() > 1 && (self.interval_us > 0 || self.bytes_limit > 0)
+    }
+
+    pub(crate) fn current(&self) -> &'a str {
+        self.domains[self.index]
+    }
+
+    pub(crate) fn rotations(&self) -> u64 {
+        self.rotations
+    }
+
+    /// Advances to the next domain when a trigger has fired. `sent_bytes` is
+    /// the connection's running total of tunnel bytes sent. Returns the
+    /// previous domain and the reason.
+    pub(crate) fn maybe_rotate(
+        &mut self,
+        now: u64,
+        sent_bytes: u64,
+    ) -> Option<(&'a str, RotationReason)> {
+        if !self.enabled() {
+            return None;
+        }
+        let since = *self.current_since.get_or_insert(now);
+        let current_bytes = sent_bytes.saturating_sub(self.current_start_bytes);
+        let reason = if self.bytes_limit > 0 && current_bytes >= self.bytes_limit {
+            RotationReason::Bytes
+        } else if self.interval_us > 0 && now.saturating_sub(since) >= self.interval_us {
+            RotationReason::Interval
+        } else {
+            return None;
+        };
+        let previous = self.current();
+        self.index = (self.index + 1) % self.domains.len();
+        self.current_since = Some(now);
+        self.current_start_bytes = sent_bytes;
+        self.rotations = self.rotations.saturating_add(1);
+        Some((previous, reason))
+    }
+}
+
+/// Length of the longest domain in the rotation set, for MTU sizing.
+pub(crate) fn longest_domain_len(primary: &str, extra: &[String]) -> usize {
+    extra
+        .iter()
+        .map(String::len)
+        .fold(primary.len(), usize::max)
+}
+
+/// The longest domain in the rotation set; the first one wins a tie.
+pub(crate) fn longest_domain<'a>(primary: &'a str, extra: &'a [String]) -> &'a str {
+    extra
+        .iter()
+        .map(String::as_str)
+        .fold(primary, |longest, domain| {
+            if domain.len() > longest.len() {
+                domain
+            } else {
+                longest
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{longest_domain, longest_domain_len, DomainRotation, RotationReason};
+
+    fn extra() -> Vec<String> {
+        vec![
+            "b.example.com".to_string(),
+            "longer-c.example.com".to_string(),
+        ]
+    }
+
+    #[test]
+    fn rotates_on_interval_and_wraps() {
+        let extra = extra();
+        let mut rotation = DomainRotation::new("a.example.com", &extra, 10, 0);
+        assert!(rotation.enabled());
+        assert_eq!(rotation.maybe_rotate(0, 0), None);
+        assert_eq!(rotation.maybe_rotate(9_999, 0), None);
+        assert_eq!(
+            rotation.maybe_rotate(10_000, 0),
+            Some(("a.example.com", RotationReason::Interval))
+        );
+        assert_eq!(rotation.current(), "b.example.com");
+        assert_eq!(rotation.maybe_rotate(15_000, 0), None);
+        rotation.maybe_rotate(20_000, 0);
+        assert_eq!(rotation.current(), "longer-c.example.com");
+        rotation.maybe_rotate(30_000, 0);
+        assert_eq!(rotation.current(), "a.example.com");
+        assert_eq!(rotation.rotations(), 3);
+    }
+
+    #[test]
+    fn rotates_on_bytes_and_resets_budget() {
+        let extra = extra();
+        let mut rotation = DomainRotation::new("a.example.com", &extra, 0, 1_000);
+        assert_eq!(rotation.maybe_rotate(0, 999), None);
+        assert_eq!(
+            rotation.maybe_rotate(1, 1_000),
+            Some(("a.example.com", RotationReason::Bytes))
+        );
+        assert_eq!(rotation.maybe_rotate(2, 1_500), None);
+        assert_eq!(rotation.current(), "b.example.com");
+    }
+
+    #[test]
+    fn disabled_without_extra_domains_or_schedule() {
+        let mut rotation = DomainRotation::new("a.example.com", &[], 10, 10);
+        assert!(!rotation.enabled());
+        assert_eq!(rotation.maybe_rotate(u64::MAX, u64::MAX), None);
+
+        let extra = extra();
+        let rotation = DomainRotation::new("a.example.com", &extra, 0, 0);
+        assert!(!rotation.enabled());
+    }
+
+    #[test]
+    fn longest_domain_sizes_mtu() {
+        assert_eq!(longest_domain_len("a.example.com", &extra()), 20);
+        assert_eq!(longest_domain_len("a.example.com", &[]), 13);
+        assert_eq!(
+            longest_domain("a.example.com", &extra()),
+            "longer-c.example.com"
+        );
+        assert_eq!(longest_domain("a.example.com", &[]), "a.example.com");
+    }
+}
@@ -0,0 +1,112 @@
+/// Spaces the `--heartbeat-interval-ms` log lines.
+///
+/// Only checked when the loop wakes up for its own reasons, so a heartbeat
+/// never shortens a sleep: while idle a line may come up to one idle sleep
+/// late.
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+    interval_us: u64,
+    last_at: Option<u64>,
+}
+
+impl Heartbeat {
+    /// A zero interval never logs.
+    pub(crate) fn new(interval_ms: u64) -> Self {
+        Self {
+            interval_us: interval_ms.saturating_mul(1_000),
+            last_at: None,
+        }
+    }
+
+    /// True when a heartbeat should be logged now. The first call only
+    /// starts the interval.
+    pub(crate) fn due(&mut self, now: u64) -> bool {
+        if self.interval_us == 0 {
+            return false;
+        }
+        match self.last_at {
+            Some(last) if now.saturating_sub(last) < self.interval_us => false,
+            Some(_) => {
+                self.last_at = Some(now);
+                true
+            }
+            None => {
+                self.last_at = Some(now);
+                false
+            }
+        }
+    }
+}
+
+/// What one heartbeat line reports. Times are loop clock readings, 0 when
+/// the event has not happened on this connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HeartbeatState {
+    pub(crate) ready: bool,
+    pub(crate) idle: bool,
+    pub(crate) streams: usize,
+    pub(crate) last_poll_at: u64,
+    pub(crate) last_response_at: u64,
+    pub(crate) rtt_us: Option<u64>,
+}
+
+pub(crate) fn heartbeat_message(now: u64, state: &HeartbeatState) -> String {
+    let ago = |at: u64| {
+        if at == 0 {
+            "-".to_string()
+        } else {
+            format!("{}ms", now.saturating_sub(at) / 1_000)
+        }
+    };
+    format!(
+        "Heartbeat ready={} idle={} streams={} last_poll={} last_response={} rtt={}",
+        state.ready,
+        state.idle,
+        state.streams,
+        ago(state.last_poll_at),
+        ago(state.last_response_at),
+        state
+            .rtt_us
+            .map_or_else(|| "-".to_string(), |rtt| format!("{}ms", rtt / 1_000))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{heartbeat_message, Heartbeat, HeartbeatState};
+
+    #[test]
+    fn heartbeat_logs_once_per_interval() {
+        let mut off = Heartbeat::new(0);
+        assert!(!off.due(0));
+        assert!(!off.due(u64::MAX));
+
+        let mut heartbeat = Heartbeat::new(30_000);
+        assert!(!heartbeat.due(5_000_000));
+        assert!(!heartbeat.due(34_999_999));
+        assert!(heartbeat.due(36_000_000));
+        assert!(!heartbeat.due(60_000_000));
+        assert!(heartbeat.due(66_000_000));
+    }
+
+    #[test]
+    fn message_reports_ages_and_missing_samples() {
+        let state = HeartbeatState {
+            ready: true,
+            idle: true,
+            streams: 0,
+            last_poll_at: 8_500_000,
+            last_response_at: 0,
+            rtt_us: Some(182_400),
+        };
+        assert_eq!(
+            heartbeat_message(10_000_000, &state),
+            "Heartbeat ready=true idle=true streams=0 last_poll=1500ms last_response=- rtt=182ms"
+        );
+        let state = HeartbeatState {
+            rtt_us: None,
+            ..state
+        };
+        assert!(heartbeat_message(10_000_000, &state).ends_with(" rtt=-"));
+    }
+}
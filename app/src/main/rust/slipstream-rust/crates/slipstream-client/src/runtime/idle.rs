@@ -0,0 +1,211 @@
+use crate::dns::bytes_per_hour;
+use slipstream_core::clock::Clock;
+
+pub(crate) const IDLE_THRESHOLD_US: u64 = 2_000_000; // 2s without streams → idle
+
+/// Whether the loop is idle, and how far apart idle polls are spaced.
+///
+/// With battery saver active the connection goes idle as soon as no
+/// streams are open, skipping the usual threshold, and idle polls use the
+/// battery saver interval. An open stream always ends idleness, so a
+/// stream opened while the screen is off gets its polls right away.
+pub(crate) fn idle_policy(
+    streams_len: usize,
+    since_active_us: u64,
+    idle_poll_interval_us: u64,
+    battery_saver_interval_us: Option<u64>,
+) -> (bool, u64) {
+    match battery_saver_interval_us {
+        Some(interval_us) => (streams_len == 0, interval_us),
+        None => (
+            idle_poll_interval_us > 0 && since_active_us >= IDLE_THRESHOLD_US,
+            idle_poll_interval_us,
+        ),
+    }
+}
+
+/// Idle state across loop iterations: when streams were last open, when the
+/// last idle poll went out, and the interval the current iteration uses.
+pub(crate) struct IdleScheduler {
+    idle_poll_interval_us: u64,
+    last_active_at: u64,
+    last_idle_poll_at: u64,
+    interval_us: u64,
+}
+
+impl IdleScheduler {
+    pub(crate) fn new(idle_poll_interval_us: u64) -> Self {
+        Self {
+            idle_poll_interval_us,
+            last_active_at: 0,
+            last_idle_poll_at: 0,
+            interval_us: idle_poll_interval_us,
+        }
+    }
+
+    /// Re-evaluates idleness for this iteration; see [`idle_policy`].
+    pub(crate) fn update(
+        &mut self,
+        clock: &dyn Clock,
+        streams_len: usize,
+        battery_saver_interval_us: Option<u64>,
+    ) -> (bool, u64) {
+        let now = clock.now_us();
+        if streams_len > 0 {
+            self.last_active_at = now;
+        }
+        let (is_idle, interval_us) = idle_policy(
+            streams_len,
+            now.saturating_sub(self.last_active_at),
+            self.idle_poll_interval_us,
+            battery_saver_interval_us,
+        );
+        self.interval_us = interval_us;
+        (is_idle, interval_us)
+    }
+
+    /// Whether an idle loop may send its next poll.
+    pub(crate) fn idle_poll_due(&self, clock: &dyn Clock) -> bool {
+        clock.now_us().saturating_sub(self.last_idle_poll_at) >= self.interval_us
+    }
+
+    pub(crate) fn record_idle_poll(&mut self, clock: &dyn Clock) {
+        self.last_idle_poll_at = clock.now_us();
+    }
+
+    /// Moves the last activity forward by `jump_us` after a clock jump, so
+    /// time the device spent suspended does not count towards going idle.
+    pub(crate) fn skip_clock_jump(&mut self, jump_us: u64) {
+        if self.last_active_at > 0 {
+            self.last_active_at = self.last_active_at.saturating_add(jump_us);
+        }
+    }
+}
+
+/// What an idle tunnel costs: keep-alive and poll bytes sent while the
+/// loop was idle, over the time it was idle, for the whole session.
+#[derive(Debug, Default)]
+pub(crate) struct IdleOverhead {
+    bytes: u64,
+    idle_us: u64,
+    /// Time, overhead byte total and idleness at the last observation.
+    last: Option<(u64, u64, bool)>,
+}
+
+impl IdleOverhead {
+    /// Called once per loop iteration with the resolvers' current overhead
+    /// byte total. The interval since the last call counts when the loop
+    /// was idle at its start; a total that dropped (new resolver counters
+    /// after a reconnect) adds nothing.
+    pub(crate) fn observe(&mut self, now: u64, is_idle: bool, overhead_bytes: u64) {
+        if let Some((then, last_bytes, true)) = self.last {
+            self.bytes = self
+                .bytes
+                .saturating_add(overhead_bytes.saturating_sub(last_bytes));
+            self.idle_us = self.idle_us.saturating_add(now.saturating_sub(then));
+        }
+        self.last = Some((now, overhead_bytes, is_idle));
+    }
+
+    /// Leaves time the device spent suspended out of the idle time.
+    pub(crate) fn skip_clock_jump(&mut self, jump_us: u64) {
+        if let Some((then, _, _)) = self.last.as_mut() {
+            *then = then.saturating_add(jump_us);
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub(crate) fn idle_us(&self) -> u64 {
+        self.idle_us
+    }
+
+    pub(crate) fn bytes_per_hour(&self) -> u64 {
+        bytes_per_hour(self.bytes, self.idle_us)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{idle_policy, IdleOverhead, IdleScheduler, IDLE_THRESHOLD_US};
+    use slipstream_core::clock::ManualClock;
+
+    #[test]
+    fn idle_after_threshold_without_battery_saver() {
+        assert_eq!(idle_policy(0, 0, 2_000_000, None), (false, 2_000_000));
+        assert_eq!(
+            idle_policy(0, IDLE_THRESHOLD_US, 2_000_000, None),
+            (true, 2_000_000)
+        );
+        // A zero idle interval disables idle throttling.
+        assert!(!idle_policy(0, IDLE_THRESHOLD_US, 0, None).0);
+    }
+
+    #[test]
+    fn battery_saver_idles_immediately_until_a_stream_opens() {
+        assert_eq!(
+            idle_policy(0, 0, 2_000_000, Some(30_000_000)),
+            (true, 30_000_000)
+        );
+        assert!(!idle_policy(1, 0, 2_000_000, Some(30_000_000)).0);
+    }
+
+    #[test]
+    fn scheduler_goes_idle_and_spaces_polls() {
+        let clock = ManualClock::new(10_000_000);
+        let mut idle = IdleScheduler::new(1_000_000);
+        assert!(!idle.update(&clock, 1, None).0);
+
+        clock.advance_us(IDLE_THRESHOLD_US - 1);
+        assert!(!idle.update(&clock, 0, None).0);
+        clock.advance_us(1);
+        assert_eq!(idle.update(&clock, 0, None), (true, 1_000_000));
+        assert!(idle.idle_poll_due(&clock));
+        idle.record_idle_poll(&clock);
+
+        clock.advance_us(999_999);
+        assert!(idle.update(&clock, 0, None).0);
+        assert!(!idle.idle_poll_due(&clock));
+        clock.advance_us(1);
+        assert!(idle.idle_poll_due(&clock));
+
+        // A new stream ends idleness at once and restarts the threshold.
+        assert!(!idle.update(&clock, 1, None).0);
+        clock.advance_us(IDLE_THRESHOLD_US - 1);
+        assert!(!idle.update(&clock, 0, None).0);
+    }
+
+    #[test]
+    fn scheduler_uses_battery_saver_interval_while_active() {
+        let clock = ManualClock::new(0);
+        let mut idle = IdleScheduler::new(1_000_000);
+        idle.record_idle_poll(&clock);
+        assert_eq!(idle.update(&clock, 0, Some(30_000_000)), (true, 30_000_000));
+        clock.advance_us(1_000_000);
+        assert!(!idle.idle_poll_due(&clock));
+        // Screen back on: the normal interval applies on the next update.
+        idle.update(&clock, 0, None);
+        assert!(idle.idle_poll_due(&clock));
+    }
+
+    #[test]
+    fn idle_overhead_only_counts_idle_intervals() {
+        let mut overhead = IdleOverhead::default();
+        overhead.observe(0, false, 0);
+        // Busy until 10s: the bytes sent meanwhile are not overhead.
+        overhead.observe(10_000_000, true, 5_000);
+        overhead.observe(40_000_000, true, 5_150);
+        assert_eq!((overhead.bytes(), overhead.idle_us()), (150, 30_000_000));
+        assert_eq!(overhead.bytes_per_hour(), 18_000);
+
+        // A suspend and a reconnect's fresh counters add neither time nor
+        // bytes.
+        overhead.skip_clock_jump(600_000_000);
+        overhead.observe(640_000_000, true, 20);
+        assert_eq!((overhead.bytes(), overhead.idle_us()), (150, 30_000_000));
+        overhead.observe(670_000_000, false, 170);
+        assert_eq!((overhead.bytes(), overhead.idle_us()), (300, 60_000_000));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::dns::{ResolverState, TrafficCount};
+use crate::error::ClientError;
+use slipstream_ffi::{ClientConfig, KeepAlivePath, ResolverMode};
+
+/// picoquic's default idle timeout, which the client does not change. The
+/// server may ask for a shorter one during the handshake.
+pub(crate) const QUIC_IDLE_TIMEOUT_MS: u64 = 30_000;
+
+/// Rejects keep-alive settings that cannot keep a connection up: an interval
+/// longer than the idle timeout, or a `--keep-alive-path` with no resolver of
+/// that kind.
+pub(crate) fn check_keep_alive_options(config: &ClientConfig<'_>) -> Result<(), ClientError> {
+    if config.keep_alive_interval as u64 > QUIC_IDLE_TIMEOUT_MS {
+        return Err(ClientError::new(format!(
+            "Keep-alive interval {}ms exceeds the {}ms QUIC idle timeout; the connection would time out between keep-alives",
+            config.keep_alive_interval, QUIC_IDLE_TIMEOUT_MS
+        )));
+    }
+    if let Some(mode) = keep_alive_mode(config.keep_alive_path) {
+        if !config
+            .resolvers
+            .iter()
+            .any(|resolver| resolver.mode == mode)
+        {
+            return Err(ClientError::new(format!(
+                "Keep-alives on the {} path need at least one {} resolver",
+                mode_name(mode),
+                mode_name(mode)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Resolver mode whose paths carry keep-alive polls, or `None` when
+/// picoquic sends its own PINGs.
+pub(crate) fn keep_alive_mode(path: KeepAlivePath) -> Option<ResolverMode> {
+    match path {
+        KeepAlivePath::Any => None,
+        KeepAlivePath::Authoritative => Some(ResolverMode::Authoritative),
+        KeepAlivePath::Recursive => Some(ResolverMode::Recursive),
+    }
+}
+
+fn mode_name(mode: ResolverMode) -> &'static str {
+    match mode {
+        ResolverMode::Authoritative => "authoritative",
+        ResolverMode::Recursive => "recursive",
+    }
+}
+
+/// Schedules keep-alive polls for `--keep-alive-path`, standing in for
+/// picoquic's keep-alive, which sends a PING once nothing else has gone out
+/// for the interval. Any query on any path restarts the interval, as it
+/// does for picoquic.
+#[derive(Debug)]
+pub(crate) struct KeepAliveTimer {
+    mode: Option<ResolverMode>,
+    interval_us: u64,
+    sent_queries: u64,
+    last_query_at: u64,
+}
+
+impl KeepAliveTimer {
+    /// Never due with a zero interval or when picoquic sends keep-alives.
+    pub(crate) fn new(path: KeepAlivePath, interval_ms: usize, now: u64) -> Self {
+        Self {
+            mode: keep_alive_mode(path),
+            interval_us: (interval_ms as u64).saturating_mul(1_000),
+            sent_queries: 0,
+            last_query_at: now,
+        }
+    }
+
+    /// Resolver mode the poll goes out on when one is due.
+    pub(crate) fn mode(&self) -> Option<ResolverMode> {
+        self.mode.filter(|_| self.interval_us > 0)
+    }
+
+    /// Notes the running total of queries sent on this connection and
+    /// returns true when a keep-alive poll should go out now. Only a ready
+    /// connection is kept alive.
+    pub(crate) fn poll_due(&mut self, now: u64, ready: bool, sent_queries: u64) -> bool {
+        if sent_queries != self.sent_queries {
+            self.sent_queries = sent_queries;
+            self.last_query_at = now;
+            return false;
+        }
+        if self.mode().is_none() || !ready || now < self.next_due() {
+            return false;
+        }
+        // Also restarts the interval when the poll could not be sent.
+        self.last_query_at = now;
+        true
+    }
+
+    /// `delay_us` shortened so the loop wakes when the next keep-alive is due.
+    pub(crate) fn cap_wake_delay(&self, now: u64, ready: bool, delay_us: u64) -> u64 {
+        if self.mode().is_none() || !ready {
+            return delay_us;
+        }
+        delay_us.min(self.next_due().saturating_sub(now))
+    }
+
+    fn next_due(&self) -> u64 {
+        self.last_query_at.saturating_add(self.interval_us)
+    }
+}
+
+/// Keep-alive queries sent during this client session, across reconnects.
+/// Counted under [`crate::dns::SendClass::KeepAlive`], so they include the
+/// ACKs a ready connection sends with no stream open.
+#[derive(Debug, Default)]
+pub(crate) struct KeepAliveTraffic {
+    earlier: TrafficCount,
+}
+
+impl KeepAliveTraffic {
+    /// Take over what the current connection sent before it is replaced.
+    pub(crate) fn collect(&mut self, resolvers: &[ResolverState]) {
+        self.earlier = self.total(resolvers);
+    }
+
+    /// Session total, including what the current connection has sent.
+    pub(crate) fn total(&self, resolvers: &[ResolverState]) -> TrafficCount {
+        resolvers
+            .iter()
+            .fold(self.earlier, |total, resolver| TrafficCount {
+                packets: total
+                    .packets
+                    .saturating_add(resolver.debug.sends.keep_alive.packets),
+                bytes: total
+                    .bytes
+                    .saturating_add(resolver.debug.sends.keep_alive.bytes),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keep_alive_mode, KeepAliveTimer};
+    use slipstream_ffi::{KeepAlivePath, ResolverMode};
+
+    #[test]
+    fn keep_alive_polls_follow_the_last_query() {
+        let mut picoquic = KeepAliveTimer::new(KeepAlivePath::Any, 400, 0);
+        assert!(!picoquic.poll_due(1_000_000, true, 0));
+        assert_eq!(picoquic.cap_wake_delay(0, true, 5_000_000), 5_000_000);
+
+        let mut timer = KeepAliveTimer::new(KeepAlivePath::Recursive, 400, 0);
+        assert_eq!(timer.mode(), Some(ResolverMode::Recursive));
+        assert!(!timer.poll_due(399_999, true, 0));
+        assert_eq!(timer.cap_wake_delay(100_000, true, 5_000_000), 300_000);
+        // Not kept alive before the connection is ready.
+        assert!(!timer.poll_due(500_000, false, 0));
+        assert_eq!(timer.cap_wake_delay(500_000, false, 5_000_000), 5_000_000);
+        assert!(timer.poll_due(500_000, true, 0));
+        assert!(!timer.poll_due(600_000, true, 0));
+        // Its own poll, or any other query, restarts the interval.
+        assert!(!timer.poll_due(700_000, true, 1));
+        assert!(!timer.poll_due(1_000_000, true, 1));
+        assert!(timer.poll_due(1_100_000, true, 1));
+
+        let off = KeepAliveTimer::new(KeepAlivePath::Authoritative, 0, 0);
+        assert_eq!(off.mode(), None);
+        assert_eq!(
+            keep_alive_mode(KeepAlivePath::Authoritative),
+            Some(ResolverMode::Authoritative)
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use super::domain_rotation::RotationReason;
+use libc::c_int;
+use slipstream_ffi::picoquic::PICOQUIC_ERROR_KEY_ROTATION_NOT_READY;
+
+/// What became of a key update request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyUpdateOutcome {
+    Started,
+    /// picoquic is still waiting for the previous update to be acknowledged;
+    /// the update stays due and is retried on the next pass.
+    Deferred,
+    Failed(c_int),
+}
+
+/// Per-connection schedule for client-initiated QUIC key updates.
+///
+/// A new one is created for every connection, so an update that is due or
+/// deferred when the connection closes is dropped with it instead of firing
+/// on the next connection before its handshake is done. The byte and time
+/// budgets start over once the new connection is ready.
+pub(crate) struct KeyUpdate {
+    interval_us: u64,
+    bytes_limit: u64,
+    since: Option<u64>,
+    start_bytes: u64,
+    requested: u64,
+    deferred: u64,
+    completed: u64,
+}
+
+impl KeyUpdate {
+    /// Zero interval and byte limits disable the respective trigger.
+    pub(crate) fn new(interval_ms: u64, bytes_limit: u64) -> Self {
+        Self {
+            interval_us: interval_ms.saturating_mul(1_000),
+            bytes_limit,
+            since: None,
+            start_bytes: 0,
+            requested: 0,
+            deferred: 0,
+            completed: 0,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.interval_us > 0 || self.bytes_limit > 0
+    }
+
+    /// `sent_bytes` is the connection's running total of tunnel bytes sent.
+    /// The first check after the connection is ready only arms the schedule.
+    pub(crate) fn due(&mut self, now: u64, sent_bytes: u64) -> Option<RotationReason> {
+        if !self.enabled() {
+            return None;
+        }
+        let Some(since) = self.since else {
+            self.since = Some(now);
+            self.start_bytes = sent_bytes;
+            return None;
+        };
+        if self.bytes_limit > 0 && sent_bytes.saturating_sub(self.start_bytes) >= self.bytes_limit {
+            Some(RotationReason::Bytes)
+        } else if self.interval_us > 0 && now.saturating_sub(since) >= self.interval_us {
+            Some(RotationReason::Interval)
+        } else {
+            None
+        }
+    }
+
+    /// Record the result of `picoquic_start_key_rotation`. Anything but a
+    /// deferral restarts both budgets, so a failing call is not retried on
+    /// every pass.
+    pub(crate) fn record(&mut self, now: u64, sent_bytes: u64, ret: c_int) -> KeyUpdateOutcome {
+        let outcome = match ret {
+            0 => {
+                self.requested = self.requested.saturating_add(1);
+                KeyUpdateOutcome::Started
+            }
+            PICOQUIC_ERROR_KEY_ROTATION_NOT_READY => {
+                self.deferred = self.deferred.saturating_add(1);
+                return KeyUpdateOutcome::Deferred;
+            }
+            err => KeyUpdateOutcome::Failed(err),
+        };
+        self.since = Some(now);
+        self.start_bytes = sent_bytes;
+        outcome
+    }
+
+    /// Take picoquic's count of key phase changes on this connection and
+    /// return how many are new since the last call. Updates started by the
+    /// server count as well.
+    pub(crate) fn observe(&mut self, completed: u64) -> u64 {
+        let new = completed.saturating_sub(self.completed);
+        self.completed = self.completed.max(completed);
+        new
+    }
+
+    pub(crate) fn requested(&self) -> u64 {
+        self.requested
+    }
+
+    pub(crate) fn deferred(&self) -> u64 {
+        self.deferred
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyUpdate, KeyUpdateOutcome};
+    use crate::runtime::domain_rotation::RotationReason;
+    use slipstream_ffi::picoquic::PICOQUIC_ERROR_KEY_ROTATION_NOT_READY;
+
+    #[test]
+    fn byte_and_time_budgets_trigger_updates() {
+        let mut update = KeyUpdate::new(10, 1_000);
+        assert_eq!(update.due(5_000, 200), None, "first check arms");
+        assert_eq!(update.due(6_000, 1_199), None);
+        assert_eq!(update.due(6_000, 1_200), Some(RotationReason::Bytes));
+        assert_eq!(update.record(6_000, 1_200, 0), KeyUpdateOutcome::Started);
+        assert_eq!(update.due(15_999, 1_300), None);
+        assert_eq!(update.due(16_000, 1_300), Some(RotationReason::Interval));
+        assert_eq!(update.requested(), 1);
+    }
+
+    #[test]
+    fn a_deferred_update_stays_due_until_picoquic_takes_it() {
+        let mut update = KeyUpdate::new(0, 100);
+        update.due(0, 0);
+        assert!(update.due(1, 100).is_some());
+        assert_eq!(
+            update.record(1, 100, PICOQUIC_ERROR_KEY_ROTATION_NOT_READY),
+            KeyUpdateOutcome::Deferred
+        );
+        assert!(update.due(2, 100).is_some());
+        assert_eq!(update.record(2, 100, 0), KeyUpdateOutcome::Started);
+        assert_eq!(update.due(3, 150), None);
+        assert_eq!((update.requested(), update.deferred()), (1, 1));
+
+        // Other errors restart the budget instead of retrying every pass.
+        assert!(update.due(4, 200).is_some());
+        assert_eq!(update.record(4, 200, -1), KeyUpdateOutcome::Failed(-1));
+        assert_eq!(update.due(5, 250), None);
+    }
+
+    #[test]
+    fn observe_reports_only_new_key_phase_changes() {
+        let mut update = KeyUpdate::new(0, 0);
+        assert!(!update.enabled());
+        assert_eq!(update.due(u64::MAX, u64::MAX), None);
+        // Server-initiated updates are counted with the triggers disabled.
+        assert_eq!(update.observe(0), 0);
+        assert_eq!(update.observe(2), 2);
+        assert_eq!(update.observe(2), 0);
+        assert_eq!(update.observe(3), 1);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::error::ClientError;
+use crate::runtime::setup::{bind_udp_socket, map_io};
+use slipstream_core::net::is_transient_udp_error;
+use slipstream_core::normalize_dual_stack_addr;
+use slipstream_dns::{
+    build_qname_with_label, encode_query_with_options, max_payload_len_for_domain,
+    parse_tunnel_response, QueryOptions, QueryParams, Rcode, CLASS_IN, RR_TXT,
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::time::{timeout_at, Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Smallest payload a probe tries; a resolver that cannot carry even this
+/// leaves the MTU to the static estimate.
+const MTU_PROBE_FLOOR: usize = 64;
+/// The search stops once its step would be smaller than this.
+const MTU_PROBE_MIN_STEP: usize = 8;
+/// Queries sent per size and resolver.
+const MTU_PROBE_ATTEMPTS: usize = 3;
+/// Answers out of [`MTU_PROBE_ATTEMPTS`] for a size to count as reliable.
+const MTU_PROBE_REQUIRED: usize = 2;
+const MTU_PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+const MTU_PROBE_RECV_BUF: usize = 4096;
+
+/// Search for the largest query payload a resolver reliably carries.
+///
+/// Sizes go up from `floor` in steps of a quarter of the range. A size that
+/// fails becomes the new ceiling and halves the step, so the search settles
+/// within [`MTU_PROBE_MIN_STEP`] bytes of the largest size that passed.
+#[derive(Debug)]
+pub(crate) struct MtuSearch {
+    good: Option<usize>,
+    floor: usize,
+    ceiling: usize,
+    step: usize,
+}
+
+impl MtuSearch {
+    pub(crate) fn new(floor: usize, ceiling: usize) -> Self {
+        let floor = floor.min(ceiling);
+        Self {
+            good: None,
+            floor,
+            ceiling,
+            step: ((ceiling - floor) / 4).max(MTU_PROBE_MIN_STEP),
+        }
+    }
+
+    /// The next payload size to probe, or `None` once the search is done.
+    pub(crate) fn next_size(&self) -> Option<usize> {
+        match self.good {
+            None if self.floor <= self.ceiling => Some(self.floor),
+            None => None,
+            Some(good) if good >= self.ceiling || self.step < MTU_PROBE_MIN_STEP => None,
+            Some(good) => Some((good + self.step).min(self.ceiling)),
+        }
+    }
+
+    pub(crate) fn record(&mut self, size: usize, passed: bool) {
+        if passed {
+            self.good = Some(size);
+            return;
+        }
+        self.ceiling = size.saturating_sub(1);
+        if self.ceiling < self.floor {
+            // Nothing at or above the floor passed; make `next_size` stop.
+            self.floor = usize::MAX;
+        }
+        if self.good.is_some() {
+            self.step /= 2;
+        }
+    }
+
+    /// Largest size that passed, if any did.
+    pub(crate) fn result(&self) -> Option<usize> {
+        self.good
+    }
+}
+
+/// Probe each resolver for the largest query it answers reliably and
+/// return the smallest of those, or `static_mtu` when probing got no
+/// answers at all. Probes are tunnel queries whose payload is random bytes
+/// with the first byte cleared, which the server hands to picoquic and
+/// answers like any other query.
+pub(crate) async fn probe_mtu(
+    resolvers: &[SocketAddr],
+    domain: &str,
+    service_label: Option<&str>,
+    query_options: &QueryOptions,
+    static_mtu: u32,
+) -> Result<u32, ClientError> {
+    let suffix = match service_label {
+        Some(label) => format!("{}.{}", label, domain),
+        None => domain.to_string(),
+    };
+    let ceiling =
+        max_payload_len_for_domain(&suffix).map_err(|err| ClientError::new(err.to_string()))?;
+    let udp = bind_udp_socket().await?;
+    let mut searches: Vec<MtuSearch> = resolvers
+        .iter()
+        .map(|_| MtuSearch::new(MTU_PROBE_FLOOR, ceiling))
+        .collect();
+    let mut dns_id = first_probe_id();
+    let mut buf = vec![0u8; MTU_PROBE_RECV_BUF];
+    loop {
+        // One round probes the next size of every unfinished search.
+        let mut outstanding: HashMap<(SocketAddr, u16), usize> = HashMap::new();
+        let mut answered = vec![0usize; resolvers.len()];
+        let mut sizes = vec![None; resolvers.len()];
+        for (index, (resolver, search)) in resolvers.iter().zip(&searches).enumerate() {
+            let Some(size) = search.next_size() else {
+                continue;
+            };
+            sizes[index] = Some(size);
+            for _ in 0..MTU_PROBE_ATTEMPTS {
+                let id = dns_id;
+                dns_id = dns_id.wrapping_add(1);
+                let packet = probe_query(id, size, service_label, domain, query_options)?;
+                let dest = normalize_dual_stack_addr(*resolver);
+                match udp.send_to(&packet, dest).await {
+                    Ok(_) => {
+                        outstanding.insert((dest, id), index);
+                    }
+                    Err(err) if is_transient_udp_error(&err) => {}
+                    Err(err) => return Err(map_io(err)),
+                }
+            }
+        }
+        if sizes.iter().all(Option::is_none) {
+            break;
+        }
+
+        let deadline = Instant::now() + MTU_PROBE_TIMEOUT;
+        while !outstanding.is_empty() {
+            let (size, peer) = match timeout_at(deadline, udp.recv_from(&mut buf)).await {
+                Ok(Ok(received)) => received,
+                Ok(Err(err)) if is_transient_udp_error(&err) => continue,
+                Ok(Err(err)) => return Err(map_io(err)),
+                Err(_) => break,
+            };
+            let Ok(response) = parse_tunnel_response(&buf[..size]) else {
+                continue;
+            };
+            let key = (normalize_dual_stack_addr(peer), response.id);
+            if let Some(index) = outstanding.remove(&key) {
+                if response.rcode == Some(Rcode::Ok) && !response.truncated {
+                    answered[index] += 1;
+                }
+            }
+        }
+
+        for (index, size) in sizes.iter().enumerate() {
+            if let Some(size) = size {
+                let passed = answered[index] >= MTU_PROBE_REQUIRED;
+                debug!(
+                    "MTU probe via {}: {} bytes {} ({}/{} answered)",
+                    resolvers[index],
+                    size,
+                    if passed { "passed" } else { "failed" },
+                    answered[index],
+                    MTU_PROBE_ATTEMPTS
+                );
+                searches[index].record(*size, passed);
+            }
+        }
+    }
+
+    let mut probed: Option<usize> = None;
+    for (resolver, search) in resolvers.iter().zip(&searches) {
+        match search.result() {
+            Some(size) => {
+                info!("MTU probe via {} settled on {} bytes", resolver, size);
+                probed = Some(probed.map_or(size, |current| current.min(size)));
+            }
+            None => warn!(
+                "MTU probe via {} got no reliable answers; leaving it out",
+                resolver
+            ),
+        }
+    }
+    match probed {
+        Some(size) => {
+            info!(
+                "Using probed MTU of {} bytes (static estimate {})",
+                size, static_mtu
+            );
+            Ok(size as u32)
+        }
+        None => {
+            warn!(
+                "MTU probe got no answers; using the static estimate of {} bytes",
+                static_mtu
+            );
+            Ok(static_mtu)
+        }
+    }
+}
+
+fn probe_query(
+    id: u16,
+    size: usize,
+    service_label: Option<&str>,
+    domain: &str,
+    query_options: &QueryOptions,
+) -> Result<Vec<u8>, ClientError> {
+    // Random bytes keep resolvers from answering repeats out of cache.
+    let mut payload = vec![0u8; size];
+    openssl::rand::rand_bytes(&mut payload)
+        .map_err(|err| ClientError::new(format!("MTU probe payload: {}", err)))?;
+    payload[0] = 0;
+    let qname = build_qname_with_label(&payload, service_label, domain)
+        .map_err(|err| ClientError::new(err.to_string()))?;
+    encode_query_with_options(
+        &QueryParams {
+            id,
+            qname: &qname,
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+            rd: true,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        },
+        query_options,
+    )
+    .map_err(|err| ClientError::new(err.to_string()))
+}
+
+fn first_probe_id() -> u16 {
+    let mut bytes = [0u8; 2];
+    match openssl::rand::rand_bytes(&mut bytes) {
+        Ok(()) => u16::from_be_bytes(bytes),
+        Err(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MtuSearch, MTU_PROBE_MIN_STEP};
+
+    fn run(search: &mut MtuSearch, limit: usize) -> Vec<usize> {
+        let mut probed = Vec::new();
+        while let Some(size) = search.next_size() {
+            probed.push(size);
+            search.record(size, size <= limit);
+        }
+        probed
+    }
+
+    #[test]
+    fn climbs_to_the_ceiling_when_everything_passes() {
+        let mut search = MtuSearch::new(64, 148);
+        assert_eq!(run(&mut search, usize::MAX), vec![64, 85, 106, 127, 148]);
+        assert_eq!(search.result(), Some(148));
+    }
+
+    #[test]
+    fn settles_close_below_the_first_failing_size() {
+        let mut search = MtuSearch::new(64, 148);
+        let probed = run(&mut search, 120);
+        let result = search.result().expect("floor passed");
+        assert!(result <= 120);
+        assert!(120 - result < MTU_PROBE_MIN_STEP, "{:?}", probed);
+        // No size is probed twice in a row.
+        assert!(probed.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn failing_floor_ends_the_search_without_a_result() {
+        let mut search = MtuSearch::new(64, 148);
+        assert_eq!(run(&mut search, 10), vec![64]);
+        assert_eq!(search.result(), None);
+
+        // A ceiling below the floor probes the ceiling itself.
+        let mut short = MtuSearch::new(64, 40);
+        assert_eq!(run(&mut short, usize::MAX), vec![40]);
+        assert_eq!(short.result(), Some(40));
+    }
+}
@@ -3,6 +3,7 @@ use crate::dns::{
     sockaddr_storage_to_socket_addr, ResolverState,
 };
 use crate::error::ClientError;
+use crate::pacing::PacingParams;
 use crate::streams::{ClientState, PathEvent};
 use slipstream_core::normalize_dual_stack_addr;
 use slipstream_ffi::picoquic::{
@@ -13,8 +14,6 @@ use slipstream_ffi::picoquic::{
 use slipstream_ffi::ResolverMode;
 use std::net::SocketAddr;
 
-const AUTHORITATIVE_LOOP_MULTIPLIER: usize = 2;
-
 pub(crate) fn apply_path_mode(
     cnx: *mut picoquic_cnx_t,
     resolver: &mut ResolverState,
@@ -47,15 +46,27 @@ pub(crate) fn fetch_path_quality(
     quality
 }
 
+/// Bytes in flight summed over every active resolver path.
+pub(crate) fn bytes_in_transit_total(
+    cnx: *mut picoquic_cnx_t,
+    resolvers: &mut [ResolverState],
+) -> u64 {
+    let mut total = 0u64;
+    for resolver in resolvers.iter_mut() {
+        if !refresh_resolver_path(cnx, resolver) {
+            continue;
+        }
+        total = total.saturating_add(fetch_path_quality(cnx, resolver).bytes_in_transit);
+    }
+    total
+}
+
 pub(crate) fn drain_path_events(
     cnx: *mut picoquic_cnx_t,
     resolvers: &mut [ResolverState],
-    state_ptr: *mut ClientState,
+    state: &mut ClientState,
 ) {
-    if state_ptr.is_null() {
-        return;
-    }
-    let events = unsafe { (*state_ptr).take_path_events() };
+    let events = state.take_path_events();
     if events.is_empty() {
         return;
     }
@@ -83,6 +94,17 @@ pub(crate) fn drain_path_events(
             }
         }
     }
+    sync_stream_paths(resolvers, state);
+}
+
+/// Hand the added resolver paths to `state` for stream pinning and logs.
+pub(crate) fn sync_stream_paths(resolvers: &[ResolverState], state: &mut ClientState) {
+    state.sync_stream_paths(
+        resolvers
+            .iter()
+            .filter(|resolver| resolver.added)
+            .filter_map(|resolver| Some((resolver.unique_path_id?, resolver.addr))),
+    );
 }
 
 fn path_peer_addr(cnx: *mut picoquic_cnx_t, unique_path_id: u64) -> Option<SocketAddr> {
@@ -94,20 +116,24 @@ fn path_peer_addr(cnx: *mut picoquic_cnx_t, unique_path_id: u64) -> Option<Socke
     sockaddr_storage_to_socket_addr(&storage).ok()
 }
 
-pub(crate) fn loop_burst_total(resolvers: &[ResolverState], base: usize) -> usize {
+pub(crate) fn loop_burst_total(
+    resolvers: &[ResolverState],
+    base: usize,
+    params: PacingParams,
+) -> usize {
     resolvers.iter().fold(0usize, |acc, resolver| {
-        acc.saturating_add(base.saturating_mul(path_loop_multiplier(resolver.mode)))
+        acc.saturating_add(base.saturating_mul(path_loop_multiplier(resolver.mode, params)))
     })
 }
 
-pub(crate) fn path_poll_burst_max(resolver: &ResolverState) -> usize {
-    PICOQUIC_PACKET_LOOP_SEND_MAX.saturating_mul(path_loop_multiplier(resolver.mode))
+pub(crate) fn path_poll_burst_max(resolver: &ResolverState, params: PacingParams) -> usize {
+    PICOQUIC_PACKET_LOOP_SEND_MAX.saturating_mul(path_loop_multiplier(resolver.mode, params))
 }
 
-fn path_loop_multiplier(mode: ResolverMode) -> usize {
+fn path_loop_multiplier(mode: ResolverMode, params: PacingParams) -> usize {
     match mode {
-        ResolverMode::Authoritative => AUTHORITATIVE_LOOP_MULTIPLIER,
-        ResolverMode::Recursive => 1,
+        ResolverMode::Authoritative => params.authoritative_burst_multiplier,
+        ResolverMode::Recursive => params.recursive_burst_multiplier,
     }
 }
 
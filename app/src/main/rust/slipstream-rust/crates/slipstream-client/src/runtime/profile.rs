@@ -0,0 +1,95 @@
+use super::{DNS_POLL_SLICE_US, DNS_WAKE_DELAY_MAX_US};
+use slipstream_ffi::Profile;
+
+/// Queries per sendmmsg call under [`Profile::Bulk`] when `--send-batch`
+/// is left at 0.
+const BULK_SEND_BATCH: usize = 16;
+
+/// The loop settings a [`Profile`] picks as a set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProfileTunables {
+    /// Longest sleep while there is work; a shorter slice notices answers
+    /// and local data sooner at the cost of more wakeups.
+    pub(crate) poll_slice_us: u64,
+    /// Cap on picoquic's next wake delay, which bounds sleeps while idle.
+    pub(crate) wake_delay_max_us: i64,
+    /// Authoritative polls wanted each pass whatever pacing and server
+    /// demand ask for, so an answer can always come back at once.
+    pub(crate) demand_poll_floor: usize,
+    /// Queries per sendmmsg call; 0 or 1 sends each on its own.
+    pub(crate) send_batch_size: usize,
+}
+
+/// `send_batch_size` is the configured `--send-batch`.
+pub(crate) fn profile_tunables(profile: Profile, send_batch_size: usize) -> ProfileTunables {
+    let balanced = ProfileTunables {
+        poll_slice_us: DNS_POLL_SLICE_US,
+        wake_delay_max_us: DNS_WAKE_DELAY_MAX_US,
+        demand_poll_floor: 0,
+        send_batch_size,
+    };
+    match profile {
+        Profile::Balanced => balanced,
+        // Every keystroke goes out in its own query as soon as it is read,
+        // and a poll always waits at the server for the echo.
+        Profile::Interactive => ProfileTunables {
+            poll_slice_us: DNS_POLL_SLICE_US / 5,
+            wake_delay_max_us: 1_000_000,
+            demand_poll_floor: 1,
+            send_batch_size: 0,
+        },
+        Profile::Bulk => ProfileTunables {
+            poll_slice_us: DNS_POLL_SLICE_US * 2,
+            send_batch_size: if send_batch_size == 0 {
+                BULK_SEND_BATCH
+            } else {
+                send_batch_size
+            },
+            ..balanced
+        },
+    }
+}
+
+pub(crate) fn profile_message(profile: Profile, tunables: &ProfileTunables) -> String {
+    format!(
+        "Client profile {:?}: poll_slice_ms={} wake_delay_max_ms={} demand_poll_floor={} send_batch={}",
+        profile,
+        tunables.poll_slice_us / 1_000,
+        tunables.wake_delay_max_us / 1_000,
+        tunables.demand_poll_floor,
+        tunables.send_batch_size
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{profile_message, profile_tunables, BULK_SEND_BATCH};
+    use slipstream_ffi::Profile;
+
+    #[test]
+    fn balanced_keeps_the_defaults_and_the_others_move_apart() {
+        let balanced = profile_tunables(Profile::Balanced, 4);
+        assert_eq!(balanced.poll_slice_us, 50_000);
+        assert_eq!(balanced.wake_delay_max_us, 10_000_000);
+        assert_eq!(balanced.demand_poll_floor, 0);
+        assert_eq!(balanced.send_batch_size, 4);
+
+        let interactive = profile_tunables(Profile::Interactive, 4);
+        assert!(interactive.poll_slice_us < balanced.poll_slice_us);
+        assert!(interactive.wake_delay_max_us < balanced.wake_delay_max_us);
+        assert!(interactive.demand_poll_floor > balanced.demand_poll_floor);
+        assert_eq!(interactive.send_batch_size, 0);
+
+        let bulk = profile_tunables(Profile::Bulk, 0);
+        assert!(bulk.poll_slice_us > balanced.poll_slice_us);
+        assert_eq!(bulk.wake_delay_max_us, balanced.wake_delay_max_us);
+        assert_eq!(bulk.demand_poll_floor, 0);
+        assert_eq!(bulk.send_batch_size, BULK_SEND_BATCH);
+        assert_eq!(profile_tunables(Profile::Bulk, 64).send_batch_size, 64);
+
+        assert_eq!(
+            profile_message(Profile::Interactive, &interactive),
+            "Client profile Interactive: poll_slice_ms=10 wake_delay_max_ms=1000 demand_poll_floor=1 send_batch=0"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::error::ClientError;
+use slipstream_dns::{build_qname_with_label, max_payload_len_for_domain};
+use tracing::warn;
+
+/// Keeps a QUIC packet too long for one query name from ending the loop.
+///
+/// Packets are sized by [`compute_mtu`](super::setup::compute_mtu) for the
+/// longest domain, so this only fires when picoquic overshoots the path MTU
+/// (or a domain turns out longer than the one measured). Such a packet is
+/// dropped, since QUIC retransmits whatever it carried, and the MTU is
+/// lowered to what fits so the next attempt goes out.
+#[derive(Debug)]
+pub(crate) struct QnameGuard {
+    mtu: u32,
+    dropped: u64,
+    /// Set when a drop lowered `mtu`, until the loop applies it.
+    clamped: bool,
+}
+
+impl QnameGuard {
+    pub(crate) fn new(mtu: u32) -> Self {
+        Self {
+            mtu,
+            dropped: 0,
+            clamped: false,
+        }
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The query name carrying `packet`, or `None` when the packet was
+    /// dropped for not fitting under `domain`.
+    pub(crate) fn qname(
+        &mut self,
+        packet: &[u8],
+        service_label: Option<&str>,
+        domain: &str,
+    ) -> Result<Option<String>, ClientError> {
+        let err = match build_qname_with_label(packet, service_label, domain) {
+            Ok(qname) => return Ok(Some(qname)),
+            Err(err) => err,
+        };
+        let domain = domain.trim_end_matches('.');
+        let full_domain = match service_label {
+            Some(label) => format!("{}.{}", label, domain),
+            None => domain.to_string(),
+        };
+        let max_payload = match max_payload_len_for_domain(&full_domain) {
+            Ok(max_payload) if packet.len() > max_payload => max_payload,
+            _ => return Err(ClientError::new(err.to_string())),
+        };
+        self.dropped = self.dropped.saturating_add(1);
+        let max_payload = u32::try_from(max_payload).unwrap_or(u32::MAX);
+        if max_payload < self.mtu {
+            // Each clamp lowers the MTU for good, so this logs at most a
+            // handful of times per run.
+            warn!(
+                "Dropping a {}-byte QUIC packet that does not fit in a query name for {} (max_payload={}); lowering the MTU from {} to {}",
+                packet.len(),
+                full_domain,
+                max_payload,
+                self.mtu,
+                max_payload
+            );
+            self.mtu = max_payload;
+            self.clamped = true;
+        }
+        Ok(None)
+    }
+
+    /// The lowered MTU, once, after a drop lowered it.
+    pub(crate) fn take_clamp(&mut self) -> Option<u32> {
+        std::mem::take(&mut self.clamped).then_some(self.mtu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QnameGuard;
+    use crate::runtime::setup::compute_mtu;
+    use slipstream_dns::max_payload_len_for_domain;
+
+    const DOMAIN: &str = "t.example.com";
+
+    #[test]
+    fn oversize_packet_is_dropped_and_the_mtu_clamped() {
+        let max_payload = max_payload_len_for_domain(&format!("svc.{}", DOMAIN)).unwrap();
+        let mtu = compute_mtu(DOMAIN.len(), Some("svc")).unwrap() + 64;
+        let mut guard = QnameGuard::new(mtu);
+        assert_eq!(guard.take_clamp(), None);
+
+        let oversize = vec![0xa5; max_payload + 1];
+        assert_eq!(guard.qname(&oversize, Some("svc"), DOMAIN).unwrap(), None);
+        assert_eq!(guard.dropped(), 1);
+        assert_eq!(guard.take_clamp(), Some(max_payload as u32));
+        assert_eq!(guard.take_clamp(), None);
+
+        // A second oversize packet is dropped without clamping again, and
+        // packets at the new MTU still go out.
+        assert_eq!(guard.qname(&oversize, Some("svc"), DOMAIN).unwrap(), None);
+        assert_eq!(guard.dropped(), 2);
+        assert_eq!(guard.take_clamp(), None);
+        let fits = vec![0xa5; max_payload];
+        let qname = guard.qname(&fits, Some("svc"), DOMAIN).unwrap().unwrap();
+        assert!(qname.ends_with(".svc.t.example.com."), "{}", qname);
+        assert_eq!(guard.dropped(), 2);
+    }
+
+    #[test]
+    fn other_qname_errors_still_fail() {
+        let mut guard = QnameGuard::new(100);
+        assert!(guard.qname(&[1, 2, 3], None, ".").is_err());
+        assert_eq!(guard.dropped(), 0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+/// Exponential reconnect backoff between a configured floor and cap
+/// (`--reconnect-delay-min` / `--reconnect-delay-max`).
+#[derive(Debug)]
+pub(crate) struct ReconnectBackoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl ReconnectBackoff {
+    /// Both bounds must be non-zero and `min_ms` may not exceed `max_ms`.
+    pub(crate) fn new(min_ms: u64, max_ms: u64) -> Result<Self, String> {
+        if min_ms == 0 || max_ms == 0 {
+            return Err(format!(
+                "Reconnect delays must be positive (min={}ms max={}ms)",
+                min_ms, max_ms
+            ));
+        }
+        if min_ms > max_ms {
+            return Err(format!(
+                "Minimum reconnect delay {}ms exceeds the maximum {}ms",
+                min_ms, max_ms
+            ));
+        }
+        let min = Duration::from_millis(min_ms);
+        Ok(Self {
+            min,
+            max: Duration::from_millis(max_ms),
+            current: min,
+        })
+    }
+
+    pub(crate) fn delay(&self) -> Duration {
+        self.current
+    }
+
+    /// Back to the floor once a connection became ready.
+    pub(crate) fn reset(&mut self) {
+        self.current = self.min;
+    }
+
+    /// Double the delay for the next attempt, up to the cap.
+    pub(crate) fn advance(&mut self) {
+        self.current = self.current.saturating_mul(2).min(self.max);
+    }
+}
+
+/// Sleep before the next reconnect attempt.
+///
+/// The backoff `delay` counts from when the connection closed. The minimum
+/// interval counts from when the previous attempt started, so connections
+/// that die right after they are created cannot reconnect faster than
+/// `min_interval`. Returns the sleep and whether the minimum interval
+/// lengthened it.
+pub(crate) fn reconnect_sleep(
+    delay: Duration,
+    min_interval: Duration,
+    since_last_attempt: Duration,
+) -> (Duration, bool) {
+    let gap = min_interval.saturating_sub(since_last_attempt);
+    if gap > delay {
+        (gap, true)
+    } else {
+        (delay, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reconnect_sleep, ReconnectBackoff};
+    use std::time::Duration;
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    #[test]
+    fn backoff_wins_for_long_lived_connections() {
+        assert_eq!(
+            reconnect_sleep(ms(250), ms(1_000), ms(60_000)),
+            (ms(250), false)
+        );
+        assert_eq!(reconnect_sleep(ms(250), ms(250), ms(0)), (ms(250), false));
+    }
+
+    #[test]
+    fn fast_reconnects_are_throttled_to_minimum_gap() {
+        assert_eq!(
+            reconnect_sleep(ms(250), ms(2_000), ms(300)),
+            (ms(1_700), true)
+        );
+        assert_eq!(
+            reconnect_sleep(ms(4_000), ms(2_000), ms(300)),
+            (ms(4_000), false)
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_to_cap_and_resets_to_floor() {
+        let mut backoff = ReconnectBackoff::new(50, 300).unwrap();
+        let mut delays = Vec::new();
+        for _ in 0..5 {
+            delays.push(backoff.delay());
+            backoff.advance();
+        }
+        assert_eq!(delays, vec![ms(50), ms(100), ms(200), ms(300), ms(300)]);
+        backoff.reset();
+        assert_eq!(backoff.delay(), ms(50));
+
+        assert!(ReconnectBackoff::new(0, 300).is_err());
+        assert!(ReconnectBackoff::new(250, 0).is_err());
+        assert!(ReconnectBackoff::new(500, 300).is_err());
+        assert!(ReconnectBackoff::new(300, 300).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use super::path::fetch_path_quality;
+use crate::dns::{refresh_resolver_path, ResolverState};
+use slipstream_ffi::picoquic::{picoquic_cnx_t, picoquic_path_quality_t};
+use std::net::SocketAddr;
+
+/// Smoothed RTT of one resolver path and the packets sent on it, which
+/// weigh the path in the aggregate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PathRtt {
+    pub(crate) addr: SocketAddr,
+    pub(crate) smoothed_us: u64,
+    pub(crate) sent: u64,
+}
+
+/// The path's smoothed RTT, or None before its first sample; until then
+/// picoquic reports its initial guess, which is not a measurement.
+fn sampled_rtt(quality: &picoquic_path_quality_t) -> Option<u64> {
+    (quality.rtt_sample > 0 && quality.rtt > 0).then_some(quality.rtt)
+}
+
+/// RTTs of the resolver paths added to the connection that have been
+/// measured, in resolver order.
+pub(crate) fn collect_path_rtts(
+    cnx: *mut picoquic_cnx_t,
+    resolvers: &mut [ResolverState],
+) -> Vec<PathRtt> {
+    let mut rtts = Vec::new();
+    for resolver in resolvers.iter_mut() {
+        if resolver.unique_path_id.is_none() || !refresh_resolver_path(cnx, resolver) {
+            continue;
+        }
+        let quality = fetch_path_quality(cnx, resolver);
+        if let Some(smoothed_us) = sampled_rtt(&quality) {
+            rtts.push(PathRtt {
+                addr: resolver.addr,
+                smoothed_us,
+                sent: quality.sent,
+            });
+        }
+    }
+    rtts
+}
+
+/// One RTT for the tunnel: the paths' smoothed RTTs weighted by the packets
+/// sent on each, so a rarely used slow path does not dominate. Paths
+/// without traffic yet count once. None before any path has a sample.
+pub(crate) fn aggregate_rtt(paths: &[PathRtt]) -> Option<u64> {
+    let (weighted, weight) = paths.iter().fold((0u128, 0u128), |(sum, total), path| {
+        let weight = u128::from(path.sent.max(1));
+        (sum + u128::from(path.smoothed_us) * weight, total + weight)
+    });
+    (weight > 0).then(|| u64::try_from(weighted / weight).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate_rtt, sampled_rtt, PathRtt};
+    use slipstream_ffi::picoquic::picoquic_path_quality_t;
+    use std::net::SocketAddr;
+
+    fn path(port: u16, smoothed_us: u64, sent: u64) -> PathRtt {
+        PathRtt {
+            addr: SocketAddr::from(([192, 0, 2, 1], port)),
+            smoothed_us,
+            sent,
+        }
+    }
+
+    #[test]
+    fn unsampled_paths_have_no_rtt() {
+        let mut quality = picoquic_path_quality_t {
+            rtt: 250_000,
+            ..Default::default()
+        };
+        assert_eq!(sampled_rtt(&quality), None);
+        quality.rtt_sample = 80_000;
+        quality.rtt = 90_000;
+        assert_eq!(sampled_rtt(&quality), Some(90_000));
+        assert_eq!(aggregate_rtt(&[]), None);
+    }
+
+    #[test]
+    fn aggregate_weighs_paths_by_their_traffic() {
+        assert_eq!(aggregate_rtt(&[path(53, 100_000, 0)]), Some(100_000));
+        // Three of four packets went over the fast path.
+        assert_eq!(
+            aggregate_rtt(&[path(53, 100_000, 300), path(54, 500_000, 100)]),
+            Some(200_000)
+        );
+        assert_eq!(
+            aggregate_rtt(&[path(53, 100_000, 0), path(54, 300_000, 0)]),
+            Some(200_000)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Optional batching of outbound DNS queries into one `sendmmsg` call
+//! (`--send-batch`).
+//!
+//! Queries are collected while picoquic prepares packets and flushed when the
+//! batch is full or the send loop ends. Where `sendmmsg` is missing, or the
+//! kernel rejects it, the batch falls back to one `send_to` per query.
+
+use super::path::find_resolver_by_addr_mut;
+use crate::dns::{retry_query, send_query, ResolverState, SendOutcome};
+use slipstream_core::net::{is_route_change_error, is_transient_udp_error};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket as TokioUdpSocket;
+use tracing::warn;
+
+pub(crate) struct SendBatch {
+    max_packets: usize,
+    packets: Vec<(Vec<u8>, SocketAddr)>,
+    sendmmsg_supported: bool,
+}
+
+impl SendBatch {
+    /// A `max_packets` of 0 or 1 sends every query on its own.
+    pub(crate) fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            packets: Vec::with_capacity(max_packets),
+            sendmmsg_supported: cfg!(any(target_os = "linux", target_os = "android")),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.max_packets > 1
+    }
+
+    /// Queue a query; returns true once the batch is full.
+    pub(crate) fn push(&mut self, packet: Vec<u8>, dest: SocketAddr) -> bool {
+        self.packets.push((packet, dest));
+        self.packets.len() >= self.max_packets
+    }
+
+    /// Send everything queued. Errors are handled as with unbatched sends:
+    /// a route change error retries the affected query once, other
+    /// transient errors drop it, and the rest are returned. Retries and
+    /// drops are counted on the resolver the query was for.
+    pub(crate) async fn flush(
+        &mut self,
+        udp: &TokioUdpSocket,
+        resolvers: &mut [ResolverState],
+    ) -> io::Result<()> {
+        if self.packets.is_empty() {
+            return Ok(());
+        }
+        let packets = std::mem::take(&mut self.packets);
+        let mut next = 0;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        while self.sendmmsg_supported && next < packets.len() {
+            match sendmmsg_async(udp, &packets[next..]).await {
+                Ok(sent) => next += sent.max(1),
+                Err(err) if is_unsupported(&err) => {
+                    warn!(
+                        "sendmmsg unavailable ({}); sending queries one at a time",
+                        err
+                    );
+                    self.sendmmsg_supported = false;
+                }
+                // The first remaining query failed; handle it like send_to would.
+                Err(err) if is_route_change_error(&err) => {
+                    let (packet, dest) = &packets[next];
+                    let outcome = retry_query(udp, packet, *dest).await?;
+                    record_outcome(resolvers, *dest, outcome);
+                    next += 1;
+                }
+                Err(err) if is_transient_udp_error(&err) => {
+                    record_outcome(resolvers, packets[next].1, SendOutcome::Dropped);
+                    next += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        for (packet, dest) in &packets[next..] {
+            let outcome = send_query(udp, packet, *dest).await?;
+            record_outcome(resolvers, *dest, outcome);
+        }
+        self.packets = packets;
+        self.packets.clear();
+        Ok(())
+    }
+}
+
+fn record_outcome(resolvers: &mut [ResolverState], dest: SocketAddr, outcome: SendOutcome) {
+    if let Some(resolver) = find_resolver_by_addr_mut(resolvers, dest) {
+        resolver.debug.record_send_outcome(outcome);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(code) if code == libc::ENOSYS || code == libc::EOPNOTSUPP
+    )
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+async fn sendmmsg_async(
+    udp: &TokioUdpSocket,
+    packets: &[(Vec<u8>, SocketAddr)],
+) -> io::Result<usize> {
+    use std::os::unix::io::AsRawFd;
+    loop {
+        udp.writable().await?;
+        match udp.try_io(tokio::io::Interest::WRITABLE, || {
+            sendmmsg_once(udp.as_raw_fd(), packets)
+        }) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn sendmmsg_once(fd: libc::c_int, packets: &[(Vec<u8>, SocketAddr)]) -> io::Result<usize> {
+    let addrs: Vec<socket2::SockAddr> = packets
+        .iter()
+        .map(|(_, dest)| socket2::SockAddr::from(*dest))
+        .collect();
+    let mut iovecs: Vec<libc::iovec> = packets
+        .iter()
+        .map(|(packet, _)| libc::iovec {
+            iov_base: packet.as_ptr() as *mut libc::c_void,
+            iov_len: packet.len(),
+        })
+        .collect();
+    let mut msgs: Vec<libc::mmsghdr> = Vec::with_capacity(packets.len());
+    for (index, addr) in addrs.iter().enumerate() {
+        // SAFETY: mmsghdr is plain-old-data; zeroing is valid.
+        let mut msg: libc::mmsghdr = unsafe { std::mem::zeroed() };
+        msg.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+        msg.msg_hdr.msg_namelen = addr.len();
+        msg.msg_hdr.msg_iov = &mut iovecs[index];
+        msg.msg_hdr.msg_iovlen = 1;
+        msgs.push(msg);
+    }
+    // SAFETY: every header points into addrs/iovecs, which outlive the call,
+    // and the iovecs borrow packet buffers that are not touched meanwhile.
+    let ret = unsafe { libc::sendmmsg(fd, msgs.as_mut_ptr(), msgs.len() as _, 0) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendBatch;
+    use std::net::SocketAddr;
+    use tokio::net::UdpSocket;
+
+    #[test]
+    fn batch_delivers_every_query_in_order() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let dest: SocketAddr = receiver.local_addr().unwrap();
+            let mut batch = SendBatch::new(4);
+            assert!(batch.enabled());
+
+            assert!(!batch.push(b"one".to_vec(), dest));
+            assert!(!batch.push(b"two".to_vec(), dest));
+            assert!(!batch.push(b"three".to_vec(), dest));
+            batch.flush(&sender, &mut []).await.unwrap();
+
+            let mut buf = [0u8; 16];
+            for expected in [&b"one"[..], b"two", b"three"] {
+                let (len, _) = receiver.recv_from(&mut buf).await.unwrap();
+                assert_eq!(&buf[..len], expected);
+            }
+            assert!(!SendBatch::new(1).enabled());
+        });
+    }
+}
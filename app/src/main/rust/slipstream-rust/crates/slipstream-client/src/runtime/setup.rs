@@ -1,12 +1,19 @@
 use crate::error::ClientError;
+use slipstream_ffi::{ClientConfig, ResolverMode};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 #[cfg(target_os = "android")]
 use std::os::unix::io::AsRawFd;
 use tokio::net::{lookup_host, TcpListener as TokioTcpListener, UdpSocket as TokioUdpSocket};
 use tracing::{info, warn};
 
-pub(crate) fn compute_mtu(domain_len: usize) -> Result<u32, ClientError> {
+/// `service_label`, when set, is one more label in every query name and
+/// so leaves less room for data.
+pub(crate) fn compute_mtu(
+    domain_len: usize,
+    service_label: Option<&str>,
+) -> Result<u32, ClientError> {
+    let domain_len = domain_len + service_label.map_or(0, |label| label.len() + 1);
     if domain_len >= 240 {
         return Err(ClientError::new(
             "Domain name is too long for DNS transport",
@@ -21,15 +28,106 @@ pub(crate) fn compute_mtu(domain_len: usize) -> Result<u32, ClientError> {
     Ok(mtu)
 }
 
+/// Option combinations that depend on the resolver list.
+pub(crate) fn check_resolver_options(config: &ClientConfig<'_>) -> Result<(), ClientError> {
+    if config.require_authoritative_path
+        && !config
+            .resolvers
+            .iter()
+            .any(|resolver| resolver.mode == ResolverMode::Authoritative)
+    {
+        return Err(ClientError::new(
+            "Requiring an authoritative path needs at least one authoritative resolver",
+        ));
+    }
+    if !config.path_callbacks && config.resolvers.len() > 1 {
+        return Err(ClientError::new(
+            "Disabling path callbacks needs a single resolver; extra resolvers are added as paths",
+        ));
+    }
+    Ok(())
+}
+
 pub(crate) async fn bind_udp_socket() -> Result<TokioUdpSocket, ClientError> {
     let bind_addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0));
     bind_udp_socket_addr(bind_addr)
 }
 
-pub(crate) async fn bind_tcp_listener(
+/// Returns the loopback host to bind instead of `host`, or `None` if `host` is
+/// already loopback. IPv6 literals map to `::1`; everything else to `127.0.0.1`.
+pub(crate) fn loopback_listen_host(host: &str) -> Option<&'static str> {
+    if host.eq_ignore_ascii_case("localhost") {
+        return None;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(addr) if addr.is_loopback() => None,
+        Ok(IpAddr::V6(_)) => Some("::1"),
+        _ => Some("127.0.0.1"),
+    }
+}
+
+/// Binds the local TCP listener, on the loopback counterpart of `host` with
+/// `loopback_only`. `::` falls back to 0.0.0.0, and `::1` under
+/// `loopback_only` to 127.0.0.1, when the IPv6 bind fails. Returns the
+/// listener and the host it is bound on.
+pub(crate) async fn bind_local_listener(
     host: &str,
     port: u16,
-) -> Result<TokioTcpListener, ClientError> {
+    loopback_only: bool,
+) -> Result<(TokioTcpListener, String), ClientError> {
+    let mut host = host;
+    if loopback_only {
+        if let Some(loopback_host) = loopback_listen_host(host) {
+            warn!(
+                "loopback_only is set; binding TCP listener on {} instead of {}",
+                loopback_host, host
+            );
+            host = loopback_host;
+        }
+    }
+    let fallback_host = if is_ipv6_unspecified(host) {
+        Some("0.0.0.0")
+    } else if loopback_only && host == "::1" {
+        Some("127.0.0.1")
+    } else {
+        None
+    };
+    let err = match bind_tcp_listener(host, port).await {
+        Ok(listener) => return Ok((listener, host.to_string())),
+        Err(err) => err,
+    };
+    let Some(fallback_host) = fallback_host else {
+        return Err(err);
+    };
+    warn!(
+        "Failed to bind TCP listener on {}:{} ({}); falling back to {}",
+        host, port, err, fallback_host
+    );
+    match bind_tcp_listener(fallback_host, port).await {
+        Ok(listener) => Ok((listener, fallback_host.to_string())),
+        Err(fallback_err) => Err(ClientError::new(format!(
+            "Failed to bind TCP listener on {}:{} ({}) or {}:{} ({})",
+            host, port, err, fallback_host, port, fallback_err
+        ))),
+    }
+}
+
+/// `host:port`, with IPv6 hosts in brackets.
+pub(crate) fn listen_label(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+fn is_ipv6_unspecified(host: &str) -> bool {
+    host.parse::<Ipv6Addr>()
+        .map(|addr| addr.is_unspecified())
+        .unwrap_or(false)
+}
+
+async fn bind_tcp_listener(host: &str, port: u16) -> Result<TokioTcpListener, ClientError> {
     let addrs: Vec<SocketAddr> = lookup_host((host, port)).await.map_err(map_io)?.collect();
     if addrs.is_empty() {
         return Err(ClientError::new(format!(
@@ -115,3 +213,41 @@ fn bind_udp_socket_addr(addr: SocketAddr) -> Result<TokioUdpSocket, ClientError>
 pub(crate) fn map_io(err: std::io::Error) -> ClientError {
     ClientError::new(err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_label_counts_against_the_mtu() {
+        let bare = compute_mtu(20, None).expect("mtu");
+        let labelled = compute_mtu(20, Some("svc1")).expect("mtu");
+        assert_eq!(labelled, compute_mtu(25, None).expect("mtu"));
+        assert!(labelled < bare);
+        assert!(compute_mtu(235, Some("svc1")).is_err());
+    }
+
+    #[tokio::test]
+    async fn a_taken_port_fails_the_listener_bind() {
+        let (held, host) = bind_local_listener("127.0.0.1", 0, false)
+            .await
+            .expect("bind");
+        assert_eq!(host, "127.0.0.1");
+        let port = held.local_addr().expect("addr").port();
+        assert!(bind_local_listener("127.0.0.1", port, false).await.is_err());
+        assert_eq!(listen_label("::1", port), format!("[::1]:{}", port));
+    }
+
+    #[test]
+    fn loopback_listen_host_overrides_non_loopback_hosts() {
+        assert_eq!(loopback_listen_host("127.0.0.1"), None);
+        assert_eq!(loopback_listen_host("127.0.0.2"), None);
+        assert_eq!(loopback_listen_host("::1"), None);
+        assert_eq!(loopback_listen_host("localhost"), None);
+        assert_eq!(loopback_listen_host("::"), Some("::1"));
+        assert_eq!(loopback_listen_host("2001:db8::1"), Some("::1"));
+        assert_eq!(loopback_listen_host("0.0.0.0"), Some("127.0.0.1"));
+        assert_eq!(loopback_listen_host("192.168.1.10"), Some("127.0.0.1"));
+        assert_eq!(loopback_listen_host("proxy.lan"), Some("127.0.0.1"));
+    }
+}
@@ -0,0 +1,100 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResetVerdict {
+    /// The connection did not end with a stateless reset.
+    NotReset,
+    /// A stateless reset below the tolerance: reconnect right away without
+    /// counting a failure or advancing the backoff.
+    Tolerated { consecutive: u32 },
+    /// Enough stateless resets in a row to handle like any other close.
+    Reconnect { consecutive: u32 },
+}
+
+/// How stateless resets are handled (`--stateless-reset-tolerance`).
+///
+/// Some networks inject stateless resets that look genuine to picoquic. The
+/// connection is gone either way, but the first `tolerance - 1` resets in a
+/// row only cost an immediate reconnect; the one that reaches the tolerance
+/// is handled like any other close. Any close that is not a stateless reset
+/// starts the count over.
+pub(crate) struct StatelessResetPolicy {
+    tolerance: u32,
+    consecutive: u32,
+    total: u64,
+}
+
+impl StatelessResetPolicy {
+    pub(crate) fn new(tolerance: u32) -> Self {
+        Self {
+            tolerance: tolerance.max(1),
+            consecutive: 0,
+            total: 0,
+        }
+    }
+
+    pub(crate) fn tolerance(&self) -> u32 {
+        self.tolerance
+    }
+
+    /// Stateless resets seen since the client started.
+    pub(crate) fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub(crate) fn observe(&mut self, stateless_reset: bool) -> ResetVerdict {
+        if !stateless_reset {
+            self.consecutive = 0;
+            return ResetVerdict::NotReset;
+        }
+        self.total = self.total.saturating_add(1);
+        self.consecutive = self.consecutive.saturating_add(1);
+        if self.consecutive < self.tolerance {
+            ResetVerdict::Tolerated {
+                consecutive: self.consecutive,
+            }
+        } else {
+            ResetVerdict::Reconnect {
+                consecutive: self.consecutive,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_tolerance_handles_every_reset() {
+        let mut policy = StatelessResetPolicy::new(0);
+        assert_eq!(policy.tolerance(), 1);
+        assert_eq!(
+            policy.observe(true),
+            ResetVerdict::Reconnect { consecutive: 1 }
+        );
+        assert_eq!(policy.observe(false), ResetVerdict::NotReset);
+        assert_eq!(policy.total(), 1);
+    }
+
+    #[test]
+    fn resets_below_tolerance_are_tolerated_until_a_different_close() {
+        let mut policy = StatelessResetPolicy::new(2);
+        assert_eq!(
+            policy.observe(true),
+            ResetVerdict::Tolerated { consecutive: 1 }
+        );
+        assert_eq!(
+            policy.observe(true),
+            ResetVerdict::Reconnect { consecutive: 2 }
+        );
+        assert_eq!(
+            policy.observe(true),
+            ResetVerdict::Reconnect { consecutive: 3 }
+        );
+        assert_eq!(policy.observe(false), ResetVerdict::NotReset);
+        assert_eq!(
+            policy.observe(true),
+            ResetVerdict::Tolerated { consecutive: 1 }
+        );
+        assert_eq!(policy.total(), 4);
+    }
+}
@@ -0,0 +1,97 @@
+use super::stream_trailers_enabled;
+use crate::pinning::pinned_cert_fingerprint;
+use slipstream_core::config_summary::ConfigSummary;
+use slipstream_ffi::{ClientConfig, ResolverMode};
+
+/// Startup summary of `config`; `listen` is the address the TCP listener
+/// actually bound and `mtu` the QUIC MTU derived from the domains.
+pub(crate) fn client_config_summary(
+    config: &ClientConfig<'_>,
+    mtu: u32,
+    listen: &str,
+) -> ConfigSummary {
+    let mut summary = ConfigSummary::new("client");
+    summary
+        .field("domain", config.domain)
+        .field("service_label", config.service_label.unwrap_or("none"))
+        .list("rotation_domains", config.rotation_domains)
+        .list(
+            "resolvers",
+            config.resolvers.iter().map(|spec| {
+                let mode = match spec.mode {
+                    ResolverMode::Recursive => "recursive",
+                    ResolverMode::Authoritative => "authoritative",
+                };
+                format!("{}:{}/udp/{}", spec.resolver.host, spec.resolver.port, mode)
+            }),
+        )
+        .field("host_overrides", config.host_overrides.len())
+        .field("mtu", mtu)
+        .field("listen", listen)
+        .field("cc", config.congestion_control.unwrap_or("mixed"))
+        .field("keep_alive_ms", config.keep_alive_interval)
+        .field("keep_alive_path", format!("{:?}", config.keep_alive_path))
+        .field("terminal_failure_repeats", config.terminal_failure_repeats)
+        .field("stream_pool", config.stream_pool_size)
+        .field("command_channel_cap", config.command_channel_capacity)
+        .field("reconnect_on_zero_credit", config.reconnect_on_zero_credit)
+        .field("max_idle_sleep_ms", config.max_idle_sleep_ms)
+        .field("heartbeat_interval_ms", config.heartbeat_interval_ms)
+        .field("profile", format!("{:?}", config.profile))
+        .field("max_local_streams", config.max_local_streams)
+        .field("closing_timeout_ms", config.closing_timeout_ms)
+        .field("ecs_opt_out", config.ecs_opt_out)
+        .field("max_streams_floor", config.max_streams_floor)
+        .field(
+            "response_size_feedback_percent",
+            config.response_size_feedback_percent,
+        );
+    match config.cert.map(pinned_cert_fingerprint) {
+        Some(Ok(digest)) => summary.fingerprint("pin_sha256", Some(&digest)),
+        Some(Err(_)) => summary.field("pin_sha256", "unreadable"),
+        None => summary.fingerprint("pin_sha256", None),
+    };
+    summary
+        .field("idle_poll_interval_ms", config.idle_poll_interval_ms)
+        .field("zero_send_reset", format!("{:?}", config.zero_send_reset))
+        .field(
+            "zero_send_stall_iterations",
+            config.zero_send_stall_iterations,
+        )
+        .field(
+            "reconnect_delay_ms",
+            format!(
+                "{}-{}",
+                config.reconnect_delay_min_ms, config.reconnect_delay_max_ms
+            ),
+        )
+        .features(&[
+            ("gso", config.gso),
+            ("loopback_only", config.loopback_only),
+            (
+                "battery_saver",
+                config.battery_saver_poll_interval_ms.is_some(),
+            ),
+            ("cid_rotation", config.cid_rotation_interval_ms > 0),
+            ("stream_path_affinity", config.stream_path_affinity),
+            (
+                "require_authoritative_path",
+                config.require_authoritative_path,
+            ),
+            ("ready_after_usable_path", config.ready_requires_usable_path),
+            ("send_batch", config.send_batch_size > 0),
+            ("debug_poll", config.debug_poll),
+            ("debug_streams", config.debug_streams),
+            ("debug_stream_checksums", config.debug_stream_checksums),
+            ("debug_stream_trailers", stream_trailers_enabled(config)),
+            ("qlog", config.qlog_dir.is_some()),
+            ("mtu_probe", config.mtu_probe),
+            ("path_callbacks", config.path_callbacks),
+            ("stop_sending_half_close", config.stop_sending_half_close),
+            (
+                "key_update",
+                config.key_update_interval_ms > 0 || config.key_update_bytes > 0,
+            ),
+        ]);
+    summary
+}
@@ -0,0 +1,134 @@
+use crate::streams::CloseReasons;
+use slipstream_ffi::picoquic::{
+    PICOQUIC_TLS_HANDSHAKE_FAILED, PICOQUIC_TRANSPORT_CRYPTO_ERROR_BASE,
+    PICOQUIC_TRANSPORT_PARAMETER_ERROR, PICOQUIC_TRANSPORT_VERSION_NEGOTIATION_ERROR,
+};
+
+/// Errors that every attempt hits again until the configuration changes: a
+/// TLS alert (a certificate pin or ALPN mismatch), a failed handshake, or
+/// transport parameters or versions the two sides do not share. Timeouts,
+/// SERVER_BUSY and internal errors can clear up and are not terminal.
+fn is_terminal(code: u64) -> bool {
+    (PICOQUIC_TRANSPORT_CRYPTO_ERROR_BASE..PICOQUIC_TRANSPORT_CRYPTO_ERROR_BASE + 0x100)
+        .contains(&code)
+        || matches!(
+            code,
+            PICOQUIC_TLS_HANDSHAKE_FAILED
+                | PICOQUIC_TRANSPORT_PARAMETER_ERROR
+                | PICOQUIC_TRANSPORT_VERSION_NEGOTIATION_ERROR
+        )
+}
+
+/// The terminal error of a close, ours first, as a log label.
+fn terminal_error(reasons: CloseReasons) -> Option<String> {
+    [("local", reasons.local), ("remote", reasons.remote)]
+        .into_iter()
+        .find(|(_, code)| is_terminal(*code))
+        .map(|(side, code)| {
+            if code & !0xff == PICOQUIC_TRANSPORT_CRYPTO_ERROR_BASE {
+                format!("{} TLS alert {} (0x{:x})", side, code & 0xff, code)
+            } else {
+                format!("{} error 0x{:x}", side, code)
+            }
+        })
+}
+
+/// Gives up on connection failures that repeat the same terminal error.
+///
+/// The generic failure limit counts every connection that never became
+/// ready and is unlimited on the CLI. A pin mismatch fails every handshake
+/// the same way, so `limit` identical terminal errors in a row end the
+/// client early with that error. Any other failure or a connection that
+/// became ready starts the count over.
+pub(crate) struct TerminalFailure {
+    limit: u32,
+    last: Option<String>,
+    repeats: u32,
+}
+
+impl TerminalFailure {
+    /// A zero `limit` never gives up.
+    pub(crate) fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            last: None,
+            repeats: 0,
+        }
+    }
+
+    /// Called after every close; `failed` is whether it counted as a
+    /// connection failure. Returns the error once it has repeated `limit`
+    /// times.
+    pub(crate) fn observe(
+        &mut self,
+        failed: bool,
+        reasons: Option<CloseReasons>,
+    ) -> Option<String> {
+        let error = reasons.filter(|_| failed).and_then(terminal_error);
+        let Some(error) = error else {
+            self.last = None;
+            self.repeats = 0;
+            return None;
+        };
+        if self.last.as_ref() == Some(&error) {
+            self.repeats = self.repeats.saturating_add(1);
+        } else {
+            self.last = Some(error.clone());
+            self.repeats = 1;
+        }
+        (self.limit > 0 && self.repeats >= self.limit).then_some(error)
+    }
+
+    pub(crate) fn repeats(&self) -> u32 {
+        self.repeats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TerminalFailure;
+    use crate::streams::CloseReasons;
+
+    fn closed(local: u64, remote: u64) -> Option<CloseReasons> {
+        Some(CloseReasons { local, remote })
+    }
+
+    #[test]
+    fn identical_tls_alerts_give_up_at_the_limit() {
+        let mut failure = TerminalFailure::new(3);
+        // Bad certificate alert from our verifier.
+        assert_eq!(failure.observe(true, closed(0x12a, 0)), None);
+        assert_eq!(failure.observe(true, closed(0x12a, 0)), None);
+        assert_eq!(
+            failure.observe(true, closed(0x12a, 0)).as_deref(),
+            Some("local TLS alert 42 (0x12a)")
+        );
+        assert_eq!(failure.repeats(), 3);
+    }
+
+    #[test]
+    fn other_closes_start_the_count_over() {
+        let mut failure = TerminalFailure::new(2);
+        assert_eq!(failure.observe(true, closed(0, 0x178)), None);
+        // A different terminal error, a transient one and a close that
+        // did not count as a failure each break the run.
+        assert_eq!(failure.observe(true, closed(0x201, 0)), None);
+        assert_eq!(failure.observe(true, closed(0x400 + 53, 0)), None);
+        assert_eq!(failure.observe(true, closed(0x201, 0)), None);
+        assert_eq!(failure.observe(false, closed(0x201, 0)), None);
+        assert_eq!(failure.observe(true, closed(0x201, 0)), None);
+        assert_eq!(failure.observe(true, None), None);
+        assert_eq!(failure.repeats(), 0);
+        assert_eq!(failure.observe(true, closed(0, 0x178)), None);
+        assert_eq!(
+            failure.observe(true, closed(0, 0x178)).as_deref(),
+            Some("remote TLS alert 120 (0x178)")
+        );
+
+        let mut never = TerminalFailure::new(0);
+        for _ in 0..10 {
+            assert_eq!(never.observe(true, closed(0x201, 0)), None);
+        }
+        assert_eq!(never.repeats(), 10);
+    }
+}
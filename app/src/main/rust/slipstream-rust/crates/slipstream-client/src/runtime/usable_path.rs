@@ -0,0 +1,137 @@
+/// Deadline for having at least one resolver path that is confirmed working.
+///
+/// Separate from the handshake: the clock only runs once the connection is
+/// ready, and restarts whenever some added path has delivered a response.
+/// Losing every usable path after that (paths deleted, re-probes failing)
+/// therefore expires the deadline instead of leaving the loop spinning.
+pub(crate) struct UsablePathDeadline {
+    timeout_us: u64,
+    last_usable_at: Option<u64>,
+}
+
+impl UsablePathDeadline {
+    /// A zero timeout disables the deadline.
+    pub(crate) fn new(timeout_ms: u64) -> Self {
+        Self {
+            timeout_us: timeout_ms.saturating_mul(1_000),
+            last_usable_at: None,
+        }
+    }
+
+    /// Returns true once the connection has gone `timeout` without a usable path.
+    pub(crate) fn observe(&mut self, now: u64, ready: bool, any_usable: bool) -> bool {
+        if self.timeout_us == 0 || !ready {
+            return false;
+        }
+        let since = self.last_usable_at.get_or_insert(now);
+        if any_usable {
+            *since = now;
+            return false;
+        }
+        now.saturating_sub(*since) >= self.timeout_us
+    }
+}
+
+/// What the loop should do about reporting the connection as ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReadyOutcome {
+    /// Not ready yet, or already reported.
+    Unchanged,
+    /// The handshake finished but no path has answered a poll yet; reported
+    /// once so the wait shows up in the logs.
+    Deferred,
+    /// Report ready now.
+    Signal,
+}
+
+/// When to report the connection as ready (`--ready-after-usable-path`).
+///
+/// By default that happens as soon as the handshake completes. When a usable
+/// path is required, the report waits until some added path has delivered a
+/// response, which means a poll made the round trip.
+pub(crate) struct ReadySignal {
+    require_usable_path: bool,
+    deferred: bool,
+    signaled: bool,
+}
+
+impl ReadySignal {
+    pub(crate) fn new(require_usable_path: bool) -> Self {
+        Self {
+            require_usable_path,
+            deferred: false,
+            signaled: false,
+        }
+    }
+
+    pub(crate) fn signaled(&self) -> bool {
+        self.signaled
+    }
+
+    pub(crate) fn observe(&mut self, ready: bool, any_usable: bool) -> ReadyOutcome {
+        if self.signaled || !ready {
+            return ReadyOutcome::Unchanged;
+        }
+        if self.require_usable_path && !any_usable {
+            if self.deferred {
+                return ReadyOutcome::Unchanged;
+            }
+            self.deferred = true;
+            return ReadyOutcome::Deferred;
+        }
+        self.signaled = true;
+        ReadyOutcome::Signal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadyOutcome, ReadySignal, UsablePathDeadline};
+
+    #[test]
+    fn expires_only_after_ready_without_usable_path() {
+        let mut deadline = UsablePathDeadline::new(10);
+        // Handshake time does not count.
+        assert!(!deadline.observe(0, false, false));
+        assert!(!deadline.observe(50_000, false, false));
+        assert!(!deadline.observe(60_000, true, false));
+        assert!(!deadline.observe(69_999, true, false));
+        assert!(deadline.observe(70_000, true, false));
+    }
+
+    #[test]
+    fn usable_path_restarts_clock() {
+        let mut deadline = UsablePathDeadline::new(10);
+        assert!(!deadline.observe(0, true, true));
+        assert!(!deadline.observe(9_000, true, false));
+        assert!(!deadline.observe(15_000, true, true));
+        assert!(!deadline.observe(24_999, true, false));
+        assert!(deadline.observe(25_000, true, false));
+    }
+
+    #[test]
+    fn zero_timeout_disables() {
+        let mut deadline = UsablePathDeadline::new(0);
+        assert!(!deadline.observe(0, true, false));
+        assert!(!deadline.observe(u64::MAX, true, false));
+    }
+
+    #[test]
+    fn ready_signals_on_handshake_by_default() {
+        let mut signal = ReadySignal::new(false);
+        assert_eq!(signal.observe(false, false), ReadyOutcome::Unchanged);
+        assert_eq!(signal.observe(true, false), ReadyOutcome::Signal);
+        assert!(signal.signaled());
+        assert_eq!(signal.observe(true, true), ReadyOutcome::Unchanged);
+    }
+
+    #[test]
+    fn ready_waits_for_usable_path_when_required() {
+        let mut signal = ReadySignal::new(true);
+        assert_eq!(signal.observe(true, false), ReadyOutcome::Deferred);
+        assert_eq!(signal.observe(true, false), ReadyOutcome::Unchanged);
+        assert!(!signal.signaled());
+        assert_eq!(signal.observe(true, true), ReadyOutcome::Signal);
+        assert!(signal.signaled());
+    }
+}
@@ -0,0 +1,200 @@
+use super::client_alpn;
+use super::domain_rotation::longest_domain_len;
+use super::keep_alive::check_keep_alive_options;
+use super::reconnect::ReconnectBackoff;
+use super::setup::{
+    bind_local_listener, bind_udp_socket, check_resolver_options, compute_mtu, listen_label, map_io,
+};
+use crate::dns::{resolve_resolvers, ResolverAddrCache};
+use crate::error::ClientError;
+use crate::pinning::{configure_pinned_certificate, pinned_cert_fingerprint};
+use slipstream_core::validation::{ValidationKind, ValidationReport};
+use slipstream_dns::validate_service_label;
+use slipstream_ffi::{
+    picoquic::{picoquic_create, picoquic_current_time, slipstream_mixed_cc_algorithm},
+    set_log_dir, take_crypto_errors, ClientConfig, QuicGuard,
+};
+use std::ffi::CString;
+
+/// Run the client's setup steps without connecting: check the options,
+/// resolve the resolvers, bind and release the local sockets, read the
+/// pinned certificate and create a QUIC context. No query is sent, so the
+/// MTU probe is not run. `Err` is kept for failures outside the checks.
+pub async fn run_client_validate(
+    config: &ClientConfig<'_>,
+) -> Result<ValidationReport, ClientError> {
+    let alpn = CString::new(client_alpn(config))
+        .map_err(|_| ClientError::new("ALPN contains an unexpected null byte"))?;
+    let mut report = ValidationReport::new();
+
+    if let Some(label) = config.service_label {
+        report.check(
+            ValidationKind::Config,
+            "service label",
+            validate_service_label(label),
+            |_| label.to_string(),
+        );
+    }
+    let domain_len = longest_domain_len(config.domain, config.rotation_domains);
+    let mtu = report.check(
+        ValidationKind::Config,
+        "mtu",
+        compute_mtu(domain_len, config.service_label),
+        |mtu| {
+            if config.mtu_probe {
+                format!("{} (probe skipped)", mtu)
+            } else {
+                mtu.to_string()
+            }
+        },
+    );
+    report.check(
+        ValidationKind::Config,
+        "resolver options",
+        check_resolver_options(config),
+        |_| format!("{} resolvers", config.resolvers.len()),
+    );
+    report.check(
+        ValidationKind::Config,
+        "keep-alive",
+        check_keep_alive_options(config),
+        |_| {
+            format!(
+                "{} ms via {:?}",
+                config.keep_alive_interval, config.keep_alive_path
+            )
+        },
+    );
+    report.check(
+        ValidationKind::Config,
+        "reconnect delay",
+        ReconnectBackoff::new(config.reconnect_delay_min_ms, config.reconnect_delay_max_ms),
+        |_| {
+            format!(
+                "{}-{} ms",
+                config.reconnect_delay_min_ms, config.reconnect_delay_max_ms
+            )
+        },
+    );
+
+    let mut resolvers = Vec::new();
+    let resolved = resolve_resolvers(
+        config.resolvers,
+        &config.host_overrides,
+        &mut ResolverAddrCache::default(),
+        mtu.unwrap_or_default(),
+        false,
+        &mut resolvers,
+    )
+    .and_then(|()| {
+        if resolvers.is_empty() {
+            Err(ClientError::new("At least one resolver is required"))
+        } else {
+            Ok(())
+        }
+    });
+    report.check(ValidationKind::Resolve, "resolvers", resolved, |_| {
+        resolvers
+            .iter()
+            .map(|resolver| resolver.label())
+            .collect::<Vec<_>>()
+            .join(", ")
+    });
+
+    report.check(
+        ValidationKind::Bind,
+        "udp socket",
+        bind_udp_socket()
+            .await
+            .and_then(|udp| udp.local_addr().map_err(map_io)),
+        |addr| addr.to_string(),
+    );
+    report.check(
+        ValidationKind::Bind,
+        "tcp listener",
+        bind_local_listener(
+            config.tcp_listen_host,
+            config.tcp_listen_port,
+            config.loopback_only,
+        )
+        .await,
+        |(_, host)| listen_label(host, config.tcp_listen_port),
+    );
+
+    if let Some(cert) = config.cert {
+        report.check(
+            ValidationKind::Certificate,
+            "pinned cert",
+            pinned_cert_fingerprint(cert),
+            |digest| {
+                let hex = digest
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>();
+                format!("sha256 {}", hex)
+            },
+        );
+    }
+
+    let quic = unsafe {
+        picoquic_create(
+            1,
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            alpn.as_ptr(),
+            None,
+            std::ptr::null_mut(),
+            None,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            picoquic_current_time(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+            0,
+        )
+    };
+    if quic.is_null() {
+        let crypto_errors = take_crypto_errors();
+        if crypto_errors.is_empty() {
+            report.fail(ValidationKind::Quic, "context", "could not create");
+        } else {
+            report.fail(
+                ValidationKind::Quic,
+                "context",
+                format!(
+                    "could not create (TLS errors: {})",
+                    crypto_errors.join("; ")
+                ),
+            );
+        }
+        return Ok(report);
+    }
+    let _quic_guard = QuicGuard::new(quic);
+    report.pass(ValidationKind::Quic, "context", "created");
+    if unsafe { slipstream_mixed_cc_algorithm }.is_null() {
+        report.fail(
+            ValidationKind::Quic,
+            "congestion control",
+            "could not load mixed congestion control",
+        );
+    }
+    if let Some(cert) = config.cert {
+        report.check(
+            ValidationKind::Quic,
+            "certificate pinning",
+            configure_pinned_certificate(quic, cert),
+            |_| "installed".to_string(),
+        );
+    }
+    if let Some(dir) = config.qlog_dir {
+        report.check(
+            ValidationKind::Quic,
+            "qlog dir",
+            unsafe { set_log_dir(quic, dir) },
+            |_| dir.to_string(),
+        );
+    }
+    Ok(report)
+}
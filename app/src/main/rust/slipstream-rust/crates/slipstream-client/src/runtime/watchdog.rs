@@ -0,0 +1,321 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::error;
+
+const WATCHDOG_CHECK_INTERVAL_MAX_MS: u64 = 5_000;
+const WATCHDOG_CHECK_INTERVAL_MIN_MS: u64 = 100;
+pub(crate) const WATCHDOG_SNAPSHOT_INTERVAL_US: u64 = 1_000_000;
+
+struct WatchdogShared {
+    progress: AtomicU64,
+    streams: AtomicUsize,
+    force_close: AtomicBool,
+    stop: AtomicBool,
+    diagnostics: Mutex<String>,
+}
+
+/// Loop-side handle for the wedged-loop watchdog.
+///
+/// The client loop reports how many packets it has moved every iteration
+/// and periodically publishes a diagnostics snapshot. A dedicated OS thread
+/// watches that count, so a loop that keeps iterating without moving
+/// packets is caught as well as one that stopped. On a stall the thread
+/// dumps the snapshot and asks the loop to close the connection; if the
+/// count still has not moved one timeout later, the loop (or the runtime
+/// thread it runs on) is stuck where it cannot act on that, and the thread
+/// runs `on_wedged` itself.
+pub(crate) struct LoopWatchdog {
+    shared: Arc<WatchdogShared>,
+}
+
+impl LoopWatchdog {
+    /// Spawns the watchdog thread; a zero timeout disables the watchdog.
+    pub(crate) fn spawn<F>(timeout_ms: u64, data_notify: Arc<Notify>, on_wedged: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let shared = Arc::new(WatchdogShared {
+            progress: AtomicU64::new(0),
+            streams: AtomicUsize::new(0),
+            force_close: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+            diagnostics: Mutex::new(String::new()),
+        });
+        if timeout_ms > 0 {
+            let watcher = shared.clone();
+            let timeout = Duration::from_millis(timeout_ms);
+            let spawned = thread::Builder::new()
+                .name("slipstream-watchdog".to_string())
+                .spawn(move || watch(watcher, timeout, data_notify, on_wedged));
+            if let Err(err) = spawned {
+                error!("Failed to spawn loop watchdog thread: {}", err);
+            }
+        }
+        Self { shared }
+    }
+
+    /// `progress` is any count that grows while packets move, such as the
+    /// queries sent plus the responses received.
+    pub(crate) fn beat(&self, progress: u64, streams: usize) {
+        self.shared.progress.store(progress, Ordering::Relaxed);
+        self.shared.streams.store(streams, Ordering::Relaxed);
+    }
+
+    pub(crate) fn publish_diagnostics(&self, diagnostics: String) {
+        if let Ok(mut slot) = self.shared.diagnostics.lock() {
+            *slot = diagnostics;
+        }
+    }
+
+    pub(crate) fn take_force_close(&self) -> bool {
+        self.shared.force_close.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl Drop for LoopWatchdog {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+    }
+}
+
+fn check_interval(timeout: Duration) -> Duration {
+    let half_ms = (timeout.as_millis() / 2) as u64;
+    Duration::from_millis(half_ms.clamp(
+        WATCHDOG_CHECK_INTERVAL_MIN_MS,
+        WATCHDOG_CHECK_INTERVAL_MAX_MS,
+    ))
+}
+
+fn watch<F>(shared: Arc<WatchdogShared>, timeout: Duration, data_notify: Arc<Notify>, on_wedged: F)
+where
+    F: FnOnce(),
+{
+    let interval = check_interval(timeout);
+    let mut tracker = StallTracker::new(timeout, Instant::now());
+    while !shared.stop.load(Ordering::Acquire) {
+        thread::sleep(interval);
+        let progress = shared.progress.load(Ordering::Relaxed);
+        let streams = shared.streams.load(Ordering::Relaxed);
+        let now = Instant::now();
+        match tracker.observe(progress, streams, now) {
+            Stall::None => {}
+            Stall::Detected => {
+                let diagnostics = shared
+                    .diagnostics
+                    .lock()
+                    .map(|slot| slot.clone())
+                    .unwrap_or_default();
+                error!(
+                    "watchdog: client loop stalled stalled_ms={} streams={} progress={} last_snapshot=[{}]; forcing connection close",
+                    tracker.stalled_for(now).as_millis(),
+                    streams,
+                    progress,
+                    diagnostics
+                );
+                shared.force_close.store(true, Ordering::Release);
+                data_notify.notify_one();
+            }
+            Stall::Wedged => {
+                error!(
+                    "watchdog: client loop did not recover stalled_ms={} streams={} progress={}; aborting it",
+                    tracker.stalled_for(now).as_millis(),
+                    streams,
+                    progress
+                );
+                on_wedged();
+                return;
+            }
+        }
+    }
+}
+
+/// Ends the process for a loop that cannot close its own connection, so
+/// the service manager (or Android) starts a fresh one.
+pub(crate) fn abort_wedged_loop() {
+    std::process::abort();
+}
+
+/// What [`StallTracker::observe`] found.
+#[derive(Debug, PartialEq, Eq)]
+enum Stall {
+    None,
+    /// No progress for the timeout: ask the loop to close.
+    Detected,
+    /// Still none a timeout after that: the loop did not act on it.
+    Wedged,
+}
+
+/// Tracks loop progress and reports each stage of a stall once per wedge.
+struct StallTracker {
+    timeout: Duration,
+    last_progress: u64,
+    last_progress_at: Instant,
+    fired: bool,
+}
+
+impl StallTracker {
+    fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_progress: 0,
+            last_progress_at: now,
+            fired: false,
+        }
+    }
+
+    fn observe(&mut self, progress: u64, streams: usize, now: Instant) -> Stall {
+        if progress != self.last_progress || streams == 0 {
+            self.last_progress = progress;
+            self.last_progress_at = now;
+            self.fired = false;
+            return Stall::None;
+        }
+        let stalled = self.stalled_for(now);
+        if !self.fired {
+            if stalled < self.timeout {
+                return Stall::None;
+            }
+            self.fired = true;
+            return Stall::Detected;
+        }
+        if stalled < self.timeout.saturating_mul(2) {
+            return Stall::None;
+        }
+        Stall::Wedged
+    }
+
+    fn stalled_for(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_progress_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn stall_fires_once_after_timeout_with_streams() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        let mut tracker = StallTracker::new(timeout, start);
+        assert_eq!(tracker.observe(5, 1, start), Stall::None);
+        assert_eq!(
+            tracker.observe(5, 1, start + Duration::from_secs(9)),
+            Stall::None
+        );
+        assert_eq!(
+            tracker.observe(5, 1, start + Duration::from_secs(10)),
+            Stall::Detected
+        );
+        assert_eq!(
+            tracker.observe(5, 1, start + Duration::from_secs(15)),
+            Stall::None
+        );
+
+        // Progress rearms the tracker.
+        let resumed = start + Duration::from_secs(16);
+        assert_eq!(tracker.observe(6, 1, resumed), Stall::None);
+        assert_eq!(tracker.observe(6, 1, resumed + timeout), Stall::Detected);
+    }
+
+    #[test]
+    fn stall_without_progress_after_close_is_wedged() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        let mut tracker = StallTracker::new(timeout, start);
+        assert_eq!(tracker.observe(5, 1, start), Stall::None);
+        assert_eq!(tracker.observe(5, 1, start + timeout), Stall::Detected);
+        assert_eq!(
+            tracker.observe(5, 1, start + Duration::from_secs(19)),
+            Stall::None
+        );
+        assert_eq!(
+            tracker.observe(5, 1, start + Duration::from_secs(20)),
+            Stall::Wedged
+        );
+    }
+
+    #[test]
+    fn stall_ignored_without_streams() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(1);
+        let mut tracker = StallTracker::new(timeout, start);
+        assert_eq!(
+            tracker.observe(0, 0, start + Duration::from_secs(5)),
+            Stall::None
+        );
+        assert_eq!(
+            tracker.observe(0, 0, start + Duration::from_secs(50)),
+            Stall::None
+        );
+        assert_eq!(
+            tracker.observe(0, 2, start + Duration::from_secs(50)),
+            Stall::None
+        );
+        assert_eq!(
+            tracker.observe(0, 2, start + Duration::from_secs(51)),
+            Stall::Detected
+        );
+    }
+
+    #[test]
+    fn iterating_loop_without_progress_is_asked_to_close() {
+        let (wedged_tx, wedged_rx) = mpsc::channel();
+        let watchdog = LoopWatchdog::spawn(100, Arc::new(Notify::new()), move || {
+            let _ = wedged_tx.send(());
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut closed = false;
+        while !closed && Instant::now() < deadline {
+            // Keeps iterating, but the packet count never moves.
+            watchdog.beat(7, 1);
+            closed = watchdog.take_force_close();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(closed, "watchdog never asked the loop to close");
+        // The reconnect path reports no streams, which rearms the watchdog.
+        watchdog.beat(0, 0);
+        assert!(wedged_rx.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn blocked_loop_is_aborted_from_the_watchdog_thread() {
+        let (wedged_tx, wedged_rx) = mpsc::channel::<()>();
+        let watchdog = LoopWatchdog::spawn(100, Arc::new(Notify::new()), move || {
+            let _ = wedged_tx.send(());
+        });
+        let started = Instant::now();
+        let stuck_loop = thread::spawn(move || {
+            watchdog.beat(7, 1);
+            // Blocks where the close request can never be read; only the
+            // watchdog's abort gets it out.
+            let aborted = wedged_rx.recv_timeout(Duration::from_secs(5)).is_ok();
+            (aborted, watchdog.take_force_close())
+        });
+        let (aborted, close_requested) = stuck_loop.join().unwrap();
+        assert!(aborted, "watchdog did not abort the blocked loop");
+        assert!(close_requested);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn check_interval_is_bounded() {
+        assert_eq!(
+            check_interval(Duration::from_millis(50)),
+            Duration::from_millis(WATCHDOG_CHECK_INTERVAL_MIN_MS)
+        );
+        assert_eq!(
+            check_interval(Duration::from_secs(4)),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            check_interval(Duration::from_secs(60)),
+            Duration::from_millis(WATCHDOG_CHECK_INTERVAL_MAX_MS)
+        );
+    }
+}
@@ -0,0 +1,72 @@
+/// How long local connects may wait on a ready connection that grants no
+/// streams before the connection counts as stuck.
+const ZERO_CREDIT_GRACE_US: u64 = 10_000_000;
+
+/// Notices a ready connection whose peer never grants bidirectional
+/// streams.
+///
+/// With an initial MAX_STREAMS of zero the acceptor holds every local
+/// connect forever: the listener is up, the tunnel looks ready, and each
+/// connect hangs. A stall is reported once its accepts have waited out the
+/// grace period, and clears as soon as credit arrives or nothing waits.
+#[derive(Debug, Default)]
+pub(crate) struct ZeroCreditWatch {
+    since: Option<u64>,
+    reported: bool,
+    stalls: u64,
+}
+
+impl ZeroCreditWatch {
+    /// Called every loop iteration with the accepts starved of credit;
+    /// returns true when a stall has just passed the grace period.
+    pub(crate) fn observe(&mut self, now: u64, starved: usize) -> bool {
+        if starved == 0 {
+            self.since = None;
+            self.reported = false;
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.reported || now.saturating_sub(since) < ZERO_CREDIT_GRACE_US {
+            return false;
+        }
+        self.reported = true;
+        self.stalls = self.stalls.saturating_add(1);
+        true
+    }
+
+    /// Whether accepts are stuck on zero credit right now.
+    pub(crate) fn is_blocked(&self) -> bool {
+        self.reported
+    }
+
+    /// Stalls reported this session.
+    pub(crate) fn stalls(&self) -> u64 {
+        self.stalls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ZeroCreditWatch, ZERO_CREDIT_GRACE_US};
+
+    #[test]
+    fn stall_is_reported_once_after_the_grace_period() {
+        let mut watch = ZeroCreditWatch::default();
+        assert!(!watch.observe(1_000, 0));
+        assert!(!watch.observe(2_000, 2));
+        assert!(!watch.observe(2_000 + ZERO_CREDIT_GRACE_US - 1, 2));
+        assert!(watch.observe(2_000 + ZERO_CREDIT_GRACE_US, 1));
+        assert!(watch.is_blocked());
+        assert!(!watch.observe(3_000 + ZERO_CREDIT_GRACE_US * 2, 1));
+        assert_eq!(watch.stalls(), 1);
+
+        // Credit arrived or the connects gave up; a later stall starts its
+        // own grace period.
+        let later = ZERO_CREDIT_GRACE_US * 5;
+        assert!(!watch.observe(later, 0));
+        assert!(!watch.is_blocked());
+        assert!(!watch.observe(later + 1, 1));
+        assert!(watch.observe(later + 1 + ZERO_CREDIT_GRACE_US, 1));
+        assert_eq!(watch.stalls(), 2);
+    }
+}
@@ -0,0 +1,112 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ZeroSendAction {
+    /// Re-mark streams active so picoquic re-queues them for sending.
+    MarkActive,
+    /// Log a diagnostic with connection and stream state.
+    Diagnose,
+    /// Apply the configured reset (worst stream or whole connection).
+    Reset,
+}
+
+/// Escalation state machine for the zero-send-with-streams pathology.
+///
+/// A stalled iteration is one where picoquic produced nothing to send while
+/// streams had ready data, nothing was in flight, and flow control was not
+/// blocking. Each stage fires once after `threshold` further stalled
+/// iterations; any progress rearms the tracker from the first stage.
+pub(crate) struct ZeroSendTracker {
+    threshold: u64,
+    consecutive: u64,
+    stage: u8,
+}
+
+impl ZeroSendTracker {
+    pub(crate) fn new(threshold: u64) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            consecutive: 0,
+            stage: 0,
+        }
+    }
+
+    pub(crate) fn consecutive(&self) -> u64 {
+        self.consecutive
+    }
+
+    pub(crate) fn observe(&mut self, stalled: bool) -> Option<ZeroSendAction> {
+        if !stalled {
+            self.consecutive = 0;
+            self.stage = 0;
+            return None;
+        }
+        self.consecutive = self.consecutive.saturating_add(1);
+        if self.consecutive < self.threshold * (self.stage as u64 + 1) {
+            return None;
+        }
+        let action = match self.stage {
+            0 => ZeroSendAction::MarkActive,
+            1 => ZeroSendAction::Diagnose,
+            _ => ZeroSendAction::Reset,
+        };
+        if action == ZeroSendAction::Reset {
+            // Start over so a stall that survives the reset escalates again.
+            self.consecutive = 0;
+            self.stage = 0;
+        } else {
+            self.stage += 1;
+        }
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stall(tracker: &mut ZeroSendTracker, iterations: u64) -> Vec<(u64, ZeroSendAction)> {
+        (1..=iterations)
+            .filter_map(|i| tracker.observe(true).map(|action| (i, action)))
+            .collect()
+    }
+
+    #[test]
+    fn escalates_through_each_stage() {
+        let mut tracker = ZeroSendTracker::new(4);
+        assert_eq!(
+            stall(&mut tracker, 12),
+            vec![
+                (4, ZeroSendAction::MarkActive),
+                (8, ZeroSendAction::Diagnose),
+                (12, ZeroSendAction::Reset),
+            ]
+        );
+        assert_eq!(tracker.consecutive(), 0);
+        assert_eq!(
+            stall(&mut tracker, 4),
+            vec![(4, ZeroSendAction::MarkActive)]
+        );
+    }
+
+    #[test]
+    fn progress_rearms_first_stage() {
+        let mut tracker = ZeroSendTracker::new(3);
+        assert_eq!(
+            stall(&mut tracker, 5),
+            vec![(3, ZeroSendAction::MarkActive)]
+        );
+        assert_eq!(tracker.observe(false), None);
+        assert_eq!(tracker.consecutive(), 0);
+        assert_eq!(
+            stall(&mut tracker, 3),
+            vec![(3, ZeroSendAction::MarkActive)]
+        );
+    }
+
+    #[test]
+    fn zero_threshold_is_clamped() {
+        let mut tracker = ZeroSendTracker::new(0);
+        assert_eq!(tracker.observe(true), Some(ZeroSendAction::MarkActive));
+        assert_eq!(tracker.observe(true), Some(ZeroSendAction::Diagnose));
+        assert_eq!(tracker.observe(true), Some(ZeroSendAction::Reset));
+    }
+}
@@ -1,42 +1,187 @@
+mod commands;
+mod pool;
+
+pub(crate) use commands::{command_channel, CommandReceiver, CommandSender};
+pub(crate) use pool::StreamPoolCounters;
+
+use pool::StreamPool;
+use slipstream_core::callback_state::from_callback_ctx;
+use slipstream_core::clock::Clock;
 use slipstream_core::flow_control::{
-    conn_reserve_bytes, consume_error_log_message, consume_stream_data, handle_stream_receive,
-    overflow_log_message, promote_error_log_message, promote_streams, reserve_target_offset,
+    conn_reserve_bytes, consume_error_log_message, consume_stream_data, exceeds_fin_offset,
+    handle_stream_receive, overflow_log_message, promote_error_log_message, promote_streams,
+    promote_target_offset, reserve_target_offset, stream_queue_max_bytes, DrainCoalescer,
     FlowControlState, HasFlowControlState, PromoteEntry, StreamReceiveConfig, StreamReceiveOps,
 };
 use slipstream_core::invariants::InvariantReporter;
+use slipstream_core::latency::{LatencyHistogram, LatencyPercentiles};
+use slipstream_core::log_sampling::{SampledLogger, DEFAULT_LOG_BURST};
+use slipstream_core::stream_checksum::StreamChecksumLog;
+use slipstream_core::stream_trailer::{TrailerReader, TrailerWriter};
 use slipstream_core::tcp::{stream_read_limit_chunks, tcp_send_buffer_bytes};
 use slipstream_ffi::picoquic::{
-    picoquic_add_to_stream, picoquic_call_back_event_t, picoquic_cnx_t, picoquic_current_time,
-    picoquic_get_close_reasons, picoquic_get_cnx_state, picoquic_get_next_local_stream_id,
-    picoquic_mark_active_stream, picoquic_provide_stream_data_buffer, picoquic_reset_stream,
-    picoquic_stop_sending, picoquic_stream_data_consumed,
+    picoquic_add_to_stream, picoquic_call_back_event_t, picoquic_cnx_t, picoquic_get_close_reasons,
+    picoquic_get_cnx_state, picoquic_get_next_local_stream_id, picoquic_mark_active_stream,
+    picoquic_provide_stream_data_buffer, picoquic_reset_stream, picoquic_set_stream_path_affinity,
+    picoquic_stop_sending, picoquic_stream_data_consumed, slipstream_get_cipher_suite,
+    slipstream_get_negotiated_version, tls_cipher_suite_name,
+};
+use slipstream_ffi::{
+    abort_stream_bidi, AbortError, AbortRetryOutcome, DeferredAborts, PicoquicClock,
+    SLIPSTREAM_CHECKSUM_ERROR, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_FINAL_SIZE_ERROR,
+    SLIPSTREAM_INTERNAL_ERROR,
 };
-use slipstream_ffi::{abort_stream_bidi, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_INTERNAL_ERROR};
 use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream as TokioTcpStream;
 use tokio::sync::{mpsc, oneshot, Notify};
 use tracing::{debug, error, info, warn};
 
+/// TCP read size until the path MTU is known.
 const STREAM_READ_CHUNK_BYTES: usize = 4096;
+/// Packets' worth of QUIC payload per TCP read.
+const STREAM_READ_CHUNK_PACKETS: usize = 4;
+const STREAM_READ_CHUNK_MIN_BYTES: usize = 512;
+const STREAM_READ_CHUNK_MAX_BYTES: usize = 64 * 1024;
 const DEFAULT_TCP_RCVBUF_BYTES: usize = 256 * 1024;
 const CLIENT_WRITE_COALESCE_DEFAULT_BYTES: usize = 256 * 1024;
 static INVARIANT_REPORTER: InvariantReporter = InvariantReporter::new(1_000_000);
+// Per-stream failures that a lost connection or a dying local application
+// repeats for every stream at once.
+static RESET_LOG: SampledLogger = SampledLogger::new("stream reset", DEFAULT_LOG_BURST);
+static TCP_READ_LOG: SampledLogger = SampledLogger::new("tcp read error", DEFAULT_LOG_BURST);
+static TCP_WRITE_LOG: SampledLogger = SampledLogger::new("tcp write error", DEFAULT_LOG_BURST);
+static WRITE_CHANNEL_LOG: SampledLogger =
+    SampledLogger::new("tcp write channel closed", DEFAULT_LOG_BURST);
+static SAMPLED_LOGS: [&SampledLogger; 4] = [
+    &RESET_LOG,
+    &TCP_READ_LOG,
+    &TCP_WRITE_LOG,
+    &WRITE_CHANNEL_LOG,
+];
+/// First streams accepted later than this after ready are not timed.
+const FIRST_STREAM_TIMING_WINDOW: Duration = Duration::from_secs(10);
 
 pub(crate) struct ClientState {
     ready: bool,
+    ready_at: Option<Instant>,
     closing: bool,
+    /// The close that set `closing` was a stateless reset.
+    stateless_reset: bool,
+    close_reasons: Option<CloseReasons>,
     streams: HashMap<u64, ClientStream>,
     multi_stream_mode: bool,
-    command_tx: mpsc::UnboundedSender<Command>,
+    command_tx: CommandSender,
     data_notify: Arc<Notify>,
     path_events: Vec<PathEvent>,
     debug_streams: bool,
+    /// `--debug-stream-checksums`: log rolling checksums of stream payload.
+    stream_checksums: bool,
+    /// `--debug-stream-trailers`: append and check CRC32 stream trailers.
+    stream_trailers: bool,
     acceptor: acceptor::ClientAcceptor,
     debug_enqueued_bytes: u64,
     debug_last_enqueue_at: u64,
     acceptor_limit_logged: bool,
+    /// Holds the acceptor at zero credit (authoritative path gate).
+    acceptor_paused: bool,
+    stream_path_affinity: bool,
+    streams_per_path: HashMap<u64, usize>,
+    stream_paths: HashMap<u64, u64>,
+    /// Resolver behind each unique path ID, for `StreamTag`.
+    path_resolvers: HashMap<u64, SocketAddr>,
+    /// Bumped by every `reset_for_reconnect`; stream IDs restart with each
+    /// connection, so logs need this to tell their streams apart.
+    connection_generation: u64,
+    quic_version: u32,
+    cipher_suite: u16,
+    version_negotiation_seen: bool,
+    version_negotiation_count: u64,
+    /// Set once the connection's first stream has been logged.
+    first_stream_logged: bool,
+    session: SessionCounters,
+    /// Local accept to first byte delivered back to the local socket, per
+    /// stream; kept for the whole session like `session`.
+    ttfb: LatencyHistogram,
+    /// Refused aborts awaiting a retry; the counters also survive
+    /// `reset_for_reconnect`.
+    aborts: DeferredAborts,
+    /// Streams aborted for data past their FIN, for the whole session.
+    fin_violations: u64,
+    /// `--stop-sending-half-close`: STOP_SENDING only resets our send side.
+    stop_sending_half_close: bool,
+    /// `--closing-timeout-ms`: longest a stream may wait in `Closing` for
+    /// its FIN; zero never expires one.
+    closing_timeout: Duration,
+    /// Streams found stuck in `Closing`, for the whole session.
+    stuck_closing: u64,
+    /// `--max-streams-floor`: accepts allowed before the server's credit
+    /// is known.
+    max_streams_floor: usize,
+    /// Streams the floor opened past the server's credit, dropped locally
+    /// and waiting for credit to cover them before their reset goes out.
+    uncredited_streams: Vec<u64>,
+    /// Streams the floor opened past the server's credit, for the whole
+    /// session.
+    floor_overruns: u64,
+    /// `--stream-pool`: idle streams opened ahead of accepts.
+    stream_pool: StreamPool,
+    /// TCP read size for new streams, from the connection's MTU.
+    read_chunk_bytes: usize,
+    /// When set, `abort_stream` and `reset_send_side` record here instead
+    /// of calling picoquic.
+    #[cfg(any(test, feature = "fault-injection"))]
+    recorded_aborts: Option<Vec<(u64, u64)>>,
+}
+
+/// Cumulative traffic for the whole client session, for accounting.
+///
+/// Unlike the per-stream and per-connection figures these survive
+/// `reset_for_reconnect`; they only start over with a new `ClientState`,
+/// which is created once per client start.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SessionCounters {
+    /// Stream payload accepted into QUIC (local TCP towards the server).
+    pub(crate) bytes_up: u64,
+    /// Stream payload received from the server.
+    pub(crate) bytes_down: u64,
+    pub(crate) streams_opened: u64,
+    /// QUIC key phase changes, whether the client or the server started them.
+    pub(crate) key_updates: u64,
+}
+
+/// Transport error codes picoquic reports for a closed connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CloseReasons {
+    pub(crate) local: u64,
+    pub(crate) remote: u64,
+}
+
+/// Names a stream in logs as `stream 4 gen=1 resolver=192.0.2.1:53`, or
+/// `stream=4 gen=1 resolver=...` with `{:#}` for key=value messages. The
+/// resolver is the one the stream is pinned to, or the only path's when
+/// there is a single one; unpinned streams on a multipath connection have
+/// none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamTag {
+    stream_id: u64,
+    generation: u64,
+    resolver: Option<SocketAddr>,
+}
+
+impl fmt::Display for StreamTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sep = if f.alternate() { "=" } else { " " };
+        write!(f, "stream{}{} gen={}", sep, self.stream_id, self.generation)?;
+        if let Some(resolver) = self.resolver {
+            write!(f, " resolver={}", resolver)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -44,11 +189,14 @@ pub(crate) enum StreamSendState {
     Open,
     Closing,
     FinQueued,
+    /// Reset in answer to the server's STOP_SENDING; the receive side
+    /// carries on.
+    Stopped,
 }
 
 impl StreamSendState {
     fn is_closed(self) -> bool {
-        matches!(self, StreamSendState::FinQueued)
+        matches!(self, StreamSendState::FinQueued | StreamSendState::Stopped)
     }
 
     fn can_queue_fin(self) -> bool {
@@ -94,13 +242,31 @@ pub(crate) struct ClientBacklogSummary {
     pub(crate) tx_bytes: u64,
 }
 
+fn backlog_summary(stream_id: u64, stream: &ClientStream) -> ClientBacklogSummary {
+    ClientBacklogSummary {
+        stream_id,
+        queued_bytes: stream.flow.queued_bytes as u64,
+        rx_bytes: stream.flow.rx_bytes,
+        consumed_offset: stream.flow.consumed_offset,
+        fin_offset: stream.flow.fin_offset,
+        recv_state: stream.recv_state,
+        send_state: stream.send_state,
+        stop_sending_sent: stream.flow.stop_sending_sent,
+        discarding: stream.flow.discarding,
+        has_data_rx: stream.data_rx.is_some(),
+        tx_bytes: stream.tx_bytes,
+    }
+}
+
 pub(crate) mod acceptor {
-    use super::Command;
+    use super::{Command, CommandSender};
     use slipstream_ffi::picoquic::{picoquic_cnx_t, slipstream_get_max_streams_bidir_remote};
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
     use std::sync::Arc;
+    use std::time::Instant;
     use tokio::net::TcpListener as TokioTcpListener;
-    use tokio::sync::{mpsc, Notify};
+    use tokio::sync::mpsc::error::TrySendError;
+    use tokio::sync::Notify;
     use tokio::time::{sleep, Duration};
     use tracing::warn;
 
@@ -112,35 +278,94 @@ pub(crate) mod acceptor {
     /// stale accepts never leak across reconnect boundaries.
     pub(crate) struct ClientAcceptor {
         limiter: Arc<AcceptorLimiter>,
+        slots: Arc<StreamSlots>,
     }
 
     impl ClientAcceptor {
+        #[cfg(test)]
         pub(crate) fn new() -> Self {
+            Self::with_max_local_streams(0)
+        }
+
+        /// Accepts wait while `max_local_streams` streams still have their
+        /// reader or writer task running; 0 leaves them uncapped.
+        pub(crate) fn with_max_local_streams(max_local_streams: usize) -> Self {
             let limit = initial_acceptor_limit();
             Self {
                 limiter: Arc::new(AcceptorLimiter::new(limit)),
+                slots: Arc::new(StreamSlots::new(max_local_streams)),
             }
         }
 
-        pub(crate) fn spawn(
-            &self,
-            listener: TokioTcpListener,
-            command_tx: mpsc::UnboundedSender<Command>,
-        ) {
-            TcpAcceptor::new(listener, command_tx, Arc::clone(&self.limiter)).spawn();
+        pub(crate) fn spawn(&self, listener: TokioTcpListener, command_tx: CommandSender) {
+            TcpAcceptor::new(
+                listener,
+                command_tx,
+                Arc::clone(&self.limiter),
+                Arc::clone(&self.slots),
+            )
+            .spawn();
         }
 
-        pub(crate) fn update_limit(&self, cnx: *mut picoquic_cnx_t) -> usize {
+        /// Hands the peer's MAX_STREAMS credit to accepts, less `withheld`
+        /// stream IDs that were used up without an accept; returns the
+        /// peer's limit.
+        pub(crate) fn update_limit(&self, cnx: *mut picoquic_cnx_t, withheld: usize) -> usize {
             let max_streams = unsafe { slipstream_get_max_streams_bidir_remote(cnx) };
             let max_streams = usize::try_from(max_streams).unwrap_or(usize::MAX);
-            self.limiter.set_max(max_streams);
+            self.limiter.set_max(max_streams.saturating_sub(withheld));
             max_streams
         }
 
+        /// Credit held by accepts on this connection, reserved or committed.
+        pub(crate) fn reserved(&self) -> usize {
+            self.limiter.used.load(Ordering::SeqCst)
+        }
+
+        /// Credit currently handed to accepts.
+        pub(crate) fn limit(&self) -> usize {
+            self.limiter.max.load(Ordering::SeqCst)
+        }
+
+        /// Accepts waiting for credit.
+        pub(crate) fn waiting(&self) -> usize {
+            self.limiter.waiting.load(Ordering::SeqCst)
+        }
+
         pub(crate) fn reset(&self) {
             self.limiter.reset();
         }
 
+        /// Accept totals since the acceptor was created; reconnects keep them.
+        pub(crate) fn counters(&self) -> AcceptCounters {
+            AcceptCounters {
+                blocked_on_stream_cap: self.slots.blocked.load(Ordering::Relaxed),
+                ..self.limiter.counters.snapshot()
+            }
+        }
+
+        /// Streams whose tasks are running, and the most seen at once.
+        pub(crate) fn local_streams(&self) -> (usize, usize) {
+            (
+                self.slots.live.load(Ordering::SeqCst),
+                self.slots.peak.load(Ordering::SeqCst),
+            )
+        }
+
+        pub(crate) fn pause(&self) {
+            if self.limiter.max.load(Ordering::SeqCst) != 0 {
+                self.limiter.set_max(0);
+            }
+        }
+
+        /// Hands out `limit` credit the peer has not granted yet, for
+        /// accepts made before its MAX_STREAMS is known.
+        pub(crate) fn allow_unconfirmed(&self, limit: usize) {
+            if self.limiter.max.load(Ordering::SeqCst) != limit {
+                self.limiter.set_max(limit);
+            }
+        }
+
         #[cfg(test)]
         pub(crate) fn set_test_limit(limit: usize) {
             TEST_ACCEPTOR_LIMIT.store(limit, Ordering::SeqCst);
@@ -150,6 +375,11 @@ pub(crate) mod acceptor {
         pub(crate) async fn reserve_for_test(&self) -> AcceptorReservation {
             self.limiter.reserve().await
         }
+
+        #[cfg(test)]
+        pub(crate) async fn slot_for_test(&self) -> StreamSlot {
+            self.slots.acquire().await
+        }
     }
 
     pub(super) fn initial_acceptor_limit() -> usize {
@@ -174,11 +404,88 @@ pub(crate) mod acceptor {
         None
     }
 
+    /// Where the local listener's accepts went, as totals.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub(crate) struct AcceptCounters {
+        /// Connections handed to the client loop as new streams.
+        pub(crate) accepted: u64,
+        /// Accepts that had to wait for MAX_STREAMS credit first.
+        pub(crate) blocked_on_credit: u64,
+        /// Accepts that had to wait for a stream under
+        /// `--max-local-streams` to finish first.
+        pub(crate) blocked_on_stream_cap: u64,
+        /// Accepted connections closed unused because a reconnect made the
+        /// credit they were reserved against stale.
+        pub(crate) vetoed: u64,
+        /// Accept calls that failed.
+        pub(crate) errors: u64,
+        /// Accepted connections that had to wait for room in the command
+        /// channel (`--command-channel-cap`).
+        pub(crate) blocked_on_queue: u64,
+    }
+
+    impl AcceptCounters {
+        /// Accepts per second between `earlier` and these totals.
+        pub(crate) fn accepts_per_sec(&self, earlier: &AcceptCounters, elapsed_us: u64) -> u64 {
+            if elapsed_us == 0 {
+                return 0;
+            }
+            let accepted = self.accepted.saturating_sub(earlier.accepted);
+            accepted.saturating_mul(1_000_000) / elapsed_us
+        }
+    }
+
+    #[derive(Default)]
+    struct AcceptTotals {
+        accepted: AtomicU64,
+        blocked_on_credit: AtomicU64,
+        vetoed: AtomicU64,
+        errors: AtomicU64,
+        blocked_on_queue: AtomicU64,
+    }
+
+    impl AcceptTotals {
+        fn bump(counter: &AtomicU64) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn snapshot(&self) -> AcceptCounters {
+            AcceptCounters {
+                accepted: self.accepted.load(Ordering::Relaxed),
+                blocked_on_credit: self.blocked_on_credit.load(Ordering::Relaxed),
+                vetoed: self.vetoed.load(Ordering::Relaxed),
+                errors: self.errors.load(Ordering::Relaxed),
+                blocked_on_queue: self.blocked_on_queue.load(Ordering::Relaxed),
+                ..AcceptCounters::default()
+            }
+        }
+    }
+
     struct AcceptorLimiter {
         max: AtomicUsize,
         used: AtomicUsize,
+        /// Accepts waiting for credit right now.
+        waiting: AtomicUsize,
         generation: AtomicUsize,
         notify: Notify,
+        counters: AcceptTotals,
+    }
+
+    /// Counts one accept in `AcceptorLimiter::waiting` while it lives, so
+    /// an accept future dropped mid-wait is not left counted.
+    struct WaitingAccept<'a>(&'a AtomicUsize);
+
+    impl<'a> WaitingAccept<'a> {
+        fn new(waiting: &'a AtomicUsize) -> Self {
+            waiting.fetch_add(1, Ordering::SeqCst);
+            Self(waiting)
+        }
+    }
+
+    impl Drop for WaitingAccept<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+        }
     }
 
     impl AcceptorLimiter {
@@ -186,8 +493,10 @@ pub(crate) mod acceptor {
             Self {
                 max: AtomicUsize::new(limit),
                 used: AtomicUsize::new(0),
+                waiting: AtomicUsize::new(0),
                 generation: AtomicUsize::new(0),
                 notify: Notify::new(),
+                counters: AcceptTotals::default(),
             }
         }
 
@@ -208,6 +517,7 @@ pub(crate) mod acceptor {
         }
 
         async fn reserve(self: &Arc<Self>) -> AcceptorReservation {
+            let mut blocked = None;
             loop {
                 let max = self.max.load(Ordering::SeqCst);
                 let used = self.used.load(Ordering::SeqCst);
@@ -231,6 +541,10 @@ pub(crate) mod acceptor {
                     }
                     continue;
                 }
+                if blocked.is_none() {
+                    blocked = Some(WaitingAccept::new(&self.waiting));
+                    AcceptTotals::bump(&self.counters.blocked_on_credit);
+                }
                 self.notify.notified().await;
             }
         }
@@ -273,6 +587,69 @@ pub(crate) mod acceptor {
         }
     }
 
+    /// Counts the streams whose reader and writer tasks are alive, and
+    /// holds accepts back while `max` of them are (`--max-local-streams`).
+    /// Unlike MAX_STREAMS credit this follows the local tasks, so it is not
+    /// reset on reconnect: a stream's slot frees when both tasks have ended.
+    struct StreamSlots {
+        /// 0 counts streams without a cap.
+        max: usize,
+        live: AtomicUsize,
+        peak: AtomicUsize,
+        blocked: AtomicU64,
+        notify: Notify,
+    }
+
+    impl StreamSlots {
+        fn new(max: usize) -> Self {
+            Self {
+                max,
+                live: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                blocked: AtomicU64::new(0),
+                notify: Notify::new(),
+            }
+        }
+
+        async fn acquire(self: &Arc<Self>) -> StreamSlot {
+            let mut blocked = false;
+            loop {
+                let live = self.live.load(Ordering::SeqCst);
+                if self.max == 0 || live < self.max {
+                    if self
+                        .live
+                        .compare_exchange(live, live + 1, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+                    {
+                        self.peak.fetch_max(live + 1, Ordering::SeqCst);
+                        return StreamSlot {
+                            slots: Arc::clone(self),
+                        };
+                    }
+                    continue;
+                }
+                if !blocked {
+                    blocked = true;
+                    AcceptTotals::bump(&self.blocked);
+                }
+                self.notify.notified().await;
+            }
+        }
+    }
+
+    /// One stream's place under `--max-local-streams`. The reader and
+    /// writer tasks share it, and the last one to finish frees it.
+    pub(crate) struct StreamSlot {
+        slots: Arc<StreamSlots>,
+    }
+
+    impl Drop for StreamSlot {
+        fn drop(&mut self) {
+            self.slots.live.fetch_sub(1, Ordering::SeqCst);
+            self.slots.notify.notify_one();
+        }
+    }
+
     pub(crate) struct AcceptorReservation {
         limiter: Arc<AcceptorLimiter>,
         generation: usize,
@@ -286,6 +663,7 @@ pub(crate) mod acceptor {
 
         pub(crate) fn commit(mut self) -> bool {
             if !self.is_fresh() {
+                AcceptTotals::bump(&self.limiter.counters.vetoed);
                 return false;
             }
             self.committed = true;
@@ -303,35 +681,51 @@ pub(crate) mod acceptor {
 
     struct AcceptorGate {
         limiter: Arc<AcceptorLimiter>,
+        slots: Arc<StreamSlots>,
     }
 
     impl AcceptorGate {
-        fn new(limiter: Arc<AcceptorLimiter>) -> Self {
-            Self { limiter }
+        fn new(limiter: Arc<AcceptorLimiter>, slots: Arc<StreamSlots>) -> Self {
+            Self { limiter, slots }
         }
 
         async fn accept_and_dispatch(
             &self,
             listener: &TokioTcpListener,
-            command_tx: &mpsc::UnboundedSender<Command>,
+            command_tx: &CommandSender,
         ) -> bool {
+            // The slot is taken before the credit so a wait for local tasks
+            // does not hold credit a reconnect would have to discard.
+            // Connections wait in the listen backlog meanwhile.
+            let slot = self.slots.acquire().await;
             let reservation = self.limiter.reserve().await;
             match listener.accept().await {
                 Ok((stream, _)) => {
                     if !reservation.is_fresh() {
+                        AcceptTotals::bump(&self.limiter.counters.vetoed);
                         drop(stream);
                         return true;
                     };
-                    if command_tx
-                        .send(Command::NewStream {
-                            stream,
-                            reservation,
-                        })
-                        .is_err()
-                    {
-                        return false;
+                    let command = Command::NewStream {
+                        stream,
+                        reservation,
+                        slot,
+                        accepted_at: Instant::now(),
+                    };
+                    // The client loop is behind: hold the connection until
+                    // there is room, leaving later ones in the listen backlog.
+                    let queued = match command_tx.try_send(command) {
+                        Ok(()) => true,
+                        Err(TrySendError::Full(command)) => {
+                            AcceptTotals::bump(&self.limiter.counters.blocked_on_queue);
+                            command_tx.send(command).await.is_ok()
+                        }
+                        Err(TrySendError::Closed(_)) => false,
+                    };
+                    if queued {
+                        AcceptTotals::bump(&self.limiter.counters.accepted);
                     }
-                    true
+                    queued
                 }
                 Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
                     drop(reservation);
@@ -339,6 +733,7 @@ pub(crate) mod acceptor {
                 }
                 Err(err) => {
                     drop(reservation);
+                    AcceptTotals::bump(&self.limiter.counters.errors);
                     warn!(
                         "acceptor: accept failed kind={:?} err={}; keeping acceptor alive",
                         err.kind(),
@@ -353,20 +748,21 @@ pub(crate) mod acceptor {
 
     struct TcpAcceptor {
         listener: TokioTcpListener,
-        command_tx: mpsc::UnboundedSender<Command>,
+        command_tx: CommandSender,
         gate: AcceptorGate,
     }
 
     impl TcpAcceptor {
         fn new(
             listener: TokioTcpListener,
-            command_tx: mpsc::UnboundedSender<Command>,
+            command_tx: CommandSender,
             acceptor_backpressure: Arc<AcceptorLimiter>,
+            slots: Arc<StreamSlots>,
         ) -> Self {
             Self {
                 listener,
                 command_tx,
-                gate: AcceptorGate::new(acceptor_backpressure),
+                gate: AcceptorGate::new(acceptor_backpressure, slots),
             }
         }
 
@@ -389,8 +785,10 @@ pub(crate) mod acceptor {
 
     #[cfg(test)]
     mod tests {
-        use super::AcceptorLimiter;
-        use std::sync::Arc;
+        use super::{AcceptCounters, AcceptorLimiter, StreamSlots};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, RwLock};
+        use std::thread;
         use tokio::time::{timeout, Duration};
 
         #[test]
@@ -425,29 +823,229 @@ pub(crate) mod acceptor {
                 );
             });
         }
+
+        #[test]
+        fn reset_races_never_commit_stale_reservations_or_leak_credit() {
+            const LIMIT: usize = 4;
+            const WORKERS: usize = 4;
+            const ROUNDS: usize = 2_000;
+            const MIN_RESETS: usize = 200;
+
+            let limiter = Arc::new(AcceptorLimiter::new(LIMIT));
+            // Commits in the current generation. Resets take the write side,
+            // so a commit and its bookkeeping never straddle one.
+            let generation_lock = Arc::new(RwLock::new(()));
+            let committed = Arc::new(AtomicUsize::new(0));
+            let stale_commits = Arc::new(AtomicUsize::new(0));
+
+            let workers: Vec<_> = (0..WORKERS)
+                .map(|worker| {
+                    let limiter = Arc::clone(&limiter);
+                    let generation_lock = Arc::clone(&generation_lock);
+                    let committed = Arc::clone(&committed);
+                    let stale_commits = Arc::clone(&stale_commits);
+                    thread::spawn(move || {
+                        let rt = tokio::runtime::Builder::new_current_thread()
+                            .enable_time()
+                            .build()
+                            .expect("build tokio runtime");
+                        rt.block_on(async {
+                            for round in 0..ROUNDS {
+                                // Credit runs out until the next reset.
+                                let Ok(reservation) =
+                                    timeout(Duration::from_millis(1), limiter.reserve()).await
+                                else {
+                                    continue;
+                                };
+                                if (round + worker) % 3 == 0 {
+                                    drop(reservation);
+                                    continue;
+                                }
+                                let _guard = generation_lock.read().unwrap();
+                                let fresh = reservation.generation == limiter.generation();
+                                if reservation.commit() {
+                                    if !fresh {
+                                        stale_commits.fetch_add(1, Ordering::SeqCst);
+                                    }
+                                    let total = committed.fetch_add(1, Ordering::SeqCst) + 1;
+                                    assert!(
+                                        total <= LIMIT,
+                                        "{} commits over a limit of {}",
+                                        total,
+                                        LIMIT
+                                    );
+                                } else {
+                                    assert!(!fresh, "fresh reservation failed to commit");
+                                }
+                            }
+                        });
+                    })
+                })
+                .collect();
+
+            let mut resets = 0;
+            while resets < MIN_RESETS || workers.iter().any(|worker| !worker.is_finished()) {
+                thread::sleep(Duration::from_micros(50));
+                let _guard = generation_lock.write().unwrap();
+                limiter.reset();
+                committed.store(0, Ordering::SeqCst);
+                limiter.set_max(LIMIT);
+                resets += 1;
+            }
+            for worker in workers {
+                worker.join().expect("worker panicked");
+            }
+
+            assert_eq!(stale_commits.load(Ordering::SeqCst), 0);
+            // Every reservation is committed or dropped by now, so only the
+            // commits since the last reset may hold credit.
+            assert_eq!(
+                limiter.used.load(Ordering::SeqCst),
+                committed.load(Ordering::SeqCst)
+            );
+        }
+
+        #[test]
+        fn counters_separate_credit_waits_from_stale_reservations() {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()
+                .expect("build tokio runtime");
+            rt.block_on(async {
+                let limiter = Arc::new(AcceptorLimiter::new(1));
+                assert!(limiter.reserve().await.commit());
+                assert_eq!(limiter.counters.snapshot(), AcceptCounters::default());
+
+                let blocked = limiter.reserve();
+                assert!(timeout(Duration::from_millis(20), blocked).await.is_err());
+                limiter.set_max(2);
+                let reservation = limiter.reserve().await;
+                limiter.reset();
+                assert!(!reservation.commit());
+
+                let counters = limiter.counters.snapshot();
+                assert_eq!((counters.blocked_on_credit, counters.vetoed), (1, 1));
+            });
+
+            let earlier = AcceptCounters {
+                accepted: 10,
+                ..AcceptCounters::default()
+            };
+            let now = AcceptCounters {
+                accepted: 25,
+                ..AcceptCounters::default()
+            };
+            assert_eq!(now.accepts_per_sec(&earlier, 5_000_000), 3);
+            assert_eq!(now.accepts_per_sec(&earlier, 0), 0);
+        }
+
+        #[test]
+        fn stream_cap_waits_for_both_tasks_of_a_stream() {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()
+                .expect("build tokio runtime");
+            rt.block_on(async {
+                let slots = Arc::new(StreamSlots::new(2));
+                let reader = Arc::new(slots.acquire().await);
+                let _other = slots.acquire().await;
+                assert!(timeout(Duration::from_millis(20), slots.acquire())
+                    .await
+                    .is_err());
+
+                // The reader has ended; the writer still holds the slot.
+                let writer = Arc::clone(&reader);
+                drop(reader);
+                assert!(timeout(Duration::from_millis(20), slots.acquire())
+                    .await
+                    .is_err());
+                drop(writer);
+                let _next = timeout(Duration::from_secs(1), slots.acquire())
+                    .await
+                    .expect("slot should free once both tasks end");
+
+                assert_eq!(slots.live.load(Ordering::SeqCst), 2);
+                assert_eq!(slots.peak.load(Ordering::SeqCst), 2);
+                assert_eq!(slots.blocked.load(Ordering::SeqCst), 2);
+
+                let uncapped = Arc::new(StreamSlots::new(0));
+                let mut held = Vec::new();
+                for _ in 0..64 {
+                    held.push(uncapped.acquire().await);
+                }
+                drop(held);
+                assert_eq!(uncapped.live.load(Ordering::SeqCst), 0);
+                assert_eq!(uncapped.peak.load(Ordering::SeqCst), 64);
+                assert_eq!(uncapped.blocked.load(Ordering::SeqCst), 0);
+            });
+        }
     }
 }
 
 impl ClientState {
     pub(crate) fn new(
-        command_tx: mpsc::UnboundedSender<Command>,
+        command_tx: CommandSender,
         data_notify: Arc<Notify>,
         debug_streams: bool,
+        stream_path_affinity: bool,
         acceptor: acceptor::ClientAcceptor,
     ) -> Self {
         Self {
             ready: false,
+            ready_at: None,
             closing: false,
+            stateless_reset: false,
+            close_reasons: None,
             streams: HashMap::new(),
             multi_stream_mode: false,
             command_tx,
             data_notify,
             path_events: Vec::new(),
             debug_streams,
+            stream_checksums: false,
+            stream_trailers: false,
             acceptor,
             debug_enqueued_bytes: 0,
             debug_last_enqueue_at: 0,
             acceptor_limit_logged: false,
+            acceptor_paused: false,
+            stream_path_affinity,
+            streams_per_path: HashMap::from([(0, 0)]),
+            stream_paths: HashMap::new(),
+            path_resolvers: HashMap::new(),
+            connection_generation: 0,
+            quic_version: 0,
+            cipher_suite: 0,
+            version_negotiation_seen: false,
+            version_negotiation_count: 0,
+            first_stream_logged: false,
+            session: SessionCounters::default(),
+            ttfb: LatencyHistogram::new(),
+            aborts: DeferredAborts::new(),
+            fin_violations: 0,
+            stop_sending_half_close: false,
+            closing_timeout: Duration::ZERO,
+            stuck_closing: 0,
+            max_streams_floor: 0,
+            uncredited_streams: Vec::new(),
+            floor_overruns: 0,
+            stream_pool: StreamPool::default(),
+            read_chunk_bytes: STREAM_READ_CHUNK_BYTES,
+            #[cfg(any(test, feature = "fault-injection"))]
+            recorded_aborts: None,
+        }
+    }
+
+    pub(crate) fn stream_tag(&self, stream_id: u64) -> StreamTag {
+        let resolver = match self.stream_paths.get(&stream_id) {
+            Some(unique_path_id) => self.path_resolvers.get(unique_path_id),
+            None if self.path_resolvers.len() == 1 => self.path_resolvers.values().next(),
+            None => None,
+        };
+        StreamTag {
+            stream_id,
+            generation: self.connection_generation,
+            resolver: resolver.copied(),
         }
     }
 
@@ -455,20 +1053,213 @@ impl ClientState {
         self.ready
     }
 
+    /// Negotiated QUIC version and TLS cipher suite ID, captured at ready.
+    pub(crate) fn connection_info(&self) -> (u32, u16) {
+        (self.quic_version, self.cipher_suite)
+    }
+
+    /// Note a Version Negotiation packet from the server. Kept across
+    /// reconnects so a version mismatch stays visible after recovery.
+    pub(crate) fn record_version_negotiation(&mut self) -> u64 {
+        self.version_negotiation_seen = true;
+        self.version_negotiation_count = self.version_negotiation_count.saturating_add(1);
+        self.version_negotiation_count
+    }
+
+    pub(crate) fn version_negotiation_seen(&self) -> Option<u64> {
+        self.version_negotiation_seen
+            .then_some(self.version_negotiation_count)
+    }
+
+    pub(crate) fn session_counters(&self) -> SessionCounters {
+        self.session
+    }
+
+    pub(crate) fn record_key_updates(&mut self, completed: u64) {
+        self.session.key_updates = self.session.key_updates.saturating_add(completed);
+    }
+
+    pub(crate) fn accept_counters(&self) -> acceptor::AcceptCounters {
+        self.acceptor.counters()
+    }
+
+    /// Streams whose reader or writer task is still running, and the most
+    /// at once; each holds up to two tasks.
+    pub(crate) fn local_streams(&self) -> (usize, usize) {
+        self.acceptor.local_streams()
+    }
+
+    pub(crate) fn ttfb_percentiles(&self) -> LatencyPercentiles {
+        self.ttfb.percentiles()
+    }
+
     pub(crate) fn is_closing(&self) -> bool {
         self.closing
     }
 
+    pub(crate) fn closed_by_stateless_reset(&self) -> bool {
+        self.stateless_reset
+    }
+
+    /// Error codes of the close that set `closing`.
+    pub(crate) fn close_reasons(&self) -> Option<CloseReasons> {
+        self.close_reasons
+    }
+
     pub(crate) fn streams_len(&self) -> usize {
         self.streams.len()
     }
 
+    pub(crate) fn set_stream_checksums(&mut self, enabled: bool) {
+        self.stream_checksums = enabled;
+    }
+
+    pub(crate) fn set_stream_trailers(&mut self, enabled: bool) {
+        self.stream_trailers = enabled;
+    }
+
+    pub(crate) fn set_stop_sending_half_close(&mut self, enabled: bool) {
+        self.stop_sending_half_close = enabled;
+    }
+
+    pub(crate) fn set_closing_timeout(&mut self, timeout: Duration) {
+        self.closing_timeout = timeout;
+    }
+
+    /// Streams whose FIN had to be forced after `--closing-timeout-ms`.
+    pub(crate) fn stuck_closing_streams(&self) -> u64 {
+        self.stuck_closing
+    }
+
+    pub(crate) fn set_max_streams_floor(&mut self, floor: usize) {
+        self.max_streams_floor = floor;
+    }
+
+    /// Streams `--max-streams-floor` let through that the server's credit
+    /// did not cover.
+    pub(crate) fn max_streams_floor_overruns(&self) -> u64 {
+        self.floor_overruns
+    }
+
+    /// Size TCP reads of streams opened from now on to `mtu`.
+    pub(crate) fn set_path_mtu(&mut self, mtu: u32) {
+        self.read_chunk_bytes = stream_read_chunk_bytes(mtu);
+    }
+
+    pub(crate) fn set_stream_pool_size(&mut self, size: usize) {
+        self.stream_pool = StreamPool::new(size);
+    }
+
+    pub(crate) fn stream_pool_counters(&self) -> StreamPoolCounters {
+        self.stream_pool.counters()
+    }
+
+    /// While paused, MAX_STREAMS credit is not handed to the acceptor.
+    pub(crate) fn set_acceptor_paused(&mut self, paused: bool) {
+        self.acceptor_paused = paused;
+    }
+
+    /// Local connects waiting for credit while the ready connection grants
+    /// none. The peer never raises a MAX_STREAMS of zero by itself, so a
+    /// lasting non-zero value means the accepts hang until a reconnect.
+    pub(crate) fn credit_starved_accepts(&self) -> usize {
+        if !self.ready || self.acceptor_paused || self.acceptor.limit() > 0 {
+            return 0;
+        }
+        self.acceptor.waiting()
+    }
+
     pub(crate) fn update_acceptor_limit(&mut self, cnx: *mut picoquic_cnx_t) {
-        let max_streams = self.acceptor.update_limit(cnx);
+        if self.acceptor_paused {
+            self.acceptor.pause();
+            return;
+        }
+        let max_streams = self.acceptor.update_limit(cnx, self.stream_pool.burned());
         if !self.acceptor_limit_logged && max_streams > 0 {
             self.acceptor_limit_logged = true;
             info!("acceptor: initial_max_streams_bidir_remote={}", max_streams);
         }
+        self.reconcile_max_streams_floor(cnx, max_streams);
+        self.refill_stream_pool(cnx, max_streams);
+    }
+
+    /// Before ready, lets `--max-streams-floor` accepts through without
+    /// waiting for the server's credit. Their streams are opened right
+    /// away; picoquic holds back the frames of any the credit turns out
+    /// not to cover.
+    pub(crate) fn apply_max_streams_floor(&mut self) {
+        if self.ready || self.max_streams_floor == 0 {
+            return;
+        }
+        if self.acceptor_paused {
+            self.acceptor.pause();
+            return;
+        }
+        self.acceptor.allow_unconfirmed(self.max_streams_floor);
+    }
+
+    /// Drops the streams the floor opened past `max_streams`, the credit
+    /// the server actually granted. Their local sockets are reset now, but
+    /// the RESET_STREAM waits until the credit covers them: any frame on a
+    /// stream the server never allowed is a STREAM_LIMIT_ERROR.
+    fn reconcile_max_streams_floor(&mut self, cnx: *mut picoquic_cnx_t, max_streams: usize) {
+        if self.max_streams_floor == 0 {
+            return;
+        }
+        let credited =
+            |stream_id: u64| usize::try_from(stream_id / 4).is_ok_and(|index| index < max_streams);
+        let mut overruns: Vec<u64> = self
+            .streams
+            .keys()
+            .copied()
+            .filter(|stream_id| !credited(*stream_id))
+            .collect();
+        overruns.sort_unstable();
+        for stream_id in overruns {
+            self.streams.remove(&stream_id);
+            self.floor_overruns = self.floor_overruns.saturating_add(1);
+            warn!(
+                "{}: opened past the server's stream credit ({}); resetting it (max_streams_floor_overruns={})",
+                self.stream_tag(stream_id),
+                max_streams,
+                self.floor_overruns
+            );
+            self.uncredited_streams.push(stream_id);
+        }
+        let (covered, waiting): (Vec<u64>, Vec<u64>) = std::mem::take(&mut self.uncredited_streams)
+            .into_iter()
+            .partition(|stream_id| credited(*stream_id));
+        self.uncredited_streams = waiting;
+        for stream_id in covered {
+            self.abort_stream(
+                cnx,
+                stream_id,
+                SLIPSTREAM_INTERNAL_ERROR,
+                "stream credit overrun",
+            );
+        }
+    }
+
+    /// Open idle streams into the credit accepts do not hold. Nothing is
+    /// sent on them until an accept binds one.
+    fn refill_stream_pool(&mut self, cnx: *mut picoquic_cnx_t, max_streams: usize) {
+        let wanted = self
+            .stream_pool
+            .wanted(max_streams, self.acceptor.reserved());
+        for _ in 0..wanted {
+            let stream_id = unsafe { picoquic_get_next_local_stream_id(cnx, 0) };
+            let ret =
+                unsafe { picoquic_mark_active_stream(cnx, stream_id, 0, std::ptr::null_mut()) };
+            if ret != 0 {
+                warn!(
+                    "{}: could not open pooled stream ret={}",
+                    self.stream_tag(stream_id),
+                    ret
+                );
+                return;
+            }
+            self.stream_pool.push(stream_id);
+        }
     }
 
     pub(crate) fn debug_snapshot(&self) -> (u64, u64) {
@@ -508,7 +1299,6 @@ impl ClientState {
         let mut summaries = Vec::new();
         for (stream_id, stream) in self.streams.iter() {
             let queued_bytes = stream.flow.queued_bytes as u64;
-            let has_data_rx = stream.data_rx.is_some();
             let unconsumed = stream
                 .flow
                 .rx_bytes
@@ -519,19 +1309,7 @@ impl ClientState {
                 || stream.flow.discarding
                 || unconsumed > 0
             {
-                summaries.push(ClientBacklogSummary {
-                    stream_id: *stream_id,
-                    queued_bytes,
-                    rx_bytes: stream.flow.rx_bytes,
-                    consumed_offset: stream.flow.consumed_offset,
-                    fin_offset: stream.flow.fin_offset,
-                    recv_state: stream.recv_state,
-                    send_state: stream.send_state,
-                    stop_sending_sent: stream.flow.stop_sending_sent,
-                    discarding: stream.flow.discarding,
-                    has_data_rx,
-                    tx_bytes: stream.tx_bytes,
-                });
+                summaries.push(backlog_summary(*stream_id, stream));
                 if summaries.len() >= limit {
                     break;
                 }
@@ -540,49 +1318,305 @@ impl ClientState {
         summaries
     }
 
-    pub(crate) fn take_path_events(&mut self) -> Vec<PathEvent> {
-        std::mem::take(&mut self.path_events)
-    }
-
-    pub(crate) fn reset_for_reconnect(&mut self) {
-        let debug_streams = self.debug_streams;
-        for (stream_id, mut stream) in self.streams.drain() {
-            if let Some(read_abort_tx) = stream.read_abort_tx.take() {
-                let _ = read_abort_tx.send(());
-            }
-            let _ = stream.write_tx.send(StreamWrite::Fin);
-            if debug_streams {
-                debug!("stream {}: closing due to reconnect", stream_id);
+    /// Re-mark every tracked stream active; returns how many picoquic accepted.
+    pub(crate) fn mark_streams_active(&self, cnx: *mut picoquic_cnx_t) -> usize {
+        let mut marked = 0usize;
+        for stream_id in self.streams.keys() {
+            let ret =
+                unsafe { picoquic_mark_active_stream(cnx, *stream_id, 1, std::ptr::null_mut()) };
+            if ret == 0 {
+                marked += 1;
+            } else if self.debug_streams {
+                debug!(
+                    "{}: mark_active_stream during zero-send recovery failed ret={}",
+                    self.stream_tag(*stream_id),
+                    ret
+                );
             }
         }
-        self.ready = false;
-        self.closing = false;
-        self.multi_stream_mode = false;
-        self.path_events.clear();
-        self.acceptor.reset();
-        self.debug_enqueued_bytes = 0;
-        self.debug_last_enqueue_at = 0;
-        self.acceptor_limit_logged = false;
+        marked
     }
-}
 
-fn report_invariant<F>(message: F)
-where
-    F: FnOnce() -> String,
-{
-    let now = unsafe { picoquic_current_time() };
-    INVARIANT_REPORTER.report(now, message, |msg| error!("{}", msg));
-}
+    /// Stream with the largest local backlog (queued plus unconsumed bytes);
+    /// ties go to the oldest stream.
+    fn worst_stream_id(&self) -> Option<u64> {
+        self.streams
+            .iter()
+            .max_by_key(|(stream_id, stream)| {
+                let unconsumed = stream
+                    .flow
+                    .rx_bytes
+                    .saturating_sub(stream.flow.consumed_offset);
+                let backlog = (stream.flow.queued_bytes as u64).saturating_add(unconsumed);
+                (backlog, std::cmp::Reverse(**stream_id))
+            })
+            .map(|(stream_id, _)| *stream_id)
+    }
 
-fn check_stream_invariants(state: &ClientState, stream_id: u64, context: &str) {
-    let Some(stream) = state.streams.get(&stream_id) else {
-        return;
-    };
-    if stream.send_state != StreamSendState::Open && stream.data_rx.is_some() {
+    /// Abort `stream_id` in both directions. A refusal is logged with
+    /// `context`, the reason for the abort, and a transient one is retried
+    /// by `retry_deferred_aborts`.
+    pub(crate) fn abort_stream(
+        &mut self,
+        cnx: *mut picoquic_cnx_t,
+        stream_id: u64,
+        app_error: u64,
+        context: &'static str,
+    ) {
+        #[cfg(any(test, feature = "fault-injection"))]
+        if let Some(recorded) = self.recorded_aborts.as_mut() {
+            recorded.push((stream_id, app_error));
+            return;
+        }
+        let Err(err) = (unsafe { abort_stream_bidi(cnx, stream_id, app_error) }) else {
+            return;
+        };
+        if self.aborts.record_failure(cnx, stream_id, app_error, err) {
+            warn!(
+                "{}: abort after {} failed ({}); retrying",
+                self.stream_tag(stream_id),
+                context,
+                err
+            );
+        } else {
+            debug!(
+                "{}: abort after {} refused ({}); that side is already closed",
+                self.stream_tag(stream_id),
+                context,
+                err
+            );
+        }
+    }
+
+    /// Honour the server's STOP_SENDING by resetting only our send side.
+    /// The local reader stops and whatever it had queued is dropped, while
+    /// data from the server keeps reaching the local socket under the usual
+    /// flow control until its FIN.
+    fn reset_send_side(&mut self, cnx: *mut picoquic_cnx_t, stream_id: u64) {
+        let tag = self.stream_tag(stream_id);
+        let Some(stream) = self.streams.get_mut(&stream_id) else {
+            return;
+        };
+        if let Some(read_abort_tx) = stream.read_abort_tx.take() {
+            let _ = read_abort_tx.send(());
+        }
+        stream.data_rx = None;
+        stream.send_state = StreamSendState::Stopped;
+        let finished = stream.recv_state.is_closed() && stream.flow.queued_bytes == 0;
+        if self.debug_streams {
+            debug!(
+                "{}: stop_sending; send side reset, receive side kept rx_bytes={} queued={}",
+                tag, stream.flow.rx_bytes, stream.flow.queued_bytes
+            );
+        }
+        if finished {
+            self.streams.remove(&stream_id);
+        }
+        #[cfg(any(test, feature = "fault-injection"))]
+        if let Some(recorded) = self.recorded_aborts.as_mut() {
+            recorded.push((stream_id, SLIPSTREAM_FILE_CANCEL_ERROR));
+            return;
+        }
+        let ret = unsafe { picoquic_reset_stream(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR) };
+        if ret != 0 {
+            debug!("{}: reset_stream after stop_sending ret={}", tag, ret);
+        }
+    }
+
+    /// Retry aborts picoquic refused earlier; called once per loop iteration.
+    pub(crate) fn retry_deferred_aborts(&mut self) {
+        if self.aborts.is_empty() {
+            return;
+        }
+        self.retry_deferred_aborts_with(|cnx, stream_id, app_error| unsafe {
+            abort_stream_bidi(cnx, stream_id, app_error)
+        });
+    }
+
+    fn retry_deferred_aborts_with<F>(&mut self, abort: F)
+    where
+        F: FnMut(*mut picoquic_cnx_t, u64, u64) -> Result<(), AbortError>,
+    {
+        for (_, stream_id, outcome) in self.aborts.retry_with(abort) {
+            let tag = self.stream_tag(stream_id);
+            match outcome {
+                AbortRetryOutcome::Succeeded { attempts } => {
+                    info!("{}: abort went through on retry {}", tag, attempts);
+                }
+                AbortRetryOutcome::GaveUp { attempts, error } => {
+                    warn!(
+                        "{}: giving up on abort after {} retries ({})",
+                        tag, attempts, error
+                    );
+                }
+            }
+        }
+    }
+
+    /// Refused aborts, ones that went through on retry, and ones given up.
+    pub(crate) fn abort_counters(&self) -> (u64, u64, u64) {
+        (
+            self.aborts.failures(),
+            self.aborts.retried(),
+            self.aborts.abandoned(),
+        )
+    }
+
+    /// Abort the worst stream (see `worst_stream_id`) and drop its state.
+    pub(crate) fn reset_worst_stream(&mut self, cnx: *mut picoquic_cnx_t) -> Option<u64> {
+        let stream_id = self.worst_stream_id()?;
+        self.abort_stream(
+            cnx,
+            stream_id,
+            SLIPSTREAM_INTERNAL_ERROR,
+            "worst-stream reset",
+        );
+        self.streams.remove(&stream_id);
+        Some(stream_id)
+    }
+
+    pub(crate) fn take_path_events(&mut self) -> Vec<PathEvent> {
+        std::mem::take(&mut self.path_events)
+    }
+
+    pub(crate) fn reset_for_reconnect(&mut self) {
+        let debug_streams = self.debug_streams;
+        let streams: Vec<_> = self.streams.drain().collect();
+        for (stream_id, mut stream) in streams {
+            if let Some(read_abort_tx) = stream.read_abort_tx.take() {
+                let _ = read_abort_tx.send(());
+            }
+            let _ = stream.write_tx.send(StreamWrite::Fin);
+            if debug_streams {
+                debug!("{}: closing due to reconnect", self.stream_tag(stream_id));
+            }
+        }
+        self.ready = false;
+        self.ready_at = None;
+        self.closing = false;
+        self.stateless_reset = false;
+        self.close_reasons = None;
+        self.multi_stream_mode = false;
+        self.path_events.clear();
+        self.acceptor.reset();
+        self.debug_enqueued_bytes = 0;
+        self.debug_last_enqueue_at = 0;
+        self.acceptor_limit_logged = false;
+        self.streams_per_path = HashMap::from([(0, 0)]);
+        self.stream_paths.clear();
+        self.path_resolvers.clear();
+        self.connection_generation = self.connection_generation.wrapping_add(1);
+        self.quic_version = 0;
+        self.cipher_suite = 0;
+        self.first_stream_logged = false;
+        self.aborts.clear();
+        self.stream_pool.clear();
+        self.uncredited_streams.clear();
+    }
+
+    /// Replace the set of paths new streams may be pinned to, as unique path
+    /// IDs with the resolver behind each.
+    pub(crate) fn sync_stream_paths<I>(&mut self, paths: I)
+    where
+        I: IntoIterator<Item = (u64, SocketAddr)>,
+    {
+        self.path_resolvers = paths.into_iter().collect();
+        let mut paths: HashMap<u64, usize> = self
+            .path_resolvers
+            .keys()
+            .map(|unique_path_id| (*unique_path_id, 0))
+            .collect();
+        self.stream_paths
+            .retain(|_, unique_path_id| paths.contains_key(unique_path_id));
+        for unique_path_id in self.stream_paths.values() {
+            if let Some(count) = paths.get_mut(unique_path_id) {
+                *count += 1;
+            }
+        }
+        self.streams_per_path = paths;
+    }
+
+    /// Drop affinity records for streams that are gone and recount per path.
+    fn refresh_stream_path_counts(&mut self) {
+        let streams = &self.streams;
+        self.stream_paths
+            .retain(|stream_id, _| streams.contains_key(stream_id));
+        for count in self.streams_per_path.values_mut() {
+            *count = 0;
+        }
+        for unique_path_id in self.stream_paths.values() {
+            if let Some(count) = self.streams_per_path.get_mut(unique_path_id) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Path with the fewest active streams; ties go to the lowest unique ID.
+    fn least_loaded_path(&self) -> Option<u64> {
+        self.streams_per_path
+            .iter()
+            .min_by_key(|(unique_path_id, count)| (**count, **unique_path_id))
+            .map(|(unique_path_id, _)| *unique_path_id)
+    }
+
+    fn assign_stream_path(&mut self, cnx: *mut picoquic_cnx_t, stream_id: u64) {
+        if !self.stream_path_affinity || self.streams_per_path.len() < 2 {
+            return;
+        }
+        self.refresh_stream_path_counts();
+        let Some(unique_path_id) = self.least_loaded_path() else {
+            return;
+        };
+        let ret = unsafe { picoquic_set_stream_path_affinity(cnx, stream_id, unique_path_id) };
+        if ret != 0 {
+            warn!(
+                "{}: set_stream_path_affinity failed unique_path_id={} ret={}",
+                self.stream_tag(stream_id),
+                unique_path_id,
+                ret
+            );
+            return;
+        }
+        self.stream_paths.insert(stream_id, unique_path_id);
+        if let Some(count) = self.streams_per_path.get_mut(&unique_path_id) {
+            *count += 1;
+        }
+        if self.debug_streams {
+            debug!(
+                "{}: pinned to unique_path_id={}",
+                self.stream_tag(stream_id),
+                unique_path_id
+            );
+        }
+    }
+}
+
+fn report_invariant<F>(message: F)
+where
+    F: FnOnce() -> String,
+{
+    INVARIANT_REPORTER.report(&PicoquicClock, message, |msg| error!("{}", msg));
+}
+
+/// Peer misbehaviour goes through the invariant reporter for rate limiting,
+/// but must not panic the way our own broken invariants do in tests.
+fn report_peer_violation<F>(message: F)
+where
+    F: FnOnce() -> String,
+{
+    INVARIANT_REPORTER.report_peer(&PicoquicClock, message, |msg| warn!("{}", msg));
+}
+
+fn check_stream_invariants(state: &ClientState, stream_id: u64, context: &str) {
+    let Some(stream) = state.streams.get(&stream_id) else {
+        return;
+    };
+    let tag = state.stream_tag(stream_id);
+    if stream.send_state != StreamSendState::Open && stream.data_rx.is_some() {
         report_invariant(|| {
             format!(
-                "client invariant violated: send_state closed with data_rx stream={} context={} send_state={:?} queued={} discarding={} tx_bytes={}",
-                stream_id,
+                "client invariant violated: send_state closed with data_rx {:#} context={} send_state={:?} queued={} discarding={} tx_bytes={}",
+                tag,
                 context,
                 stream.send_state,
                 stream.flow.queued_bytes,
@@ -594,8 +1628,8 @@ fn check_stream_invariants(state: &ClientState, stream_id: u64, context: &str) {
     if stream.send_state == StreamSendState::Open && stream.data_rx.is_none() {
         report_invariant(|| {
             format!(
-                "client invariant violated: send_state open without data_rx stream={} context={} send_state={:?} recv_state={:?} queued={} discarding={} tx_bytes={}",
-                stream_id,
+                "client invariant violated: send_state open without data_rx {:#} context={} send_state={:?} recv_state={:?} queued={} discarding={} tx_bytes={}",
+                tag,
                 context,
                 stream.send_state,
                 stream.recv_state,
@@ -608,8 +1642,8 @@ fn check_stream_invariants(state: &ClientState, stream_id: u64, context: &str) {
     if stream.recv_state == StreamRecvState::FinReceived && stream.flow.fin_offset.is_none() {
         report_invariant(|| {
             format!(
-                "client invariant violated: recv_state fin without fin_offset stream={} context={} recv_state={:?} rx_bytes={} queued={} tx_bytes={}",
-                stream_id,
+                "client invariant violated: recv_state fin without fin_offset {:#} context={} recv_state={:?} rx_bytes={} queued={} tx_bytes={}",
+                tag,
                 context,
                 stream.recv_state,
                 stream.flow.rx_bytes,
@@ -628,6 +1662,30 @@ struct ClientStream {
     recv_state: StreamRecvState,
     send_state: StreamSendState,
     flow: FlowControlState,
+    timing: StreamTiming,
+}
+
+/// When a stream passed each step towards its first response byte.
+struct StreamTiming {
+    accepted_at: Instant,
+    activated_at: Instant,
+    first_up_at: Option<Instant>,
+    first_down_at: Option<Instant>,
+    /// When the reader finished and the send side entered `Closing`.
+    closing_at: Option<Instant>,
+}
+
+impl StreamTiming {
+    /// For a stream that was accepted at `accepted_at` and activated now.
+    fn new(accepted_at: Instant) -> Self {
+        Self {
+            accepted_at,
+            activated_at: Instant::now(),
+            first_up_at: None,
+            first_down_at: None,
+            closing_at: None,
+        }
+    }
 }
 
 impl HasFlowControlState for ClientStream {
@@ -649,6 +1707,8 @@ pub(crate) enum Command {
     NewStream {
         stream: TokioTcpStream,
         reservation: acceptor::AcceptorReservation,
+        slot: acceptor::StreamSlot,
+        accepted_at: Instant,
     },
     StreamData {
         stream_id: u64,
@@ -667,6 +1727,11 @@ pub(crate) enum Command {
         stream_id: u64,
         bytes: usize,
     },
+    /// The server's data failed its `--debug-stream-trailers` check.
+    StreamChecksumMismatch {
+        stream_id: u64,
+        detail: String,
+    },
 }
 
 pub(crate) enum PathEvent {
@@ -695,12 +1760,21 @@ pub(crate) unsafe extern "C" fn client_callback(
     if callback_ctx.is_null() {
         return 0;
     }
-    let state = &mut *(callback_ctx as *mut ClientState);
+    let state: &mut ClientState = from_callback_ctx(callback_ctx);
 
     match fin_or_event {
         picoquic_call_back_event_t::picoquic_callback_ready => {
             state.ready = true;
-            info!("Connection ready");
+            state.ready_at = Some(Instant::now());
+            state.quic_version = slipstream_get_negotiated_version(cnx);
+            state.cipher_suite = slipstream_get_cipher_suite(cnx);
+            info!(
+                "Connection ready gen={} quic_version=0x{:08x} cipher={} (0x{:04x})",
+                state.connection_generation,
+                state.quic_version,
+                tls_cipher_suite_name(state.cipher_suite),
+                state.cipher_suite
+            );
             state.update_acceptor_limit(cnx);
         }
         picoquic_call_back_event_t::picoquic_callback_stream_data
@@ -716,6 +1790,12 @@ pub(crate) unsafe extern "C" fn client_callback(
             };
             handle_stream_data(cnx, state, stream_id, fin, data);
         }
+        picoquic_call_back_event_t::picoquic_callback_stop_sending
+            if state.stop_sending_half_close && state.streams.contains_key(&stream_id) =>
+        {
+            state.reset_send_side(cnx, stream_id);
+            check_stream_invariants(state, stream_id, "stop_sending");
+        }
         picoquic_call_back_event_t::picoquic_callback_stream_reset
         | picoquic_call_back_event_t::picoquic_callback_stop_sending => {
             let reason = match fin_or_event {
@@ -723,31 +1803,42 @@ pub(crate) unsafe extern "C" fn client_callback(
                 picoquic_call_back_event_t::picoquic_callback_stop_sending => "stop_sending",
                 _ => "unknown",
             };
-            if let Some(stream) = state.streams.remove(&stream_id) {
-                warn!(
-                    "stream {}: reset event={} rx_bytes={} tx_bytes={} queued={} consumed_offset={} fin_offset={:?} recv_state={:?} send_state={:?}",
-                    stream_id,
-                    reason,
-                    stream.flow.rx_bytes,
-                    stream.tx_bytes,
-                    stream.flow.queued_bytes,
-                    stream.flow.consumed_offset,
-                    stream.flow.fin_offset,
-                    stream.recv_state,
-                    stream.send_state
-                );
-            } else {
-                warn!(
-                    "stream {}: reset event={} (unknown stream)",
-                    stream_id, reason
-                );
+            let tag = state.stream_tag(stream_id);
+            if state.stream_pool.forfeit(stream_id) {
+                warn!("{}: pooled stream got {} before use", tag, reason);
+                let _ = picoquic_reset_stream(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR);
+                return 0;
             }
+            let stream = state.streams.remove(&stream_id);
+            RESET_LOG.log(
+                &PicoquicClock,
+                || match stream {
+                    Some(stream) => format!(
+                        "{}: reset event={} rx_bytes={} tx_bytes={} queued={} consumed_offset={} fin_offset={:?} recv_state={:?} send_state={:?}",
+                        tag,
+                        reason,
+                        stream.flow.rx_bytes,
+                        stream.tx_bytes,
+                        stream.flow.queued_bytes,
+                        stream.flow.consumed_offset,
+                        stream.flow.fin_offset,
+                        stream.recv_state,
+                        stream.send_state
+                    ),
+                    None => format!("{}: reset event={} (unknown stream)", tag, reason),
+                },
+                |msg| warn!("{}", msg),
+            );
             let _ = picoquic_reset_stream(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR);
         }
         picoquic_call_back_event_t::picoquic_callback_close
         | picoquic_call_back_event_t::picoquic_callback_application_close
         | picoquic_call_back_event_t::picoquic_callback_stateless_reset => {
             state.closing = true;
+            state.stateless_reset = matches!(
+                fin_or_event,
+                picoquic_call_back_event_t::picoquic_callback_stateless_reset
+            );
             let mut local_reason = 0u64;
             let mut remote_reason = 0u64;
             let mut local_app_reason = 0u64;
@@ -762,8 +1853,13 @@ pub(crate) unsafe extern "C" fn client_callback(
                     &mut remote_app_reason,
                 );
             }
-            warn!(
-                "Connection closed event={} state={:?} local_error=0x{:x} remote_error=0x{:x} local_app=0x{:x} remote_app=0x{:x} ready={}",
+            state.close_reasons = Some(CloseReasons {
+                local: local_reason,
+                remote: remote_reason,
+            });
+            let message = format!(
+                "Connection closed gen={} event={} state={:?} local_error=0x{:x} remote_error=0x{:x} local_app=0x{:x} remote_app=0x{:x} ready={}",
+                state.connection_generation,
                 close_event_label(fin_or_event),
                 cnx_state,
                 local_reason,
@@ -772,10 +1868,35 @@ pub(crate) unsafe extern "C" fn client_callback(
                 remote_app_reason,
                 state.ready
             );
+            // The runtime warns about stateless resets once it has weighed
+            // them against `--stateless-reset-tolerance`.
+            if state.stateless_reset {
+                info!("{}", message);
+            } else {
+                warn!("{}", message);
+            }
         }
-        picoquic_call_back_event_t::picoquic_callback_prepare_to_send => {
-            if !bytes.is_null() {
-                let _ = picoquic_provide_stream_data_buffer(bytes as *mut _, 0, 0, 0);
+        picoquic_call_back_event_t::picoquic_callback_prepare_to_send if !bytes.is_null() => {
+            // Upload data goes to picoquic through picoquic_add_to_stream
+            // (see `Command::StreamData`), so nothing is ever waiting here.
+            // Streams only get this callback because they were marked
+            // active, on accept or in zero-send recovery; answering with no
+            // data and not active hands them back to the queued-data path.
+            // See "Stream send models" in docs/design.md for why the client
+            // does not fill the buffer the way the server does.
+            let buffer = if fault_injection::provide_stream_data_buffer_failure() {
+                std::ptr::null_mut()
+            } else {
+                picoquic_provide_stream_data_buffer(bytes as *mut _, 0, 0, 0)
+            };
+            // An empty answer always fits. Should it still be refused, the
+            // stream goes inactive all the same: picoquic takes a callback
+            // that wrote nothing as having nothing to send.
+            if buffer.is_null() {
+                warn!(
+                    "{}: provide_stream_data_buffer returned null for an empty answer",
+                    state.stream_tag(stream_id)
+                );
             }
         }
         picoquic_call_back_event_t::picoquic_callback_path_available => {
@@ -798,6 +1919,7 @@ fn handle_stream_data(
     data: &[u8],
 ) {
     let debug_streams = state.debug_streams;
+    let tag = state.stream_tag(stream_id);
     let mut reset_stream = false;
     let mut remove_stream = false;
     let multi_stream = state.multi_stream_mode;
@@ -810,14 +1932,39 @@ fn handle_stream_data(
     {
         let Some(stream) = state.streams.get_mut(&stream_id) else {
             warn!(
-                "stream {}: data for unknown stream len={} fin={}",
-                stream_id,
+                "{}: data for unknown stream len={} fin={}",
+                tag,
                 data.len(),
                 fin
             );
-            unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR) };
+            state.abort_stream(
+                cnx,
+                stream_id,
+                SLIPSTREAM_FILE_CANCEL_ERROR,
+                "data for unknown stream",
+            );
             return;
         };
+        if exceeds_fin_offset(stream.flow.fin_offset, stream.flow.rx_bytes, data.len()) {
+            let (rx_bytes, fin_offset) = (stream.flow.rx_bytes, stream.flow.fin_offset);
+            state.fin_violations = state.fin_violations.saturating_add(1);
+            let violations = state.fin_violations;
+            report_peer_violation(|| {
+                format!(
+                    "client protocol violation: data past fin {:#} rx_bytes={} len={} fin_offset={:?} fin={} violations={}",
+                    tag,
+                    rx_bytes,
+                    data.len(),
+                    fin_offset,
+                    fin,
+                    violations
+                )
+            });
+            state.abort_stream(cnx, stream_id, SLIPSTREAM_FINAL_SIZE_ERROR, "data past fin");
+            state.streams.remove(&stream_id);
+            return;
+        }
+        state.session.bytes_down = state.session.bytes_down.saturating_add(data.len() as u64);
 
         if handle_stream_receive(
             stream,
@@ -830,12 +1977,18 @@ fn handle_stream_data(
                         .send(StreamWrite::Data(data.to_vec()))
                         .is_err()
                     {
-                        warn!(
-                            "stream {}: tcp write channel closed queued={} rx_bytes={} tx_bytes={}",
-                            stream_id,
-                            stream.flow.queued_bytes,
-                            stream.flow.rx_bytes,
-                            stream.tx_bytes
+                        WRITE_CHANNEL_LOG.log(
+                            &PicoquicClock,
+                            || {
+                                format!(
+                                    "{}: tcp write channel closed queued={} rx_bytes={} tx_bytes={}",
+                                    tag,
+                                    stream.flow.queued_bytes,
+                                    stream.flow.rx_bytes,
+                                    stream.tx_bytes
+                                )
+                            },
+                            |msg| warn!("{}", msg),
                         );
                         Err(())
                     } else {
@@ -846,20 +1999,18 @@ fn handle_stream_data(
                     let (drain_tx, _drain_rx) = mpsc::unbounded_channel();
                     stream.write_tx = drain_tx;
                 },
-                consume: |new_offset| unsafe {
-                    picoquic_stream_data_consumed(cnx, stream_id, new_offset)
-                },
+                consume: |new_offset| stream_data_consumed(cnx, stream_id, new_offset),
                 stop_sending: || {
                     let _ =
                         unsafe { picoquic_stop_sending(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
                 },
                 log_overflow: |queued, incoming, max| {
-                    warn!("{}", overflow_log_message(stream_id, queued, incoming, max));
+                    warn!("{}", overflow_log_message(tag, queued, incoming, max));
                 },
                 on_consume_error: |ret, current, target| {
                     warn!(
                         "{}",
-                        consume_error_log_message(stream_id, "", ret, current, target)
+                        consume_error_log_message(tag, "", ret, current, target)
                     );
                 },
             },
@@ -877,11 +2028,8 @@ fn handle_stream_data(
                 if stream.recv_state == StreamRecvState::Open {
                     if stream.write_tx.send(StreamWrite::Fin).is_err() {
                         warn!(
-                            "stream {}: tcp write channel closed on fin queued={} rx_bytes={} tx_bytes={}",
-                            stream_id,
-                            stream.flow.queued_bytes,
-                            stream.flow.rx_bytes,
-                            stream.tx_bytes
+                            "{}: tcp write channel closed on fin queued={} rx_bytes={} tx_bytes={}",
+                            tag, stream.flow.queued_bytes, stream.flow.rx_bytes, stream.tx_bytes
                         );
                         reset_stream = true;
                     } else {
@@ -903,13 +2051,13 @@ fn handle_stream_data(
 
     if reset_stream {
         if debug_streams {
-            debug!("stream {}: resetting", stream_id);
+            debug!("{}: resetting", tag);
         }
-        unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR) };
+        state.abort_stream(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR, "stream reset");
         state.streams.remove(&stream_id);
     } else if remove_stream {
         if debug_streams {
-            debug!("stream {}: finished", stream_id);
+            debug!("{}: finished", tag);
         }
         state.streams.remove(&stream_id);
     }
@@ -917,48 +2065,49 @@ fn handle_stream_data(
     check_stream_invariants(state, stream_id, "handle_stream_data");
 }
 
-#[cfg(test)]
-mod test_hooks {
-    use slipstream_core::test_support::FailureCounter;
-
-    pub(super) const FORCED_ADD_TO_STREAM_ERROR: i32 = -1;
-    pub(super) const FORCED_MARK_ACTIVE_STREAM_ERROR: i32 = 0x400 + 36;
-    pub(super) static ADD_TO_STREAM_FAILS_LEFT: FailureCounter = FailureCounter::new();
-    pub(super) static MARK_ACTIVE_STREAM_FAILS_LEFT: FailureCounter = FailureCounter::new();
-
-    pub(super) fn set_add_to_stream_failures(count: usize) {
-        ADD_TO_STREAM_FAILS_LEFT.set(count);
-    }
-
-    pub(super) fn set_mark_active_stream_failures(count: usize) {
-        MARK_ACTIVE_STREAM_FAILS_LEFT.set(count);
-    }
-
-    pub(super) fn take_add_to_stream_failure() -> bool {
-        ADD_TO_STREAM_FAILS_LEFT.take()
-    }
-
-    pub(super) fn take_mark_active_stream_failure() -> bool {
-        MARK_ACTIVE_STREAM_FAILS_LEFT.take()
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use slipstream_core::callback_state::CallbackState;
     use slipstream_core::test_support::ResetOnDrop;
     use std::sync::Arc;
     use tokio::net::TcpListener as TokioTcpListener;
     use tokio::sync::{mpsc, oneshot, Notify};
     use tokio::time::{sleep, timeout, Duration};
 
+    #[test]
+    fn read_chunks_follow_the_mtu_within_the_same_byte_budget() {
+        use slipstream_core::tcp::{read_limit_chunks, STREAM_READ_BUFFER_MIN_BYTES};
+
+        assert_eq!(stream_read_chunk_bytes(130), 512);
+        assert_eq!(stream_read_chunk_bytes(1_200), 4_096);
+        assert_eq!(stream_read_chunk_bytes(0), STREAM_READ_CHUNK_MIN_BYTES);
+        assert_eq!(
+            stream_read_chunk_bytes(u32::MAX),
+            STREAM_READ_CHUNK_MAX_BYTES
+        );
+        for buffer_bytes in [DEFAULT_TCP_RCVBUF_BYTES, 8 * 1024 * 1024] {
+            let budget =
+                read_limit_chunks(buffer_bytes, STREAM_READ_CHUNK_BYTES) * STREAM_READ_CHUNK_BYTES;
+            assert!(budget >= STREAM_READ_BUFFER_MIN_BYTES);
+            for mtu in [60, 130, 200, 900, 1_200, 1_452, 9_000, 65_535] {
+                let chunk = stream_read_chunk_bytes(mtu);
+                assert_eq!(
+                    read_limit_chunks(buffer_bytes, chunk) * chunk,
+                    budget,
+                    "mtu {mtu}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn add_to_stream_fin_failure_removes_stream() {
-        let _guard = ResetOnDrop::new(|| test_hooks::set_add_to_stream_failures(0));
-        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let _guard = ResetOnDrop::new(|| fault_injection::set_add_to_stream_failures(0));
+        let (command_tx, _command_rx) = command_channel(0);
         let data_notify = Arc::new(Notify::new());
         let acceptor = acceptor::ClientAcceptor::new();
-        let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
         let stream_id = 4;
         let (write_tx, _write_rx) = mpsc::unbounded_channel();
         let (read_abort_tx, _read_abort_rx) = oneshot::channel();
@@ -973,14 +2122,15 @@ mod tests {
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::Open,
                 flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
             },
         );
 
-        test_hooks::set_add_to_stream_failures(1);
+        fault_injection::set_add_to_stream_failures(1);
 
         handle_command(
             std::ptr::null_mut(),
-            &mut state as *mut _,
+            &mut state,
             Command::StreamClosed { stream_id },
         );
 
@@ -991,103 +2141,554 @@ mod tests {
     }
 
     #[test]
-    fn remote_fin_keeps_local_read_open() {
-        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+    fn consume_failure_resets_the_stream() {
+        let _guard = ResetOnDrop::new(|| fault_injection::set_stream_data_consumed_failures(0));
+        let (command_tx, _command_rx) = command_channel(0);
         let data_notify = Arc::new(Notify::new());
         let acceptor = acceptor::ClientAcceptor::new();
-        let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
-        let stream_id = 4;
-        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        state.recorded_aborts = Some(Vec::new());
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
         let (read_abort_tx, _read_abort_rx) = oneshot::channel();
-        let (_data_tx, data_rx) = mpsc::channel(1);
-
         state.streams.insert(
-            stream_id,
+            4,
             ClientStream {
                 write_tx,
                 read_abort_tx: Some(read_abort_tx),
-                data_rx: Some(data_rx),
+                data_rx: None,
                 tx_bytes: 0,
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::Open,
                 flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
             },
         );
 
-        handle_stream_data(std::ptr::null_mut(), &mut state, stream_id, true, &[]);
+        // Single-stream mode hands the credit back as the data arrives.
+        fault_injection::set_stream_data_consumed_failures(1);
+        handle_stream_data(0x10 as *mut picoquic_cnx_t, &mut state, 4, false, b"down");
 
-        let stream = state
-            .streams
-            .get(&stream_id)
-            .expect("stream should remain after remote fin");
-        assert_eq!(stream.recv_state, StreamRecvState::FinReceived);
-        assert_eq!(stream.send_state, StreamSendState::Open);
-        assert!(
-            stream.data_rx.is_some(),
-            "local TCP read side should stay open after remote fin"
-        );
-        assert!(
-            matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)),
-            "expected a TCP fin to be enqueued"
+        assert!(!state.streams.contains_key(&4));
+        assert_eq!(
+            state.recorded_aborts.as_deref(),
+            Some(&[(4, SLIPSTREAM_FILE_CANCEL_ERROR)][..])
         );
     }
 
     #[test]
-    fn stream_removal_requires_both_halves_closed() {
-        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+    fn refused_empty_send_buffer_keeps_the_stream() {
+        let _guard =
+            ResetOnDrop::new(|| fault_injection::set_provide_stream_data_buffer_failures(0));
+        let (command_tx, _command_rx) = command_channel(0);
         let data_notify = Arc::new(Notify::new());
         let acceptor = acceptor::ClientAcceptor::new();
-        let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
-        let stream_id = 4;
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
         let (write_tx, _write_rx) = mpsc::unbounded_channel();
         let (read_abort_tx, _read_abort_rx) = oneshot::channel();
-        let (_data_tx, data_rx) = mpsc::channel(1);
-
         state.streams.insert(
-            stream_id,
+            4,
             ClientStream {
                 write_tx,
                 read_abort_tx: Some(read_abort_tx),
-                data_rx: Some(data_rx),
+                data_rx: None,
                 tx_bytes: 0,
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::Open,
                 flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
             },
         );
+        let state = CallbackState::new(state);
+        let mut frame = [0u8; 16];
+
+        fault_injection::set_provide_stream_data_buffer_failures(1);
+        let ret = unsafe {
+            client_callback(
+                0x10 as *mut picoquic_cnx_t,
+                4,
+                frame.as_mut_ptr(),
+                frame.len(),
+                picoquic_call_back_event_t::picoquic_callback_prepare_to_send,
+                state.callback_ctx(),
+                std::ptr::null_mut(),
+            )
+        };
 
-        handle_stream_data(std::ptr::null_mut(), &mut state, stream_id, true, &[]);
-        assert!(
-            state.streams.contains_key(&stream_id),
-            "stream should remain when only recv side is closed"
-        );
+        assert_eq!(ret, 0, "a refused buffer must not fail the connection");
+        assert!(!fault_injection::provide_stream_data_buffer_failure());
+        let mut state = state;
+        let stream = state.get_mut().streams.get(&4).expect("stream kept");
+        assert_eq!(stream.send_state, StreamSendState::Open);
+    }
 
-        if let Some(stream) = state.streams.get_mut(&stream_id) {
-            stream.send_state = StreamSendState::FinQueued;
-        }
-        handle_command(
-            std::ptr::null_mut(),
-            &mut state as *mut _,
-            Command::StreamWriteDrained {
+    #[test]
+    fn stream_stuck_in_closing_gets_its_fin_forced() {
+        let _guard = ResetOnDrop::new(|| fault_injection::set_add_to_stream_failures(0));
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let long_ago = Instant::now()
+            .checked_sub(Duration::from_secs(60))
+            .expect("monotonic clock has run for a minute");
+        // Stream 4's StreamClosed was lost a minute ago; stream 8 just closed.
+        let mut local_ends = Vec::new();
+        for (stream_id, closing_at) in [(4u64, long_ago), (8, Instant::now())] {
+            let (write_tx, write_rx) = mpsc::unbounded_channel();
+            let (read_abort_tx, read_abort_rx) = oneshot::channel();
+            local_ends.push((write_rx, read_abort_rx));
+            let mut timing = StreamTiming::new(Instant::now());
+            timing.closing_at = Some(closing_at);
+            state.streams.insert(
                 stream_id,
-                bytes: 0,
-            },
-        );
-        assert!(
-            !state.streams.contains_key(&stream_id),
-            "stream should be removed once both halves are closed"
+                ClientStream {
+                    write_tx,
+                    read_abort_tx: Some(read_abort_tx),
+                    data_rx: None,
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Closing,
+                    flow: FlowControlState::default(),
+                    timing,
+                },
+            );
+        }
+
+        expire_stuck_closing(std::ptr::null_mut(), &mut state);
+        assert_eq!(state.streams.len(), 2, "no timeout means no expiry");
+
+        state.set_closing_timeout(Duration::from_secs(5));
+        // The forced FIN fails here, so the stream must not be left behind.
+        fault_injection::set_add_to_stream_failures(1);
+        expire_stuck_closing(std::ptr::null_mut(), &mut state);
+
+        assert!(!state.streams.contains_key(&4));
+        assert_eq!(
+            state.streams.get(&8).map(|stream| stream.send_state),
+            Some(StreamSendState::Closing)
         );
+        assert_eq!(state.stuck_closing_streams(), 1);
     }
 
     #[test]
-    fn local_fin_does_not_remove_until_recv_fin() {
-        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+    fn max_streams_floor_drops_streams_past_the_servers_credit() {
+        let (command_tx, _command_rx) = command_channel(0);
         let data_notify = Arc::new(Notify::new());
         let acceptor = acceptor::ClientAcceptor::new();
-        let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
-        let stream_id = 4;
-        let (write_tx, _write_rx) = mpsc::unbounded_channel();
-        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        state.recorded_aborts = Some(Vec::new());
+        state.apply_max_streams_floor();
+        assert_eq!(state.acceptor.limit(), 0, "no floor waits for credit");
+
+        state.set_max_streams_floor(3);
+        state.set_acceptor_paused(true);
+        state.apply_max_streams_floor();
+        assert_eq!(state.acceptor.limit(), 0, "the path gate still wins");
+        state.set_acceptor_paused(false);
+        state.apply_max_streams_floor();
+        assert_eq!(state.acceptor.limit(), 3);
+
+        // Three accepts went through before the handshake; the server then
+        // grants a single stream.
+        let mut local_ends = Vec::new();
+        for stream_id in [0u64, 4, 8] {
+            let (write_tx, write_rx) = mpsc::unbounded_channel();
+            let (read_abort_tx, read_abort_rx) = oneshot::channel();
+            local_ends.push((write_rx, read_abort_rx));
+            state.streams.insert(
+                stream_id,
+                ClientStream {
+                    write_tx,
+                    read_abort_tx: Some(read_abort_tx),
+                    data_rx: None,
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Open,
+                    flow: FlowControlState::default(),
+                    timing: StreamTiming::new(Instant::now()),
+                },
+            );
+        }
+        state.ready = true;
+        state.reconcile_max_streams_floor(std::ptr::null_mut(), 1);
+        assert_eq!(state.streams.keys().copied().collect::<Vec<_>>(), [0]);
+        assert_eq!(state.max_streams_floor_overruns(), 2);
+        for (write_rx, read_abort_rx) in local_ends.iter_mut().skip(1) {
+            // The writer resets a socket whose channel closes without a FIN.
+            assert_eq!(
+                write_rx.try_recv().err(),
+                Some(mpsc::error::TryRecvError::Disconnected)
+            );
+            assert_eq!(
+                read_abort_rx.try_recv(),
+                Err(oneshot::error::TryRecvError::Closed)
+            );
+        }
+        assert_eq!(
+            state.recorded_aborts.as_deref(),
+            Some(&[][..]),
+            "no reset on streams the server has not allowed"
+        );
+
+        // MAX_STREAMS now covers stream 4, so its reset may go out.
+        state.reconcile_max_streams_floor(std::ptr::null_mut(), 2);
+        assert_eq!(
+            state.recorded_aborts.as_deref(),
+            Some(&[(4, SLIPSTREAM_INTERNAL_ERROR)][..])
+        );
+        state.reset_for_reconnect();
+        state.reconcile_max_streams_floor(std::ptr::null_mut(), 3);
+        assert_eq!(state.recorded_aborts.as_deref().map(<[_]>::len), Some(1));
+        assert_eq!(state.max_streams_floor_overruns(), 2);
+    }
+
+    #[test]
+    fn remote_fin_keeps_local_read_open() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+
+        state.streams.insert(
+            stream_id,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+
+        handle_stream_data(std::ptr::null_mut(), &mut state, stream_id, true, &[]);
+
+        let stream = state
+            .streams
+            .get(&stream_id)
+            .expect("stream should remain after remote fin");
+        assert_eq!(stream.recv_state, StreamRecvState::FinReceived);
+        assert_eq!(stream.send_state, StreamSendState::Open);
+        assert!(
+            stream.data_rx.is_some(),
+            "local TCP read side should stay open after remote fin"
+        );
+        assert!(
+            matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)),
+            "expected a TCP fin to be enqueued"
+        );
+    }
+
+    #[test]
+    fn data_past_fin_aborts_without_reaching_the_writer() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        state.recorded_aborts = Some(Vec::new());
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        for stream_id in [4u64, 8] {
+            state.streams.insert(
+                stream_id,
+                ClientStream {
+                    write_tx: write_tx.clone(),
+                    read_abort_tx: None,
+                    data_rx: None,
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Open,
+                    flow: FlowControlState::default(),
+                    timing: StreamTiming::new(Instant::now()),
+                },
+            );
+        }
+        let stream = state.streams.get_mut(&4).unwrap();
+        stream.read_abort_tx = Some(read_abort_tx);
+        stream.data_rx = Some(data_rx);
+
+        handle_stream_data(std::ptr::null_mut(), &mut state, 4, true, &[]);
+        assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)));
+        // A repeated FIN at the same offset is not a violation.
+        handle_stream_data(std::ptr::null_mut(), &mut state, 4, true, &[]);
+        assert!(state.streams.contains_key(&4));
+
+        handle_stream_data(std::ptr::null_mut(), &mut state, 4, false, b"late");
+        handle_stream_data(std::ptr::null_mut(), &mut state, 4, true, b"late");
+        assert!(write_rx.try_recv().is_err(), "no bytes may follow the fin");
+        assert!(!state.streams.contains_key(&4));
+        assert!(state.streams.contains_key(&8));
+        assert_eq!(state.fin_violations, 1);
+        assert_eq!(
+            state.recorded_aborts.as_deref(),
+            Some(
+                &[
+                    (4, SLIPSTREAM_FINAL_SIZE_ERROR),
+                    (4, SLIPSTREAM_FILE_CANCEL_ERROR)
+                ][..]
+            )
+        );
+    }
+
+    #[test]
+    fn stop_sending_half_close_keeps_the_receive_side() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        state.set_stop_sending_half_close(true);
+        state.recorded_aborts = Some(Vec::new());
+        // A backpressured stream in multi-stream mode holds its credit, so
+        // delivery below never reaches picoquic.
+        state.multi_stream_mode = true;
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, mut read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        state.streams.insert(
+            4,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState {
+                    backpressured: true,
+                    ..FlowControlState::default()
+                },
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+        let state = CallbackState::new(state);
+        let cnx = 0x10 as *mut picoquic_cnx_t;
+
+        unsafe {
+            client_callback(
+                cnx,
+                4,
+                std::ptr::null_mut(),
+                0,
+                picoquic_call_back_event_t::picoquic_callback_stop_sending,
+                state.callback_ctx(),
+                std::ptr::null_mut(),
+            );
+        }
+        let mut state = state;
+        let client = state.get_mut();
+        let stream = client.streams.get(&4).expect("receive side kept");
+        assert_eq!(stream.send_state, StreamSendState::Stopped);
+        assert!(stream.data_rx.is_none());
+        assert!(read_abort_rx.try_recv().is_ok(), "local reader stopped");
+        assert_eq!(
+            client.recorded_aborts.as_deref(),
+            Some(&[(4, SLIPSTREAM_FILE_CANCEL_ERROR)][..])
+        );
+
+        handle_stream_data(cnx, client, 4, true, b"down");
+        assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Data(data)) if data == b"down"));
+        assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)));
+        assert!(client.streams.contains_key(&4), "waits for the writer");
+        handle_command(
+            cnx,
+            client,
+            Command::StreamWriteDrained {
+                stream_id: 4,
+                bytes: 4,
+            },
+        );
+        assert!(!client.streams.contains_key(&4));
+    }
+
+    #[test]
+    fn refused_abort_is_retried_and_counted() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let cnx = 0x10 as *mut picoquic_cnx_t;
+        let refused = AbortError {
+            stop_sending: 0,
+            reset_stream: 0x400 + 5,
+        };
+        assert!(state
+            .aborts
+            .record_failure(cnx, 4, SLIPSTREAM_INTERNAL_ERROR, refused));
+
+        let mut attempts = 0;
+        state.retry_deferred_aborts_with(|_, _, _| {
+            attempts += 1;
+            Err(refused)
+        });
+        state.retry_deferred_aborts_with(|retry_cnx, stream_id, app_error| {
+            attempts += 1;
+            assert_eq!(
+                (retry_cnx, stream_id, app_error),
+                (cnx, 4, SLIPSTREAM_INTERNAL_ERROR)
+            );
+            Ok(())
+        });
+        assert_eq!(attempts, 2);
+        assert_eq!(state.abort_counters(), (1, 1, 0));
+        assert!(state.aborts.is_empty());
+
+        // A reconnect drops whatever is still pending but keeps the counts.
+        state
+            .aborts
+            .record_failure(cnx, 8, SLIPSTREAM_INTERNAL_ERROR, refused);
+        state.reset_for_reconnect();
+        assert!(state.aborts.is_empty());
+        assert_eq!(state.abort_counters(), (2, 1, 0));
+    }
+
+    #[test]
+    fn session_counters_survive_reconnect() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+
+        state.streams.insert(
+            stream_id,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: None,
+                tx_bytes: 7,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+        state.session = SessionCounters {
+            bytes_up: 7,
+            bytes_down: 5,
+            streams_opened: 1,
+            key_updates: 2,
+        };
+
+        state.reset_for_reconnect();
+
+        assert!(state.streams.is_empty());
+        assert_eq!(
+            state.session_counters(),
+            SessionCounters {
+                bytes_up: 7,
+                bytes_down: 5,
+                streams_opened: 1,
+                key_updates: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn stream_removal_requires_both_halves_closed() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+
+        state.streams.insert(
+            stream_id,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+
+        handle_stream_data(std::ptr::null_mut(), &mut state, stream_id, true, &[]);
+        assert!(
+            state.streams.contains_key(&stream_id),
+            "stream should remain when only recv side is closed"
+        );
+
+        if let Some(stream) = state.streams.get_mut(&stream_id) {
+            stream.send_state = StreamSendState::FinQueued;
+        }
+        handle_command(
+            std::ptr::null_mut(),
+            &mut state,
+            Command::StreamWriteDrained {
+                stream_id,
+                bytes: 0,
+            },
+        );
+        assert!(
+            !state.streams.contains_key(&stream_id),
+            "stream should be removed once both halves are closed"
+        );
+    }
+
+    #[test]
+    fn first_delivered_bytes_record_ttfb_once() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        let accepted_at = Instant::now() - Duration::from_millis(150);
+
+        state.streams.insert(
+            stream_id,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState::default(),
+                timing: StreamTiming::new(accepted_at),
+            },
+        );
+        for bytes in [0, 512, 512] {
+            handle_command(
+                std::ptr::null_mut(),
+                &mut state,
+                Command::StreamWriteDrained { stream_id, bytes },
+            );
+        }
+
+        let ttfb = state.ttfb_percentiles();
+        assert_eq!(ttfb.count, 1);
+        assert!(ttfb.p50_ms >= 150, "ttfb {:?}", ttfb);
+        state.reset_for_reconnect();
+        assert_eq!(state.ttfb_percentiles().count, 1);
+    }
+
+    #[test]
+    fn local_fin_does_not_remove_until_recv_fin() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
 
         state.streams.insert(
             stream_id,
@@ -1099,12 +2700,13 @@ mod tests {
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::FinQueued,
                 flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
             },
         );
 
         handle_command(
             std::ptr::null_mut(),
-            &mut state as *mut _,
+            &mut state,
             Command::StreamWriteDrained {
                 stream_id,
                 bytes: 0,
@@ -1118,12 +2720,387 @@ mod tests {
     }
 
     #[test]
-    fn mark_active_stream_failure_removes_stream() {
-        let _guard = ResetOnDrop::new(|| test_hooks::set_mark_active_stream_failures(0));
-        let _limit_guard = ResetOnDrop::new(|| acceptor::ClientAcceptor::set_test_limit(0));
-        acceptor::ClientAcceptor::set_test_limit(1);
+    fn dropped_data_tx_marks_stream_closing() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (data_tx, data_rx) = mpsc::channel(2);
+
+        state.streams.insert(
+            stream_id,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+
+        data_tx
+            .try_send(b"tail".to_vec())
+            .expect("queue data before reader exit");
+        // Reader exit without an explicit close command: only the sender drops.
+        drop(data_tx);
+
+        let (pending, closed_streams) = collect_stream_data(&mut state);
+
+        assert_eq!(pending, vec![(stream_id, b"tail".to_vec())]);
+        assert_eq!(closed_streams, vec![stream_id]);
+        let stream = state
+            .streams
+            .get(&stream_id)
+            .expect("stream should remain until fin is queued");
+        assert_eq!(stream.send_state, StreamSendState::Closing);
+        assert!(
+            stream.data_rx.is_none(),
+            "disconnected data_rx should be released"
+        );
+    }
+
+    #[test]
+    fn worst_stream_prefers_largest_backlog_then_oldest() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        assert_eq!(state.worst_stream_id(), None);
+
+        for (stream_id, queued_bytes, rx_bytes, consumed_offset) in
+            [(0u64, 0usize, 0u64, 0u64), (4, 0, 0, 0), (8, 100, 300, 100)]
+        {
+            let (write_tx, _write_rx) = mpsc::unbounded_channel();
+            let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+            let flow = FlowControlState {
+                queued_bytes,
+                rx_bytes,
+                consumed_offset,
+                ..FlowControlState::default()
+            };
+            state.streams.insert(
+                stream_id,
+                ClientStream {
+                    write_tx,
+                    read_abort_tx: Some(read_abort_tx),
+                    data_rx: None,
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Open,
+                    flow,
+                    timing: StreamTiming::new(Instant::now()),
+                },
+            );
+        }
+
+        assert_eq!(state.worst_stream_id(), Some(8));
+        state.streams.remove(&8);
+        assert_eq!(
+            state.worst_stream_id(),
+            Some(0),
+            "ties should pick the oldest stream"
+        );
+    }
+
+    #[test]
+    fn stream_path_counts_follow_live_streams() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, true, acceptor);
+        state.sync_stream_paths([(0, resolver(1)), (3, resolver(2))]);
+        assert_eq!(state.least_loaded_path(), Some(0));
+
+        for (stream_id, unique_path_id) in [(0u64, 0u64), (4, 0), (8, 3)] {
+            let (write_tx, _write_rx) = mpsc::unbounded_channel();
+            let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+            state.streams.insert(
+                stream_id,
+                ClientStream {
+                    write_tx,
+                    read_abort_tx: Some(read_abort_tx),
+                    data_rx: None,
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Open,
+                    flow: FlowControlState::default(),
+                    timing: StreamTiming::new(Instant::now()),
+                },
+            );
+            state.stream_paths.insert(stream_id, unique_path_id);
+        }
+        state.refresh_stream_path_counts();
+        assert_eq!(state.least_loaded_path(), Some(3));
+
+        state.streams.remove(&0);
+        state.streams.remove(&4);
+        state.refresh_stream_path_counts();
+        assert_eq!(state.streams_per_path.get(&0), Some(&0));
+        assert_eq!(state.least_loaded_path(), Some(0));
+
+        // Losing a path forgets the streams pinned to it.
+        state.sync_stream_paths([(0, resolver(1))]);
+        assert!(state.stream_paths.is_empty());
+        assert_eq!(state.streams_per_path.len(), 1);
+    }
+
+    fn resolver(last_octet: u8) -> SocketAddr {
+        SocketAddr::from(([192, 0, 2, last_octet], 53))
+    }
+
+    #[test]
+    fn stream_tags_name_the_generation_and_resolver() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, true, acceptor);
+        state.sync_stream_paths([(0, resolver(1))]);
+        assert_eq!(
+            state.stream_tag(4).to_string(),
+            "stream 4 gen=0 resolver=192.0.2.1:53"
+        );
+
+        // With two paths only pinned streams can name their resolver.
+        state.sync_stream_paths([(0, resolver(1)), (3, resolver(2))]);
+        state.stream_paths.insert(8, 3);
+        assert_eq!(state.stream_tag(4).to_string(), "stream 4 gen=0");
+        assert_eq!(
+            format!("{:#}", state.stream_tag(8)),
+            "stream=8 gen=0 resolver=192.0.2.2:53"
+        );
+
+        state.reset_for_reconnect();
+        state.reset_for_reconnect();
+        assert_eq!(state.stream_tag(8).to_string(), "stream 8 gen=2");
+    }
+
+    #[test]
+    fn open_data_tx_keeps_stream_open() {
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let stream_id = 4;
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+
+        state.streams.insert(
+            stream_id,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                flow: FlowControlState::default(),
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+
+        let (pending, closed_streams) = collect_stream_data(&mut state);
+
+        assert!(pending.is_empty());
+        assert!(closed_streams.is_empty());
+        let stream = state.streams.get(&stream_id).expect("stream should remain");
+        assert_eq!(stream.send_state, StreamSendState::Open);
+        assert!(stream.data_rx.is_some());
+    }
+
+    #[test]
+    fn accepts_waiting_on_zero_credit_are_reported_once_ready() {
+        let _limit_guard = ResetOnDrop::new(|| acceptor::ClientAcceptor::set_test_limit(0));
+        acceptor::ClientAcceptor::set_test_limit(0);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let (command_tx, _command_rx) = command_channel(0);
+            let data_notify = Arc::new(Notify::new());
+            let acceptor = acceptor::ClientAcceptor::new();
+            let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+            let pending = {
+                let acceptor = state.acceptor.clone();
+                tokio::spawn(async move { acceptor.reserve_for_test().await })
+            };
+            sleep(Duration::from_millis(10)).await;
+            assert_eq!(state.acceptor.waiting(), 1);
+            // Not ready yet: waiting for credit is expected.
+            assert_eq!(state.credit_starved_accepts(), 0);
+
+            state.ready = true;
+            assert_eq!(state.credit_starved_accepts(), 1);
+            state.set_acceptor_paused(true);
+            assert_eq!(state.credit_starved_accepts(), 0);
+            state.set_acceptor_paused(false);
+
+            pending.abort();
+            let _ = pending.await;
+            assert_eq!(state.acceptor.waiting(), 0);
+            assert_eq!(state.credit_starved_accepts(), 0);
+        });
+    }
+
+    #[test]
+    fn mark_active_stream_failure_removes_stream() {
+        let _guard = ResetOnDrop::new(|| fault_injection::set_mark_active_stream_failures(0));
+        let _limit_guard = ResetOnDrop::new(|| acceptor::ClientAcceptor::set_test_limit(0));
+        acceptor::ClientAcceptor::set_test_limit(1);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let listener = TokioTcpListener::bind("127.0.0.1:0")
+                .await
+                .expect("bind listener");
+            let addr = listener.local_addr().expect("listener addr");
+            let accept = tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.expect("accept");
+                stream
+            });
+            let _client = TokioTcpStream::connect(addr).await.expect("connect");
+            let stream = accept.await.expect("accept join");
+
+            let (command_tx, _command_rx) = command_channel(0);
+            let data_notify = Arc::new(Notify::new());
+            let acceptor = acceptor::ClientAcceptor::new();
+            let reservation = acceptor.reserve_for_test().await;
+            let slot = acceptor.slot_for_test().await;
+            let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+
+            fault_injection::set_mark_active_stream_failures(1);
+
+            handle_command(
+                std::ptr::null_mut(),
+                &mut state,
+                Command::NewStream {
+                    stream,
+                    reservation,
+                    slot,
+                    accepted_at: Instant::now(),
+                },
+            );
+
+            assert!(
+                state.streams.is_empty(),
+                "stream state should be removed when mark_active_stream fails"
+            );
+        });
+    }
+
+    #[test]
+    fn failed_promotion_aborts_only_that_stream() {
+        let _guard = ResetOnDrop::new(|| fault_injection::set_promote_consume_limit(None));
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let mut write_rxs = Vec::new();
+        for stream_id in [0u64, 4, 8] {
+            let (write_tx, write_rx) = mpsc::unbounded_channel();
+            let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+            write_rxs.push(write_rx);
+            let flow = FlowControlState {
+                rx_bytes: 100,
+                queued_bytes: 40,
+                consumed_offset: 20,
+                ..Default::default()
+            };
+            state.streams.insert(
+                stream_id,
+                ClientStream {
+                    write_tx,
+                    read_abort_tx: Some(read_abort_tx),
+                    data_rx: None,
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Open,
+                    flow,
+                    timing: StreamTiming::new(Instant::now()),
+                },
+            );
+        }
+        // Stream 8 rejects both the full offset and the clamped one (60).
+        fault_injection::set_promote_consume_limit(Some((8, 20)));
+
+        promote_to_multi_stream(std::ptr::null_mut(), &mut state);
+
+        assert!(state.multi_stream_mode);
+        assert!(
+            !state.streams.contains_key(&8),
+            "stream that failed promotion should be dropped"
+        );
+        for stream_id in [0u64, 4] {
+            let stream = state.streams.get(&stream_id).expect("stream kept");
+            assert_eq!(stream.flow.consumed_offset, 100);
+        }
+    }
+
+    #[test]
+    fn drains_after_promotion_only_shrink_the_queue() {
+        let _guard = ResetOnDrop::new(|| fault_injection::set_promote_consume_limit(None));
+        let (command_tx, _command_rx) = command_channel(0);
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, false, acceptor);
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (read_abort_tx, _read_abort_rx) = oneshot::channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        state.streams.insert(
+            0,
+            ClientStream {
+                write_tx,
+                read_abort_tx: Some(read_abort_tx),
+                data_rx: Some(data_rx),
+                tx_bytes: 0,
+                recv_state: StreamRecvState::Open,
+                send_state: StreamSendState::Open,
+                // Single-stream credit: 60 bytes written plus a reserve.
+                flow: FlowControlState {
+                    rx_bytes: 100,
+                    queued_bytes: 40,
+                    consumed_offset: 80,
+                    ..Default::default()
+                },
+                timing: StreamTiming::new(Instant::now()),
+            },
+        );
+        // Keeps promotion off picoquic without rejecting anything.
+        fault_injection::set_promote_consume_limit(Some((u64::MAX, 0)));
+
+        promote_to_multi_stream(std::ptr::null_mut(), &mut state);
+        // Writes of pre-promotion data reported after the switch.
+        for bytes in [25, 15] {
+            handle_command(
+                std::ptr::null_mut(),
+                &mut state,
+                Command::StreamWriteDrained {
+                    stream_id: 0,
+                    bytes,
+                },
+            );
+        }
+
+        let stream = state.streams.get(&0).expect("stream kept");
+        assert_eq!(stream.flow.queued_bytes, 0);
+        assert_eq!(stream.flow.consumed_offset, 100);
+        assert!(!stream.flow.discarding);
+    }
+
+    #[test]
+    fn writer_resets_the_local_socket_when_the_stream_is_dropped() {
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_io()
+            .enable_time()
             .build()
             .expect("build tokio runtime");
         rt.block_on(async {
@@ -1131,34 +3108,41 @@ mod tests {
                 .await
                 .expect("bind listener");
             let addr = listener.local_addr().expect("listener addr");
-            let accept = tokio::spawn(async move {
-                let (stream, _) = listener.accept().await.expect("accept");
-                stream
-            });
-            let _client = TokioTcpStream::connect(addr).await.expect("connect");
-            let stream = accept.await.expect("accept join");
-
-            let (command_tx, _command_rx) = mpsc::unbounded_channel();
-            let data_notify = Arc::new(Notify::new());
             let acceptor = acceptor::ClientAcceptor::new();
-            let reservation = acceptor.reserve_for_test().await;
-            let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
-
-            test_hooks::set_mark_active_stream_failures(1);
-
-            handle_command(
-                std::ptr::null_mut(),
-                &mut state as *mut _,
-                Command::NewStream {
-                    stream,
-                    reservation,
-                },
-            );
+            let (command_tx, _command_rx) = command_channel(0);
+            let mut results = Vec::new();
+            for fin in [true, false] {
+                let mut app = TokioTcpStream::connect(addr).await.expect("connect");
+                let (stream, _) = listener.accept().await.expect("accept");
+                let (read_half, write_half) = stream.into_split();
+                let (write_tx, write_rx) = mpsc::unbounded_channel();
+                spawn_client_writer(
+                    0,
+                    write_half,
+                    write_rx,
+                    command_tx.clone(),
+                    1024,
+                    None,
+                    None,
+                    Arc::new(acceptor.slot_for_test().await),
+                );
+                let _ = write_tx.send(StreamWrite::Data(b"partial".to_vec()));
+                if fin {
+                    let _ = write_tx.send(StreamWrite::Fin);
+                }
+                drop(write_tx);
+                // The reader task has ended too.
+                sleep(Duration::from_millis(50)).await;
+                drop(read_half);
 
-            assert!(
-                state.streams.is_empty(),
-                "stream state should be removed when mark_active_stream fails"
-            );
+                let mut received = Vec::new();
+                let result = timeout(Duration::from_secs(2), app.read_to_end(&mut received))
+                    .await
+                    .expect("local socket closed");
+                results.push(result.map(|_| received).map_err(|err| err.kind()));
+            }
+            assert_eq!(results[0], Ok(b"partial".to_vec()));
+            assert_eq!(results[1], Err(std::io::ErrorKind::ConnectionReset));
         });
     }
 
@@ -1176,7 +3160,7 @@ mod tests {
                 .await
                 .expect("bind listener");
             let addr = listener.local_addr().expect("listener addr");
-            let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+            let (command_tx, mut command_rx) = command_channel(0);
             let acceptor = acceptor::ClientAcceptor::new();
             acceptor.spawn(listener, command_tx);
 
@@ -1201,106 +3185,411 @@ mod tests {
             drop(clients);
         });
     }
+
+    #[test]
+    fn full_command_queue_holds_the_accepted_connection() {
+        let _guard = ResetOnDrop::new(|| acceptor::ClientAcceptor::set_test_limit(0));
+        acceptor::ClientAcceptor::set_test_limit(4);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let listener = TokioTcpListener::bind("127.0.0.1:0")
+                .await
+                .expect("bind listener");
+            let addr = listener.local_addr().expect("listener addr");
+            let (command_tx, mut command_rx) = command_channel(1);
+            assert!(command_tx
+                .try_send(Command::StreamReadError { stream_id: 0 })
+                .is_ok());
+            let acceptor = acceptor::ClientAcceptor::new();
+            acceptor.spawn(listener, command_tx);
+
+            let mut client = TokioTcpStream::connect(addr).await.expect("connect");
+            sleep(Duration::from_millis(50)).await;
+            assert_eq!(acceptor.counters().blocked_on_queue, 1);
+            assert_eq!(acceptor.counters().accepted, 0);
+
+            let filler = command_rx.recv().await.expect("queued command");
+            assert!(matches!(filler, Command::StreamReadError { stream_id: 0 }));
+            let accepted = timeout(Duration::from_secs(1), command_rx.recv())
+                .await
+                .expect("accept once there is room")
+                .expect("accept command");
+            assert!(matches!(accepted, Command::NewStream { .. }));
+            assert_eq!(acceptor.counters().accepted, 1);
+
+            // Still open: a read finds nothing rather than EOF.
+            let mut buf = [0u8; 1];
+            assert!(timeout(Duration::from_millis(50), client.read(&mut buf))
+                .await
+                .is_err());
+            drop(accepted);
+        });
+    }
+}
+
+/// Forced picoquic failures for the loop's error paths, used by this
+/// crate's tests and, with the `fault-injection` feature, by crates that
+/// embed the client.
+///
+/// Each `set_*` arms that many failures of one picoquic call: the next
+/// calls the loop makes report an error instead, until the count runs out.
+/// The counters assume what the loop itself guarantees, that one thread
+/// makes every picoquic call. They are process-wide, so a process running
+/// two client loops has them race for the failures; keep to one loop per
+/// process while any are armed.
+///
+/// A forced call never reaches picoquic, and neither does the abort that
+/// would follow a real failure of it, so these paths run without a live
+/// connection. The local side unwinds as it would for a real failure:
+/// stream state is dropped and local sockets are reset.
+#[cfg(any(test, feature = "fault-injection"))]
+// The client binary builds this module too but never arms a failure.
+#[allow(dead_code)]
+pub mod fault_injection {
+    use slipstream_core::test_support::FailureCounter;
+    use std::sync::Mutex;
+
+    const FORCED_ADD_TO_STREAM_ERROR: i32 = -1;
+    /// PICOQUIC_ERROR_MEMORY.
+    const FORCED_MARK_ACTIVE_STREAM_ERROR: i32 = 0x400 + 36;
+    const FORCED_CONSUME_ERROR: i32 = -1;
+    static ADD_TO_STREAM_FAILS_LEFT: FailureCounter = FailureCounter::new();
+    static MARK_ACTIVE_STREAM_FAILS_LEFT: FailureCounter = FailureCounter::new();
+    static STREAM_DATA_CONSUMED_FAILS_LEFT: FailureCounter = FailureCounter::new();
+    static PROVIDE_BUFFER_FAILS_LEFT: FailureCounter = FailureCounter::new();
+
+    /// `picoquic_add_to_stream` when queueing a stream's FIN.
+    pub fn set_add_to_stream_failures(count: usize) {
+        ADD_TO_STREAM_FAILS_LEFT.set(count);
+    }
+
+    /// `picoquic_mark_active_stream` when an accepted connection opens its
+    /// stream.
+    pub fn set_mark_active_stream_failures(count: usize) {
+        MARK_ACTIVE_STREAM_FAILS_LEFT.set(count);
+    }
+
+    /// `picoquic_stream_data_consumed`, wherever the loop hands back
+    /// receive credit.
+    pub fn set_stream_data_consumed_failures(count: usize) {
+        STREAM_DATA_CONSUMED_FAILS_LEFT.set(count);
+    }
+
+    /// `picoquic_provide_stream_data_buffer` returning null in the
+    /// prepare-to-send callback.
+    pub fn set_provide_stream_data_buffer_failures(count: usize) {
+        PROVIDE_BUFFER_FAILS_LEFT.set(count);
+    }
+
+    pub(super) fn add_to_stream_failure() -> Option<i32> {
+        ADD_TO_STREAM_FAILS_LEFT
+            .take()
+            .then_some(FORCED_ADD_TO_STREAM_ERROR)
+    }
+
+    pub(super) fn mark_active_stream_failure() -> Option<i32> {
+        MARK_ACTIVE_STREAM_FAILS_LEFT
+            .take()
+            .then_some(FORCED_MARK_ACTIVE_STREAM_ERROR)
+    }
+
+    pub(super) fn stream_data_consumed_failure() -> Option<i32> {
+        STREAM_DATA_CONSUMED_FAILS_LEFT
+            .take()
+            .then_some(FORCED_CONSUME_ERROR)
+    }
+
+    pub(super) fn provide_stream_data_buffer_failure() -> bool {
+        PROVIDE_BUFFER_FAILS_LEFT.take()
+    }
+
+    /// Stream ID given to a stream whose `picoquic_mark_active_stream` is
+    /// forced to fail, so that no ID is taken from picoquic.
+    const FORCED_STREAM_ID: u64 = 4;
+    static PROMOTE_CONSUME_LIMIT: Mutex<Option<(u64, u64)>> = Mutex::new(None);
+
+    /// While set to `(stream_id, max_offset)`, the consumes that promote
+    /// streams to multi-stream mode never reach picoquic: offsets above
+    /// `max_offset` are rejected for `stream_id` and everything else
+    /// succeeds. Streams that still fail are dropped without an abort.
+    pub fn set_promote_consume_limit(limit: Option<(u64, u64)>) {
+        *PROMOTE_CONSUME_LIMIT.lock().unwrap() = limit;
+    }
+
+    pub(super) fn forced_stream_id() -> u64 {
+        FORCED_STREAM_ID
+    }
+
+    pub(super) fn promote_consume_simulated() -> bool {
+        PROMOTE_CONSUME_LIMIT.lock().unwrap().is_some()
+    }
+
+    pub(super) fn promote_consume_result(stream_id: u64, new_offset: u64) -> Option<i32> {
+        let (limited_stream, max_offset) = (*PROMOTE_CONSUME_LIMIT.lock().unwrap())?;
+        if stream_id == limited_stream && new_offset > max_offset {
+            Some(FORCED_CONSUME_ERROR)
+        } else {
+            Some(0)
+        }
+    }
+}
+
+/// Without the feature every call goes to picoquic.
+#[cfg(not(any(test, feature = "fault-injection")))]
+mod fault_injection {
+    pub(super) fn add_to_stream_failure() -> Option<i32> {
+        None
+    }
+
+    pub(super) fn mark_active_stream_failure() -> Option<i32> {
+        None
+    }
+
+    pub(super) fn stream_data_consumed_failure() -> Option<i32> {
+        None
+    }
+
+    pub(super) fn provide_stream_data_buffer_failure() -> bool {
+        false
+    }
+
+    pub(super) fn forced_stream_id() -> u64 {
+        unreachable!("mark_active_stream failures are never forced")
+    }
+
+    pub(super) fn promote_consume_simulated() -> bool {
+        false
+    }
+
+    pub(super) fn promote_consume_result(_stream_id: u64, _new_offset: u64) -> Option<i32> {
+        None
+    }
+}
+
+/// `picoquic_stream_data_consumed`, unless a failure is being injected.
+fn stream_data_consumed(cnx: *mut picoquic_cnx_t, stream_id: u64, new_offset: u64) -> i32 {
+    if let Some(ret) = fault_injection::stream_data_consumed_failure() {
+        return ret;
+    }
+    unsafe { picoquic_stream_data_consumed(cnx, stream_id, new_offset) }
+}
+
+/// Switch the connection to multi-stream mode, releasing every stream's
+/// single-stream reserve. Streams picoquic will not let go of are aborted.
+fn promote_to_multi_stream(cnx: *mut picoquic_cnx_t, state: &mut ClientState) {
+    state.multi_stream_mode = true;
+    // Tagged up front: the error callback runs while the streams are borrowed.
+    let tags: HashMap<u64, StreamTag> = state
+        .streams
+        .keys()
+        .map(|stream_id| (*stream_id, state.stream_tag(*stream_id)))
+        .collect();
+    let failed = promote_streams(
+        state
+            .streams
+            .iter_mut()
+            .map(|(stream_id, stream)| PromoteEntry {
+                stream_id: *stream_id,
+                rx_bytes: stream.flow.rx_bytes,
+                queued_bytes: stream.flow.queued_bytes,
+                fin_offset: stream.flow.fin_offset,
+                consumed_offset: &mut stream.flow.consumed_offset,
+                discarding: stream.flow.discarding,
+            }),
+        stream_queue_max_bytes(),
+        |stream_id, new_offset| {
+            fault_injection::promote_consume_result(stream_id, new_offset)
+                .unwrap_or_else(|| stream_data_consumed(cnx, stream_id, new_offset))
+        },
+        |stream_id, ret, consumed_offset, target| {
+            warn!(
+                "{}",
+                promote_error_log_message(tags[&stream_id], ret, consumed_offset, target)
+            );
+        },
+    );
+    for stream_id in failed {
+        warn!(
+            "{}: aborting after failed promotion to multi-stream mode",
+            tags[&stream_id]
+        );
+        if !fault_injection::promote_consume_simulated() {
+            state.abort_stream(
+                cnx,
+                stream_id,
+                SLIPSTREAM_INTERNAL_ERROR,
+                "failed promotion",
+            );
+        }
+        state.streams.remove(&stream_id);
+    }
 }
 
 pub(crate) fn drain_commands(
     cnx: *mut picoquic_cnx_t,
-    state_ptr: *mut ClientState,
-    command_rx: &mut mpsc::UnboundedReceiver<Command>,
+    state: &mut ClientState,
+    command_rx: &mut CommandReceiver,
 ) {
+    // Writers report every chunk; fold back-to-back reports per stream so
+    // the credit update runs once (see `DrainCoalescer`).
+    let mut drained = DrainCoalescer::new();
     while let Ok(command) = command_rx.try_recv() {
-        handle_command(cnx, state_ptr, command);
+        if let Command::StreamWriteDrained { stream_id, bytes } = command {
+            if let Some((stream_id, bytes)) = drained.push(stream_id, bytes) {
+                handle_command(cnx, state, Command::StreamWriteDrained { stream_id, bytes });
+            }
+            continue;
+        }
+        if let Some((stream_id, bytes)) = drained.take() {
+            handle_command(cnx, state, Command::StreamWriteDrained { stream_id, bytes });
+        }
+        handle_command(cnx, state, command);
+    }
+    if let Some((stream_id, bytes)) = drained.take() {
+        handle_command(cnx, state, Command::StreamWriteDrained { stream_id, bytes });
     }
 }
 
-pub(crate) fn drain_stream_data(cnx: *mut picoquic_cnx_t, state_ptr: *mut ClientState) {
-    let mut pending = Vec::new();
-    let mut closed_streams = Vec::new();
-    {
-        let state = unsafe { &mut *state_ptr };
-        slipstream_core::drain_stream_data!(state.streams, data_rx, pending, closed_streams);
-        for stream_id in &closed_streams {
-            if let Some(stream) = state.streams.get_mut(stream_id) {
-                if stream.send_state == StreamSendState::Open {
-                    stream.send_state = StreamSendState::Closing;
-                }
-            }
-        }
+/// Print the "and N more" summaries of sampled per-stream warnings whose
+/// window has ended; called once per loop iteration.
+pub(crate) fn flush_sampled_logs() {
+    for logger in SAMPLED_LOGS {
+        logger.flush(&PicoquicClock, |msg| warn!("{}", msg));
     }
+}
+
+pub(crate) fn drain_stream_data(cnx: *mut picoquic_cnx_t, state: &mut ClientState) {
+    let (pending, closed_streams) = collect_stream_data(state);
     for (stream_id, data) in pending {
-        handle_command(cnx, state_ptr, Command::StreamData { stream_id, data });
+        handle_command(cnx, state, Command::StreamData { stream_id, data });
     }
     for stream_id in closed_streams {
-        handle_command(cnx, state_ptr, Command::StreamClosed { stream_id });
+        handle_command(cnx, state, Command::StreamClosed { stream_id });
     }
 }
 
-pub(crate) fn handle_command(
-    cnx: *mut picoquic_cnx_t,
-    state_ptr: *mut ClientState,
-    command: Command,
-) {
-    let state = unsafe { &mut *state_ptr };
+/// Force the FIN of streams that sat in `Closing` past `--closing-timeout-ms`.
+///
+/// `drain_stream_data` queues the FIN right after the reader closes, so a
+/// stream only lingers here if that `StreamClosed` never ran. The FIN is
+/// retried once; a stream still not past `Closing` is aborted.
+pub(crate) fn expire_stuck_closing(cnx: *mut picoquic_cnx_t, state: &mut ClientState) {
+    if state.closing_timeout.is_zero() {
+        return;
+    }
+    let now = Instant::now();
+    let stuck: Vec<u64> = state
+        .streams
+        .iter()
+        .filter(|(_, stream)| {
+            stream.send_state == StreamSendState::Closing
+                && stream.timing.closing_at.is_some_and(|closing_at| {
+                    now.saturating_duration_since(closing_at) >= state.closing_timeout
+                })
+        })
+        .map(|(stream_id, _)| *stream_id)
+        .collect();
+    for stream_id in stuck {
+        state.stuck_closing = state.stuck_closing.saturating_add(1);
+        warn!(
+            "{}: stuck in Closing for over {}ms; forcing the FIN (stuck_closing={})",
+            state.stream_tag(stream_id),
+            state.closing_timeout.as_millis(),
+            state.stuck_closing
+        );
+        handle_command(cnx, state, Command::StreamClosed { stream_id });
+        let still_closing = state
+            .streams
+            .get(&stream_id)
+            .is_some_and(|stream| stream.send_state == StreamSendState::Closing);
+        if still_closing {
+            state.abort_stream(
+                cnx,
+                stream_id,
+                SLIPSTREAM_INTERNAL_ERROR,
+                "stuck in Closing",
+            );
+            state.streams.remove(&stream_id);
+        }
+    }
+}
+
+/// Pull queued reader data and detect finished readers.
+///
+/// A reader signals close only by dropping its `data_tx` (EOF, abort, or task
+/// exit), so a disconnected `data_rx` is the single close path for the local
+/// read side. Such streams move to `Closing` here; the caller queues the FIN.
+fn collect_stream_data(state: &mut ClientState) -> (Vec<(u64, Vec<u8>)>, Vec<u64>) {
+    let mut pending = Vec::new();
+    let mut closed_streams = Vec::new();
+    slipstream_core::drain_stream_data!(state.streams, data_rx, pending, closed_streams);
+    for stream_id in &closed_streams {
+        if let Some(stream) = state.streams.get_mut(stream_id) {
+            if stream.send_state == StreamSendState::Open {
+                stream.send_state = StreamSendState::Closing;
+                stream.timing.closing_at = Some(Instant::now());
+            }
+        }
+    }
+    (pending, closed_streams)
+}
+
+pub(crate) fn handle_command(cnx: *mut picoquic_cnx_t, state: &mut ClientState, command: Command) {
     match command {
         Command::NewStream {
             stream,
             reservation,
+            slot,
+            accepted_at,
         } => {
             if !reservation.is_fresh() {
                 drop(stream);
                 return;
             }
             let _ = stream.set_nodelay(true);
-            #[cfg(test)]
-            let forced_failure = test_hooks::take_mark_active_stream_failure();
-            #[cfg(not(test))]
-            let forced_failure = false;
-            #[cfg(test)]
-            let stream_id = if forced_failure {
-                4
-            } else {
-                assert!(
-                    !cnx.is_null(),
-                    "picoquic connection must be non-null when not forcing failures in tests"
-                );
-                unsafe { picoquic_get_next_local_stream_id(cnx, 0) }
-            };
-            #[cfg(not(test))]
-            let stream_id = unsafe { picoquic_get_next_local_stream_id(cnx, 0) };
-            #[cfg(test)]
-            let ret = if forced_failure {
-                test_hooks::FORCED_MARK_ACTIVE_STREAM_ERROR
-            } else {
-                unsafe { picoquic_mark_active_stream(cnx, stream_id, 1, std::ptr::null_mut()) }
+            let forced = fault_injection::mark_active_stream_failure();
+            let forced_failure = forced.is_some();
+            let pooled = state.stream_pool.take();
+            let stream_id = match pooled {
+                Some(stream_id) => stream_id,
+                None if forced_failure => fault_injection::forced_stream_id(),
+                None => unsafe { picoquic_get_next_local_stream_id(cnx, 0) },
             };
-            #[cfg(not(test))]
-            let ret =
-                unsafe { picoquic_mark_active_stream(cnx, stream_id, 1, std::ptr::null_mut()) };
+            let ret = forced.unwrap_or_else(|| unsafe {
+                picoquic_mark_active_stream(cnx, stream_id, 1, std::ptr::null_mut())
+            });
+            let tag = state.stream_tag(stream_id);
             if ret != 0 {
-                warn!(
-                    "stream {}: mark_active_stream failed ret={}",
-                    stream_id, ret
-                );
+                warn!("{}: mark_active_stream failed ret={}", tag, ret);
                 if !forced_failure {
-                    unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                    state.abort_stream(
+                        cnx,
+                        stream_id,
+                        SLIPSTREAM_INTERNAL_ERROR,
+                        "mark_active_stream failure",
+                    );
                 }
                 return;
             }
             if !reservation.commit() {
-                warn!(
-                    "stream {}: acceptor generation changed during activation",
-                    stream_id
-                );
+                warn!("{}: acceptor generation changed during activation", tag);
                 if !forced_failure {
-                    unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                    state.abort_stream(
+                        cnx,
+                        stream_id,
+                        SLIPSTREAM_INTERNAL_ERROR,
+                        "acceptor generation change",
+                    );
                 }
                 return;
             }
-            let read_limit = stream_read_limit_chunks(
-                &stream,
-                DEFAULT_TCP_RCVBUF_BYTES,
-                STREAM_READ_CHUNK_BYTES,
-            );
+            let read_chunk_bytes = state.read_chunk_bytes;
+            let read_limit =
+                stream_read_limit_chunks(&stream, DEFAULT_TCP_RCVBUF_BYTES, read_chunk_bytes);
             let (data_tx, data_rx) = mpsc::channel(read_limit);
             let data_notify = state.data_notify.clone();
             let send_buffer_bytes = tcp_send_buffer_bytes(&stream)
@@ -1320,8 +3609,12 @@ pub(crate) fn handle_command(
                     recv_state: StreamRecvState::Open,
                     send_state: StreamSendState::Open,
                     flow: FlowControlState::default(),
+                    timing: StreamTiming::new(accepted_at),
                 },
             );
+            let checksums = state.stream_checksums;
+            let trailers = state.stream_trailers;
+            let slot = Arc::new(slot);
             spawn_client_reader(
                 stream_id,
                 read_half,
@@ -1329,6 +3622,10 @@ pub(crate) fn handle_command(
                 command_tx.clone(),
                 data_tx,
                 data_notify,
+                read_chunk_bytes,
+                checksums.then(|| checksum_log(stream_id, "up")),
+                trailers.then(TrailerWriter::new),
+                Arc::clone(&slot),
             );
             spawn_client_writer(
                 stream_id,
@@ -1336,52 +3633,62 @@ pub(crate) fn handle_command(
                 write_rx,
                 command_tx,
                 send_buffer_bytes,
+                checksums.then(|| checksum_log(stream_id, "down")),
+                trailers.then(TrailerReader::new),
+                slot,
             );
+            state.session.streams_opened = state.session.streams_opened.saturating_add(1);
+            state.assign_stream_path(cnx, stream_id);
             if !state.multi_stream_mode && state.streams.len() > 1 {
-                state.multi_stream_mode = true;
-                promote_streams(
-                    state
-                        .streams
-                        .iter_mut()
-                        .map(|(stream_id, stream)| PromoteEntry {
-                            stream_id: *stream_id,
-                            rx_bytes: stream.flow.rx_bytes,
-                            consumed_offset: &mut stream.flow.consumed_offset,
-                            discarding: stream.flow.discarding,
-                        }),
-                    |stream_id, new_offset| unsafe {
-                        picoquic_stream_data_consumed(cnx, stream_id, new_offset)
-                    },
-                    |stream_id, ret, consumed_offset, rx_bytes| {
-                        warn!(
-                            "{}",
-                            promote_error_log_message(stream_id, ret, consumed_offset, rx_bytes)
-                        );
-                    },
-                );
+                promote_to_multi_stream(cnx, state);
             }
             if state.debug_streams {
-                debug!("stream {}: accepted", stream_id);
+                debug!(
+                    "{}: accepted pooled={}",
+                    state.stream_tag(stream_id),
+                    pooled.is_some()
+                );
             } else {
                 debug!("Accepted TCP stream {}", stream_id);
             }
+            if !state.first_stream_logged {
+                state.first_stream_logged = true;
+                info!(
+                    "First stream accepted: stream_id={} total_streams={}",
+                    stream_id,
+                    state.streams.len()
+                );
+                if let Some(elapsed) = state.ready_at.map(|ready_at| ready_at.elapsed()) {
+                    if elapsed <= FIRST_STREAM_TIMING_WINDOW {
+                        info!("First stream established in {}ms", elapsed.as_millis());
+                    }
+                }
+            }
             check_stream_invariants(state, stream_id, "NewStream");
         }
         Command::StreamData { stream_id, data } => {
+            let tag = state.stream_tag(stream_id);
             let ret =
                 unsafe { picoquic_add_to_stream(cnx, stream_id, data.as_ptr(), data.len(), 0) };
             if ret < 0 {
                 warn!(
-                    "stream {}: add_to_stream failed ret={} chunk_len={}",
-                    stream_id,
+                    "{}: add_to_stream failed ret={} chunk_len={}",
+                    tag,
                     ret,
                     data.len()
                 );
-                unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                state.abort_stream(
+                    cnx,
+                    stream_id,
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "add_to_stream failure",
+                );
                 state.streams.remove(&stream_id);
             } else if let Some(stream) = state.streams.get_mut(&stream_id) {
                 stream.tx_bytes = stream.tx_bytes.saturating_add(data.len() as u64);
-                let now = unsafe { picoquic_current_time() };
+                stream.timing.first_up_at.get_or_insert_with(Instant::now);
+                state.session.bytes_up = state.session.bytes_up.saturating_add(data.len() as u64);
+                let now = PicoquicClock.now_us();
                 state.debug_enqueued_bytes =
                     state.debug_enqueued_bytes.saturating_add(data.len() as u64);
                 state.debug_last_enqueue_at = now;
@@ -1396,29 +3703,26 @@ pub(crate) fn handle_command(
             if !should_send_fin {
                 return;
             }
-            #[cfg(test)]
-            let forced_failure = test_hooks::take_add_to_stream_failure();
-            #[cfg(not(test))]
-            let forced_failure = false;
-            #[cfg(test)]
-            let ret = if forced_failure {
-                test_hooks::FORCED_ADD_TO_STREAM_ERROR
-            } else {
+            let tag = state.stream_tag(stream_id);
+            let forced = fault_injection::add_to_stream_failure();
+            let forced_failure = forced.is_some();
+            let ret = forced.unwrap_or_else(|| {
+                #[cfg(test)]
                 assert!(
                     !cnx.is_null(),
                     "picoquic connection must be non-null when not forcing failures in tests"
                 );
                 unsafe { picoquic_add_to_stream(cnx, stream_id, std::ptr::null(), 0, 1) }
-            };
-            #[cfg(not(test))]
-            let ret = unsafe { picoquic_add_to_stream(cnx, stream_id, std::ptr::null(), 0, 1) };
+            });
             if ret < 0 {
-                warn!(
-                    "stream {}: add_to_stream(fin) failed ret={}",
-                    stream_id, ret
-                );
+                warn!("{}: add_to_stream(fin) failed ret={}", tag, ret);
                 if !forced_failure {
-                    unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                    state.abort_stream(
+                        cnx,
+                        stream_id,
+                        SLIPSTREAM_INTERNAL_ERROR,
+                        "add_to_stream(fin) failure",
+                    );
                 }
                 state.streams.remove(&stream_id);
             } else if let Some(stream) = state.streams.get_mut(&stream_id) {
@@ -1430,65 +3734,132 @@ pub(crate) fn handle_command(
             check_stream_invariants(state, stream_id, "StreamClosed");
         }
         Command::StreamReadError { stream_id } => {
-            if let Some(stream) = state.streams.remove(&stream_id) {
-                warn!(
-                    "stream {}: tcp read error rx_bytes={} tx_bytes={} queued={} consumed_offset={} fin_offset={:?}",
-                    stream_id,
-                    stream.flow.rx_bytes,
-                    stream.tx_bytes,
-                    stream.flow.queued_bytes,
-                    stream.flow.consumed_offset,
-                    stream.flow.fin_offset
-                );
-            } else {
-                warn!("stream {}: tcp read error (unknown stream)", stream_id);
-            }
-            unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+            let tag = state.stream_tag(stream_id);
+            let stream = state.streams.remove(&stream_id);
+            TCP_READ_LOG.log(
+                &PicoquicClock,
+                || match stream {
+                    Some(stream) => format!(
+                        "{}: tcp read error rx_bytes={} tx_bytes={} queued={} consumed_offset={} fin_offset={:?}",
+                        tag,
+                        stream.flow.rx_bytes,
+                        stream.tx_bytes,
+                        stream.flow.queued_bytes,
+                        stream.flow.consumed_offset,
+                        stream.flow.fin_offset
+                    ),
+                    None => format!("{}: tcp read error (unknown stream)", tag),
+                },
+                |msg| warn!("{}", msg),
+            );
+            state.abort_stream(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR, "tcp read error");
         }
         Command::StreamWriteError { stream_id } => {
-            if let Some(stream) = state.streams.remove(&stream_id) {
-                warn!(
-                    "stream {}: tcp write error rx_bytes={} tx_bytes={} queued={} consumed_offset={} fin_offset={:?}",
-                    stream_id,
-                    stream.flow.rx_bytes,
-                    stream.tx_bytes,
-                    stream.flow.queued_bytes,
-                    stream.flow.consumed_offset,
-                    stream.flow.fin_offset
-                );
-            } else {
-                warn!("stream {}: tcp write error (unknown stream)", stream_id);
+            let tag = state.stream_tag(stream_id);
+            let stream = state.streams.remove(&stream_id);
+            TCP_WRITE_LOG.log(
+                &PicoquicClock,
+                || match stream {
+                    Some(stream) => format!(
+                        "{}: tcp write error rx_bytes={} tx_bytes={} queued={} consumed_offset={} fin_offset={:?}",
+                        tag,
+                        stream.flow.rx_bytes,
+                        stream.tx_bytes,
+                        stream.flow.queued_bytes,
+                        stream.flow.consumed_offset,
+                        stream.flow.fin_offset
+                    ),
+                    None => format!("{}: tcp write error (unknown stream)", tag),
+                },
+                |msg| warn!("{}", msg),
+            );
+            state.abort_stream(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR, "tcp write error");
+        }
+        Command::StreamChecksumMismatch { stream_id, detail } => {
+            let tag = state.stream_tag(stream_id);
+            match state.streams.remove(&stream_id) {
+                Some(stream) => error!(
+                    "{}: stream trailer check failed: {} backlog={:?}",
+                    tag,
+                    detail,
+                    backlog_summary(stream_id, &stream)
+                ),
+                None => error!("{}: stream trailer check failed: {}", tag, detail),
             }
-            unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+            state.abort_stream(
+                cnx,
+                stream_id,
+                SLIPSTREAM_CHECKSUM_ERROR,
+                "checksum mismatch",
+            );
         }
         Command::StreamWriteDrained { stream_id, bytes } => {
+            let tag = state.stream_tag(stream_id);
             let mut remove_stream = false;
             if let Some(stream) = state.streams.get_mut(&stream_id) {
                 if stream.flow.discarding {
                     return;
                 }
+                if bytes > 0 && stream.timing.first_down_at.is_none() {
+                    let now = Instant::now();
+                    stream.timing.first_down_at = Some(now);
+                    let timing = &stream.timing;
+                    let ttfb = now.saturating_duration_since(timing.accepted_at);
+                    state.ttfb.record(ttfb);
+                    if state.debug_streams {
+                        let span = |from: Option<Instant>, to: Option<Instant>| match (from, to) {
+                            (Some(from), Some(to)) => {
+                                format!("{}ms", to.saturating_duration_since(from).as_millis())
+                            }
+                            _ => "-".to_string(),
+                        };
+                        debug!(
+                            "{}: ttfb={}ms accept_to_active={} active_to_first_up={} first_up_to_first_down={}",
+                            tag,
+                            ttfb.as_millis(),
+                            span(Some(timing.accepted_at), Some(timing.activated_at)),
+                            span(Some(timing.activated_at), timing.first_up_at),
+                            span(timing.first_up_at, Some(now))
+                        );
+                    }
+                }
                 stream.flow.queued_bytes = stream.flow.queued_bytes.saturating_sub(bytes);
-                if !state.multi_stream_mode {
-                    let new_offset = reserve_target_offset(
-                        stream.flow.rx_bytes,
-                        stream.flow.queued_bytes,
-                        stream.flow.fin_offset,
-                        conn_reserve_bytes(),
-                    );
+                // Multi-stream streams lag behind rx_bytes only where the
+                // queue cap held them back; drains release the rest. A
+                // backpressured stream holds its credit until released.
+                if !(state.multi_stream_mode && stream.flow.backpressured) {
+                    let new_offset = if state.multi_stream_mode {
+                        promote_target_offset(
+                            stream.flow.rx_bytes,
+                            stream.flow.queued_bytes,
+                            stream.flow.fin_offset,
+                            stream_queue_max_bytes(),
+                        )
+                    } else {
+                        reserve_target_offset(
+                            stream.flow.rx_bytes,
+                            stream.flow.queued_bytes,
+                            stream.flow.fin_offset,
+                            conn_reserve_bytes(),
+                        )
+                    };
                     if !consume_stream_data(
                         &mut stream.flow.consumed_offset,
                         new_offset,
-                        |new_offset| unsafe {
-                            picoquic_stream_data_consumed(cnx, stream_id, new_offset)
-                        },
+                        |new_offset| stream_data_consumed(cnx, stream_id, new_offset),
                         |ret, current, target| {
                             warn!(
                                 "{}",
-                                consume_error_log_message(stream_id, "", ret, current, target)
+                                consume_error_log_message(tag, "", ret, current, target)
                             );
                         },
                     ) {
-                        unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                        state.abort_stream(
+                            cnx,
+                            stream_id,
+                            SLIPSTREAM_INTERNAL_ERROR,
+                            "consume failure",
+                        );
                         state.streams.remove(&stream_id);
                         return;
                     }
@@ -1508,16 +3879,38 @@ pub(crate) fn handle_command(
     }
 }
 
+/// TCP read size for a path MTU: about `STREAM_READ_CHUNK_PACKETS` packets
+/// of payload, rounded down to a power of two so that the reader channel
+/// holds the same number of bytes at every MTU.
+fn stream_read_chunk_bytes(mtu: u32) -> usize {
+    let bytes = (mtu as usize)
+        .saturating_mul(STREAM_READ_CHUNK_PACKETS)
+        .clamp(STREAM_READ_CHUNK_MIN_BYTES, STREAM_READ_CHUNK_MAX_BYTES);
+    1 << bytes.ilog2()
+}
+
+fn checksum_log(stream_id: u64, direction: &'static str) -> StreamChecksumLog {
+    StreamChecksumLog::new("client", stream_id, direction, |message| {
+        info!("{}", message)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_client_reader(
     stream_id: u64,
     mut read_half: tokio::net::tcp::OwnedReadHalf,
     mut read_abort_rx: oneshot::Receiver<()>,
-    command_tx: mpsc::UnboundedSender<Command>,
+    command_tx: CommandSender,
     data_tx: mpsc::Sender<Vec<u8>>,
     data_notify: Arc<Notify>,
+    read_chunk_bytes: usize,
+    mut checksum: Option<StreamChecksumLog>,
+    mut trailer: Option<TrailerWriter>,
+    slot: Arc<acceptor::StreamSlot>,
 ) {
     tokio::spawn(async move {
-        let mut buf = vec![0u8; STREAM_READ_CHUNK_BYTES];
+        let _slot = slot;
+        let mut buf = vec![0u8; read_chunk_bytes];
         loop {
             tokio::select! {
                 _ = &mut read_abort_rx => {
@@ -1526,10 +3919,21 @@ fn spawn_client_reader(
                 read_result = read_half.read(&mut buf) => {
                     match read_result {
                         Ok(0) => {
+                            if let Some(trailer) = trailer.take() {
+                                if data_tx.send(trailer.finish().to_vec()).await.is_ok() {
+                                    data_notify.notify_one();
+                                }
+                            }
                             break;
                         }
                         Ok(n) => {
                             let data = buf[..n].to_vec();
+                            if let Some(checksum) = checksum.as_mut() {
+                                checksum.update(&data);
+                            }
+                            if let Some(trailer) = trailer.as_mut() {
+                                trailer.update(&data);
+                            }
                             if data_tx.send(data).await.is_err() {
                                 break;
                             }
@@ -1539,7 +3943,7 @@ fn spawn_client_reader(
                             continue;
                         }
                         Err(_) => {
-                            let _ = command_tx.send(Command::StreamReadError { stream_id });
+                            let _ = command_tx.send(Command::StreamReadError { stream_id }).await;
                             break;
                         }
                     }
@@ -1551,14 +3955,19 @@ fn spawn_client_reader(
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_client_writer(
     stream_id: u64,
     mut write_half: tokio::net::tcp::OwnedWriteHalf,
     mut write_rx: mpsc::UnboundedReceiver<StreamWrite>,
-    command_tx: mpsc::UnboundedSender<Command>,
+    command_tx: CommandSender,
     coalesce_max_bytes: usize,
+    mut checksum: Option<StreamChecksumLog>,
+    mut trailer: Option<TrailerReader>,
+    slot: Arc<acceptor::StreamSlot>,
 ) {
     tokio::spawn(async move {
+        let _slot = slot;
         let coalesce_max_bytes = coalesce_max_bytes.max(1);
         while let Some(msg) = write_rx.recv().await {
             match msg {
@@ -1577,33 +3986,85 @@ fn spawn_client_writer(
                                 saw_fin = true;
                                 break;
                             }
-                            Err(mpsc::error::TryRecvError::Empty) => break,
-                            Err(mpsc::error::TryRecvError::Disconnected) => {
-                                saw_fin = true;
-                                break;
-                            }
+                            // A dropped channel is handled by the outer
+                            // loop once this chunk is written.
+                            Err(mpsc::error::TryRecvError::Empty)
+                            | Err(mpsc::error::TryRecvError::Disconnected) => break,
                         }
                     }
                     let len = buffer.len();
+                    if let Some(trailer) = trailer.as_mut() {
+                        buffer = trailer.push(buffer);
+                    }
                     if write_half.write_all(&buffer).await.is_err() {
-                        let _ = command_tx.send(Command::StreamWriteError { stream_id });
+                        let _ = command_tx
+                            .send(Command::StreamWriteError { stream_id })
+                            .await;
                         return;
                     }
-                    let _ = command_tx.send(Command::StreamWriteDrained {
-                        stream_id,
-                        bytes: len,
-                    });
+                    if let Some(checksum) = checksum.as_mut() {
+                        checksum.update(&buffer);
+                    }
+                    let _ = command_tx
+                        .send(Command::StreamWriteDrained {
+                            stream_id,
+                            bytes: len,
+                        })
+                        .await;
                     if saw_fin {
+                        if !trailer_verified(stream_id, &trailer, &write_half, &command_tx).await {
+                            write_half.forget();
+                            return;
+                        }
                         let _ = write_half.shutdown().await;
                         return;
                     }
                 }
                 StreamWrite::Fin => {
+                    if !trailer_verified(stream_id, &trailer, &write_half, &command_tx).await {
+                        write_half.forget();
+                        return;
+                    }
                     let _ = write_half.shutdown().await;
                     return;
                 }
             }
         }
-        let _ = write_half.shutdown().await;
+        // The loop dropped the stream before the server's FIN: it was reset,
+        // aborted, or discarded after overflowing its queue. A clean close
+        // here would pass the truncated download off as complete.
+        reset_on_close(&write_half);
+        write_half.forget();
     });
 }
+
+/// Makes the local socket send a reset instead of a FIN when it closes.
+/// Called before `forget`, so the close waits for the reader half.
+fn reset_on_close(write_half: &tokio::net::tcp::OwnedWriteHalf) {
+    let _ = socket2::SockRef::from(write_half.as_ref()).set_linger(Some(Duration::ZERO));
+}
+
+/// Checks the server's trailer once it has sent FIN. On a failure the loop
+/// is told to abort the stream, and the local socket is set to reset when it
+/// closes, so the application cannot take corrupted data for a clean end.
+async fn trailer_verified(
+    stream_id: u64,
+    trailer: &Option<TrailerReader>,
+    write_half: &tokio::net::tcp::OwnedWriteHalf,
+    command_tx: &CommandSender,
+) -> bool {
+    let Some(trailer) = trailer else {
+        return true;
+    };
+    let Err(err) = trailer.finish() else {
+        return true;
+    };
+    reset_on_close(write_half);
+    let _ = command_tx
+        .send(Command::StreamChecksumMismatch {
+            stream_id,
+            detail: err.to_string(),
+        })
+        .await;
+    false
+}
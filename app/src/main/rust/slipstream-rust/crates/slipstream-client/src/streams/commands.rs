@@ -0,0 +1,110 @@
+use super::Command;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendError, TryRecvError, TrySendError};
+
+/// Commands from the acceptor and the per-stream tasks to the client loop.
+///
+/// Unbounded by default. With `--command-channel-cap` the channel holds at
+/// most that many commands: stream tasks and the acceptor wait for room,
+/// which slows their TCP side down and leaves new connections in the listen
+/// backlog.
+#[derive(Clone)]
+pub(crate) enum CommandSender {
+    Unbounded(mpsc::UnboundedSender<Command>),
+    Bounded(mpsc::Sender<Command>),
+}
+
+pub(crate) enum CommandReceiver {
+    Unbounded(mpsc::UnboundedReceiver<Command>),
+    Bounded(mpsc::Receiver<Command>),
+}
+
+/// A command channel holding at most `capacity` commands; zero is
+/// unbounded.
+pub(crate) fn command_channel(capacity: usize) -> (CommandSender, CommandReceiver) {
+    if capacity == 0 {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (CommandSender::Unbounded(tx), CommandReceiver::Unbounded(rx))
+    } else {
+        let (tx, rx) = mpsc::channel(capacity);
+        (CommandSender::Bounded(tx), CommandReceiver::Bounded(rx))
+    }
+}
+
+impl CommandSender {
+    /// Queue a command, waiting for room in a bounded channel. Fails only
+    /// once the client loop is gone.
+    pub(crate) async fn send(&self, command: Command) -> Result<(), SendError<Command>> {
+        match self {
+            Self::Unbounded(tx) => tx.send(command),
+            Self::Bounded(tx) => tx.send(command).await,
+        }
+    }
+
+    /// Queue a command without waiting; `Full` only from a bounded channel.
+    pub(crate) fn try_send(&self, command: Command) -> Result<(), TrySendError<Command>> {
+        match self {
+            Self::Unbounded(tx) => tx
+                .send(command)
+                .map_err(|SendError(command)| TrySendError::Closed(command)),
+            Self::Bounded(tx) => tx.try_send(command),
+        }
+    }
+}
+
+impl CommandReceiver {
+    pub(crate) async fn recv(&mut self) -> Option<Command> {
+        match self {
+            Self::Unbounded(rx) => rx.recv().await,
+            Self::Bounded(rx) => rx.recv().await,
+        }
+    }
+
+    pub(crate) fn try_recv(&mut self) -> Result<Command, TryRecvError> {
+        match self {
+            Self::Unbounded(rx) => rx.try_recv(),
+            Self::Bounded(rx) => rx.try_recv(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::command_channel;
+    use crate::streams::Command;
+    use tokio::sync::mpsc::error::TrySendError;
+
+    fn read_error(stream_id: u64) -> Command {
+        Command::StreamReadError { stream_id }
+    }
+
+    #[test]
+    fn bounded_channel_reports_full_apart_from_closed() {
+        let (tx, mut rx) = command_channel(1);
+        assert!(tx.try_send(read_error(0)).is_ok());
+        assert!(matches!(
+            tx.try_send(read_error(4)),
+            Err(TrySendError::Full(_))
+        ));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Command::StreamReadError { stream_id: 0 })
+        ));
+        assert!(tx.try_send(read_error(4)).is_ok());
+        drop(rx);
+        assert!(matches!(
+            tx.try_send(read_error(8)),
+            Err(TrySendError::Closed(_))
+        ));
+
+        let (tx, rx) = command_channel(0);
+        for stream_id in 0..64 {
+            assert!(tx.try_send(read_error(stream_id)).is_ok());
+        }
+        drop(rx);
+        assert!(matches!(
+            tx.try_send(read_error(0)),
+            Err(TrySendError::Closed(_))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+
+/// How the stream pool served accepts, for the whole session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamPoolCounters {
+    /// Streams opened ahead of an accept.
+    pub(crate) opened: u64,
+    /// Accepts bound to a pooled stream.
+    pub(crate) hits: u64,
+    /// Accepts that found the pool empty and opened their own stream.
+    pub(crate) misses: u64,
+    /// Pooled streams the peer reset or stopped before an accept used them.
+    pub(crate) reset_before_use: u64,
+}
+
+/// Idle QUIC streams opened ahead of local accepts (`--stream-pool`).
+///
+/// A pooled stream exists in picoquic but nothing has been sent on it, so
+/// binding an accept to it skips stream creation. Every pooled stream uses
+/// up a stream ID under the peer's MAX_STREAMS, so the pool only grows into
+/// credit that neither open streams nor reserved accepts hold. Accepts take
+/// pooled streams oldest first, which keeps stream IDs in order on the
+/// wire.
+#[derive(Debug, Default)]
+pub(crate) struct StreamPool {
+    target: usize,
+    idle: VecDeque<u64>,
+    /// Stream IDs lost to resets before use on this connection; their
+    /// credit is gone for good.
+    burned: usize,
+    counters: StreamPoolCounters,
+}
+
+impl StreamPool {
+    /// A zero `target` keeps the pool empty.
+    pub(crate) fn new(target: usize) -> Self {
+        Self {
+            target,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn burned(&self) -> usize {
+        self.burned
+    }
+
+    pub(crate) fn counters(&self) -> StreamPoolCounters {
+        self.counters
+    }
+
+    /// How many streams to open now, given the peer's `max_streams` and the
+    /// accepts holding credit (`reserved`); every accept may still end up
+    /// opening its own stream, so their credit is never taken.
+    pub(crate) fn wanted(&self, max_streams: usize, reserved: usize) -> usize {
+        let spare = max_streams
+            .saturating_sub(reserved)
+            .saturating_sub(self.idle.len())
+            .saturating_sub(self.burned);
+        self.target.saturating_sub(self.idle.len()).min(spare)
+    }
+
+    pub(crate) fn push(&mut self, stream_id: u64) {
+        self.idle.push_back(stream_id);
+        self.counters.opened = self.counters.opened.saturating_add(1);
+    }
+
+    /// The stream for a new accept, if one is waiting.
+    pub(crate) fn take(&mut self) -> Option<u64> {
+        if self.target == 0 {
+            return None;
+        }
+        let stream_id = self.idle.pop_front();
+        if stream_id.is_some() {
+            self.counters.hits = self.counters.hits.saturating_add(1);
+        } else {
+            self.counters.misses = self.counters.misses.saturating_add(1);
+        }
+        stream_id
+    }
+
+    /// Drop a pooled stream the peer reset; false when `stream_id` is not
+    /// in the pool.
+    pub(crate) fn forfeit(&mut self, stream_id: u64) -> bool {
+        let Some(index) = self.idle.iter().position(|id| *id == stream_id) else {
+            return false;
+        };
+        self.idle.remove(index);
+        self.burned = self.burned.saturating_add(1);
+        self.counters.reset_before_use = self.counters.reset_before_use.saturating_add(1);
+        true
+    }
+
+    /// Forget the pooled streams of a closed connection; the counters
+    /// stay.
+    pub(crate) fn clear(&mut self) {
+        self.idle.clear();
+        self.burned = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StreamPool, StreamPoolCounters};
+
+    #[test]
+    fn pool_fills_only_into_spare_credit() {
+        let mut pool = StreamPool::new(4);
+        assert_eq!(pool.wanted(0, 0), 0);
+        // Two of three credits held by accepts leave room for one.
+        assert_eq!(pool.wanted(3, 2), 1);
+        pool.push(0);
+        assert_eq!(pool.wanted(3, 2), 0);
+        assert_eq!(pool.wanted(100, 2), 3);
+        for stream_id in [4, 8, 12] {
+            pool.push(stream_id);
+        }
+        assert_eq!(pool.wanted(100, 2), 0);
+
+        assert_eq!(StreamPool::new(0).wanted(100, 0), 0);
+        assert_eq!(StreamPool::new(0).take(), None);
+        assert_eq!(StreamPool::new(0).counters(), StreamPoolCounters::default());
+    }
+
+    #[test]
+    fn reset_pooled_streams_burn_their_credit() {
+        let mut pool = StreamPool::new(3);
+        for stream_id in [0, 4, 8] {
+            pool.push(stream_id);
+        }
+        assert!(pool.forfeit(4));
+        assert!(!pool.forfeit(4));
+        assert_eq!((pool.idle.len(), pool.burned()), (2, 1));
+        // Three credits: two pooled and one burned.
+        assert_eq!(pool.wanted(3, 0), 0);
+        assert_eq!(pool.wanted(4, 0), 1);
+
+        assert_eq!(pool.take(), Some(0));
+        assert_eq!(pool.take(), Some(8));
+        assert_eq!(pool.take(), None);
+        assert_eq!(
+            pool.counters(),
+            StreamPoolCounters {
+                opened: 3,
+                hits: 2,
+                misses: 1,
+                reset_before_use: 1,
+            }
+        );
+
+        pool.push(12);
+        pool.clear();
+        assert_eq!((pool.idle.len(), pool.burned()), (0, 0));
+        assert_eq!(pool.counters().opened, 4);
+    }
+}
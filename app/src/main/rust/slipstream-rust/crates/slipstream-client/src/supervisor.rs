@@ -0,0 +1,297 @@
+//! Supervision of a client loop running on its own blocking thread.
+//!
+//! Embedders that drive the client from a host API (the Android JNI
+//! bindings, a service wrapper) start the loop on a thread, ask it to stop
+//! through a shutdown flag, and must never run two loops at once: a second
+//! instance would race the first for the listener port. A loop that does
+//! not exit within the stop timeout is abandoned rather than joined, so the
+//! host call returns; the shutdown flag stays raised for it, and the next
+//! start waits for it to finish before re-arming the flag.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Spacing of the checks for the thread to finish.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What [`ClientSupervisor::prepare_start`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartCheck {
+    /// Nothing is running; the shutdown flag has been cleared.
+    Ready,
+    /// A loop is already running.
+    AlreadyRunning,
+    /// An abandoned loop did not finish within the stop timeout.
+    PreviousStillRunning,
+}
+
+/// How [`ClientSupervisor::stop`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// The thread exited and was joined (or none was running).
+    Joined,
+    /// The thread outlived the stop timeout and was left to exit on its own.
+    Abandoned,
+}
+
+/// The supervised loop as a host would report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientStatus {
+    Idle,
+    Running,
+    /// Stopped, but the thread has not exited yet.
+    Stopping,
+}
+
+/// Starts, stops, and tracks one client loop thread.
+///
+/// `const`-constructible so it can live in a `static` next to the host
+/// bindings that call into it.
+pub struct ClientSupervisor {
+    running: AtomicBool,
+    shutdown: AtomicBool,
+    /// Wakes a loop waiting in [`shutdown_requested`](Self::shutdown_requested).
+    shutdown_wake: Notify,
+    thread_done: AtomicBool,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    stop_timeout: Duration,
+}
+
+impl ClientSupervisor {
+    /// `stop_timeout` bounds how long a stop waits for the loop to exit,
+    /// and how long a start waits for an abandoned loop.
+    pub const fn new(stop_timeout: Duration) -> Self {
+        Self {
+            running: AtomicBool::new(false),
+            shutdown: AtomicBool::new(false),
+            shutdown_wake: Notify::const_new(),
+            thread_done: AtomicBool::new(true),
+            thread: Mutex::new(None),
+            stop_timeout,
+        }
+    }
+
+    /// Polled by the loop; true once a stop was requested.
+    pub fn should_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once a stop was requested, so a loop can wait on it next to
+    /// its sleeps instead of only checking the flag when it wakes.
+    pub async fn shutdown_requested(&self) {
+        while !self.should_shutdown() {
+            self.shutdown_wake.notified().await;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> ClientStatus {
+        if self.is_running() {
+            ClientStatus::Running
+        } else if self.thread_done.load(Ordering::SeqCst) {
+            ClientStatus::Idle
+        } else {
+            ClientStatus::Stopping
+        }
+    }
+
+    /// Checks a new loop may start, waiting up to the stop timeout for an
+    /// abandoned one to finish. On [`StartCheck::Ready`] the shutdown flag
+    /// is cleared for the new loop.
+    pub fn prepare_start(&self) -> StartCheck {
+        if self.is_running() {
+            return StartCheck::AlreadyRunning;
+        }
+        if !self.thread_done.load(Ordering::SeqCst) {
+            info!("Waiting for previous client thread to finish...");
+            if !self.wait_for_thread() {
+                return StartCheck::PreviousStillRunning;
+            }
+        }
+        self.shutdown.store(false, Ordering::SeqCst);
+        StartCheck::Ready
+    }
+
+    /// Runs `run` on a new thread named `name`. The loop counts as running
+    /// until `run` returns or panics.
+    pub fn spawn<F>(&'static self, name: &str, run: F) -> io::Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.running.store(true, Ordering::SeqCst);
+        self.thread_done.store(false, Ordering::SeqCst);
+        let spawned = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let _exit = ThreadExit(self);
+                run();
+            });
+        match spawned {
+            Ok(handle) => {
+                if let Ok(mut slot) = self.thread.lock() {
+                    *slot = Some(handle);
+                }
+                Ok(())
+            }
+            Err(err) => {
+                drop(ThreadExit(self));
+                Err(err)
+            }
+        }
+    }
+
+    /// Asks the loop to shut down and waits up to the stop timeout for it.
+    ///
+    /// A loop that exits is joined and the shutdown flag cleared. One that
+    /// does not is abandoned with the flag left raised, so it still sees the
+    /// request and releases the listener port; the next
+    /// [`prepare_start`](Self::prepare_start) waits for it.
+    pub fn stop(&self) -> StopOutcome {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.shutdown_wake.notify_one();
+        let outcome = if self.wait_for_thread() {
+            if let Some(handle) = self.take_thread() {
+                let _ = handle.join();
+            }
+            self.shutdown.store(false, Ordering::SeqCst);
+            StopOutcome::Joined
+        } else {
+            warn!("Client thread did not exit within timeout, abandoning");
+            // Dropping the handle detaches the thread.
+            drop(self.take_thread());
+            StopOutcome::Abandoned
+        };
+        self.running.store(false, Ordering::SeqCst);
+        outcome
+    }
+
+    fn take_thread(&self) -> Option<JoinHandle<()>> {
+        self.thread.lock().ok().and_then(|mut slot| slot.take())
+    }
+
+    /// True once the thread has finished, waiting up to the stop timeout.
+    fn wait_for_thread(&self) -> bool {
+        let deadline = Instant::now() + self.stop_timeout;
+        loop {
+            if self.thread_done.load(Ordering::SeqCst) {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+/// Marks the loop finished when its thread ends, panics included.
+struct ThreadExit(&'static ClientSupervisor);
+
+impl Drop for ThreadExit {
+    fn drop(&mut self) {
+        self.0.running.store(false, Ordering::SeqCst);
+        self.0.thread_done.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientStatus, ClientSupervisor, StartCheck, StopOutcome};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn supervisor(stop_timeout_ms: u64) -> &'static ClientSupervisor {
+        Box::leak(Box::new(ClientSupervisor::new(Duration::from_millis(
+            stop_timeout_ms,
+        ))))
+    }
+
+    #[test]
+    fn stop_joins_a_loop_that_sees_the_shutdown_flag() {
+        let supervisor = supervisor(5_000);
+        assert_eq!(supervisor.status(), ClientStatus::Idle);
+        assert_eq!(supervisor.stop(), StopOutcome::Joined);
+        assert!(!supervisor.should_shutdown());
+
+        assert_eq!(supervisor.prepare_start(), StartCheck::Ready);
+        supervisor
+            .spawn("fake-client", move || {
+                while !supervisor.should_shutdown() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+            .unwrap();
+        assert_eq!(supervisor.status(), ClientStatus::Running);
+        assert_eq!(supervisor.prepare_start(), StartCheck::AlreadyRunning);
+
+        assert_eq!(supervisor.stop(), StopOutcome::Joined);
+        assert!(!supervisor.should_shutdown());
+        assert_eq!(supervisor.status(), ClientStatus::Idle);
+
+        // A loop that panics still counts as finished.
+        assert_eq!(supervisor.prepare_start(), StartCheck::Ready);
+        supervisor
+            .spawn("fake-client", || panic!("fake client failed"))
+            .unwrap();
+        assert_eq!(supervisor.stop(), StopOutcome::Joined);
+        assert_eq!(supervisor.status(), ClientStatus::Idle);
+    }
+
+    #[test]
+    fn stop_wakes_a_loop_waiting_on_shutdown() {
+        let supervisor = supervisor(5_000);
+        assert_eq!(supervisor.prepare_start(), StartCheck::Ready);
+        supervisor
+            .spawn("fake-client", move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_time()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async {
+                    tokio::select! {
+                        _ = supervisor.shutdown_requested() => {}
+                        _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+                    }
+                });
+            })
+            .unwrap();
+        let started = std::time::Instant::now();
+        assert_eq!(supervisor.stop(), StopOutcome::Joined);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stuck_loop_is_abandoned_until_it_exits() {
+        let supervisor = supervisor(200);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        assert_eq!(supervisor.prepare_start(), StartCheck::Ready);
+        supervisor
+            .spawn("fake-client", move || {
+                let _ = release_rx.recv();
+            })
+            .unwrap();
+
+        assert_eq!(supervisor.stop(), StopOutcome::Abandoned);
+        // The flag stays raised for the abandoned loop.
+        assert!(supervisor.should_shutdown());
+        assert!(!supervisor.is_running());
+        assert_eq!(supervisor.status(), ClientStatus::Stopping);
+        assert_eq!(supervisor.prepare_start(), StartCheck::PreviousStillRunning);
+        assert!(supervisor.should_shutdown());
+
+        release_tx.send(()).unwrap();
+        assert_eq!(supervisor.prepare_start(), StartCheck::Ready);
+        assert!(!supervisor.should_shutdown());
+        assert_eq!(supervisor.status(), ClientStatus::Idle);
+    }
+}
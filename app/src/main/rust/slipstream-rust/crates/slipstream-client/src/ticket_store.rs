@@ -0,0 +1,267 @@
+//! Persistence for QUIC session tickets.
+//!
+//! Tickets carry resumption secrets, so besides the plaintext file the store
+//! can seal them with AES-256-GCM under a caller-supplied key (on Android
+//! one held by the Keystore). A file that fails to open is discarded: a lost
+//! ticket only costs a full handshake, while a crash costs the tunnel.
+
+use crate::error::ClientError;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use slipstream_ffi::TicketStoreKey;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Leads every sealed file; the last byte is the format version.
+const SEALED_MAGIC: &[u8; 5] = b"SLTK\x01";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+pub trait TicketStore {
+    /// The stored tickets, or `None` when there are none or the file was
+    /// unreadable and has been discarded.
+    fn load(&self) -> Option<Vec<u8>>;
+
+    /// Replace the stored tickets.
+    fn save(&self, tickets: &[u8]) -> Result<(), ClientError>;
+}
+
+/// The matching store for `path`: sealed when a key is given.
+pub fn ticket_store(path: PathBuf, key: Option<TicketStoreKey>) -> Box<dyn TicketStore> {
+    match key {
+        Some(key) => Box::new(EncryptedTicketStore::new(path, key)),
+        None => Box::new(PlainTicketStore::new(path)),
+    }
+}
+
+/// Tickets as picoquic writes them, readable by anyone who can read `path`.
+#[derive(Debug)]
+pub struct PlainTicketStore {
+    path: PathBuf,
+}
+
+impl PlainTicketStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl TicketStore for PlainTicketStore {
+    fn load(&self) -> Option<Vec<u8>> {
+        read_store(&self.path)
+    }
+
+    fn save(&self, tickets: &[u8]) -> Result<(), ClientError> {
+        write_store(&self.path, tickets)
+    }
+}
+
+/// Tickets sealed with AES-256-GCM: magic, a random nonce, then the
+/// ciphertext and its tag. The magic is authenticated too.
+#[derive(Debug)]
+pub struct EncryptedTicketStore {
+    path: PathBuf,
+    key: TicketStoreKey,
+}
+
+impl EncryptedTicketStore {
+    pub fn new(path: PathBuf, key: TicketStoreKey) -> Self {
+        Self { path, key }
+    }
+
+    fn seal(&self, tickets: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        openssl::rand::rand_bytes(&mut nonce)
+            .map_err(|err| ClientError::new(format!("Ticket store nonce: {}", err)))?;
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key.0,
+            Some(&nonce),
+            SEALED_MAGIC,
+            tickets,
+            &mut tag,
+        )
+        .map_err(|err| ClientError::new(format!("Ticket store encryption: {}", err)))?;
+        let mut sealed =
+            Vec::with_capacity(SEALED_MAGIC.len() + NONCE_LEN + ciphertext.len() + TAG_LEN);
+        sealed.extend_from_slice(SEALED_MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed.extend_from_slice(&tag);
+        Ok(sealed)
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let body = sealed
+            .strip_prefix(SEALED_MAGIC.as_slice())
+            .ok_or("not a sealed ticket store")?;
+        if body.len() < NONCE_LEN + TAG_LEN {
+            return Err("truncated");
+        }
+        let (nonce, rest) = body.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key.0,
+            Some(nonce),
+            SEALED_MAGIC,
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| "authentication failed")
+    }
+}
+
+impl TicketStore for EncryptedTicketStore {
+    fn load(&self) -> Option<Vec<u8>> {
+        let sealed = read_store(&self.path)?;
+        match self.open(&sealed) {
+            Ok(tickets) => Some(tickets),
+            Err(reason) => {
+                warn!(
+                    "Discarding session ticket store {} ({}); the next connection does a full handshake",
+                    self.path.display(),
+                    reason
+                );
+                discard(&self.path);
+                None
+            }
+        }
+    }
+
+    fn save(&self, tickets: &[u8]) -> Result<(), ClientError> {
+        write_store(&self.path, &self.seal(tickets)?)
+    }
+}
+
+fn read_store(path: &Path) -> Option<Vec<u8>> {
+    match fs::read(path) {
+        Ok(bytes) => Some(bytes),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!(
+                "Could not read session ticket store {}: {}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+fn discard(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        if err.kind() != io::ErrorKind::NotFound {
+            warn!(
+                "Could not remove session ticket store {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// Write through a temporary file and rename it over `path`, so a crash
+/// mid-write leaves the previous tickets rather than a torn file.
+fn write_store(path: &Path, bytes: &[u8]) -> Result<(), ClientError> {
+    let map_err = |err: io::Error| {
+        ClientError::new(format!(
+            "Could not write session ticket store {}: {}",
+            path.display(),
+            err
+        ))
+    };
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).map_err(map_err)?;
+    file.write_all(bytes).map_err(map_err)?;
+    file.sync_all().map_err(map_err)?;
+    fs::rename(&tmp, path).map_err(map_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: TicketStoreKey = TicketStoreKey([7u8; 32]);
+
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "slipstream-tickets-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn both_stores_round_trip_and_start_empty() {
+        let tickets = b"ticket bytes".to_vec();
+        for (name, key) in [("plain", None), ("sealed", Some(KEY))] {
+            let path = TempPath::new(name);
+            let store = ticket_store(path.0.clone(), key);
+            assert_eq!(store.load(), None);
+            store.save(&tickets).expect("save");
+            assert_eq!(store.load(), Some(tickets.clone()));
+        }
+    }
+
+    #[test]
+    fn sealed_store_does_not_hold_the_tickets_in_the_clear() {
+        let path = TempPath::new("clear");
+        let store = EncryptedTicketStore::new(path.0.clone(), KEY);
+        store.save(b"resumption secret").expect("save");
+        let on_disk = fs::read(&path.0).expect("read");
+        assert!(on_disk.starts_with(SEALED_MAGIC));
+        assert!(!on_disk
+            .windows(b"resumption secret".len())
+            .any(|window| window == b"resumption secret"));
+    }
+
+    #[test]
+    fn corrupt_sealed_store_is_discarded() {
+        let path = TempPath::new("corrupt");
+        let store = EncryptedTicketStore::new(path.0.clone(), KEY);
+        store.save(b"tickets").expect("save");
+        let mut sealed = fs::read(&path.0).expect("read");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0x01;
+        fs::write(&path.0, &sealed).expect("write");
+        assert_eq!(store.load(), None);
+        assert!(!path.0.exists(), "bad file should be removed");
+
+        // A wrong key, a truncated file and a plaintext file all fail the
+        // same way.
+        store.save(b"tickets").expect("save");
+        let other = EncryptedTicketStore::new(path.0.clone(), TicketStoreKey([8u8; 32]));
+        assert_eq!(other.load(), None);
+        fs::write(&path.0, &SEALED_MAGIC[..]).expect("write");
+        assert_eq!(store.load(), None);
+        PlainTicketStore::new(path.0.clone())
+            .save(b"tickets")
+            .expect("save");
+        assert_eq!(store.load(), None);
+        assert!(!path.0.exists());
+    }
+}
@@ -0,0 +1,120 @@
+//! Single-threaded owner for state shared with picoquic callbacks.
+//!
+//! picoquic hands the callback context back as a raw pointer, so the state
+//! must live at a stable address and the loop must never hold a Rust
+//! reference to it across a picoquic call that can re-enter the callback.
+//! [`CallbackState`] allocates the state once, gives picoquic the context
+//! pointer, and lends out short-lived references through safe methods. In
+//! debug builds every access also checks that it happens on the thread that
+//! created the state.
+
+use std::ffi::c_void;
+use std::ptr::NonNull;
+#[cfg(debug_assertions)]
+use std::thread::{self, ThreadId};
+
+struct Owned<T> {
+    #[cfg(debug_assertions)]
+    owner: ThreadId,
+    value: T,
+}
+
+impl<T> Owned<T> {
+    #[inline]
+    fn check_thread(&self) {
+        #[cfg(debug_assertions)]
+        debug_assert_eq!(
+            self.owner,
+            thread::current().id(),
+            "callback state used off its owning thread"
+        );
+    }
+}
+
+/// Owns `T` for the lifetime of a picoquic context that points at it.
+///
+/// Not `Send`: the state stays on the thread that runs the picoquic loop.
+pub struct CallbackState<T> {
+    ptr: NonNull<Owned<T>>,
+}
+
+impl<T> CallbackState<T> {
+    pub fn new(value: T) -> Self {
+        let owned = Box::new(Owned {
+            #[cfg(debug_assertions)]
+            owner: thread::current().id(),
+            value,
+        });
+        Self {
+            ptr: NonNull::from(Box::leak(owned)),
+        }
+    }
+
+    /// Context pointer to register with picoquic; read it back with
+    /// [`from_callback_ctx`].
+    pub fn callback_ctx(&self) -> *mut c_void {
+        self.ptr.as_ptr().cast()
+    }
+
+    pub fn get(&self) -> &T {
+        // SAFETY: the allocation lives until drop, and callbacks only run
+        // inside picoquic calls, which cannot overlap this borrow of self.
+        let owned = unsafe { self.ptr.as_ref() };
+        owned.check_thread();
+        &owned.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        // SAFETY: as in `get`; `&mut self` rules out other Rust borrows.
+        let owned = unsafe { self.ptr.as_mut() };
+        owned.check_thread();
+        &mut owned.value
+    }
+}
+
+impl<T> Drop for CallbackState<T> {
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from `Box::leak` in `new`.
+        drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+    }
+}
+
+/// Recovers the state behind a context pointer inside a picoquic callback.
+///
+/// # Safety
+///
+/// `ctx` must come from [`CallbackState::callback_ctx`] on a live owner of
+/// the same `T`, and no reference obtained from that owner may be alive for
+/// the returned lifetime.
+pub unsafe fn from_callback_ctx<'a, T>(ctx: *mut c_void) -> &'a mut T {
+    let owned = &mut *ctx.cast::<Owned<T>>();
+    owned.check_thread();
+    &mut owned.value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_ctx_reaches_owned_value() {
+        let mut state = CallbackState::new(1u32);
+        let ctx = state.callback_ctx();
+        unsafe { *from_callback_ctx::<u32>(ctx) += 1 };
+        *state.get_mut() += 1;
+        assert_eq!(*state.get(), 3);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn use_from_another_thread_is_caught() {
+        let state = CallbackState::new(0u32);
+        let ctx = state.callback_ctx() as usize;
+        let result = std::thread::spawn(move || unsafe {
+            *from_callback_ctx::<u32>(ctx as *mut c_void) += 1;
+        })
+        .join();
+        assert!(result.is_err(), "cross-thread access should panic");
+        assert_eq!(*state.get(), 0);
+    }
+}
@@ -0,0 +1,54 @@
+use std::cell::Cell;
+
+/// Microsecond time source for loop scheduling.
+///
+/// The runtime uses picoquic's clock (`slipstream_ffi::PicoquicClock`) so its
+/// timestamps line up with the ones picoquic hands back; tests drive a
+/// [`ManualClock`] instead of sleeping.
+pub trait Clock {
+    fn now_us(&self) -> u64;
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now_us: Cell<u64>,
+}
+
+impl ManualClock {
+    pub fn new(start_us: u64) -> Self {
+        Self {
+            now_us: Cell::new(start_us),
+        }
+    }
+
+    pub fn advance_us(&self, delta_us: u64) {
+        self.now_us.set(self.now_us.get().saturating_add(delta_us));
+    }
+
+    pub fn set_us(&self, now_us: u64) {
+        self.now_us.set(now_us);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_us(&self) -> u64 {
+        self.now_us.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn manual_clock_moves_only_when_advanced() {
+        let clock = ManualClock::new(5);
+        assert_eq!(clock.now_us(), 5);
+        clock.advance_us(10);
+        assert_eq!(clock.now_us(), 15);
+        clock.set_us(u64::MAX);
+        clock.advance_us(1);
+        assert_eq!(clock.now_us(), u64::MAX);
+    }
+}
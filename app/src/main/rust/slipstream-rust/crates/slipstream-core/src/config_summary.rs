@@ -0,0 +1,140 @@
+//! One-line summary of the effective configuration, logged at startup so
+//! support requests carry it.
+//!
+//! Values go through the builder methods, which decide what may appear:
+//! [`ConfigSummary::secret`] only records whether a value was set and
+//! [`ConfigSummary::fingerprint`] keeps a short prefix of a digest, so the
+//! summary is safe to paste into an issue.
+
+use std::fmt;
+
+/// Hex characters of a fingerprint kept by [`ConfigSummary::fingerprint`].
+pub const FINGERPRINT_PREFIX_CHARS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct ConfigSummary {
+    side: &'static str,
+    entries: Vec<(&'static str, String)>,
+}
+
+impl ConfigSummary {
+    /// `side` is `client` or `server`.
+    pub fn new(side: &'static str) -> Self {
+        Self {
+            side,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn field(&mut self, key: &'static str, value: impl fmt::Display) -> &mut Self {
+        self.entries.push((key, value.to_string()));
+        self
+    }
+
+    /// Comma-separated list, or `none` when empty.
+    pub fn list<I, T>(&mut self, key: &'static str, values: I) -> &mut Self
+    where
+        I: IntoIterator<Item = T>,
+        T: fmt::Display,
+    {
+        let joined = values
+            .into_iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.field(key, if joined.is_empty() { "none" } else { &joined })
+    }
+
+    /// Records only whether a secret is present, never its value.
+    pub fn secret(&mut self, key: &'static str, present: bool) -> &mut Self {
+        self.field(key, if present { "<redacted>" } else { "none" })
+    }
+
+    /// The first [`FINGERPRINT_PREFIX_CHARS`] hex characters of `digest`,
+    /// or `none`.
+    pub fn fingerprint(&mut self, key: &'static str, digest: Option<&[u8]>) -> &mut Self {
+        let value = match digest {
+            Some(digest) => {
+                let mut hex = digest
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>();
+                hex.truncate(FINGERPRINT_PREFIX_CHARS);
+                format!("{}...", hex)
+            }
+            None => "none".to_string(),
+        };
+        self.field(key, value)
+    }
+
+    /// Names of the features that are on, in the order given.
+    pub fn features(&mut self, features: &[(&'static str, bool)]) -> &mut Self {
+        self.list(
+            "features",
+            features
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name),
+        )
+    }
+}
+
+impl fmt::Display for ConfigSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Effective config side={}", self.side)?;
+        for (key, value) in &self.entries {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                write!(f, " {}={:?}", key, value)?;
+            } else {
+                write!(f, " {}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_fields_lists_and_features() {
+        let mut summary = ConfigSummary::new("client");
+        summary
+            .field("domain", "test.example.com")
+            .list(
+                "resolvers",
+                ["1.1.1.1:53/recursive", "8.8.8.8:53/authoritative"],
+            )
+            .list("rotation_domains", Vec::<String>::new())
+            .field("cc", "bbr v2")
+            .features(&[
+                ("gso", false),
+                ("debug_poll", true),
+                ("battery_saver", true),
+            ]);
+        assert_eq!(
+            summary.to_string(),
+            "Effective config side=client domain=test.example.com \
+             resolvers=1.1.1.1:53/recursive,8.8.8.8:53/authoritative rotation_domains=none \
+             cc=\"bbr v2\" features=debug_poll,battery_saver"
+        );
+    }
+
+    #[test]
+    fn secrets_and_fingerprints_are_redacted() {
+        let digest = [0xde, 0xad, 0xbe, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89];
+        let mut summary = ConfigSummary::new("server");
+        summary
+            .secret("key", true)
+            .secret("reset_seed", false)
+            .fingerprint("pin", Some(&digest))
+            .fingerprint("other_pin", None);
+        let line = summary.to_string();
+        assert_eq!(
+            line,
+            "Effective config side=server key=<redacted> reset_seed=none pin=deadbeef... other_pin=none"
+        );
+        assert!(!line.contains("01234567"));
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::sync::OnceLock;
 
 const DEFAULT_STREAM_QUEUE_MAX_BYTES: usize = 2 * 1024 * 1024;
@@ -11,6 +12,8 @@ pub struct FlowControlState {
     pub fin_offset: Option<u64>,
     pub discarding: bool,
     pub stop_sending_sent: bool,
+    /// Receive credit is being withheld; see [`BackpressureWatermarks`].
+    pub backpressured: bool,
 }
 
 pub trait HasFlowControlState {
@@ -62,10 +65,53 @@ pub trait FlowControlStream: HasFlowControlState {
     fn set_stop_sending_sent(&mut self, value: bool) {
         self.flow_control_mut().stop_sending_sent = value;
     }
+
+    fn backpressured(&self) -> bool {
+        self.flow_control().backpressured
+    }
 }
 
 impl<T: HasFlowControlState> FlowControlStream for T {}
 
+/// Queue thresholds for multi-stream receive backpressure.
+///
+/// Above `high_bytes` a stream stops advancing its consumed offset, so the
+/// peer runs out of stream (and connection) credit instead of pushing the
+/// queue into overflow. Once the queue drains to `low_bytes` the consumed
+/// offset catches up to everything received and the window reopens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackpressureWatermarks {
+    pub high_bytes: usize,
+    pub low_bytes: usize,
+}
+
+impl BackpressureWatermarks {
+    /// A zero high watermark disables backpressure; a zero low watermark
+    /// defaults to half the high one.
+    pub fn new(high_bytes: usize, low_bytes: usize) -> Option<Self> {
+        if high_bytes == 0 {
+            return None;
+        }
+        let low_bytes = if low_bytes == 0 {
+            high_bytes / 2
+        } else {
+            low_bytes.min(high_bytes)
+        };
+        Some(Self {
+            high_bytes,
+            low_bytes,
+        })
+    }
+
+    pub fn should_apply(&self, queued_bytes: usize) -> bool {
+        queued_bytes > self.high_bytes
+    }
+
+    pub fn should_release(&self, queued_bytes: usize) -> bool {
+        queued_bytes <= self.low_bytes
+    }
+}
+
 pub struct QueueOverflowOps<Log, Consume, Stop, Err> {
     pub log_overflow: Log,
     pub consume: Consume,
@@ -103,33 +149,40 @@ pub struct StreamReceiveOps<Enqueue, Overflow, Consume, Stop, Log, Err> {
     pub on_consume_error: Err,
 }
 
+/// `stream` is whatever names the stream in the caller's logs, usually a tag
+/// carrying the connection as well as the stream ID.
 pub fn overflow_log_message(
-    stream_id: u64,
+    stream: impl fmt::Display,
     queued_bytes: usize,
     incoming_len: usize,
     max_queue: usize,
 ) -> String {
     format!(
-        "stream {}: queued_bytes {} + {} exceeds limit {}; stopping",
-        stream_id, queued_bytes, incoming_len, max_queue
+        "{}: queued_bytes {} + {} exceeds limit {}; stopping",
+        stream, queued_bytes, incoming_len, max_queue
     )
 }
 
 pub fn consume_error_log_message(
-    stream_id: u64,
+    stream: impl fmt::Display,
     context: &str,
     ret: i32,
     current: u64,
     target: u64,
 ) -> String {
     format!(
-        "stream {}: stream_data_consumed failed{} ret={} consumed_offset={} target={}",
-        stream_id, context, ret, current, target
+        "{}: stream_data_consumed failed{} ret={} consumed_offset={} target={}",
+        stream, context, ret, current, target
     )
 }
 
-pub fn promote_error_log_message(stream_id: u64, ret: i32, current: u64, target: u64) -> String {
-    consume_error_log_message(stream_id, " during promote", ret, current, target)
+pub fn promote_error_log_message(
+    stream: impl fmt::Display,
+    ret: i32,
+    current: u64,
+    target: u64,
+) -> String {
+    consume_error_log_message(stream, " during promote", ret, current, target)
 }
 
 pub fn stream_queue_max_bytes() -> usize {
@@ -173,6 +226,13 @@ pub fn reserve_target_offset(
     target
 }
 
+/// Whether `incoming_len` bytes at `rx_bytes` run past the final size the
+/// peer already announced with its FIN. A repeated FIN at the same offset
+/// is harmless and passes.
+pub fn exceeds_fin_offset(fin_offset: Option<u64>, rx_bytes: u64, incoming_len: usize) -> bool {
+    fin_offset.is_some_and(|fin| rx_bytes.saturating_add(incoming_len as u64) > fin)
+}
+
 pub fn apply_consumed_offset<F, G>(
     consumed_offset: &mut u64,
     target: u64,
@@ -195,6 +255,49 @@ where
     true
 }
 
+/// Merges back-to-back write-drained reports for the same stream so one
+/// command pass updates each stream's credit once.
+///
+/// Summing is exact: neither `reserve_target_offset` nor
+/// `promote_target_offset` grows as `queued_bytes` grows and consumed
+/// offsets only move forward, so after a run of drains only the target
+/// computed from the final `queued_bytes` can win.
+/// Reports for different streams in between end the run, keeping drains
+/// ordered with respect to every other command.
+#[derive(Debug)]
+pub struct DrainCoalescer<K> {
+    pending: Option<(K, usize)>,
+}
+
+impl<K: PartialEq> DrainCoalescer<K> {
+    pub fn new() -> Self {
+        Self { pending: None }
+    }
+
+    /// Adds a drain for `key`; returns the previous run when `key` starts a
+    /// new one.
+    pub fn push(&mut self, key: K, bytes: usize) -> Option<(K, usize)> {
+        match &mut self.pending {
+            Some((pending_key, pending_bytes)) if *pending_key == key => {
+                *pending_bytes = pending_bytes.saturating_add(bytes);
+                None
+            }
+            _ => self.pending.replace((key, bytes)),
+        }
+    }
+
+    /// Ends the current run, if any.
+    pub fn take(&mut self) -> Option<(K, usize)> {
+        self.pending.take()
+    }
+}
+
+impl<K: PartialEq> Default for DrainCoalescer<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub fn consume_stream_data<F, G>(
     consumed_offset: &mut u64,
     target: u64,
@@ -244,51 +347,107 @@ where
 pub struct PromoteEntry<'a> {
     pub stream_id: u64,
     pub rx_bytes: u64,
+    pub queued_bytes: usize,
+    pub fin_offset: Option<u64>,
     pub consumed_offset: &'a mut u64,
     pub discarding: bool,
 }
 
-pub fn promote_consumed_offset<F, G>(
+/// Offset to retry with when picoquic rejects the promotion target: the data
+/// already drained to the local socket, never past a known FIN.
+pub fn promote_clamp_offset(rx_bytes: u64, queued_bytes: usize, fin_offset: Option<u64>) -> u64 {
+    let drained = rx_bytes.saturating_sub(queued_bytes as u64);
+    fin_offset.map_or(drained, |fin| drained.min(fin))
+}
+
+/// Offset a multi-stream stream may be consumed up to: the drained data plus
+/// whatever room its queue has left under `max_queue`, never past `rx_bytes`
+/// or a known FIN. Consuming further would let the peer overrun the queue
+/// cap and force the stream into discard mode.
+pub fn promote_target_offset(
     rx_bytes: u64,
+    queued_bytes: usize,
+    fin_offset: Option<u64>,
+    max_queue: usize,
+) -> u64 {
+    let headroom = max_queue.saturating_sub(queued_bytes) as u64;
+    let target = rx_bytes
+        .saturating_sub(queued_bytes as u64)
+        .saturating_add(headroom)
+        .min(rx_bytes);
+    fin_offset.map_or(target, |fin| target.min(fin))
+}
+
+/// Moves `consumed_offset` up to `target`, falling back to `clamp_offset`
+/// if picoquic rejects it. Returns false when both fail.
+pub fn promote_consumed_offset<F, G>(
+    target: u64,
+    clamp_offset: u64,
     consumed_offset: &mut u64,
     mut consume_fn: F,
     mut on_error: G,
-) where
+) -> bool
+where
     F: FnMut(u64) -> i32,
-    G: FnMut(i32, u64),
+    G: FnMut(i32, u64, u64),
 {
-    if *consumed_offset >= rx_bytes {
-        return;
+    if *consumed_offset >= target {
+        return true;
     }
-    let _ = apply_consumed_offset(
-        consumed_offset,
-        rx_bytes,
-        &mut consume_fn,
-        |ret, current, _| {
-            on_error(ret, current);
-        },
-    );
+    if apply_consumed_offset(consumed_offset, target, &mut consume_fn, &mut on_error) {
+        return true;
+    }
+    clamp_offset < target
+        && apply_consumed_offset(
+            consumed_offset,
+            clamp_offset,
+            &mut consume_fn,
+            &mut on_error,
+        )
 }
 
-pub fn promote_streams<'a, I, Consume, Log>(entries: I, mut consume_fn: Consume, mut on_error: Log)
+/// Releases the single-stream reserve of every stream when a connection
+/// switches to multi-stream mode. Each stream is consumed only as far as
+/// [`promote_target_offset`] allows; later write-drained reports move it the
+/// rest of the way. Returns the streams whose consumed offset could not be
+/// moved even after clamping; callers must abort them.
+#[must_use]
+pub fn promote_streams<'a, I, Consume, Log>(
+    entries: I,
+    max_queue: usize,
+    mut consume_fn: Consume,
+    mut on_error: Log,
+) -> Vec<u64>
 where
     I: IntoIterator<Item = PromoteEntry<'a>>,
     Consume: FnMut(u64, u64) -> i32,
     Log: FnMut(u64, i32, u64, u64),
 {
+    let mut failed = Vec::new();
     for entry in entries {
         if entry.discarding {
             continue;
         }
         let stream_id = entry.stream_id;
-        let rx_bytes = entry.rx_bytes;
-        promote_consumed_offset(
-            rx_bytes,
+        let target = promote_target_offset(
+            entry.rx_bytes,
+            entry.queued_bytes,
+            entry.fin_offset,
+            max_queue,
+        );
+        let clamp_offset =
+            promote_clamp_offset(entry.rx_bytes, entry.queued_bytes, entry.fin_offset);
+        if !promote_consumed_offset(
+            target,
+            clamp_offset,
             entry.consumed_offset,
             |new_offset| consume_fn(stream_id, new_offset),
-            |ret, consumed_offset| on_error(stream_id, ret, consumed_offset, rx_bytes),
-        );
+            |ret, consumed_offset, target| on_error(stream_id, ret, consumed_offset, target),
+        ) {
+            failed.push(stream_id);
+        }
     }
+    failed
 }
 
 pub fn handle_stream_receive<S, Enqueue, Overflow, Consume, Stop, Log, Err>(
@@ -356,9 +515,10 @@ where
         }
 
         if !discarding
+            && !stream.backpressured()
             && !consume_stream_data(
                 &mut consumed_offset,
-                rx_bytes,
+                promote_target_offset(rx_bytes, queued_bytes, fin_offset, config.max_queue),
                 &mut ops.consume,
                 &mut ops.on_consume_error,
             )
@@ -392,3 +552,163 @@ where
 
     reset_stream
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_consumed_offset, exceeds_fin_offset, promote_streams, promote_target_offset,
+        reserve_target_offset, BackpressureWatermarks, DrainCoalescer, PromoteEntry,
+    };
+
+    #[test]
+    fn backpressure_watermarks_defaults_and_hysteresis() {
+        assert!(BackpressureWatermarks::new(0, 1024).is_none());
+
+        let marks = BackpressureWatermarks::new(1024, 0).expect("enabled");
+        assert_eq!(marks.low_bytes, 512);
+        assert!(!marks.should_apply(1024));
+        assert!(marks.should_apply(1025));
+        assert!(!marks.should_release(513));
+        assert!(marks.should_release(512));
+
+        let clamped = BackpressureWatermarks::new(1024, 4096).expect("enabled");
+        assert_eq!(clamped.low_bytes, 1024);
+    }
+
+    #[test]
+    fn promote_streams_clamps_then_reports_failures() {
+        // (stream_id, rx_bytes, queued_bytes, consumed_offset)
+        let mut streams = [
+            (0u64, 100u64, 40usize, 20u64),
+            (4, 100, 40, 20),
+            (8, 100, 40, 20),
+            (12, 100, 0, 20),
+        ];
+        let mut attempts = Vec::new();
+        let failed = promote_streams(
+            streams
+                .iter_mut()
+                .map(
+                    |(stream_id, rx_bytes, queued_bytes, consumed_offset)| PromoteEntry {
+                        stream_id: *stream_id,
+                        rx_bytes: *rx_bytes,
+                        queued_bytes: *queued_bytes,
+                        fin_offset: None,
+                        consumed_offset,
+                        discarding: false,
+                    },
+                ),
+            50,
+            |stream_id, new_offset| {
+                attempts.push((stream_id, new_offset));
+                match stream_id {
+                    // Rejects the full offset but takes the clamped one.
+                    4 if new_offset > 60 => -1,
+                    8 => -1,
+                    _ => 0,
+                }
+            },
+            |_, _, _, _| {},
+        );
+
+        assert_eq!(failed, vec![8]);
+        // Drained data plus the 10 bytes the queue cap still allows.
+        assert_eq!(streams[0].3, 70);
+        assert_eq!(streams[1].3, 60);
+        assert_eq!(streams[2].3, 20);
+        // An empty queue is consumed up to rx_bytes and no further.
+        assert_eq!(streams[3].3, 100);
+        assert_eq!(
+            attempts,
+            vec![(0, 70), (4, 70), (4, 60), (8, 70), (8, 60), (12, 100)]
+        );
+    }
+
+    #[test]
+    fn promote_target_offset_stays_under_the_queue_cap() {
+        // Queue at the cap: only the drained data is released.
+        assert_eq!(promote_target_offset(1_000, 400, None, 400), 600);
+        // Room left in the queue is released on top of the drained data.
+        assert_eq!(promote_target_offset(1_000, 300, None, 400), 800);
+        assert_eq!(promote_target_offset(1_000, 300, Some(750), 400), 750);
+        // Released data never runs past what has arrived.
+        assert_eq!(promote_target_offset(1_000, 100, None, 4_096), 1_000);
+        // Whatever the cap, more queued data never releases more.
+        for queued in 0..1_000usize {
+            assert!(
+                promote_target_offset(1_000, queued + 1, None, 600)
+                    <= promote_target_offset(1_000, queued, None, 600)
+            );
+        }
+    }
+
+    #[test]
+    fn drain_coalescer_merges_only_consecutive_runs() {
+        let mut coalescer = DrainCoalescer::new();
+        assert_eq!(coalescer.push(4u64, 10), None);
+        assert_eq!(coalescer.push(4, 5), None);
+        assert_eq!(coalescer.push(8, 7), Some((4, 15)));
+        assert_eq!(coalescer.push(4, 1), Some((8, 7)));
+        assert_eq!(coalescer.take(), Some((4, 1)));
+        assert_eq!(coalescer.take(), None);
+    }
+
+    #[test]
+    fn coalesced_drains_advance_consumed_offset_like_single_drains() {
+        let drains: &[usize] = &[1, 300, 0, 4096, 17, 2000, 5];
+        for &(rx_bytes, reserve, fin_offset) in &[
+            (10_000u64, 0usize, None),
+            (10_000, 1024, None),
+            (10_000, 64 * 1024, None),
+            (10_000, 1024, Some(9_000u64)),
+            (10_000, 0, Some(10_000)),
+        ] {
+            let queued_start = 8_000usize;
+            let drain = |queued: usize, consumed: &mut u64, calls: &mut Vec<u64>| {
+                let target = reserve_target_offset(rx_bytes, queued, fin_offset, reserve);
+                apply_consumed_offset(
+                    consumed,
+                    target,
+                    |offset| {
+                        calls.push(offset);
+                        0
+                    },
+                    |_, _, _| {},
+                );
+            };
+
+            let mut queued = queued_start;
+            let mut single = 0u64;
+            let mut single_calls = Vec::new();
+            for &bytes in drains {
+                queued = queued.saturating_sub(bytes);
+                drain(queued, &mut single, &mut single_calls);
+            }
+
+            let mut coalescer = DrainCoalescer::new();
+            for &bytes in drains {
+                assert_eq!(coalescer.push(0u64, bytes), None);
+            }
+            let (_, total) = coalescer.take().expect("pending run");
+            let merged_queued = queued_start.saturating_sub(total);
+            let mut merged = 0u64;
+            let mut merged_calls = Vec::new();
+            drain(merged_queued, &mut merged, &mut merged_calls);
+
+            assert_eq!(merged_queued, queued);
+            assert_eq!(merged, single, "reserve={} fin={:?}", reserve, fin_offset);
+            assert_eq!(merged_calls.last(), single_calls.last());
+            assert!(merged_calls.len() <= 1);
+        }
+    }
+
+    #[test]
+    fn only_data_past_the_fin_offset_exceeds_it() {
+        assert!(!exceeds_fin_offset(None, 100, 10));
+        assert!(!exceeds_fin_offset(Some(100), 90, 10));
+        // A duplicate FIN carries no data.
+        assert!(!exceeds_fin_offset(Some(100), 100, 0));
+        assert!(exceeds_fin_offset(Some(100), 100, 1));
+        assert!(exceeds_fin_offset(Some(100), 95, 10));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::clock::Clock;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 pub struct InvariantReporter {
@@ -13,7 +14,8 @@ impl InvariantReporter {
         }
     }
 
-    fn should_log(&self, now_us: u64) -> bool {
+    fn should_log(&self, clock: &dyn Clock) -> bool {
+        let now_us = clock.now_us();
         loop {
             let last = self.last_log_at.load(Ordering::Relaxed);
             if now_us.saturating_sub(last) < self.interval_us {
@@ -29,12 +31,12 @@ impl InvariantReporter {
         }
     }
 
-    pub fn report<M, L>(&self, now_us: u64, make_message: M, log: L)
+    pub fn report<M, L>(&self, clock: &dyn Clock, make_message: M, log: L)
     where
         M: FnOnce() -> String,
         L: FnOnce(&str),
     {
-        let should_log = self.should_log(now_us);
+        let should_log = self.should_log(clock);
         let should_panic = cfg!(any(test, feature = "invariant-panic"));
         if should_log || should_panic {
             let message = make_message();
@@ -46,4 +48,33 @@ impl InvariantReporter {
             }
         }
     }
+
+    /// Rate-limited like [`Self::report`] but never panics: the broken
+    /// invariant is the peer's, so a test that provokes one still runs on.
+    pub fn report_peer<M, L>(&self, clock: &dyn Clock, make_message: M, log: L)
+    where
+        M: FnOnce() -> String,
+        L: FnOnce(&str),
+    {
+        if self.should_log(clock) {
+            log(&make_message());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InvariantReporter;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn logs_at_most_once_per_interval() {
+        let reporter = InvariantReporter::new(1_000);
+        let clock = ManualClock::new(5_000);
+        assert!(reporter.should_log(&clock));
+        clock.advance_us(999);
+        assert!(!reporter.should_log(&clock));
+        clock.advance_us(1);
+        assert!(reporter.should_log(&clock));
+    }
 }
@@ -0,0 +1,134 @@
+//! Fixed-bucket latency histogram for streaming percentile estimates.
+//!
+//! Samples land in one of a fixed set of millisecond buckets, so recording
+//! is constant time and memory and a percentile is reported as the upper
+//! bound of the bucket it falls in. That is coarse but stable, which is
+//! what a stats line needs.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Upper bounds of the buckets in milliseconds; anything slower lands in a
+/// final overflow bucket reported as the last bound.
+const BUCKET_BOUNDS_MS: [u64; 16] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 300, 500, 750, 1_000, 2_000, 5_000, 10_000, 30_000,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    count: u64,
+}
+
+/// Percentiles in milliseconds, all zero while no sample was recorded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub count: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX);
+        let index = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[index] = self.buckets[index].saturating_add(1);
+        self.count = self.count.saturating_add(1);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Upper bound of the bucket holding the `percent`th percentile sample.
+    pub fn percentile_ms(&self, percent: u8) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let percent = u64::from(percent.min(100));
+        let rank = (self.count.saturating_mul(percent).div_ceil(100)).max(1);
+        let mut seen = 0u64;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen = seen.saturating_add(*count);
+            if seen >= rank {
+                return BUCKET_BOUNDS_MS[index.min(BUCKET_BOUNDS_MS.len() - 1)];
+            }
+        }
+        BUCKET_BOUNDS_MS[BUCKET_BOUNDS_MS.len() - 1]
+    }
+
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        LatencyPercentiles {
+            count: self.count,
+            p50_ms: self.percentile_ms(50),
+            p95_ms: self.percentile_ms(95),
+            p99_ms: self.percentile_ms(99),
+        }
+    }
+}
+
+impl fmt::Display for LatencyPercentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "count=0");
+        }
+        write!(
+            f,
+            "count={} p50<={}ms p95<={}ms p99<={}ms",
+            self.count, self.p50_ms, self.p95_ms, self.p99_ms
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_report_bucket_upper_bounds() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentiles(), LatencyPercentiles::default());
+        assert_eq!(histogram.percentiles().to_string(), "count=0");
+
+        for _ in 0..90 {
+            histogram.record(Duration::from_millis(40));
+        }
+        for _ in 0..9 {
+            histogram.record(Duration::from_millis(450));
+        }
+        histogram.record(Duration::from_millis(1_500));
+        let percentiles = histogram.percentiles();
+        assert_eq!(
+            percentiles,
+            LatencyPercentiles {
+                count: 100,
+                p50_ms: 50,
+                p95_ms: 500,
+                p99_ms: 500,
+            }
+        );
+        assert_eq!(histogram.percentile_ms(100), 2_000);
+        assert_eq!(
+            percentiles.to_string(),
+            "count=100 p50<=50ms p95<=500ms p99<=500ms"
+        );
+    }
+
+    #[test]
+    fn outliers_land_in_the_overflow_bucket() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.record(Duration::ZERO);
+        histogram.record(Duration::from_secs(3_600));
+        assert_eq!(histogram.percentile_ms(50), 1);
+        assert_eq!(histogram.percentile_ms(99), 30_000);
+        assert_eq!(histogram.count(), 2);
+    }
+}
@@ -1,12 +1,20 @@
 use std::fmt;
 
+pub mod callback_state;
+pub mod clock;
+pub mod config_summary;
 pub mod flow_control;
 pub mod invariants;
+pub mod latency;
+pub mod log_sampling;
 mod macros;
 pub mod net;
 pub mod sip003;
 pub mod stream;
+pub mod stream_checksum;
+pub mod stream_trailer;
 pub mod tcp;
+pub mod validation;
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 
 #[cfg(feature = "test-support")]
@@ -64,6 +72,9 @@ impl AddressKind {
     }
 }
 
+/// Normalizes a configured tunnel domain: surrounding whitespace and
+/// trailing dots are removed and ASCII letters lowercased, so
+/// `Example.COM.` and `example.com` name the same tunnel.
 pub fn normalize_domain(input: &str) -> Result<String, ConfigError> {
     let trimmed = input.trim();
     if trimmed.is_empty() {
@@ -73,7 +84,7 @@ pub fn normalize_domain(input: &str) -> Result<String, ConfigError> {
     if without_dot.is_empty() {
         return Err(ConfigError::new("Domain must not be empty"));
     }
-    Ok(without_dot.to_string())
+    Ok(without_dot.to_ascii_lowercase())
 }
 
 pub fn parse_resolver_addresses(addrs: &[String]) -> Result<Vec<HostPort>, ConfigError> {
@@ -266,3 +277,22 @@ fn parse_port(port_str: &str, input: &str, kind: AddressKind) -> Result<u16, Con
     }
     Ok(port)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_domain;
+
+    #[test]
+    fn domains_normalize_case_and_trailing_dots() {
+        for input in [
+            "Example.COM.",
+            "example.com.",
+            "example.com",
+            " example.com.. ",
+        ] {
+            assert_eq!(normalize_domain(input).unwrap(), "example.com");
+        }
+        assert!(normalize_domain("").is_err());
+        assert!(normalize_domain(" . ").is_err());
+    }
+}
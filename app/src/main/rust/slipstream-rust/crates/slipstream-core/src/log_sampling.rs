@@ -0,0 +1,153 @@
+use crate::clock::Clock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Messages a category prints per window before the rest are only counted.
+pub const DEFAULT_LOG_BURST: u32 = 10;
+const LOG_WINDOW_US: u64 = 1_000_000;
+
+/// Rate limiter for warnings that fire once per stream, such as every
+/// stream of a dead target failing at once.
+///
+/// Unlike [`crate::invariants::InvariantReporter`], which keeps one message
+/// per interval, the first `burst` messages of each one-second window are
+/// printed in full and the rest are folded into a single
+/// "and N more in the last second" line when the window ends.
+pub struct SampledLogger {
+    category: &'static str,
+    burst: u32,
+    window: Mutex<LogWindow>,
+    suppressed_total: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct LogWindow {
+    started_at: Option<u64>,
+    logged: u32,
+    suppressed: u64,
+}
+
+impl SampledLogger {
+    pub const fn new(category: &'static str, burst: u32) -> Self {
+        Self {
+            category,
+            burst,
+            window: Mutex::new(LogWindow {
+                started_at: None,
+                logged: 0,
+                suppressed: 0,
+            }),
+            suppressed_total: AtomicU64::new(0),
+        }
+    }
+
+    /// Log `make_message` through `log` unless this window's burst is used
+    /// up. The message is only built when it is printed.
+    pub fn log<M, L>(&self, clock: &dyn Clock, make_message: M, log: L)
+    where
+        M: FnOnce() -> String,
+        L: Fn(&str),
+    {
+        let now_us = clock.now_us();
+        let mut window = self.window.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(summary) = self.roll(&mut window, now_us) {
+            log(&summary);
+        }
+        window.started_at.get_or_insert(now_us);
+        if window.logged < self.burst {
+            window.logged += 1;
+            drop(window);
+            log(&make_message());
+        } else {
+            window.suppressed = window.suppressed.saturating_add(1);
+            self.suppressed_total.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Print the summary of a window that ended without a later message to
+    /// carry it; call periodically.
+    pub fn flush<L>(&self, clock: &dyn Clock, log: L)
+    where
+        L: Fn(&str),
+    {
+        let mut window = self.window.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(summary) = self.roll(&mut window, clock.now_us()) {
+            log(&summary);
+        }
+    }
+
+    /// Messages dropped since startup.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed_total.load(Ordering::Relaxed)
+    }
+
+    fn roll(&self, window: &mut LogWindow, now_us: u64) -> Option<String> {
+        let started_at = window.started_at?;
+        if now_us.saturating_sub(started_at) < LOG_WINDOW_US {
+            return None;
+        }
+        let suppressed = window.suppressed;
+        *window = LogWindow::default();
+        (suppressed > 0).then(|| {
+            format!(
+                "{}: and {} more in the last second",
+                self.category, suppressed
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SampledLogger;
+    use crate::clock::ManualClock;
+    use std::cell::RefCell;
+
+    #[test]
+    fn prints_the_burst_then_summarizes_the_rest() {
+        let logger = SampledLogger::new("target write failed", 2);
+        let clock = ManualClock::new(5_000_000);
+        let lines = RefCell::new(Vec::new());
+        let log = |line: &str| lines.borrow_mut().push(line.to_string());
+        for stream_id in 0..5 {
+            logger.log(&clock, || format!("stream {}", stream_id), log);
+        }
+        assert_eq!(*lines.borrow(), ["stream 0", "stream 1"]);
+        assert_eq!(logger.suppressed(), 3);
+
+        // Nothing is summarized before the window ends.
+        clock.advance_us(999_999);
+        logger.flush(&clock, log);
+        assert_eq!(lines.borrow().len(), 2);
+        clock.advance_us(1);
+        logger.flush(&clock, log);
+        assert_eq!(
+            lines.borrow().last().map(String::as_str),
+            Some("target write failed: and 3 more in the last second")
+        );
+
+        // The next window starts with a fresh burst and a quiet one
+        // flushes nothing.
+        logger.log(&clock, || "stream 5".to_string(), log);
+        clock.advance_us(1_000_000);
+        logger.flush(&clock, log);
+        assert_eq!(lines.borrow().len(), 4);
+        assert_eq!(lines.borrow()[3], "stream 5");
+    }
+
+    #[test]
+    fn a_late_message_carries_the_summary_of_the_previous_window() {
+        let logger = SampledLogger::new("reset", 1);
+        let clock = ManualClock::new(0);
+        let lines = RefCell::new(Vec::new());
+        let log = |line: &str| lines.borrow_mut().push(line.to_string());
+        logger.log(&clock, || "first".to_string(), log);
+        logger.log(&clock, || unreachable!("suppressed"), log);
+        clock.advance_us(3_000_000);
+        logger.log(&clock, || "later".to_string(), log);
+        assert_eq!(
+            *lines.borrow(),
+            ["first", "reset: and 1 more in the last second", "later"]
+        );
+    }
+}
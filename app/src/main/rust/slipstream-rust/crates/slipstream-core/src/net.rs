@@ -8,8 +8,49 @@ pub fn is_transient_udp_error(err: &Error) -> bool {
         _ => {}
     }
 
-    matches!(
-        err.raw_os_error(),
-        Some(code) if code == libc::ENETUNREACH || code == libc::EHOSTUNREACH
-    )
+    is_route_change_error(err)
+}
+
+/// Send errors seen while the routes change under the socket, as when an
+/// Android VPN comes up or the device switches networks: the next send a
+/// few milliseconds later usually goes through.
+pub fn is_route_change_error(err: &Error) -> bool {
+    let Some(code) = err.raw_os_error() else {
+        return false;
+    };
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if code == libc::ENONET {
+        return true;
+    }
+    code == libc::ENETUNREACH || code == libc::EHOSTUNREACH || code == libc::EPERM
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_route_change_error, is_transient_udp_error};
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn route_changes_are_transient() {
+        for code in [libc::ENETUNREACH, libc::EHOSTUNREACH, libc::EPERM] {
+            let err = Error::from_raw_os_error(code);
+            assert!(is_route_change_error(&err), "errno {}", code);
+            assert!(is_transient_udp_error(&err), "errno {}", code);
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        assert!(is_transient_udp_error(&Error::from_raw_os_error(
+            libc::ENONET
+        )));
+    }
+
+    #[test]
+    fn other_errors_are_not_route_changes() {
+        let would_block = Error::from(ErrorKind::WouldBlock);
+        assert!(is_transient_udp_error(&would_block));
+        assert!(!is_route_change_error(&would_block));
+        for code in [libc::EBADF, libc::EMSGSIZE, libc::EACCES] {
+            let err = Error::from_raw_os_error(code);
+            assert!(!is_transient_udp_error(&err), "errno {}", code);
+        }
+    }
 }
@@ -0,0 +1,179 @@
+//! Rolling checksums of stream payload for the `--debug-stream-checksums`
+//! diagnostic.
+//!
+//! Both ends hash each direction of a stream from its first byte and report
+//! the running hash at the same fixed offsets, so diffing the client and
+//! server logs shows the first checkpoint where the tunneled bytes differ.
+
+/// Payload between two reported checkpoints.
+pub const CHECKPOINT_INTERVAL_BYTES: u64 = 1024 * 1024;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a over every byte seen so far, with a checkpoint every `interval`
+/// bytes.
+#[derive(Debug, Clone)]
+pub struct StreamChecksum {
+    offset: u64,
+    hash: u64,
+    interval: u64,
+}
+
+impl StreamChecksum {
+    pub fn new() -> Self {
+        Self::with_interval(CHECKPOINT_INTERVAL_BYTES)
+    }
+
+    pub fn with_interval(interval: u64) -> Self {
+        Self {
+            offset: 0,
+            hash: FNV_OFFSET_BASIS,
+            interval: interval.max(1),
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Adds `data`; `on_checkpoint(offset, hash)` runs for every checkpoint
+    /// it crosses, whatever the chunking.
+    pub fn update<F>(&mut self, mut data: &[u8], mut on_checkpoint: F)
+    where
+        F: FnMut(u64, u64),
+    {
+        while !data.is_empty() {
+            let to_checkpoint = self.interval - self.offset % self.interval;
+            let take = to_checkpoint.min(data.len() as u64) as usize;
+            for &byte in &data[..take] {
+                self.hash ^= u64::from(byte);
+                self.hash = self.hash.wrapping_mul(FNV_PRIME);
+            }
+            self.offset += take as u64;
+            data = &data[take..];
+            if self.offset.is_multiple_of(self.interval) {
+                on_checkpoint(self.offset, self.hash);
+            }
+        }
+    }
+}
+
+impl Default for StreamChecksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`StreamChecksum`] for one direction of one stream that logs each
+/// checkpoint, and its final offset and hash when dropped.
+///
+/// `side` is `client` or `server`; `direction` is `up` (towards the target)
+/// or `down`, the same on both sides.
+pub struct StreamChecksumLog {
+    side: &'static str,
+    stream_id: u64,
+    direction: &'static str,
+    checksum: StreamChecksum,
+    log: fn(&str),
+}
+
+impl StreamChecksumLog {
+    pub fn new(side: &'static str, stream_id: u64, direction: &'static str, log: fn(&str)) -> Self {
+        Self {
+            side,
+            stream_id,
+            direction,
+            checksum: StreamChecksum::new(),
+            log,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let (side, stream_id, direction, log) =
+            (self.side, self.stream_id, self.direction, self.log);
+        self.checksum.update(data, |offset, hash| {
+            log(&checksum_message(
+                side, stream_id, direction, offset, hash, false,
+            ));
+        });
+    }
+}
+
+impl Drop for StreamChecksumLog {
+    fn drop(&mut self) {
+        (self.log)(&checksum_message(
+            self.side,
+            self.stream_id,
+            self.direction,
+            self.checksum.offset(),
+            self.checksum.hash(),
+            true,
+        ));
+    }
+}
+
+fn checksum_message(
+    side: &str,
+    stream_id: u64,
+    direction: &str,
+    offset: u64,
+    hash: u64,
+    end: bool,
+) -> String {
+    format!(
+        "stream checksum side={} stream={} dir={} offset={} fnv1a={:016x}{}",
+        side,
+        stream_id,
+        direction,
+        offset,
+        hash,
+        if end { " end" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoints(chunks: &[&[u8]], interval: u64) -> (Vec<(u64, u64)>, u64) {
+        let mut checksum = StreamChecksum::with_interval(interval);
+        let mut seen = Vec::new();
+        for chunk in chunks {
+            checksum.update(chunk, |offset, hash| seen.push((offset, hash)));
+        }
+        (seen, checksum.hash())
+    }
+
+    #[test]
+    fn matches_fnv1a() {
+        let mut checksum = StreamChecksum::new();
+        assert_eq!(checksum.hash(), FNV_OFFSET_BASIS);
+        checksum.update(b"a", |_, _| {});
+        assert_eq!(checksum.hash(), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(checksum.offset(), 1);
+    }
+
+    #[test]
+    fn checkpoints_do_not_depend_on_chunking() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let whole = checkpoints(&[&data], 64);
+        let split = checkpoints(
+            &[&data[..1], &data[1..100], &data[100..640], &data[640..]],
+            64,
+        );
+        assert_eq!(whole, split);
+        assert_eq!(whole.0.len(), 15);
+        assert_eq!(whole.0[0].0, 64);
+
+        let mut corrupted = data.clone();
+        corrupted[700] ^= 1;
+        let bad = checkpoints(&[&corrupted], 64);
+        assert_eq!(bad.0[..10], whole.0[..10]);
+        assert_ne!(bad.0[10], whole.0[10]);
+    }
+}
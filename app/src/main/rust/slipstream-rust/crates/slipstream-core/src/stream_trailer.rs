@@ -0,0 +1,281 @@
+//! CRC32 trailers on stream payload for `--debug-stream-trailers`.
+//!
+//! With the feature negotiated, the sending end of each stream direction
+//! appends [`TRAILER_LEN`] bytes after the last payload byte: a magic tag
+//! and the CRC32 of everything sent before it. The receiving end holds the
+//! last [`TRAILER_LEN`] bytes back from its target, and on FIN checks them
+//! against the CRC32 of what it delivered, so data corrupted in the tunnel
+//! fails the stream instead of reaching the application.
+
+use std::fmt;
+
+/// ALPN offered by a client that appends trailers. A server only selects it
+/// when it is configured for trailers too, so an end that does not expect
+/// them refuses the handshake instead of passing trailers through as data.
+pub const STREAM_TRAILER_ALPN: &str = "slipstream-crc32";
+
+/// Bytes appended to each stream direction.
+pub const TRAILER_LEN: usize = 8;
+
+const TRAILER_MAGIC: [u8; 4] = *b"SSCK";
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// Rolling CRC32 (IEEE) over every byte seen so far.
+#[derive(Debug, Clone)]
+struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            let index = ((self.state ^ u32::from(byte)) & 0xff) as usize;
+            self.state = (self.state >> 8) ^ CRC32_TABLE[index];
+        }
+    }
+
+    fn value(&self) -> u32 {
+        !self.state
+    }
+}
+
+/// Sending half: hashes the payload and produces the trailer at FIN.
+#[derive(Debug, Clone)]
+pub struct TrailerWriter {
+    crc: Crc32,
+    sent: u64,
+}
+
+impl TrailerWriter {
+    pub fn new() -> Self {
+        Self {
+            crc: Crc32::new(),
+            sent: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc.update(data);
+        self.sent += data.len() as u64;
+    }
+
+    /// Payload bytes hashed so far.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// The trailer to send after the last payload byte.
+    pub fn finish(&self) -> [u8; TRAILER_LEN] {
+        let mut trailer = [0u8; TRAILER_LEN];
+        trailer[..4].copy_from_slice(&TRAILER_MAGIC);
+        trailer[4..].copy_from_slice(&self.crc.value().to_be_bytes());
+        trailer
+    }
+}
+
+impl Default for TrailerWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why a stream direction failed its trailer check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailerError {
+    /// FIN arrived before a whole trailer.
+    Truncated { held: usize },
+    /// The last bytes were not a trailer.
+    BadMagic,
+    /// The payload does not match the CRC32 the sender computed.
+    Mismatch { sent: u32, received: u32 },
+}
+
+impl fmt::Display for TrailerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { held } => {
+                write!(f, "stream ended {} bytes into its trailer", held)
+            }
+            Self::BadMagic => f.write_str("stream trailer has a bad magic"),
+            Self::Mismatch { sent, received } => write!(
+                f,
+                "crc32 mismatch: sender {:08x}, receiver {:08x}",
+                sent, received
+            ),
+        }
+    }
+}
+
+/// Receiving half: strips the trailer from the incoming bytes and checks
+/// it at FIN.
+///
+/// Until FIN any byte may belong to the trailer, so the last
+/// [`TRAILER_LEN`] bytes seen are always held back.
+#[derive(Debug, Clone)]
+pub struct TrailerReader {
+    crc: Crc32,
+    held: Vec<u8>,
+    delivered: u64,
+}
+
+impl TrailerReader {
+    pub fn new() -> Self {
+        Self {
+            crc: Crc32::new(),
+            held: Vec::with_capacity(TRAILER_LEN),
+            delivered: 0,
+        }
+    }
+
+    /// Takes bytes off the stream and returns the ones that are safe to
+    /// deliver, which may be none.
+    pub fn push(&mut self, data: Vec<u8>) -> Vec<u8> {
+        let total = self.held.len() + data.len();
+        if total <= TRAILER_LEN {
+            self.held.extend_from_slice(&data);
+            return Vec::new();
+        }
+        let release = total - TRAILER_LEN;
+        let out = if release <= self.held.len() {
+            let out: Vec<u8> = self.held.drain(..release).collect();
+            self.held.extend_from_slice(&data);
+            out
+        } else {
+            let from_data = release - self.held.len();
+            let mut out = std::mem::take(&mut self.held);
+            out.extend_from_slice(&data[..from_data]);
+            self.held.extend_from_slice(&data[from_data..]);
+            out
+        };
+        self.crc.update(&out);
+        self.delivered += out.len() as u64;
+        out
+    }
+
+    /// Payload bytes released so far.
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// Checks the held-back trailer once the peer has sent FIN.
+    pub fn finish(&self) -> Result<(), TrailerError> {
+        if self.held.len() < TRAILER_LEN {
+            return Err(TrailerError::Truncated {
+                held: self.held.len(),
+            });
+        }
+        if self.held[..4] != TRAILER_MAGIC {
+            return Err(TrailerError::BadMagic);
+        }
+        let mut sent = [0u8; 4];
+        sent.copy_from_slice(&self.held[4..]);
+        let sent = u32::from_be_bytes(sent);
+        let received = self.crc.value();
+        if sent != received {
+            return Err(TrailerError::Mismatch { sent, received });
+        }
+        Ok(())
+    }
+}
+
+impl Default for TrailerReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed(payload: &[u8]) -> Vec<u8> {
+        let mut writer = TrailerWriter::new();
+        writer.update(payload);
+        let mut wire = payload.to_vec();
+        wire.extend_from_slice(&writer.finish());
+        wire
+    }
+
+    fn receive(chunks: &[&[u8]]) -> (Vec<u8>, Result<(), TrailerError>) {
+        let mut reader = TrailerReader::new();
+        let mut delivered = Vec::new();
+        for chunk in chunks {
+            delivered.extend(reader.push(chunk.to_vec()));
+        }
+        assert_eq!(reader.delivered(), delivered.len() as u64);
+        (delivered, reader.finish())
+    }
+
+    #[test]
+    fn matches_crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xcbf4_3926);
+        assert_eq!(&framed(b"")[..], b"SSCK\x00\x00\x00\x00");
+    }
+
+    #[test]
+    fn trailer_is_stripped_whatever_the_chunking() {
+        let payload: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let wire = framed(&payload);
+        let cuts: [&[usize]; 4] = [&[], &[1, 2, 3], &[995, 1001, 1007], &[4, 500, 999]];
+        for cut in cuts {
+            let mut chunks = Vec::new();
+            let mut start = 0;
+            for &end in cut {
+                chunks.push(&wire[start..end]);
+                start = end;
+            }
+            chunks.push(&wire[start..]);
+            let (delivered, result) = receive(&chunks);
+            assert_eq!(delivered, payload, "cuts {:?}", cut);
+            assert_eq!(result, Ok(()));
+        }
+
+        let empty = framed(b"");
+        assert_eq!(receive(&[&empty[..3], &empty[3..]]), (Vec::new(), Ok(())));
+    }
+
+    #[test]
+    fn damage_fails_the_check() {
+        let payload = b"hello through the tunnel";
+        let mut wire = framed(payload);
+        wire[3] ^= 0x20;
+        let (_, result) = receive(&[&wire]);
+        assert!(matches!(result, Err(TrailerError::Mismatch { .. })));
+
+        let wire = framed(payload);
+        let (_, result) = receive(&[&wire[..wire.len() - 3]]);
+        assert_eq!(result, Err(TrailerError::BadMagic));
+        assert_eq!(
+            receive(&[b"SSC"]).1,
+            Err(TrailerError::Truncated { held: 3 })
+        );
+        assert_eq!(receive(&[payload]).1, Err(TrailerError::BadMagic));
+    }
+}
@@ -34,13 +34,7 @@ pub fn stream_read_limit_chunks<T: AsRawFd>(
     chunk_bytes: usize,
 ) -> usize {
     let buffer_bytes = tcp_recv_buffer_bytes(stream).unwrap_or(default_buffer_bytes);
-    let buffer_bytes = clamp_stream_read_buffer_bytes(buffer_bytes);
-    let chunks = buffer_bytes / chunk_bytes;
-    if chunks == 0 {
-        1
-    } else {
-        chunks
-    }
+    read_limit_chunks(buffer_bytes, chunk_bytes)
 }
 
 #[cfg(not(unix))]
@@ -49,13 +43,14 @@ pub fn stream_read_limit_chunks<T>(
     default_buffer_bytes: usize,
     chunk_bytes: usize,
 ) -> usize {
-    let buffer_bytes = clamp_stream_read_buffer_bytes(default_buffer_bytes);
-    let chunks = buffer_bytes / chunk_bytes;
-    if chunks == 0 {
-        1
-    } else {
-        chunks
-    }
+    read_limit_chunks(default_buffer_bytes, chunk_bytes)
+}
+
+/// Chunks of `chunk_bytes` that fit the clamped read buffer, at least one.
+/// With a power-of-two chunk size the chunks cover the buffer exactly, so
+/// the byte budget does not depend on the chunk size.
+pub fn read_limit_chunks(buffer_bytes: usize, chunk_bytes: usize) -> usize {
+    (clamp_stream_read_buffer_bytes(buffer_bytes) / chunk_bytes.max(1)).max(1)
 }
 
 pub fn within_stream_buffer(queued_bytes: usize, incoming_len: usize) -> bool {
@@ -53,3 +53,72 @@ impl<F: FnOnce()> Drop for ResetOnDrop<F> {
         }
     }
 }
+
+/// Hands items back after a fixed latency plus seeded jitter, so tests can
+/// give responses resolver-like timing without a network. Time is whatever
+/// the caller passes in, usually a `ManualClock`, which keeps runs
+/// deterministic; jitter can reorder items.
+pub struct DelayLine<T> {
+    latency_us: u64,
+    jitter_us: u64,
+    state: u64,
+    next_seq: u64,
+    queue: Vec<(u64, u64, T)>,
+}
+
+impl<T> DelayLine<T> {
+    pub fn new(latency_us: u64, jitter_us: u64, seed: u64) -> Self {
+        Self {
+            latency_us,
+            jitter_us,
+            state: seed | 1,
+            next_seq: 0,
+            queue: Vec::new(),
+        }
+    }
+
+    /// Queues `item` and returns when it becomes due.
+    pub fn push(&mut self, item: T, now_us: u64) -> u64 {
+        let due = now_us
+            .saturating_add(self.latency_us)
+            .saturating_add(self.next_jitter());
+        self.queue.push((due, self.next_seq, item));
+        self.next_seq += 1;
+        due
+    }
+
+    /// The earliest due item, if it is due by `now_us`. Items due at the
+    /// same time come out in push order.
+    pub fn pop_due(&mut self, now_us: u64) -> Option<T> {
+        let index = self
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _, _))| *due <= now_us)
+            .min_by_key(|(_, (due, seq, _))| (*due, *seq))
+            .map(|(index, _)| index)?;
+        Some(self.queue.swap_remove(index).2)
+    }
+
+    pub fn next_due(&self) -> Option<u64> {
+        self.queue.iter().map(|(due, _, _)| *due).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn next_jitter(&mut self) -> u64 {
+        if self.jitter_us == 0 {
+            return 0;
+        }
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state % (self.jitter_us + 1)
+    }
+}
@@ -0,0 +1,175 @@
+//! Result of a validate-only run, which goes through the setup steps of the
+//! client or server without sending any traffic.
+//!
+//! Every check is recorded, so one run reports all problems it can find.
+//! [`ValidationReport::exit_code`] maps the first failure to a per-kind
+//! exit code for deployment scripts.
+
+use std::fmt;
+
+/// What a check covers, in the order the steps run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationKind {
+    /// Option values and combinations, such as the domain fitting a query.
+    Config,
+    /// Hostname lookups for resolvers, targets and fallbacks.
+    Resolve,
+    /// Binding the sockets the run would listen on.
+    Bind,
+    /// Reading and parsing certificates and keys.
+    Certificate,
+    /// Creating the QUIC context.
+    Quic,
+}
+
+impl ValidationKind {
+    /// Exit code of a run whose first failure is of this kind. 1 and 2
+    /// stay with runtime and usage errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ValidationKind::Config => 3,
+            ValidationKind::Resolve => 4,
+            ValidationKind::Bind => 5,
+            ValidationKind::Certificate => 6,
+            ValidationKind::Quic => 7,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValidationKind::Config => "config",
+            ValidationKind::Resolve => "resolve",
+            ValidationKind::Bind => "bind",
+            ValidationKind::Certificate => "certificate",
+            ValidationKind::Quic => "quic",
+        }
+    }
+}
+
+/// One check: a passing check carries a detail such as the bound address,
+/// a failing one the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationCheck {
+    pub kind: ValidationKind,
+    pub name: &'static str,
+    pub result: Result<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    checks: Vec<ValidationCheck>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pass(&mut self, kind: ValidationKind, name: &'static str, detail: impl fmt::Display) {
+        self.checks.push(ValidationCheck {
+            kind,
+            name,
+            result: Ok(detail.to_string()),
+        });
+    }
+
+    pub fn fail(&mut self, kind: ValidationKind, name: &'static str, err: impl fmt::Display) {
+        self.checks.push(ValidationCheck {
+            kind,
+            name,
+            result: Err(err.to_string()),
+        });
+    }
+
+    /// Records `result` and returns the value of a passing check.
+    pub fn check<T, E: fmt::Display>(
+        &mut self,
+        kind: ValidationKind,
+        name: &'static str,
+        result: Result<T, E>,
+        detail: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.pass(kind, name, detail(&value));
+                Some(value)
+            }
+            Err(err) => {
+                self.fail(kind, name, err);
+                None
+            }
+        }
+    }
+
+    pub fn checks(&self) -> &[ValidationCheck] {
+        &self.checks
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+
+    pub fn first_failure(&self) -> Option<&ValidationCheck> {
+        self.checks.iter().find(|check| check.result.is_err())
+    }
+
+    /// 0 when every check passed, otherwise the code of the first failure.
+    pub fn exit_code(&self) -> i32 {
+        self.first_failure()
+            .map_or(0, |check| check.kind.exit_code())
+    }
+}
+
+/// One line per check, `ok` or `FAIL` first.
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, check) in self.checks.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let (status, detail) = match &check.result {
+                Ok(detail) => ("ok", detail),
+                Err(err) => ("FAIL", err),
+            };
+            write!(
+                f,
+                "{:<4} {}/{}: {}",
+                status,
+                check.kind.as_str(),
+                check.name,
+                detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ValidationKind, ValidationReport};
+
+    #[test]
+    fn first_failure_picks_the_exit_code() {
+        let mut report = ValidationReport::new();
+        report.pass(ValidationKind::Config, "mtu", 140);
+        assert!(report.is_ok());
+        assert_eq!(report.exit_code(), 0);
+
+        let parsed: Result<u16, &str> = Err("No such file");
+        assert_eq!(
+            report.check(ValidationKind::Certificate, "cert", parsed, |_| {
+                unreachable!("failed checks have no detail")
+            }),
+            None
+        );
+        report.fail(ValidationKind::Bind, "udp socket", "address in use");
+        assert!(!report.is_ok());
+        assert_eq!(report.exit_code(), ValidationKind::Certificate.exit_code());
+        assert_eq!(
+            report.to_string(),
+            "ok   config/mtu: 140\n\
+             FAIL certificate/cert: No such file\n\
+             FAIL bind/udp socket: address in use"
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use crate::base32;
 use crate::dots;
+use crate::edns::write_ecs_opt_out;
 
-use crate::name::{encode_name, extract_subdomain_multi, parse_name};
+use crate::name::{encode_name, extract_subdomain_multi, parse_name, strip_service_label};
+use crate::response::{build_tunnel_response, ResponseOptions};
 use crate::types::{
-    DecodeQueryError, DecodedQuery, DnsError, QueryParams, Rcode, ResponseParams, EDNS_UDP_PAYLOAD,
-    RR_OPT, RR_TXT,
+    DecodeQueryError, DecodedQuery, DnsError, ParseError, QueryParams, Rcode, ResponseParams,
+    TunnelResponse, EDNS_UDP_PAYLOAD, RR_OPT, RR_TXT,
 };
 use crate::wire::{
     parse_header, parse_question, parse_question_for_reply, read_u16, read_u32, write_u16,
@@ -18,6 +20,17 @@ pub fn decode_query(packet: &[u8], domain: &str) -> Result<DecodedQuery, DecodeQ
 pub fn decode_query_with_domains(
     packet: &[u8],
     domains: &[&str],
+) -> Result<DecodedQuery, DecodeQueryError> {
+    decode_query_with_label(packet, domains, None)
+}
+
+/// Like [`decode_query_with_domains`], but with `service_label` set the
+/// label right under the matched domain must equal it (ignoring case) and
+/// is removed before decoding; queries without it are refused.
+pub fn decode_query_with_label(
+    packet: &[u8],
+    domains: &[&str],
+    service_label: Option<&str>,
 ) -> Result<DecodedQuery, DecodeQueryError> {
     let header = match parse_header(packet) {
         Some(header) => header,
@@ -64,18 +77,24 @@ pub fn decode_query_with_domains(
         });
     }
 
-    let subdomain_raw = match extract_subdomain_multi(&question.name, domains) {
-        Ok(subdomain_raw) => subdomain_raw,
-        Err(rcode) => {
-            return Err(DecodeQueryError::Reply {
-                id: header.id,
-                rd,
-                cd,
-                question: Some(question),
-                rcode,
-            })
-        }
-    };
+    let subdomain_raw =
+        match extract_subdomain_multi(&question.name, domains).and_then(|subdomain| {
+            match service_label {
+                Some(label) => strip_service_label(&subdomain, label),
+                None => Ok(subdomain),
+            }
+        }) {
+            Ok(subdomain_raw) => subdomain_raw,
+            Err(rcode) => {
+                return Err(DecodeQueryError::Reply {
+                    id: header.id,
+                    rd,
+                    cd,
+                    question: Some(question),
+                    rcode,
+                })
+            }
+        };
 
     let undotted = dots::undotify(&subdomain_raw);
     if undotted.is_empty() {
@@ -110,7 +129,23 @@ pub fn decode_query_with_domains(
     })
 }
 
+/// Knobs for the OPT record of an outbound query. `Default` keeps the
+/// historical wire format: an OPT record with no options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryOptions {
+    /// Add an EDNS Client Subnet option with source prefix 0, asking the
+    /// resolver not to forward the client's subnet upstream.
+    pub ecs_opt_out: bool,
+}
+
 pub fn encode_query(params: &QueryParams<'_>) -> Result<Vec<u8>, DnsError> {
+    encode_query_with_options(params, &QueryOptions::default())
+}
+
+pub fn encode_query_with_options(
+    params: &QueryParams<'_>,
+    opts: &QueryOptions,
+) -> Result<Vec<u8>, DnsError> {
     let mut out = Vec::with_capacity(256);
     let mut flags = 0u16;
     if !params.is_query {
@@ -136,75 +171,30 @@ pub fn encode_query(params: &QueryParams<'_>) -> Result<Vec<u8>, DnsError> {
         write_u16(&mut out, params.qclass);
     }
 
-    encode_opt_record(&mut out)?;
+    encode_opt_record(&mut out, opts)?;
 
     Ok(out)
 }
 
 pub fn encode_response(params: &ResponseParams<'_>) -> Result<Vec<u8>, DnsError> {
-    let payload_len = params.payload.map(|payload| payload.len()).unwrap_or(0);
-
-    let mut rcode = params.rcode.unwrap_or(if payload_len > 0 {
-        Rcode::Ok
-    } else {
-        Rcode::NameError
-    });
-
-    let mut ancount = 0u16;
-    if payload_len > 0 && rcode == Rcode::Ok {
-        ancount = 1;
-    } else if params.rcode.is_some() {
-        rcode = params.rcode.unwrap_or(Rcode::Ok);
-    }
-
-    let mut out = Vec::with_capacity(256);
-    let mut flags = 0x8000 | 0x0400;
-    if params.rd {
-        flags |= 0x0100;
-    }
-    if params.cd {
-        flags |= 0x0010;
-    }
-    flags |= rcode.to_u8() as u16;
-
-    write_u16(&mut out, params.id);
-    write_u16(&mut out, flags);
-    write_u16(&mut out, 1);
-    write_u16(&mut out, ancount);
-    write_u16(&mut out, 0);
-    write_u16(&mut out, 1);
-
-    encode_name(&params.question.name, &mut out)?;
-    write_u16(&mut out, params.question.qtype);
-    write_u16(&mut out, params.question.qclass);
+    build_tunnel_response(params, &ResponseOptions::default())
+}
 
-    if ancount == 1 {
-        out.extend_from_slice(&[0xC0, 0x0C]);
-        write_u16(&mut out, params.question.qtype);
-        write_u16(&mut out, params.question.qclass);
-        write_u32(&mut out, 60);
-        let chunk_count = payload_len.div_ceil(255);
-        let rdata_len = payload_len + chunk_count;
-        if rdata_len > u16::MAX as usize {
-            return Err(DnsError::new("payload too long"));
-        }
-        write_u16(&mut out, rdata_len as u16);
-        if let Some(payload) = params.payload {
-            let mut remaining = payload_len;
-            let mut cursor = 0;
-            while remaining > 0 {
-                let chunk_len = remaining.min(255);
-                out.push(chunk_len as u8);
-                out.extend_from_slice(&payload[cursor..cursor + chunk_len]);
-                cursor += chunk_len;
-                remaining -= chunk_len;
-            }
-        }
+/// Parse a tunnel DNS response without touching any connection state.
+///
+/// Transport-agnostic: UDP, TCP fallback and DoH/DoT bodies all carry the same
+/// DNS message and should go through this function.
+pub fn parse_tunnel_response(packet: &[u8]) -> Result<TunnelResponse, ParseError> {
+    let header = parse_header(packet).ok_or(ParseError::TooShort)?;
+    if !header.is_response {
+        return Err(ParseError::NotResponse);
     }
-
-    encode_opt_record(&mut out)?;
-
-    Ok(out)
+    Ok(TunnelResponse {
+        id: header.id,
+        rcode: header.rcode,
+        truncated: header.tc,
+        payload: decode_response(packet),
+    })
 }
 
 pub fn decode_response(packet: &[u8]) -> Option<Vec<u8>> {
@@ -276,19 +266,57 @@ pub fn is_response(packet: &[u8]) -> bool {
         .unwrap_or(false)
 }
 
-fn encode_opt_record(out: &mut Vec<u8>) -> Result<(), DnsError> {
+fn encode_opt_record(out: &mut Vec<u8>, opts: &QueryOptions) -> Result<(), DnsError> {
     out.push(0);
     write_u16(out, RR_OPT);
     write_u16(out, EDNS_UDP_PAYLOAD);
     write_u32(out, 0);
+    let rdlen_at = out.len();
     write_u16(out, 0);
+    if opts.ecs_opt_out {
+        write_ecs_opt_out(out);
+    }
+    let rdlen = (out.len() - rdlen_at - 2) as u16;
+    out[rdlen_at..rdlen_at + 2].copy_from_slice(&rdlen.to_be_bytes());
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::encode_response;
-    use crate::types::{Question, ResponseParams, CLASS_IN, RR_TXT};
+    use super::{
+        decode_query, encode_query, encode_query_with_options, encode_response, QueryOptions,
+    };
+    use crate::edns::{response_ecs, EcsObservation};
+    use crate::types::{QueryParams, Question, ResponseParams, CLASS_IN, RR_TXT};
+
+    #[test]
+    fn ecs_opt_out_adds_one_option_the_server_ignores() {
+        let params = QueryParams {
+            id: 7,
+            qname: "mzxw6.test.com.",
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+            rd: true,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        };
+        let plain = encode_query(&params).unwrap();
+        let opts = QueryOptions { ecs_opt_out: true };
+        let opted_out = encode_query_with_options(&params, &opts).unwrap();
+        assert_eq!(opted_out.len(), plain.len() + 8);
+        assert_eq!(response_ecs(&plain), EcsObservation::Absent);
+        assert_eq!(
+            response_ecs(&opted_out),
+            EcsObservation::Present {
+                family: 1,
+                source_prefix: 0,
+                scope_prefix: 0,
+            }
+        );
+        let decoded = decode_query(&opted_out, "test.com").unwrap();
+        assert_eq!(decoded.payload, b"foo");
+    }
 
     #[test]
     fn encode_response_rejects_large_payload() {
@@ -0,0 +1,221 @@
+use crate::name::parse_name;
+use crate::types::RR_OPT;
+use crate::wire::{parse_header, read_u16, write_u16};
+
+/// EDNS Client Subnet option code (RFC 7871).
+pub const EDNS_OPTION_ECS: u16 = 8;
+
+const ECS_FAMILY_IPV4: u16 = 1;
+const ECS_FAMILY_IPV6: u16 = 2;
+
+/// The EDNS Client Subnet option found in a response's OPT record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcsObservation {
+    /// No OPT record, or one without an ECS option.
+    Absent,
+    Present {
+        family: u16,
+        source_prefix: u8,
+        scope_prefix: u8,
+    },
+    /// An ECS option whose body does not follow RFC 7871.
+    Malformed,
+}
+
+impl EcsObservation {
+    /// True for an option that may carry part of the client's address: any
+    /// well-formed one with a non-zero source prefix, and any malformed one.
+    pub fn may_reveal_subnet(self) -> bool {
+        match self {
+            EcsObservation::Absent => false,
+            EcsObservation::Present { source_prefix, .. } => source_prefix > 0,
+            EcsObservation::Malformed => true,
+        }
+    }
+}
+
+/// Looks for an ECS option in the OPT record of `packet`'s additional
+/// section. A message that cannot be walked that far reports `Absent`;
+/// only a broken ECS option itself is `Malformed`.
+pub fn response_ecs(packet: &[u8]) -> EcsObservation {
+    match opt_rdata(packet) {
+        Some(rdata) => ecs_in_options(rdata),
+        None => EcsObservation::Absent,
+    }
+}
+
+/// Appends an ECS option with SOURCE PREFIX-LENGTH 0, which asks the
+/// resolver not to forward any of the client's address (RFC 7871 7.1.2).
+pub(crate) fn write_ecs_opt_out(out: &mut Vec<u8>) {
+    write_u16(out, EDNS_OPTION_ECS);
+    write_u16(out, 4);
+    write_u16(out, ECS_FAMILY_IPV4);
+    out.push(0);
+    out.push(0);
+}
+
+fn opt_rdata(packet: &[u8]) -> Option<&[u8]> {
+    let header = parse_header(packet)?;
+    let mut offset = header.offset;
+    for _ in 0..header.qdcount {
+        let (_, after_name) = parse_name(packet, offset).ok()?;
+        offset = after_name + 4;
+    }
+    let skipped = header.ancount as usize + header.nscount as usize;
+    for index in 0..skipped + header.arcount as usize {
+        let (_, after_name) = parse_name(packet, offset).ok()?;
+        let rtype = read_u16(packet, after_name)?;
+        let rdlen = read_u16(packet, after_name + 8)? as usize;
+        let rdata_start = after_name + 10;
+        let rdata = packet.get(rdata_start..rdata_start + rdlen)?;
+        if index >= skipped && rtype == RR_OPT {
+            return Some(rdata);
+        }
+        offset = rdata_start + rdlen;
+    }
+    None
+}
+
+fn ecs_in_options(rdata: &[u8]) -> EcsObservation {
+    let mut cursor = 0;
+    while let (Some(code), Some(len)) = (read_u16(rdata, cursor), read_u16(rdata, cursor + 2)) {
+        let body_start = cursor + 4;
+        let body = rdata.get(body_start..body_start + len as usize);
+        match (code, body) {
+            (EDNS_OPTION_ECS, Some(body)) => return parse_ecs(body),
+            (EDNS_OPTION_ECS, None) => return EcsObservation::Malformed,
+            (_, None) => break,
+            (_, Some(_)) => cursor = body_start + len as usize,
+        }
+    }
+    EcsObservation::Absent
+}
+
+fn parse_ecs(body: &[u8]) -> EcsObservation {
+    let (Some(family), Some(&source_prefix), Some(&scope_prefix)) =
+        (read_u16(body, 0), body.get(2), body.get(3))
+    else {
+        return EcsObservation::Malformed;
+    };
+    let max_prefix = match family {
+        ECS_FAMILY_IPV4 => 32,
+        ECS_FAMILY_IPV6 => 128,
+        _ => return EcsObservation::Malformed,
+    };
+    // The address holds just enough bytes for the source prefix.
+    let address_len = (source_prefix as usize).div_ceil(8);
+    if source_prefix > max_prefix || scope_prefix > max_prefix || body.len() != 4 + address_len {
+        return EcsObservation::Malformed;
+    }
+    EcsObservation::Present {
+        family,
+        source_prefix,
+        scope_prefix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{response_ecs, write_ecs_opt_out, EcsObservation, EDNS_OPTION_ECS};
+    use crate::response::{build_tunnel_response, ResponseOptions};
+    use crate::types::{Question, ResponseParams, CLASS_IN, RR_TXT};
+
+    fn response(opts: &ResponseOptions) -> Vec<u8> {
+        let question = Question {
+            name: "abc.test.com.".to_string(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        };
+        let params = ResponseParams {
+            id: 0x1234,
+            rd: true,
+            cd: false,
+            question: &question,
+            payload: Some(b"quic"),
+            rcode: None,
+        };
+        build_tunnel_response(&params, opts).unwrap()
+    }
+
+    /// A default response ends with the OPT record's zero RDLENGTH; swap it
+    /// for `options`.
+    fn with_options(options: &[u8]) -> Vec<u8> {
+        let mut packet = response(&ResponseOptions::default());
+        packet.truncate(packet.len() - 2);
+        packet.extend_from_slice(&(options.len() as u16).to_be_bytes());
+        packet.extend_from_slice(options);
+        packet
+    }
+
+    fn ecs_option(body: &[u8]) -> Vec<u8> {
+        let mut option = EDNS_OPTION_ECS.to_be_bytes().to_vec();
+        option.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        option.extend_from_slice(body);
+        option
+    }
+
+    #[test]
+    fn responses_without_ecs_report_absent() {
+        assert_eq!(
+            response_ecs(&response(&ResponseOptions::default())),
+            EcsObservation::Absent
+        );
+        let padded = ResponseOptions {
+            pad_block: 128,
+            ..ResponseOptions::default()
+        };
+        assert_eq!(response_ecs(&response(&padded)), EcsObservation::Absent);
+        assert_eq!(response_ecs(&[0u8; 4]), EcsObservation::Absent);
+    }
+
+    #[test]
+    fn ecs_option_is_found_after_other_options() {
+        // Padding, then 192.0.2.0/24 with scope 0.
+        let mut options = vec![0, 12, 0, 2, 0, 0];
+        options.extend(ecs_option(&[0, 1, 24, 0, 192, 0, 2]));
+        let observed = response_ecs(&with_options(&options));
+        assert_eq!(
+            observed,
+            EcsObservation::Present {
+                family: 1,
+                source_prefix: 24,
+                scope_prefix: 0,
+            }
+        );
+        assert!(observed.may_reveal_subnet());
+
+        let mut opt_out = Vec::new();
+        write_ecs_opt_out(&mut opt_out);
+        let observed = response_ecs(&with_options(&opt_out));
+        assert!(matches!(
+            observed,
+            EcsObservation::Present {
+                source_prefix: 0,
+                ..
+            }
+        ));
+        assert!(!observed.may_reveal_subnet());
+    }
+
+    #[test]
+    fn broken_ecs_options_report_malformed() {
+        for body in [
+            &[0, 1, 24][..],               // shorter than the fixed fields
+            &[0, 3, 8, 0, 10],             // unknown family
+            &[0, 1, 33, 0, 1, 2, 3, 4, 5], // prefix longer than IPv4
+            &[0, 1, 24, 0, 192, 0],        // address shorter than the prefix
+            &[0, 2, 8, 0, 0x20, 0x01],     // address longer than the prefix
+        ] {
+            let observed = response_ecs(&with_options(&ecs_option(body)));
+            assert_eq!(observed, EcsObservation::Malformed, "{:?}", body);
+            assert!(observed.may_reveal_subnet());
+        }
+        // The option claims more bytes than the OPT record holds.
+        let mut overrun = ecs_option(&[0, 1, 0, 0]);
+        overrun[3] = 40;
+        assert_eq!(
+            response_ecs(&with_options(&overrun)),
+            EcsObservation::Malformed
+        );
+    }
+}
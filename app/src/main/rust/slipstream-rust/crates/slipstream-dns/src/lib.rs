@@ -1,27 +1,48 @@
 mod base32;
 mod codec;
 mod dots;
+mod edns;
 mod name;
+mod response;
 mod types;
 mod wire;
 
 pub use base32::{decode as base32_decode, encode as base32_encode, Base32Error};
 pub use codec::{
-    decode_query, decode_query_with_domains, decode_response, encode_query, encode_response,
-    is_response,
+    decode_query, decode_query_with_domains, decode_query_with_label, decode_response,
+    encode_query, encode_query_with_options, encode_response, is_response, parse_tunnel_response,
+    QueryOptions,
 };
 pub use dots::{dotify, undotify};
+pub use edns::{response_ecs, EcsObservation, EDNS_OPTION_ECS};
+pub use response::{build_tunnel_response, ResponseOptions};
 pub use types::{
-    DecodeQueryError, DecodedQuery, DnsError, QueryParams, Question, Rcode, ResponseParams,
-    CLASS_IN, EDNS_UDP_PAYLOAD, RR_A, RR_OPT, RR_TXT,
+    DecodeQueryError, DecodedQuery, DnsError, ParseError, QueryParams, Question, Rcode,
+    ResponseParams, TunnelResponse, CLASS_IN, EDNS_UDP_PAYLOAD, RR_A, RR_OPT, RR_TXT,
 };
 
 pub fn build_qname(payload: &[u8], domain: &str) -> Result<String, DnsError> {
-    let domain = domain.trim_end_matches('.');
+    build_qname_with_label(payload, None, domain)
+}
+
+/// Like [`build_qname`], with `service_label` inserted as the first label
+/// under `domain`, e.g. `<data>.svc1.example.com.`.
+pub fn build_qname_with_label(
+    payload: &[u8],
+    service_label: Option<&str>,
+    domain: &str,
+) -> Result<String, DnsError> {
+    // Configured domains are normalized already; do it here too so every
+    // caller puts the same name on the wire.
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
     if domain.is_empty() {
         return Err(DnsError::new("domain must not be empty"));
     }
-    let max_payload = max_payload_len_for_domain(domain)?;
+    let domain = match service_label {
+        Some(label) => format!("{}.{}", label, domain),
+        None => domain,
+    };
+    let max_payload = max_payload_len_for_domain(&domain)?;
     if payload.len() > max_payload {
         return Err(DnsError::new("payload too large for domain"));
     }
@@ -30,6 +51,12 @@ pub fn build_qname(payload: &[u8], domain: &str) -> Result<String, DnsError> {
     Ok(format!("{}.{}.", dotted, domain))
 }
 
+/// Checks a service label from configuration: one DNS label of letters,
+/// digits and hyphens.
+pub fn validate_service_label(label: &str) -> Result<(), DnsError> {
+    name::validate_label(label)
+}
+
 pub fn max_payload_len_for_domain(domain: &str) -> Result<usize, DnsError> {
     let domain = domain.trim_end_matches('.');
     if domain.is_empty() {
@@ -78,6 +105,16 @@ mod tests {
         assert!(build_qname(&payload, domain).is_err());
     }
 
+    #[test]
+    fn build_qname_normalizes_the_domain() {
+        let payload = [0x12, 0x34, 0x56];
+        let expected = build_qname(&payload, "example.com").expect("qname");
+        assert!(expected.ends_with(".example.com."));
+        for domain in ["Example.COM.", "example.com."] {
+            assert_eq!(build_qname(&payload, domain).expect("qname"), expected);
+        }
+    }
+
     #[test]
     fn build_qname_rejects_long_domain() {
         let domain = format!("{}.com", "a".repeat(260));
@@ -28,6 +28,43 @@ fn extract_subdomain(qname: &str, domain: &str) -> Result<String, Rcode> {
     Ok(subdomain.to_string())
 }
 
+/// Checks that `label` is a single DNS label of letters, digits and
+/// hyphens that does not start or end with a hyphen.
+pub(crate) fn validate_label(label: &str) -> Result<(), DnsError> {
+    if label.is_empty() {
+        return Err(DnsError::new("label must not be empty"));
+    }
+    if label.len() > 63 {
+        return Err(DnsError::new("label too long"));
+    }
+    if !label
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+    {
+        return Err(DnsError::new(
+            "label may only contain letters, digits and hyphens",
+        ));
+    }
+    if label.starts_with('-') || label.ends_with('-') {
+        return Err(DnsError::new("label must not start or end with a hyphen"));
+    }
+    Ok(())
+}
+
+/// Splits the service label off the end of `subdomain` (the labels above
+/// the matched domain). A missing or different label is refused.
+pub(crate) fn strip_service_label(subdomain: &str, label: &str) -> Result<String, Rcode> {
+    if subdomain.eq_ignore_ascii_case(label) {
+        return Err(Rcode::NameError);
+    }
+    match subdomain.rsplit_once('.') {
+        Some((data, last)) if last.eq_ignore_ascii_case(label) && !data.is_empty() => {
+            Ok(data.to_string())
+        }
+        _ => Err(Rcode::Refused),
+    }
+}
+
 pub(crate) fn extract_subdomain_multi(qname: &str, domains: &[&str]) -> Result<String, Rcode> {
     let qname_trimmed = qname.trim_end_matches('.');
     if qname_trimmed.is_empty() {
@@ -187,7 +224,8 @@ pub(crate) fn encode_name(name: &str, out: &mut Vec<u8>) -> Result<(), DnsError>
 #[cfg(test)]
 mod tests {
     use super::MAX_DNS_NAME_LEN;
-    use super::{encode_name, parse_name};
+    use super::{encode_name, parse_name, strip_service_label, validate_label};
+    use crate::types::Rcode;
 
     fn build_name(last_label_len: usize) -> String {
         format!(
@@ -231,4 +269,32 @@ mod tests {
         packet.push(0);
         assert!(parse_name(&packet, 0).is_err());
     }
+
+    #[test]
+    fn validate_label_accepts_dns_labels_only() {
+        assert!(validate_label("svc1").is_ok());
+        assert!(validate_label("a-b").is_ok());
+        assert!(validate_label(&"a".repeat(63)).is_ok());
+        assert!(validate_label("").is_err());
+        assert!(validate_label(&"a".repeat(64)).is_err());
+        assert!(validate_label("svc.1").is_err());
+        assert!(validate_label("svc_1").is_err());
+        assert!(validate_label("-svc").is_err());
+        assert!(validate_label("svc-").is_err());
+    }
+
+    #[test]
+    fn strip_service_label_refuses_missing_or_wrong_labels() {
+        assert_eq!(
+            strip_service_label("abc.def.svc1", "svc1"),
+            Ok("abc.def".to_string())
+        );
+        assert_eq!(
+            strip_service_label("abc.SVC1", "svc1"),
+            Ok("abc".to_string())
+        );
+        assert_eq!(strip_service_label("abc.svc2", "svc1"), Err(Rcode::Refused));
+        assert_eq!(strip_service_label("abc", "svc1"), Err(Rcode::Refused));
+        assert_eq!(strip_service_label("svc1", "svc1"), Err(Rcode::NameError));
+    }
 }
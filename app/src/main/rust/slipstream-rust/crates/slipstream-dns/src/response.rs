@@ -0,0 +1,205 @@
+use crate::name::encode_name;
+use crate::types::{DnsError, Rcode, ResponseParams, EDNS_UDP_PAYLOAD, RR_OPT};
+use crate::wire::{write_u16, write_u32};
+
+const TXT_STRING_MAX: usize = 255;
+const EDNS_OPTION_PADDING: u16 = 12;
+const DEFAULT_TTL: u32 = 60;
+
+/// Knobs for wrapping an outbound QUIC packet into a DNS answer.
+///
+/// `Default` reproduces the historical wire format byte-for-byte: one answer
+/// echoing the question type, TTL 60, no EDNS padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseOptions {
+    /// Answer RR type; `None` echoes the question's qtype.
+    pub record_type: Option<u16>,
+    pub ttl: u32,
+    /// Pad the whole message to a multiple of this many bytes with an EDNS(0)
+    /// Padding option (RFC 7830). Zero disables padding.
+    pub pad_block: usize,
+    /// Split the payload across several answers of at most this many payload
+    /// bytes each. Zero keeps a single answer. Current slipstream clients
+    /// only accept single-answer responses.
+    pub max_record_payload: usize,
+}
+
+impl Default for ResponseOptions {
+    fn default() -> Self {
+        Self {
+            record_type: None,
+            ttl: DEFAULT_TTL,
+            pad_block: 0,
+            max_record_payload: 0,
+        }
+    }
+}
+
+/// Build the DNS response that carries `params.payload` back to the client.
+pub fn build_tunnel_response(
+    params: &ResponseParams<'_>,
+    opts: &ResponseOptions,
+) -> Result<Vec<u8>, DnsError> {
+    let payload = params.payload.unwrap_or(&[]);
+
+    let mut rcode = params.rcode.unwrap_or(if payload.is_empty() {
+        Rcode::NameError
+    } else {
+        Rcode::Ok
+    });
+
+    let records: Vec<&[u8]> = if !payload.is_empty() && rcode == Rcode::Ok {
+        if opts.max_record_payload == 0 {
+            vec![payload]
+        } else {
+            payload.chunks(opts.max_record_payload).collect()
+        }
+    } else {
+        if let Some(explicit) = params.rcode {
+            rcode = explicit;
+        }
+        Vec::new()
+    };
+    if records.len() > u16::MAX as usize {
+        return Err(DnsError::new("too many answer records"));
+    }
+
+    let mut out = Vec::with_capacity(256);
+    let mut flags = 0x8000 | 0x0400;
+    if params.rd {
+        flags |= 0x0100;
+    }
+    if params.cd {
+        flags |= 0x0010;
+    }
+    flags |= rcode.to_u8() as u16;
+
+    write_u16(&mut out, params.id);
+    write_u16(&mut out, flags);
+    write_u16(&mut out, 1);
+    write_u16(&mut out, records.len() as u16);
+    write_u16(&mut out, 0);
+    write_u16(&mut out, 1);
+
+    encode_name(&params.question.name, &mut out)?;
+    write_u16(&mut out, params.question.qtype);
+    write_u16(&mut out, params.question.qclass);
+
+    let record_type = opts.record_type.unwrap_or(params.question.qtype);
+    for record in records {
+        encode_answer(
+            &mut out,
+            record_type,
+            params.question.qclass,
+            opts.ttl,
+            record,
+        )?;
+    }
+
+    encode_opt_record(&mut out, opts.pad_block)?;
+
+    Ok(out)
+}
+
+fn encode_answer(
+    out: &mut Vec<u8>,
+    record_type: u16,
+    qclass: u16,
+    ttl: u32,
+    payload: &[u8],
+) -> Result<(), DnsError> {
+    // Owner name is a pointer back to the question name at offset 12.
+    out.extend_from_slice(&[0xC0, 0x0C]);
+    write_u16(out, record_type);
+    write_u16(out, qclass);
+    write_u32(out, ttl);
+    let chunk_count = payload.len().div_ceil(TXT_STRING_MAX);
+    let rdata_len = payload.len() + chunk_count;
+    if rdata_len > u16::MAX as usize {
+        return Err(DnsError::new("payload too long"));
+    }
+    write_u16(out, rdata_len as u16);
+    for chunk in payload.chunks(TXT_STRING_MAX) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+    Ok(())
+}
+
+fn encode_opt_record(out: &mut Vec<u8>, pad_block: usize) -> Result<(), DnsError> {
+    out.push(0);
+    write_u16(out, RR_OPT);
+    write_u16(out, EDNS_UDP_PAYLOAD);
+    write_u32(out, 0);
+    if pad_block == 0 {
+        write_u16(out, 0);
+        return Ok(());
+    }
+    // RDLENGTH (2) plus the padding option header (4) precede the padding.
+    let unpadded = out.len() + 2 + 4;
+    let pad_len = unpadded.next_multiple_of(pad_block) - unpadded;
+    if 4 + pad_len > u16::MAX as usize {
+        return Err(DnsError::new("padding block too large"));
+    }
+    write_u16(out, (4 + pad_len) as u16);
+    write_u16(out, EDNS_OPTION_PADDING);
+    write_u16(out, pad_len as u16);
+    out.resize(out.len() + pad_len, 0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_tunnel_response, ResponseOptions};
+    use crate::types::{Question, ResponseParams, CLASS_IN, RR_TXT};
+
+    fn question() -> Question {
+        Question {
+            name: "a.test.com.".to_string(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        }
+    }
+
+    #[test]
+    fn padding_rounds_message_to_block() {
+        let question = question();
+        let payload = [0xaau8; 37];
+        let params = ResponseParams {
+            id: 1,
+            rd: false,
+            cd: false,
+            question: &question,
+            payload: Some(&payload),
+            rcode: None,
+        };
+        for block in [1usize, 64, 128, 468] {
+            let opts = ResponseOptions {
+                pad_block: block,
+                ..ResponseOptions::default()
+            };
+            let out = build_tunnel_response(&params, &opts).expect("build");
+            assert_eq!(out.len() % block, 0, "block {}", block);
+        }
+    }
+
+    #[test]
+    fn split_records_cover_payload() {
+        let question = question();
+        let payload: Vec<u8> = (0..=255u8).cycle().take(700).collect();
+        let params = ResponseParams {
+            id: 1,
+            rd: false,
+            cd: false,
+            question: &question,
+            payload: Some(&payload),
+            rcode: None,
+        };
+        let opts = ResponseOptions {
+            max_record_payload: 300,
+            ..ResponseOptions::default()
+        };
+        let out = build_tunnel_response(&params, &opts).expect("build");
+        assert_eq!(u16::from_be_bytes([out[6], out[7]]), 3);
+    }
+}
@@ -12,6 +12,7 @@ pub enum Rcode {
     FormatError,
     ServerFailure,
     NameError,
+    Refused,
 }
 
 impl Rcode {
@@ -21,6 +22,7 @@ impl Rcode {
             Rcode::FormatError => 1,
             Rcode::ServerFailure => 2,
             Rcode::NameError => 3,
+            Rcode::Refused => 5,
         }
     }
 
@@ -30,6 +32,7 @@ impl Rcode {
             1 => Some(Rcode::FormatError),
             2 => Some(Rcode::ServerFailure),
             3 => Some(Rcode::NameError),
+            5 => Some(Rcode::Refused),
             _ => None,
         }
     }
@@ -85,6 +88,37 @@ pub struct ResponseParams<'a> {
     pub rcode: Option<Rcode>,
 }
 
+/// A DNS response as seen by the tunnel client, independent of transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TunnelResponse {
+    pub id: u16,
+    /// `None` for RCODEs the tunnel does not model.
+    pub rcode: Option<Rcode>,
+    /// TC bit; the payload (if any) should be refetched over a stream transport.
+    pub truncated: bool,
+    /// Tunnel payload carried in the single TXT answer, if present and well formed.
+    pub payload: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// Shorter than a DNS header.
+    TooShort,
+    /// QR bit clear.
+    NotResponse,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort => write!(f, "packet shorter than a DNS header"),
+            ParseError::NotResponse => write!(f, "packet is not a DNS response"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[derive(Debug, Clone)]
 pub struct DnsError {
     message: String,
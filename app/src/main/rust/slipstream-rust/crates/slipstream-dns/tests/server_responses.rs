@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::Path;
+
+use slipstream_dns::{
+    build_tunnel_response, parse_tunnel_response, Question, Rcode, ResponseOptions, ResponseParams,
+    RR_TXT,
+};
+
+struct Golden {
+    id: u16,
+    rd: bool,
+    cd: bool,
+    question: Question,
+    payload: Option<Vec<u8>>,
+    rcode: Option<Rcode>,
+    options: ResponseOptions,
+    expected: Vec<u8>,
+}
+
+#[test]
+fn builder_matches_golden_responses() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/vectors/server-responses");
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .expect("read server-responses dir")
+        .map(|entry| entry.expect("dir entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hex"))
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty(), "no golden responses found");
+
+    for path in entries {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let data = fs::read_to_string(&path).expect("read golden response");
+        let golden = parse_golden(&name, &data);
+        let params = ResponseParams {
+            id: golden.id,
+            rd: golden.rd,
+            cd: golden.cd,
+            question: &golden.question,
+            payload: golden.payload.as_deref(),
+            rcode: golden.rcode,
+        };
+        let built = build_tunnel_response(&params, &golden.options).expect("build response");
+        assert_eq!(encode_hex(&built), encode_hex(&golden.expected), "{}", name);
+
+        // Single-answer TXT outputs must stay readable by the client parser.
+        let single_txt = golden.options.max_record_payload == 0
+            && golden.options.record_type.unwrap_or(golden.question.qtype) == RR_TXT;
+        let parsed = parse_tunnel_response(&built).expect("parse built response");
+        assert_eq!(parsed.id, golden.id, "{}", name);
+        if single_txt && parsed.rcode == Some(Rcode::Ok) {
+            assert_eq!(parsed.payload, golden.payload, "{}", name);
+        }
+    }
+}
+
+fn parse_golden(name: &str, data: &str) -> Golden {
+    let mut golden = Golden {
+        id: 0,
+        rd: false,
+        cd: false,
+        question: Question {
+            name: String::new(),
+            qtype: 0,
+            qclass: 0,
+        },
+        payload: None,
+        rcode: None,
+        options: ResponseOptions::default(),
+        expected: Vec::new(),
+    };
+    let mut hex = String::new();
+    let mut sections = 0;
+    for line in data.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("# query:") {
+            sections += 1;
+            for (key, value) in fields(rest) {
+                match key {
+                    "id" => golden.id = parse_hex_u16(value),
+                    "rd" => golden.rd = value == "true",
+                    "cd" => golden.cd = value == "true",
+                    "name" => golden.question.name = value.to_string(),
+                    "qtype" => golden.question.qtype = value.parse().expect("qtype"),
+                    "qclass" => golden.question.qclass = value.parse().expect("qclass"),
+                    other => panic!("{}: unknown query key {}", name, other),
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("# payload:") {
+            sections += 1;
+            let mut fields = rest.split_whitespace();
+            golden.payload = parse_payload(fields.next().expect("payload value"));
+            for (key, value) in fields.map(|field| field.split_once('=').expect("key=value")) {
+                match key {
+                    "rcode" => golden.rcode = parse_rcode(value),
+                    other => panic!("{}: unknown payload key {}", name, other),
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("# options:") {
+            sections += 1;
+            for (key, value) in fields(rest) {
+                match key {
+                    "record_type" => {
+                        golden.options.record_type = if value == "echo" {
+                            None
+                        } else {
+                            Some(value.parse().expect("record_type"))
+                        }
+                    }
+                    "ttl" => golden.options.ttl = value.parse().expect("ttl"),
+                    "pad_block" => golden.options.pad_block = value.parse().expect("pad_block"),
+                    "max_record_payload" => {
+                        golden.options.max_record_payload =
+                            value.parse().expect("max_record_payload")
+                    }
+                    other => panic!("{}: unknown options key {}", name, other),
+                }
+            }
+        } else if !line.starts_with('#') {
+            hex.push_str(line);
+        }
+    }
+    assert_eq!(
+        sections, 3,
+        "{}: expected query, payload and options lines",
+        name
+    );
+    golden.expected = decode_hex(&hex);
+    golden
+}
+
+fn fields(rest: &str) -> impl Iterator<Item = (&str, &str)> {
+    rest.split_whitespace()
+        .map(|field| field.split_once('=').expect("key=value"))
+}
+
+fn parse_payload(value: &str) -> Option<Vec<u8>> {
+    if value == "none" {
+        None
+    } else if let Some(len) = value.strip_prefix("seq:") {
+        let len: usize = len.parse().expect("seq length");
+        Some((0..len).map(|i| i as u8).collect())
+    } else {
+        Some(decode_hex(value))
+    }
+}
+
+fn parse_rcode(value: &str) -> Option<Rcode> {
+    match value {
+        "default" => None,
+        "ok" => Some(Rcode::Ok),
+        "format_error" => Some(Rcode::FormatError),
+        "server_failure" => Some(Rcode::ServerFailure),
+        "name_error" => Some(Rcode::NameError),
+        other => panic!("unknown rcode {}", other),
+    }
+}
+
+fn parse_hex_u16(value: &str) -> u16 {
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).expect("hex u16")
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    assert!(hex.len().is_multiple_of(2), "hex length must be even");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("valid hex"))
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
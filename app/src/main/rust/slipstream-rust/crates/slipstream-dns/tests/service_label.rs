@@ -0,0 +1,62 @@
+use slipstream_dns::{
+    build_qname, build_qname_with_label, decode_query_with_label, encode_query,
+    max_payload_len_for_domain, DecodeQueryError, QueryParams, Rcode, CLASS_IN, RR_TXT,
+};
+
+fn query_for(qname: &str) -> Vec<u8> {
+    encode_query(&QueryParams {
+        id: 7,
+        qname,
+        qtype: RR_TXT,
+        qclass: CLASS_IN,
+        rd: true,
+        cd: false,
+        qdcount: 1,
+        is_query: true,
+    })
+    .expect("encode query")
+}
+
+fn decode_rcode(query: &[u8], label: Option<&str>) -> Rcode {
+    match decode_query_with_label(query, &["example.com"], label) {
+        Err(DecodeQueryError::Reply { rcode, .. }) => rcode,
+        other => panic!("expected reply, got {:?}", other.map(|query| query.payload)),
+    }
+}
+
+#[test]
+fn service_label_round_trips_under_the_domain() {
+    let payload = vec![4u8, 5, 6, 7];
+    let qname = build_qname_with_label(&payload, Some("svc1"), "example.com").expect("qname");
+    assert!(qname.ends_with(".svc1.example.com."));
+
+    let decoded = decode_query_with_label(&query_for(&qname), &["example.com"], Some("SVC1"))
+        .expect("decode query");
+    assert_eq!(decoded.payload, payload);
+}
+
+#[test]
+fn wrong_or_missing_service_label_is_refused() {
+    let payload = vec![1u8, 2, 3];
+    let other = build_qname_with_label(&payload, Some("svc2"), "example.com").expect("qname");
+    assert_eq!(
+        decode_rcode(&query_for(&other), Some("svc1")),
+        Rcode::Refused
+    );
+
+    let bare = build_qname(&payload, "example.com").expect("qname");
+    assert_eq!(
+        decode_rcode(&query_for(&bare), Some("svc1")),
+        Rcode::Refused
+    );
+}
+
+#[test]
+fn service_label_takes_payload_capacity() {
+    let domain = "example.com";
+    let max_payload = max_payload_len_for_domain("svc1.example.com").expect("max payload");
+    assert!(max_payload < max_payload_len_for_domain(domain).expect("max payload"));
+    let payload = vec![0u8; max_payload + 1];
+    assert!(build_qname(&payload, domain).is_ok());
+    assert!(build_qname_with_label(&payload, Some("svc1"), domain).is_err());
+}
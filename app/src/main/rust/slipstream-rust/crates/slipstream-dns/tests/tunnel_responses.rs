@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::Path;
+
+use slipstream_dns::{parse_tunnel_response, ParseError, Rcode, TunnelResponse};
+
+#[test]
+fn golden_responses_match_parser() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/vectors/tunnel-responses");
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .expect("read tunnel-responses dir")
+        .map(|entry| entry.expect("dir entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hex"))
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty(), "no golden packets found");
+
+    for path in entries {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let data = fs::read_to_string(&path).expect("read golden packet");
+        let mut expect = None;
+        let mut hex = String::new();
+        for line in data.lines() {
+            let line = line.trim();
+            if let Some(rest) = line.strip_prefix("# expect:") {
+                expect = Some(rest.trim().to_string());
+            } else if !line.starts_with('#') {
+                hex.push_str(line);
+            }
+        }
+        let expect = expect.unwrap_or_else(|| panic!("{}: missing '# expect:' line", name));
+        let packet = decode_hex(&hex);
+        let parsed = parse_tunnel_response(&packet);
+        assert_eq!(parsed, parse_expectation(&expect), "{}", name);
+    }
+}
+
+fn parse_expectation(expect: &str) -> Result<TunnelResponse, ParseError> {
+    if let Some(error) = expect.strip_prefix("error=") {
+        return Err(match error {
+            "too_short" => ParseError::TooShort,
+            "not_response" => ParseError::NotResponse,
+            other => panic!("unknown error expectation {}", other),
+        });
+    }
+    let mut response = TunnelResponse {
+        id: 0,
+        rcode: None,
+        truncated: false,
+        payload: None,
+    };
+    for field in expect.split_whitespace() {
+        let (key, value) = field.split_once('=').expect("key=value");
+        match key {
+            "id" => {
+                let value = value.trim_start_matches("0x");
+                response.id = u16::from_str_radix(value, 16).expect("hex id");
+            }
+            "rcode" => response.rcode = rcode_from_str(value),
+            "truncated" => response.truncated = value == "true",
+            "payload" => {
+                response.payload = if value == "none" {
+                    None
+                } else {
+                    Some(decode_hex(value))
+                }
+            }
+            other => panic!("unknown expectation key {}", other),
+        }
+    }
+    Ok(response)
+}
+
+fn rcode_from_str(value: &str) -> Option<Rcode> {
+    match value {
+        "ok" => Some(Rcode::Ok),
+        "format_error" => Some(Rcode::FormatError),
+        "server_failure" => Some(Rcode::ServerFailure),
+        "name_error" => Some(Rcode::NameError),
+        "unknown" => None,
+        other => panic!("unknown rcode {}", other),
+    }
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    assert!(hex.len().is_multiple_of(2), "hex length must be even");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("valid hex"))
+        .collect()
+}
@@ -141,12 +141,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let stateless_packet_src = cc_dir.join("slipstream_stateless_packet.c");
     let test_helpers_src = cc_dir.join("slipstream_test_helpers.c");
     let picotls_layout_src = cc_dir.join("picotls_layout.c");
+    let cnx_info_src = cc_dir.join("slipstream_cnx_info.c");
+    let lb_src = cc_dir.join("slipstream_lb.c");
     println!("cargo:rerun-if-changed={}", cc_src.display());
     println!("cargo:rerun-if-changed={}", mixed_cc_src.display());
     println!("cargo:rerun-if-changed={}", poll_src.display());
     println!("cargo:rerun-if-changed={}", stateless_packet_src.display());
     println!("cargo:rerun-if-changed={}", test_helpers_src.display());
     println!("cargo:rerun-if-changed={}", picotls_layout_src.display());
+    println!("cargo:rerun-if-changed={}", cnx_info_src.display());
+    println!("cargo:rerun-if-changed={}", lb_src.display());
     let picoquic_internal = picoquic_include_dir.join("picoquic_internal.h");
     if picoquic_internal.exists() {
         println!("cargo:rerun-if-changed={}", picoquic_internal.display());
@@ -190,6 +194,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     )?;
     object_paths.push(picotls_layout_obj);
 
+    let cnx_info_obj = out_dir.join("slipstream_cnx_info.c.o");
+    compile_cc_with_includes(
+        &cc,
+        &cnx_info_src,
+        &cnx_info_obj,
+        &[&picoquic_include_dir, &picotls_include_dir],
+    )?;
+    object_paths.push(cnx_info_obj);
+
+    let lb_obj = out_dir.join("slipstream_lb.c.o");
+    compile_cc(&cc, &lb_src, &lb_obj, &picoquic_include_dir)?;
+    object_paths.push(lb_obj);
+
     let archive = out_dir.join("libslipstream_client_objs.a");
     create_archive(&ar, &archive, &object_paths)?;
     println!("cargo:rustc-link-search=native={}", out_dir.display());
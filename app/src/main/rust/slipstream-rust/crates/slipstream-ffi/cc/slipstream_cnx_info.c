@@ -0,0 +1,39 @@
+#include <stdint.h>
+#include "picoquic_internal.h"
+#include "picotls.h"
+
+uint32_t slipstream_get_negotiated_version(picoquic_cnx_t *cnx) {
+    if (cnx == NULL || cnx->version_index < 0) {
+        return 0;
+    }
+    return picoquic_supported_versions[cnx->version_index].version;
+}
+
+uint16_t slipstream_get_cipher_suite(picoquic_cnx_t *cnx) {
+    if (cnx == NULL || cnx->tls_ctx == NULL) {
+        return 0;
+    }
+    /* picoquic_tls_ctx_t is private to tls_api.c; its first member is the ptls_t*. */
+    ptls_t *tls = *(ptls_t **)cnx->tls_ctx;
+    if (tls == NULL) {
+        return 0;
+    }
+    ptls_cipher_suite_t *cipher = ptls_get_cipher(tls);
+    return cipher != NULL ? cipher->id : 0;
+}
+
+/* Key phase changes seen on receive, whichever side started them. */
+uint64_t slipstream_get_key_updates(picoquic_cnx_t *cnx) {
+    if (cnx == NULL) {
+        return 0;
+    }
+    return cnx->nb_crypto_key_rotations;
+}
+
+/* Packets that decrypted and were processed; rejected ones do not count. */
+uint64_t slipstream_get_packets_received(picoquic_cnx_t *cnx) {
+    if (cnx == NULL) {
+        return 0;
+    }
+    return cnx->nb_packets_received;
+}
@@ -0,0 +1,19 @@
+#include <string.h>
+#include "picoquic_internal.h"
+#include "picoquic_lb.h"
+
+/* Issue local connection IDs of cid_length bytes with server_id in clear in
+ * the byte after the first, so a demux in front of several servers can route
+ * packets to the server that owns the connection. */
+int slipstream_set_lb_server_id(picoquic_quic_t *quic, uint8_t server_id, uint8_t cid_length) {
+    if (quic == NULL) {
+        return -1;
+    }
+    picoquic_load_balancer_config_t config;
+    memset(&config, 0, sizeof(config));
+    config.method = picoquic_load_balancer_cid_clear;
+    config.server_id_length = 1;
+    config.connection_id_length = cid_length;
+    config.server_id64 = server_id;
+    return picoquic_lb_compat_cid_config(quic, &config);
+}
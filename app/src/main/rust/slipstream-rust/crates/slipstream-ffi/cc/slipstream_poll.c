@@ -28,6 +28,18 @@ void slipstream_disable_ack_delay(picoquic_cnx_t *cnx) {
     cnx->no_ack_delay = 1;
 }
 
+void slipstream_clamp_send_mtu(picoquic_cnx_t *cnx, uint32_t mtu) {
+    if (cnx == NULL || mtu == 0) {
+        return;
+    }
+    for (int path_id = 0; path_id < cnx->nb_paths; path_id++) {
+        picoquic_path_t* path_x = cnx->path[path_id];
+        if (path_x != NULL && path_x->send_mtu > mtu) {
+            path_x->send_mtu = mtu;
+        }
+    }
+}
+
 int slipstream_find_path_id_by_addr(picoquic_cnx_t *cnx, const struct sockaddr* addr_peer) {
     if (cnx == NULL || addr_peer == NULL || addr_peer->sa_family == 0) {
         return -1;
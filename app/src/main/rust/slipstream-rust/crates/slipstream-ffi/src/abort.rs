@@ -0,0 +1,297 @@
+use crate::picoquic::{picoquic_cnx_t, picoquic_reset_stream, picoquic_stop_sending};
+use libc::c_int;
+use std::fmt;
+
+/// picoquic has no stream with that ID, e.g. it was already freed.
+pub const PICOQUIC_ERROR_INVALID_STREAM_ID: c_int = 0x400 + 14;
+/// The half being aborted already finished: a reset arrived before our
+/// STOP_SENDING, or our FIN was acknowledged before our RESET_STREAM.
+pub const PICOQUIC_ERROR_STREAM_ALREADY_CLOSED: c_int = 0x400 + 15;
+
+/// Retries before a deferred abort is dropped.
+pub const ABORT_RETRY_LIMIT: u32 = 3;
+
+/// picoquic's return codes for the two halves of a refused
+/// [`abort_stream_bidi`]; 0 for the half that went through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbortError {
+    pub stop_sending: c_int,
+    pub reset_stream: c_int,
+}
+
+impl AbortError {
+    /// False when every refusal only says that half of the stream is
+    /// already gone, which no retry can change.
+    pub fn is_transient(&self) -> bool {
+        [self.stop_sending, self.reset_stream].iter().any(|ret| {
+            !matches!(
+                *ret,
+                0 | PICOQUIC_ERROR_INVALID_STREAM_ID | PICOQUIC_ERROR_STREAM_ALREADY_CLOSED
+            )
+        })
+    }
+}
+
+impl fmt::Display for AbortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stop_sending ret={} reset_stream ret={}",
+            self.stop_sending, self.reset_stream
+        )
+    }
+}
+
+/// Send STOP_SENDING and RESET_STREAM for `stream_id`. Both calls are
+/// idempotent in picoquic, so a failed abort can simply be repeated.
+///
+/// # Safety
+/// Caller must ensure `cnx` points to a valid picoquic connection.
+pub unsafe fn abort_stream_bidi(
+    cnx: *mut picoquic_cnx_t,
+    stream_id: u64,
+    app_error: u64,
+) -> Result<(), AbortError> {
+    let stop_sending = picoquic_stop_sending(cnx, stream_id, app_error);
+    let reset_stream = picoquic_reset_stream(cnx, stream_id, app_error);
+    if stop_sending == 0 && reset_stream == 0 {
+        return Ok(());
+    }
+    Err(AbortError {
+        stop_sending,
+        reset_stream,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortRetryOutcome {
+    /// The abort went through on retry number `attempts`.
+    Succeeded { attempts: u32 },
+    /// Still refused after [`ABORT_RETRY_LIMIT`] retries, or refused for a
+    /// reason that is no longer transient; the abort is dropped.
+    GaveUp { attempts: u32, error: AbortError },
+}
+
+#[derive(Debug)]
+struct DeferredAbort {
+    cnx: *mut picoquic_cnx_t,
+    stream_id: u64,
+    app_error: u64,
+    attempts: u32,
+}
+
+/// Aborts picoquic refused for a transient reason, retried once per loop
+/// iteration. Stream state is dropped as soon as the first attempt is made,
+/// so this list is the only thing left that knows the peer may still see
+/// the stream open.
+#[derive(Debug, Default)]
+pub struct DeferredAborts {
+    pending: Vec<DeferredAbort>,
+    failures: u64,
+    retried: u64,
+    abandoned: u64,
+}
+
+impl DeferredAborts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a refused abort and queues it for a retry when the refusal
+    /// is transient; returns whether it was queued.
+    pub fn record_failure(
+        &mut self,
+        cnx: *mut picoquic_cnx_t,
+        stream_id: u64,
+        app_error: u64,
+        error: AbortError,
+    ) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        if !error.is_transient() {
+            return false;
+        }
+        let queued = self
+            .pending
+            .iter()
+            .any(|abort| abort.cnx == cnx && abort.stream_id == stream_id);
+        if !queued {
+            self.pending.push(DeferredAbort {
+                cnx,
+                stream_id,
+                app_error,
+                attempts: 0,
+            });
+        }
+        true
+    }
+
+    /// Retries every pending abort once through `abort` and returns the
+    /// connections and stream IDs that succeeded or were given up on.
+    pub fn retry_with<F>(
+        &mut self,
+        mut abort: F,
+    ) -> Vec<(*mut picoquic_cnx_t, u64, AbortRetryOutcome)>
+    where
+        F: FnMut(*mut picoquic_cnx_t, u64, u64) -> Result<(), AbortError>,
+    {
+        let mut outcomes = Vec::new();
+        let (retried, abandoned) = (&mut self.retried, &mut self.abandoned);
+        self.pending.retain_mut(|pending| {
+            pending.attempts = pending.attempts.saturating_add(1);
+            let attempts = pending.attempts;
+            match abort(pending.cnx, pending.stream_id, pending.app_error) {
+                Ok(()) => {
+                    *retried = retried.saturating_add(1);
+                    outcomes.push((
+                        pending.cnx,
+                        pending.stream_id,
+                        AbortRetryOutcome::Succeeded { attempts },
+                    ));
+                    false
+                }
+                Err(error) if !error.is_transient() || attempts >= ABORT_RETRY_LIMIT => {
+                    *abandoned = abandoned.saturating_add(1);
+                    outcomes.push((
+                        pending.cnx,
+                        pending.stream_id,
+                        AbortRetryOutcome::GaveUp { attempts, error },
+                    ));
+                    false
+                }
+                Err(_) => true,
+            }
+        });
+        outcomes
+    }
+
+    /// [`Self::retry_with`] through [`abort_stream_bidi`].
+    ///
+    /// # Safety
+    /// Every connection with a pending abort must still be valid; drop
+    /// closed ones with [`Self::forget_connection`] first.
+    pub unsafe fn retry(&mut self) -> Vec<(*mut picoquic_cnx_t, u64, AbortRetryOutcome)> {
+        self.retry_with(|cnx, stream_id, app_error| unsafe {
+            abort_stream_bidi(cnx, stream_id, app_error)
+        })
+    }
+
+    /// Drops the pending aborts of a connection that is going away.
+    pub fn forget_connection(&mut self, cnx: *mut picoquic_cnx_t) {
+        self.pending.retain(|abort| abort.cnx != cnx);
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Refused aborts so far, transient or not.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Deferred aborts that later went through.
+    pub fn retried(&self) -> u64 {
+        self.retried
+    }
+
+    /// Deferred aborts that were dropped without going through.
+    pub fn abandoned(&self) -> u64 {
+        self.abandoned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSIENT: AbortError = AbortError {
+        stop_sending: 0,
+        reset_stream: 0x400 + 5,
+    };
+
+    fn cnx(id: usize) -> *mut picoquic_cnx_t {
+        id as *mut picoquic_cnx_t
+    }
+
+    #[test]
+    fn already_closed_halves_are_counted_but_not_retried() {
+        let mut aborts = DeferredAborts::new();
+        let closed = AbortError {
+            stop_sending: PICOQUIC_ERROR_STREAM_ALREADY_CLOSED,
+            reset_stream: 0,
+        };
+        assert!(!closed.is_transient());
+        assert!(!aborts.record_failure(cnx(1), 4, 0x101, closed));
+        assert_eq!(aborts.failures(), 1);
+        assert!(aborts.is_empty());
+    }
+
+    #[test]
+    fn failed_abort_goes_through_on_retry() {
+        let mut aborts = DeferredAborts::new();
+        assert!(aborts.record_failure(cnx(1), 4, 0x101, TRANSIENT));
+        // A second failure for the same stream does not queue it twice.
+        assert!(aborts.record_failure(cnx(1), 4, 0x101, TRANSIENT));
+        assert_eq!(aborts.pending(), 1);
+
+        let mut calls = Vec::new();
+        let outcomes = aborts.retry_with(|cnx, stream_id, app_error| {
+            calls.push((cnx as usize, stream_id, app_error));
+            if calls.len() == 1 {
+                Err(TRANSIENT)
+            } else {
+                Ok(())
+            }
+        });
+        assert!(outcomes.is_empty());
+        assert_eq!(aborts.pending(), 1);
+
+        let outcomes = aborts.retry_with(|cnx, stream_id, app_error| {
+            calls.push((cnx as usize, stream_id, app_error));
+            Ok(())
+        });
+        assert_eq!(
+            outcomes,
+            vec![(cnx(1), 4, AbortRetryOutcome::Succeeded { attempts: 2 })]
+        );
+        assert_eq!(calls, vec![(1, 4, 0x101), (1, 4, 0x101)]);
+        assert!(aborts.is_empty());
+        assert_eq!((aborts.failures(), aborts.retried()), (2, 1));
+    }
+
+    #[test]
+    fn gives_up_after_the_retry_limit_and_forgets_closed_connections() {
+        let mut aborts = DeferredAborts::new();
+        aborts.record_failure(cnx(1), 4, 0x101, TRANSIENT);
+        aborts.record_failure(cnx(2), 8, 0x101, TRANSIENT);
+        aborts.forget_connection(cnx(2));
+        for _ in 1..ABORT_RETRY_LIMIT {
+            assert!(aborts.retry_with(|_, _, _| Err(TRANSIENT)).is_empty());
+        }
+        let outcomes = aborts.retry_with(|cnx, _, _| {
+            assert_eq!(cnx as usize, 1);
+            Err(TRANSIENT)
+        });
+        assert_eq!(
+            outcomes,
+            vec![(
+                cnx(1),
+                4,
+                AbortRetryOutcome::GaveUp {
+                    attempts: ABORT_RETRY_LIMIT,
+                    error: TRANSIENT
+                }
+            )]
+        );
+        assert!(aborts.is_empty());
+        assert_eq!(aborts.abandoned(), 1);
+    }
+}
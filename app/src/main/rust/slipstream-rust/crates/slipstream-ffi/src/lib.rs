@@ -2,7 +2,9 @@
 #[allow(unused_imports)]
 use openssl_sys as _;
 use slipstream_core::HostPort;
+use std::net::IpAddr;
 
+pub mod abort;
 pub mod picoquic;
 pub mod runtime;
 
@@ -16,29 +18,173 @@ pub enum ResolverMode {
     Authoritative = 2,
 }
 
+/// Final escalation step when picoquic keeps producing nothing to send while
+/// streams have ready data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeroSendReset {
+    Off,
+    Stream,
+    Connection,
+}
+
+/// What happens when no authoritative path validates in time under
+/// `require_authoritative_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthoritativePathTimeout {
+    /// Start accepting streams over the paths that are available.
+    Fallback,
+    /// Count a connection failure and reconnect.
+    Fail,
+}
+
+/// Which resolver paths carry keep-alives (`--keep-alive-path`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlivePath {
+    /// picoquic's own PINGs, on whichever path it sends next.
+    Any,
+    /// A poll on an authoritative path instead of a PING.
+    Authoritative,
+    /// A poll on a recursive path instead of a PING.
+    Recursive,
+}
+
+/// How the client loop trades query count for latency (`--profile`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Balanced,
+    /// Smallest delay per keystroke, for SSH-like tunnels; sends more
+    /// queries.
+    Interactive,
+    /// Fewer wakeups and batched sends, for transfers.
+    Bulk,
+}
+
+/// AES-256-GCM key for the session ticket store. Debug output never shows
+/// the key bytes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TicketStoreKey(pub [u8; 32]);
+
+impl std::fmt::Debug for TicketStoreKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TicketStoreKey(<redacted>)")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ResolverSpec {
     pub resolver: HostPort,
     pub mode: ResolverMode,
+    /// Most unanswered polls this resolver may have at once; 0 is unlimited.
+    pub max_inflight_polls: usize,
 }
 
 #[derive(Debug)]
 pub struct ClientConfig<'a> {
     pub tcp_listen_host: &'a str,
     pub tcp_listen_port: u16,
+    pub loopback_only: bool,
     pub resolvers: &'a [ResolverSpec],
+    pub host_overrides: Vec<(String, IpAddr)>,
     pub domain: &'a str,
+    pub rotation_domains: &'a [String],
     pub cert: Option<&'a str>,
     pub congestion_control: Option<&'a str>,
     pub gso: bool,
     pub keep_alive_interval: usize,
+    pub keep_alive_path: KeepAlivePath,
     pub debug_poll: bool,
     pub debug_streams: bool,
     pub idle_poll_interval_ms: u64,
+    pub watchdog_timeout_ms: u64,
+    pub reconnect_poll_grace_ms: u64,
+    pub usable_path_timeout_ms: u64,
+    pub min_reconnect_interval_ms: u64,
+    pub cid_rotation_interval_ms: u64,
+    pub domain_rotation_interval_ms: u64,
+    pub domain_rotation_bytes: u64,
+    pub battery_saver_poll_interval_ms: Option<u64>,
+    pub zero_send_reset: ZeroSendReset,
+    pub zero_send_stall_iterations: u64,
+    pub stream_path_affinity: bool,
+    pub require_authoritative_path: bool,
+    pub authoritative_path_timeout_ms: u64,
+    pub authoritative_path_timeout_action: AuthoritativePathTimeout,
+    pub ready_requires_usable_path: bool,
+    pub data_poll_bias_percent: u8,
+    pub send_batch_size: usize,
+    pub reconnect_delay_min_ms: u64,
+    pub reconnect_delay_max_ms: u64,
+    /// Log rolling checksums of each stream's payload (debug only).
+    pub debug_stream_checksums: bool,
+    /// Stateless resets in a row before one is handled like any other close.
+    pub stateless_reset_tolerance: u32,
+    /// Directory for picoquic connection logs; off when unset.
+    pub qlog_dir: Option<&'a str>,
+    /// Label inserted right under the domain in every query name.
+    pub service_label: Option<&'a str>,
+    /// Probe the resolvers for the largest query they carry before the
+    /// first connection instead of relying on the static MTU estimate.
+    pub mtu_probe: bool,
+    /// Ask picoquic for path events. Only needed to add and track extra
+    /// resolvers as paths; off requires a single resolver.
+    pub path_callbacks: bool,
+    /// Reserved for sealing the session ticket store at rest. The client
+    /// does not persist tickets yet, so nothing reads it.
+    pub ticket_store_key: Option<TicketStoreKey>,
+    /// Answer a peer's STOP_SENDING by resetting only our send side and
+    /// keep delivering what the server sends, instead of aborting the
+    /// stream in both directions.
+    pub stop_sending_half_close: bool,
+    /// Start a QUIC key update this often; 0 disables the time trigger.
+    pub key_update_interval_ms: u64,
+    /// Start a QUIC key update after this many tunnel bytes sent under the
+    /// current keys; 0 disables the byte trigger.
+    pub key_update_bytes: u64,
+    /// Give up once this many connections in a row failed with the same
+    /// TLS or version error; 0 only stops at the generic failure limit.
+    pub terminal_failure_repeats: u32,
+    /// Idle QUIC streams to keep open for new local connections; 0 opens
+    /// each stream on accept.
+    pub stream_pool_size: usize,
+    /// Commands the acceptor and stream tasks may queue for the client
+    /// loop; 0 is unbounded.
+    pub command_channel_capacity: usize,
+    /// Reconnect when a ready connection grants no streams while local
+    /// connects wait, instead of only reporting it.
+    pub reconnect_on_zero_credit: bool,
+    /// Longest the client loop sleeps while nothing is due. A shutdown
+    /// request wakes it regardless.
+    pub max_idle_sleep_ms: u64,
+    /// Append a CRC32 trailer to each stream direction and check the
+    /// server's; only with `debug_streams`, and the server must have it on.
+    pub debug_stream_trailers: bool,
+    /// Log a heartbeat line with the connection state this often; 0 is off.
+    /// It rides on the loop's own wakeups and never adds one.
+    pub heartbeat_interval_ms: u64,
+    /// Poll slice, wake delay cap, poll floor and send batching as a set.
+    pub profile: Profile,
+    /// Local connections with live reader or writer tasks before accepts
+    /// wait in the listen backlog; 0 is no cap.
+    pub max_local_streams: usize,
+    /// Longest a stream waits for its FIN after the local reader closed
+    /// before the FIN is forced; 0 never forces one.
+    pub closing_timeout_ms: u64,
+    /// Send an EDNS Client Subnet option with source prefix 0 in every
+    /// query, asking resolvers not to forward the client's subnet.
+    pub ecs_opt_out: bool,
+    /// Accepts let through before the handshake confirms the server's
+    /// MAX_STREAMS credit; 0 waits for the credit.
+    pub max_streams_floor: usize,
+    /// How strongly authoritative pacing follows response sizes, as a
+    /// percentage of the poll target; 0 ignores them.
+    pub response_size_feedback_percent: u8,
 }
 
+pub use abort::{abort_stream_bidi, AbortError, AbortRetryOutcome, DeferredAborts};
 pub use runtime::{
-    abort_stream_bidi, configure_quic, configure_quic_with_custom, sockaddr_storage_to_socket_addr,
-    socket_addr_to_storage, take_crypto_errors, take_stateless_packet_for_cid,
-    write_stream_or_reset, QuicGuard, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_INTERNAL_ERROR,
+    configure_quic, configure_quic_with_custom, set_lb_server_id, set_log_dir, set_retry_required,
+    sockaddr_storage_to_socket_addr, socket_addr_to_storage, take_crypto_errors,
+    take_stateless_packet_for_cid, write_stream_or_reset, PicoquicClock, QuicGuard, LB_CID_LENGTH,
+    SLIPSTREAM_ADMIN_CLOSE, SLIPSTREAM_CHECKSUM_ERROR, SLIPSTREAM_FILE_CANCEL_ERROR,
+    SLIPSTREAM_FINAL_SIZE_ERROR, SLIPSTREAM_INTERNAL_ERROR,
 };
@@ -7,6 +7,14 @@ pub const PICOQUIC_MAX_PACKET_SIZE: usize = 1536;
 pub const PICOQUIC_RESET_SECRET_SIZE: usize = 16;
 pub const PICOQUIC_PACKET_LOOP_RECV_MAX: usize = 10;
 pub const PICOQUIC_PACKET_LOOP_SEND_MAX: usize = 10;
+/// `picoquic_start_key_rotation` result while the connection is not ready
+/// or the previous key update has not been acknowledged yet.
+pub const PICOQUIC_ERROR_KEY_ROTATION_NOT_READY: c_int = 0x400 + 40;
+pub const PICOQUIC_TRANSPORT_PARAMETER_ERROR: u64 = 0x8;
+pub const PICOQUIC_TRANSPORT_VERSION_NEGOTIATION_ERROR: u64 = 0x11;
+/// Crypto errors carry the TLS alert in the low byte.
+pub const PICOQUIC_TRANSPORT_CRYPTO_ERROR_BASE: u64 = 0x100;
+pub const PICOQUIC_TLS_HANDSHAKE_FAILED: u64 = 0x201;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -178,6 +186,14 @@ pub type picoquic_connection_id_cb_fn = Option<
     ),
 >;
 
+pub type picoquic_alpn_select_fn = Option<
+    unsafe extern "C" fn(
+        quic: *mut picoquic_quic_t,
+        list: *mut ptls_iovec_t,
+        count: size_t,
+    ) -> size_t,
+>;
+
 extern "C" {
     pub fn picoquic_current_time() -> u64;
 
@@ -202,12 +218,24 @@ extern "C" {
     pub fn picoquic_free(quic: *mut picoquic_quic_t);
 
     pub fn picoquic_set_cookie_mode(quic: *mut picoquic_quic_t, cookie_mode: c_int);
+    pub fn picoquic_adjust_max_connections(
+        quic: *mut picoquic_quic_t,
+        max_nb_connections: u32,
+    ) -> c_int;
     pub fn picoquic_set_default_priority(quic: *mut picoquic_quic_t, default_stream_priority: u8);
+    pub fn picoquic_set_default_connection_id_length(
+        quic: *mut picoquic_quic_t,
+        cid_length: u8,
+    ) -> c_int;
     pub fn picoquic_set_default_direct_receive_callback(
         quic: *mut picoquic_quic_t,
         direct_receive_fn: picoquic_stream_direct_receive_fn,
         direct_receive_ctx: *mut c_void,
     );
+    pub fn picoquic_set_alpn_select_fn(
+        quic: *mut picoquic_quic_t,
+        alpn_select_fn: picoquic_alpn_select_fn,
+    );
     pub fn picoquic_set_stream_data_consumption_mode(
         quic: *mut picoquic_quic_t,
         defer_stream_data_consumption: c_int,
@@ -237,6 +265,7 @@ extern "C" {
         initial_mtu_ipv6: u32,
     );
     pub fn picoquic_set_key_log_file_from_env(quic: *mut picoquic_quic_t);
+    pub fn picoquic_set_binlog(quic: *mut picoquic_quic_t, binlog_dir: *const c_char) -> c_int;
     pub fn picoquic_enable_path_callbacks_default(quic: *mut picoquic_quic_t, are_enabled: c_int);
 
     pub fn picoquic_explain_crypto_error(
@@ -289,6 +318,8 @@ extern "C" {
 
     pub fn picoquic_enable_keep_alive(cnx: *mut picoquic_cnx_t, interval: u64);
     pub fn picoquic_disable_keep_alive(cnx: *mut picoquic_cnx_t);
+    pub fn picoquic_renew_connection_id(cnx: *mut picoquic_cnx_t, path_id: c_int) -> c_int;
+    pub fn picoquic_start_key_rotation(cnx: *mut picoquic_cnx_t) -> c_int;
 
     pub fn picoquic_get_next_wake_delay(
         quic: *mut picoquic_quic_t,
@@ -312,6 +343,7 @@ extern "C" {
     pub fn slipstream_is_flow_blocked(cnx: *mut picoquic_cnx_t) -> c_int;
     pub fn slipstream_has_ready_stream(cnx: *mut picoquic_cnx_t) -> c_int;
     pub fn slipstream_disable_ack_delay(cnx: *mut picoquic_cnx_t);
+    pub fn slipstream_clamp_send_mtu(cnx: *mut picoquic_cnx_t, mtu: u32);
     pub fn slipstream_find_path_id_by_addr(
         cnx: *mut picoquic_cnx_t,
         addr_peer: *const sockaddr,
@@ -323,12 +355,22 @@ extern "C" {
     pub fn slipstream_get_max_streams_bidir_remote(cnx: *mut picoquic_cnx_t) -> u64;
     pub fn slipstream_set_cc_override(alg_name: *const c_char);
     pub fn slipstream_set_default_path_mode(mode: c_int);
+    pub fn slipstream_get_negotiated_version(cnx: *mut picoquic_cnx_t) -> u32;
+    pub fn slipstream_get_cipher_suite(cnx: *mut picoquic_cnx_t) -> u16;
+    pub fn slipstream_get_key_updates(cnx: *mut picoquic_cnx_t) -> u64;
+    pub fn slipstream_get_packets_received(cnx: *mut picoquic_cnx_t) -> u64;
+    pub fn slipstream_set_lb_server_id(
+        quic: *mut picoquic_quic_t,
+        server_id: u8,
+        cid_length: u8,
+    ) -> c_int;
     pub fn slipstream_set_path_mode(cnx: *mut picoquic_cnx_t, path_id: c_int, mode: c_int);
     pub fn slipstream_set_path_ack_delay(cnx: *mut picoquic_cnx_t, path_id: c_int, disable: c_int);
 
     pub fn picoquic_get_first_cnx(quic: *mut picoquic_quic_t) -> *mut picoquic_cnx_t;
     pub fn picoquic_get_next_cnx(cnx: *mut picoquic_cnx_t) -> *mut picoquic_cnx_t;
     pub fn picoquic_get_cnx_state(cnx: *mut picoquic_cnx_t) -> picoquic_state_enum;
+    pub fn picoquic_tls_get_negotiated_alpn(cnx: *mut picoquic_cnx_t) -> *const c_char;
     pub fn picoquic_get_close_reasons(
         cnx: *mut picoquic_cnx_t,
         local_reason: *mut u64,
@@ -422,6 +464,11 @@ extern "C" {
         is_active: c_int,
         v_stream_ctx: *mut c_void,
     ) -> c_int;
+    pub fn picoquic_set_stream_path_affinity(
+        cnx: *mut picoquic_cnx_t,
+        stream_id: u64,
+        unique_path_id: u64,
+    ) -> c_int;
 
     pub fn picoquic_probe_new_path_ex(
         cnx: *mut picoquic_cnx_t,
@@ -490,3 +537,15 @@ pub unsafe fn get_bytes_in_transit(cnx: *mut picoquic_cnx_t) -> u64 {
         quality.bytes_in_transit
     }
 }
+
+/// IANA name for a TLS 1.3 cipher suite ID as reported by
+/// `slipstream_get_cipher_suite`.
+pub fn tls_cipher_suite_name(id: u16) -> &'static str {
+    match id {
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0 => "none",
+        _ => "unknown",
+    }
+}
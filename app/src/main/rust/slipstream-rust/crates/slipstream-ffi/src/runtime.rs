@@ -1,26 +1,46 @@
+use crate::abort::abort_stream_bidi;
 use crate::picoquic::{
     picoquic_clear_crypto_errors, picoquic_cnx_t, picoquic_congestion_algorithm_t,
-    picoquic_disable_port_blocking, picoquic_explain_crypto_error, picoquic_free, picoquic_quic_t,
-    picoquic_reset_stream, picoquic_set_cookie_mode, picoquic_set_default_congestion_algorithm,
-    picoquic_set_default_congestion_algorithm_by_name, picoquic_set_default_multipath_option,
-    picoquic_set_default_priority, picoquic_set_initial_send_mtu,
-    picoquic_set_key_log_file_from_env, picoquic_set_max_data_control, picoquic_set_mtu_max,
-    picoquic_set_preemptive_repeat_policy, picoquic_set_stream_data_consumption_mode,
-    picoquic_stop_sending, slipstream_take_stateless_packet_for_cid, PICOQUIC_MAX_PACKET_SIZE,
+    picoquic_current_time, picoquic_disable_port_blocking, picoquic_explain_crypto_error,
+    picoquic_free, picoquic_quic_t, picoquic_set_binlog, picoquic_set_cookie_mode,
+    picoquic_set_default_congestion_algorithm, picoquic_set_default_congestion_algorithm_by_name,
+    picoquic_set_default_multipath_option, picoquic_set_default_priority,
+    picoquic_set_initial_send_mtu, picoquic_set_key_log_file_from_env,
+    picoquic_set_max_data_control, picoquic_set_mtu_max, picoquic_set_preemptive_repeat_policy,
+    picoquic_set_stream_data_consumption_mode, slipstream_set_lb_server_id,
+    slipstream_take_stateless_packet_for_cid, PICOQUIC_MAX_PACKET_SIZE,
 };
 use libc::{c_char, c_int, c_ulong, size_t, sockaddr_storage};
+use slipstream_core::clock::Clock;
 use slipstream_core::tcp::stream_write_buffer_bytes;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::io::Write;
 use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6, TcpStream};
 
 pub const SLIPSTREAM_INTERNAL_ERROR: u64 = 0x101;
 pub const SLIPSTREAM_FILE_CANCEL_ERROR: u64 = 0x105;
+/// The peer sent stream data past the final size its FIN announced.
+pub const SLIPSTREAM_FINAL_SIZE_ERROR: u64 = 0x106;
+/// A stream direction failed its `--debug-stream-trailers` CRC32 check.
+pub const SLIPSTREAM_CHECKSUM_ERROR: u64 = 0x107;
+/// An operator closed the connection from the server control socket.
+pub const SLIPSTREAM_ADMIN_CLOSE: u64 = 0x108;
 
 extern "C" {
     fn ERR_error_string_n(e: c_ulong, buf: *mut c_char, len: size_t);
 }
 
+/// picoquic's own clock, the one its timestamps and wake delays use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PicoquicClock;
+
+impl Clock for PicoquicClock {
+    fn now_us(&self) -> u64 {
+        // SAFETY: picoquic_current_time only reads the system clock.
+        unsafe { picoquic_current_time() }
+    }
+}
+
 pub struct QuicGuard {
     quic: *mut picoquic_quic_t,
 }
@@ -207,6 +227,48 @@ pub fn sockaddr_storage_to_socket_addr(storage: &sockaddr_storage) -> Result<Soc
     }
 }
 
+/// Make picoquic answer every new connection with a Retry, so a client has
+/// to prove its address before the handshake starts. Tokens are not handed
+/// out after the handshake either way.
+///
+/// # Safety
+/// Caller must ensure `quic` points to a valid picoquic context.
+pub unsafe fn set_retry_required(quic: *mut picoquic_quic_t, required: bool) {
+    picoquic_set_cookie_mode(quic, if required { 1 } else { 0 });
+}
+
+/// Length of the connection IDs a server issues once it has a load
+/// balancer server ID.
+pub const LB_CID_LENGTH: u8 = 8;
+
+/// Make the server issue [`LB_CID_LENGTH`]-byte connection IDs carrying
+/// `server_id` in clear at byte 1, so `slipstream-demux` can route on them.
+///
+/// # Safety
+/// Caller must ensure `quic` points to a valid picoquic context with no
+/// connections yet.
+pub unsafe fn set_lb_server_id(quic: *mut picoquic_quic_t, server_id: u8) -> Result<(), String> {
+    if slipstream_set_lb_server_id(quic, server_id, LB_CID_LENGTH) != 0 {
+        return Err(format!("Could not configure server ID {}", server_id));
+    }
+    Ok(())
+}
+
+/// Write picoquic's binary connection logs into `dir`, one file per
+/// connection. `picolog` from the picoquic tree converts them to qlog.
+///
+/// # Safety
+/// Caller must ensure `quic` points to a valid picoquic context.
+pub unsafe fn set_log_dir(quic: *mut picoquic_quic_t, dir: &str) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("Could not create log directory {}: {}", dir, err))?;
+    let dir_c = CString::new(dir).map_err(|_| "Log directory contains NUL".to_string())?;
+    if picoquic_set_binlog(quic, dir_c.as_ptr()) != 0 {
+        return Err(format!("Could not enable connection logs in {}", dir));
+    }
+    Ok(())
+}
+
 /// # Safety
 /// Caller must ensure `quic` points to a valid picoquic context for the duration of the call.
 pub unsafe fn take_stateless_packet_for_cid(
@@ -249,16 +311,9 @@ pub unsafe fn write_stream_or_reset(
             SLIPSTREAM_INTERNAL_ERROR
         };
         // SAFETY: caller guarantees cnx is a valid picoquic connection.
-        unsafe { abort_stream_bidi(cnx, stream_id, code) };
+        let _ = unsafe { abort_stream_bidi(cnx, stream_id, code) };
         let _ = stream.shutdown(Shutdown::Both);
         return true;
     }
     false
 }
-
-/// # Safety
-/// Caller must ensure `cnx` points to a valid picoquic connection.
-pub unsafe fn abort_stream_bidi(cnx: *mut picoquic_cnx_t, stream_id: u64, app_error: u64) {
-    let _ = picoquic_stop_sending(cnx, stream_id, app_error);
-    let _ = picoquic_reset_stream(cnx, stream_id, app_error);
-}
@@ -0,0 +1,179 @@
+use slipstream_ffi::picoquic::{picoquic_cnx_t, picoquic_get_cnx_state, picoquic_state_enum};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Bytes are accounted per source IP over windows of this length.
+const AMPLIFICATION_WINDOW: Duration = Duration::from_secs(2);
+/// Source IPs tracked at once; new sources past this get no budget.
+const MAX_TRACKED_SOURCES: usize = 65_536;
+/// How long picoquic keeps forcing Retry after the last clamped response.
+const RETRY_HOLD: Duration = Duration::from_secs(30);
+const CLAMP_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+struct SourceWindow {
+    started_at: Instant,
+    received: u64,
+    sent: u64,
+}
+
+/// Amplification limit for sources that have not completed a handshake
+/// (`--amplification-limit`).
+///
+/// Every DNS packet from a source IP adds to that IP's received bytes for
+/// the current window, whatever its port: resolvers send each query from a
+/// fresh port, so a window per address would grant nothing to the response.
+/// Responses to it that are not carried by a validated connection may only
+/// add up to `factor` times that; the rest are clamped. With
+/// `--amplification-retry` the server also answers new connections with a
+/// Retry while clamping, so that spoofed sources never reach the handshake.
+pub(crate) struct AmplificationLimiter {
+    factor: u64,
+    windows: HashMap<IpAddr, SourceWindow>,
+    clamped_responses: u64,
+    retry_until: Option<Instant>,
+    last_log_at: Option<Instant>,
+}
+
+impl AmplificationLimiter {
+    pub(crate) fn new(factor: u32) -> Self {
+        Self {
+            factor: u64::from(factor),
+            windows: HashMap::new(),
+            clamped_responses: 0,
+            retry_until: None,
+            last_log_at: None,
+        }
+    }
+
+    pub(crate) fn record_received(&mut self, peer: SocketAddr, bytes: usize, now: Instant) {
+        let source = peer.ip();
+        if !self.windows.contains_key(&source) && self.windows.len() >= MAX_TRACKED_SOURCES {
+            self.windows
+                .retain(|_, window| now.duration_since(window.started_at) < AMPLIFICATION_WINDOW);
+            if self.windows.len() >= MAX_TRACKED_SOURCES {
+                return;
+            }
+        }
+        let window = self.windows.entry(source).or_insert(SourceWindow {
+            started_at: now,
+            received: 0,
+            sent: 0,
+        });
+        if now.duration_since(window.started_at) >= AMPLIFICATION_WINDOW {
+            *window = SourceWindow {
+                started_at: now,
+                received: 0,
+                sent: 0,
+            };
+        }
+        window.received = window.received.saturating_add(bytes as u64);
+    }
+
+    /// Bytes that may still be sent to `peer` in its current window.
+    pub(crate) fn remaining(&self, peer: SocketAddr) -> usize {
+        self.windows.get(&peer.ip()).map_or(0, |window| {
+            let budget = window.received.saturating_mul(self.factor);
+            budget.saturating_sub(window.sent) as usize
+        })
+    }
+
+    pub(crate) fn record_sent(&mut self, peer: SocketAddr, bytes: usize) {
+        if let Some(window) = self.windows.get_mut(&peer.ip()) {
+            window.sent = window.sent.saturating_add(bytes as u64);
+        }
+    }
+
+    /// Counts a response that did not fit; warns at most once per second.
+    pub(crate) fn clamp(&mut self, peer: SocketAddr, wanted: usize, now: Instant) {
+        self.clamped_responses = self.clamped_responses.saturating_add(1);
+        self.retry_until = Some(now + RETRY_HOLD);
+        let due = self
+            .last_log_at
+            .is_none_or(|last| now.duration_since(last) >= CLAMP_LOG_INTERVAL);
+        if due {
+            self.last_log_at = Some(now);
+            tracing::warn!(
+                "Clamped {} byte response to unvalidated source {} (remaining={} clamped_responses={})",
+                wanted,
+                peer,
+                self.remaining(peer),
+                self.clamped_responses
+            );
+        }
+    }
+
+    /// Whether new connections should currently be sent a Retry, if the
+    /// server was asked to (`--amplification-retry`).
+    pub(crate) fn retry_required(&self, now: Instant) -> bool {
+        self.retry_until.is_some_and(|until| now < until)
+    }
+
+    pub(crate) fn clamped_responses(&self) -> u64 {
+        self.clamped_responses
+    }
+}
+
+/// Whether responses on `cnx` go to an address the handshake has validated.
+pub(crate) fn is_validated_cnx(cnx: *mut picoquic_cnx_t) -> bool {
+    if cnx.is_null() {
+        return false;
+    }
+    let state = unsafe { picoquic_get_cnx_state(cnx) };
+    matches!(
+        state,
+        picoquic_state_enum::picoquic_state_server_almost_ready
+            | picoquic_state_enum::picoquic_state_ready
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_is_a_multiple_of_received_bytes() {
+        let peer: SocketAddr = "192.0.2.1:5353".parse().unwrap();
+        let other: SocketAddr = "192.0.2.2:5353".parse().unwrap();
+        let mut limiter = AmplificationLimiter::new(3);
+        let now = Instant::now();
+        assert_eq!(limiter.remaining(peer), 0);
+        limiter.record_received(peer, 100, now);
+        assert_eq!(limiter.remaining(peer), 300);
+        assert_eq!(limiter.remaining(other), 0);
+        limiter.record_sent(peer, 250);
+        assert_eq!(limiter.remaining(peer), 50);
+        limiter.record_sent(peer, 100);
+        assert_eq!(limiter.remaining(peer), 0);
+
+        // A new window starts from the next packet.
+        limiter.record_received(peer, 10, now + AMPLIFICATION_WINDOW);
+        assert_eq!(limiter.remaining(peer), 30);
+    }
+
+    #[test]
+    fn rotating_ports_share_their_address_budget() {
+        let query: SocketAddr = "192.0.2.1:40001".parse().unwrap();
+        let next_query: SocketAddr = "192.0.2.1:40002".parse().unwrap();
+        let mut limiter = AmplificationLimiter::new(3);
+        let now = Instant::now();
+        limiter.record_received(query, 100, now);
+        limiter.record_received(next_query, 100, now);
+        assert_eq!(limiter.remaining(next_query), 600);
+        limiter.record_sent(query, 250);
+        assert_eq!(limiter.remaining(next_query), 350);
+    }
+
+    #[test]
+    fn clamps_are_counted_and_hold_retry() {
+        let peer: SocketAddr = "192.0.2.1:5353".parse().unwrap();
+        let mut limiter = AmplificationLimiter::new(3);
+        let now = Instant::now();
+        assert!(!limiter.retry_required(now));
+        limiter.clamp(peer, 1200, now);
+        limiter.clamp(peer, 1200, now);
+        assert_eq!(limiter.clamped_responses(), 2);
+        assert!(limiter.retry_required(now + Duration::from_secs(1)));
+        assert!(!limiter.retry_required(now + RETRY_HOLD));
+    }
+}
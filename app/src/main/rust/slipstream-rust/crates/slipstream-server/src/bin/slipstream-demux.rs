@@ -0,0 +1,85 @@
+use clap::Parser;
+use slipstream_core::{normalize_domain, parse_host_port, AddressKind, HostPort};
+use slipstream_dns::validate_service_label;
+use slipstream_server::demux::{run_demux, DemuxConfig};
+use tokio::runtime::Builder;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "slipstream-demux",
+    about = "slipstream-demux - Shard slipstream-server processes by QUIC connection ID"
+)]
+struct Args {
+    #[arg(long = "dns-listen-host", default_value = "::")]
+    dns_listen_host: String,
+    #[arg(long = "dns-listen-port", short = 'l', default_value_t = 53)]
+    dns_listen_port: u16,
+    #[arg(long = "backend", short = 'b', value_name = "HOST:PORT", value_parser = parse_backend_address, required = true)]
+    backends: Vec<HostPort>,
+    #[arg(long = "domain", short = 'd', value_parser = parse_domain, required = true)]
+    domains: Vec<String>,
+    #[arg(long = "service-label", value_name = "LABEL", value_parser = parse_service_label)]
+    service_label: Option<String>,
+    #[arg(long = "health-interval", value_name = "SECONDS", default_value_t = 5)]
+    health_interval: u64,
+    #[arg(
+        long = "session-idle-timeout",
+        value_name = "SECONDS",
+        default_value_t = 60
+    )]
+    session_idle_timeout: u64,
+    #[arg(long = "max-sessions", default_value_t = 4096)]
+    max_sessions: usize,
+}
+
+fn main() {
+    init_logging();
+    let args = Args::parse();
+    let config = DemuxConfig {
+        dns_listen_host: args.dns_listen_host,
+        dns_listen_port: args.dns_listen_port,
+        backends: args.backends,
+        domains: args.domains,
+        service_label: args.service_label,
+        health_interval_seconds: args.health_interval,
+        session_idle_timeout_seconds: args.session_idle_timeout,
+        max_sessions: args.max_sessions,
+    };
+
+    let runtime = Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .expect("Failed to build Tokio runtime");
+    if let Err(err) = runtime.block_on(run_demux(&config)) {
+        tracing::error!("Demux error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .without_time()
+        .try_init();
+}
+
+fn parse_domain(input: &str) -> Result<String, String> {
+    normalize_domain(input).map_err(|err| err.to_string())
+}
+
+fn parse_service_label(input: &str) -> Result<String, String> {
+    validate_service_label(input).map_err(|err| err.to_string())?;
+    Ok(input.to_ascii_lowercase())
+}
+
+fn parse_backend_address(input: &str) -> Result<HostPort, String> {
+    let parsed = parse_host_port(input, 0, AddressKind::Target).map_err(|err| err.to_string())?;
+    if parsed.port == 0 {
+        return Err("backend address must include a port".to_string());
+    }
+    Ok(parsed)
+}
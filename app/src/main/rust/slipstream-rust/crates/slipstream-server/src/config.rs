@@ -120,6 +120,52 @@ pub(crate) fn ensure_cert_key(cert_path: &Path, key_path: &Path) -> Result<bool,
     }
 }
 
+/// What [`ensure_cert_key`] would do, without writing anything: a missing
+/// pair would be generated, an existing pair must parse.
+pub(crate) fn check_cert_key(cert_path: &Path, key_path: &Path) -> Result<String, String> {
+    if !cert_path.exists() && !key_path.exists() {
+        return Ok("would generate a self-signed pair".to_string());
+    }
+    ensure_cert_key(cert_path, key_path)?;
+    let digest = cert_fingerprint(cert_path)?;
+    let pem = fs::read(key_path)
+        .map_err(|err| format!("Failed to read key {}: {}", key_path.display(), err))?;
+    PKey::private_key_from_pem(&pem)
+        .map_err(|err| format!("Failed to parse key {}: {}", key_path.display(), err))?;
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    Ok(format!("sha256 {}", hex))
+}
+
+/// What [`load_or_create_reset_seed`] would do, without writing anything.
+pub(crate) fn check_reset_seed(path: &Path) -> Result<String, String> {
+    match load_reset_seed(path) {
+        Ok(_) => Ok(path.display().to_string()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Ok(format!("{} would be created", path.display()))
+        }
+        Err(err) => Err(format!(
+            "Failed to read reset seed {}: {}",
+            path.display(),
+            err
+        )),
+    }
+}
+
+/// SHA-256 of the certificate's DER encoding, the value clients pin.
+pub(crate) fn cert_fingerprint(cert_path: &Path) -> Result<Vec<u8>, String> {
+    let pem = fs::read(cert_path)
+        .map_err(|err| format!("Failed to read cert {}: {}", cert_path.display(), err))?;
+    let der = X509::from_pem(&pem)
+        .and_then(|cert| cert.to_der())
+        .map_err(|err| format!("Failed to parse cert {}: {}", cert_path.display(), err))?;
+    openssl::hash::hash(MessageDigest::sha256(), &der)
+        .map(|digest| digest.to_vec())
+        .map_err(|err| format!("Failed to hash cert {}: {}", cert_path.display(), err))
+}
+
 fn load_reset_seed(path: &Path) -> io::Result<[u8; PICOQUIC_RESET_SECRET_SIZE]> {
     let contents = fs::read_to_string(path)?;
     parse_hex_seed(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
@@ -309,6 +355,29 @@ mod tests {
         let _ = fs::remove_file(&path);
     }
 
+    #[test]
+    fn checks_do_not_create_missing_files() {
+        let cert = temp_path("check-cert");
+        let key = temp_path("check-key");
+        let seed = temp_path("check-seed");
+        assert!(check_cert_key(&cert, &key)
+            .expect("missing pair")
+            .contains("would generate"));
+        assert!(check_reset_seed(&seed)
+            .expect("missing seed")
+            .contains("would be created"));
+        assert!(!cert.exists() && !key.exists() && !seed.exists());
+
+        fs::write(&seed, b"deadbeef").expect("write seed");
+        assert!(check_reset_seed(&seed).is_err());
+        fs::write(&cert, b"not a cert").expect("write cert");
+        assert!(check_cert_key(&cert, &key)
+            .unwrap_err()
+            .contains("Key file is missing"));
+        let _ = fs::remove_file(&seed);
+        let _ = fs::remove_file(&cert);
+    }
+
     #[test]
     fn reset_seed_rejects_bad_length() {
         let path = temp_path("reset-seed-bad");
@@ -0,0 +1,387 @@
+//! The `--control-socket` Unix socket for operator commands.
+//!
+//! Every line a client writes is one command and gets one reply line. The
+//! socket tasks only parse commands: anything that touches picoquic is
+//! queued to the server loop, which applies it between packets on the
+//! thread that owns the QUIC context and answers through a oneshot.
+//!
+//! `close-connection <cnx> [SECONDS]` closes the connection logged as
+//! `cnx=<cnx>` the way `--shutdown-drain` closes all of them: the
+//! connection takes no new streams, data already received from the client
+//! is delivered to the targets for up to SECONDS (default: the
+//! `--shutdown-drain` value), and the connection is then closed with
+//! [`SLIPSTREAM_ADMIN_CLOSE`].
+
+use crate::drain::{DrainStatus, ShutdownDrain};
+use crate::server::{collect_active_connections, ConnTag, ServerError};
+use crate::streams::{remove_connection_streams, ServerState};
+use slipstream_ffi::picoquic::{picoquic_close, picoquic_quic_t};
+use slipstream_ffi::SLIPSTREAM_ADMIN_CLOSE;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ControlCommand {
+    CloseConnection {
+        short_id: u32,
+        /// Overrides `--shutdown-drain` for this close.
+        drain: Option<Duration>,
+    },
+}
+
+pub(crate) fn parse_command(line: &str) -> Result<ControlCommand, String> {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("close-connection") => {
+            let id = words
+                .next()
+                .ok_or_else(|| "usage: close-connection <cnx> [SECONDS]".to_string())?;
+            let hex = id.strip_prefix("cnx=").unwrap_or(id);
+            let short_id = (hex.len() <= 8)
+                .then(|| u32::from_str_radix(hex, 16).ok())
+                .flatten()
+                .ok_or_else(|| format!("invalid connection id {:?}", id))?;
+            let drain = words
+                .next()
+                .map(|seconds| {
+                    seconds
+                        .parse::<u64>()
+                        .map(Duration::from_secs)
+                        .map_err(|_| format!("invalid drain seconds {:?}", seconds))
+                })
+                .transpose()?;
+            if words.next().is_some() {
+                return Err("usage: close-connection <cnx> [SECONDS]".to_string());
+            }
+            Ok(ControlCommand::CloseConnection { short_id, drain })
+        }
+        Some(other) => Err(format!("unknown command {:?}", other)),
+        None => Err("empty command".to_string()),
+    }
+}
+
+struct ControlRequest {
+    command: ControlCommand,
+    reply: oneshot::Sender<String>,
+}
+
+/// A close waiting for its connection's drain.
+struct PendingClose {
+    cnx: usize,
+    tag: ConnTag,
+    drain: ShutdownDrain,
+    reply: oneshot::Sender<String>,
+}
+
+/// The loop's end of the control socket. Dropping it removes the socket
+/// file.
+pub(crate) struct ControlSocket {
+    path: String,
+    requests: mpsc::UnboundedReceiver<ControlRequest>,
+    closes: Vec<PendingClose>,
+    default_drain: Duration,
+}
+
+impl ControlSocket {
+    /// Binds `path`, replacing a socket left behind by an earlier run, and
+    /// starts accepting clients.
+    pub(crate) fn bind(path: &str, default_drain: Duration) -> Result<Self, ServerError> {
+        let requests = listen(path)?;
+        tracing::info!("Control socket listening on {}", path);
+        Ok(Self {
+            path: path.to_string(),
+            requests,
+            closes: Vec::new(),
+            default_drain,
+        })
+    }
+
+    /// Takes queued commands and moves pending closes along; called once
+    /// per loop iteration.
+    pub(crate) fn poll(&mut self, quic: *mut picoquic_quic_t, state: &mut ServerState) {
+        let now = Instant::now();
+        while let Ok(request) = self.requests.try_recv() {
+            match request.command {
+                ControlCommand::CloseConnection { short_id, drain } => {
+                    self.start_close(state, short_id, drain, request.reply, now);
+                }
+            }
+        }
+        if self.closes.is_empty() {
+            return;
+        }
+
+        let active = collect_active_connections(quic);
+        let mut index = 0;
+        while index < self.closes.len() {
+            let close = &self.closes[index];
+            let cnx = match active.get(&close.cnx) {
+                Some(&cnx) if state.is_registered(close.cnx) => cnx,
+                _ => {
+                    let close = self.closes.swap_remove(index);
+                    tracing::info!("Control socket: {} closed during its drain", close.tag);
+                    let _ = close
+                        .reply
+                        .send(format!("closed {}: ended during the drain", close.tag));
+                    continue;
+                }
+            };
+            let message = match close
+                .drain
+                .poll(state.connection_backlog_bytes(close.cnx), now)
+            {
+                DrainStatus::Draining => {
+                    index += 1;
+                    continue;
+                }
+                DrainStatus::Flushed => format!(
+                    "closed {}: drained in {}ms",
+                    close.tag,
+                    close.drain.elapsed(now).as_millis()
+                ),
+                DrainStatus::Expired { backlog_bytes } => format!(
+                    "closed {}: drain deadline reached with {} bytes still queued for targets",
+                    close.tag, backlog_bytes
+                ),
+            };
+            let close = self.closes.swap_remove(index);
+            unsafe {
+                let _ = picoquic_close(cnx, SLIPSTREAM_ADMIN_CLOSE);
+            }
+            remove_connection_streams(state, close.cnx);
+            tracing::info!("Control socket: {}", message);
+            let _ = close.reply.send(message);
+        }
+    }
+
+    fn start_close(
+        &mut self,
+        state: &mut ServerState,
+        short_id: u32,
+        drain: Option<Duration>,
+        reply: oneshot::Sender<String>,
+        now: Instant,
+    ) {
+        let Some(cnx) = state.connection_by_short_id(short_id) else {
+            let _ = reply.send(format!("error: no connection cnx={:08x}", short_id));
+            return;
+        };
+        let tag = state.conn_tag(cnx);
+        if self.closes.iter().any(|close| close.cnx == cnx) {
+            let _ = reply.send(format!("error: {} is already closing", tag));
+            return;
+        }
+        let timeout = drain.unwrap_or(self.default_drain);
+        state.refuse_new_streams(cnx);
+        tracing::info!(
+            "Control socket: closing {}; draining for up to {}s (target_backlog_bytes={})",
+            tag,
+            timeout.as_secs(),
+            state.connection_backlog_bytes(cnx)
+        );
+        self.closes.push(PendingClose {
+            cnx,
+            tag,
+            drain: ShutdownDrain::start(timeout, now),
+            reply,
+        });
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn listen(path: &str) -> Result<mpsc::UnboundedReceiver<ControlRequest>, ServerError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // Only a socket is replaced, so a mistyped path cannot delete a file.
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(ServerError::new(format!(
+                "Control socket path {} exists and is not a socket",
+                path
+            )));
+        }
+        std::fs::remove_file(path).map_err(|err| {
+            ServerError::new(format!(
+                "Failed to remove stale control socket {}: {}",
+                path, err
+            ))
+        })?;
+    }
+    let listener = bind_private(std::path::Path::new(path))?;
+    let (request_tx, request_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_client(stream, request_tx.clone()));
+                }
+                Err(err) => {
+                    tracing::warn!("Control socket accept failed: {}", err);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    });
+    Ok(request_rx)
+}
+
+/// Binds the socket inside a fresh 0700 directory next to `path`, restricts
+/// it to mode 0600 and only then moves it to `path`. Connecting needs write
+/// access, so only the server's user can send commands, and nobody else can
+/// reach the socket while the umask's mode is still on it.
+#[cfg(unix)]
+fn bind_private(path: &std::path::Path) -> Result<tokio::net::UnixListener, ServerError> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let name = path.file_name().ok_or_else(|| {
+        ServerError::new(format!(
+            "Control socket path {} has no file name",
+            path.display()
+        ))
+    })?;
+    let mut staging_name = std::ffi::OsString::from(".");
+    staging_name.push(name);
+    staging_name.push(format!(".{}", std::process::id()));
+    let staging = path.with_file_name(staging_name);
+    let staged = staging.join("control.sock");
+    // Left behind if an earlier run with the same pid was killed mid-bind.
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .map_err(|err| {
+            ServerError::new(format!(
+                "Failed to create directory {} for the control socket: {}",
+                staging.display(),
+                err
+            ))
+        })?;
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .map_err(|err| format!("Failed to bind control socket {}: {}", path.display(), err))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600)).map_err(
+                |err| {
+                    format!(
+                        "Failed to restrict control socket {}: {}",
+                        path.display(),
+                        err
+                    )
+                },
+            )?;
+            std::fs::rename(&staged, path).map_err(|err| {
+                format!(
+                    "Failed to move control socket to {}: {}",
+                    path.display(),
+                    err
+                )
+            })?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    bound.map_err(ServerError::new)
+}
+
+#[cfg(not(unix))]
+fn listen(_path: &str) -> Result<mpsc::UnboundedReceiver<ControlRequest>, ServerError> {
+    Err(ServerError::new(
+        "The control socket needs Unix domain sockets",
+    ))
+}
+
+#[cfg(unix)]
+async fn serve_client(
+    stream: tokio::net::UnixStream,
+    request_tx: mpsc::UnboundedSender<ControlRequest>,
+) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (read_half, mut write_half) = stream.into_split();
+    let mut lines = BufReader::new(read_half).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse_command(&line) {
+            Ok(command) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if request_tx
+                    .send(ControlRequest {
+                        command,
+                        reply: reply_tx,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+                reply_rx
+                    .await
+                    .unwrap_or_else(|_| "error: server is shutting down".to_string())
+            }
+            Err(err) => format!("error: {}", err),
+        };
+        if write_half
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_command, ControlCommand};
+    use std::time::Duration;
+
+    #[test]
+    fn close_connection_takes_the_logged_id() {
+        assert_eq!(
+            parse_command("close-connection 1a2b3c4d"),
+            Ok(ControlCommand::CloseConnection {
+                short_id: 0x1a2b_3c4d,
+                drain: None,
+            })
+        );
+        assert_eq!(
+            parse_command("  close-connection cnx=0000beef 5\n"),
+            Ok(ControlCommand::CloseConnection {
+                short_id: 0xbeef,
+                drain: Some(Duration::from_secs(5)),
+            })
+        );
+        assert!(parse_command("close-connection").is_err());
+        assert!(parse_command("close-connection 1a2b3c4d5").is_err());
+        assert!(parse_command("close-connection xyz").is_err());
+        assert!(parse_command("close-connection 1a2b3c4d soon").is_err());
+        assert!(parse_command("close-connection 1a2b3c4d 5 now").is_err());
+        assert!(parse_command("restart").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn only_the_owner_can_use_the_socket() {
+        use super::ControlSocket;
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("slipstream-control-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+        let socket = ControlSocket::bind(path, Duration::from_secs(1)).unwrap();
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let staging = format!("{}.{}", path, std::process::id())
+            .replace("slipstream-control-", ".slipstream-control-");
+        assert!(std::fs::metadata(staging).is_err());
+        drop(socket);
+        assert!(std::fs::metadata(path).is_err());
+    }
+}
@@ -0,0 +1,924 @@
+//! DNS-level demultiplexer that spreads tunnel traffic over several server
+//! processes (`slipstream-demux`).
+//!
+//! The server loop is single-threaded, so scaling past one core means
+//! running several servers. Resolvers rotate their source ports, so the UDP
+//! 4-tuple says nothing about which server owns a QUIC connection. Instead,
+//! each backend runs with `--server-id N` and writes N into byte 1 of every
+//! connection ID it issues. The demux decodes just enough of each tunnel
+//! query to read the destination connection ID and forwards the raw DNS
+//! datagram to the backend named there. Initial and 0-RTT packets still
+//! carry the client's random connection ID; those are spread over the
+//! healthy backends by rendezvous hashing, which keeps retransmissions on
+//! the same backend.
+//!
+//! Every resolver address gets its own upstream socket per backend, so a
+//! backend still sees one peer per resolver. Resolvers pick a fresh source
+//! port for most queries, so the port is left out: each socket remembers
+//! which port sent a query and returns the reply with the same DNS ID and
+//! question there.
+
+use crate::server::{bind_udp_socket, map_io, ServerError};
+use crate::source_filter::{QUIC_V1, QUIC_V2};
+use crate::udp_fallback::MAX_UDP_PACKET_SIZE;
+use slipstream_core::net::is_transient_udp_error;
+use slipstream_core::{resolve_host_port, HostPort};
+use slipstream_dns::{
+    decode_query_with_label, encode_query, validate_service_label, DecodeQueryError, QueryParams,
+    CLASS_IN, RR_A,
+};
+use slipstream_ffi::LB_CID_LENGTH;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket as TokioUdpSocket;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Probes in a row without an answer before a backend is marked down.
+const UNHEALTHY_AFTER_MISSES: u32 = 3;
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+/// Queries still waiting for a reply after this long are forgotten; the
+/// resolver has given up on them by then.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most queries waiting for a reply on one upstream socket.
+const MAX_PENDING_QUERIES: usize = 16_384;
+
+pub struct DemuxConfig {
+    pub dns_listen_host: String,
+    pub dns_listen_port: u16,
+    /// Backend servers; the one at index N must run with `--server-id N`.
+    pub backends: Vec<HostPort>,
+    pub domains: Vec<String>,
+    pub service_label: Option<String>,
+    /// Seconds between health probes; 0 treats every backend as up.
+    pub health_interval_seconds: u64,
+    /// Upstream sockets unused for this long are closed.
+    pub session_idle_timeout_seconds: u64,
+    /// Most upstream sockets open at once, one per resolver address and
+    /// backend; queries needing another are dropped.
+    pub max_sessions: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Route {
+    /// The connection ID carries the ID of the backend that issued it.
+    ServerId(u8),
+    /// The client picked the connection ID; hash it onto a backend.
+    Hash(u64),
+}
+
+/// Where the QUIC packet at the start of `payload` should go, or `None`
+/// when it is too short to carry a connection ID.
+pub(crate) fn route_for_payload(payload: &[u8]) -> Option<Route> {
+    let first = *payload.first()?;
+    let cid_len = LB_CID_LENGTH as usize;
+    if first & 0x80 == 0 {
+        // Short header: the connection ID follows the first byte.
+        let dcid = payload.get(1..1 + cid_len)?;
+        return Some(Route::ServerId(dcid[1]));
+    }
+    let version = u32::from_be_bytes(payload.get(1..5)?.try_into().ok()?);
+    let dcid_len = *payload.get(5)? as usize;
+    let dcid = payload.get(6..6 + dcid_len)?;
+    let packet_type = (first >> 4) & 0x03;
+    let client_chosen = match version {
+        QUIC_V1 => packet_type <= 1,
+        QUIC_V2 => packet_type == 1 || packet_type == 2,
+        _ => true,
+    };
+    if client_chosen || dcid.len() != cid_len {
+        return Some(Route::Hash(fnv1a(dcid)));
+    }
+    Some(Route::ServerId(dcid[1]))
+}
+
+/// Rendezvous hash of `key` over the backends that are up in `healthy`, or
+/// over all of them when none is. Taking a backend down only moves the keys
+/// that were on it.
+pub(crate) fn pick_backend(key: u64, healthy: &[bool]) -> usize {
+    let any_up = healthy.iter().any(|up| *up);
+    let mut best = 0;
+    let mut best_score = 0;
+    for (index, up) in healthy.iter().enumerate() {
+        if any_up && !up {
+            continue;
+        }
+        let score = mix64(key ^ (index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        if score >= best_score {
+            best = index;
+            best_score = score;
+        }
+    }
+    best
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn mix64(mut value: u64) -> u64 {
+    value ^= value >> 30;
+    value = value.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value ^= value >> 27;
+    value = value.wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// DNS ID and question of a query, which its reply repeats. The question is
+/// lowercased since resolvers may randomise the case of the name.
+pub(crate) type TransactionKey = (u16, Vec<u8>);
+
+/// The [`TransactionKey`] of a DNS message, or `None` when its question
+/// cannot be read.
+pub(crate) fn transaction_key(packet: &[u8]) -> Option<TransactionKey> {
+    if packet.len() < 12 {
+        return None;
+    }
+    let id = u16::from_be_bytes([packet[0], packet[1]]);
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    if qdcount == 0 {
+        return Some((id, Vec::new()));
+    }
+    let mut offset = 12;
+    loop {
+        let len = usize::from(*packet.get(offset)?);
+        // The question comes first, so its name has nothing to point back to.
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        offset += 1 + len;
+        if len == 0 {
+            break;
+        }
+    }
+    let question = packet.get(12..offset + 4)?;
+    Some((id, question.to_ascii_lowercase()))
+}
+
+/// Source ports of the queries one upstream socket forwarded, by
+/// [`TransactionKey`], until the reply comes back or [`QUERY_TIMEOUT`].
+#[derive(Debug, Default)]
+pub(crate) struct PendingQueries {
+    ports: HashMap<TransactionKey, (u16, Instant)>,
+}
+
+impl PendingQueries {
+    /// Records the port a query came from; false when too many are waiting.
+    /// A retransmission from another port takes over the entry.
+    pub(crate) fn insert(&mut self, key: TransactionKey, port: u16, now: Instant) -> bool {
+        if self.ports.len() >= MAX_PENDING_QUERIES && !self.ports.contains_key(&key) {
+            return false;
+        }
+        self.ports.insert(key, (port, now));
+        true
+    }
+
+    /// Port the reply with `key` goes back to.
+    pub(crate) fn take(&mut self, key: &TransactionKey) -> Option<u16> {
+        self.ports.remove(key).map(|(port, _)| port)
+    }
+
+    pub(crate) fn expire(&mut self, now: Instant) {
+        self.ports
+            .retain(|_, (_, sent_at)| now.duration_since(*sent_at) < QUERY_TIMEOUT);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.ports.len()
+    }
+}
+
+fn peer_key(peer: SocketAddr) -> u64 {
+    match peer.ip() {
+        IpAddr::V4(ip) => fnv1a(&ip.octets()),
+        IpAddr::V6(ip) => fnv1a(&ip.octets()),
+    }
+}
+
+/// Up/down state of one backend from its health probes.
+#[derive(Debug)]
+pub(crate) struct BackendHealth {
+    up: bool,
+    missed: u32,
+}
+
+impl BackendHealth {
+    pub(crate) fn new() -> Self {
+        Self {
+            up: true,
+            missed: 0,
+        }
+    }
+
+    pub(crate) fn is_up(&self) -> bool {
+        self.up
+    }
+
+    /// Records a probe result; true when the backend changed state.
+    pub(crate) fn record(&mut self, answered: bool) -> bool {
+        let was_up = self.up;
+        if answered {
+            self.missed = 0;
+            self.up = true;
+        } else {
+            self.missed = self.missed.saturating_add(1);
+            if self.missed >= UNHEALTHY_AFTER_MISSES {
+                self.up = false;
+            }
+        }
+        was_up != self.up
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct DemuxCounters {
+    queries: u64,
+    by_server_id: u64,
+    by_hash: u64,
+    /// Connection IDs naming a server ID with no backend.
+    unknown_server_id: u64,
+    /// DNS queries that are not tunnel queries; any backend answers them.
+    non_tunnel: u64,
+    dropped: u64,
+    sessions_rejected: u64,
+    /// Queries dropped because too many on their socket await a reply.
+    pending_rejected: u64,
+}
+
+struct Backend {
+    addr: SocketAddr,
+    up: Arc<AtomicBool>,
+    forwarded: u64,
+    replies: Arc<ReplyCounters>,
+}
+
+#[derive(Default)]
+struct ReplyCounters {
+    responses: AtomicU64,
+    /// Replies matching no query still waiting for one.
+    unmatched: AtomicU64,
+}
+
+struct DemuxSession {
+    socket: Arc<TokioUdpSocket>,
+    pending: Arc<Mutex<PendingQueries>>,
+    last_seen: Arc<Mutex<Instant>>,
+    shutdown_tx: watch::Sender<bool>,
+    reply_task: JoinHandle<()>,
+}
+
+pub(crate) struct Demux<'a> {
+    main_socket: Arc<TokioUdpSocket>,
+    domains: Vec<&'a str>,
+    service_label: Option<&'a str>,
+    backends: Vec<Backend>,
+    /// Snapshot of the backends' up flags, refreshed by `housekeeping`.
+    healthy: Vec<bool>,
+    sessions: HashMap<(IpAddr, usize), DemuxSession>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    counters: DemuxCounters,
+    reported: DemuxCounters,
+    last_report: Instant,
+}
+
+impl<'a> Demux<'a> {
+    pub(crate) fn new(
+        config: &'a DemuxConfig,
+        main_socket: Arc<TokioUdpSocket>,
+        backend_addrs: Vec<SocketAddr>,
+    ) -> Self {
+        let backends: Vec<Backend> = backend_addrs
+            .into_iter()
+            .map(|addr| Backend {
+                addr,
+                up: Arc::new(AtomicBool::new(true)),
+                forwarded: 0,
+                replies: Arc::new(ReplyCounters::default()),
+            })
+            .collect();
+        Self {
+            main_socket,
+            domains: config.domains.iter().map(String::as_str).collect(),
+            service_label: config.service_label.as_deref(),
+            healthy: vec![true; backends.len()],
+            backends,
+            sessions: HashMap::new(),
+            idle_timeout: Duration::from_secs(config.session_idle_timeout_seconds.max(1)),
+            max_sessions: config.max_sessions,
+            counters: DemuxCounters::default(),
+            reported: DemuxCounters::default(),
+            last_report: Instant::now(),
+        }
+    }
+
+    pub(crate) async fn handle_query(&mut self, packet: &[u8], peer: SocketAddr) {
+        self.counters.queries = self.counters.queries.saturating_add(1);
+        let Some(backend) = self.choose_backend(packet, peer) else {
+            self.counters.dropped = self.counters.dropped.saturating_add(1);
+            return;
+        };
+        let Some(key) = transaction_key(packet) else {
+            self.counters.dropped = self.counters.dropped.saturating_add(1);
+            return;
+        };
+        let Some(session) = self.ensure_session(peer.ip(), backend).await else {
+            self.counters.dropped = self.counters.dropped.saturating_add(1);
+            return;
+        };
+        let recorded = session
+            .pending
+            .lock()
+            .map(|mut pending| pending.insert(key, peer.port(), Instant::now()))
+            .unwrap_or(false);
+        if !recorded {
+            self.counters.pending_rejected = self.counters.pending_rejected.saturating_add(1);
+            self.counters.dropped = self.counters.dropped.saturating_add(1);
+            return;
+        }
+        let socket = session.socket.clone();
+        match socket.send(packet).await {
+            Ok(_) => {
+                let forwarded = &mut self.backends[backend].forwarded;
+                *forwarded = forwarded.saturating_add(1);
+            }
+            Err(err) => {
+                if !is_transient_udp_error(&err) {
+                    warn!(
+                        "demux write to backend {} for {} failed: {}",
+                        self.backends[backend].addr, peer, err
+                    );
+                }
+            }
+        }
+    }
+
+    fn choose_backend(&mut self, packet: &[u8], peer: SocketAddr) -> Option<usize> {
+        let counters = &mut self.counters;
+        match decode_query_with_label(packet, &self.domains, self.service_label) {
+            Ok(query) => match route_for_payload(&query.payload)? {
+                Route::ServerId(id) if usize::from(id) < self.backends.len() => {
+                    counters.by_server_id = counters.by_server_id.saturating_add(1);
+                    Some(usize::from(id))
+                }
+                Route::ServerId(id) => {
+                    counters.unknown_server_id = counters.unknown_server_id.saturating_add(1);
+                    Some(pick_backend(u64::from(id), &self.healthy))
+                }
+                Route::Hash(key) => {
+                    counters.by_hash = counters.by_hash.saturating_add(1);
+                    Some(pick_backend(key, &self.healthy))
+                }
+            },
+            // The backends answer these with the same error reply.
+            Err(DecodeQueryError::Reply { .. }) => {
+                counters.non_tunnel = counters.non_tunnel.saturating_add(1);
+                Some(pick_backend(peer_key(peer), &self.healthy))
+            }
+            Err(DecodeQueryError::Drop) => None,
+        }
+    }
+
+    async fn ensure_session(&mut self, peer: IpAddr, backend: usize) -> Option<&DemuxSession> {
+        let key = (peer, backend);
+        if self
+            .sessions
+            .get(&key)
+            .is_some_and(|session| session.reply_task.is_finished())
+        {
+            self.sessions.remove(&key);
+        }
+        if !self.sessions.contains_key(&key) {
+            if self.sessions.len() >= self.max_sessions {
+                self.counters.sessions_rejected = self.counters.sessions_rejected.saturating_add(1);
+                return None;
+            }
+            if let Err(err) = self.create_session(peer, backend).await {
+                warn!(
+                    "failed to create demux session for {} to backend {}: {}",
+                    peer, self.backends[backend].addr, err
+                );
+                return None;
+            }
+        }
+        let session = self.sessions.get(&key)?;
+        if let Ok(mut last_seen) = session.last_seen.lock() {
+            *last_seen = Instant::now();
+        }
+        Some(session)
+    }
+
+    async fn create_session(&mut self, peer: IpAddr, backend: usize) -> Result<(), ServerError> {
+        let backend_addr = self.backends[backend].addr;
+        let socket = TokioUdpSocket::bind(unspecified_addr(backend_addr))
+            .await
+            .map_err(map_io)?;
+        socket.connect(backend_addr).await.map_err(map_io)?;
+        let socket = Arc::new(socket);
+        let pending = Arc::new(Mutex::new(PendingQueries::default()));
+        let last_seen = Arc::new(Mutex::new(Instant::now()));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let reply_task = tokio::spawn(relay_backend_replies(
+            socket.clone(),
+            self.main_socket.clone(),
+            peer,
+            pending.clone(),
+            last_seen.clone(),
+            self.backends[backend].replies.clone(),
+            shutdown_rx,
+        ));
+        self.sessions.insert(
+            (peer, backend),
+            DemuxSession {
+                socket,
+                pending,
+                last_seen,
+                shutdown_tx,
+                reply_task,
+            },
+        );
+        debug!("created demux session for {} to backend {}", peer, backend);
+        Ok(())
+    }
+
+    /// Picks up health changes, forgets unanswered queries, closes idle
+    /// sessions and logs the counters once a minute while they move.
+    pub(crate) fn housekeeping(&mut self, now: Instant) {
+        for (healthy, backend) in self.healthy.iter_mut().zip(&self.backends) {
+            *healthy = backend.up.load(Ordering::Relaxed);
+        }
+        let idle_timeout = self.idle_timeout;
+        self.sessions.retain(|_, session| {
+            if let Ok(mut pending) = session.pending.lock() {
+                pending.expire(now);
+            }
+            let idle = session
+                .last_seen
+                .lock()
+                .map(|last_seen| now.duration_since(*last_seen) > idle_timeout)
+                .unwrap_or(true);
+            if idle {
+                let _ = session.shutdown_tx.send(true);
+            }
+            !idle
+        });
+        if now.duration_since(self.last_report) < STATS_REPORT_INTERVAL {
+            return;
+        }
+        self.last_report = now;
+        if self.counters == self.reported {
+            return;
+        }
+        self.reported = self.counters;
+        let backends = self
+            .backends
+            .iter()
+            .enumerate()
+            .map(|(index, backend)| {
+                format!(
+                    "{}={} {} forwarded={} responses={} unmatched={}",
+                    index,
+                    backend.addr,
+                    if self.healthy[index] { "up" } else { "down" },
+                    backend.forwarded,
+                    backend.replies.responses.load(Ordering::Relaxed),
+                    backend.replies.unmatched.load(Ordering::Relaxed)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let pending: usize = self
+            .sessions
+            .values()
+            .filter_map(|session| session.pending.lock().ok().map(|pending| pending.len()))
+            .sum();
+        let counters = &self.counters;
+        info!(
+            "Demux stats: queries={} by_server_id={} by_hash={} unknown_server_id={} non_tunnel={} dropped={} sessions={} sessions_rejected={} pending={} pending_rejected={} backends: {}",
+            counters.queries,
+            counters.by_server_id,
+            counters.by_hash,
+            counters.unknown_server_id,
+            counters.non_tunnel,
+            counters.dropped,
+            self.sessions.len(),
+            counters.sessions_rejected,
+            pending,
+            counters.pending_rejected,
+            backends
+        );
+    }
+}
+
+fn unspecified_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    }
+}
+
+/// Returns each reply from the backend to the port its query came from.
+async fn relay_backend_replies(
+    upstream: Arc<TokioUdpSocket>,
+    main_socket: Arc<TokioUdpSocket>,
+    peer: IpAddr,
+    pending: Arc<Mutex<PendingQueries>>,
+    last_seen: Arc<Mutex<Instant>>,
+    replies: Arc<ReplyCounters>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+    loop {
+        tokio::select! {
+            recv = upstream.recv(&mut buf) => {
+                match recv {
+                    Ok(size) => {
+                        if let Ok(mut last_seen) = last_seen.lock() {
+                            *last_seen = Instant::now();
+                        }
+                        replies.responses.fetch_add(1, Ordering::Relaxed);
+                        let port = transaction_key(&buf[..size]).and_then(|key| {
+                            pending.lock().ok().and_then(|mut pending| pending.take(&key))
+                        });
+                        let Some(port) = port else {
+                            replies.unmatched.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        };
+                        let peer = SocketAddr::new(peer, port);
+                        if let Err(err) = main_socket.send_to(&buf[..size], peer).await {
+                            if !is_transient_udp_error(&err) {
+                                warn!("demux write to {} failed: {}", peer, err);
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        if is_transient_udp_error(&err) {
+                            continue;
+                        }
+                        warn!("demux read for {} failed: {}", peer, err);
+                        break;
+                    }
+                }
+            }
+            changed = shutdown_rx.changed() => {
+                if changed.is_err() || *shutdown_rx.borrow() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Sends a DNS query to the backend every `interval` and records whether
+/// any reply with the same ID came back before the next one is due. The
+/// query is an A lookup of `health.<domain>`, which a server answers with
+/// an error reply.
+async fn probe_backend(
+    index: usize,
+    addr: SocketAddr,
+    qname: String,
+    interval: Duration,
+    up: Arc<AtomicBool>,
+) {
+    let socket = match TokioUdpSocket::bind(unspecified_addr(addr)).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            warn!("Health checks for backend {} disabled: {}", index, err);
+            return;
+        }
+    };
+    if let Err(err) = socket.connect(addr).await {
+        warn!("Health checks for backend {} disabled: {}", index, err);
+        return;
+    }
+    let mut health = BackendHealth::new();
+    let mut id: u16 = 0;
+    let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+    loop {
+        id = id.wrapping_add(1);
+        let deadline = tokio::time::Instant::now() + interval;
+        let answered = match encode_query(&QueryParams {
+            id,
+            qname: &qname,
+            qtype: RR_A,
+            qclass: CLASS_IN,
+            rd: false,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        }) {
+            Ok(query) if socket.send(&query).await.is_ok() => loop {
+                match tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+                    Ok(Ok(size)) if size >= 2 && buf[..2] == id.to_be_bytes() => break true,
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(err)) if is_transient_udp_error(&err) => continue,
+                    Ok(Err(_)) | Err(_) => break false,
+                }
+            },
+            _ => false,
+        };
+        if health.record(answered) {
+            up.store(health.is_up(), Ordering::Relaxed);
+            if health.is_up() {
+                info!("Backend {} ({}) is answering again", index, addr);
+            } else {
+                warn!(
+                    "Backend {} ({}) marked down after {} unanswered probes",
+                    index, addr, UNHEALTHY_AFTER_MISSES
+                );
+            }
+        }
+        tokio::time::sleep_until(deadline).await;
+    }
+}
+
+pub async fn run_demux(config: &DemuxConfig) -> Result<(), ServerError> {
+    if config.backends.is_empty() {
+        return Err(ServerError::new("At least one backend is required"));
+    }
+    if config.backends.len() > usize::from(u8::MAX) + 1 {
+        return Err(ServerError::new("At most 256 backends are supported"));
+    }
+    if config.domains.is_empty() {
+        return Err(ServerError::new("At least one domain must be configured"));
+    }
+    if let Some(label) = config.service_label.as_deref() {
+        validate_service_label(label)
+            .map_err(|err| ServerError::new(format!("Invalid service label: {}", err)))?;
+    }
+    let backend_addrs = config
+        .backends
+        .iter()
+        .map(|backend| resolve_host_port(backend).map_err(|err| ServerError::new(err.to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let socket = Arc::new(bind_udp_socket(&config.dns_listen_host, config.dns_listen_port).await?);
+    let local_addr = socket.local_addr().map_err(map_io)?;
+    let mut demux = Demux::new(config, socket.clone(), backend_addrs.clone());
+    info!(
+        "slipstream-demux listening on {} with {} backends",
+        local_addr,
+        backend_addrs.len()
+    );
+    for (index, addr) in backend_addrs.iter().enumerate() {
+        info!("Backend {} is {}", index, addr);
+        if config.health_interval_seconds > 0 {
+            tokio::spawn(probe_backend(
+                index,
+                *addr,
+                format!("health.{}.", config.domains[0].trim_end_matches('.')),
+                Duration::from_secs(config.health_interval_seconds),
+                demux.backends[index].up.clone(),
+            ));
+        }
+    }
+
+    let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+    let mut housekeeping = tokio::time::interval(HOUSEKEEPING_INTERVAL);
+    loop {
+        tokio::select! {
+            recv = socket.recv_from(&mut buf) => {
+                match recv {
+                    Ok((size, peer)) => demux.handle_query(&buf[..size], peer).await,
+                    Err(err) if is_transient_udp_error(&err) => {}
+                    Err(err) => return Err(map_io(err)),
+                }
+            }
+            _ = housekeeping.tick() => demux.housekeeping(Instant::now()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slipstream_dns::{build_qname, RR_TXT};
+
+    fn short_header(server_id: u8) -> Vec<u8> {
+        let mut packet = vec![0x40, 0x3a, server_id, 1, 2, 3, 4, 5, 6];
+        packet.extend_from_slice(&[0u8; 24]);
+        packet
+    }
+
+    fn long_header(first: u8, version: u32, dcid: &[u8]) -> Vec<u8> {
+        let mut packet = vec![first];
+        packet.extend_from_slice(&version.to_be_bytes());
+        packet.push(dcid.len() as u8);
+        packet.extend_from_slice(dcid);
+        packet.extend_from_slice(&[0u8; 24]);
+        packet
+    }
+
+    #[test]
+    fn routes_on_the_server_id_unless_the_client_chose_the_cid() {
+        assert_eq!(
+            route_for_payload(&short_header(3)),
+            Some(Route::ServerId(3))
+        );
+        let server_cid = [0x11, 7, 0, 0, 0, 0, 0, 0];
+        // Handshake packets carry the connection ID the server issued.
+        assert_eq!(
+            route_for_payload(&long_header(0xe0, QUIC_V1, &server_cid)),
+            Some(Route::ServerId(7))
+        );
+        assert_eq!(
+            route_for_payload(&long_header(0xf0, QUIC_V2, &server_cid)),
+            Some(Route::ServerId(7))
+        );
+
+        let client_cid = [9, 8, 7, 6, 5, 4, 3, 2];
+        let initial = route_for_payload(&long_header(0xc0, QUIC_V1, &client_cid));
+        assert_eq!(initial, Some(Route::Hash(fnv1a(&client_cid))));
+        assert_eq!(
+            route_for_payload(&long_header(0xd0, QUIC_V2, &client_cid)),
+            initial
+        );
+        // 0-RTT also uses the client's connection ID.
+        assert_eq!(
+            route_for_payload(&long_header(0xd0, QUIC_V1, &client_cid)),
+            initial
+        );
+
+        assert_eq!(route_for_payload(&[0x40, 1, 2]), None);
+        assert_eq!(route_for_payload(&[0xc0, 0, 0, 0, 1, 8, 1]), None);
+        assert_eq!(route_for_payload(&[]), None);
+    }
+
+    #[test]
+    fn rendezvous_hash_only_moves_keys_off_a_down_backend() {
+        let all_up = [true, true, true, true];
+        let three_up = [true, true, false, true];
+        let mut counts = [0usize; 4];
+        for key in 0..4000u64 {
+            let before = pick_backend(fnv1a(&key.to_be_bytes()), &all_up);
+            let after = pick_backend(fnv1a(&key.to_be_bytes()), &three_up);
+            counts[before] += 1;
+            assert_ne!(after, 2);
+            if before != 2 {
+                assert_eq!(before, after);
+            }
+        }
+        assert!(counts.iter().all(|count| *count > 800), "{:?}", counts);
+        // With nothing up every backend is a candidate again.
+        assert!(pick_backend(42, &[false, false]) < 2);
+    }
+
+    #[test]
+    fn backend_goes_down_after_missed_probes_and_back_on_an_answer() {
+        let mut health = BackendHealth::new();
+        assert!(health.is_up());
+        for _ in 1..UNHEALTHY_AFTER_MISSES {
+            assert!(!health.record(false));
+        }
+        assert!(health.record(false));
+        assert!(!health.is_up());
+        assert!(!health.record(false));
+        assert!(health.record(true));
+        assert!(health.is_up());
+    }
+
+    #[tokio::test]
+    async fn forwards_to_the_named_backend_and_relays_the_reply() {
+        let backend_a = TokioUdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_b = TokioUdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let main_socket = Arc::new(TokioUdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let main_addr = main_socket.local_addr().unwrap();
+        let client = TokioUdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = DemuxConfig {
+            dns_listen_host: "127.0.0.1".to_string(),
+            dns_listen_port: 0,
+            backends: Vec::new(),
+            domains: vec!["example.com".to_string()],
+            service_label: None,
+            health_interval_seconds: 0,
+            session_idle_timeout_seconds: 60,
+            max_sessions: 16,
+        };
+        let mut demux = Demux::new(
+            &config,
+            main_socket.clone(),
+            vec![
+                backend_a.local_addr().unwrap(),
+                backend_b.local_addr().unwrap(),
+            ],
+        );
+
+        // Two queries from the same resolver on different source ports.
+        let (first, first_query) = (client, query(99, 1));
+        let second = TokioUdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second_query = query(100, 1);
+        demux
+            .handle_query(&first_query, first.local_addr().unwrap())
+            .await;
+        demux
+            .handle_query(&second_query, second.local_addr().unwrap())
+            .await;
+
+        let mut buf = [0u8; 512];
+        let mut upstream = None;
+        for expected in [&first_query, &second_query] {
+            let (size, from) =
+                tokio::time::timeout(Duration::from_secs(1), backend_b.recv_from(&mut buf))
+                    .await
+                    .expect("backend 1 should get the query")
+                    .unwrap();
+            assert_eq!(&buf[..size], &expected[..]);
+            assert!(upstream.is_none_or(|upstream| upstream == from));
+            upstream = Some(from);
+        }
+        let upstream = upstream.unwrap();
+        // Replies go back to the port their query came from, in any order.
+        for (client, query) in [(&second, &second_query), (&first, &first_query)] {
+            let reply = reply_to(query);
+            backend_b.send_to(&reply, upstream).await.unwrap();
+            let (size, from) =
+                tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                    .await
+                    .expect("client should get the reply")
+                    .unwrap();
+            assert_eq!(&buf[..size], &reply[..]);
+            assert_eq!(from, main_addr);
+        }
+        // A second reply to the same query has nowhere to go.
+        backend_b
+            .send_to(&reply_to(&first_query), upstream)
+            .await
+            .unwrap();
+        let replies = demux.backends[1].replies.clone();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while replies.unmatched.load(Ordering::Relaxed) == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the extra reply should be counted as unmatched");
+
+        assert_eq!(demux.counters.by_server_id, 2);
+        assert_eq!(demux.backends[1].forwarded, 2);
+        assert_eq!(replies.responses.load(Ordering::Relaxed), 3);
+        assert_eq!(demux.sessions.len(), 1);
+    }
+
+    fn query(id: u16, server_id: u8) -> Vec<u8> {
+        let qname = build_qname(&short_header(server_id), "example.com").unwrap();
+        encode_query(&QueryParams {
+            id,
+            qname: &qname,
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+            rd: true,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        })
+        .unwrap()
+    }
+
+    fn reply_to(query: &[u8]) -> Vec<u8> {
+        let mut reply = query.to_vec();
+        reply[2] |= 0x80;
+        reply
+    }
+
+    #[test]
+    fn transaction_key_ignores_the_case_of_the_question() {
+        let query = query(7, 2);
+        let (id, question) = transaction_key(&query).unwrap();
+        assert_eq!(id, 7);
+        let question_end = 12 + question.len();
+        let mut upper = query.clone();
+        upper[12..question_end].make_ascii_uppercase();
+        assert_ne!(upper, query);
+        assert_eq!(transaction_key(&upper), Some((id, question)));
+        assert_eq!(transaction_key(&reply_to(&query)), transaction_key(&query));
+        assert_eq!(transaction_key(&query[..question_end - 1]), None);
+        assert_eq!(transaction_key(&query[..11]), None);
+    }
+
+    #[test]
+    fn pending_queries_expire_and_follow_retransmissions() {
+        let now = Instant::now();
+        let mut pending = PendingQueries::default();
+        let key = transaction_key(&query(1, 0)).unwrap();
+        assert!(pending.insert(key.clone(), 4000, now));
+        assert!(pending.insert(key.clone(), 4001, now));
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending.take(&key), Some(4001));
+        assert_eq!(pending.take(&key), None);
+
+        assert!(pending.insert(key.clone(), 4000, now));
+        pending.expire(now + QUERY_TIMEOUT - Duration::from_millis(1));
+        assert_eq!(pending.len(), 1);
+        pending.expire(now + QUERY_TIMEOUT);
+        assert_eq!(pending.len(), 0);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DrainStatus {
+    /// Data is still queued for targets and the deadline has not passed.
+    Draining,
+    /// Nothing is left to deliver to targets.
+    Flushed,
+    /// The deadline passed with `backlog_bytes` still queued.
+    Expired { backlog_bytes: u64 },
+}
+
+/// Graceful shutdown after SIGTERM (`--shutdown-drain`).
+///
+/// While draining, new connections are refused and the loop keeps running
+/// so stream data already received from clients reaches the targets. The
+/// drain ends once nothing is queued for a target or the deadline passes,
+/// whichever comes first; the caller then closes every connection. A
+/// control socket `close-connection` runs the same drain over one
+/// connection's backlog.
+pub(crate) struct ShutdownDrain {
+    started_at: Instant,
+    deadline: Instant,
+}
+
+impl ShutdownDrain {
+    pub(crate) fn start(timeout: Duration, now: Instant) -> Self {
+        Self {
+            started_at: now,
+            deadline: now + timeout,
+        }
+    }
+
+    pub(crate) fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.started_at)
+    }
+
+    /// `backlog_bytes` is the stream data still queued for targets.
+    pub(crate) fn poll(&self, backlog_bytes: u64, now: Instant) -> DrainStatus {
+        if backlog_bytes == 0 {
+            DrainStatus::Flushed
+        } else if now >= self.deadline {
+            DrainStatus::Expired { backlog_bytes }
+        } else {
+            DrainStatus::Draining
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_ends_when_flushed_or_at_the_deadline() {
+        let now = Instant::now();
+        let drain = ShutdownDrain::start(Duration::from_secs(5), now);
+        assert_eq!(drain.poll(1200, now), DrainStatus::Draining);
+        assert_eq!(
+            drain.poll(1200, now + Duration::from_secs(4)),
+            DrainStatus::Draining
+        );
+        assert_eq!(
+            drain.poll(0, now + Duration::from_secs(1)),
+            DrainStatus::Flushed
+        );
+        assert_eq!(
+            drain.poll(300, now + Duration::from_secs(5)),
+            DrainStatus::Expired { backlog_bytes: 300 }
+        );
+        assert_eq!(
+            drain.elapsed(now + Duration::from_millis(1500)),
+            Duration::from_millis(1500)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Stream lifecycle callbacks for audit logging, metrics or access checks.
+//!
+//! A hook is set through [`ServerConfig::lifecycle_hook`] and is called on the
+//! server loop thread, so implementations should return quickly and hand any
+//! slow work off elsewhere.
+//!
+//! [`ServerConfig::lifecycle_hook`]: crate::server::ServerConfig::lifecycle_hook
+
+use std::net::SocketAddr;
+use tracing::info;
+
+pub trait StreamLifecycleHook: Send + Sync {
+    /// A client opened a stream; the target connection starts right after.
+    fn on_stream_open(&self, cnx_id: usize, stream_id: u64, target_addr: SocketAddr);
+
+    /// The server dropped its state for a stream, for whatever reason.
+    /// `tx_bytes` went to the client and `rx_bytes` came from it.
+    fn on_stream_close(
+        &self,
+        cnx_id: usize,
+        stream_id: u64,
+        tx_bytes: u64,
+        rx_bytes: u64,
+        duration_us: u64,
+    );
+
+    /// The stream is being torn down because of an error; `on_stream_close`
+    /// follows.
+    fn on_stream_error(&self, cnx_id: usize, stream_id: u64, reason: &str);
+}
+
+/// Logs every lifecycle event at info level (`--log-stream-lifecycle`).
+#[derive(Debug, Default)]
+pub struct DefaultLogHook;
+
+impl StreamLifecycleHook for DefaultLogHook {
+    fn on_stream_open(&self, cnx_id: usize, stream_id: u64, target_addr: SocketAddr) {
+        info!(
+            "stream open cnx={:#x} stream={} target={}",
+            cnx_id, stream_id, target_addr
+        );
+    }
+
+    fn on_stream_close(
+        &self,
+        cnx_id: usize,
+        stream_id: u64,
+        tx_bytes: u64,
+        rx_bytes: u64,
+        duration_us: u64,
+    ) {
+        info!(
+            "stream close cnx={:#x} stream={} tx_bytes={} rx_bytes={} duration_ms={}",
+            cnx_id,
+            stream_id,
+            tx_bytes,
+            rx_bytes,
+            duration_us / 1_000
+        );
+    }
+
+    fn on_stream_error(&self, cnx_id: usize, stream_id: u64, reason: &str) {
+        info!(
+            "stream error cnx={:#x} stream={} reason={}",
+            cnx_id, stream_id, reason
+        );
+    }
+}
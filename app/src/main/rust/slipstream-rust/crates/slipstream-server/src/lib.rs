@@ -0,0 +1,20 @@
+//! The slipstream server as a library, so other crates can run it with their
+//! own [`hooks::StreamLifecycleHook`].
+
+mod amplification;
+mod config;
+mod control;
+pub mod demux;
+mod drain;
+pub mod hooks;
+pub mod server;
+mod source_filter;
+mod streams;
+mod summary;
+mod target;
+mod udp_fallback;
+mod validate;
+
+/// Forced picoquic failures for the server loop, see `docs/build.md`.
+#[cfg(feature = "fault-injection")]
+pub use streams::fault_injection;
@@ -1,14 +1,14 @@
-mod config;
-mod server;
-mod streams;
-mod target;
-mod udp_fallback;
-
 use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser};
-use server::{run_server, ServerConfig};
 use slipstream_core::{
     normalize_domain, parse_host_port, parse_host_port_parts, sip003, AddressKind, HostPort,
 };
+use slipstream_dns::validate_service_label;
+use slipstream_server::hooks::{DefaultLogHook, StreamLifecycleHook};
+use slipstream_server::server::{
+    run_server, run_server_validate, validate_send_coalesce, validate_target_read_chunk,
+    ServerConfig,
+};
+use std::sync::Arc;
 use tokio::runtime::Builder;
 use tracing_subscriber::EnvFilter;
 
@@ -29,6 +29,12 @@ struct Args {
         value_parser = parse_target_address
     )]
     target_address: HostPort,
+    #[arg(
+        long = "target-resolve-ttl",
+        value_name = "SECONDS",
+        default_value_t = 60
+    )]
+    target_resolve_ttl: u64,
     #[arg(long = "fallback", value_name = "HOST:PORT", value_parser = parse_fallback_address)]
     fallback: Option<HostPort>,
     #[arg(long = "cert", short = 'c', value_name = "PATH")]
@@ -47,6 +53,62 @@ struct Args {
     debug_streams: bool,
     #[arg(long = "debug-commands")]
     debug_commands: bool,
+    #[arg(long = "debug-stream-checksums")]
+    debug_stream_checksums: bool,
+    #[arg(long = "validate-source-addr")]
+    validate_source_addr: bool,
+    #[arg(long = "amplification-limit", default_value_t = 0)]
+    amplification_limit: u32,
+    #[arg(long = "amplification-retry")]
+    amplification_retry: bool,
+    #[arg(long = "shutdown-drain", value_name = "SECONDS", default_value_t = 0)]
+    shutdown_drain: u64,
+    #[arg(long = "backpressure-high-watermark", default_value_t = 0)]
+    backpressure_high_watermark: usize,
+    #[arg(long = "backpressure-low-watermark", default_value_t = 0)]
+    backpressure_low_watermark: usize,
+    #[arg(long = "stream-buffer-null-retries", default_value_t = 3)]
+    stream_buffer_null_retries: u32,
+    #[arg(long = "pending-stream-max-bytes", default_value_t = 0)]
+    pending_stream_max_bytes: usize,
+    #[arg(long = "pending-conn-max-bytes", default_value_t = 0)]
+    pending_conn_max_bytes: usize,
+    #[arg(long = "log-stream-lifecycle")]
+    log_stream_lifecycle: bool,
+    #[arg(long = "stream-summaries")]
+    stream_summaries: bool,
+    #[arg(long = "stream-summary-file", value_name = "PATH")]
+    stream_summary_file: Option<String>,
+    #[arg(long = "stream-summary-max-bytes", default_value_t = 64 * 1024 * 1024)]
+    stream_summary_max_bytes: u64,
+    #[arg(long = "qlog-dir", value_name = "DIR")]
+    qlog_dir: Option<String>,
+    #[arg(long = "service-label", value_name = "LABEL", value_parser = parse_service_label)]
+    service_label: Option<String>,
+    #[arg(long = "server-id", value_name = "N")]
+    server_id: Option<u8>,
+    #[arg(long = "stop-sending-half-close")]
+    stop_sending_half_close: bool,
+    #[arg(
+        long = "target-read-chunk",
+        value_name = "BYTES",
+        default_value_t = 4096,
+        value_parser = parse_target_read_chunk
+    )]
+    target_read_chunk: usize,
+    #[arg(
+        long = "send-coalesce",
+        value_name = "BYTES",
+        default_value_t = 0,
+        value_parser = parse_send_coalesce
+    )]
+    send_coalesce: usize,
+    #[arg(long = "debug-stream-trailers")]
+    debug_stream_trailers: bool,
+    #[arg(long = "control-socket", value_name = "PATH")]
+    control_socket: Option<String>,
+    #[arg(long = "validate-only")]
+    validate_only: bool,
 }
 
 fn main() {
@@ -165,6 +227,7 @@ fn main() {
         dns_listen_host,
         dns_listen_port,
         target_address,
+        target_resolve_ttl_seconds: args.target_resolve_ttl,
         fallback_address,
         cert,
         key,
@@ -174,6 +237,30 @@ fn main() {
         idle_timeout_seconds: args.idle_timeout_seconds,
         debug_streams: args.debug_streams,
         debug_commands: args.debug_commands,
+        debug_stream_checksums: args.debug_stream_checksums,
+        validate_source_addr: args.validate_source_addr,
+        amplification_limit: args.amplification_limit,
+        amplification_retry: args.amplification_retry,
+        shutdown_drain_seconds: args.shutdown_drain,
+        backpressure_high_watermark_bytes: args.backpressure_high_watermark,
+        backpressure_low_watermark_bytes: args.backpressure_low_watermark,
+        stream_buffer_null_retries: args.stream_buffer_null_retries,
+        pending_stream_max_bytes: args.pending_stream_max_bytes,
+        pending_conn_max_bytes: args.pending_conn_max_bytes,
+        lifecycle_hook: args
+            .log_stream_lifecycle
+            .then(|| Arc::new(DefaultLogHook) as Arc<dyn StreamLifecycleHook>),
+        stream_summaries: args.stream_summaries,
+        stream_summary_file: args.stream_summary_file,
+        stream_summary_max_bytes: args.stream_summary_max_bytes,
+        qlog_dir: args.qlog_dir,
+        service_label: args.service_label,
+        server_id: args.server_id,
+        stop_sending_half_close: args.stop_sending_half_close,
+        target_read_chunk_bytes: args.target_read_chunk,
+        send_coalesce_bytes: args.send_coalesce,
+        debug_stream_trailers: args.debug_stream_trailers,
+        control_socket: args.control_socket,
     };
 
     let runtime = Builder::new_current_thread()
@@ -181,6 +268,18 @@ fn main() {
         .enable_time()
         .build()
         .expect("Failed to build Tokio runtime");
+    if args.validate_only {
+        match runtime.block_on(run_server_validate(&config)) {
+            Ok(report) => {
+                println!("{}", report);
+                std::process::exit(report.exit_code());
+            }
+            Err(err) => {
+                tracing::error!("Server error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
     match runtime.block_on(run_server(&config)) {
         Ok(code) => std::process::exit(code),
         Err(err) => {
@@ -203,6 +302,29 @@ fn parse_domain(input: &str) -> Result<String, String> {
     normalize_domain(input).map_err(|err| err.to_string())
 }
 
+fn parse_service_label(input: &str) -> Result<String, String> {
+    validate_service_label(input).map_err(|err| err.to_string())?;
+    Ok(input.to_ascii_lowercase())
+}
+
+fn parse_target_read_chunk(input: &str) -> Result<usize, String> {
+    let trimmed = input.trim();
+    let value = trimmed
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid target-read-chunk value: {}", trimmed))?;
+    validate_target_read_chunk(value)?;
+    Ok(value)
+}
+
+fn parse_send_coalesce(input: &str) -> Result<usize, String> {
+    let trimmed = input.trim();
+    let value = trimmed
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid send-coalesce value: {}", trimmed))?;
+    validate_send_coalesce(value)?;
+    Ok(value)
+}
+
 fn parse_target_address(input: &str) -> Result<HostPort, String> {
     parse_host_port(input, 5201, AddressKind::Target).map_err(|err| err.to_string())
 }
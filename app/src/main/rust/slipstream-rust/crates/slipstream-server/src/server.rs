@@ -1,17 +1,31 @@
-use crate::config::{ensure_cert_key, load_or_create_reset_seed, ResetSeed};
+use crate::amplification::{is_validated_cnx, AmplificationLimiter};
+use crate::config::{cert_fingerprint, ensure_cert_key, load_or_create_reset_seed, ResetSeed};
+use crate::control::ControlSocket;
+use crate::drain::{DrainStatus, ShutdownDrain};
+use crate::hooks::StreamLifecycleHook;
+use crate::source_filter::SourceFilter;
+use crate::summary::StreamSummaryLog;
+use crate::target::TargetResolver;
 use crate::udp_fallback::{handle_packet, FallbackManager, PacketContext, MAX_UDP_PACKET_SIZE};
+pub use crate::validate::run_server_validate;
 use slipstream_core::{
-    net::is_transient_udp_error, normalize_dual_stack_addr, resolve_host_port, HostPort,
+    callback_state::CallbackState, config_summary::ConfigSummary,
+    flow_control::BackpressureWatermarks, net::is_transient_udp_error, normalize_dual_stack_addr,
+    resolve_host_port, stream_trailer::STREAM_TRAILER_ALPN, AddressFamily, HostPort,
+};
+use slipstream_dns::{
+    build_tunnel_response, validate_service_label, Question, Rcode, ResponseOptions, ResponseParams,
 };
-use slipstream_dns::{encode_response, Question, Rcode, ResponseParams};
 use slipstream_ffi::picoquic::{
-    picoquic_cnx_t, picoquic_create, picoquic_current_time, picoquic_delete_cnx,
-    picoquic_get_first_cnx, picoquic_get_next_cnx, picoquic_prepare_packet_ex, picoquic_quic_t,
-    slipstream_has_ready_stream, slipstream_is_flow_blocked, slipstream_server_cc_algorithm,
-    PICOQUIC_MAX_PACKET_SIZE, PICOQUIC_PACKET_LOOP_RECV_MAX,
+    picoquic_adjust_max_connections, picoquic_cnx_t, picoquic_create, picoquic_current_time,
+    picoquic_delete_cnx, picoquic_get_first_cnx, picoquic_get_next_cnx, picoquic_prepare_packet_ex,
+    picoquic_quic_t, picoquic_set_alpn_select_fn, ptls_iovec_t, slipstream_has_ready_stream,
+    slipstream_is_flow_blocked, slipstream_server_cc_algorithm, PICOQUIC_MAX_PACKET_SIZE,
+    PICOQUIC_PACKET_LOOP_RECV_MAX,
 };
 use slipstream_ffi::{
-    configure_quic_with_custom, socket_addr_to_storage, take_crypto_errors, QuicGuard,
+    configure_quic_with_custom, set_lb_server_id, set_log_dir, set_retry_required,
+    socket_addr_to_storage, take_crypto_errors, QuicGuard,
 };
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use std::collections::HashMap;
@@ -27,18 +41,23 @@ use tokio::sync::mpsc;
 use tokio::time::sleep;
 
 use crate::streams::{
-    drain_commands, handle_command, handle_shutdown, maybe_report_command_stats,
-    remove_connection_streams, server_callback, ServerState,
+    drain_commands, flush_sampled_logs, handle_command, handle_shutdown,
+    maybe_report_command_stats, maybe_report_stream_latency, remove_connection_streams,
+    retry_deferred_aborts, server_callback, PendingLimits, ServerState,
 };
 
 // Protocol defaults; see docs/config.md for details.
-const SLIPSTREAM_ALPN: &str = "picoquic_sample";
+pub(crate) const SLIPSTREAM_ALPN: &str = "picoquic_sample";
 const DNS_MAX_QUERY_SIZE: usize = 512;
 const IDLE_SLEEP_MS: u64 = 10;
 const IDLE_GC_INTERVAL: Duration = Duration::from_secs(1);
 // Default QUIC MTU for server packets; see docs/config.md for details.
 const QUIC_MTU: u32 = 900;
 pub(crate) const STREAM_READ_CHUNK_BYTES: usize = 4096;
+/// Largest accepted [`ServerConfig::target_read_chunk_bytes`].
+pub const TARGET_READ_CHUNK_MAX_BYTES: usize = 1024 * 1024;
+/// Largest accepted [`ServerConfig::send_coalesce_bytes`].
+pub const SEND_COALESCE_MAX_BYTES: usize = 1024 * 1024;
 pub(crate) const DEFAULT_TCP_RCVBUF_BYTES: usize = 256 * 1024;
 pub(crate) const TARGET_WRITE_COALESCE_DEFAULT_BYTES: usize = 256 * 1024;
 const FLOW_BLOCKED_LOG_INTERVAL_US: u64 = 1_000_000;
@@ -74,6 +93,9 @@ pub struct ServerConfig {
     pub dns_listen_host: String,
     pub dns_listen_port: u16,
     pub target_address: HostPort,
+    /// Re-resolve a hostname target after this many seconds; 0 only on
+    /// connect failure.
+    pub target_resolve_ttl_seconds: u64,
     pub fallback_address: Option<HostPort>,
     pub cert: String,
     pub key: String,
@@ -83,6 +105,81 @@ pub struct ServerConfig {
     pub idle_timeout_seconds: u64,
     pub debug_streams: bool,
     pub debug_commands: bool,
+    /// Log rolling checksums of each stream's payload (debug only).
+    pub debug_stream_checksums: bool,
+    pub validate_source_addr: bool,
+    /// Cap responses to sources without a validated connection at this
+    /// multiple of the bytes received from them; 0 disables the cap.
+    pub amplification_limit: u32,
+    /// While the amplification limit is clamping, answer new connections
+    /// with a Retry.
+    pub amplification_retry: bool,
+    /// After SIGTERM, keep delivering queued stream data to targets for up
+    /// to this long before closing; 0 closes at once.
+    pub shutdown_drain_seconds: u64,
+    pub backpressure_high_watermark_bytes: usize,
+    pub backpressure_low_watermark_bytes: usize,
+    pub stream_buffer_null_retries: u32,
+    pub pending_stream_max_bytes: usize,
+    pub pending_conn_max_bytes: usize,
+    /// Called on stream open, close and error; `None` skips the calls.
+    pub lifecycle_hook: Option<Arc<dyn StreamLifecycleHook>>,
+    /// Log one summary record per finished stream.
+    pub stream_summaries: bool,
+    /// Also append the summaries as JSON lines to this file.
+    pub stream_summary_file: Option<String>,
+    /// Rotate the summary file past this size; 0 never rotates.
+    pub stream_summary_max_bytes: u64,
+    /// Directory for picoquic connection logs; off when unset.
+    pub qlog_dir: Option<String>,
+    /// Label every query must carry right under the domain; queries
+    /// without it are refused.
+    pub service_label: Option<String>,
+    /// Encode this ID in every issued connection ID, for `slipstream-demux`.
+    pub server_id: Option<u8>,
+    /// Answer a client's STOP_SENDING by resetting only our send side and
+    /// keep writing what it sends to the target, instead of aborting the
+    /// stream in both directions.
+    pub stop_sending_half_close: bool,
+    /// Size of each read from a target, and so the largest chunk a stream
+    /// hands to QUIC at once.
+    pub target_read_chunk_bytes: usize,
+    /// Join target reads already queued on a stream until one QUIC send
+    /// carries this many bytes; 0 hands QUIC one read at a time.
+    pub send_coalesce_bytes: usize,
+    /// Accept clients that append CRC32 trailers to their streams, and
+    /// trail and check those streams too; only with `debug_streams`.
+    pub debug_stream_trailers: bool,
+    /// Unix socket that accepts operator commands such as
+    /// `close-connection`; off when unset.
+    pub control_socket: Option<String>,
+}
+
+/// Checks a target read chunk size is at least one byte and at most
+/// [`TARGET_READ_CHUNK_MAX_BYTES`].
+pub fn validate_target_read_chunk(bytes: usize) -> Result<(), String> {
+    if bytes == 0 {
+        return Err("target read chunk must be at least 1 byte".to_string());
+    }
+    if bytes > TARGET_READ_CHUNK_MAX_BYTES {
+        return Err(format!(
+            "target read chunk must be at most {} bytes",
+            TARGET_READ_CHUNK_MAX_BYTES
+        ));
+    }
+    Ok(())
+}
+
+/// Checks a send coalesce size is at most [`SEND_COALESCE_MAX_BYTES`];
+/// zero turns coalescing off.
+pub fn validate_send_coalesce(bytes: usize) -> Result<(), String> {
+    if bytes > SEND_COALESCE_MAX_BYTES {
+        return Err(format!(
+            "send coalesce must be at most {} bytes",
+            SEND_COALESCE_MAX_BYTES
+        ));
+    }
+    Ok(())
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -91,6 +188,70 @@ pub(crate) struct StreamKey {
     pub(crate) stream_id: u64,
 }
 
+/// Names a connection in logs as `cnx=1a2b3c4d peer=198.51.100.7:40000`.
+/// `cnx` is a short hash of the picoquic pointer, stable for the
+/// connection's lifetime; the peer is the client address picoquic reported
+/// when the connection opened its first stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ConnTag {
+    cnx: usize,
+    peer: Option<SocketAddr>,
+}
+
+impl ConnTag {
+    pub(crate) fn new(cnx: usize, peer: Option<SocketAddr>) -> Self {
+        Self { cnx, peer }
+    }
+
+    pub(crate) fn stream(self, stream_id: u64) -> StreamTag {
+        StreamTag {
+            conn: self,
+            stream_id,
+        }
+    }
+}
+
+/// The `cnx=` id of a connection in logs and on the control socket.
+pub(crate) fn conn_short_id(cnx: usize) -> u32 {
+    // Fibonacci hashing spreads the pointer's low, allocator-aligned bits
+    // over the 32 bits kept.
+    ((cnx as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as u32
+}
+
+impl fmt::Display for ConnTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cnx={:08x}", conn_short_id(self.cnx))?;
+        if let Some(peer) = self.peer {
+            write!(f, " peer={}", peer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Names a stream in logs as `stream 4 cnx=1a2b3c4d peer=...`, or
+/// `stream=4 cnx=...` with `{:#}` for key=value messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct StreamTag {
+    conn: ConnTag,
+    stream_id: u64,
+}
+
+impl StreamTag {
+    pub(crate) fn key(&self) -> StreamKey {
+        StreamKey {
+            cnx: self.conn.cnx,
+            stream_id: self.stream_id,
+        }
+    }
+}
+
+impl fmt::Display for StreamTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sep = if f.alternate() { "=" } else { " " };
+        write!(f, "stream{}{} {}", sep, self.stream_id, self.conn)
+    }
+}
+
 pub(crate) enum StreamWrite {
     Data(Vec<u8>),
     Fin,
@@ -130,6 +291,12 @@ pub(crate) enum Command {
         stream_id: u64,
         bytes: usize,
     },
+    /// The client's data failed its `--debug-stream-trailers` check.
+    StreamChecksumMismatch {
+        cnx_id: usize,
+        stream_id: u64,
+        detail: String,
+    },
 }
 
 pub(crate) struct Slot {
@@ -144,7 +311,160 @@ pub(crate) struct Slot {
     pub(crate) payload_override: Option<Vec<u8>>,
 }
 
+impl Slot {
+    /// Wrap this slot's answer, the prepared QUIC packet or an override, into
+    /// a DNS response.
+    fn encode_response(
+        &self,
+        quic_packet: &[u8],
+        opts: &ResponseOptions,
+    ) -> Result<Vec<u8>, ServerError> {
+        let (payload, rcode) = if let Some(payload) = self.payload_override.as_deref() {
+            (Some(payload), self.rcode)
+        } else if !quic_packet.is_empty() {
+            (Some(quic_packet), self.rcode)
+        } else if self.rcode.is_none() {
+            // No QUIC payload ready; still answer the poll with NOERROR and empty payload to clear it.
+            (None, Some(Rcode::Ok))
+        } else {
+            (None, self.rcode)
+        };
+        build_tunnel_response(
+            &ResponseParams {
+                id: self.id,
+                rd: self.rd,
+                cd: self.cd,
+                question: &self.question,
+                payload,
+                rcode,
+            },
+            opts,
+        )
+        .map_err(|err| ServerError::new(err.to_string()))
+    }
+
+    /// The same answer without any QUIC payload.
+    fn encode_empty_response(&self, opts: &ResponseOptions) -> Result<Vec<u8>, ServerError> {
+        build_tunnel_response(
+            &ResponseParams {
+                id: self.id,
+                rd: self.rd,
+                cd: self.cd,
+                question: &self.question,
+                payload: None,
+                rcode: Some(self.rcode.unwrap_or(Rcode::Ok)),
+            },
+            opts,
+        )
+        .map_err(|err| ServerError::new(err.to_string()))
+    }
+}
+
+/// Whether clients may negotiate stream trailers; like the client side they
+/// stay off without `debug_streams`.
+fn stream_trailers_enabled(config: &ServerConfig) -> bool {
+    config.debug_streams && config.debug_stream_trailers
+}
+
+/// Index of the ALPN to select from a client's offer: the trailer ALPN when
+/// offered, else the plain one.
+fn select_alpn(offered: &[&[u8]]) -> Option<usize> {
+    let find = |alpn: &str| offered.iter().position(|item| *item == alpn.as_bytes());
+    find(STREAM_TRAILER_ALPN).or_else(|| find(SLIPSTREAM_ALPN))
+}
+
+/// picoquic's ALPN callback for a server with stream trailers on; a return
+/// of `count` or more refuses the handshake.
+unsafe extern "C" fn select_alpn_callback(
+    _quic: *mut picoquic_quic_t,
+    list: *mut ptls_iovec_t,
+    count: libc::size_t,
+) -> libc::size_t {
+    if list.is_null() {
+        return count;
+    }
+    let offered: Vec<&[u8]> = std::slice::from_raw_parts(list, count)
+        .iter()
+        .map(|item| {
+            if item.base.is_null() {
+                &[][..]
+            } else {
+                std::slice::from_raw_parts(item.base, item.len)
+            }
+        })
+        .collect();
+    select_alpn(&offered).unwrap_or(count)
+}
+
+fn host_port_label(address: &HostPort) -> String {
+    match address.family {
+        AddressFamily::V4 => format!("{}:{}", address.host, address.port),
+        AddressFamily::V6 => format!("[{}]:{}", address.host, address.port),
+    }
+}
+
+/// Startup summary of `config`; `listen` is the bound DNS socket address.
+fn server_config_summary(
+    config: &ServerConfig,
+    listen: SocketAddr,
+    cert_digest: Option<Vec<u8>>,
+) -> ConfigSummary {
+    let mut summary = ConfigSummary::new("server");
+    summary
+        .list("domains", &config.domains)
+        .field(
+            "service_label",
+            config.service_label.as_deref().unwrap_or("none"),
+        )
+        .field(
+            "server_id",
+            config
+                .server_id
+                .map_or_else(|| "none".to_string(), |id| id.to_string()),
+        )
+        .field("listen", listen)
+        .field("target", host_port_label(&config.target_address))
+        .field("target_resolve_ttl_s", config.target_resolve_ttl_seconds)
+        .field(
+            "fallback",
+            config
+                .fallback_address
+                .as_ref()
+                .map_or_else(|| "none".to_string(), host_port_label),
+        )
+        .field("cert", &config.cert)
+        .fingerprint("cert_sha256", cert_digest.as_deref())
+        .field("key", &config.key)
+        .secret("reset_seed", config.reset_seed_path.is_some())
+        .field("mtu", QUIC_MTU)
+        .field("max_connections", config.max_connections)
+        .field("idle_timeout_s", config.idle_timeout_seconds)
+        .field("amplification_limit", config.amplification_limit)
+        .field("shutdown_drain_s", config.shutdown_drain_seconds)
+        .field("target_read_chunk", config.target_read_chunk_bytes)
+        .field("send_coalesce", config.send_coalesce_bytes)
+        .features(&[
+            ("validate_source_addr", config.validate_source_addr),
+            ("amplification_retry", config.amplification_retry),
+            ("lifecycle_hook", config.lifecycle_hook.is_some()),
+            (
+                "stream_summaries",
+                config.stream_summaries || config.stream_summary_file.is_some(),
+            ),
+            ("debug_streams", config.debug_streams),
+            ("debug_commands", config.debug_commands),
+            ("debug_stream_checksums", config.debug_stream_checksums),
+            ("debug_stream_trailers", stream_trailers_enabled(config)),
+            ("qlog", config.qlog_dir.is_some()),
+            ("stop_sending_half_close", config.stop_sending_half_close),
+            ("control_socket", config.control_socket.is_some()),
+        ]);
+    summary
+}
+
 pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
+    validate_target_read_chunk(config.target_read_chunk_bytes).map_err(ServerError::new)?;
+    validate_send_coalesce(config.send_coalesce_bytes).map_err(ServerError::new)?;
     let cert_path = Path::new(&config.cert);
     let key_path = Path::new(&config.key);
     let generated = ensure_cert_key(cert_path, key_path).map_err(ServerError::new)?;
@@ -174,8 +494,13 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
         None
     };
 
-    let target_addr = resolve_host_port(&config.target_address)
-        .map_err(|err| ServerError::new(err.to_string()))?;
+    let target = Arc::new(
+        TargetResolver::resolve(
+            &config.target_address,
+            Duration::from_secs(config.target_resolve_ttl_seconds),
+        )
+        .map_err(|err| ServerError::new(err.to_string()))?,
+    );
     let fallback_addr = match &config.fallback_address {
         Some(address) => {
             Some(resolve_host_port(address).map_err(|err| ServerError::new(err.to_string()))?)
@@ -193,14 +518,52 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     let debug_streams = config.debug_streams;
     let debug_commands = config.debug_commands;
     let idle_timeout = Duration::from_secs(config.idle_timeout_seconds);
-    let mut state = Box::new(ServerState::new(
-        target_addr,
+    // Declared before the QUIC context so picoquic is freed first.
+    let mut state = CallbackState::new(ServerState::new(
+        target.primary(),
         command_tx,
         debug_streams,
         debug_commands,
+        BackpressureWatermarks::new(
+            config.backpressure_high_watermark_bytes,
+            config.backpressure_low_watermark_bytes,
+        ),
+        config.stream_buffer_null_retries,
+        PendingLimits::new(
+            config.pending_stream_max_bytes,
+            config.pending_conn_max_bytes,
+        ),
     ));
-    let state_ptr: *mut ServerState = &mut *state;
-    let _state = state;
+    state.get_mut().set_target_resolver(Arc::clone(&target));
+    state
+        .get_mut()
+        .set_stream_checksums(config.debug_stream_checksums);
+    state
+        .get_mut()
+        .set_stop_sending_half_close(config.stop_sending_half_close);
+    state
+        .get_mut()
+        .set_target_read_chunk_bytes(config.target_read_chunk_bytes);
+    state
+        .get_mut()
+        .set_send_coalesce_bytes(config.send_coalesce_bytes);
+    let stream_trailers = stream_trailers_enabled(config);
+    state.get_mut().set_stream_trailers(stream_trailers);
+    if let Some(hook) = config.lifecycle_hook.as_ref() {
+        state.get_mut().set_lifecycle_hook(Arc::clone(hook));
+    }
+    if config.stream_summaries || config.stream_summary_file.is_some() {
+        let path = config.stream_summary_file.as_deref().map(Path::new);
+        let summaries =
+            StreamSummaryLog::new(path, config.stream_summary_max_bytes).map_err(|err| {
+                ServerError::new(format!(
+                    "Failed to open stream summary file {}: {}",
+                    config.stream_summary_file.as_deref().unwrap_or_default(),
+                    err
+                ))
+            })?;
+        state.get_mut().set_stream_summaries(summaries);
+    }
 
     let current_time = unsafe { picoquic_current_time() };
     let reset_seed_ptr = reset_seed
@@ -213,9 +576,14 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
             cert.as_ptr(),
             key.as_ptr(),
             std::ptr::null(),
-            alpn.as_ptr(),
+            // Without a default ALPN picoquic asks select_alpn_callback.
+            if stream_trailers {
+                std::ptr::null()
+            } else {
+                alpn.as_ptr()
+            },
             Some(server_callback),
-            state_ptr as *mut _,
+            state.callback_ctx(),
             None,
             std::ptr::null_mut(),
             reset_seed_ptr,
@@ -238,16 +606,32 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     }
     let _quic_guard = QuicGuard::new(quic);
     unsafe {
+        if stream_trailers {
+            picoquic_set_alpn_select_fn(quic, Some(select_alpn_callback));
+        }
         if slipstream_server_cc_algorithm.is_null() {
             return Err(ServerError::new(
                 "Slipstream server congestion algorithm is unavailable",
             ));
         }
         configure_quic_with_custom(quic, slipstream_server_cc_algorithm, QUIC_MTU);
+        if let Some(server_id) = config.server_id {
+            set_lb_server_id(quic, server_id).map_err(ServerError::new)?;
+        }
+        if let Some(dir) = config.qlog_dir.as_deref() {
+            set_log_dir(quic, dir).map_err(ServerError::new)?;
+            tracing::warn!(
+                "QUIC connection logs go to {}; they grow with traffic and hold connection details",
+                dir
+            );
+        }
     }
 
     let udp = Arc::new(bind_udp_socket(&config.dns_listen_host, config.dns_listen_port).await?);
     let udp_local_addr = udp.local_addr().map_err(map_io)?;
+    target
+        .refuse_loop(udp_local_addr)
+        .map_err(|err| ServerError::new(err.to_string()))?;
     let map_ipv4_peers = matches!(udp_local_addr, SocketAddr::V6(_));
     let local_addr_storage = socket_addr_to_storage(udp_local_addr);
     if let Some(addr) = fallback_addr {
@@ -266,11 +650,24 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     if domains.is_empty() {
         return Err(ServerError::new("At least one domain must be configured"));
     }
+    if let Some(label) = config.service_label.as_deref() {
+        validate_service_label(label)
+            .map_err(|err| ServerError::new(format!("Invalid service label: {}", err)))?;
+    }
+    tracing::info!(
+        "{}",
+        server_config_summary(config, udp_local_addr, cert_fingerprint(cert_path).ok())
+    );
 
     unsafe {
         let handler = handle_sigterm as *const () as libc::sighandler_t;
         libc::signal(libc::SIGTERM, handler);
     }
+    let mut control = config
+        .control_socket
+        .as_deref()
+        .map(|path| ControlSocket::bind(path, Duration::from_secs(config.shutdown_drain_seconds)))
+        .transpose()?;
 
     let recv_buf_len = if fallback_mgr.is_some() {
         MAX_UDP_PACKET_SIZE
@@ -279,18 +676,32 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     };
     let mut recv_buf = vec![0u8; recv_buf_len];
     let mut send_buf = vec![0u8; PICOQUIC_MAX_PACKET_SIZE];
+    let response_options = ResponseOptions::default();
+    let mut source_filter = config.validate_source_addr.then(SourceFilter::new);
+    let mut amplification = (config.amplification_limit > 0)
+        .then(|| AmplificationLimiter::new(config.amplification_limit));
+    let mut retry_required = false;
+    let mut shutdown_drain: Option<ShutdownDrain> = None;
     let mut last_seen = HashMap::new();
     let mut last_idle_gc = Instant::now();
     let mut last_flow_block_log_at: u64 = 0;
 
     loop {
-        drain_commands(state_ptr, &mut command_rx);
+        drain_commands(state.get_mut(), &mut command_rx);
 
-        if SHOULD_SHUTDOWN.load(Ordering::Relaxed) {
-            let state = unsafe { &mut *state_ptr };
-            if handle_shutdown(quic, state) {
-                break;
-            }
+        if SHOULD_SHUTDOWN.load(Ordering::Relaxed)
+            && drain_finished(
+                quic,
+                state.get(),
+                &mut shutdown_drain,
+                config.shutdown_drain_seconds,
+            )
+            && handle_shutdown(quic, state.get_mut())
+        {
+            break;
+        }
+        if let Some(control) = control.as_mut() {
+            control.poll(quic, state.get_mut());
         }
 
         let mut slots = Vec::new();
@@ -301,15 +712,19 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
         tokio::select! {
             command = command_rx.recv() => {
                 if let Some(command) = command {
-                    handle_command(state_ptr, command);
+                    handle_command(state.get_mut(), command);
                 }
             }
             recv = udp.recv_from(&mut recv_buf) => {
                 match recv {
                     Ok((size, peer)) => {
+                        if let Some(limiter) = amplification.as_mut() {
+                            limiter.record_received(peer, size, Instant::now());
+                        }
                         let loop_time = unsafe { picoquic_current_time() };
                         let context = PacketContext {
                             domains: &domains,
+                            service_label: config.service_label.as_deref(),
                             quic,
                             current_time: loop_time,
                             local_addr_storage: &local_addr_storage,
@@ -320,17 +735,22 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
                             peer,
                             &context,
                             &mut fallback_mgr,
+                            &mut source_filter,
                         )
                         .await?;
                         for _ in 1..PICOQUIC_PACKET_LOOP_RECV_MAX {
                             match udp.try_recv_from(&mut recv_buf) {
                                 Ok((size, peer)) => {
+                                    if let Some(limiter) = amplification.as_mut() {
+                                        limiter.record_received(peer, size, Instant::now());
+                                    }
                                     handle_packet(
                                         &mut slots,
                                         &recv_buf[..size],
                                         peer,
                                         &context,
                                         &mut fallback_mgr,
+                                        &mut source_filter,
                                     )
                                     .await?;
                                 }
@@ -360,7 +780,7 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
             note_active_connections(&mut last_seen, &slots, now);
             maybe_gc_idle_connections(
                 quic,
-                state_ptr,
+                state.get_mut(),
                 &mut last_seen,
                 idle_timeout,
                 &mut last_idle_gc,
@@ -368,8 +788,17 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
             );
         }
 
-        drain_commands(state_ptr, &mut command_rx);
-        maybe_report_command_stats(state_ptr);
+        if let Some(filter) = source_filter.as_mut() {
+            if filter.prune_due(now) {
+                filter.forget_closed(&collect_active_connections(quic));
+            }
+        }
+
+        drain_commands(state.get_mut(), &mut command_rx);
+        retry_deferred_aborts(state.get_mut());
+        flush_sampled_logs();
+        maybe_report_command_stats(state.get_mut());
+        maybe_report_stream_latency(state.get_mut());
 
         if slots.is_empty() {
             continue;
@@ -404,7 +833,7 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
 
                 if send_length == 0 {
                     let cnx_id = slot.cnx as usize;
-                    let metrics = unsafe { (&*state_ptr).stream_debug_metrics(cnx_id) };
+                    let metrics = state.get().stream_debug_metrics(cnx_id);
                     if metrics.streams_total > 0
                         && metrics.has_send_backlog()
                         && loop_time.saturating_sub(last_flow_block_log_at)
@@ -413,11 +842,10 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
                         let flow_blocked = unsafe { slipstream_is_flow_blocked(slot.cnx) != 0 };
                         let has_ready_stream =
                             unsafe { slipstream_has_ready_stream(slot.cnx) != 0 };
-                        let send_backlog =
-                            unsafe { (&*state_ptr).stream_send_backlog_summaries(cnx_id, 8) };
+                        let send_backlog = state.get().stream_send_backlog_summaries(cnx_id, 8);
                         tracing::warn!(
-                            "server connection stalled: cnx={} streams={} streams_with_write_tx={} streams_with_data_rx={} queued_bytes_total={} streams_with_pending_data={} pending_chunks_total={} pending_bytes_total={} streams_with_pending_fin={} streams_with_fin_enqueued={} streams_with_target_fin_pending={} streams_with_send_pending={} streams_with_send_stash={} send_stash_bytes_total={} streams_discarding={} streams_close_after_flush={} multi_stream={} flow_blocked={} has_ready_stream={} send_backlog={:?}",
-                            cnx_id,
+                            "server connection stalled: {} streams={} streams_with_write_tx={} streams_with_data_rx={} queued_bytes_total={} streams_with_pending_data={} pending_chunks_total={} pending_bytes_total={} streams_with_pending_fin={} streams_with_fin_enqueued={} streams_with_target_fin_pending={} streams_with_send_pending={} streams_with_send_stash={} send_stash_bytes_total={} streams_discarding={} streams_backpressured={} streams_pending_held={} pending_bytes_high_watermark={} abort_failures={} abort_retried={} abort_abandoned={} streams_close_after_flush={} multi_stream={} flow_blocked={} has_ready_stream={} send_backlog={:?}",
+                            state.get().conn_tag(cnx_id),
                             metrics.streams_total,
                             metrics.streams_with_write_tx,
                             metrics.streams_with_data_rx,
@@ -432,6 +860,12 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
                             metrics.streams_with_send_stash,
                             metrics.send_stash_bytes_total,
                             metrics.streams_discarding,
+                            metrics.streams_backpressured,
+                            metrics.streams_pending_held,
+                            metrics.pending_bytes_high_watermark,
+                            metrics.abort_failures,
+                            metrics.abort_retried,
+                            metrics.abort_abandoned,
                             metrics.streams_close_after_flush,
                             metrics.multi_stream,
                             flow_blocked,
@@ -443,26 +877,13 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
                 }
             }
 
-            let payload_override = slot.payload_override.as_deref();
-            let (payload, rcode) = if let Some(payload) = payload_override {
-                (Some(payload), slot.rcode)
-            } else if send_length > 0 {
-                (Some(&send_buf[..send_length]), slot.rcode)
-            } else if slot.rcode.is_none() {
-                // No QUIC payload ready; still answer the poll with NOERROR and empty payload to clear it.
-                (None, Some(slipstream_dns::Rcode::Ok))
-            } else {
-                (None, slot.rcode)
-            };
-            let response = encode_response(&ResponseParams {
-                id: slot.id,
-                rd: slot.rd,
-                cd: slot.cd,
-                question: &slot.question,
-                payload,
-                rcode,
-            })
-            .map_err(|err| ServerError::new(err.to_string()))?;
+            let mut response = slot.encode_response(&send_buf[..send_length], &response_options)?;
+            if let Some(limiter) = amplification.as_mut() {
+                match clamp_response(limiter, slot, response, &response_options)? {
+                    Some(clamped) => response = clamped,
+                    None => continue,
+                }
+            }
             let peer = if map_ipv4_peers {
                 normalize_dual_stack_addr(slot.peer)
             } else {
@@ -474,12 +895,112 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
                 }
             }
         }
+
+        if let Some(limiter) = amplification
+            .as_ref()
+            .filter(|_| config.amplification_retry)
+        {
+            let required = limiter.retry_required(Instant::now());
+            if required != retry_required {
+                unsafe { set_retry_required(quic, required) };
+                retry_required = required;
+                if required {
+                    tracing::warn!(
+                        "Amplification limit reached; requiring Retry for new connections"
+                    );
+                } else {
+                    tracing::info!("No responses clamped recently; no longer requiring Retry");
+                }
+            }
+        }
+    }
+
+    if let Some(filter) = &source_filter {
+        tracing::info!(
+            "Source validation discarded {} packets from unknown sources",
+            filter.unknown_source_packets()
+        );
+    }
+    if let Some(limiter) = &amplification {
+        tracing::info!(
+            "Amplification limit clamped {} responses to unvalidated sources",
+            limiter.clamped_responses()
+        );
     }
 
     Ok(0)
 }
 
-async fn bind_udp_socket(host: &str, port: u16) -> Result<TokioUdpSocket, ServerError> {
+/// Runs the `--shutdown-drain` state machine once SIGTERM was received;
+/// true once every connection may be closed.
+fn drain_finished(
+    quic: *mut picoquic_quic_t,
+    state: &ServerState,
+    shutdown_drain: &mut Option<ShutdownDrain>,
+    drain_seconds: u64,
+) -> bool {
+    if drain_seconds == 0 {
+        return true;
+    }
+    let now = Instant::now();
+    let backlog_bytes = state.target_backlog_bytes();
+    let drain = shutdown_drain.get_or_insert_with(|| {
+        // New connections fail their handshake with SERVER_BUSY.
+        unsafe { picoquic_adjust_max_connections(quic, 0) };
+        tracing::info!(
+            "Shutdown requested; draining for up to {}s (connections={} target_backlog_bytes={})",
+            drain_seconds,
+            collect_active_connections(quic).len(),
+            backlog_bytes
+        );
+        ShutdownDrain::start(Duration::from_secs(drain_seconds), now)
+    });
+    match drain.poll(backlog_bytes, now) {
+        DrainStatus::Draining => false,
+        DrainStatus::Flushed => {
+            tracing::info!(
+                "Drain complete after {}ms; closing connections",
+                drain.elapsed(now).as_millis()
+            );
+            true
+        }
+        DrainStatus::Expired { backlog_bytes } => {
+            tracing::warn!(
+                "Drain deadline reached with {} bytes still queued for targets; closing connections",
+                backlog_bytes
+            );
+            true
+        }
+    }
+}
+
+/// Applies the amplification limit to a response for an unvalidated source:
+/// one that does not fit is sent without its QUIC payload, or not at all.
+fn clamp_response(
+    limiter: &mut AmplificationLimiter,
+    slot: &Slot,
+    response: Vec<u8>,
+    opts: &ResponseOptions,
+) -> Result<Option<Vec<u8>>, ServerError> {
+    if is_validated_cnx(slot.cnx) {
+        return Ok(Some(response));
+    }
+    let remaining = limiter.remaining(slot.peer);
+    let response = if response.len() > remaining {
+        limiter.clamp(slot.peer, response.len(), Instant::now());
+        let empty = slot.encode_empty_response(opts)?;
+        if empty.len() > remaining {
+            return Ok(None);
+        }
+        empty
+    } else {
+        response
+    };
+    limiter.record_sent(slot.peer, response.len());
+    Ok(Some(response))
+}
+
+pub(crate) async fn bind_udp_socket(host: &str, port: u16) -> Result<TokioUdpSocket, ServerError> {
     let addrs: Vec<SocketAddr> = lookup_host((host, port)).await.map_err(map_io)?.collect();
     if addrs.is_empty() {
         return Err(ServerError::new(format!(
@@ -533,7 +1054,9 @@ fn note_active_connections(last_seen: &mut HashMap<usize, Instant>, slots: &[Slo
     }
 }
 
-fn collect_active_connections(quic: *mut picoquic_quic_t) -> HashMap<usize, *mut picoquic_cnx_t> {
+pub(crate) fn collect_active_connections(
+    quic: *mut picoquic_quic_t,
+) -> HashMap<usize, *mut picoquic_cnx_t> {
     let mut active = HashMap::new();
     let mut cnx = unsafe { picoquic_get_first_cnx(quic) };
     while !cnx.is_null() {
@@ -561,7 +1084,7 @@ fn prune_and_collect_idle<T>(
 
 fn maybe_gc_idle_connections(
     quic: *mut picoquic_quic_t,
-    state_ptr: *mut ServerState,
+    state: &mut ServerState,
     last_seen: &mut HashMap<usize, Instant>,
     idle_timeout: Duration,
     last_gc: &mut Instant,
@@ -593,14 +1116,14 @@ fn maybe_gc_idle_connections(
         return;
     }
 
-    let state = unsafe { &mut *state_ptr };
     for cnx_id in idle {
         if let Some(&cnx) = active.get(&cnx_id) {
+            let tag = state.conn_tag(cnx_id);
             remove_connection_streams(state, cnx_id);
             if let Some(last) = last_seen.get(&cnx_id) {
                 tracing::debug!(
-                    "idle gc: closing connection cnx_id={} idle_for_ms={}",
-                    cnx_id,
+                    "idle gc: closing connection {} idle_for_ms={}",
+                    tag,
                     now.duration_since(*last).as_millis()
                 );
             }
@@ -662,6 +1185,16 @@ fn is_label_suffix(domain: &str, suffix: &str) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn trailer_alpn_is_preferred_when_offered() {
+        let plain = SLIPSTREAM_ALPN.as_bytes();
+        let trailers = STREAM_TRAILER_ALPN.as_bytes();
+        assert_eq!(select_alpn(&[plain, trailers]), Some(1));
+        assert_eq!(select_alpn(&[b"h3", plain]), Some(1));
+        assert_eq!(select_alpn(&[b"h3"]), None);
+        assert_eq!(select_alpn(&[]), None);
+    }
+
     #[test]
     fn prune_and_collect_idle_prunes_and_collects() {
         let now = Instant::now();
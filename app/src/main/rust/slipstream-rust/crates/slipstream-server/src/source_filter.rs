@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const UNKNOWN_SOURCE_LOG_INTERVAL: Duration = Duration::from_secs(1);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+pub(crate) const QUIC_V1: u32 = 0x0000_0001;
+pub(crate) const QUIC_V2: u32 = 0x6b33_43cf;
+
+/// Opt-in source address validation (`--validate-source-addr`).
+///
+/// A source IP becomes known when a query from it carries a QUIC Initial
+/// that picoquic accepts as a connection. Ports are not compared, since
+/// resolvers send each query from a fresh one. Tunnel queries from any other
+/// IP are dropped without a reply, and so are error replies to them. An IP
+/// is forgotten once every connection its Initials opened has closed.
+pub(crate) struct SourceFilter {
+    /// Known source IPs and the connections they opened.
+    known: HashMap<IpAddr, Vec<usize>>,
+    unknown_source_packets: u64,
+    last_log_at: Option<Instant>,
+    last_prune_at: Option<Instant>,
+}
+
+impl SourceFilter {
+    pub(crate) fn new() -> Self {
+        Self {
+            known: HashMap::new(),
+            unknown_source_packets: 0,
+            last_log_at: None,
+            last_prune_at: None,
+        }
+    }
+
+    pub(crate) fn is_known(&self, peer: SocketAddr) -> bool {
+        self.known.contains_key(&peer.ip())
+    }
+
+    /// Notes that an Initial from `peer` opened the connection `cnx_id`.
+    pub(crate) fn learn(&mut self, peer: SocketAddr, cnx_id: usize) {
+        let connections = self.known.entry(peer.ip()).or_default();
+        if !connections.contains(&cnx_id) {
+            connections.push(cnx_id);
+        }
+    }
+
+    /// True at most once per second, when [`SourceFilter::forget_closed`]
+    /// should run.
+    pub(crate) fn prune_due(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_prune_at
+            .is_none_or(|last| now.duration_since(last) >= PRUNE_INTERVAL);
+        if due {
+            self.last_prune_at = Some(now);
+        }
+        due && !self.known.is_empty()
+    }
+
+    /// Forgets sources none of whose connections are in `active`.
+    pub(crate) fn forget_closed<T>(&mut self, active: &HashMap<usize, T>) {
+        self.known.retain(|_, connections| {
+            connections.retain(|cnx_id| active.contains_key(cnx_id));
+            !connections.is_empty()
+        });
+    }
+
+    /// Counts a discarded packet; warns at most once per second.
+    pub(crate) fn reject(&mut self, peer: SocketAddr, now: Instant) {
+        self.unknown_source_packets = self.unknown_source_packets.saturating_add(1);
+        let due = self
+            .last_log_at
+            .is_none_or(|last| now.duration_since(last) >= UNKNOWN_SOURCE_LOG_INTERVAL);
+        if due {
+            self.last_log_at = Some(now);
+            tracing::warn!(
+                "Discarding DNS packet from unknown source {} (unknown_source_packets={})",
+                peer,
+                self.unknown_source_packets
+            );
+        }
+    }
+
+    pub(crate) fn unknown_source_packets(&self) -> u64 {
+        self.unknown_source_packets
+    }
+}
+
+/// Whether a QUIC datagram starts with an Initial packet (v1 or v2).
+pub(crate) fn is_quic_initial(packet: &[u8]) -> bool {
+    if packet.len() < 5 || packet[0] & 0xc0 != 0xc0 {
+        return false;
+    }
+    let version = u32::from_be_bytes([packet[1], packet[2], packet[3], packet[4]]);
+    let packet_type = (packet[0] >> 4) & 0x03;
+    match version {
+        QUIC_V1 => packet_type == 0,
+        QUIC_V2 => packet_type == 1,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_initial_packets() {
+        assert!(is_quic_initial(&[0xc3, 0, 0, 0, 1, 8]));
+        assert!(is_quic_initial(&[0xd0, 0x6b, 0x33, 0x43, 0xcf, 8]));
+        // Handshake (v1), short header, version negotiation, truncated.
+        assert!(!is_quic_initial(&[0xe0, 0, 0, 0, 1, 8]));
+        assert!(!is_quic_initial(&[0x43, 0, 0, 0, 1, 8]));
+        assert!(!is_quic_initial(&[0xc0, 0, 0, 0, 0, 8]));
+        assert!(!is_quic_initial(&[0xc0, 0, 0]));
+    }
+
+    #[test]
+    fn rejects_are_counted() {
+        let peer: SocketAddr = "192.0.2.1:5353".parse().unwrap();
+        let mut filter = SourceFilter::new();
+        assert!(!filter.is_known(peer));
+        let now = Instant::now();
+        filter.reject(peer, now);
+        filter.reject(peer, now);
+        assert_eq!(filter.unknown_source_packets(), 2);
+        filter.learn(peer, 1);
+        assert!(filter.is_known(peer));
+    }
+
+    #[test]
+    fn rotating_ports_stay_known() {
+        let initial: SocketAddr = "192.0.2.1:40001".parse().unwrap();
+        let next_query: SocketAddr = "192.0.2.1:40002".parse().unwrap();
+        let other: SocketAddr = "192.0.2.2:40001".parse().unwrap();
+        let mut filter = SourceFilter::new();
+        filter.learn(initial, 1);
+        assert!(filter.is_known(next_query));
+        assert!(!filter.is_known(other));
+    }
+
+    #[test]
+    fn sources_are_forgotten_with_their_last_connection() {
+        let peer: SocketAddr = "192.0.2.1:5353".parse().unwrap();
+        let other: SocketAddr = "192.0.2.2:5353".parse().unwrap();
+        let mut filter = SourceFilter::new();
+        let now = Instant::now();
+        assert!(!filter.prune_due(now));
+        filter.learn(peer, 1);
+        filter.learn(peer, 2);
+        filter.learn(other, 3);
+        assert!(!filter.prune_due(now));
+        assert!(filter.prune_due(now + PRUNE_INTERVAL));
+
+        filter.forget_closed(&HashMap::from([(2usize, ()), (4, ())]));
+        assert!(filter.is_known(peer));
+        assert!(!filter.is_known(other));
+        filter.forget_closed(&HashMap::<usize, ()>::new());
+        assert!(!filter.is_known(peer));
+    }
+}
@@ -1,44 +1,149 @@
-use crate::server::{Command, StreamKey, StreamWrite};
-use crate::target::spawn_target_connector;
+use crate::hooks::StreamLifecycleHook;
+use crate::server::{
+    conn_short_id, Command, ConnTag, StreamKey, StreamTag, StreamWrite, STREAM_READ_CHUNK_BYTES,
+};
+use crate::summary::{StreamSummary, StreamSummaryLog};
+use crate::target::{spawn_target_connector, TargetResolver};
+use slipstream_core::callback_state::from_callback_ctx;
+use slipstream_core::clock::Clock;
 use slipstream_core::flow_control::{
-    conn_reserve_bytes, consume_error_log_message, consume_stream_data, handle_stream_receive,
-    overflow_log_message, promote_error_log_message, promote_streams, reserve_target_offset,
-    FlowControlState, HasFlowControlState, PromoteEntry, StreamReceiveConfig, StreamReceiveOps,
+    conn_reserve_bytes, consume_error_log_message, consume_stream_data, exceeds_fin_offset,
+    handle_stream_receive, overflow_log_message, promote_error_log_message, promote_streams,
+    promote_target_offset, reserve_target_offset, stream_queue_max_bytes, BackpressureWatermarks,
+    DrainCoalescer, FlowControlState, HasFlowControlState, PromoteEntry, StreamReceiveConfig,
+    StreamReceiveOps,
 };
 use slipstream_core::invariants::InvariantReporter;
+use slipstream_core::latency::{LatencyHistogram, LatencyPercentiles};
+use slipstream_core::log_sampling::{SampledLogger, DEFAULT_LOG_BURST};
+use slipstream_core::stream_trailer::STREAM_TRAILER_ALPN;
 #[cfg(test)]
 use slipstream_core::test_support::FailureCounter;
 use slipstream_ffi::picoquic::{
     picoquic_call_back_event_t, picoquic_close, picoquic_close_immediate, picoquic_cnx_t,
-    picoquic_current_time, picoquic_get_first_cnx, picoquic_get_next_cnx,
+    picoquic_get_first_cnx, picoquic_get_next_cnx, picoquic_get_path_addr,
     picoquic_mark_active_stream, picoquic_provide_stream_data_buffer, picoquic_quic_t,
     picoquic_reset_stream, picoquic_stop_sending, picoquic_stream_data_consumed,
+    picoquic_tls_get_negotiated_alpn, slipstream_get_key_updates,
+};
+use slipstream_ffi::{
+    abort_stream_bidi, sockaddr_storage_to_socket_addr, AbortError, AbortRetryOutcome,
+    DeferredAborts, PicoquicClock, SLIPSTREAM_ADMIN_CLOSE, SLIPSTREAM_CHECKSUM_ERROR,
+    SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_FINAL_SIZE_ERROR, SLIPSTREAM_INTERNAL_ERROR,
 };
-use slipstream_ffi::{abort_stream_bidi, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_INTERNAL_ERROR};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::CStr;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, watch};
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
 static INVARIANT_REPORTER: InvariantReporter = InvariantReporter::new(1_000_000);
+// Per-stream failures that a dead target or a lost client repeats for every
+// stream at once.
+static RESET_LOG: SampledLogger = SampledLogger::new("stream reset", DEFAULT_LOG_BURST);
+static TARGET_CONNECT_LOG: SampledLogger =
+    SampledLogger::new("target connect failed", DEFAULT_LOG_BURST);
+static TARGET_READ_LOG: SampledLogger = SampledLogger::new("target read error", DEFAULT_LOG_BURST);
+static TARGET_WRITE_LOG: SampledLogger =
+    SampledLogger::new("target write failed", DEFAULT_LOG_BURST);
+static PENDING_FLUSH_LOG: SampledLogger =
+    SampledLogger::new("pending flush failed", DEFAULT_LOG_BURST);
+static SAMPLED_LOGS: [&SampledLogger; 5] = [
+    &RESET_LOG,
+    &TARGET_CONNECT_LOG,
+    &TARGET_READ_LOG,
+    &TARGET_WRITE_LOG,
+    &PENDING_FLUSH_LOG,
+];
+const STREAM_LATENCY_REPORT_INTERVAL: Duration = Duration::from_secs(60);
 
 pub(crate) struct ServerState {
-    target_addr: SocketAddr,
+    target: Arc<TargetResolver>,
     streams: HashMap<StreamKey, ServerStream>,
     multi_streams: HashSet<usize>,
+    /// Client address of each connection with streams, for `ConnTag`.
+    conn_peers: HashMap<usize, SocketAddr>,
+    /// Live connections by their `cnx=` short id, for the control socket.
+    conn_ids: HashMap<u32, usize>,
+    /// Connections closing from the control socket; they take no new
+    /// streams.
+    closing_conns: HashSet<usize>,
     command_tx: mpsc::UnboundedSender<Command>,
     debug_streams: bool,
     debug_commands: bool,
+    /// `--debug-stream-checksums`: log rolling checksums of stream payload.
+    stream_checksums: bool,
+    /// `--debug-stream-trailers`: append and check CRC32 stream trailers on
+    /// connections that negotiated them.
+    stream_trailers: bool,
+    /// `--stop-sending-half-close`: STOP_SENDING resets only our send side.
+    stop_sending_half_close: bool,
+    /// `--target-read-chunk`: bytes per target read.
+    target_read_chunk_bytes: usize,
+    /// `--send-coalesce`: queued target reads joined per QUIC send, in
+    /// bytes; 0 sends one read at a time.
+    send_coalesce_bytes: usize,
+    backpressure: Option<BackpressureWatermarks>,
+    stream_buffer_null_retries: u32,
+    pending_limits: Option<PendingLimits>,
+    /// Largest per-connection pending_data total seen so far.
+    pending_bytes_high_watermark: usize,
+    lifecycle_hook: Option<Arc<dyn StreamLifecycleHook>>,
+    stream_summaries: Option<StreamSummaryLog>,
     command_counts: CommandCounts,
     last_command_report: Instant,
     last_mark_active_fail_log_at: u64,
+    /// Stream open to target connected, since the server started.
+    target_connect_latency: LatencyHistogram,
+    /// Stream open to the first target byte handed to QUIC.
+    first_byte_latency: LatencyHistogram,
+    last_latency_report: Instant,
+    /// Samples in both histograms at the last report.
+    latency_reported_samples: u64,
+    /// Refused aborts awaiting a retry, with counters since startup.
+    aborts: DeferredAborts,
+    /// Streams aborted for data past their FIN since startup.
+    fin_violations: u64,
+    /// QUIC key phase changes on connections closed since startup.
+    key_updates: u64,
+    /// When set, `abort_stream` records here instead of calling picoquic.
+    #[cfg(test)]
+    recorded_aborts: Option<Vec<(u64, u64)>>,
     #[cfg(test)]
     mark_active_stream_failures: FailureCounter,
 }
 
+/// Caps on data buffered for streams whose target has not connected yet.
+///
+/// A stream over either cap stops advancing its consumed offset, the same way
+/// backpressure does, until the target connects and the buffer is flushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PendingLimits {
+    pub(crate) stream_max_bytes: usize,
+    pub(crate) conn_max_bytes: usize,
+}
+
+impl PendingLimits {
+    /// Zero disables a cap; with both disabled there are no limits.
+    pub(crate) fn new(stream_max_bytes: usize, conn_max_bytes: usize) -> Option<Self> {
+        if stream_max_bytes == 0 && conn_max_bytes == 0 {
+            return None;
+        }
+        Some(Self {
+            stream_max_bytes,
+            conn_max_bytes,
+        })
+    }
+
+    fn exceeded(&self, stream_pending: usize, conn_pending: usize) -> bool {
+        (self.stream_max_bytes > 0 && stream_pending > self.stream_max_bytes)
+            || (self.conn_max_bytes > 0 && conn_pending > self.conn_max_bytes)
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct ServerStreamMetrics {
     pub(crate) streams_total: usize,
@@ -55,6 +160,14 @@ pub(crate) struct ServerStreamMetrics {
     pub(crate) streams_with_send_stash: usize,
     pub(crate) send_stash_bytes_total: u64,
     pub(crate) streams_discarding: usize,
+    pub(crate) streams_backpressured: usize,
+    pub(crate) streams_pending_held: usize,
+    pub(crate) pending_bytes_high_watermark: usize,
+    /// Refused aborts since startup, then deferred ones that went through
+    /// and ones given up on.
+    pub(crate) abort_failures: u64,
+    pub(crate) abort_retried: u64,
+    pub(crate) abort_abandoned: u64,
     pub(crate) streams_close_after_flush: usize,
     pub(crate) multi_stream: bool,
 }
@@ -87,25 +200,152 @@ impl ServerState {
         command_tx: mpsc::UnboundedSender<Command>,
         debug_streams: bool,
         debug_commands: bool,
+        backpressure: Option<BackpressureWatermarks>,
+        stream_buffer_null_retries: u32,
+        pending_limits: Option<PendingLimits>,
     ) -> Self {
         Self {
-            target_addr,
+            target: Arc::new(TargetResolver::fixed(target_addr)),
             streams: HashMap::new(),
             multi_streams: HashSet::new(),
+            conn_peers: HashMap::new(),
+            conn_ids: HashMap::new(),
+            closing_conns: HashSet::new(),
             command_tx,
             debug_streams,
             debug_commands,
+            stream_checksums: false,
+            stop_sending_half_close: false,
+            target_read_chunk_bytes: STREAM_READ_CHUNK_BYTES,
+            send_coalesce_bytes: 0,
+            stream_trailers: false,
+            backpressure,
+            stream_buffer_null_retries,
+            pending_limits,
+            pending_bytes_high_watermark: 0,
+            lifecycle_hook: None,
+            stream_summaries: None,
             command_counts: CommandCounts::default(),
             last_command_report: Instant::now(),
             last_mark_active_fail_log_at: 0,
+            target_connect_latency: LatencyHistogram::new(),
+            first_byte_latency: LatencyHistogram::new(),
+            last_latency_report: Instant::now(),
+            latency_reported_samples: 0,
+            aborts: DeferredAborts::new(),
+            fin_violations: 0,
+            key_updates: 0,
+            #[cfg(test)]
+            recorded_aborts: None,
             #[cfg(test)]
             mark_active_stream_failures: FailureCounter::new(),
         }
     }
 
+    pub(crate) fn conn_tag(&self, cnx: usize) -> ConnTag {
+        ConnTag::new(cnx, self.conn_peers.get(&cnx).copied())
+    }
+
+    pub(crate) fn stream_tag(&self, key: StreamKey) -> StreamTag {
+        self.conn_tag(key.cnx).stream(key.stream_id)
+    }
+
+    /// Adds a connection to the short id registry; called again for every
+    /// event that may be its first.
+    pub(crate) fn register_connection(&mut self, cnx: usize) {
+        self.conn_ids.entry(conn_short_id(cnx)).or_insert(cnx);
+    }
+
+    /// The live connection logged as `cnx=<short_id>`.
+    pub(crate) fn connection_by_short_id(&self, short_id: u32) -> Option<usize> {
+        self.conn_ids.get(&short_id).copied()
+    }
+
+    pub(crate) fn is_registered(&self, cnx: usize) -> bool {
+        self.conn_ids.get(&conn_short_id(cnx)) == Some(&cnx)
+    }
+
+    /// Starts refusing new streams on a connection the control socket is
+    /// closing; its existing streams keep their credit so they can flush.
+    pub(crate) fn refuse_new_streams(&mut self, cnx: usize) {
+        self.closing_conns.insert(cnx);
+    }
+
+    /// Whether the client stopped the stream's send side; target events for
+    /// that side have nowhere to go.
+    fn send_stopped(&self, key: StreamKey) -> bool {
+        self.streams
+            .get(&key)
+            .is_some_and(|stream| stream.send_stopped)
+    }
+
+    /// Target connect and first relayed byte percentiles, in that order.
+    pub(crate) fn stream_latency(&self) -> (LatencyPercentiles, LatencyPercentiles) {
+        (
+            self.target_connect_latency.percentiles(),
+            self.first_byte_latency.percentiles(),
+        )
+    }
+
+    pub(crate) fn set_lifecycle_hook(&mut self, hook: Arc<dyn StreamLifecycleHook>) {
+        self.lifecycle_hook = Some(hook);
+    }
+
+    /// Replaces the fixed target from `new`, e.g. with a hostname target.
+    pub(crate) fn set_target_resolver(&mut self, target: Arc<TargetResolver>) {
+        self.target = target;
+    }
+
+    pub(crate) fn set_stream_checksums(&mut self, enabled: bool) {
+        self.stream_checksums = enabled;
+    }
+
+    pub(crate) fn set_stream_trailers(&mut self, enabled: bool) {
+        self.stream_trailers = enabled;
+    }
+
+    pub(crate) fn set_stop_sending_half_close(&mut self, enabled: bool) {
+        self.stop_sending_half_close = enabled;
+    }
+
+    pub(crate) fn set_target_read_chunk_bytes(&mut self, bytes: usize) {
+        self.target_read_chunk_bytes = bytes;
+    }
+
+    pub(crate) fn set_send_coalesce_bytes(&mut self, bytes: usize) {
+        self.send_coalesce_bytes = bytes;
+    }
+
+    pub(crate) fn set_stream_summaries(&mut self, summaries: StreamSummaryLog) {
+        self.stream_summaries = Some(summaries);
+    }
+
+    /// Stream data received from clients that has not reached a target yet,
+    /// queued for a target writer or waiting for the target to connect.
+    pub(crate) fn target_backlog_bytes(&self) -> u64 {
+        self.streams
+            .values()
+            .map(|stream| stream.flow.queued_bytes as u64 + stream.pending_bytes as u64)
+            .sum()
+    }
+
+    /// [`target_backlog_bytes`](Self::target_backlog_bytes) for one
+    /// connection.
+    pub(crate) fn connection_backlog_bytes(&self, cnx: usize) -> u64 {
+        self.streams
+            .iter()
+            .filter(|(key, _)| key.cnx == cnx)
+            .map(|(_, stream)| stream.flow.queued_bytes as u64 + stream.pending_bytes as u64)
+            .sum()
+    }
+
     pub(crate) fn stream_debug_metrics(&self, cnx_id: usize) -> ServerStreamMetrics {
         let mut metrics = ServerStreamMetrics {
             multi_stream: self.multi_streams.contains(&cnx_id),
+            pending_bytes_high_watermark: self.pending_bytes_high_watermark,
+            abort_failures: self.aborts.failures(),
+            abort_retried: self.aborts.retried(),
+            abort_abandoned: self.aborts.abandoned(),
             ..ServerStreamMetrics::default()
         };
         for (key, stream) in self.streams.iter() {
@@ -127,13 +367,9 @@ impl ServerState {
                 metrics.pending_chunks_total = metrics
                     .pending_chunks_total
                     .saturating_add(stream.pending_data.len());
-                let pending_bytes: u64 = stream
-                    .pending_data
-                    .iter()
-                    .map(|chunk| chunk.len() as u64)
-                    .sum();
-                metrics.pending_bytes_total =
-                    metrics.pending_bytes_total.saturating_add(pending_bytes);
+                metrics.pending_bytes_total = metrics
+                    .pending_bytes_total
+                    .saturating_add(stream.pending_bytes as u64);
             }
             if stream.pending_fin {
                 metrics.streams_with_pending_fin =
@@ -165,6 +401,12 @@ impl ServerState {
             if stream.flow.discarding {
                 metrics.streams_discarding = metrics.streams_discarding.saturating_add(1);
             }
+            if stream.flow.backpressured {
+                metrics.streams_backpressured = metrics.streams_backpressured.saturating_add(1);
+            }
+            if stream.pending_held {
+                metrics.streams_pending_held = metrics.streams_pending_held.saturating_add(1);
+            }
             if stream.close_after_flush {
                 metrics.streams_close_after_flush =
                     metrics.streams_close_after_flush.saturating_add(1);
@@ -183,28 +425,9 @@ impl ServerState {
             if key.cnx != cnx_id {
                 continue;
             }
-            let send_pending = stream
-                .send_pending
-                .as_ref()
-                .map(|flag| flag.load(Ordering::SeqCst))
-                .unwrap_or(false);
-            let send_stash_bytes = stream
-                .send_stash
-                .as_ref()
-                .map(|data| data.len())
-                .unwrap_or(0);
-            if send_pending || send_stash_bytes > 0 || stream.target_fin_pending {
-                summaries.push(BacklogStreamSummary {
-                    stream_id: key.stream_id,
-                    send_pending,
-                    send_stash_bytes,
-                    target_fin_pending: stream.target_fin_pending,
-                    close_after_flush: stream.close_after_flush,
-                    pending_fin: stream.pending_fin,
-                    fin_enqueued: stream.fin_enqueued,
-                    queued_bytes: stream.flow.queued_bytes as u64,
-                    pending_chunks: stream.pending_data.len(),
-                });
+            let summary = stream.backlog_summary(key.stream_id);
+            if summary.send_pending || summary.send_stash_bytes > 0 || summary.target_fin_pending {
+                summaries.push(summary);
                 if summaries.len() >= limit {
                     break;
                 }
@@ -227,23 +450,92 @@ mod test_helpers {
     }
 }
 
+/// Forced picoquic failures for the server loop's error paths, used by this
+/// crate's tests and, with the `fault-injection` feature, by crates that
+/// embed the server.
+///
+/// Each `set_*` arms that many failures of one picoquic call: the next
+/// calls the loop makes report an error instead, until the count runs out.
+/// The counters are process-wide, so keep to one server loop per process
+/// while any are armed. A forced call never reaches picoquic, and neither
+/// does the abort or retry that would follow a real failure of it.
+#[cfg(any(test, feature = "fault-injection"))]
+pub mod fault_injection {
+    use slipstream_core::test_support::FailureCounter;
+
+    const FORCED_CONSUME_ERROR: i32 = -1;
+    static STREAM_DATA_CONSUMED_FAILS_LEFT: FailureCounter = FailureCounter::new();
+    static PROVIDE_BUFFER_FAILS_LEFT: FailureCounter = FailureCounter::new();
+
+    /// `picoquic_stream_data_consumed`, wherever the loop hands back
+    /// receive credit.
+    pub fn set_stream_data_consumed_failures(count: usize) {
+        STREAM_DATA_CONSUMED_FAILS_LEFT.set(count);
+    }
+
+    /// `picoquic_provide_stream_data_buffer` returning null in the
+    /// prepare-to-send callback.
+    pub fn set_provide_stream_data_buffer_failures(count: usize) {
+        PROVIDE_BUFFER_FAILS_LEFT.set(count);
+    }
+
+    pub(super) fn stream_data_consumed_failure() -> Option<i32> {
+        STREAM_DATA_CONSUMED_FAILS_LEFT
+            .take()
+            .then_some(FORCED_CONSUME_ERROR)
+    }
+
+    pub(super) fn provide_stream_data_buffer_failure() -> bool {
+        PROVIDE_BUFFER_FAILS_LEFT.take()
+    }
+}
+
+/// Without the feature every call goes to picoquic.
+#[cfg(not(any(test, feature = "fault-injection")))]
+mod fault_injection {
+    pub(super) fn stream_data_consumed_failure() -> Option<i32> {
+        None
+    }
+
+    pub(super) fn provide_stream_data_buffer_failure() -> bool {
+        false
+    }
+}
+
+/// `picoquic_stream_data_consumed`, unless a failure is being injected.
+fn stream_data_consumed(cnx: *mut picoquic_cnx_t, stream_id: u64, new_offset: u64) -> i32 {
+    if let Some(ret) = fault_injection::stream_data_consumed_failure() {
+        return ret;
+    }
+    unsafe { picoquic_stream_data_consumed(cnx, stream_id, new_offset) }
+}
+
 fn report_invariant<F>(message: F)
 where
     F: FnOnce() -> String,
 {
-    let now = unsafe { picoquic_current_time() };
-    INVARIANT_REPORTER.report(now, message, |msg| error!("{}", msg));
+    INVARIANT_REPORTER.report(&PicoquicClock, message, |msg| error!("{}", msg));
+}
+
+/// Peer misbehaviour goes through the invariant reporter for rate limiting,
+/// but must not panic the way our own broken invariants do in tests.
+fn report_peer_violation<F>(message: F)
+where
+    F: FnOnce() -> String,
+{
+    INVARIANT_REPORTER.report_peer(&PicoquicClock, message, |msg| warn!("{}", msg));
 }
 
 fn check_stream_invariants(state: &ServerState, key: StreamKey, context: &str) {
     let Some(stream) = state.streams.get(&key) else {
         return;
     };
+    let tag = state.stream_tag(key);
     if stream.close_after_flush && !stream.target_fin_pending {
         report_invariant(|| {
             format!(
-                "server invariant violated: close_after_flush without target_fin_pending stream={} context={} queued={} pending_fin={} fin_enqueued={} target_fin_pending={} close_after_flush={}",
-                key.stream_id,
+                "server invariant violated: close_after_flush without target_fin_pending {:#} context={} queued={} pending_fin={} fin_enqueued={} target_fin_pending={} close_after_flush={}",
+                tag,
                 context,
                 stream.flow.queued_bytes,
                 stream.pending_fin,
@@ -256,8 +548,8 @@ fn check_stream_invariants(state: &ServerState, key: StreamKey, context: &str) {
     if stream.pending_fin && stream.fin_enqueued {
         report_invariant(|| {
             format!(
-                "server invariant violated: pending_fin with fin_enqueued stream={} context={} queued={} pending_chunks={} target_fin_pending={} close_after_flush={}",
-                key.stream_id,
+                "server invariant violated: pending_fin with fin_enqueued {:#} context={} queued={} pending_chunks={} target_fin_pending={} close_after_flush={}",
+                tag,
                 context,
                 stream.flow.queued_bytes,
                 stream.pending_data.len(),
@@ -269,8 +561,8 @@ fn check_stream_invariants(state: &ServerState, key: StreamKey, context: &str) {
     if stream.write_tx.is_some() != stream.send_pending.is_some() {
         report_invariant(|| {
             format!(
-                "server invariant violated: write_tx/send_pending mismatch stream={} context={} write_tx={} send_pending={} data_rx={}",
-                key.stream_id,
+                "server invariant violated: write_tx/send_pending mismatch {:#} context={} write_tx={} send_pending={} data_rx={}",
+                tag,
                 context,
                 stream.write_tx.is_some(),
                 stream.send_pending.is_some(),
@@ -289,6 +581,7 @@ struct CommandCounts {
     stream_read_error: u64,
     stream_write_error: u64,
     stream_write_drained: u64,
+    stream_checksum_mismatch: u64,
 }
 
 impl CommandCounts {
@@ -301,6 +594,7 @@ impl CommandCounts {
             Command::StreamReadError { .. } => self.stream_read_error += 1,
             Command::StreamWriteError { .. } => self.stream_write_error += 1,
             Command::StreamWriteDrained { .. } => self.stream_write_drained += 1,
+            Command::StreamChecksumMismatch { .. } => self.stream_checksum_mismatch += 1,
         }
     }
 
@@ -312,6 +606,7 @@ impl CommandCounts {
             + self.stream_read_error
             + self.stream_write_error
             + self.stream_write_drained
+            + self.stream_checksum_mismatch
     }
 
     fn reset(&mut self) {
@@ -329,9 +624,24 @@ struct ServerStream {
     target_fin_pending: bool,
     close_after_flush: bool,
     pending_data: VecDeque<Vec<u8>>,
+    pending_bytes: usize,
+    /// Credit withheld because pending_data went over a PendingLimits cap.
+    pending_held: bool,
     pending_fin: bool,
     fin_enqueued: bool,
     flow: FlowControlState,
+    backpressure_applied_count: u64,
+    backpressure_released_count: u64,
+    /// Consecutive prepare-to-send callbacks that got a null data buffer.
+    buffer_null_retries: u32,
+    opened_at: Instant,
+    /// QUIC peer at open; only looked up when stream summaries are on.
+    peer: Option<SocketAddr>,
+    /// First error reported for the stream (see `report_stream_error`).
+    close_reason: Option<&'static str>,
+    /// The client sent STOP_SENDING and our send side was reset; the stream
+    /// lives on until the client's FIN reaches the target.
+    send_stopped: bool,
 }
 
 impl HasFlowControlState for ServerStream {
@@ -344,6 +654,78 @@ impl HasFlowControlState for ServerStream {
     }
 }
 
+impl ServerStream {
+    fn backlog_summary(&self, stream_id: u64) -> BacklogStreamSummary {
+        BacklogStreamSummary {
+            stream_id,
+            send_pending: self
+                .send_pending
+                .as_ref()
+                .map(|flag| flag.load(Ordering::SeqCst))
+                .unwrap_or(false),
+            send_stash_bytes: self.send_stash.as_ref().map(|data| data.len()).unwrap_or(0),
+            target_fin_pending: self.target_fin_pending,
+            close_after_flush: self.close_after_flush,
+            pending_fin: self.pending_fin,
+            fin_enqueued: self.fin_enqueued,
+            queued_bytes: self.flow.queued_bytes as u64,
+            pending_chunks: self.pending_data.len(),
+        }
+    }
+
+    /// Up to `length` bytes for a prepare-to-send callback: the stash left by
+    /// the previous callback first, then the next target read. Whatever does
+    /// not fit stays in the stash, so a target read larger than `length` goes
+    /// out over several callbacks. With a non-zero `coalesce_bytes`, reads
+    /// already queued are joined until the chunk holds that many bytes or
+    /// fills `length`, so small target writes share a send. A closed reader
+    /// marks the stream for FIN.
+    fn take_send_chunk(&mut self, length: usize, coalesce_bytes: usize) -> Option<Vec<u8>> {
+        let mut data = match self.send_stash.take() {
+            Some(stash) => stash,
+            None => self.next_target_read()?,
+        };
+        let coalesce_bytes = coalesce_bytes.min(length);
+        while data.len() < coalesce_bytes && self.data_rx.is_some() {
+            match self.next_target_read() {
+                Some(more) => data.extend_from_slice(&more),
+                None => break,
+            }
+        }
+        if data.len() > length {
+            self.send_stash = Some(data.split_off(length));
+        }
+        Some(data)
+    }
+
+    /// The next queued target read, if any.
+    fn next_target_read(&mut self) -> Option<Vec<u8>> {
+        match self.data_rx.as_mut()?.try_recv() {
+            Ok(data) => Some(data),
+            Err(mpsc::error::TryRecvError::Empty) => None,
+            Err(mpsc::error::TryRecvError::Disconnected) => {
+                self.data_rx = None;
+                self.target_fin_pending = true;
+                self.close_after_flush = true;
+                None
+            }
+        }
+    }
+
+    /// Counts `len` bytes handed to picoquic; true for the stream's first.
+    fn record_sent(&mut self, len: usize) -> bool {
+        let first = self.tx_bytes == 0 && len > 0;
+        self.tx_bytes = self.tx_bytes.saturating_add(len as u64);
+        first
+    }
+
+    /// Every byte up to the client's FIN was written to the target, which
+    /// is all a stream with a stopped send side waits for.
+    fn receive_finished(&self) -> bool {
+        self.fin_enqueued && self.flow.queued_bytes == 0
+    }
+}
+
 fn mark_multi_stream(state: &mut ServerState, cnx_id: usize) -> bool {
     if state.multi_streams.contains(&cnx_id) {
         return false;
@@ -369,7 +751,7 @@ pub(crate) unsafe extern "C" fn server_callback(
     if callback_ctx.is_null() {
         return 0;
     }
-    let state = &mut *(callback_ctx as *mut ServerState);
+    let state: &mut ServerState = from_callback_ctx(callback_ctx);
 
     match fin_or_event {
         picoquic_call_back_event_t::picoquic_callback_stream_data
@@ -385,6 +767,24 @@ pub(crate) unsafe extern "C" fn server_callback(
             };
             handle_stream_data(cnx, state, stream_id, fin, data);
         }
+        picoquic_call_back_event_t::picoquic_callback_almost_ready
+        | picoquic_call_back_event_t::picoquic_callback_ready => {
+            state.register_connection(cnx as usize);
+        }
+        picoquic_call_back_event_t::picoquic_callback_stop_sending
+            if state.stop_sending_half_close
+                && state.streams.contains_key(&StreamKey {
+                    cnx: cnx as usize,
+                    stream_id,
+                }) =>
+        {
+            let key = StreamKey {
+                cnx: cnx as usize,
+                stream_id,
+            };
+            stop_send_side(state, cnx, key);
+            check_stream_invariants(state, key, "stop_sending");
+        }
         picoquic_call_back_event_t::picoquic_callback_stream_reset
         | picoquic_call_back_event_t::picoquic_callback_stop_sending => {
             let reason = match fin_or_event {
@@ -396,33 +796,46 @@ pub(crate) unsafe extern "C" fn server_callback(
                 cnx: cnx as usize,
                 stream_id,
             };
-            if let Some(stream) = shutdown_stream(state, key) {
-                warn!(
-                    "stream {:?}: reset event={} tx_bytes={} rx_bytes={} consumed_offset={} queued={} pending_chunks={} pending_fin={} fin_enqueued={} fin_offset={:?} target_fin_pending={} close_after_flush={}",
-                    key.stream_id,
-                    reason,
-                    stream.tx_bytes,
-                    stream.flow.rx_bytes,
-                    stream.flow.consumed_offset,
-                    stream.flow.queued_bytes,
-                    stream.pending_data.len(),
-                    stream.pending_fin,
-                    stream.fin_enqueued,
-                    stream.flow.fin_offset,
-                    stream.target_fin_pending,
-                    stream.close_after_flush
-                );
-            } else {
-                warn!(
-                    "stream {:?}: reset event={} (unknown stream)",
-                    stream_id, reason
-                );
-            }
+            let tag = state.stream_tag(key);
+            report_stream_error(state, key, reason);
+            let stream = shutdown_stream(state, key);
+            RESET_LOG.log(
+                &PicoquicClock,
+                || match stream {
+                    Some(stream) => format!(
+                        "{}: reset event={} tx_bytes={} rx_bytes={} consumed_offset={} queued={} pending_chunks={} pending_fin={} fin_enqueued={} fin_offset={:?} target_fin_pending={} close_after_flush={} backpressure_applied={} backpressure_released={}",
+                        tag,
+                        reason,
+                        stream.tx_bytes,
+                        stream.flow.rx_bytes,
+                        stream.flow.consumed_offset,
+                        stream.flow.queued_bytes,
+                        stream.pending_data.len(),
+                        stream.pending_fin,
+                        stream.fin_enqueued,
+                        stream.flow.fin_offset,
+                        stream.target_fin_pending,
+                        stream.close_after_flush,
+                        stream.backpressure_applied_count,
+                        stream.backpressure_released_count
+                    ),
+                    None => format!("{}: reset event={} (unknown stream)", tag, reason),
+                },
+                |msg| warn!("{}", msg),
+            );
             let _ = picoquic_reset_stream(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR);
         }
         picoquic_call_back_event_t::picoquic_callback_close
         | picoquic_call_back_event_t::picoquic_callback_application_close
         | picoquic_call_back_event_t::picoquic_callback_stateless_reset => {
+            let key_updates = slipstream_get_key_updates(cnx);
+            if key_updates > 0 {
+                state.key_updates = state.key_updates.saturating_add(key_updates);
+                info!(
+                    "Connection closed after {} QUIC key updates (key_updates={})",
+                    key_updates, state.key_updates
+                );
+            }
             remove_connection_streams(state, cnx as usize);
             let _ = picoquic_close(cnx, 0);
         }
@@ -434,6 +847,7 @@ pub(crate) unsafe extern "C" fn server_callback(
                 cnx: cnx as usize,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
             let mut remove_stream = false;
             if let Some(stream) = state.streams.get_mut(&key) {
                 let pending_flag = stream
@@ -465,14 +879,12 @@ pub(crate) unsafe extern "C" fn server_callback(
                             let tx_bytes = stream.tx_bytes;
                             let target_fin_pending = stream.target_fin_pending;
                             let close_after_flush = stream.close_after_flush;
-                            let now = unsafe { picoquic_current_time() };
                             INVARIANT_REPORTER.report(
-                                now,
+                                &PicoquicClock,
                                 || {
                                     format!(
-                                        "cnx {} stream {:?}: zero-length send callback saw pending flag with empty queue send_pending={} send_stash_bytes={} target_fin_pending={} close_after_flush={} queued={} pending_chunks={} tx_bytes={}",
-                                        key.cnx,
-                                        key.stream_id,
+                                        "{}: zero-length send callback saw pending flag with empty queue send_pending={} send_stash_bytes={} target_fin_pending={} close_after_flush={} queued={} pending_chunks={} tx_bytes={}",
+                                        tag,
                                         pending_flag,
                                         send_stash_bytes,
                                         target_fin_pending,
@@ -501,58 +913,72 @@ pub(crate) unsafe extern "C" fn server_callback(
                     return 0;
                 }
 
-                let mut send_data: Option<Vec<u8>> = None;
-                if let Some(mut stash) = stream.send_stash.take() {
-                    if stash.len() > length {
-                        let remainder = stash.split_off(length);
-                        stream.send_stash = Some(remainder);
-                    }
-                    send_data = Some(stash);
-                } else if let Some(rx) = stream.data_rx.as_mut() {
-                    match rx.try_recv() {
-                        Ok(mut data) => {
-                            if data.len() > length {
-                                let remainder = data.split_off(length);
-                                stream.send_stash = Some(remainder);
-                            }
-                            send_data = Some(data);
-                        }
-                        Err(mpsc::error::TryRecvError::Empty) => {}
-                        Err(mpsc::error::TryRecvError::Disconnected) => {
-                            stream.data_rx = None;
-                            stream.target_fin_pending = true;
-                            stream.close_after_flush = true;
-                        }
-                    }
-                }
-
-                if let Some(data) = send_data {
+                if let Some(mut data) = stream.take_send_chunk(length, state.send_coalesce_bytes) {
                     let send_len = data.len();
-                    let buffer =
-                        picoquic_provide_stream_data_buffer(bytes as *mut _, send_len, 0, 1);
+                    let forced_null = fault_injection::provide_stream_data_buffer_failure();
+                    let buffer = if forced_null {
+                        std::ptr::null_mut()
+                    } else {
+                        picoquic_provide_stream_data_buffer(bytes as *mut _, send_len, 0, 1)
+                    };
                     if buffer.is_null() {
+                        // picoquic only returns null when the request exceeds the space it
+                        // offered, which the split above rules out, so the cause is unknown.
+                        // Keep the data and let the next callback try again.
+                        if stream.buffer_null_retries < state.stream_buffer_null_retries {
+                            stream.buffer_null_retries += 1;
+                            warn!(
+                                "{}: provide_stream_data_buffer returned null send_len={} length={}; retrying ({}/{})",
+                                tag,
+                                send_len,
+                                length,
+                                stream.buffer_null_retries,
+                                state.stream_buffer_null_retries
+                            );
+                            if let Some(stash) = stream.send_stash.take() {
+                                data.extend_from_slice(&stash);
+                            }
+                            stream.send_stash = Some(data);
+                            if !forced_null {
+                                let _ =
+                                    picoquic_provide_stream_data_buffer(bytes as *mut _, 0, 0, 1);
+                            }
+                            return 0;
+                        }
                         if let Some(stream) = shutdown_stream(state, key) {
                             error!(
-                                "stream {:?}: provide_stream_data_buffer returned null send_len={} queued={} pending_chunks={} tx_bytes={}",
-                                key.stream_id,
+                                "{}: provide_stream_data_buffer returned null send_len={} queued={} pending_chunks={} tx_bytes={} retries={}",
+                                tag,
                                 send_len,
                                 stream.flow.queued_bytes,
                                 stream.pending_data.len(),
-                                stream.tx_bytes
+                                stream.tx_bytes,
+                                stream.buffer_null_retries
                             );
                         } else {
                             error!(
-                                "stream {:?}: provide_stream_data_buffer returned null send_len={}",
-                                key.stream_id, send_len
+                                "{}: provide_stream_data_buffer returned null send_len={}",
+                                tag, send_len
+                            );
+                        }
+                        if !forced_null {
+                            abort_stream(
+                                state,
+                                cnx,
+                                stream_id,
+                                SLIPSTREAM_INTERNAL_ERROR,
+                                "null send buffer",
                             );
                         }
-                        unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
                         return 0;
                     }
+                    stream.buffer_null_retries = 0;
                     unsafe {
                         std::ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
                     }
-                    stream.tx_bytes = stream.tx_bytes.saturating_add(data.len() as u64);
+                    if stream.record_sent(data.len()) {
+                        state.first_byte_latency.record(stream.opened_at.elapsed());
+                    }
                 } else if stream.target_fin_pending {
                     stream.target_fin_pending = false;
                     if stream.close_after_flush {
@@ -594,21 +1020,53 @@ fn handle_stream_data(
         stream_id,
     };
     let debug_streams = state.debug_streams;
+    let backpressure = state.backpressure;
     let mut reset_stream = false;
     let mut remove_stream = false;
 
+    if !state.streams.contains_key(&key)
+        && !cnx.is_null()
+        && !state.conn_peers.contains_key(&key.cnx)
+    {
+        if let Some(peer) = connection_peer_addr(cnx) {
+            state.conn_peers.insert(key.cnx, peer);
+        }
+    }
+    if !state.streams.contains_key(&key) && state.closing_conns.contains(&key.cnx) {
+        if debug_streams {
+            debug!("{}: refused, connection is closing", state.stream_tag(key));
+        }
+        if !cnx.is_null() {
+            unsafe {
+                let _ = picoquic_stop_sending(cnx, stream_id, SLIPSTREAM_ADMIN_CLOSE);
+                let _ = picoquic_reset_stream(cnx, stream_id, SLIPSTREAM_ADMIN_CLOSE);
+            }
+        }
+        return;
+    }
+    state.register_connection(key.cnx);
+    let tag = state.stream_tag(key);
+
     if !state.streams.contains_key(&key) {
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         if debug_streams {
-            debug!("stream {:?}: connecting", key.stream_id);
+            debug!("{}: connecting", tag);
         }
+        let trailers = state.stream_trailers && negotiated_stream_trailers(cnx);
         spawn_target_connector(
-            key,
-            state.target_addr,
+            tag,
+            Arc::clone(&state.target),
             state.command_tx.clone(),
             debug_streams,
+            state.stream_checksums,
+            trailers,
+            state.target_read_chunk_bytes,
             shutdown_rx,
         );
+        if let Some(hook) = state.lifecycle_hook.as_ref() {
+            hook.on_stream_open(key.cnx, key.stream_id, state.target.primary());
+        }
+        let peer = state.conn_peers.get(&key.cnx).copied();
         state.streams.insert(
             key,
             ServerStream {
@@ -621,15 +1079,25 @@ fn handle_stream_data(
                 target_fin_pending: false,
                 close_after_flush: false,
                 pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
                 pending_fin: false,
                 fin_enqueued: false,
                 flow: FlowControlState::default(),
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer,
+                close_reason: None,
+                send_stopped: false,
             },
         );
     }
 
     if mark_multi_stream(state, key.cnx) {
-        promote_streams(
+        let conn = state.conn_tag(key.cnx);
+        let failed = promote_streams(
             state
                 .streams
                 .iter_mut()
@@ -637,19 +1105,40 @@ fn handle_stream_data(
                 .map(|(entry_key, stream)| PromoteEntry {
                     stream_id: entry_key.stream_id,
                     rx_bytes: stream.flow.rx_bytes,
+                    queued_bytes: stream.flow.queued_bytes,
+                    fin_offset: stream.flow.fin_offset,
                     consumed_offset: &mut stream.flow.consumed_offset,
                     discarding: stream.flow.discarding,
                 }),
-            |stream_id, new_offset| unsafe {
-                picoquic_stream_data_consumed(cnx, stream_id, new_offset)
-            },
-            |stream_id, ret, consumed_offset, rx_bytes| {
+            stream_queue_max_bytes(),
+            |stream_id, new_offset| stream_data_consumed(cnx, stream_id, new_offset),
+            |stream_id, ret, consumed_offset, target| {
                 warn!(
                     "{}",
-                    promote_error_log_message(stream_id, ret, consumed_offset, rx_bytes)
+                    promote_error_log_message(conn.stream(stream_id), ret, consumed_offset, target)
                 );
             },
         );
+        for stream_id in failed {
+            let failed_key = StreamKey {
+                cnx: key.cnx,
+                stream_id,
+            };
+            report_stream_error(state, failed_key, "promotion to multi-stream failed");
+            if shutdown_stream(state, failed_key).is_some() {
+                abort_stream(
+                    state,
+                    cnx,
+                    stream_id,
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "failed promotion",
+                );
+                warn!(
+                    "{}: aborting after failed promotion to multi-stream mode",
+                    conn.stream(stream_id)
+                );
+            }
+        }
     }
     let multi_stream = state.multi_streams.contains(&key.cnx);
     let reserve_bytes = if multi_stream {
@@ -658,6 +1147,35 @@ fn handle_stream_data(
         conn_reserve_bytes()
     };
 
+    if let Some(stream) = state.streams.get(&key) {
+        if exceeds_fin_offset(stream.flow.fin_offset, stream.flow.rx_bytes, data.len()) {
+            let (rx_bytes, fin_offset) = (stream.flow.rx_bytes, stream.flow.fin_offset);
+            state.fin_violations = state.fin_violations.saturating_add(1);
+            let violations = state.fin_violations;
+            report_peer_violation(|| {
+                format!(
+                    "server protocol violation: data past fin {:#} rx_bytes={} len={} fin_offset={:?} fin={} violations={}",
+                    tag,
+                    rx_bytes,
+                    data.len(),
+                    fin_offset,
+                    fin,
+                    violations
+                )
+            });
+            report_stream_error(state, key, "data past fin");
+            shutdown_stream(state, key);
+            abort_stream(
+                state,
+                cnx,
+                stream_id,
+                SLIPSTREAM_FINAL_SIZE_ERROR,
+                "data past fin",
+            );
+            return;
+        }
+    }
+
     {
         let stream = match state.streams.get_mut(&key) {
             Some(stream) => stream,
@@ -676,11 +1194,14 @@ fn handle_stream_data(
                         }
                     } else {
                         stream.pending_data.push_back(data.to_vec());
+                        stream.pending_bytes = stream.pending_bytes.saturating_add(data.len());
                     }
                     Ok(())
                 },
                 on_overflow: |stream: &mut ServerStream| {
                     stream.pending_data.clear();
+                    stream.pending_bytes = 0;
+                    stream.pending_held = false;
                     stream.pending_fin = false;
                     stream.fin_enqueued = false;
                     stream.data_rx = None;
@@ -691,20 +1212,18 @@ fn handle_stream_data(
                     stream.close_after_flush = false;
                     let _ = stream.shutdown_tx.send(true);
                 },
-                consume: |new_offset| unsafe {
-                    picoquic_stream_data_consumed(cnx, stream_id, new_offset)
-                },
+                consume: |new_offset| stream_data_consumed(cnx, stream_id, new_offset),
                 stop_sending: || {
                     let _ =
                         unsafe { picoquic_stop_sending(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
                 },
                 log_overflow: |queued, incoming, max| {
-                    warn!("{}", overflow_log_message(stream_id, queued, incoming, max));
+                    warn!("{}", overflow_log_message(tag, queued, incoming, max));
                 },
                 on_consume_error: |ret, current, target| {
                     warn!(
                         "{}",
-                        consume_error_log_message(stream_id, "", ret, current, target)
+                        consume_error_log_message(tag, "", ret, current, target)
                     );
                 },
             },
@@ -712,6 +1231,29 @@ fn handle_stream_data(
             reset_stream = true;
         }
 
+        if let Some(marks) = backpressure {
+            if multi_stream
+                && !reset_stream
+                && !stream.flow.discarding
+                && !stream.flow.backpressured
+                && marks.should_apply(stream.flow.queued_bytes)
+            {
+                stream.flow.backpressured = true;
+                stream.backpressure_applied_count =
+                    stream.backpressure_applied_count.saturating_add(1);
+                if debug_streams {
+                    debug!(
+                        "{}: backpressure applied queued={} consumed_offset={} rx_bytes={} applied={}",
+                        tag,
+                        stream.flow.queued_bytes,
+                        stream.flow.consumed_offset,
+                        stream.flow.rx_bytes,
+                        stream.backpressure_applied_count
+                    );
+                }
+            }
+        }
+
         if fin {
             if stream.flow.discarding {
                 if !reset_stream {
@@ -737,6 +1279,9 @@ fn handle_stream_data(
                 }
             }
         }
+        if stream.send_stopped && stream.receive_finished() {
+            remove_stream = true;
+        }
     }
 
     if remove_stream {
@@ -744,9 +1289,13 @@ fn handle_stream_data(
         return;
     }
 
+    if !reset_stream {
+        hold_pending_stream(state, key, multi_stream);
+    }
+
     if reset_stream {
         if debug_streams {
-            debug!("stream {:?}: resetting", stream_id);
+            debug!("{}: resetting", tag);
         }
         if !state
             .streams
@@ -756,52 +1305,330 @@ fn handle_stream_data(
         {
             shutdown_stream(state, key);
         }
-        unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+        abort_stream(
+            state,
+            cnx,
+            stream_id,
+            SLIPSTREAM_INTERNAL_ERROR,
+            "stream data reset",
+        );
     }
 
     check_stream_invariants(state, key, "handle_stream_data");
 }
 
-pub(crate) fn remove_connection_streams(state: &mut ServerState, cnx: usize) {
-    let keys: Vec<StreamKey> = state
+/// Tracks the pending_data high watermark and withholds credit from a stream
+/// that pushed its connection or itself over a PendingLimits cap.
+fn hold_pending_stream(state: &mut ServerState, key: StreamKey, multi_stream: bool) {
+    let stream_pending = match state.streams.get(&key) {
+        Some(stream) if stream.write_tx.is_none() && stream.pending_bytes > 0 => {
+            stream.pending_bytes
+        }
+        _ => return,
+    };
+    let conn_pending: usize = state
         .streams
-        .keys()
-        .filter(|key| key.cnx == cnx)
-        .cloned()
-        .collect();
-    for key in keys {
-        shutdown_stream(state, key);
+        .iter()
+        .filter(|(entry_key, _)| entry_key.cnx == key.cnx)
+        .map(|(_, stream)| stream.pending_bytes)
+        .sum();
+    state.pending_bytes_high_watermark = state.pending_bytes_high_watermark.max(conn_pending);
+    // Single-stream consumption already stops at the reserve past queued data.
+    let Some(limits) = state.pending_limits else {
+        return;
+    };
+    if !multi_stream || !limits.exceeded(stream_pending, conn_pending) {
+        return;
+    }
+    let tag = state.stream_tag(key);
+    let Some(stream) = state.streams.get_mut(&key) else {
+        return;
+    };
+    if stream.flow.discarding || stream.flow.backpressured {
+        return;
+    }
+    stream.flow.backpressured = true;
+    stream.pending_held = true;
+    if state.debug_streams {
+        debug!(
+            "{}: pending data held stream_pending={} conn_pending={} consumed_offset={} rx_bytes={}",
+            tag,
+            stream_pending,
+            conn_pending,
+            stream.flow.consumed_offset,
+            stream.flow.rx_bytes
+        );
     }
-    state.multi_streams.remove(&cnx);
 }
 
-fn shutdown_stream(state: &mut ServerState, key: StreamKey) -> Option<ServerStream> {
-    if let Some(stream) = state.streams.remove(&key) {
-        let _ = stream.shutdown_tx.send(true);
-        return Some(stream);
+/// Answer STOP_SENDING with a reset of our send side only. Target data still
+/// queued for the client is dropped and the target reader stops at its next
+/// chunk, while the client's data keeps flowing to the target under the
+/// usual credit and backpressure rules.
+fn stop_send_side(state: &mut ServerState, cnx: *mut picoquic_cnx_t, key: StreamKey) {
+    let tag = state.stream_tag(key);
+    let Some(stream) = state.streams.get_mut(&key) else {
+        return;
+    };
+    stream.send_stopped = true;
+    stream.data_rx = None;
+    stream.send_stash = None;
+    stream.target_fin_pending = false;
+    stream.close_after_flush = false;
+    if let Some(pending) = stream.send_pending.as_ref() {
+        pending.store(false, Ordering::SeqCst);
+    }
+    if state.debug_streams {
+        debug!(
+            "{}: stop_sending; send side reset, receive side kept tx_bytes={} rx_bytes={} queued={}",
+            tag, stream.tx_bytes, stream.flow.rx_bytes, stream.flow.queued_bytes
+        );
+    }
+    if stream.receive_finished() {
+        shutdown_stream(state, key);
+    }
+    #[cfg(test)]
+    if let Some(recorded) = state.recorded_aborts.as_mut() {
+        recorded.push((key.stream_id, SLIPSTREAM_FILE_CANCEL_ERROR));
+        return;
+    }
+    let ret = unsafe { picoquic_reset_stream(cnx, key.stream_id, SLIPSTREAM_FILE_CANCEL_ERROR) };
+    if ret != 0 {
+        debug!("{}: reset_stream after stop_sending ret={}", tag, ret);
     }
-    None
 }
 
-pub(crate) fn drain_commands(
-    state_ptr: *mut ServerState,
-    command_rx: &mut mpsc::UnboundedReceiver<Command>,
+/// Abort `stream_id` in both directions. A refusal is logged with
+/// `context`, the reason for the abort, and a transient one is retried by
+/// `retry_deferred_aborts`.
+fn abort_stream(
+    state: &mut ServerState,
+    cnx: *mut picoquic_cnx_t,
+    stream_id: u64,
+    app_error: u64,
+    context: &'static str,
 ) {
-    while let Ok(command) = command_rx.try_recv() {
-        handle_command(state_ptr, command);
+    #[cfg(test)]
+    if let Some(recorded) = state.recorded_aborts.as_mut() {
+        recorded.push((stream_id, app_error));
+        return;
+    }
+    let Err(err) = (unsafe { abort_stream_bidi(cnx, stream_id, app_error) }) else {
+        return;
+    };
+    let tag = state.conn_tag(cnx as usize).stream(stream_id);
+    if state.aborts.record_failure(cnx, stream_id, app_error, err) {
+        warn!(
+            "{}: abort after {} failed ({}); retrying",
+            tag, context, err
+        );
+    } else {
+        debug!(
+            "{}: abort after {} refused ({}); that side is already closed",
+            tag, context, err
+        );
     }
 }
 
-pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
-    let state = unsafe { &mut *state_ptr };
-    if state.debug_commands {
-        state.command_counts.bump(&command);
+/// Print the "and N more" summaries of sampled per-stream warnings whose
+/// window has ended; called once per loop iteration.
+pub(crate) fn flush_sampled_logs() {
+    for logger in SAMPLED_LOGS {
+        logger.flush(&PicoquicClock, |msg| warn!("{}", msg));
     }
-    match command {
-        Command::StreamConnected {
-            cnx_id,
-            stream_id,
-            write_tx,
+}
+
+/// Retry aborts picoquic refused earlier; called once per loop iteration.
+pub(crate) fn retry_deferred_aborts(state: &mut ServerState) {
+    if state.aborts.is_empty() {
+        return;
+    }
+    retry_deferred_aborts_with(state, |cnx, stream_id, app_error| unsafe {
+        abort_stream_bidi(cnx, stream_id, app_error)
+    });
+}
+
+fn retry_deferred_aborts_with<F>(state: &mut ServerState, abort: F)
+where
+    F: FnMut(*mut picoquic_cnx_t, u64, u64) -> Result<(), AbortError>,
+{
+    for (cnx, stream_id, outcome) in state.aborts.retry_with(abort) {
+        let tag = state.conn_tag(cnx as usize).stream(stream_id);
+        match outcome {
+            AbortRetryOutcome::Succeeded { attempts } => {
+                info!("{}: abort went through on retry {}", tag, attempts);
+            }
+            AbortRetryOutcome::GaveUp { attempts, error } => {
+                warn!(
+                    "{}: giving up on abort after {} retries ({})",
+                    tag, attempts, error
+                );
+            }
+        }
+    }
+}
+
+pub(crate) fn remove_connection_streams(state: &mut ServerState, cnx: usize) {
+    let keys: Vec<StreamKey> = state
+        .streams
+        .keys()
+        .filter(|key| key.cnx == cnx)
+        .cloned()
+        .collect();
+    for key in keys {
+        if let Some(stream) = state.streams.get_mut(&key) {
+            stream.close_reason.get_or_insert("connection closed");
+        }
+        shutdown_stream(state, key);
+    }
+    state.multi_streams.remove(&cnx);
+    state.conn_peers.remove(&cnx);
+    if state.is_registered(cnx) {
+        state.conn_ids.remove(&conn_short_id(cnx));
+    }
+    state.closing_conns.remove(&cnx);
+    state.aborts.forget_connection(cnx as *mut picoquic_cnx_t);
+}
+
+/// Lets a held stream take credit again once its pending data is flushed.
+///
+/// Backpressure shares the same flag, so the credit stays withheld while the
+/// flushed data still keeps the queue above the low watermark; the usual
+/// release in StreamWriteDrained picks it up from there.
+fn release_pending_hold(
+    stream: &mut ServerStream,
+    tag: StreamTag,
+    backpressure: Option<BackpressureWatermarks>,
+) -> bool {
+    let StreamKey { cnx, stream_id } = tag.key();
+    stream.pending_held = false;
+    if !backpressure.is_none_or(|marks| marks.should_release(stream.flow.queued_bytes)) {
+        return true;
+    }
+    stream.flow.backpressured = false;
+    consume_stream_data(
+        &mut stream.flow.consumed_offset,
+        stream.flow.rx_bytes,
+        |new_offset| stream_data_consumed(cnx as *mut picoquic_cnx_t, stream_id, new_offset),
+        |ret, current, target| {
+            warn!(
+                "{}",
+                consume_error_log_message(tag, " on pending release", ret, current, target)
+            );
+        },
+    )
+}
+
+fn shutdown_stream(state: &mut ServerState, key: StreamKey) -> Option<ServerStream> {
+    if let Some(stream) = state.streams.remove(&key) {
+        let _ = stream.shutdown_tx.send(true);
+        if let Some(hook) = state.lifecycle_hook.as_ref() {
+            hook.on_stream_close(
+                key.cnx,
+                key.stream_id,
+                stream.tx_bytes,
+                stream.flow.rx_bytes,
+                stream.opened_at.elapsed().as_micros() as u64,
+            );
+        }
+        if let Some(summaries) = state.stream_summaries.as_mut() {
+            let completed = stream.fin_enqueued && stream.flow.fin_offset.is_some();
+            summaries.record(&StreamSummary {
+                cnx_id: key.cnx,
+                stream_id: key.stream_id,
+                peer: stream.peer,
+                target: state.target.primary(),
+                bytes_up: stream.flow.rx_bytes,
+                bytes_down: stream.tx_bytes,
+                duration_ms: stream.opened_at.elapsed().as_millis() as u64,
+                reason: stream.close_reason.unwrap_or(if completed {
+                    "completed"
+                } else {
+                    "aborted"
+                }),
+            });
+        }
+        return Some(stream);
+    }
+    None
+}
+
+/// Reports why a stream is about to be torn down; call before shutdown_stream.
+fn report_stream_error(state: &mut ServerState, key: StreamKey, reason: &'static str) {
+    let Some(stream) = state.streams.get_mut(&key) else {
+        return;
+    };
+    stream.close_reason.get_or_insert(reason);
+    if let Some(hook) = state.lifecycle_hook.as_ref() {
+        hook.on_stream_error(key.cnx, key.stream_id, reason);
+    }
+}
+
+/// Whether the client chose the trailer ALPN; a server with trailers on
+/// still accepts clients without them.
+fn negotiated_stream_trailers(cnx: *mut picoquic_cnx_t) -> bool {
+    if cnx.is_null() {
+        return false;
+    }
+    let alpn = unsafe { picoquic_tls_get_negotiated_alpn(cnx) };
+    !alpn.is_null() && unsafe { CStr::from_ptr(alpn) }.to_bytes() == STREAM_TRAILER_ALPN.as_bytes()
+}
+
+fn connection_peer_addr(cnx: *mut picoquic_cnx_t) -> Option<SocketAddr> {
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let ret = unsafe { picoquic_get_path_addr(cnx, 0, 2, &mut storage) };
+    if ret != 0 {
+        return None;
+    }
+    sockaddr_storage_to_socket_addr(&storage).ok()
+}
+
+pub(crate) fn drain_commands(
+    state: &mut ServerState,
+    command_rx: &mut mpsc::UnboundedReceiver<Command>,
+) {
+    // Target writers report every chunk; fold back-to-back reports per
+    // stream so the credit update runs once (see `DrainCoalescer`).
+    let mut drained = DrainCoalescer::new();
+    while let Ok(command) = command_rx.try_recv() {
+        if let Command::StreamWriteDrained {
+            cnx_id,
+            stream_id,
+            bytes,
+        } = command
+        {
+            if let Some(run) = drained.push((cnx_id, stream_id), bytes) {
+                handle_command(state, write_drained(run));
+            }
+            continue;
+        }
+        if let Some(run) = drained.take() {
+            handle_command(state, write_drained(run));
+        }
+        handle_command(state, command);
+    }
+    if let Some(run) = drained.take() {
+        handle_command(state, write_drained(run));
+    }
+}
+
+fn write_drained(((cnx_id, stream_id), bytes): ((usize, u64), usize)) -> Command {
+    Command::StreamWriteDrained {
+        cnx_id,
+        stream_id,
+        bytes,
+    }
+}
+
+pub(crate) fn handle_command(state: &mut ServerState, command: Command) {
+    if state.debug_commands {
+        state.command_counts.bump(&command);
+    }
+    match command {
+        Command::StreamConnected {
+            cnx_id,
+            stream_id,
+            write_tx,
             data_rx,
             send_pending,
         } => {
@@ -809,33 +1636,46 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
             let mut reset_stream = false;
             {
                 let Some(stream) = state.streams.get_mut(&key) else {
                     return;
                 };
+                state
+                    .target_connect_latency
+                    .record(stream.opened_at.elapsed());
                 if state.debug_streams {
-                    debug!("stream {:?}: target connected", stream_id);
+                    debug!("{}: target connected", tag);
                 }
                 if stream.flow.discarding {
                     stream.pending_data.clear();
+                    stream.pending_bytes = 0;
                     stream.pending_fin = false;
                     stream.fin_enqueued = false;
                     let _ = stream.shutdown_tx.send(true);
                     return;
                 }
                 stream.write_tx = Some(write_tx);
-                stream.data_rx = Some(data_rx);
+                // A stopped send side drops the receiver, which ends the reader.
+                stream.data_rx = (!stream.send_stopped).then_some(data_rx);
                 stream.send_pending = Some(send_pending);
                 if let Some(write_tx) = stream.write_tx.as_ref() {
                     while let Some(chunk) = stream.pending_data.pop_front() {
+                        stream.pending_bytes = stream.pending_bytes.saturating_sub(chunk.len());
                         if write_tx.send(StreamWrite::Data(chunk)).is_err() {
-                            warn!(
-                                "stream {:?}: pending write flush failed queued={} pending_chunks={} tx_bytes={}",
-                                stream_id,
-                                stream.flow.queued_bytes,
-                                stream.pending_data.len(),
-                                stream.tx_bytes
+                            PENDING_FLUSH_LOG.log(
+                                &PicoquicClock,
+                                || {
+                                    format!(
+                                        "{}: pending write flush failed queued={} pending_chunks={} tx_bytes={}",
+                                        tag,
+                                        stream.flow.queued_bytes,
+                                        stream.pending_data.len(),
+                                        stream.tx_bytes
+                                    )
+                                },
+                                |msg| warn!("{}", msg),
                             );
                             reset_stream = true;
                             break;
@@ -843,12 +1683,18 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                     }
                     if !reset_stream && stream.pending_fin && !stream.fin_enqueued {
                         if write_tx.send(StreamWrite::Fin).is_err() {
-                            warn!(
-                                "stream {:?}: pending fin flush failed queued={} pending_chunks={} tx_bytes={}",
-                                stream_id,
-                                stream.flow.queued_bytes,
-                                stream.pending_data.len(),
-                                stream.tx_bytes
+                            PENDING_FLUSH_LOG.log(
+                                &PicoquicClock,
+                                || {
+                                    format!(
+                                        "{}: pending fin flush failed queued={} pending_chunks={} tx_bytes={}",
+                                        tag,
+                                        stream.flow.queued_bytes,
+                                        stream.pending_data.len(),
+                                        stream.tx_bytes
+                                    )
+                                },
+                                |msg| warn!("{}", msg),
                             );
                             reset_stream = true;
                         } else {
@@ -857,11 +1703,23 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                         }
                     }
                 }
+                if !reset_stream
+                    && stream.pending_held
+                    && !release_pending_hold(stream, tag, state.backpressure)
+                {
+                    reset_stream = true;
+                }
             }
             if reset_stream {
                 let cnx = cnx_id as *mut picoquic_cnx_t;
                 shutdown_stream(state, key);
-                unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                abort_stream(
+                    state,
+                    cnx,
+                    stream_id,
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "target connect flush failure",
+                );
             }
             check_stream_invariants(state, key, "StreamConnected");
         }
@@ -871,9 +1729,21 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
+            report_stream_error(state, key, "target connect failed");
             if shutdown_stream(state, key).is_some() {
-                unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
-                warn!("stream {:?}: target connect failed", stream_id);
+                abort_stream(
+                    state,
+                    cnx,
+                    stream_id,
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "target connect failure",
+                );
+                TARGET_CONNECT_LOG.log(
+                    &PicoquicClock,
+                    || format!("{}: target connect failed", tag),
+                    |msg| warn!("{}", msg),
+                );
             }
         }
         Command::StreamClosed { cnx_id, stream_id } => {
@@ -881,7 +1751,11 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
             let mut remove_stream = false;
+            if state.send_stopped(key) {
+                return;
+            }
             if state.streams.contains_key(&key) {
                 #[cfg(test)]
                 let forced_failure = test_helpers::take_mark_active_stream_failure(state);
@@ -894,10 +1768,7 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 stream.target_fin_pending = true;
                 stream.close_after_flush = true;
                 if state.debug_streams {
-                    debug!(
-                        "stream {:?}: closed by target tx_bytes={}",
-                        stream_id, stream.tx_bytes
-                    );
+                    debug!("{}: closed by target tx_bytes={}", tag, stream.tx_bytes);
                 }
                 if let Some(pending) = stream.send_pending.as_ref() {
                     pending.store(true, Ordering::SeqCst);
@@ -918,39 +1789,25 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                     unsafe { picoquic_mark_active_stream(cnx, stream_id, 1, std::ptr::null_mut()) };
                 if ret != 0 {
                     const MARK_ACTIVE_FAIL_LOG_INTERVAL_US: u64 = 1_000_000;
-                    let now = unsafe { picoquic_current_time() };
+                    let now = PicoquicClock.now_us();
                     if now.saturating_sub(state.last_mark_active_fail_log_at)
                         >= MARK_ACTIVE_FAIL_LOG_INTERVAL_US
                     {
-                        let send_pending = stream
-                            .send_pending
-                            .as_ref()
-                            .map(|pending| pending.load(Ordering::SeqCst))
-                            .unwrap_or(false);
-                        let send_stash_bytes = stream
-                            .send_stash
-                            .as_ref()
-                            .map(|stash| stash.len())
-                            .unwrap_or(0);
-                        let backlog = BacklogStreamSummary {
-                            stream_id,
-                            send_pending,
-                            send_stash_bytes,
-                            target_fin_pending: stream.target_fin_pending,
-                            close_after_flush: stream.close_after_flush,
-                            pending_fin: stream.pending_fin,
-                            fin_enqueued: stream.fin_enqueued,
-                            queued_bytes: stream.flow.queued_bytes as u64,
-                            pending_chunks: stream.pending_data.len(),
-                        };
+                        let backlog = stream.backlog_summary(stream_id);
                         warn!(
-                            "stream {:?}: mark_active_stream fin failed ret={} backlog={:?}",
-                            stream_id, ret, backlog
+                            "{}: mark_active_stream fin failed ret={} backlog={:?}",
+                            tag, ret, backlog
                         );
                         state.last_mark_active_fail_log_at = now;
                     }
                     if !forced_failure {
-                        unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                        abort_stream(
+                            state,
+                            cnx,
+                            stream_id,
+                            SLIPSTREAM_INTERNAL_ERROR,
+                            "mark_active_stream fin failure",
+                        );
                     }
                     remove_stream = true;
                 }
@@ -965,7 +1822,8 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
-            if !state.streams.contains_key(&key) {
+            let tag = state.stream_tag(key);
+            if !state.streams.contains_key(&key) || state.send_stopped(key) {
                 return;
             }
             #[cfg(test)]
@@ -989,8 +1847,8 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
             if ret != 0 {
                 if let Some(stream) = shutdown_stream(state, key) {
                     warn!(
-                        "stream {:?}: mark_active_stream readable failed ret={} tx_bytes={} rx_bytes={} consumed_offset={} queued={} fin_offset={:?}",
-                        stream_id,
+                        "{}: mark_active_stream readable failed ret={} tx_bytes={} rx_bytes={} consumed_offset={} queued={} fin_offset={:?}",
+                        tag,
                         ret,
                         stream.tx_bytes,
                         stream.flow.rx_bytes,
@@ -999,13 +1857,16 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                         stream.flow.fin_offset
                     );
                     if !forced_failure {
-                        unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
+                        abort_stream(
+                            state,
+                            cnx,
+                            stream_id,
+                            SLIPSTREAM_INTERNAL_ERROR,
+                            "mark_active_stream readable failure",
+                        );
                     }
                 } else if state.debug_streams {
-                    debug!(
-                        "stream {:?}: mark_active_stream readable failed ret={}",
-                        stream_id, ret
-                    );
+                    debug!("{}: mark_active_stream readable failed ret={}", tag, ret);
                 }
             }
         }
@@ -1015,17 +1876,34 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
+            if state.send_stopped(key) {
+                return;
+            }
+            report_stream_error(state, key, "target read error");
             if let Some(stream) = shutdown_stream(state, key) {
-                warn!(
-                    "stream {:?}: target read error tx_bytes={} rx_bytes={} consumed_offset={} queued={} fin_offset={:?}",
+                TARGET_READ_LOG.log(
+                    &PicoquicClock,
+                    || {
+                        format!(
+                            "{}: target read error tx_bytes={} rx_bytes={} consumed_offset={} queued={} fin_offset={:?}",
+                            tag,
+                            stream.tx_bytes,
+                            stream.flow.rx_bytes,
+                            stream.flow.consumed_offset,
+                            stream.flow.queued_bytes,
+                            stream.flow.fin_offset
+                        )
+                    },
+                    |msg| warn!("{}", msg),
+                );
+                abort_stream(
+                    state,
+                    cnx,
                     stream_id,
-                    stream.tx_bytes,
-                    stream.flow.rx_bytes,
-                    stream.flow.consumed_offset,
-                    stream.flow.queued_bytes,
-                    stream.flow.fin_offset
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "target read error",
                 );
-                unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
             }
         }
         Command::StreamWriteError { cnx_id, stream_id } => {
@@ -1034,17 +1912,63 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
+            report_stream_error(state, key, "target write failed");
             if let Some(stream) = shutdown_stream(state, key) {
-                warn!(
-                    "stream {:?}: target write failed tx_bytes={} rx_bytes={} consumed_offset={} queued={} fin_offset={:?}",
+                TARGET_WRITE_LOG.log(
+                    &PicoquicClock,
+                    || {
+                        format!(
+                            "{}: target write failed tx_bytes={} rx_bytes={} consumed_offset={} queued={} fin_offset={:?}",
+                            tag,
+                            stream.tx_bytes,
+                            stream.flow.rx_bytes,
+                            stream.flow.consumed_offset,
+                            stream.flow.queued_bytes,
+                            stream.flow.fin_offset
+                        )
+                    },
+                    |msg| warn!("{}", msg),
+                );
+                abort_stream(
+                    state,
+                    cnx,
                     stream_id,
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "target write error",
+                );
+            }
+        }
+        Command::StreamChecksumMismatch {
+            cnx_id,
+            stream_id,
+            detail,
+        } => {
+            let cnx = cnx_id as *mut picoquic_cnx_t;
+            let key = StreamKey {
+                cnx: cnx_id,
+                stream_id,
+            };
+            let tag = state.stream_tag(key);
+            report_stream_error(state, key, "checksum mismatch");
+            if let Some(stream) = shutdown_stream(state, key) {
+                error!(
+                    "{}: stream trailer check failed: {} tx_bytes={} rx_bytes={} consumed_offset={} fin_offset={:?} backlog={:?}",
+                    tag,
+                    detail,
                     stream.tx_bytes,
                     stream.flow.rx_bytes,
                     stream.flow.consumed_offset,
-                    stream.flow.queued_bytes,
-                    stream.flow.fin_offset
+                    stream.flow.fin_offset,
+                    stream.backlog_summary(stream_id)
+                );
+                abort_stream(
+                    state,
+                    cnx,
+                    stream_id,
+                    SLIPSTREAM_CHECKSUM_ERROR,
+                    "checksum mismatch",
                 );
-                unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_INTERNAL_ERROR) };
             }
         }
         Command::StreamWriteDrained {
@@ -1056,24 +1980,62 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                 cnx: cnx_id,
                 stream_id,
             };
+            let tag = state.stream_tag(key);
             let mut reset_stream = false;
             if let Some(stream) = state.streams.get_mut(&key) {
                 if stream.flow.discarding {
                     return;
                 }
                 stream.flow.queued_bytes = stream.flow.queued_bytes.saturating_sub(bytes);
-                if !state.multi_streams.contains(&cnx_id) {
-                    let new_offset = reserve_target_offset(
-                        stream.flow.rx_bytes,
-                        stream.flow.queued_bytes,
-                        stream.flow.fin_offset,
-                        conn_reserve_bytes(),
-                    );
+                let release_backpressure = stream.flow.backpressured
+                    && state
+                        .backpressure
+                        .is_some_and(|marks| marks.should_release(stream.flow.queued_bytes));
+                if release_backpressure {
+                    stream.flow.backpressured = false;
+                    stream.backpressure_released_count =
+                        stream.backpressure_released_count.saturating_add(1);
+                    if state.debug_streams {
+                        debug!(
+                            "{}: backpressure released queued={} consumed_offset={} rx_bytes={} released={}",
+                            tag,
+                            stream.flow.queued_bytes,
+                            stream.flow.consumed_offset,
+                            stream.flow.rx_bytes,
+                            stream.backpressure_released_count
+                        );
+                    }
+                }
+                // Backpressure only applies to multi-stream streams, which
+                // otherwise lag behind rx_bytes only where the queue cap held
+                // them back; drains release the rest.
+                let multi_stream = state.multi_streams.contains(&cnx_id);
+                if !(multi_stream && stream.flow.backpressured) {
+                    let new_offset = if multi_stream {
+                        promote_target_offset(
+                            stream.flow.rx_bytes,
+                            stream.flow.queued_bytes,
+                            stream.flow.fin_offset,
+                            stream_queue_max_bytes(),
+                        )
+                    } else {
+                        reserve_target_offset(
+                            stream.flow.rx_bytes,
+                            stream.flow.queued_bytes,
+                            stream.flow.fin_offset,
+                            conn_reserve_bytes(),
+                        )
+                    };
+                    let context = if release_backpressure {
+                        " on backpressure release"
+                    } else {
+                        ""
+                    };
                     if !consume_stream_data(
                         &mut stream.flow.consumed_offset,
                         new_offset,
-                        |new_offset| unsafe {
-                            picoquic_stream_data_consumed(
+                        |new_offset| {
+                            stream_data_consumed(
                                 cnx_id as *mut picoquic_cnx_t,
                                 stream_id,
                                 new_offset,
@@ -1082,7 +2044,7 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                         |ret, current, target| {
                             warn!(
                                 "{}",
-                                consume_error_log_message(stream_id, "", ret, current, target)
+                                consume_error_log_message(tag, context, ret, current, target)
                             );
                         },
                     ) {
@@ -1092,21 +2054,26 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
             }
             if reset_stream {
                 shutdown_stream(state, key);
-                unsafe {
-                    abort_stream_bidi(
-                        cnx_id as *mut picoquic_cnx_t,
-                        stream_id,
-                        SLIPSTREAM_INTERNAL_ERROR,
-                    )
-                };
+                abort_stream(
+                    state,
+                    cnx_id as *mut picoquic_cnx_t,
+                    stream_id,
+                    SLIPSTREAM_INTERNAL_ERROR,
+                    "consume failure",
+                );
+            } else if state
+                .streams
+                .get(&key)
+                .is_some_and(|stream| stream.send_stopped && stream.receive_finished())
+            {
+                shutdown_stream(state, key);
             }
             check_stream_invariants(state, key, "StreamWriteDrained");
         }
     }
 }
 
-pub(crate) fn maybe_report_command_stats(state_ptr: *mut ServerState) {
-    let state = unsafe { &mut *state_ptr };
+pub(crate) fn maybe_report_command_stats(state: &mut ServerState) {
     if !state.debug_commands {
         return;
     }
@@ -1117,7 +2084,7 @@ pub(crate) fn maybe_report_command_stats(state_ptr: *mut ServerState) {
     let total = state.command_counts.total();
     if total > 0 {
         debug!(
-            "debug: commands total={} connected={} connect_err={} closed={} readable={} read_err={} write_err={} write_drained={}",
+            "debug: commands total={} connected={} connect_err={} closed={} readable={} read_err={} write_err={} write_drained={} checksum_mismatch={}",
             total,
             state.command_counts.stream_connected,
             state.command_counts.stream_connect_error,
@@ -1125,13 +2092,34 @@ pub(crate) fn maybe_report_command_stats(state_ptr: *mut ServerState) {
             state.command_counts.stream_readable,
             state.command_counts.stream_read_error,
             state.command_counts.stream_write_error,
-            state.command_counts.stream_write_drained
+            state.command_counts.stream_write_drained,
+            state.command_counts.stream_checksum_mismatch
         );
     }
     state.command_counts.reset();
     state.last_command_report = now;
 }
 
+/// Logs the stream latency percentiles once a minute while new streams
+/// keep adding samples.
+pub(crate) fn maybe_report_stream_latency(state: &mut ServerState) {
+    let now = Instant::now();
+    if now.duration_since(state.last_latency_report) < STREAM_LATENCY_REPORT_INTERVAL {
+        return;
+    }
+    state.last_latency_report = now;
+    let (target_connect, first_byte) = state.stream_latency();
+    let samples = target_connect.count.saturating_add(first_byte.count);
+    if samples == state.latency_reported_samples {
+        return;
+    }
+    state.latency_reported_samples = samples;
+    info!(
+        "Stream latency since start: target_connect: {}; first_byte: {}",
+        target_connect, first_byte
+    );
+}
+
 pub(crate) fn handle_shutdown(quic: *mut picoquic_quic_t, state: &mut ServerState) -> bool {
     let mut cnx = unsafe { picoquic_get_first_cnx(quic) };
     while !cnx.is_null() {
@@ -1142,6 +2130,7 @@ pub(crate) fn handle_shutdown(quic: *mut picoquic_quic_t, state: &mut ServerStat
     }
     state.streams.clear();
     state.multi_streams.clear();
+    state.aborts.clear();
     true
 }
 
@@ -1153,6 +2142,8 @@ mod test_hooks {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use slipstream_core::callback_state::CallbackState;
+    use slipstream_core::test_support::ResetOnDrop;
     use std::collections::VecDeque;
     use std::net::SocketAddr;
     use std::sync::atomic::AtomicBool;
@@ -1163,7 +2154,7 @@ mod tests {
     fn mark_active_stream_failure_should_remove_stream() {
         let (command_tx, _command_rx) = mpsc::unbounded_channel();
         let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
-        let mut state = ServerState::new(target_addr, command_tx, false, false);
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
         let key = StreamKey {
             cnx: 0x1,
             stream_id: 4,
@@ -1182,16 +2173,25 @@ mod tests {
                 target_fin_pending: false,
                 close_after_flush: false,
                 pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
                 pending_fin: false,
                 fin_enqueued: false,
                 flow: FlowControlState::default(),
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
             },
         );
 
         test_helpers::set_mark_active_stream_failures(&mut state, 1);
 
         handle_command(
-            &mut state as *mut _,
+            &mut state,
             Command::StreamClosed {
                 cnx_id: key.cnx,
                 stream_id: key.stream_id,
@@ -1208,7 +2208,7 @@ mod tests {
     fn mark_active_stream_readable_failure_should_not_leave_send_pending_stuck() {
         let (command_tx, _command_rx) = mpsc::unbounded_channel();
         let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
-        let mut state = ServerState::new(target_addr, command_tx, false, false);
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
         let key = StreamKey {
             cnx: 0x1,
             stream_id: 4,
@@ -1229,16 +2229,25 @@ mod tests {
                 target_fin_pending: false,
                 close_after_flush: false,
                 pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
                 pending_fin: false,
                 fin_enqueued: false,
                 flow: FlowControlState::default(),
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
             },
         );
 
         test_helpers::set_mark_active_stream_failures(&mut state, 1);
 
         handle_command(
-            &mut state as *mut _,
+            &mut state,
             Command::StreamReadable {
                 cnx_id: key.cnx,
                 stream_id: key.stream_id,
@@ -1255,4 +2264,851 @@ mod tests {
             "send_pending should be dropped when the stream is removed"
         );
     }
+
+    #[test]
+    fn null_stream_buffer_retries_before_aborting() {
+        let _guard =
+            ResetOnDrop::new(|| fault_injection::set_provide_stream_data_buffer_failures(0));
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut state = CallbackState::new(ServerState::new(
+            target_addr,
+            command_tx,
+            false,
+            false,
+            None,
+            1,
+            None,
+        ));
+        let key = StreamKey {
+            cnx: 0x1,
+            stream_id: 4,
+        };
+        let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+
+        state.get_mut().streams.insert(
+            key,
+            ServerStream {
+                write_tx: None,
+                data_rx: None,
+                send_pending: Some(Arc::new(AtomicBool::new(true))),
+                send_stash: Some(vec![1, 2, 3, 4]),
+                shutdown_tx,
+                tx_bytes: 0,
+                target_fin_pending: false,
+                close_after_flush: false,
+                pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
+                pending_fin: false,
+                fin_enqueued: false,
+                flow: FlowControlState::default(),
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
+            },
+        );
+
+        fault_injection::set_provide_stream_data_buffer_failures(2);
+        let mut frame = [0u8; 16];
+        let prepare_to_send =
+            |state: &CallbackState<ServerState>, frame: &mut [u8], length: usize| unsafe {
+                server_callback(
+                    key.cnx as *mut picoquic_cnx_t,
+                    key.stream_id,
+                    frame.as_mut_ptr(),
+                    length,
+                    picoquic_call_back_event_t::picoquic_callback_prepare_to_send,
+                    state.callback_ctx(),
+                    std::ptr::null_mut(),
+                )
+            };
+
+        // A short callback splits the stash; the retry must put it back whole.
+        prepare_to_send(&state, &mut frame, 2);
+        let stream = state
+            .get()
+            .streams
+            .get(&key)
+            .expect("stream kept for retry");
+        assert_eq!(stream.send_stash.as_deref(), Some(&[1u8, 2, 3, 4][..]));
+        assert_eq!(stream.buffer_null_retries, 1);
+        assert_eq!(stream.tx_bytes, 0);
+
+        prepare_to_send(&state, &mut frame, 4);
+        assert!(
+            !state.get().streams.contains_key(&key),
+            "stream should be removed once null buffer retries are exhausted"
+        );
+    }
+
+    #[test]
+    fn large_target_read_goes_out_over_many_short_callbacks() {
+        let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+        let (data_tx, data_rx) = mpsc::channel(4);
+        let mut stream = ServerStream {
+            write_tx: None,
+            data_rx: Some(data_rx),
+            send_pending: Some(Arc::new(AtomicBool::new(true))),
+            send_stash: None,
+            shutdown_tx,
+            tx_bytes: 0,
+            target_fin_pending: false,
+            close_after_flush: false,
+            pending_data: VecDeque::new(),
+            pending_bytes: 0,
+            pending_held: false,
+            pending_fin: false,
+            fin_enqueued: false,
+            flow: FlowControlState::default(),
+            backpressure_applied_count: 0,
+            backpressure_released_count: 0,
+            buffer_null_retries: 0,
+            opened_at: Instant::now(),
+            peer: None,
+            close_reason: None,
+            send_stopped: false,
+        };
+        let huge: Vec<u8> = (0..(256 << 10) + 7).map(|i| (i % 251) as u8).collect();
+        let tail = vec![0xAA, 0xBB, 0xCC];
+        data_tx.try_send(huge.clone()).unwrap();
+        data_tx.try_send(tail.clone()).unwrap();
+        drop(data_tx);
+
+        let lengths = [1usize, 37, 1200, 4096, 1];
+        let mut delivered = Vec::new();
+        let mut callbacks = 0usize;
+        let mut first_bytes = 0;
+        while let Some(chunk) = stream.take_send_chunk(lengths[callbacks % lengths.len()], 0) {
+            assert!(chunk.len() <= lengths[callbacks % lengths.len()]);
+            assert!(!chunk.is_empty());
+            if stream.record_sent(chunk.len()) {
+                first_bytes += 1;
+            }
+            delivered.extend_from_slice(&chunk);
+            callbacks += 1;
+            assert_eq!(stream.tx_bytes, delivered.len() as u64);
+        }
+
+        let mut expected = huge;
+        expected.extend_from_slice(&tail);
+        assert_eq!(delivered.len(), expected.len());
+        assert!(delivered == expected, "payload must arrive byte for byte");
+        assert!(callbacks > 200);
+        assert_eq!(first_bytes, 1);
+        assert!(stream.send_stash.is_none());
+        assert!(stream.data_rx.is_none());
+        assert!(stream.target_fin_pending && stream.close_after_flush);
+    }
+
+    #[test]
+    fn coalescing_joins_queued_target_reads_up_to_the_send_length() {
+        let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+        let (data_tx, data_rx) = mpsc::channel(8);
+        let mut stream = ServerStream {
+            write_tx: None,
+            data_rx: Some(data_rx),
+            send_pending: Some(Arc::new(AtomicBool::new(true))),
+            send_stash: None,
+            shutdown_tx,
+            tx_bytes: 0,
+            target_fin_pending: false,
+            close_after_flush: false,
+            pending_data: VecDeque::new(),
+            pending_bytes: 0,
+            pending_held: false,
+            pending_fin: false,
+            fin_enqueued: false,
+            flow: FlowControlState::default(),
+            backpressure_applied_count: 0,
+            backpressure_released_count: 0,
+            buffer_null_retries: 0,
+            opened_at: Instant::now(),
+            peer: None,
+            close_reason: None,
+            send_stopped: false,
+        };
+        for byte in 0..5u8 {
+            data_tx.try_send(vec![byte; 100]).unwrap();
+        }
+
+        // Two reads reach the 150 byte target; the second is not split.
+        let chunk = stream.take_send_chunk(1200, 150).unwrap();
+        assert_eq!(chunk.len(), 200);
+        assert!(stream.send_stash.is_none());
+        // The send length still caps the chunk; the rest waits in the stash.
+        let chunk = stream.take_send_chunk(250, 4096).unwrap();
+        assert_eq!(chunk.len(), 250);
+        assert_eq!(stream.send_stash.as_ref().map(Vec::len), Some(50));
+        // Without coalescing one read goes out at a time.
+        drop(data_tx);
+        assert_eq!(stream.take_send_chunk(1200, 0).unwrap(), vec![4u8; 50]);
+        assert!(stream.take_send_chunk(1200, 0).is_none());
+        assert!(stream.target_fin_pending && stream.close_after_flush);
+    }
+
+    #[test]
+    fn pending_limits_cap_stream_and_connection() {
+        assert_eq!(PendingLimits::new(0, 0), None);
+        let stream_only = PendingLimits::new(1000, 0).expect("stream cap");
+        assert!(!stream_only.exceeded(1000, usize::MAX));
+        assert!(stream_only.exceeded(1001, 0));
+        let conn_only = PendingLimits::new(0, 4000).expect("conn cap");
+        assert!(!conn_only.exceeded(usize::MAX, 4000));
+        assert!(conn_only.exceeded(10, 4001));
+    }
+
+    #[test]
+    fn stream_connected_flushes_and_releases_pending_hold() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut state = ServerState::new(
+            target_addr,
+            command_tx,
+            false,
+            false,
+            None,
+            0,
+            PendingLimits::new(4, 0),
+        );
+        let key = StreamKey {
+            cnx: 0x1,
+            stream_id: 4,
+        };
+        let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+        // Consumed and received match so the release needs no picoquic call.
+        let flow = FlowControlState {
+            queued_bytes: 6,
+            rx_bytes: 6,
+            consumed_offset: 6,
+            backpressured: true,
+            ..FlowControlState::default()
+        };
+
+        state.streams.insert(
+            key,
+            ServerStream {
+                write_tx: None,
+                data_rx: None,
+                send_pending: None,
+                send_stash: None,
+                shutdown_tx,
+                tx_bytes: 0,
+                target_fin_pending: false,
+                close_after_flush: false,
+                pending_data: VecDeque::from(vec![vec![1, 2, 3], vec![4, 5, 6]]),
+                pending_bytes: 6,
+                pending_held: true,
+                pending_fin: false,
+                fin_enqueued: false,
+                flow,
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
+            },
+        );
+        assert_eq!(state.stream_debug_metrics(key.cnx).streams_pending_held, 1);
+
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        handle_command(
+            &mut state,
+            Command::StreamConnected {
+                cnx_id: key.cnx,
+                stream_id: key.stream_id,
+                write_tx,
+                data_rx,
+                send_pending: Arc::new(AtomicBool::new(false)),
+            },
+        );
+
+        let stream = state.streams.get(&key).expect("stream kept");
+        assert!(stream.pending_data.is_empty());
+        assert_eq!(stream.pending_bytes, 0);
+        assert!(!stream.pending_held);
+        assert!(!stream.flow.backpressured);
+        let mut flushed = Vec::new();
+        while let Ok(StreamWrite::Data(chunk)) = write_rx.try_recv() {
+            flushed.extend(chunk);
+        }
+        assert_eq!(flushed, vec![1, 2, 3, 4, 5, 6]);
+        let (target_connect, first_byte) = state.stream_latency();
+        assert_eq!(target_connect.count, 1);
+        assert_eq!(first_byte.count, 0);
+    }
+
+    #[derive(Default)]
+    struct RecordingHook {
+        events: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl StreamLifecycleHook for RecordingHook {
+        fn on_stream_open(&self, _cnx_id: usize, stream_id: u64, _target_addr: SocketAddr) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("open {}", stream_id));
+        }
+
+        fn on_stream_close(
+            &self,
+            _cnx_id: usize,
+            stream_id: u64,
+            tx_bytes: u64,
+            rx_bytes: u64,
+            _duration_us: u64,
+        ) {
+            self.events.lock().unwrap().push(format!(
+                "close {} tx={} rx={}",
+                stream_id, tx_bytes, rx_bytes
+            ));
+        }
+
+        fn on_stream_error(&self, _cnx_id: usize, stream_id: u64, reason: &str) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("error {} {}", stream_id, reason));
+        }
+    }
+
+    #[test]
+    fn lifecycle_hook_sees_error_before_close() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        let hook = Arc::new(RecordingHook::default());
+        state.set_lifecycle_hook(hook.clone());
+        let key = StreamKey {
+            cnx: 0x1,
+            stream_id: 8,
+        };
+        let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+        let flow = FlowControlState {
+            rx_bytes: 20,
+            ..FlowControlState::default()
+        };
+
+        state.streams.insert(
+            key,
+            ServerStream {
+                write_tx: None,
+                data_rx: None,
+                send_pending: None,
+                send_stash: None,
+                shutdown_tx,
+                tx_bytes: 10,
+                target_fin_pending: false,
+                close_after_flush: false,
+                pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
+                pending_fin: false,
+                fin_enqueued: false,
+                flow,
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
+            },
+        );
+
+        report_stream_error(&mut state, key, "target read error");
+        remove_connection_streams(&mut state, key.cnx);
+        // Unknown streams report nothing.
+        report_stream_error(&mut state, key, "target read error");
+        assert!(shutdown_stream(&mut state, key).is_none());
+
+        assert_eq!(
+            *hook.events.lock().unwrap(),
+            vec![
+                "error 8 target read error".to_string(),
+                "close 8 tx=10 rx=20".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_summaries_record_close_reason() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        let path = std::env::temp_dir().join(format!(
+            "slipstream-stream-summaries-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        state.set_stream_summaries(StreamSummaryLog::new(Some(&path), 0).unwrap());
+        for (stream_id, finished) in [(4u64, true), (8, false), (12, false)] {
+            let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+            state.streams.insert(
+                StreamKey {
+                    cnx: 0x1,
+                    stream_id,
+                },
+                ServerStream {
+                    write_tx: None,
+                    data_rx: None,
+                    send_pending: None,
+                    send_stash: None,
+                    shutdown_tx,
+                    tx_bytes: 10,
+                    target_fin_pending: false,
+                    close_after_flush: false,
+                    pending_data: VecDeque::new(),
+                    pending_bytes: 0,
+                    pending_held: false,
+                    pending_fin: false,
+                    fin_enqueued: finished,
+                    flow: FlowControlState {
+                        rx_bytes: 20,
+                        fin_offset: finished.then_some(20),
+                        ..FlowControlState::default()
+                    },
+                    backpressure_applied_count: 0,
+                    backpressure_released_count: 0,
+                    buffer_null_retries: 0,
+                    opened_at: Instant::now(),
+                    peer: None,
+                    close_reason: None,
+                    send_stopped: false,
+                },
+            );
+        }
+        let key = |stream_id| StreamKey {
+            cnx: 0x1,
+            stream_id,
+        };
+
+        shutdown_stream(&mut state, key(4));
+        report_stream_error(&mut state, key(8), "target write failed");
+        report_stream_error(&mut state, key(8), "stream_reset");
+        shutdown_stream(&mut state, key(8));
+        remove_connection_streams(&mut state, 0x1);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let reasons: Vec<&str> = contents
+            .lines()
+            .map(|line| {
+                assert!(line.contains("\"bytes_up\":20,\"bytes_down\":10"));
+                line.rsplit("\"reason\":\"")
+                    .next()
+                    .unwrap()
+                    .trim_end_matches("\"}")
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            vec!["completed", "target write failed", "connection closed"]
+        );
+    }
+
+    #[test]
+    fn stream_tags_name_the_connection_until_it_closes() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        let peer = SocketAddr::from(([198, 51, 100, 7], 40000));
+        state.conn_peers.insert(0x10, peer);
+        let key = StreamKey {
+            cnx: 0x10,
+            stream_id: 4,
+        };
+
+        let plain = state.stream_tag(key).to_string();
+        let hash = plain
+            .strip_prefix("stream 4 cnx=")
+            .and_then(|rest| rest.strip_suffix(" peer=198.51.100.7:40000"))
+            .expect(&plain);
+        assert_eq!(hash.len(), 8);
+        assert_eq!(
+            format!("{:#}", state.stream_tag(key)),
+            format!("stream=4 cnx={} peer={}", hash, peer)
+        );
+        assert_ne!(
+            state.conn_tag(0x20).to_string(),
+            format!("cnx={}", hash),
+            "neighbouring connections should hash apart"
+        );
+
+        remove_connection_streams(&mut state, 0x10);
+        assert_eq!(state.conn_tag(0x10).to_string(), format!("cnx={}", hash));
+    }
+
+    #[test]
+    fn short_id_registry_follows_the_connection_and_refuses_while_closing() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        let short_id = conn_short_id(0x10);
+        assert_eq!(state.connection_by_short_id(short_id), None);
+
+        state.register_connection(0x10);
+        state.register_connection(0x10);
+        assert_eq!(state.connection_by_short_id(short_id), Some(0x10));
+        assert_eq!(
+            state.conn_tag(0x10).to_string(),
+            format!("cnx={:08x}", short_id)
+        );
+
+        // A closing connection gets no new stream, so no target connect.
+        state.refuse_new_streams(0x0);
+        handle_stream_data(std::ptr::null_mut(), &mut state, 4, false, b"GET /");
+        assert!(state.streams.is_empty());
+
+        state.refuse_new_streams(0x10);
+        remove_connection_streams(&mut state, 0x10);
+        assert_eq!(state.connection_by_short_id(short_id), None);
+        assert!(!state.closing_conns.contains(&0x10));
+    }
+
+    #[test]
+    fn deferred_aborts_retry_until_accepted_and_drop_with_their_connection() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        let refused = AbortError {
+            stop_sending: 0,
+            reset_stream: 0x400 + 5,
+        };
+        let cnx_a = 0x10 as *mut picoquic_cnx_t;
+        let cnx_b = 0x20 as *mut picoquic_cnx_t;
+        state
+            .aborts
+            .record_failure(cnx_a, 4, SLIPSTREAM_INTERNAL_ERROR, refused);
+        state
+            .aborts
+            .record_failure(cnx_b, 8, SLIPSTREAM_INTERNAL_ERROR, refused);
+        remove_connection_streams(&mut state, 0x20);
+
+        let mut calls = Vec::new();
+        retry_deferred_aborts_with(&mut state, |cnx, stream_id, _| {
+            calls.push((cnx as usize, stream_id));
+            Err(refused)
+        });
+        retry_deferred_aborts_with(&mut state, |cnx, stream_id, _| {
+            calls.push((cnx as usize, stream_id));
+            Ok(())
+        });
+        assert_eq!(calls, vec![(0x10, 4), (0x10, 4)]);
+        assert!(state.aborts.is_empty());
+        let metrics = state.stream_debug_metrics(0x10);
+        assert_eq!(
+            (
+                metrics.abort_failures,
+                metrics.abort_retried,
+                metrics.abort_abandoned
+            ),
+            (2, 1, 0)
+        );
+    }
+
+    #[test]
+    fn consecutive_write_drains_are_folded_per_stream() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, true, None, 0, None);
+        // Credit is already released up to rx_bytes, so draining never
+        // reaches picoquic here.
+        state.multi_streams.insert(0x10);
+        for stream_id in [4u64, 8] {
+            let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+            state.streams.insert(
+                StreamKey {
+                    cnx: 0x10,
+                    stream_id,
+                },
+                ServerStream {
+                    write_tx: None,
+                    data_rx: None,
+                    send_pending: None,
+                    send_stash: None,
+                    shutdown_tx,
+                    tx_bytes: 0,
+                    target_fin_pending: false,
+                    close_after_flush: false,
+                    pending_data: VecDeque::new(),
+                    pending_bytes: 0,
+                    pending_held: false,
+                    pending_fin: false,
+                    fin_enqueued: false,
+                    flow: FlowControlState {
+                        queued_bytes: 100,
+                        rx_bytes: 100,
+                        consumed_offset: 100,
+                        ..FlowControlState::default()
+                    },
+                    backpressure_applied_count: 0,
+                    backpressure_released_count: 0,
+                    buffer_null_retries: 0,
+                    opened_at: Instant::now(),
+                    peer: None,
+                    close_reason: None,
+                    send_stopped: false,
+                },
+            );
+        }
+
+        let (drain_tx, mut drain_rx) = mpsc::unbounded_channel();
+        for (stream_id, bytes) in [(4u64, 10usize), (4, 20), (8, 5), (4, 1), (4, 2)] {
+            drain_tx
+                .send(Command::StreamWriteDrained {
+                    cnx_id: 0x10,
+                    stream_id,
+                    bytes,
+                })
+                .unwrap();
+        }
+        drain_commands(&mut state, &mut drain_rx);
+
+        let queued = |stream_id| {
+            state.streams[&StreamKey {
+                cnx: 0x10,
+                stream_id,
+            }]
+                .flow
+                .queued_bytes
+        };
+        assert_eq!((queued(4), queued(8)), (67, 95));
+        // Three runs: 4 (10 + 20), 8 (5), 4 (1 + 2).
+        assert_eq!(state.command_counts.stream_write_drained, 3);
+    }
+
+    #[test]
+    fn data_past_fin_aborts_without_reaching_the_target_writer() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        state.recorded_aborts = Some(Vec::new());
+        let cnx = 0x10 as *mut picoquic_cnx_t;
+        let key = StreamKey {
+            cnx: 0x10,
+            stream_id: 4,
+        };
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+        state.streams.insert(
+            key,
+            ServerStream {
+                write_tx: Some(write_tx),
+                data_rx: Some(data_rx),
+                send_pending: Some(Arc::new(AtomicBool::new(false))),
+                send_stash: None,
+                shutdown_tx,
+                tx_bytes: 0,
+                target_fin_pending: false,
+                close_after_flush: false,
+                pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
+                pending_fin: false,
+                fin_enqueued: false,
+                flow: FlowControlState::default(),
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
+            },
+        );
+
+        handle_stream_data(cnx, &mut state, 4, true, &[]);
+        assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)));
+        // A repeated FIN at the same offset is not a violation.
+        handle_stream_data(cnx, &mut state, 4, true, &[]);
+        assert!(state.streams.contains_key(&key));
+
+        handle_stream_data(cnx, &mut state, 4, false, b"late");
+        assert!(write_rx.try_recv().is_err(), "no bytes may follow the fin");
+        assert!(!state.streams.contains_key(&key));
+        assert!(*shutdown_rx.borrow_and_update());
+        assert_eq!(state.fin_violations, 1);
+        assert_eq!(
+            state.recorded_aborts.as_deref(),
+            Some(&[(4, SLIPSTREAM_FINAL_SIZE_ERROR)][..])
+        );
+    }
+
+    #[test]
+    fn consume_failure_resets_the_stream() {
+        let _guard = ResetOnDrop::new(|| fault_injection::set_stream_data_consumed_failures(0));
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = ServerState::new(target_addr, command_tx, false, false, None, 0, None);
+        state.recorded_aborts = Some(Vec::new());
+        let cnx = 0x10 as *mut picoquic_cnx_t;
+        let key = StreamKey {
+            cnx: 0x10,
+            stream_id: 4,
+        };
+        let (write_tx, _write_rx) = mpsc::unbounded_channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+        state.streams.insert(
+            key,
+            ServerStream {
+                write_tx: Some(write_tx),
+                data_rx: Some(data_rx),
+                send_pending: Some(Arc::new(AtomicBool::new(false))),
+                send_stash: None,
+                shutdown_tx,
+                tx_bytes: 0,
+                target_fin_pending: false,
+                close_after_flush: false,
+                pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
+                pending_fin: false,
+                fin_enqueued: false,
+                flow: FlowControlState::default(),
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
+            },
+        );
+
+        // Single-stream mode hands the credit back as the data arrives.
+        fault_injection::set_stream_data_consumed_failures(1);
+        handle_stream_data(cnx, &mut state, 4, false, b"up");
+
+        assert!(!state.streams.contains_key(&key));
+        assert!(*shutdown_rx.borrow_and_update());
+        assert_eq!(
+            state.recorded_aborts.as_deref(),
+            Some(&[(4, SLIPSTREAM_INTERNAL_ERROR)][..])
+        );
+    }
+
+    #[test]
+    fn stop_sending_half_close_keeps_writing_to_the_target() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let target_addr = SocketAddr::from(([127, 0, 0, 1], 5201));
+        let mut state = CallbackState::new(ServerState::new(
+            target_addr,
+            command_tx,
+            false,
+            false,
+            None,
+            0,
+            None,
+        ));
+        let server = state.get_mut();
+        server.set_stop_sending_half_close(true);
+        server.recorded_aborts = Some(Vec::new());
+        // A backpressured stream in multi-stream mode holds its credit, so
+        // delivery below never reaches picoquic.
+        server.multi_streams.insert(0x10);
+        let cnx = 0x10 as *mut picoquic_cnx_t;
+        let key = StreamKey {
+            cnx: 0x10,
+            stream_id: 4,
+        };
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+        let (_data_tx, data_rx) = mpsc::channel(1);
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+        let send_pending = Arc::new(AtomicBool::new(true));
+        server.streams.insert(
+            key,
+            ServerStream {
+                write_tx: Some(write_tx),
+                data_rx: Some(data_rx),
+                send_pending: Some(Arc::clone(&send_pending)),
+                send_stash: Some(b"unsent".to_vec()),
+                shutdown_tx,
+                tx_bytes: 0,
+                target_fin_pending: false,
+                close_after_flush: false,
+                pending_data: VecDeque::new(),
+                pending_bytes: 0,
+                pending_held: false,
+                pending_fin: false,
+                fin_enqueued: false,
+                flow: FlowControlState {
+                    backpressured: true,
+                    ..FlowControlState::default()
+                },
+                backpressure_applied_count: 0,
+                backpressure_released_count: 0,
+                buffer_null_retries: 0,
+                opened_at: Instant::now(),
+                peer: None,
+                close_reason: None,
+                send_stopped: false,
+            },
+        );
+
+        unsafe {
+            server_callback(
+                cnx,
+                4,
+                std::ptr::null_mut(),
+                0,
+                picoquic_call_back_event_t::picoquic_callback_stop_sending,
+                state.callback_ctx(),
+                std::ptr::null_mut(),
+            );
+        }
+        let server = state.get_mut();
+        let stream = server.streams.get(&key).expect("receive side kept");
+        assert!(stream.send_stopped);
+        assert!(stream.data_rx.is_none());
+        assert!(stream.send_stash.is_none());
+        assert!(!send_pending.load(Ordering::SeqCst));
+        assert!(!*shutdown_rx.borrow_and_update());
+        assert_eq!(
+            server.recorded_aborts.as_deref(),
+            Some(&[(4, SLIPSTREAM_FILE_CANCEL_ERROR)][..])
+        );
+
+        // Target events for the stopped side are dropped; a synthetic cnx
+        // would trip the mark_active_stream guard otherwise.
+        handle_command(
+            server,
+            Command::StreamReadable {
+                cnx_id: 0x10,
+                stream_id: 4,
+            },
+        );
+        handle_command(
+            server,
+            Command::StreamClosed {
+                cnx_id: 0x10,
+                stream_id: 4,
+            },
+        );
+
+        handle_stream_data(cnx, server, 4, true, b"up");
+        assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Data(data)) if data == b"up"));
+        assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)));
+        assert!(server.streams.contains_key(&key), "waits for the writer");
+        handle_command(
+            server,
+            Command::StreamWriteDrained {
+                cnx_id: 0x10,
+                stream_id: 4,
+                bytes: 2,
+            },
+        );
+        assert!(!server.streams.contains_key(&key));
+        assert!(*shutdown_rx.borrow_and_update());
+    }
 }
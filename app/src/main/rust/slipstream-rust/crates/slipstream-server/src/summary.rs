@@ -0,0 +1,185 @@
+//! One record per finished stream for accounting and abuse investigation.
+//!
+//! Enabled with `--stream-summaries`; each record is logged at info level and,
+//! with `--stream-summary-file`, appended as a JSON line to a file that is
+//! rotated to `<path>.1` once it grows past the configured size.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+pub(crate) struct StreamSummary<'a> {
+    pub(crate) cnx_id: usize,
+    pub(crate) stream_id: u64,
+    /// QUIC peer (usually the recursive resolver) when the stream opened.
+    pub(crate) peer: Option<SocketAddr>,
+    pub(crate) target: SocketAddr,
+    /// Bytes from the client towards the target.
+    pub(crate) bytes_up: u64,
+    /// Bytes from the target back to the client.
+    pub(crate) bytes_down: u64,
+    pub(crate) duration_ms: u64,
+    pub(crate) reason: &'a str,
+}
+
+impl StreamSummary<'_> {
+    fn peer_label(&self) -> String {
+        self.peer
+            .map(|peer| peer.to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"cnx\":\"{:#x}\",\"stream\":{},\"peer\":\"{}\",\"target\":\"{}\",\"bytes_up\":{},\"bytes_down\":{},\"duration_ms\":{},\"reason\":\"{}\"}}",
+            self.cnx_id,
+            self.stream_id,
+            self.peer_label(),
+            self.target,
+            self.bytes_up,
+            self.bytes_down,
+            self.duration_ms,
+            json_escape(self.reason)
+        )
+    }
+}
+
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+pub(crate) struct StreamSummaryLog {
+    file: Option<RotatingFile>,
+}
+
+impl StreamSummaryLog {
+    /// `max_file_bytes` of 0 never rotates.
+    pub(crate) fn new(path: Option<&Path>, max_file_bytes: u64) -> io::Result<Self> {
+        let file = path
+            .map(|path| RotatingFile::open(path.to_path_buf(), max_file_bytes))
+            .transpose()?;
+        Ok(Self { file })
+    }
+
+    pub(crate) fn record(&mut self, summary: &StreamSummary<'_>) {
+        info!(
+            "stream summary cnx={:#x} stream={} peer={} target={} bytes_up={} bytes_down={} duration_ms={} reason={}",
+            summary.cnx_id,
+            summary.stream_id,
+            summary.peer_label(),
+            summary.target,
+            summary.bytes_up,
+            summary.bytes_down,
+            summary.duration_ms,
+            summary.reason
+        );
+        if let Some(file) = self.file.as_mut() {
+            if let Err(err) = file.append_line(&summary.to_json()) {
+                warn!(
+                    "stream summary: write to {} failed: {}",
+                    file.path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            file,
+            written,
+        })
+    }
+
+    fn append_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.max_bytes > 0 && self.written > 0 && self.written + len > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(stream_id: u64) -> StreamSummary<'static> {
+        StreamSummary {
+            cnx_id: 0x10,
+            stream_id,
+            peer: Some("192.0.2.1:53".parse().unwrap()),
+            target: "127.0.0.1:5201".parse().unwrap(),
+            bytes_up: 12,
+            bytes_down: 34,
+            duration_ms: 5,
+            reason: "completed",
+        }
+    }
+
+    #[test]
+    fn summaries_are_json_lines_and_rotate() {
+        let dir = std::env::temp_dir().join(format!(
+            "slipstream-summary-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("streams.jsonl");
+        let line_len = summary(4).to_json().len() as u64 + 1;
+        let mut log = StreamSummaryLog::new(Some(&path), line_len * 2).unwrap();
+
+        log.record(&summary(4));
+        log.record(&summary(8));
+        log.record(&summary(12));
+
+        let current = fs::read_to_string(&path).unwrap();
+        let rotated = fs::read_to_string(dir.join("streams.jsonl.1")).unwrap();
+        assert_eq!(rotated.lines().count(), 2);
+        assert_eq!(
+            current.trim_end(),
+            "{\"cnx\":\"0x10\",\"stream\":12,\"peer\":\"192.0.2.1:53\",\"target\":\"127.0.0.1:5201\",\"bytes_up\":12,\"bytes_down\":34,\"duration_ms\":5,\"reason\":\"completed\"}"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
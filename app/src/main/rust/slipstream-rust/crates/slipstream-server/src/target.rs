@@ -1,28 +1,249 @@
 use crate::server::{
-    Command, StreamKey, StreamWrite, DEFAULT_TCP_RCVBUF_BYTES, STREAM_READ_CHUNK_BYTES,
+    Command, StreamKey, StreamTag, StreamWrite, DEFAULT_TCP_RCVBUF_BYTES,
     TARGET_WRITE_COALESCE_DEFAULT_BYTES,
 };
+use slipstream_core::stream_checksum::StreamChecksumLog;
+use slipstream_core::stream_trailer::{TrailerReader, TrailerWriter};
 use slipstream_core::tcp::{stream_read_limit_chunks, tcp_send_buffer_bytes};
-use std::net::SocketAddr;
+use slipstream_core::{AddressFamily, HostPort};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream as TokioTcpStream;
 use tokio::sync::{mpsc, watch};
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 
+/// Where new streams connect.
+///
+/// A literal IP target never changes. A hostname target keeps every address
+/// of the configured family and is looked up again once the cache is older
+/// than the TTL, or after a connect to all of its addresses failed, so a
+/// backend whose IP moves is picked up without a restart.
+///
+/// Once the DNS socket is bound, addresses that point back at it are
+/// refused: at startup with an error, and on later lookups by dropping
+/// them, so a misconfigured target cannot make streams loop.
+pub(crate) struct TargetResolver {
+    hostname: Option<HostPort>,
+    ttl: Duration,
+    cache: Mutex<TargetCache>,
+    /// The server's DNS listen address, from [`TargetResolver::refuse_loop`].
+    listen_addr: OnceLock<SocketAddr>,
+}
+
+struct TargetCache {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+    stale: bool,
+}
+
+impl TargetResolver {
+    pub(crate) fn fixed(addr: SocketAddr) -> Self {
+        Self::with_addrs(None, vec![addr], Duration::ZERO)
+    }
+
+    /// Resolves `target` once up front; a literal IP becomes a fixed target.
+    /// A `ttl` of zero keeps the startup addresses until a connect fails.
+    pub(crate) fn resolve(target: &HostPort, ttl: Duration) -> io::Result<Self> {
+        if target.host.parse::<IpAddr>().is_ok() {
+            let addr = (target.host.as_str(), target.port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| no_addresses(target))?;
+            return Ok(Self::fixed(addr));
+        }
+        let addrs = family_addrs(
+            target,
+            (target.host.as_str(), target.port).to_socket_addrs()?,
+        );
+        if addrs.is_empty() {
+            return Err(no_addresses(target));
+        }
+        Ok(Self::with_addrs(Some(target.clone()), addrs, ttl))
+    }
+
+    fn with_addrs(hostname: Option<HostPort>, addrs: Vec<SocketAddr>, ttl: Duration) -> Self {
+        Self {
+            hostname,
+            ttl,
+            cache: Mutex::new(TargetCache {
+                addrs,
+                resolved_at: Instant::now(),
+                stale: false,
+            }),
+            listen_addr: OnceLock::new(),
+        }
+    }
+
+    /// Fails when a current target address is the server's own
+    /// `listen_addr`, and from then on drops such addresses from lookups.
+    pub(crate) fn refuse_loop(&self, listen_addr: SocketAddr) -> io::Result<()> {
+        if let Some(addr) = self
+            .cached_addrs()
+            .into_iter()
+            .find(|addr| is_own_address(*addr, listen_addr))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Target {} is the server's own listen address {}; streams would loop back into the server",
+                    addr, listen_addr
+                ),
+            ));
+        }
+        let _ = self.listen_addr.set(listen_addr);
+        Ok(())
+    }
+
+    /// First cached address, reported as the stream target.
+    pub(crate) fn primary(&self) -> SocketAddr {
+        self.cache.lock().unwrap().addrs[0]
+    }
+
+    /// Addresses to try in order, refreshed first if the cache is due.
+    pub(crate) async fn addrs(&self) -> Vec<SocketAddr> {
+        let Some(hostname) = self.hostname.as_ref() else {
+            return self.cached_addrs();
+        };
+        let due = {
+            let cache = self.cache.lock().unwrap();
+            cache.stale || (!self.ttl.is_zero() && cache.resolved_at.elapsed() >= self.ttl)
+        };
+        if !due {
+            return self.cached_addrs();
+        }
+        match tokio::net::lookup_host((hostname.host.as_str(), hostname.port)).await {
+            Ok(found) => {
+                let mut addrs = family_addrs(hostname, found);
+                if let Some(listen_addr) = self.listen_addr.get() {
+                    let before = addrs.len();
+                    addrs.retain(|addr| !is_own_address(*addr, *listen_addr));
+                    if addrs.len() < before {
+                        warn!(
+                            "target {}: ignoring addresses that point back at the listen address {}",
+                            hostname.host, listen_addr
+                        );
+                    }
+                }
+                if addrs.is_empty() {
+                    warn!(
+                        "target {}: lookup returned no usable addresses",
+                        hostname.host
+                    );
+                } else {
+                    self.store(addrs);
+                }
+            }
+            Err(err) => {
+                warn!("target {}: lookup failed: {}", hostname.host, err);
+            }
+        }
+        self.cached_addrs()
+    }
+
+    /// Every address refused the connection; look the name up next time.
+    pub(crate) fn mark_stale(&self) {
+        if self.hostname.is_some() {
+            self.cache.lock().unwrap().stale = true;
+        }
+    }
+
+    fn cached_addrs(&self) -> Vec<SocketAddr> {
+        self.cache.lock().unwrap().addrs.clone()
+    }
+
+    fn store(&self, addrs: Vec<SocketAddr>) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.addrs != addrs {
+            if let Some(hostname) = self.hostname.as_ref() {
+                info!(
+                    "target {}: addresses changed {:?} -> {:?}",
+                    hostname.host, cache.addrs, addrs
+                );
+            }
+            cache.addrs = addrs;
+        }
+        cache.resolved_at = Instant::now();
+        cache.stale = false;
+    }
+}
+
+fn family_addrs(target: &HostPort, addrs: impl Iterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let mut matching: Vec<SocketAddr> = Vec::new();
+    for addr in addrs {
+        let wanted = match target.family {
+            AddressFamily::V4 => addr.is_ipv4(),
+            AddressFamily::V6 => addr.is_ipv6(),
+        };
+        if wanted && !matching.contains(&addr) {
+            matching.push(addr);
+        }
+    }
+    matching
+}
+
+/// Whether a connect to `target` reaches the socket bound at `listen`: the
+/// same port on the same IP, or on a loopback or unspecified IP when the
+/// listener is bound to every address.
+fn is_own_address(target: SocketAddr, listen: SocketAddr) -> bool {
+    if target.port() != listen.port() {
+        return false;
+    }
+    let target_ip = target.ip().to_canonical();
+    let listen_ip = listen.ip().to_canonical();
+    target_ip == listen_ip
+        || (listen_ip.is_unspecified() && (target_ip.is_loopback() || target_ip.is_unspecified()))
+}
+
+fn no_addresses(target: &HostPort) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Cannot resolve {}", target.host),
+    )
+}
+
+/// Connects to the first address that accepts, in order.
+async fn connect_any(addrs: &[SocketAddr]) -> io::Result<TokioTcpStream> {
+    let mut last_err = None;
+    for addr in addrs {
+        match TokioTcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => {
+                debug!("target {} connect failed: {}", addr, err);
+                last_err = Some(err);
+            }
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no target address")))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn spawn_target_connector(
-    key: StreamKey,
-    target_addr: SocketAddr,
+    tag: StreamTag,
+    target: Arc<TargetResolver>,
     command_tx: mpsc::UnboundedSender<Command>,
     debug_streams: bool,
+    checksums: bool,
+    trailers: bool,
+    read_chunk_bytes: usize,
     mut shutdown_rx: watch::Receiver<bool>,
 ) {
+    let key = tag.key();
     tokio::spawn(async move {
         if *shutdown_rx.borrow() {
             return;
         }
-        let connect = TokioTcpStream::connect(target_addr);
+        let connect = async {
+            let addrs = target.addrs().await;
+            let result = connect_any(&addrs).await;
+            if result.is_err() {
+                target.mark_stale();
+            }
+            result
+        };
         let stream = tokio::select! {
             _ = shutdown_rx.changed() => {
                 return;
@@ -35,11 +256,8 @@ pub(crate) fn spawn_target_connector(
         match stream {
             Ok(stream) => {
                 let _ = stream.set_nodelay(true);
-                let read_limit = stream_read_limit_chunks(
-                    &stream,
-                    DEFAULT_TCP_RCVBUF_BYTES,
-                    STREAM_READ_CHUNK_BYTES,
-                );
+                let read_limit =
+                    stream_read_limit_chunks(&stream, DEFAULT_TCP_RCVBUF_BYTES, read_chunk_bytes);
                 let (data_tx, data_rx) = mpsc::channel(read_limit);
                 let send_buffer_bytes = tcp_send_buffer_bytes(&stream)
                     .filter(|bytes| *bytes > 0)
@@ -48,12 +266,15 @@ pub(crate) fn spawn_target_connector(
                 let (write_tx, write_rx) = mpsc::unbounded_channel();
                 let send_pending = Arc::new(AtomicBool::new(false));
                 spawn_target_reader(
-                    key,
+                    tag,
                     read_half,
                     data_tx,
                     command_tx.clone(),
                     send_pending.clone(),
                     debug_streams,
+                    checksums.then(|| checksum_log(key, "down")),
+                    trailers.then(TrailerWriter::new),
+                    read_chunk_bytes,
                     shutdown_rx.clone(),
                 );
                 spawn_target_writer(
@@ -63,6 +284,8 @@ pub(crate) fn spawn_target_connector(
                     command_tx.clone(),
                     shutdown_rx,
                     send_buffer_bytes,
+                    checksums.then(|| checksum_log(key, "up")),
+                    trailers.then(TrailerReader::new),
                 );
                 let _ = command_tx.send(Command::StreamConnected {
                     cnx_id: key.cnx,
@@ -74,8 +297,8 @@ pub(crate) fn spawn_target_connector(
             }
             Err(err) => {
                 warn!(
-                    "stream {:?}: target connect failed err={} kind={:?}",
-                    key.stream_id,
+                    "{}: target connect failed err={} kind={:?}",
+                    tag,
                     err,
                     err.kind()
                 );
@@ -88,17 +311,28 @@ pub(crate) fn spawn_target_connector(
     });
 }
 
+fn checksum_log(key: StreamKey, direction: &'static str) -> StreamChecksumLog {
+    StreamChecksumLog::new("server", key.stream_id, direction, |message| {
+        info!("{}", message)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn spawn_target_reader(
-    key: StreamKey,
+    tag: StreamTag,
     mut read_half: tokio::net::tcp::OwnedReadHalf,
     data_tx: mpsc::Sender<Vec<u8>>,
     command_tx: mpsc::UnboundedSender<Command>,
     send_pending: Arc<AtomicBool>,
     debug_streams: bool,
+    mut checksum: Option<StreamChecksumLog>,
+    mut trailer: Option<TrailerWriter>,
+    chunk_bytes: usize,
     mut shutdown_rx: watch::Receiver<bool>,
 ) {
+    let key = tag.key();
     tokio::spawn(async move {
-        let mut buf = vec![0u8; STREAM_READ_CHUNK_BYTES];
+        let mut buf = vec![0u8; chunk_bytes.max(1)];
         let mut total = 0u64;
         loop {
             tokio::select! {
@@ -112,10 +346,20 @@ pub(crate) fn spawn_target_reader(
                         Ok(0) => {
                             if debug_streams {
                                 debug!(
-                                    "stream {:?}: target eof read_bytes={}",
-                                    key.stream_id, total
+                                    "{}: target eof read_bytes={}",
+                                    tag, total
                                 );
                             }
+                            if let Some(trailer) = trailer.take() {
+                                if data_tx.send(trailer.finish().to_vec()).await.is_ok()
+                                    && !send_pending.swap(true, Ordering::SeqCst)
+                                {
+                                    let _ = command_tx.send(Command::StreamReadable {
+                                        cnx_id: key.cnx,
+                                        stream_id: key.stream_id,
+                                    });
+                                }
+                            }
                             let _ = command_tx.send(Command::StreamClosed {
                                 cnx_id: key.cnx,
                                 stream_id: key.stream_id,
@@ -125,6 +369,12 @@ pub(crate) fn spawn_target_reader(
                         Ok(n) => {
                             total = total.saturating_add(n as u64);
                             let data = buf[..n].to_vec();
+                            if let Some(checksum) = checksum.as_mut() {
+                                checksum.update(&data);
+                            }
+                            if let Some(trailer) = trailer.as_mut() {
+                                trailer.update(&data);
+                            }
                             if data_tx.send(data).await.is_err() {
                                 break;
                             }
@@ -141,8 +391,8 @@ pub(crate) fn spawn_target_reader(
                         Err(err) => {
                             if debug_streams {
                                 debug!(
-                                    "stream {:?}: target read error after {} bytes (kind={:?} err={})",
-                                    key.stream_id,
+                                    "{}: target read error after {} bytes (kind={:?} err={})",
+                                    tag,
                                     total,
                                     err.kind(),
                                     err
@@ -162,6 +412,7 @@ pub(crate) fn spawn_target_reader(
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn spawn_target_writer(
     key: StreamKey,
     mut write_half: tokio::net::tcp::OwnedWriteHalf,
@@ -169,6 +420,8 @@ pub(crate) fn spawn_target_writer(
     command_tx: mpsc::UnboundedSender<Command>,
     mut shutdown_rx: watch::Receiver<bool>,
     coalesce_max_bytes: usize,
+    mut checksum: Option<StreamChecksumLog>,
+    mut trailer: Option<TrailerReader>,
 ) {
     tokio::spawn(async move {
         let coalesce_max_bytes = coalesce_max_bytes.max(1);
@@ -187,6 +440,7 @@ pub(crate) fn spawn_target_writer(
                         StreamWrite::Data(data) => {
                             let mut buffer = data;
                             let mut saw_fin = false;
+                            let mut peer_fin = false;
                             while buffer.len() < coalesce_max_bytes {
                                 match write_rx.try_recv() {
                                     Ok(StreamWrite::Data(more)) => {
@@ -197,6 +451,7 @@ pub(crate) fn spawn_target_writer(
                                     }
                                     Ok(StreamWrite::Fin) => {
                                         saw_fin = true;
+                                        peer_fin = true;
                                         break;
                                     }
                                     Err(mpsc::error::TryRecvError::Empty) => break,
@@ -207,6 +462,9 @@ pub(crate) fn spawn_target_writer(
                                 }
                             }
                             let len = buffer.len();
+                            if let Some(trailer) = trailer.as_mut() {
+                                buffer = trailer.push(buffer);
+                            }
                             if write_half.write_all(&buffer).await.is_err() {
                                 let _ = command_tx.send(Command::StreamWriteError {
                                     cnx_id: key.cnx,
@@ -214,17 +472,30 @@ pub(crate) fn spawn_target_writer(
                                 });
                                 return;
                             }
+                            if let Some(checksum) = checksum.as_mut() {
+                                checksum.update(&buffer);
+                            }
                             let _ = command_tx.send(Command::StreamWriteDrained {
                                 cnx_id: key.cnx,
                                 stream_id: key.stream_id,
                                 bytes: len,
                             });
                             if saw_fin {
+                                if peer_fin
+                                    && !trailer_verified(key, &trailer, &write_half, &command_tx)
+                                {
+                                    write_half.forget();
+                                    return;
+                                }
                                 let _ = write_half.shutdown().await;
                                 return;
                             }
                         }
                         StreamWrite::Fin => {
+                            if !trailer_verified(key, &trailer, &write_half, &command_tx) {
+                                write_half.forget();
+                                return;
+                            }
                             let _ = write_half.shutdown().await;
                             return;
                         }
@@ -235,3 +506,156 @@ pub(crate) fn spawn_target_writer(
         let _ = write_half.shutdown().await;
     });
 }
+
+/// Checks the client's trailer once it has sent FIN. On a failure the loop
+/// is told to abort the stream, and the target socket is set to reset when
+/// it closes, so the target cannot take corrupted data for a clean end.
+fn trailer_verified(
+    key: StreamKey,
+    trailer: &Option<TrailerReader>,
+    write_half: &tokio::net::tcp::OwnedWriteHalf,
+    command_tx: &mpsc::UnboundedSender<Command>,
+) -> bool {
+    let Some(trailer) = trailer else {
+        return true;
+    };
+    let Err(err) = trailer.finish() else {
+        return true;
+    };
+    let _ = socket2::SockRef::from(write_half.as_ref()).set_linger(Some(Duration::ZERO));
+    let _ = command_tx.send(Command::StreamChecksumMismatch {
+        cnx_id: key.cnx,
+        stream_id: key.stream_id,
+        detail: err.to_string(),
+    });
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ConnTag;
+
+    fn localhost(port: u16) -> HostPort {
+        HostPort {
+            host: "localhost".to_string(),
+            port,
+            family: AddressFamily::V4,
+        }
+    }
+
+    #[tokio::test]
+    async fn hostname_target_tries_addresses_in_order_and_refreshes_when_stale() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let target = TargetResolver::resolve(&localhost(port), Duration::ZERO).unwrap();
+        assert_eq!(target.primary(), SocketAddr::from(([127, 0, 0, 1], port)));
+
+        // A dead address first still reaches the live one after it.
+        let dead = {
+            let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            socket.local_addr().unwrap()
+        };
+        target.store(vec![dead, target.primary()]);
+        let stream = connect_any(&target.addrs().await).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+
+        // Until marked stale the cached pair is kept; afterwards the lookup
+        // replaces it.
+        assert_eq!(target.addrs().await.len(), 2);
+        target.mark_stale();
+        assert_eq!(
+            target.addrs().await,
+            vec![SocketAddr::from(([127, 0, 0, 1], port))]
+        );
+    }
+
+    #[test]
+    fn self_referential_target_is_refused() {
+        let listen: SocketAddr = "0.0.0.0:53".parse().unwrap();
+        let own = TargetResolver::fixed("127.0.0.1:53".parse().unwrap());
+        let err = own.refuse_loop(listen).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mapped = TargetResolver::fixed("[::ffff:192.0.2.1]:53".parse().unwrap());
+        assert!(mapped.refuse_loop("192.0.2.1:53".parse().unwrap()).is_err());
+        assert!(mapped.refuse_loop("[::]:53".parse().unwrap()).is_ok());
+
+        let other_port = TargetResolver::fixed("127.0.0.1:5201".parse().unwrap());
+        assert!(other_port.refuse_loop(listen).is_ok());
+        let other_host = TargetResolver::fixed("192.0.2.9:53".parse().unwrap());
+        assert!(other_host
+            .refuse_loop("192.0.2.1:53".parse().unwrap())
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn lookups_drop_addresses_that_loop_back() {
+        let target = TargetResolver::resolve(&localhost(53), Duration::ZERO).unwrap();
+        target.store(vec!["192.0.2.9:53".parse().unwrap()]);
+        target.refuse_loop("0.0.0.0:53".parse().unwrap()).unwrap();
+        // localhost resolves to the listener itself, so the old address
+        // stays in use.
+        target.mark_stale();
+        assert_eq!(target.addrs().await, vec!["192.0.2.9:53".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn literal_target_is_fixed() {
+        let literal = HostPort {
+            host: "192.0.2.9".to_string(),
+            port: 5201,
+            family: AddressFamily::V4,
+        };
+        let target = TargetResolver::resolve(&literal, Duration::from_secs(1)).unwrap();
+        target.mark_stale();
+        assert!(target.hostname.is_none());
+        assert_eq!(
+            target.addrs().await,
+            vec!["192.0.2.9:5201".parse().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn target_reader_hands_over_chunks_of_the_configured_size() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut target = TokioTcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        let payload: Vec<u8> = (0..2_500u32).map(|i| i as u8).collect();
+        target.write_all(&payload).await.unwrap();
+        target.shutdown().await.unwrap();
+
+        let (read_half, _write_half) = accepted.into_split();
+        let (data_tx, mut data_rx) = mpsc::channel(16);
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        spawn_target_reader(
+            ConnTag::new(1, None).stream(4),
+            read_half,
+            data_tx,
+            command_tx,
+            Arc::new(AtomicBool::new(false)),
+            false,
+            None,
+            None,
+            1_000,
+            shutdown_rx,
+        );
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = data_rx.recv().await {
+            chunks.push(chunk);
+        }
+        assert_eq!(
+            chunks.iter().map(Vec::len).collect::<Vec<_>>(),
+            [1_000, 1_000, 500]
+        );
+        assert_eq!(chunks.concat(), payload);
+        assert!(matches!(
+            command_rx.recv().await,
+            Some(Command::StreamReadable { stream_id: 4, .. })
+        ));
+    }
+}
@@ -1,5 +1,5 @@
 use slipstream_core::{net::is_transient_udp_error, normalize_dual_stack_addr};
-use slipstream_dns::{decode_query_with_domains, DecodeQueryError};
+use slipstream_dns::{decode_query_with_label, DecodeQueryError};
 use slipstream_ffi::picoquic::{
     picoquic_cnx_t, picoquic_incoming_packet_ex, picoquic_quic_t, slipstream_disable_ack_delay,
 };
@@ -13,6 +13,7 @@ use tokio::sync::watch;
 use tokio::task::JoinHandle;
 
 use crate::server::{map_io, ServerError, Slot};
+use crate::source_filter::{is_quic_initial, SourceFilter};
 
 pub(crate) const MAX_UDP_PACKET_SIZE: usize = 65535;
 const FALLBACK_IDLE_TIMEOUT: Duration = Duration::from_secs(180);
@@ -39,6 +40,7 @@ struct DnsPeerState {
 
 pub(crate) struct PacketContext<'a> {
     pub(crate) domains: &'a [&'a str],
+    pub(crate) service_label: Option<&'a str>,
     pub(crate) quic: *mut picoquic_quic_t,
     pub(crate) current_time: u64,
     pub(crate) local_addr_storage: &'a libc::sockaddr_storage,
@@ -276,6 +278,7 @@ pub(crate) async fn handle_packet(
     peer: SocketAddr,
     context: &PacketContext<'_>,
     fallback_mgr: &mut Option<FallbackManager>,
+    source_filter: &mut Option<SourceFilter>,
 ) -> Result<(), ServerError> {
     if let Some(manager) = fallback_mgr.as_mut() {
         if manager.is_active_fallback_peer(peer) {
@@ -284,14 +287,7 @@ pub(crate) async fn handle_packet(
         }
     }
 
-    match decode_slot(
-        packet,
-        peer,
-        context.domains,
-        context.quic,
-        context.current_time,
-        context.local_addr_storage,
-    )? {
+    match decode_slot(packet, peer, context, source_filter.as_mut())? {
         DecodeSlotOutcome::Slot(slot) => {
             if let Some(manager) = fallback_mgr.as_mut() {
                 manager.mark_dns(peer);
@@ -316,15 +312,23 @@ pub(crate) async fn handle_packet(
 fn decode_slot(
     packet: &[u8],
     peer: SocketAddr,
-    domains: &[&str],
-    quic: *mut picoquic_quic_t,
-    current_time: u64,
-    local_addr_storage: &libc::sockaddr_storage,
+    context: &PacketContext<'_>,
+    mut source_filter: Option<&mut SourceFilter>,
 ) -> Result<DecodeSlotOutcome, ServerError> {
-    match decode_query_with_domains(packet, domains) {
+    let quic = context.quic;
+    let unknown_source = source_filter
+        .as_deref()
+        .is_some_and(|filter| !filter.is_known(peer));
+    match decode_query_with_label(packet, context.domains, context.service_label) {
         Ok(query) => {
+            if unknown_source && !is_quic_initial(&query.payload) {
+                if let Some(filter) = source_filter {
+                    filter.reject(peer, Instant::now());
+                }
+                return Ok(DecodeSlotOutcome::DnsOnly);
+            }
             let mut peer_storage = dummy_sockaddr_storage();
-            let mut local_storage = unsafe { std::ptr::read(local_addr_storage) };
+            let mut local_storage = unsafe { std::ptr::read(context.local_addr_storage) };
             let mut first_cnx: *mut picoquic_cnx_t = std::ptr::null_mut();
             let mut first_path: libc::c_int = -1;
             let ret = unsafe {
@@ -338,7 +342,7 @@ fn decode_slot(
                     0,
                     &mut first_cnx,
                     &mut first_path,
-                    current_time,
+                    context.current_time,
                 )
             };
             if ret < 0 {
@@ -367,6 +371,13 @@ fn decode_slot(
             unsafe {
                 slipstream_disable_ack_delay(first_cnx);
             }
+            // Known sources note each connection they open too, so they stay
+            // known while any of them is up.
+            if let Some(filter) = source_filter.as_deref_mut() {
+                if is_quic_initial(&query.payload) {
+                    filter.learn(peer, first_cnx as usize);
+                }
+            }
             Ok(DecodeSlotOutcome::Slot(Slot {
                 peer,
                 id: query.id,
@@ -391,6 +402,12 @@ fn decode_slot(
                 // Treat empty-question queries (QDCOUNT=0) as non-DNS for fallback.
                 return Ok(DecodeSlotOutcome::Drop);
             };
+            if unknown_source {
+                if let Some(filter) = source_filter {
+                    filter.reject(peer, Instant::now());
+                }
+                return Ok(DecodeSlotOutcome::DnsOnly);
+            }
             Ok(DecodeSlotOutcome::Slot(Slot {
                 peer,
                 id,
@@ -547,6 +564,7 @@ mod tests {
         let local_addr_storage = dummy_sockaddr_storage();
         let context = PacketContext {
             domains: &domains,
+            service_label: None,
             quic: std::ptr::null_mut(),
             current_time: 0,
             local_addr_storage: &local_addr_storage,
@@ -563,6 +581,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -586,6 +605,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -624,6 +644,7 @@ mod tests {
         let local_addr_storage = dummy_sockaddr_storage();
         let context = PacketContext {
             domains: &domains,
+            service_label: None,
             quic: std::ptr::null_mut(),
             current_time: 0,
             local_addr_storage: &local_addr_storage,
@@ -643,6 +664,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -682,6 +704,7 @@ mod tests {
         let local_addr_storage = dummy_sockaddr_storage();
         let context = PacketContext {
             domains: &domains,
+            service_label: None,
             quic: std::ptr::null_mut(),
             current_time: 0,
             local_addr_storage: &local_addr_storage,
@@ -698,6 +721,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -717,6 +741,7 @@ mod tests {
                 peer,
                 &context,
                 &mut fallback_mgr,
+                &mut None,
             )
             .await
             .unwrap();
@@ -733,6 +758,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -769,6 +795,7 @@ mod tests {
         let local_addr_storage = dummy_sockaddr_storage();
         let context = PacketContext {
             domains: &domains,
+            service_label: None,
             quic: std::ptr::null_mut(),
             current_time: 0,
             local_addr_storage: &local_addr_storage,
@@ -785,6 +812,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -813,6 +841,7 @@ mod tests {
             peer,
             &context,
             &mut fallback_mgr,
+            &mut None,
         )
         .await
         .unwrap();
@@ -830,4 +859,79 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn source_validation_discards_unknown_sources() {
+        let domains = vec!["example.com"];
+        let local_addr_storage = dummy_sockaddr_storage();
+        let context = PacketContext {
+            domains: &domains,
+            service_label: None,
+            quic: std::ptr::null_mut(),
+            current_time: 0,
+            local_addr_storage: &local_addr_storage,
+        };
+        let peer: SocketAddr = "192.0.2.10:5300".parse().unwrap();
+        // Apex query: answered with an error reply when validation is off.
+        let dns_packet = build_dns_query("example.com");
+
+        let mut slots = Vec::new();
+        handle_packet(
+            &mut slots,
+            &dns_packet,
+            peer,
+            &context,
+            &mut None,
+            &mut None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(slots.len(), 1);
+
+        let mut source_filter = Some(SourceFilter::new());
+        let mut slots = Vec::new();
+        handle_packet(
+            &mut slots,
+            &dns_packet,
+            peer,
+            &context,
+            &mut None,
+            &mut source_filter,
+        )
+        .await
+        .unwrap();
+        assert!(slots.is_empty());
+        assert_eq!(source_filter.as_ref().unwrap().unknown_source_packets(), 1);
+
+        source_filter.as_mut().unwrap().learn(peer, 1);
+        let mut slots = Vec::new();
+        handle_packet(
+            &mut slots,
+            &dns_packet,
+            peer,
+            &context,
+            &mut None,
+            &mut source_filter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(source_filter.as_ref().unwrap().unknown_source_packets(), 1);
+
+        // The resolver's next query comes from another port.
+        let rotated: SocketAddr = "192.0.2.10:5301".parse().unwrap();
+        let mut slots = Vec::new();
+        handle_packet(
+            &mut slots,
+            &dns_packet,
+            rotated,
+            &context,
+            &mut None,
+            &mut source_filter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(source_filter.as_ref().unwrap().unknown_source_packets(), 1);
+    }
 }
@@ -0,0 +1,192 @@
+use crate::config::{check_cert_key, check_reset_seed};
+use crate::server::{
+    bind_udp_socket, map_io, validate_send_coalesce, validate_target_read_chunk, ServerConfig,
+    ServerError, SLIPSTREAM_ALPN,
+};
+use crate::target::TargetResolver;
+use slipstream_core::resolve_host_port;
+use slipstream_core::validation::{ValidationKind, ValidationReport};
+use slipstream_dns::validate_service_label;
+use slipstream_ffi::picoquic::{
+    picoquic_create, picoquic_current_time, slipstream_server_cc_algorithm,
+};
+use slipstream_ffi::{set_lb_server_id, set_log_dir, take_crypto_errors, QuicGuard};
+use std::ffi::CString;
+use std::path::Path;
+use std::time::Duration;
+
+/// Run the server's setup steps without serving: check the options, read
+/// the certificate, key and reset seed, resolve the target and fallback,
+/// bind and release the DNS socket and create a QUIC context. Missing
+/// files that a real run would create are reported, not written, and the
+/// QUIC context only loads an existing certificate pair. `Err` is kept for
+/// failures outside the checks.
+pub async fn run_server_validate(config: &ServerConfig) -> Result<ValidationReport, ServerError> {
+    let alpn = CString::new(SLIPSTREAM_ALPN)
+        .map_err(|_| ServerError::new("ALPN contains an unexpected null byte"))?;
+    let mut report = ValidationReport::new();
+
+    if config.domains.is_empty() {
+        report.fail(
+            ValidationKind::Config,
+            "domains",
+            "At least one domain must be configured",
+        );
+    } else {
+        report.pass(ValidationKind::Config, "domains", config.domains.join(", "));
+    }
+    if let Some(label) = config.service_label.as_deref() {
+        report.check(
+            ValidationKind::Config,
+            "service label",
+            validate_service_label(label),
+            |_| label.to_string(),
+        );
+    }
+    report.check(
+        ValidationKind::Config,
+        "target read chunk",
+        validate_target_read_chunk(config.target_read_chunk_bytes),
+        |_| format!("{} bytes", config.target_read_chunk_bytes),
+    );
+    report.check(
+        ValidationKind::Config,
+        "send coalesce",
+        validate_send_coalesce(config.send_coalesce_bytes),
+        |_| format!("{} bytes", config.send_coalesce_bytes),
+    );
+
+    let cert_path = Path::new(&config.cert);
+    let key_path = Path::new(&config.key);
+    let cert_pair = report.check(
+        ValidationKind::Certificate,
+        "cert and key",
+        check_cert_key(cert_path, key_path),
+        |detail| detail.clone(),
+    );
+    if let Some(path) = config.reset_seed_path.as_deref() {
+        report.check(
+            ValidationKind::Certificate,
+            "reset seed",
+            check_reset_seed(Path::new(path)),
+            |detail| detail.clone(),
+        );
+    }
+
+    let target = report.check(
+        ValidationKind::Resolve,
+        "target",
+        TargetResolver::resolve(
+            &config.target_address,
+            Duration::from_secs(config.target_resolve_ttl_seconds),
+        ),
+        |target| target.primary().to_string(),
+    );
+    if let Some(address) = &config.fallback_address {
+        report.check(
+            ValidationKind::Resolve,
+            "fallback",
+            resolve_host_port(address),
+            |addr| addr.to_string(),
+        );
+    }
+
+    let listen_addr = report.check(
+        ValidationKind::Bind,
+        "dns socket",
+        bind_udp_socket(&config.dns_listen_host, config.dns_listen_port)
+            .await
+            .and_then(|udp| udp.local_addr().map_err(map_io)),
+        |addr| addr.to_string(),
+    );
+    if let (Some(target), Some(listen_addr)) = (target, listen_addr) {
+        report.check(
+            ValidationKind::Config,
+            "target loop",
+            target.refuse_loop(listen_addr),
+            |_| format!("{} is not the listen address", target.primary()),
+        );
+    }
+
+    // A pair that would be generated cannot be loaded yet; create the
+    // context without one so the rest of the QUIC setup is still checked.
+    let load_pair = cert_pair.is_some_and(|_| cert_path.exists());
+    let cert = CString::new(config.cert.clone())
+        .map_err(|_| ServerError::new("Cert path contains an unexpected null byte"))?;
+    let key = CString::new(config.key.clone())
+        .map_err(|_| ServerError::new("Key path contains an unexpected null byte"))?;
+    let (cert_ptr, key_ptr) = if load_pair {
+        (cert.as_ptr(), key.as_ptr())
+    } else {
+        (std::ptr::null(), std::ptr::null())
+    };
+    let quic = unsafe {
+        picoquic_create(
+            config.max_connections,
+            cert_ptr,
+            key_ptr,
+            std::ptr::null(),
+            alpn.as_ptr(),
+            None,
+            std::ptr::null_mut(),
+            None,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            picoquic_current_time(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+            0,
+        )
+    };
+    if quic.is_null() {
+        let crypto_errors = take_crypto_errors();
+        if crypto_errors.is_empty() {
+            report.fail(ValidationKind::Quic, "context", "could not create");
+        } else {
+            report.fail(
+                ValidationKind::Quic,
+                "context",
+                format!(
+                    "could not create (TLS errors: {})",
+                    crypto_errors.join("; ")
+                ),
+            );
+        }
+        return Ok(report);
+    }
+    let _quic_guard = QuicGuard::new(quic);
+    report.pass(
+        ValidationKind::Quic,
+        "context",
+        if load_pair {
+            "created with the cert and key"
+        } else {
+            "created without a cert"
+        },
+    );
+    if unsafe { slipstream_server_cc_algorithm }.is_null() {
+        report.fail(
+            ValidationKind::Quic,
+            "congestion control",
+            "Slipstream server congestion algorithm is unavailable",
+        );
+    }
+    if let Some(server_id) = config.server_id {
+        report.check(
+            ValidationKind::Quic,
+            "server id",
+            unsafe { set_lb_server_id(quic, server_id) },
+            |_| server_id.to_string(),
+        );
+    }
+    if let Some(dir) = config.qlog_dir.as_deref() {
+        report.check(
+            ValidationKind::Quic,
+            "qlog dir",
+            unsafe { set_log_dir(quic, dir) },
+            |_| dir.to_string(),
+        );
+    }
+    Ok(report)
+}
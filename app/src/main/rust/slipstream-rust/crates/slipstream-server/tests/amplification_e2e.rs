@@ -0,0 +1,157 @@
+mod support;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use slipstream_dns::decode_query;
+
+use support::{
+    ensure_client_bin, log_snapshot, pick_tcp_port, pick_udp_port, server_bin_path, spawn_client,
+    spawn_server, test_cert_and_key, wait_for_log, workspace_root, ClientArgs, ServerArgs,
+};
+
+/// Runs a real client against a socket that never answers and returns the
+/// first query that carries a QUIC Initial.
+fn capture_initial_query(
+    client_bin: &std::path::Path,
+    domain: &str,
+    cert: &std::path::Path,
+) -> io::Result<Option<Vec<u8>>> {
+    let capture = UdpSocket::bind("127.0.0.1:0")?;
+    capture.set_read_timeout(Some(Duration::from_millis(200)))?;
+    let capture_port = capture.local_addr()?.port();
+    let tcp_port = pick_tcp_port()?;
+    let (mut client, _client_logs) = spawn_client(ClientArgs {
+        client_bin,
+        dns_port: capture_port,
+        tcp_port,
+        domain,
+        cert: Some(cert),
+        keep_alive_interval: None,
+        extra_args: &[],
+        envs: &[],
+        rust_log: "info",
+        capture_logs: false,
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut buf = [0u8; 2048];
+    let mut initial = None;
+    while Instant::now() < deadline {
+        let size = match capture.recv_from(&mut buf) {
+            Ok((size, _)) => size,
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let Ok(query) = decode_query(&buf[..size], domain) else {
+            continue;
+        };
+        // Long header, QUIC v1, Initial.
+        if query.payload.len() > 5
+            && query.payload[0] & 0xf0 == 0xc0
+            && query.payload[1..5] == [0, 0, 0, 1]
+        {
+            initial = Some(buf[..size].to_vec());
+            break;
+        }
+    }
+    client.kill();
+    Ok(initial)
+}
+
+#[test]
+fn amplification_limit_clamps_spoofed_initial() {
+    let root = workspace_root();
+    let client_bin = ensure_client_bin(&root);
+    let server_bin = server_bin_path();
+
+    let (cert, key) = test_cert_and_key(&root);
+
+    let dns_port = match pick_udp_port() {
+        Ok(port) => port,
+        Err(err) => {
+            eprintln!("skipping amplification e2e test: {}", err);
+            return;
+        }
+    };
+    let domain = "test.example.com";
+
+    let query = match capture_initial_query(&client_bin, domain, &cert) {
+        Ok(Some(query)) => query,
+        Ok(None) => {
+            eprintln!("skipping amplification e2e test: client sent no QUIC Initial");
+            return;
+        }
+        Err(err) => {
+            eprintln!("skipping amplification e2e test: {}", err);
+            return;
+        }
+    };
+
+    // A limit of 1x: the handshake flight alone is larger than the Initial
+    // query that asked for it.
+    let (mut server, server_logs) = spawn_server(ServerArgs {
+        server_bin: &server_bin,
+        dns_listen_host: Some("127.0.0.1"),
+        dns_port,
+        target_address: "127.0.0.1:1",
+        domains: &[domain],
+        cert: &cert,
+        key: &key,
+        reset_seed_path: None,
+        fallback_addr: None,
+        idle_timeout_seconds: None,
+        extra_args: &["--amplification-limit", "1", "--amplification-retry"],
+        envs: &[],
+        rust_log: "info",
+        capture_logs: true,
+    });
+    let server_logs = server_logs.expect("server logs");
+    thread::sleep(Duration::from_millis(200));
+    if server.has_exited() {
+        eprintln!("skipping amplification e2e test: server failed to start");
+        return;
+    }
+
+    // The replayed query stands in for one sent with a spoofed source: the
+    // socket that receives the answers never takes part in the handshake.
+    let spoofed = UdpSocket::bind("127.0.0.1:0").expect("bind spoofed source");
+    spoofed
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .expect("set spoofed read timeout");
+    let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, dns_port));
+    spoofed
+        .send_to(&query, server_addr)
+        .expect("send spoofed query");
+
+    let mut buf = [0u8; 4096];
+    let mut received = 0usize;
+    while let Ok((size, _)) = spoofed.recv_from(&mut buf) {
+        received += size;
+    }
+    assert!(
+        received <= query.len(),
+        "spoofed source got {} bytes for a {} byte query",
+        received,
+        query.len()
+    );
+
+    if !wait_for_log(&server_logs, "Clamped", Duration::from_secs(2)) {
+        panic!(
+            "server did not clamp the response\n{}",
+            log_snapshot(&server_logs)
+        );
+    }
+    assert!(
+        wait_for_log(&server_logs, "requiring Retry", Duration::from_secs(2)),
+        "server did not switch to Retry\n{}",
+        log_snapshot(&server_logs)
+    );
+}
@@ -55,6 +55,7 @@ fn cert_pinning_e2e() {
         reset_seed_path: None,
         fallback_addr: None,
         idle_timeout_seconds: None,
+        extra_args: &[],
         envs: &[],
         rust_log: "info",
         capture_logs: false,
@@ -73,6 +74,7 @@ fn cert_pinning_e2e() {
             domain,
             cert: Some(&cert),
             keep_alive_interval: None,
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -107,6 +109,7 @@ fn cert_pinning_e2e() {
             domain: alt_domain,
             cert: Some(&alt_cert),
             keep_alive_interval: None,
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
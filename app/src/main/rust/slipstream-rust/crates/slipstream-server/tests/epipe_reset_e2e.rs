@@ -123,6 +123,7 @@ fn epipe_triggers_quic_reset() {
             reset_seed_path: None,
             fallback_addr: None,
             idle_timeout_seconds: None,
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -134,6 +135,7 @@ fn epipe_triggers_quic_reset() {
             domain: DOMAIN,
             cert: Some(&cert),
             keep_alive_interval: Some(0),
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -142,6 +142,7 @@ fn setup_flow_control(envs: &[(&str, &str)]) -> Option<FlowControlHarness> {
             reset_seed_path: None,
             fallback_addr: None,
             idle_timeout_seconds: None,
+            extra_args: &[],
             envs,
             rust_log: "info",
             capture_logs: true,
@@ -153,6 +154,7 @@ fn setup_flow_control(envs: &[(&str, &str)]) -> Option<FlowControlHarness> {
             domain: DOMAIN,
             cert: Some(&cert),
             keep_alive_interval: Some(0),
+            extra_args: &[],
             envs,
             rust_log: "debug",
             capture_logs: true,
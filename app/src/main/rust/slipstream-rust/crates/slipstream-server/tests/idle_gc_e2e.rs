@@ -62,6 +62,7 @@ fn idle_gc_closes_connection() {
         reset_seed_path: None,
         fallback_addr: None,
         idle_timeout_seconds: Some(1),
+        extra_args: &[],
         envs: &[],
         rust_log: "debug",
         capture_logs: true,
@@ -80,6 +81,7 @@ fn idle_gc_closes_connection() {
         domain,
         cert: Some(&cert),
         keep_alive_interval: Some(0),
+        extra_args: &[],
         envs: &[],
         rust_log: "info",
         capture_logs: true,
@@ -114,6 +116,7 @@ fn idle_gc_closes_connection() {
         domain,
         cert: Some(&cert),
         keep_alive_interval: Some(0),
+        extra_args: &[],
         envs: &[],
         rust_log: "info",
         capture_logs: true,
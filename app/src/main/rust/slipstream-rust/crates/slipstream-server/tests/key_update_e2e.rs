@@ -0,0 +1,63 @@
+mod support;
+
+use std::time::Duration;
+
+use support::{
+    connect_echo, echo_until_log, log_snapshot, server_bin_path, spawn_echo_session,
+    EchoSessionArgs,
+};
+
+const ENV_ENABLE: &str = "SLIPSTREAM_KEY_UPDATE_TEST";
+
+#[test]
+fn transfer_is_byte_exact_across_key_updates() {
+    if std::env::var(ENV_ENABLE).is_err() {
+        eprintln!(
+            "skipping key update e2e test; set {}=1 to enable",
+            ENV_ENABLE
+        );
+        return;
+    }
+
+    let server_bin = server_bin_path();
+    let Some(session) = spawn_echo_session(EchoSessionArgs {
+        name: "key update",
+        server_bin: &server_bin,
+        server_extra_args: &[],
+        client_extra_args: &["--key-update-bytes", "16384"],
+        keep_alive_interval: Some(0),
+        proxy: None,
+    }) else {
+        return;
+    };
+
+    let mut echo = connect_echo(session.client_addr);
+    let rounds = echo_until_log(
+        &mut echo,
+        4096,
+        &session.client_logs,
+        "QUIC key update completed",
+        Duration::from_secs(20),
+    );
+
+    let client_snapshot = log_snapshot(&session.client_logs);
+    let server_snapshot = log_snapshot(&session.server_logs);
+    assert!(
+        rounds.seen,
+        "no key update completed\nclient logs:\n{}\nserver logs:\n{}",
+        client_snapshot, server_snapshot
+    );
+    assert!(
+        rounds.received == rounds.sent,
+        "echo payload mismatch (got {} of {})\nclient logs:\n{}\nserver logs:\n{}",
+        rounds.received.len(),
+        rounds.sent.len(),
+        client_snapshot,
+        server_snapshot
+    );
+    assert!(
+        !client_snapshot.contains("Connection closed"),
+        "connection dropped across a key update\nclient logs:\n{}",
+        client_snapshot
+    );
+}
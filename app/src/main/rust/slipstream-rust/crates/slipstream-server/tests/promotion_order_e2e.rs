@@ -0,0 +1,149 @@
+mod support;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use support::{
+    connect_echo, is_timeout, log_snapshot, server_bin_path, spawn_echo_session, EchoSessionArgs,
+};
+
+const ENV_ENABLE: &str = "SLIPSTREAM_PROMOTION_ORDER_TEST";
+const PAYLOAD_BYTES: usize = 1024 * 1024;
+const CHUNK_BYTES: usize = 16 * 1024;
+
+/// One short echo exchange on its own connection, closed from both ends.
+fn side_stream_round(client_addr: SocketAddr, round: usize) -> Result<(), String> {
+    let mut side = TcpStream::connect_timeout(&client_addr, Duration::from_secs(2))
+        .map_err(|err| format!("connect side stream: {}", err))?;
+    let _ = side.set_nodelay(true);
+    let _ = side.set_read_timeout(Some(Duration::from_secs(10)));
+    let ping = format!("side-stream-{}", round);
+    side.write_all(ping.as_bytes())
+        .map_err(|err| format!("write side stream: {}", err))?;
+    let mut echoed = vec![0u8; ping.len()];
+    side.read_exact(&mut echoed)
+        .map_err(|err| format!("read side stream: {}", err))?;
+    if echoed != ping.as_bytes() {
+        return Err(format!("side stream {} echoed {:?}", round, echoed));
+    }
+    let _ = side.shutdown(Shutdown::Write);
+    let mut rest = Vec::new();
+    let _ = side.read_to_end(&mut rest);
+    Ok(())
+}
+
+#[test]
+fn bulk_echo_survives_side_streams_opening_mid_transfer() {
+    if std::env::var(ENV_ENABLE).is_err() {
+        eprintln!(
+            "skipping promotion order e2e test; set {}=1 to enable",
+            ENV_ENABLE
+        );
+        return;
+    }
+
+    let server_bin = server_bin_path();
+    let Some(session) = spawn_echo_session(EchoSessionArgs {
+        name: "promotion order",
+        server_bin: &server_bin,
+        server_extra_args: &[],
+        client_extra_args: &[],
+        keep_alive_interval: Some(1),
+        proxy: None,
+    }) else {
+        return;
+    };
+    let client_addr = session.client_addr;
+    let mut bulk = connect_echo(client_addr);
+
+    // The bulk stream starts alone, so the connection is in single-stream
+    // mode with data queued when the first side stream promotes it.
+    let payload: Vec<u8> = (0..PAYLOAD_BYTES).map(|i| (i % 251) as u8).collect();
+    let mut writer = bulk.try_clone().expect("clone bulk stream");
+    let upload = payload.clone();
+    let write_handle = thread::spawn(move || {
+        for chunk in upload.chunks(CHUNK_BYTES) {
+            writer.write_all(chunk).expect("write bulk payload");
+        }
+        writer.shutdown(Shutdown::Write).expect("close bulk upload");
+    });
+
+    let done = Arc::new(AtomicBool::new(false));
+    let side_done = Arc::clone(&done);
+    let side_handle = thread::spawn(move || {
+        // Wait for the bulk stream to get going before the first side stream.
+        thread::sleep(Duration::from_millis(500));
+        let mut rounds = 0usize;
+        while !side_done.load(Ordering::Relaxed) {
+            side_stream_round(client_addr, rounds)?;
+            rounds += 1;
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok::<usize, String>(rounds)
+    });
+
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut closed = false;
+    let mut read_error = None;
+    let deadline = Instant::now() + Duration::from_secs(120);
+    while Instant::now() < deadline {
+        match bulk.read(&mut buf) {
+            Ok(0) => {
+                closed = true;
+                break;
+            }
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(err) if is_timeout(&err) => continue,
+            Err(err) => {
+                read_error = Some(err);
+                break;
+            }
+        }
+    }
+    done.store(true, Ordering::Relaxed);
+    let _ = write_handle.join();
+    let side_rounds = side_handle.join().expect("side stream thread panicked");
+
+    let client_snapshot = log_snapshot(&session.client_logs);
+    let server_snapshot = log_snapshot(&session.server_logs);
+    let first_mismatch = received
+        .iter()
+        .zip(&payload)
+        .position(|(got, sent)| got != sent);
+    assert!(
+        closed && received == payload,
+        "bulk echo mismatch (got {} of {}, closed={}, read_error={:?}, first_mismatch={:?}, side_rounds={:?})\nclient logs:\n{}\nserver logs:\n{}",
+        received.len(),
+        payload.len(),
+        closed,
+        read_error,
+        first_mismatch,
+        side_rounds,
+        client_snapshot,
+        server_snapshot
+    );
+    // Promotion must leave the bulk stream's queue within its cap rather than
+    // reopen the window and discard what overruns it.
+    assert!(
+        !client_snapshot.contains("exceeds limit") && !server_snapshot.contains("exceeds limit"),
+        "bulk stream overflowed its queue after promotion\nclient logs:\n{}\nserver logs:\n{}",
+        client_snapshot,
+        server_snapshot
+    );
+    let side_rounds = side_rounds.unwrap_or_else(|err| {
+        panic!(
+            "side stream failed: {}\nclient logs:\n{}\nserver logs:\n{}",
+            err, client_snapshot, server_snapshot
+        )
+    });
+    assert!(
+        side_rounds >= 2,
+        "transfer finished after {} side streams; it should overlap several",
+        side_rounds
+    );
+}
@@ -0,0 +1,197 @@
+mod support;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use support::{
+    connect_echo, is_timeout, log_snapshot, server_bin_path, spawn_echo_session, wait_for_log,
+    EchoSessionArgs, LogCapture,
+};
+
+const ENV_ENABLE: &str = "SLIPSTREAM_RECONNECT_INFLIGHT_TEST";
+const STALLED_PAYLOAD_BYTES: usize = 4 * 1024 * 1024;
+const FRESH_PAYLOAD_BYTES: usize = 256 * 1024;
+const PROXY_SEED: u64 = 0x5eed_0216;
+const INVARIANT_NEEDLE: &str = "invariant violated";
+// Nothing but picoquic's idle timeout ends a connection whose path is dead,
+// so the blackhole has to outlast it.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(90);
+
+#[test]
+fn reconnect_drops_inflight_streams_and_serves_new_ones() {
+    if std::env::var(ENV_ENABLE).is_err() {
+        eprintln!(
+            "skipping reconnect in-flight e2e test; set {}=1 to enable",
+            ENV_ENABLE
+        );
+        return;
+    }
+
+    let server_bin = server_bin_path();
+    let Some(session) = spawn_echo_session(EchoSessionArgs {
+        name: "reconnect in-flight",
+        server_bin: &server_bin,
+        server_extra_args: &[],
+        client_extra_args: &[],
+        keep_alive_interval: Some(1),
+        proxy: Some((0, PROXY_SEED)),
+    }) else {
+        return;
+    };
+    let proxy = session.proxy.as_ref().expect("lossy proxy");
+    let client_logs = &session.client_logs;
+    let client_addr = session.client_addr;
+
+    let stalled_payload = payload(STALLED_PAYLOAD_BYTES, 251);
+    let mut stalled = connect_echo(client_addr);
+    let mut writer = stalled.try_clone().expect("clone stalled stream");
+    let upload = stalled_payload.clone();
+    // The upload is cut short by the reconnect, so its result is not checked.
+    let stalled_writer = thread::spawn(move || {
+        let _ = writer.write_all(&upload);
+        let _ = writer.shutdown(Shutdown::Write);
+    });
+
+    // Cut the path once the echo is flowing, so the transfer is in flight on
+    // both sides when the connection dies.
+    let mut stalled_received = Vec::new();
+    let mut buf = [0u8; 4096];
+    let flowing_deadline = Instant::now() + Duration::from_secs(30);
+    while stalled_received.len() < 64 * 1024 && Instant::now() < flowing_deadline {
+        match stalled.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => stalled_received.extend_from_slice(&buf[..n]),
+            Err(err) if is_timeout(&err) => continue,
+            Err(err) => panic!("read stalled echo failed: {}", err),
+        }
+    }
+    assert!(
+        stalled_received.len() >= 64 * 1024,
+        "echo did not start flowing before the blackhole (got {} bytes)\nclient logs:\n{}",
+        stalled_received.len(),
+        log_snapshot(client_logs)
+    );
+    proxy.set_blackhole(true);
+
+    // Accepted while the path is dead: its reservation and stream belong to
+    // the connection that is about to go away.
+    let mut orphan = connect_echo(client_addr);
+    let _ = orphan.write_all(b"opened during the blackhole");
+
+    let reconnected = wait_for_log(
+        client_logs,
+        "Connection closed; reconnecting",
+        RECONNECT_TIMEOUT,
+    );
+    proxy.set_blackhole(false);
+    assert!(
+        reconnected,
+        "client did not give up on the blackholed connection\nclient logs:\n{}",
+        log_snapshot(client_logs)
+    );
+    if !wait_for_log(client_logs, "Connection ready", Duration::from_secs(20)) {
+        panic!(
+            "client did not become ready after the blackhole\nclient logs:\n{}",
+            log_snapshot(client_logs)
+        );
+    }
+
+    // Streams of the dead connection must end rather than hang or resume on
+    // the new one.
+    let stalled_end = drain_until_closed(&mut stalled, Duration::from_secs(10));
+    let _ = stalled_writer.join();
+    assert!(
+        stalled_end.is_some(),
+        "stream in flight across the reconnect was never closed\nclient logs:\n{}",
+        log_snapshot(client_logs)
+    );
+    let total_stalled = stalled_received.len() + stalled_end.unwrap_or_default();
+    assert!(
+        total_stalled < STALLED_PAYLOAD_BYTES,
+        "stalled echo completed in full ({} bytes) despite the blackhole",
+        total_stalled
+    );
+    assert!(
+        drain_until_closed(&mut orphan, Duration::from_secs(10)).is_some(),
+        "stream accepted during the blackhole was never closed\nclient logs:\n{}",
+        log_snapshot(client_logs)
+    );
+
+    let fresh_payload = payload(FRESH_PAYLOAD_BYTES, 241);
+    let fresh_received = echo_transfer(client_addr, &fresh_payload, Duration::from_secs(60));
+    let client_snapshot = log_snapshot(client_logs);
+    let server_snapshot = log_snapshot(&session.server_logs);
+    assert!(
+        fresh_received.as_deref() == Some(&fresh_payload[..]),
+        "echo after reconnect mismatch (got {:?} of {} bytes, proxy {:?})\nclient logs:\n{}\nserver logs:\n{}",
+        fresh_received.as_ref().map(Vec::len),
+        fresh_payload.len(),
+        proxy.stats(),
+        client_snapshot,
+        server_snapshot
+    );
+    assert_no_invariant_violations(client_logs, &session.server_logs);
+}
+
+fn payload(len: usize, modulus: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % modulus) as u8).collect()
+}
+
+/// Bytes read before the stream closed or was reset, or `None` if it was
+/// still open at the deadline.
+fn drain_until_closed(stream: &mut TcpStream, timeout: Duration) -> Option<usize> {
+    let mut buf = [0u8; 4096];
+    let mut drained = 0;
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match stream.read(&mut buf) {
+            Ok(0) => return Some(drained),
+            Ok(n) => drained += n,
+            Err(err) if is_timeout(&err) => continue,
+            Err(_) => return Some(drained),
+        }
+    }
+    None
+}
+
+/// Uploads `payload`, closes the upload and returns what came back before the
+/// echo's FIN, or `None` if it never arrived.
+fn echo_transfer(addr: SocketAddr, payload: &[u8], timeout: Duration) -> Option<Vec<u8>> {
+    let mut stream = connect_echo(addr);
+    let mut writer = stream.try_clone().expect("clone echo stream");
+    let upload = payload.to_vec();
+    let write_handle = thread::spawn(move || {
+        writer.write_all(&upload).expect("write echo payload");
+        writer.shutdown(Shutdown::Write).expect("close echo upload");
+    });
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    let deadline = Instant::now() + timeout;
+    let mut closed = false;
+    while Instant::now() < deadline {
+        match stream.read(&mut buf) {
+            Ok(0) => {
+                closed = true;
+                break;
+            }
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(err) if is_timeout(&err) => continue,
+            Err(err) => panic!("read echo payload failed: {}", err),
+        }
+    }
+    let _ = write_handle.join();
+    closed.then_some(received)
+}
+
+fn assert_no_invariant_violations(client_logs: &LogCapture, server_logs: &LogCapture) {
+    let client_snapshot = log_snapshot(client_logs);
+    let server_snapshot = log_snapshot(server_logs);
+    assert!(
+        !client_snapshot.contains(INVARIANT_NEEDLE) && !server_snapshot.contains(INVARIANT_NEEDLE),
+        "invariant violated around the reconnect\nclient logs:\n{}\nserver logs:\n{}",
+        client_snapshot,
+        server_snapshot
+    );
+}
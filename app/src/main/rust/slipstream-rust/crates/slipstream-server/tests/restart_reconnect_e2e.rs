@@ -46,6 +46,7 @@ fn restart_reconnects_idle_client() {
         reset_seed_path: Some(&reset_seed_path),
         fallback_addr: None,
         idle_timeout_seconds: None,
+        extra_args: &[],
         envs: &[],
         rust_log: "info",
         capture_logs: false,
@@ -63,6 +64,7 @@ fn restart_reconnects_idle_client() {
         domain,
         cert: Some(&cert),
         keep_alive_interval: Some(0),
+        extra_args: &[],
         envs: &[],
         rust_log: "info",
         capture_logs: true,
@@ -93,6 +95,7 @@ fn restart_reconnects_idle_client() {
         reset_seed_path: Some(&reset_seed_path),
         fallback_addr: None,
         idle_timeout_seconds: None,
+        extra_args: &[],
         envs: &[],
         rust_log: "info",
         capture_logs: false,
@@ -0,0 +1,92 @@
+mod support;
+
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use support::{
+    connect_echo, is_timeout, log_snapshot, server_bin_path, spawn_echo_session, EchoSessionArgs,
+};
+
+const ENV_ENABLE: &str = "SLIPSTREAM_STREAM_TRAILER_TEST";
+const PAYLOAD_BYTES: usize = 256 * 1024;
+const LOSS_PERCENT: u8 = 10;
+const LOSS_SEED: u64 = 0x5eed_0209;
+const MISMATCH_NEEDLE: &str = "stream trailer check failed";
+
+#[test]
+fn trailers_pass_a_lossy_echo_transfer() {
+    if std::env::var(ENV_ENABLE).is_err() {
+        eprintln!(
+            "skipping stream trailer e2e test; set {}=1 to enable",
+            ENV_ENABLE
+        );
+        return;
+    }
+
+    let server_bin = server_bin_path();
+    let Some(session) = spawn_echo_session(EchoSessionArgs {
+        name: "stream trailer",
+        server_bin: &server_bin,
+        server_extra_args: &["--debug-streams", "--debug-stream-trailers"],
+        client_extra_args: &["--debug-streams", "--debug-stream-trailers"],
+        keep_alive_interval: Some(1),
+        proxy: Some((LOSS_PERCENT, LOSS_SEED)),
+    }) else {
+        return;
+    };
+    let mut echo = connect_echo(session.client_addr);
+
+    // Both trailers are only checked at FIN, so the upload is closed once
+    // written and the download read until the server's FIN.
+    let payload: Vec<u8> = (0..PAYLOAD_BYTES).map(|i| (i % 251) as u8).collect();
+    let mut writer = echo.try_clone().expect("clone echo stream");
+    let upload = payload.clone();
+    let write_handle = thread::spawn(move || {
+        writer.write_all(&upload).expect("write echo payload");
+        writer.shutdown(Shutdown::Write).expect("close echo upload");
+    });
+
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut closed = false;
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while Instant::now() < deadline {
+        match echo.read(&mut buf) {
+            Ok(0) => {
+                closed = true;
+                break;
+            }
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(err) if is_timeout(&err) => continue,
+            Err(err) => panic!("read echo payload failed: {}", err),
+        }
+    }
+    let _ = write_handle.join();
+
+    let client_snapshot = log_snapshot(&session.client_logs);
+    let server_snapshot = log_snapshot(&session.server_logs);
+    let stats = session.proxy.as_ref().map(|proxy| proxy.stats());
+    assert!(
+        closed && received == payload,
+        "echo payload mismatch (got {} of {}, closed={}, proxy {:?})\nclient logs:\n{}\nserver logs:\n{}",
+        received.len(),
+        payload.len(),
+        closed,
+        stats,
+        client_snapshot,
+        server_snapshot
+    );
+    assert!(
+        !client_snapshot.contains(MISMATCH_NEEDLE) && !server_snapshot.contains(MISMATCH_NEEDLE),
+        "trailer check failed on a clean transfer\nclient logs:\n{}\nserver logs:\n{}",
+        client_snapshot,
+        server_snapshot
+    );
+    assert!(
+        server_snapshot.contains("debug_stream_trailers"),
+        "server did not enable stream trailers\nserver logs:\n{}",
+        server_snapshot
+    );
+}
@@ -97,6 +97,7 @@ fn target_read_error_triggers_client_reset() {
             reset_seed_path: None,
             fallback_addr: None,
             idle_timeout_seconds: None,
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -108,6 +109,7 @@ fn target_read_error_triggers_client_reset() {
             domain: DOMAIN,
             cert: Some(&cert),
             keep_alive_interval: Some(1),
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -0,0 +1,209 @@
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    ensure_client_bin, pick_tcp_port, pick_udp_port, spawn_accept_loop_target,
+    spawn_server_client_ready, test_cert_and_key, wait_for_log, workspace_root, ChildGuard,
+    ClientArgs, LogCapture, LossyUdpProxy, ServerArgs, ServerClientHarness, TargetHarness,
+};
+
+const ECHO_DOMAIN: &str = "test.example.com";
+
+/// Spawns a TCP target that echoes every connection back and closes its
+/// side once the upload ends.
+pub fn spawn_echo_target() -> io::Result<TargetHarness<()>> {
+    spawn_accept_loop_target(|stream, _tx: Sender<()>, stop_flag, _index| {
+        let stop_conn = Arc::clone(&stop_flag);
+        Some(thread::spawn(move || {
+            let mut stream = stream;
+            let _ = stream.set_nodelay(true);
+            let _ = stream.set_read_timeout(Some(Duration::from_millis(200)));
+            let mut buf = [0u8; 4096];
+            while !stop_conn.load(Ordering::Relaxed) {
+                match stream.read(&mut buf) {
+                    Ok(0) => {
+                        let _ = stream.shutdown(Shutdown::Write);
+                        break;
+                    }
+                    Ok(n) => {
+                        if stream.write_all(&buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) if is_timeout(&err) => continue,
+                    Err(_) => break,
+                }
+            }
+        }))
+    })
+}
+
+/// Settings for [`spawn_echo_session`].
+pub struct EchoSessionArgs<'a> {
+    /// Names the test in skip messages, as in "skipping {name} e2e test".
+    pub name: &'a str,
+    pub server_bin: &'a Path,
+    pub server_extra_args: &'a [&'a str],
+    pub client_extra_args: &'a [&'a str],
+    pub keep_alive_interval: Option<u16>,
+    /// Loss percentage and seed of a [`LossyUdpProxy`] to put between client
+    /// and server.
+    pub proxy: Option<(u8, u64)>,
+}
+
+/// A ready client and server tunnelling to an echo target. Dropping it
+/// stops the processes before the target.
+pub struct EchoSession {
+    pub server: ChildGuard,
+    pub client: ChildGuard,
+    pub server_logs: LogCapture,
+    pub client_logs: LogCapture,
+    pub proxy: Option<LossyUdpProxy>,
+    pub target: TargetHarness<()>,
+    /// Where the client listens for TCP connections.
+    pub client_addr: SocketAddr,
+}
+
+/// Starts an echo target, the server and a client connected to it, and waits
+/// for the connection to be ready. Returns `None` after printing why when
+/// the environment cannot run the test.
+pub fn spawn_echo_session(args: EchoSessionArgs<'_>) -> Option<EchoSession> {
+    let root = workspace_root();
+    let client_bin = ensure_client_bin(&root);
+    let (cert, key) = test_cert_and_key(&root);
+    let skip = |err: io::Error| eprintln!("skipping {} e2e test: {}", args.name, err);
+
+    let dns_port = pick_udp_port().map_err(skip).ok()?;
+    let tcp_port = pick_tcp_port().map_err(skip).ok()?;
+    let proxy = match args.proxy {
+        Some((loss_percent, seed)) => Some(
+            LossyUdpProxy::spawn(
+                SocketAddr::from((Ipv4Addr::LOCALHOST, dns_port)),
+                loss_percent,
+                seed,
+            )
+            .map_err(skip)
+            .ok()?,
+        ),
+        None => None,
+    };
+    let target = spawn_echo_target().map_err(skip).ok()?;
+
+    let ServerClientHarness {
+        server,
+        client,
+        server_logs,
+        client_logs,
+    } = spawn_server_client_ready(
+        ServerArgs {
+            server_bin: args.server_bin,
+            dns_listen_host: Some("127.0.0.1"),
+            dns_port,
+            target_address: &format!("127.0.0.1:{}", target.addr.port()),
+            domains: &[ECHO_DOMAIN],
+            cert: &cert,
+            key: &key,
+            reset_seed_path: None,
+            fallback_addr: None,
+            idle_timeout_seconds: None,
+            extra_args: args.server_extra_args,
+            envs: &[],
+            rust_log: "info",
+            capture_logs: true,
+        },
+        ClientArgs {
+            client_bin: &client_bin,
+            dns_port: proxy.as_ref().map_or(dns_port, |proxy| proxy.addr().port()),
+            tcp_port,
+            domain: ECHO_DOMAIN,
+            cert: Some(&cert),
+            keep_alive_interval: args.keep_alive_interval,
+            extra_args: args.client_extra_args,
+            envs: &[],
+            rust_log: "info",
+            capture_logs: true,
+        },
+        &format!("skipping {} e2e test: server failed to start", args.name),
+        Duration::from_millis(200),
+    )?;
+
+    Some(EchoSession {
+        server,
+        client,
+        server_logs,
+        client_logs,
+        proxy,
+        target,
+        client_addr: SocketAddr::from((Ipv4Addr::LOCALHOST, tcp_port)),
+    })
+}
+
+/// Opens a stream through the client with a short read timeout, so readers
+/// can check their deadlines.
+pub fn connect_echo(addr: SocketAddr) -> TcpStream {
+    let stream =
+        TcpStream::connect_timeout(&addr, Duration::from_secs(2)).expect("connect echo stream");
+    let _ = stream.set_nodelay(true);
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(200)));
+    stream
+}
+
+pub fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+/// What [`echo_until_log`] sent and got back.
+pub struct EchoRounds {
+    pub sent: Vec<u8>,
+    pub received: Vec<u8>,
+    /// Whether the log line showed up before the deadline.
+    pub seen: bool,
+}
+
+/// Echoes `chunk_bytes` chunks over `stream` until `needle` shows up in
+/// `logs`, then one more chunk so data also crosses whatever the logged
+/// event changed.
+pub fn echo_until_log(
+    stream: &mut TcpStream,
+    chunk_bytes: usize,
+    logs: &LogCapture,
+    needle: &str,
+    timeout: Duration,
+) -> EchoRounds {
+    let mut sent = Vec::new();
+    let mut received = Vec::new();
+    let mut seen = false;
+    let mut buf = [0u8; 4096];
+    let deadline = Instant::now() + timeout;
+    let mut round = 0u8;
+    while Instant::now() < deadline {
+        let chunk = vec![round; chunk_bytes];
+        stream.write_all(&chunk).expect("write echo payload");
+        sent.extend_from_slice(&chunk);
+        let chunk_deadline = Instant::now() + Duration::from_secs(5);
+        while received.len() < sent.len() && Instant::now() < chunk_deadline {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(err) if is_timeout(&err) => continue,
+                Err(err) => panic!("read echo payload failed: {}", err),
+            }
+        }
+        if seen {
+            break;
+        }
+        seen = wait_for_log(logs, needle, Duration::from_millis(250));
+        round = round.wrapping_add(1);
+    }
+    EchoRounds {
+        sent,
+        received,
+        seen,
+    }
+}
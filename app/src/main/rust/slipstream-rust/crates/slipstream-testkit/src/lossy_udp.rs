@@ -0,0 +1,255 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const PROXY_BUF_BYTES: usize = 65_535;
+const PROXY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LossyUdpStats {
+    pub forwarded: u64,
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    /// Drop everything, whatever the loss rate.
+    blackhole: AtomicBool,
+}
+
+/// A UDP proxy for one client that drops a share of the datagrams in each
+/// direction. Point the client's `--resolver` at [`LossyUdpProxy::addr`].
+///
+/// Drops come from a generator seeded by the caller, so a failing run can be
+/// repeated with the same loss pattern. [`LossyUdpProxy::set_blackhole`]
+/// cuts the path entirely until it is lifted.
+pub struct LossyUdpProxy {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl LossyUdpProxy {
+    pub fn spawn(upstream: SocketAddr, loss_percent: u8, seed: u64) -> io::Result<Self> {
+        let front = Arc::new(UdpSocket::bind("127.0.0.1:0")?);
+        front.set_read_timeout(Some(PROXY_POLL_INTERVAL))?;
+        let back = Arc::new(UdpSocket::bind(match upstream {
+            SocketAddr::V4(_) => "127.0.0.1:0",
+            SocketAddr::V6(_) => "[::1]:0",
+        })?);
+        back.set_read_timeout(Some(PROXY_POLL_INTERVAL))?;
+        back.connect(upstream)?;
+        let addr = front.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let counters = Arc::new(Counters::default());
+        let client = Arc::new(Mutex::new(None::<SocketAddr>));
+
+        let upstream_handle = {
+            let (front, back) = (Arc::clone(&front), Arc::clone(&back));
+            let (stop, counters, client) = (
+                Arc::clone(&stop),
+                Arc::clone(&counters),
+                Arc::clone(&client),
+            );
+            let mut loss = Loss::new(loss_percent, seed);
+            thread::spawn(move || {
+                let mut buf = vec![0u8; PROXY_BUF_BYTES];
+                while !stop.load(Ordering::Relaxed) {
+                    let Ok((size, peer)) = front.recv_from(&mut buf) else {
+                        continue;
+                    };
+                    if let Ok(mut client) = client.lock() {
+                        *client = Some(peer);
+                    }
+                    if counters.blackhole.load(Ordering::Relaxed) || loss.drop_next() {
+                        counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        // Counted first so stats are current once the peer has it.
+                        counters.forwarded.fetch_add(1, Ordering::Relaxed);
+                        let _ = back.send(&buf[..size]);
+                    }
+                }
+            })
+        };
+        let downstream_handle = {
+            let (stop, counters) = (Arc::clone(&stop), Arc::clone(&counters));
+            let mut loss = Loss::new(loss_percent, !seed);
+            thread::spawn(move || {
+                let mut buf = vec![0u8; PROXY_BUF_BYTES];
+                while !stop.load(Ordering::Relaxed) {
+                    let Ok(size) = back.recv(&mut buf) else {
+                        continue;
+                    };
+                    let Some(peer) = client.lock().ok().and_then(|client| *client) else {
+                        continue;
+                    };
+                    if counters.blackhole.load(Ordering::Relaxed) || loss.drop_next() {
+                        counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        // Counted first so stats are current once the peer has it.
+                        counters.forwarded.fetch_add(1, Ordering::Relaxed);
+                        let _ = front.send_to(&buf[..size], peer);
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            stop,
+            counters,
+            handles: vec![upstream_handle, downstream_handle],
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Drops every datagram in both directions while `on`; the loss pattern
+    /// resumes where it left off once lifted.
+    pub fn set_blackhole(&self, on: bool) {
+        self.counters.blackhole.store(on, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> LossyUdpStats {
+        LossyUdpStats {
+            forwarded: self.counters.forwarded.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for LossyUdpProxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// xorshift64 drop decisions.
+struct Loss {
+    percent: u64,
+    state: u64,
+}
+
+impl Loss {
+    fn new(percent: u8, seed: u64) -> Self {
+        Self {
+            percent: u64::from(percent.min(100)),
+            state: seed | 1,
+        }
+    }
+
+    fn drop_next(&mut self) -> bool {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state % 100 < self.percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_through(proxy: &LossyUdpProxy, count: usize) -> usize {
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .unwrap();
+        let mut echoed = 0;
+        let mut buf = [0u8; 64];
+        for i in 0..count {
+            client.send_to(&[i as u8], proxy.addr()).unwrap();
+            if client.recv_from(&mut buf).is_ok() {
+                echoed += 1;
+            }
+        }
+        echoed
+    }
+
+    fn spawn_echo() -> (SocketAddr, Arc<AtomicBool>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(PROXY_POLL_INTERVAL)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
+        thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while !stop_flag.load(Ordering::Relaxed) {
+                if let Ok((size, peer)) = socket.recv_from(&mut buf) {
+                    let _ = socket.send_to(&buf[..size], peer);
+                }
+            }
+        });
+        (addr, stop)
+    }
+
+    #[test]
+    fn forwards_both_ways_without_loss() {
+        let (echo, stop) = spawn_echo();
+        let proxy = LossyUdpProxy::spawn(echo, 0, 7).unwrap();
+        assert_eq!(send_through(&proxy, 5), 5);
+        assert_eq!(
+            proxy.stats(),
+            LossyUdpStats {
+                forwarded: 10,
+                dropped: 0
+            }
+        );
+        stop.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn full_loss_drops_everything() {
+        let (echo, stop) = spawn_echo();
+        let proxy = LossyUdpProxy::spawn(echo, 100, 7).unwrap();
+        assert_eq!(send_through(&proxy, 3), 0);
+        assert_eq!(
+            proxy.stats(),
+            LossyUdpStats {
+                forwarded: 0,
+                dropped: 3
+            }
+        );
+        stop.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn blackhole_drops_until_lifted() {
+        let (echo, stop) = spawn_echo();
+        let proxy = LossyUdpProxy::spawn(echo, 0, 7).unwrap();
+        proxy.set_blackhole(true);
+        assert_eq!(send_through(&proxy, 2), 0);
+        proxy.set_blackhole(false);
+        assert_eq!(send_through(&proxy, 1), 1);
+        assert_eq!(
+            proxy.stats(),
+            LossyUdpStats {
+                forwarded: 2,
+                dropped: 2
+            }
+        );
+        stop.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn loss_pattern_repeats_for_a_seed() {
+        let pattern = |seed| {
+            let mut loss = Loss::new(30, seed);
+            (0..200).map(|_| loss.drop_next()).collect::<Vec<_>>()
+        };
+        assert_eq!(pattern(42), pattern(42));
+        let dropped = pattern(42).iter().filter(|dropped| **dropped).count();
+        assert!((30..90).contains(&dropped), "dropped {}", dropped);
+    }
+}
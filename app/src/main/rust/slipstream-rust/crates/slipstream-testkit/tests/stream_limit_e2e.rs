@@ -1,14 +1,12 @@
-mod support;
-
 use std::io::{Read, Write};
 use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use support::{
-    ensure_client_bin, log_snapshot, pick_tcp_port, pick_udp_port, server_bin_path,
-    spawn_accept_loop_target, spawn_server_client_ready, test_cert_and_key, workspace_root,
-    ClientArgs, ServerArgs,
+use slipstream_testkit::{
+    ensure_client_bin, ensure_server_bin, log_snapshot, pick_tcp_port, pick_udp_port,
+    spawn_accept_loop_target, spawn_server_client_ready, test_cert_and_key, wait_for_any_log,
+    workspace_root, ClientArgs, LogCapture, ServerArgs, ServerClientHarness,
 };
 
 const DOMAIN: &str = "test.example.com";
@@ -27,15 +25,13 @@ fn parse_stream_limit_line(line: &str) -> Option<usize> {
     value.parse::<usize>().ok()
 }
 
-fn derive_stream_limit(logs: &support::LogCapture) -> usize {
+fn derive_stream_limit(logs: &LogCapture) -> usize {
     let snapshot = log_snapshot(logs);
     if let Some(limit) = snapshot.lines().find_map(parse_stream_limit_line) {
         return limit;
     }
 
-    if let Some(line) =
-        support::wait_for_any_log(logs, &[STREAM_LIMIT_LOG_NEEDLE], Duration::from_secs(2))
-    {
+    if let Some(line) = wait_for_any_log(logs, &[STREAM_LIMIT_LOG_NEEDLE], Duration::from_secs(2)) {
         if let Some(limit) = parse_stream_limit_line(&line) {
             return limit;
         }
@@ -52,7 +48,7 @@ fn derive_stream_limit(logs: &support::LogCapture) -> usize {
 fn stream_limit_reuse_allows_next_stream() {
     let root = workspace_root();
     let client_bin = ensure_client_bin(&root);
-    let server_bin = server_bin_path();
+    let server_bin = ensure_server_bin(&root);
 
     let (cert, key) = test_cert_and_key(&root);
 
@@ -96,6 +92,7 @@ fn stream_limit_reuse_allows_next_stream() {
             reset_seed_path: None,
             fallback_addr: None,
             idle_timeout_seconds: None,
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -107,6 +104,7 @@ fn stream_limit_reuse_allows_next_stream() {
             domain: DOMAIN,
             cert: Some(&cert),
             keep_alive_interval: Some(1),
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -118,7 +116,7 @@ fn stream_limit_reuse_allows_next_stream() {
         None => return,
     };
 
-    let support::ServerClientHarness {
+    let ServerClientHarness {
         server: _server,
         client: _client,
         server_logs,
@@ -165,7 +163,7 @@ fn stream_limit_reuse_allows_next_stream() {
 fn stream_limit_server_close_allows_next_stream() {
     let root = workspace_root();
     let client_bin = ensure_client_bin(&root);
-    let server_bin = server_bin_path();
+    let server_bin = ensure_server_bin(&root);
 
     let (cert, key) = test_cert_and_key(&root);
 
@@ -209,6 +207,7 @@ fn stream_limit_server_close_allows_next_stream() {
             reset_seed_path: None,
             fallback_addr: None,
             idle_timeout_seconds: None,
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -220,6 +219,7 @@ fn stream_limit_server_close_allows_next_stream() {
             domain: DOMAIN,
             cert: Some(&cert),
             keep_alive_interval: Some(1),
+            extra_args: &[],
             envs: &[],
             rust_log: "info",
             capture_logs: true,
@@ -231,7 +231,7 @@ fn stream_limit_server_close_allows_next_stream() {
         None => return,
     };
 
-    let support::ServerClientHarness {
+    let ServerClientHarness {
         server: _server,
         client: _client,
         server_logs,